@@ -0,0 +1,36 @@
+// Sends one operator command to a uhf or lband daemon, sealed with its [control] key so it gets
+// past a daemon that requires one, see ax5043::auth and ax5043::control:
+//
+//   cargo run --example control -- --key 8899aabbccddeeff0011223344556677 --encrypt \
+//       10.18.17.5:10017 freq 437000000
+use anyhow::{Context, Result};
+use ax5043::{auth::ControlAuth, control::Command};
+use clap::Parser;
+use std::{net::UdpSocket, time::SystemTime};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Hex key from the daemon's [control] section, the command goes in the clear without one
+    #[arg(long)]
+    key: Option<String>,
+    /// Encrypt the command
+    #[arg(long)]
+    encrypt: bool,
+    /// The daemon's control socket, host:port
+    dest: String,
+    #[arg(required = true)]
+    command: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let command = args.command.join(" ");
+    command.parse::<Command>().context("Invalid command")?;
+    let datagram = match args.key {
+        Some(ref key) => ControlAuth::new(key, args.encrypt)?.seal(&command, SystemTime::now()),
+        None => command.into_bytes(),
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.send_to(&datagram, &args.dest)?;
+    Ok(())
+}
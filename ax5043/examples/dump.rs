@@ -7,7 +7,7 @@ fn main() -> Result<()> {
     let mut status = Status::empty();
     let mut callback = |_: &_, _, s, _: &_| {
         if s != status {
-            println!("TX Status change: {:?}", s);
+            println!("TX Status change: {}", s);
             status = s;
         }
     };
@@ -15,7 +15,7 @@ fn main() -> Result<()> {
     radio.reset()?;
     println!("REVISION       {:?}", radio.REVISION().read()?);
     println!("SCRATCH        {:?}", radio.SCRATCH().read()?);
-    println!("PWRMODE        {:?}", radio.PWRMODE().read()?);
+    println!("PWRMODE        {}", radio.PWRMODE().read()?);
     println!("POWSTAT        {:?}", radio.POWSTAT().read()?);
     println!("POWSTICKYSTAT  {:?}", radio.POWSTICKYSTAT().read()?);
     println!("POWIRQMASK     {:?}", radio.POWIRQMASK().read()?);
@@ -31,7 +31,7 @@ fn main() -> Result<()> {
     println!("FEC            {:?}", radio.FEC().read()?);
     println!("FECSYNC        {:?}", radio.FECSYNC().read()?);
     println!("FECSTATUS      {:?}", radio.FECSTATUS().read()?);
-    println!("RADIOSTATE     {:?}", radio.RADIOSTATE().read()?);
+    println!("RADIOSTATE     {}", radio.RADIOSTATE().read()?);
     println!("XTALSTATUS     {:?}", radio.XTALSTATUS().read()?);
     println!("PINSTATE       {:?}", radio.PINSTATE().read()?);
     println!("PINFUNCSYSCLK  {:?}", radio.PINFUNCSYSCLK().read()?);
@@ -41,7 +41,7 @@ fn main() -> Result<()> {
     println!("PINFUNCANTSEL  {:?}", radio.PINFUNCANTSEL().read()?);
     println!("PINFUNCPWRAMP  {:?}", radio.PINFUNCPWRAMP().read()?);
     println!("PWRAMP         {:?}", radio.PWRAMP().read()?);
-    println!("FIFOSTAT       {:?}", radio.FIFOSTAT().read()?);
+    println!("FIFOSTAT       {}", radio.FIFOSTAT().read()?);
     println!("FIFODATA       {:?}", radio.FIFODATA().read()?);
     println!("FIFOCOUNT      {:?}", radio.FIFOCOUNT().read()?);
     println!("FIFOFREE       {:?}", radio.FIFOFREE().read()?);
@@ -116,7 +116,7 @@ fn main() -> Result<()> {
     let mut status = Status::empty();
     let mut callback = |_: &_, _addr, s, _data: &_| {
         if s != status {
-            println!("TX Status change: {:?}", s);
+            println!("TX Status change: {}", s);
             status = s;
         }
         //println!("{addr:03X}: {data:02X?}");
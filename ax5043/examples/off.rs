@@ -22,7 +22,7 @@ fn main() -> Result<()> {
     let args = Args::parse();
 
     let callback = &mut |_: &_, _, status, _: &_| {
-        println!("{:?}", status);
+        println!("{}", status);
     };
 
     for path in args.path.iter() {
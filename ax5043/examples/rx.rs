@@ -113,8 +113,8 @@ fn main() -> Result<()> {
     let contents = read_to_string(args.config)?;
     let config: config::Config = toml::from_str(&contents)?;
     config.write(&mut radio)?;
-    radio.RADIOEVENTMASK().write(RadioEvent::all())?;
 
+    CommState::HELLO(PROTOCOL).send(&uplink)?;
     CommState::BOARD(config.board.clone()).send(&uplink)?;
 
     CommState::REGISTERS(StatusRegisters::new(&mut radio)?).send(&uplink)?;
@@ -156,7 +156,24 @@ fn main() -> Result<()> {
     const IRQ: Token = Token(3);
     registry.register(&mut SourceFd(&irq.as_raw_fd()), IRQ, Interest::READABLE)?;
 
-    radio.IRQMASK().write(IRQ::FIFONOTEMPTY)?;
+    irq::IrqConfig {
+        fifo: IRQ::FIFONOTEMPTY,
+        radio: RadioEvent::DONE | RadioEvent::RADIOSTATECHG,
+        ..irq::IrqConfig::default()
+    }
+    .write(&mut radio)?;
+    let fifo = uplink.try_clone()?;
+    let mut dispatcher = irq::Dispatcher::default()
+        .on(irq::Source::Fifo, move |radio, _| {
+            if let Err(e) = read_packet(radio, &fifo) {
+                println!("{:?}", e);
+            }
+            Ok(())
+        })
+        .on(irq::Source::RadioCtrl, |_, event| {
+            println!("{:?}", event);
+            Ok(())
+        });
 
     let mut events = Events::with_capacity(128);
     'outer: loop {
@@ -165,18 +182,15 @@ fn main() -> Result<()> {
             match event.token() {
                 TELEMETRY => {
                     tfd.read();
-                    CommState::STATE(RXState::new(&mut radio, &config.channel[0])?)
+                    CommState::HELLO(PROTOCOL).send(&uplink)?;
+                    CommState::STATE(RXState::new(&mut radio, &config.board, &config.channel[0])?)
                         .send(&uplink)?;
                     CommState::REGISTERS(StatusRegisters::new(&mut radio)?).send(&uplink)?;
                 }
                 IRQ => {
                     while irq.has_edge_event()? {
                         irq.read_edge_event()?;
-                        let r = read_packet(&mut radio, &uplink);
-                        if r.is_err() {
-                            println!("{:?}", r);
-                            //uplink.send(CommState::ERR(r))?;
-                        }
+                        dispatcher.dispatch(&mut radio)?;
                     }
                     //radio.FIFOCMD().write(FIFOCmd {
                     //    mode: FIFOCmds::CLEAR_DATA,
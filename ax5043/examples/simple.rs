@@ -6,7 +6,7 @@ fn main() -> Result<()> {
     let mut status = Status::empty();
     let mut callback = |_: &_, _, s, _: &_| {
         if s != status {
-            println!("TX Status change: {:?}", s);
+            println!("TX Status change: {}", s);
             status = s;
         }
     };
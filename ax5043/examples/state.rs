@@ -37,7 +37,7 @@ fn main() -> Result<()> {
     let mut status = Status::empty();
     let mut callback = |_: &_, _, s, _: &_| {
         if s != status {
-            println!("TX Status change: {:?}", s);
+            println!("TX Status change: {}", s);
             status = s;
         }
     };
@@ -55,7 +55,7 @@ fn main() -> Result<()> {
     // reading it causes BEVMODEM amd BEVANA (the brownout detectors) to reset
     // and the SPI status to mark PWRGOOD
 
-    println!("PWRMODE:        {:?}", radio.PWRMODE().read()?);
+    println!("PWRMODE:        {}", radio.PWRMODE().read()?);
     print_powerstate(&mut radio, &mut state)?;
     println!("\nRef eneable");
     // NOTE: REFEN enables REF and VREF in POWSTAT
@@ -1,29 +1,112 @@
 use anyhow::Result;
 use ax5043::registers::*;
+use ax5043::telemetry::Frames;
 use ax5043::tui::*;
 use ax5043::*;
-use ciborium;
+use clap::Parser;
 use crossterm::{
     event::{Event, KeyCode, KeyEvent},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use itertools::Itertools;
-use mio::{net::UdpSocket, unix::SourceFd, Events, Interest, Poll, Token};
+use mio::{
+    net::{TcpListener, TcpStream, UdpSocket},
+    unix::SourceFd,
+    Events, Interest, Poll, Token,
+};
 use mio_signals::{Signal, Signals};
 use ratatui::{backend::CrosstermBackend, prelude::*, widgets::*, Terminal};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::{backtrace::Backtrace, collections::VecDeque, io, os::fd::AsRawFd, panic};
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    io::{self, Read},
+    os::fd::AsRawFd,
+    panic,
+    time::{Duration, Instant, SystemTime},
+};
+
+#[derive(Parser, Debug)]
+/// Try it out: `cargo run --example tui -- --replay pass.tl`
+struct Args {
+    /// Port a daemon's --telemetry points at, repeat for several radios. Daemons sharing a
+    /// port get a tab each too.
+    #[arg(short, long, default_value = "10035")]
+    port: Vec<u16>,
+    /// Save the telemetry to this file, see ax5043::recording
+    #[arg(long)]
+    record: Option<String>,
+    /// Play back a --record file instead of listening. It doesn't say which daemon sent what, so
+    /// everything shows up as one radio.
+    #[arg(long)]
+    replay: Option<String>,
+    /// Playback speed, 2 for twice as fast
+    #[arg(long, default_value = "1.0", value_parser = parse_speed)]
+    speed: f64,
+    /// Also accept daemons connecting with --telemetry tcp://... on this port
+    #[arg(long)]
+    tcp: Option<u16>,
+    /// Seconds of RSSI/AGC/frequency history to plot
+    #[arg(long, default_value = "300")]
+    history: u64,
+    /// Seal console commands with the daemons' [control] key, see ax5043::auth
+    #[arg(long)]
+    control_key: Option<String>,
+    /// Encrypt them too
+    #[arg(long)]
+    control_encrypt: bool,
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(_) => Err("must be positive".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
 // FIXME: Default isn't really the way to go, maybe ::new()?
 
 struct UIState {
     board: config::Board,
-    rx: VecDeque<RXState>,
+    /// Periodic STATE samples, see --history
+    rx: History,
     packets: VecDeque<(usize, usize, FIFOChunkRX)>,
     status: Status,
     reg: StatusRegisters,
     config: Config,
+    stats: rx::Stats,
+    /// Why the radio was last reset
+    watchdog: Option<watchdog::Reason>,
+    /// From CommState::SUPPLY
+    supply: supply::History,
+    waterfall: Waterfall,
+    /// Frames from the uhf/lband daemons, see CommState::PACKET
+    log: PacketLog,
+    /// Daemon command being typed after ':', see control::Command::remote
+    command: Option<String>,
+    /// Outcome of the last command
+    message: String,
+    /// Where telemetry comes from, writes are sent back there
+    daemon: Option<SocketAddr>,
+    /// The daemon's TCP connection went away, see Radios::reconnected()
+    closed: bool,
+    /// Waterfall in place of the parameter panes, toggled with 'w'
+    show_waterfall: bool,
+    constellation: Constellation,
+    /// Constellation in place of the parameter panes (or waterfall), toggled with 'd'
+    show_constellation: bool,
+    offset: OffsetTrack,
+    /// OffsetTrack in place of the parameter panes, toggled with 'f'
+    show_offset: bool,
+    link: Link,
+    /// From CommState::RADIO
+    name: Option<String>,
+    /// Index of the socket this radio's telemetry arrives on
+    socket: usize,
+    /// When the last datagram arrived
+    heard: Option<Instant>,
     counter: usize,
 }
 
@@ -31,12 +114,12 @@ impl Default for UIState {
     fn default() -> Self {
         Self {
             board: config::Board::default(),
-            rx: VecDeque::<RXState>::default(),
+            rx: History::new(Duration::from_secs(300)),
             packets: VecDeque::<(usize, usize, FIFOChunkRX)>::default(),
             status: Status::empty(),
             reg: StatusRegisters {
                 ranginga: PLLRanging {
-                    vcor: 0,
+                    vcor: U4::new(0),
                     flags: PLLRangingFlags::empty(),
                 },
                 pwrmode: PwrMode {
@@ -47,6 +130,7 @@ impl Default for UIState {
                 irq: IRQ::empty(),
                 radio_event: RadioEvent::empty(),
                 radio_state: RadioState::IDLE,
+                fifo: FIFOState::default(),
             },
             config: Config {
                 txparams: TXParameters::default(),
@@ -60,37 +144,57 @@ impl Default for UIState {
                 packet_format: PacketFormat::default(),
                 channel: ChannelParameters::default(),
             },
+            stats: rx::Stats::default(),
+            watchdog: None,
+            supply: supply::History::default(),
+            waterfall: Waterfall::default(),
+            log: PacketLog::default(),
+            command: None,
+            message: String::new(),
+            daemon: None,
+            closed: false,
+            show_waterfall: false,
+            constellation: Constellation::default(),
+            show_constellation: false,
+            offset: OffsetTrack::default(),
+            show_offset: false,
+            link: Link::default(),
+            name: None,
+            socket: 0,
+            heard: None,
             counter: 0,
         }
     }
 }
 
 impl UIState {
-    fn chart<'a, T>(&self, area: Rect, buf: &mut Buffer, name: T, unit: T, data: &'a [f64])
+    fn chart<T>(&self, area: Rect, buf: &mut Buffer, name: T, unit: T, value: fn(&RXState) -> f64)
     where
         T: AsRef<str> + std::fmt::Display,
     {
-        let min = data.iter().copied().fold(f64::NAN, f64::min).round() - 1.0;
-        let max = data.iter().copied().fold(f64::NAN, f64::max).round() + 1.0;
-        let values = data
-            .iter()
-            .enumerate()
-            .map(|(i, v)| (i as f64, *v))
-            .collect::<Vec<(f64, f64)>>();
+        let values = self.rx.series(Instant::now(), value);
+        let min = values.iter().map(|v| v.1).fold(f64::NAN, f64::min).round() - 1.0;
+        let max = values.iter().map(|v| v.1).fold(f64::NAN, f64::max).round() + 1.0;
         let set = vec![Dataset::default()
             .graph_type(GraphType::Line)
             .marker(symbols::Marker::Braille)
             .style(Style::default().fg(Color::Red))
             .data(&values)];
 
-        let x_axis = Axis::default().bounds([0.0, data.len() as f64]);
+        let window = self.rx.window().as_secs_f64();
+        let x_axis = Axis::default()
+            .bounds([-window, 0.0])
+            .labels(vec![format!("-{}s", window).into(), "now".into()]);
 
         let y_axis = Axis::default()
             .bounds([min, max])
             .labels(vec![min.to_string().into(), max.to_string().into()]);
 
         let chart = Chart::new(set)
-            .block(Block::default().title(format!("{} ({})", name, unit)))
+            .block(Block::default().title(match self.rx.latest() {
+                Some(latest) => format!("{} ({}) {:.1}", name, unit, value(latest)),
+                None => format!("{} ({})", name, unit),
+            }))
             .style(Style::default().fg(Color::Black).bg(Color::White))
             .x_axis(x_axis)
             .y_axis(y_axis);
@@ -99,7 +203,11 @@ impl UIState {
     }
 
     fn update(&mut self, buf: &[u8], amt: usize) -> Result<()> {
-        match ciborium::de::from_reader(&buf[..amt])? {
+        self.heard = Some(Instant::now());
+        let Some(state) = self.link.decode(&buf[..amt]) else {
+            return Ok(());
+        };
+        match state {
             CommState::RX(chunk) => {
                 self.packets.push_front((self.counter, 0 /*len*/, chunk));
                 self.packets.truncate(10);
@@ -111,12 +219,86 @@ impl UIState {
                 self.status = status;
             }
             CommState::STATE(state) => {
-                self.rx.push_front(state);
-                self.rx.truncate(100);
+                let hz = OffsetTrack::hz(state.rffreq, &self.board);
+                self.offset.push(Instant::now(), hz);
+                self.rx.push(Instant::now(), state);
             }
             CommState::REGISTERS(reg) => self.reg = reg,
             CommState::BOARD(board) => self.board = board,
             CommState::CONFIG(conf) => self.config = conf,
+            CommState::STATS(stats) => self.stats = stats,
+            CommState::WATCHDOG(reason) => self.watchdog = Some(reason),
+            CommState::SWEEP(sweep) => {
+                // First sweep switches to the waterfall, after that it's up to the user
+                self.show_waterfall |= self.waterfall.is_empty();
+                self.waterfall.push(sweep);
+            }
+            CommState::PACKET(frame) => self.log.push(frame),
+            CommState::TXSTATE(_) => (),
+            CommState::HELLO(_) => (),
+            CommState::ABORT(_) => (),
+            CommState::RADIO(name) => self.name = Some(name),
+            CommState::TRACKING(samples) => self.constellation.push(samples),
+            CommState::PASS(report) => {
+                self.message = format!("(pass: {})", report);
+                self.offset.clear();
+            }
+            CommState::FALLBACK(switch) => self.message = format!("(rate: {})", switch),
+            CommState::SUPPLY(event) => {
+                self.message = format!("(supply: {})", event);
+                self.supply.push(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UIState {
+    /// Tab label: the daemon's name for it, else where it's sending from
+    fn title(&self) -> String {
+        match (&self.name, self.daemon) {
+            (Some(name), _) => name.clone(),
+            (None, Some(daemon)) => daemon.to_string(),
+            (None, None) => "replay".to_string(),
+        }
+    }
+
+    /// Handles a key while the command line is open
+    /// `socket` is the UDP socket the telemetry came in on, None over TCP
+    fn command_key(
+        &mut self,
+        code: KeyCode,
+        socket: Option<&UdpSocket>,
+        auth: Option<&auth::ControlAuth>,
+    ) -> Result<()> {
+        let Some(ref mut command) = self.command else {
+            return Ok(());
+        };
+        match code {
+            KeyCode::Char(c) => command.push(c),
+            KeyCode::Backspace => _ = command.pop(),
+            KeyCode::Esc => self.command = None,
+            KeyCode::Enter => {
+                self.message = match (command.parse::<control::Command>(), self.daemon, socket) {
+                    (Err(e), _, _) => format!("({})", e),
+                    (Ok(cmd), _, _) if !cmd.remote() => {
+                        "(only write/test/beacon/squelch, the rest is on the control socket)"
+                            .to_string()
+                    }
+                    (Ok(_), None, _) => "(no telemetry received yet)".to_string(),
+                    (Ok(_), _, None) => "(commands need UDP telemetry)".to_string(),
+                    (Ok(_), Some(daemon), Some(socket)) => {
+                        let datagram = match auth {
+                            Some(auth) => auth.seal(command.trim(), SystemTime::now()),
+                            None => command.trim().as_bytes().to_vec(),
+                        };
+                        socket.send_to(&datagram, daemon)?;
+                        format!("(sent {})", command.trim())
+                    }
+                };
+                command.clear();
+            }
+            _ => (),
         }
         Ok(())
     }
@@ -145,6 +327,7 @@ impl Widget for &UIState {
                     Constraint::Min(46),
                     Constraint::Min(85),
                     Constraint::Min(40),
+                    Constraint::Min(15),
                     Constraint::Min(0),
                 ]
                 .as_ref(),
@@ -155,6 +338,10 @@ impl Widget for &UIState {
         self.reg.irq.render(power[2], buf);
         self.reg.radio_event.render(power[3], buf);
         self.reg.radio_state.render(power[4], buf);
+        self.reg
+            .fifo
+            .gauge(self.stats.fifo_errors)
+            .render(power[5], buf);
 
         let rx = Layout::default()
             .direction(Direction::Horizontal)
@@ -162,39 +349,65 @@ impl Widget for &UIState {
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
             .split(chunks[1]);
 
-        let parameters = Layout::default()
-            .direction(Direction::Vertical)
-            .margin(1)
-            .constraints(
-                [
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(20),
-                ]
-                .as_ref(),
-            )
-            .split(rx[1]);
+        if self.show_offset {
+            self.offset.render(rx[1], buf);
+        } else if self.show_constellation {
+            self.constellation.render(rx[1], buf);
+        } else if self.show_waterfall {
+            self.waterfall.render(rx[1], buf);
+        } else {
+            let parameters = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(1)
+                .constraints(
+                    [
+                        Constraint::Percentage(20),
+                        Constraint::Percentage(20),
+                        Constraint::Percentage(20),
+                        Constraint::Percentage(20),
+                        Constraint::Percentage(20),
+                    ]
+                    .as_ref(),
+                )
+                .split(rx[1]);
 
-        self.config.synthesizer.render(parameters[0], buf);
-        self.config.packet_controller.render(parameters[1], buf);
-        let packets = Paragraph::new(
-            self.packets
-                .iter()
-                .map(|x| format!("{}: {} {:02X?}", x.0, x.1, x.2))
-                .join("\n"),
-        )
-        .style(Style::default().fg(Color::Yellow))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Packets received")
-                .border_type(BorderType::Rounded),
-        );
-        packets.render(parameters[2], buf);
-        self.config.packet_format.render(parameters[3], buf);
-        self.config.rxparams.render(parameters[4], buf);
+            self.config.synthesizer.render(parameters[0], buf);
+            self.config.packet_controller.render(parameters[1], buf);
+            let packets = Paragraph::new(
+                self.packets
+                    .iter()
+                    .map(|x| format!("{}: {} {:02X?}", x.0, x.1, x.2))
+                    .join("\n"),
+            )
+            .style(Style::default().fg(Color::Yellow))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(match self.watchdog {
+                        Some(reason) => format!(
+                            "Packets received: {}, reset: {}, {}, {}",
+                            self.stats,
+                            reason,
+                            self.supply.counts(),
+                            self.link
+                        ),
+                        None => format!(
+                            "Packets received: {}, {}, {}",
+                            self.stats,
+                            self.supply.counts(),
+                            self.link
+                        ),
+                    })
+                    .border_type(BorderType::Rounded),
+            );
+            if self.log.is_empty() {
+                packets.render(parameters[2], buf);
+            } else {
+                self.log.render(parameters[2], buf);
+            }
+            self.config.packet_format.render(parameters[3], buf);
+            self.config.rxparams.render(parameters[4], buf);
+        }
         /*
         self.receiver_parameter_set(&state.set0, state.rx.back().unwrap_or(&RXState::default()).paramcurset.number == RxParamSet::Set0).render(parameters[2], buf);
         self.receiver_parameter_set(&state.set1, state.rx.back().unwrap_or(&RXState::default()).paramcurset.number == RxParamSet::Set1).render(parameters[2], buf);
@@ -220,88 +433,208 @@ impl Widget for &UIState {
             )
             .split(rx[0]);
 
-        self.chart(
-            sparks[0],
-            buf,
-            "RSSI",
-            "dB",
-            &self.rx.iter().map(|r| r.rssi).collect::<Vec<f64>>(),
-        );
-        self.chart(
-            sparks[1],
-            buf,
-            "AGC Counter",
-            "dB",
-            &self.rx.iter().map(|r| r.agccounter).collect::<Vec<f64>>(),
-        );
-        self.chart(
-            sparks[2],
-            buf,
-            "Amplitude",
-            "",
-            &self.rx.iter().map(|r| r.ampl).collect::<Vec<f64>>(),
-        );
-        self.chart(
-            sparks[3],
-            buf,
-            "RF Frequency (carrier?)",
-            "Δ Hz",
-            &self.rx.iter().map(|r| r.rffreq).collect::<Vec<f64>>(),
-        );
-        self.chart(
-            sparks[4],
-            buf,
-            "Phase",
-            "",
-            &self.rx.iter().map(|r| r.phase).collect::<Vec<f64>>(),
-        );
-        self.chart(
-            sparks[5],
-            buf,
-            "Data Rate",
-            "Δ bits/s",
-            &self.rx.iter().map(|r| r.datarate).collect::<Vec<f64>>(),
-        );
-        self.chart(
-            sparks[6],
-            buf,
-            "FSK Demodulation",
-            "",
-            &self.rx.iter().map(|r| r.fskdemod).collect::<Vec<f64>>(),
-        );
-        self.chart(
-            sparks[7],
-            buf,
-            "Frequency (intermediate?)",
-            "Δ Hz",
-            &self.rx.iter().map(|r| r.freq).collect::<Vec<f64>>(),
-        );
+        self.chart(sparks[0], buf, "RSSI", "dBm", |r| r.rssi);
+        self.chart(sparks[1], buf, "AGC Counter", "dB", |r| r.agccounter);
+        self.chart(sparks[2], buf, "Amplitude", "", |r| r.ampl);
+        self.chart(sparks[3], buf, "RF Frequency (carrier?)", "Δ Hz", |r| {
+            r.rffreq
+        });
+        self.chart(sparks[4], buf, "Phase", "", |r| r.phase);
+        self.chart(sparks[5], buf, "Data Rate Error", "bits/s", |r| {
+            r.quality.datarate_error
+        });
+        self.chart(sparks[6], buf, "FSK Deviation", "Hz", |r| {
+            r.quality.deviation
+        });
+        self.chart(sparks[7], buf, "Frequency (intermediate?)", "Δ Hz", |r| {
+            r.freq
+        });
 
         // TODO: Title like how it used to be
         //.block(Block::default().borders(Borders::ALL).title(format!(
         //    "Current RX Parameters - stage {} ({:?}) special {}",
         //    last.index, last.number, last.special
         //)))
-        self.chart(
-            sparks[8],
-            buf,
-            "Current RX Parameters",
-            "",
-            &self
-                .rx
+        self.chart(sparks[8], buf, "Current RX Parameters", "", |r| {
+            f64::from(r.paramcurset.index)
+        });
+        match self.command {
+            Some(ref command) => Paragraph::new(format!(":{}_", command))
+                .block(Block::default().borders(Borders::ALL).title(format!(
+                    "write REG N | test [LEN] | beacon on|off | squelch DBM|off, Enter to send, \
+                     Esc to close {}",
+                    self.message
+                )))
+                .render(chunks[2], buf),
+            None => self.status.render(chunks[2], buf),
+        }
+    }
+}
+
+/// Every daemon heard from, one tab each after the summary
+struct Radios {
+    radios: Vec<UIState>,
+    /// 0 is the summary, radios[selected - 1] otherwise
+    selected: usize,
+    history: Duration,
+}
+
+impl Radios {
+    /// The radio sending from `daemon` to `socket`, added if it's new
+    fn radio(&mut self, socket: usize, daemon: Option<SocketAddr>) -> &mut UIState {
+        let found = self
+            .radios
+            .iter()
+            .position(|r| r.socket == socket && r.daemon == daemon);
+        let index = match found {
+            Some(index) => index,
+            None => {
+                self.radios.push(UIState {
+                    rx: History::new(self.history),
+                    socket,
+                    daemon,
+                    ..Default::default()
+                });
+                // With just one radio there's nothing to summarize
+                if self.radios.len() == 1 {
+                    self.selected = 1;
+                }
+                self.radios.len() - 1
+            }
+        };
+        &mut self.radios[index]
+    }
+
+    /// A daemon that reconnected over TCP takes back the tab of its last connection, once its
+    /// CommState::RADIO says which one that was: same host, same name, connection closed
+    fn reconnected(&mut self, daemon: SocketAddr) {
+        let Some(new) = self.radios.iter().position(|r| r.daemon == Some(daemon)) else {
+            return;
+        };
+        let old = self.radios.iter().position(|r| {
+            r.closed
+                && r.socket == self.radios[new].socket
+                && r.daemon.map(|d| d.ip()) == Some(daemon.ip())
+                && r.name.is_some()
+                && r.name == self.radios[new].name
+        });
+        if let Some(old) = old {
+            self.radios[old].daemon = Some(daemon);
+            self.radios[old].closed = false;
+            self.radios.remove(new);
+            if self.selected > new {
+                self.selected -= 1;
+            }
+        }
+    }
+
+    fn current(&mut self) -> Option<&mut UIState> {
+        let selected = self.selected.checked_sub(1)?;
+        self.radios.get_mut(selected)
+    }
+
+    fn select(&mut self, tab: usize) {
+        if tab <= self.radios.len() {
+            self.selected = tab;
+        }
+    }
+
+    /// Steps through the tabs, wrapping around
+    fn cycle(&mut self, step: isize) {
+        let tabs = self.radios.len() as isize + 1;
+        self.selected = (self.selected as isize + step).rem_euclid(tabs) as usize;
+    }
+
+    fn summary(&self, area: Rect, buf: &mut Buffer) {
+        let rows = self.radios.iter().map(|r| {
+            let row = Row::new(vec![
+                r.title(),
+                format!("{:?}", r.reg.radio_state),
+                r.rx.latest()
+                    .map_or(String::new(), |s| format!("{:.0}", s.rssi)),
+                format!("{:.0}%", r.reg.fifo.ratio() * 100.0),
+                r.heard
+                    .map_or(String::new(), |h| format!("{}s", h.elapsed().as_secs())),
+                r.stats.to_string(),
+                r.link.to_string(),
+                r.watchdog.map_or(String::new(), |w| w.to_string()),
+            ]);
+            match r.link.mismatch() {
+                true => row.style(Style::default().fg(Color::Red)),
+                false => row,
+            }
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(16),
+                Constraint::Length(13),
+                Constraint::Length(5),
+                Constraint::Length(5),
+                Constraint::Length(6),
+                Constraint::Min(60),
+                Constraint::Length(30),
+                Constraint::Min(0),
+            ],
+        )
+        .header(
+            Row::new(vec![
+                "Radio",
+                "State",
+                "RSSI",
+                "FIFO",
+                "Heard",
+                "Packets",
+                "Telemetry",
+                "Reset",
+            ])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Summary"));
+        Widget::render(table, area, buf);
+    }
+}
+
+impl Widget for &Radios {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+            .split(area);
+        let titles = std::iter::once("Summary".to_string()).chain(
+            self.radios
                 .iter()
-                .map(|r| f64::from(r.paramcurset.index))
-                .collect::<Vec<f64>>(),
+                .enumerate()
+                .map(|(i, r)| format!("{} {}", i + 1, r.title())),
         );
-        self.status.render(chunks[2], buf);
+        Tabs::new(titles)
+            .select(self.selected)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .render(chunks[0], buf);
+        match self.selected.checked_sub(1) {
+            Some(selected) => self.radios[selected].render(chunks[1], buf),
+            None => self.summary(chunks[1], buf),
+        }
     }
 }
 
-fn run_ui(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
-    let mut state = UIState::default();
-    terminal.draw(|f| {
-        f.render_widget(&state, f.size());
-    })?;
+fn run_ui(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, args: &Args) -> Result<()> {
+    let mut radios = Radios {
+        radios: Vec::new(),
+        selected: 0,
+        history: Duration::from_secs(args.history),
+    };
+    let draw = |terminal: &mut Terminal<_>, radios: &Radios| -> Result<()> {
+        terminal.draw(|f| {
+            f.render_widget(radios, f.size());
+        })?;
+        Ok(())
+    };
+    draw(terminal, &radios)?;
+    let auth = match args.control_key {
+        Some(ref key) => Some(auth::ControlAuth::new(key, args.control_encrypt)?),
+        None => None,
+    };
 
     let mut poll = Poll::new()?;
     let registry = poll.registry();
@@ -317,36 +650,177 @@ fn run_ui(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
         Interest::READABLE,
     )?;
 
-    let src = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 10035);
-    let mut telemetry = UdpSocket::bind(src)?;
-    const TELEMETRY: Token = Token(2);
-    registry.register(&mut telemetry, TELEMETRY, Interest::READABLE)?;
+    // Any port while replaying, the daemon may be running next to us
+    let ports = match args.replay {
+        Some(_) => vec![0],
+        None => args.port.clone(),
+    };
+    // Telemetry sockets are Token(TELEMETRY + index)
+    const TELEMETRY: usize = 2;
+    let mut sockets = Vec::new();
+    for (i, port) in ports.into_iter().enumerate() {
+        let src = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+        let mut socket = UdpSocket::bind(src)?;
+        registry.register(&mut socket, Token(TELEMETRY + i), Interest::READABLE)?;
+        sockets.push(socket);
+    }
+
+    // Daemons connecting over TCP, see ax5043::telemetry. A tab per connection, so daemons on the
+    // same host each get their own, and a reconnect takes back its old tab, see
+    // Radios::reconnected(). Closed connections free their slot in `streams` for the next.
+    const LISTENER: Token = Token(usize::MAX);
+    const STREAMS: usize = 1 << 16;
+    const TCP: usize = usize::MAX;
+    let listener = match args.tcp {
+        Some(port) => {
+            let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+            let mut listener = TcpListener::bind(addr)?;
+            registry.register(&mut listener, LISTENER, Interest::READABLE)?;
+            Some(listener)
+        }
+        None => None,
+    };
+    let mut streams: Vec<Option<(TcpStream, Frames, SocketAddr)>> = Vec::new();
 
     let mut events = Events::with_capacity(128);
 
+    let mut recorder = match args.record {
+        Some(ref path) => Some(recording::create(path)?),
+        None => None,
+    };
+    let mut player = match args.replay {
+        Some(ref path) => Some(recording::open(path)?),
+        None => None,
+    };
+    let mut next = match player {
+        Some(ref mut player) => player.next_record()?,
+        None => None,
+    };
+    let first = next.as_ref().map_or(0, |r| r.micros);
+    let start = Instant::now();
+    // How long after start a recorded datagram is due
+    let due = |r: &recording::Record| {
+        Duration::from_micros(r.micros.saturating_sub(first)).div_f64(args.speed)
+    };
+
     'outer: loop {
-        poll.poll(&mut events, None)?;
+        let timeout = next
+            .as_ref()
+            .map(|r| due(r).saturating_sub(start.elapsed()));
+        poll.poll(&mut events, timeout)?;
+        if let Some(ref mut player) = player {
+            while let Some(record) = next.take_if(|r| due(r) <= start.elapsed()) {
+                radios
+                    .radio(0, None)
+                    .update(&record.datagram, record.datagram.len())?;
+                next = player.next_record()?;
+            }
+            draw(terminal, &radios)?;
+        }
         for event in events.iter() {
             match event.token() {
-                TELEMETRY => loop {
-                    let mut buf = [0; 2048];
-                    let Ok(amt) = telemetry.recv(&mut buf) else {
-                        break;
-                    };
-                    state.update(&buf, amt)?;
-                    terminal.draw(|f| {
-                        f.render_widget(&state, f.size());
-                    })?;
-                },
                 STDIN => match crossterm::event::read()? {
+                    Event::Key(KeyEvent { code, .. })
+                        if radios.current().is_some_and(|r| r.command.is_some()) =>
+                    {
+                        let radio = radios.current().unwrap();
+                        radio.command_key(code, sockets.get(radio.socket), auth.as_ref())?;
+                        draw(terminal, &radios)?;
+                    }
                     Event::Key(KeyEvent {
                         code: KeyCode::Char('c'),
                         ..
                     }) => break 'outer,
+                    Event::Key(KeyEvent { code, .. }) => {
+                        match (code, radios.current()) {
+                            (KeyCode::Tab, _) => radios.cycle(1),
+                            (KeyCode::BackTab, _) => radios.cycle(-1),
+                            (KeyCode::Char(c @ '0'..='9'), _) => {
+                                radios.select(c as usize - '0' as usize)
+                            }
+                            (KeyCode::Char('w'), Some(radio)) => {
+                                radio.show_waterfall = !radio.show_waterfall
+                            }
+                            (KeyCode::Char('d'), Some(radio)) => {
+                                radio.show_constellation = !radio.show_constellation
+                            }
+                            (KeyCode::Char('f'), Some(radio)) => {
+                                radio.show_offset = !radio.show_offset
+                            }
+                            (KeyCode::Char('r'), Some(radio)) => radio.offset.clear(),
+                            (KeyCode::Char(':'), Some(radio)) => {
+                                radio.command = Some(String::new())
+                            }
+                            (KeyCode::Up, Some(radio)) => radio.log.scroll(-1),
+                            (KeyCode::Down, Some(radio)) => radio.log.scroll(1),
+                            (KeyCode::PageUp, Some(radio)) => radio.log.scroll(-10),
+                            (KeyCode::PageDown, Some(radio)) => radio.log.scroll(10),
+                            _ => continue,
+                        }
+                        draw(terminal, &radios)?;
+                    }
                     _ => continue,
                 },
                 CTRLC => break 'outer,
-                _ => unreachable!(),
+                LISTENER => {
+                    let Some(ref listener) = listener else {
+                        continue;
+                    };
+                    while let Ok((mut stream, peer)) = listener.accept() {
+                        let slot = streams.iter().position(Option::is_none).unwrap_or_else(|| {
+                            streams.push(None);
+                            streams.len() - 1
+                        });
+                        poll.registry().register(
+                            &mut stream,
+                            Token(STREAMS + slot),
+                            Interest::READABLE,
+                        )?;
+                        streams[slot] = Some((stream, Frames::default(), peer));
+                    }
+                }
+                Token(token) if token >= STREAMS => {
+                    let Some((stream, frames, peer)) = &mut streams[token - STREAMS] else {
+                        continue;
+                    };
+                    let daemon = *peer;
+                    let mut buf = [0; 65536];
+                    let closed = loop {
+                        match stream.read(&mut buf) {
+                            Ok(0) => break true,
+                            Ok(amt) => frames.push(&buf[..amt]),
+                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break false,
+                            Err(_) => break true,
+                        }
+                    };
+                    for data in frames {
+                        if let Some(ref mut recorder) = recorder {
+                            recorder.write(&data)?;
+                        }
+                        radios.radio(TCP, Some(daemon)).update(&data, data.len())?;
+                        radios.reconnected(daemon);
+                    }
+                    if closed {
+                        if let Some((mut stream, _, _)) = streams[token - STREAMS].take() {
+                            poll.registry().deregister(&mut stream)?;
+                        }
+                        radios.radio(TCP, Some(daemon)).closed = true;
+                    }
+                    draw(terminal, &radios)?;
+                }
+                Token(token) => loop {
+                    let socket = token - TELEMETRY;
+                    // SWEEPs are bigger than the other states
+                    let mut buf = [0; 65536];
+                    let Ok((amt, src)) = sockets[socket].recv_from(&mut buf) else {
+                        break;
+                    };
+                    if let Some(ref mut recorder) = recorder {
+                        recorder.write(&buf[..amt])?;
+                    }
+                    radios.radio(socket, Some(src)).update(&buf, amt)?;
+                    draw(terminal, &radios)?;
+                },
             }
         }
     }
@@ -354,6 +828,7 @@ fn run_ui(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse();
     panic::set_hook(Box::new(|panic| {
         disable_raw_mode().unwrap();
         execute!(io::stdout(), LeaveAlternateScreen,).unwrap();
@@ -368,7 +843,7 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let err = run_ui(&mut terminal);
+    let err = run_ui(&mut terminal, &args);
 
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
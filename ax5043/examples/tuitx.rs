@@ -25,6 +25,8 @@ struct UIState {
     reg: StatusRegisters,
     config: Config,
     chan: ChannelParameters,
+    tx: TXState,
+    link: Link,
     counter: usize,
 }
 
@@ -36,7 +38,7 @@ impl Default for UIState {
             status: Status::empty(),
             reg: StatusRegisters {
                 ranginga: PLLRanging {
-                    vcor: 0,
+                    vcor: U4::new(0),
                     flags: PLLRangingFlags::empty(),
                 },
                 pwrmode: PwrMode {
@@ -47,6 +49,7 @@ impl Default for UIState {
                 irq: IRQ::empty(),
                 radio_event: RadioEvent::empty(),
                 radio_state: RadioState::IDLE,
+                fifo: FIFOState::default(),
             },
             config: Config {
                 txparams: TXParameters::default(),
@@ -61,6 +64,8 @@ impl Default for UIState {
                 channel: ChannelParameters::default(),
             },
             chan: ChannelParameters::default(),
+            tx: TXState::default(),
+            link: Link::default(),
             counter: 0,
         }
     }
@@ -117,6 +122,8 @@ impl Widget for &UIState {
         self.config.synthesizer.render(parameters[0], buf);
         self.config.txparams.render(parameters[1], buf);
         self.chan.render(parameters[2], buf);
+        self.tx.render(parameters[3], buf);
+        self.link.render(parameters[4], buf);
 
         self.status.render(chunks[2], buf);
     }
@@ -124,7 +131,10 @@ impl Widget for &UIState {
 
 impl UIState {
     fn update(&mut self, buf: &[u8], amt: usize) -> Result<()> {
-        match ciborium::de::from_reader(&buf[..amt])? {
+        let Some(state) = self.link.decode(&buf[..amt]) else {
+            return Ok(());
+        };
+        match state {
             CommState::TX(chunk) => {
                 self.packets.push_front((self.counter, 0 /*len*/, chunk));
                 self.packets.truncate(10);
@@ -138,12 +148,17 @@ impl UIState {
             CommState::BOARD(board) => self.board = board,
             CommState::CONFIG(conf) => self.config = conf,
             CommState::RX(_) => (),
-            // TODO: does TX have any visible state?
-            //CommState::STATE(state) => {
-            //    self.rx.push_front(state);
-            //    self.rx.truncate(100);
-            //}
             CommState::STATE(_) => (),
+            CommState::STATS(_) => (),
+            CommState::WATCHDOG(_) => (),
+            CommState::SWEEP(_) => (),
+            CommState::PACKET(_) => (),
+            CommState::TXSTATE(tx) => self.tx = tx,
+            CommState::HELLO(_) => (),
+            CommState::RADIO(_) => (),
+            CommState::ABORT(_) => (),
+            CommState::TRACKING(_) => (),
+            CommState::PASS(_) | CommState::FALLBACK(_) | CommState::SUPPLY(_) => (),
         }
         Ok(())
     }
@@ -156,6 +156,7 @@ fn main() -> Result<()> {
     config.write(&mut radio)?;
     radio.RADIOEVENTMASK().write(RadioEvent::DONE)?;
 
+    CommState::HELLO(PROTOCOL).send(&uplink)?;
     CommState::BOARD(config.board.clone()).send(&uplink)?;
     CommState::REGISTERS(StatusRegisters::new(&mut radio)?).send(&uplink)?;
     CommState::CONFIG(Config {
@@ -1,15 +1,13 @@
 use anyhow::Result;
-use ax5043::{config, Status};
+use ax5043::{config, rx::PacketAssembler, Status};
 use ax5043::{registers::*, Registers, RX, TX};
 use clap::Parser;
-use crc::{Crc, CRC_16_GENIBUS}; // TODO: this CRC works but is it correct?
 use gpiocdev::{line::EdgeDetection, Request};
 use mio::net::UdpSocket;
 use mio::{unix::SourceFd, Events, Interest, Poll, Token};
 use mio_signals::{Signal, Signals};
 use std::{
     fs::read_to_string,
-    io::Write,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     os::fd::AsRawFd,
 };
@@ -17,53 +15,19 @@ use toml;
 
 fn read_packet(
     radio: &mut Registers,
-    packet: &mut Vec<u8>,
+    assembler: &mut PacketAssembler,
     downlink: &mut UdpSocket,
 ) -> Result<()> {
-    let len = radio.FIFOCOUNT().read()?;
-    if len <= 0 {
-        return Ok(());
-    }
-
-    for chunk in radio.FIFODATARX().read(len.into())? {
-        if let FIFOChunkRX::DATA { flags, ref data } = chunk {
-            println!("{:02X?}", chunk);
-            if flags.intersects(
-                FIFODataRXFlags::ABORT
-                    | FIFODataRXFlags::SIZEFAIL
-                    | FIFODataRXFlags::ADDRFAIL
-                    | FIFODataRXFlags::CRCFAIL
-                    | FIFODataRXFlags::RESIDUE,
-            ) {
-                packet.clear();
-                continue;
-            }
-
-            if flags.contains(FIFODataRXFlags::PKTSTART) {
-                packet.clear();
-            }
-            packet.write(&data)?;
-            if flags.contains(FIFODataRXFlags::PKTEND) {
-                let bytes = packet.split_off(packet.len() - 2);
-                let checksum = u16::from_be_bytes([bytes[0], bytes[1]]);
-                let ccitt = Crc::<u16>::new(&CRC_16_GENIBUS);
-                let mut digest = ccitt.digest();
-                digest.update(packet);
-                let calculated = digest.finalize();
-
-                if calculated == checksum {
-                    downlink.send(packet)?;
-                    println!("UHF RX PACKET: {:02X?}", packet);
-                } else {
-                    println!(
-                        "Rejected CRC: received 0x{:x}, calculated 0x{:x}",
-                        checksum, calculated
-                    );
-                }
-            }
+    // Checked (and the CRC stripped or not) as the channel's crc_trailer says
+    assembler.drain_with(radio, |_, packet, _, crc_ok| -> Result<()> {
+        if crc_ok {
+            downlink.send(packet)?;
+            println!("UHF RX PACKET: {:02X?}", packet);
+        } else {
+            println!("UHF RX PACKET CRC failed: {:02X?}", packet);
         }
-    }
-    Ok(())
+        Ok(())
+    })
 }
 
 #[derive(Parser, Debug)]
@@ -105,7 +69,7 @@ fn main() -> Result<()> {
     let mut status = Status::empty();
     let mut callback = |_: &_, _, s, _: &_| {
         if s != status {
-            println!("TX Status change: {:?}", s);
+            println!("TX Status change: {}", s);
             status = s;
         }
     };
@@ -147,7 +111,7 @@ fn main() -> Result<()> {
         .IRQMASK()
         .write(ax5043::registers::IRQ::FIFONOTEMPTY)?;
 
-    let mut packet = Vec::new();
+    let mut assembler = PacketAssembler::new().trailer(config.channel[0].crc_trailer);
 
     'outer: loop {
         poll.poll(&mut events, None)?;
@@ -156,7 +120,7 @@ fn main() -> Result<()> {
                 IRQ => {
                     while uhf_irq.has_edge_event()? {
                         uhf_irq.read_edge_event()?;
-                        read_packet(&mut radio, &mut packet, &mut downlink)?;
+                        read_packet(&mut radio, &mut assembler, &mut downlink)?;
                     }
                 }
                 //TIMER => {
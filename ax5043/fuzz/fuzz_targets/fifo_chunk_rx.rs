@@ -0,0 +1,19 @@
+// Feeds arbitrary FIFODATA reads to the chunk parser: `cargo +nightly fuzz run fifo_chunk_rx`
+// from the ax5043 directory.
+//
+// Anything may come out of the FIFO during a pass (overflows, reads cut off mid chunk), so the
+// parser has to return an error rather than panic. Whatever parses has to encode back to the
+// same bytes.
+#![no_main]
+
+use ax5043::{registers::FIFOChunkRX, split_chunks};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(chunks) = split_chunks::<FIFOChunkRX>(data) {
+        let encoded: Vec<u8> = chunks.into_iter().flat_map(Vec::<u8>::from).collect();
+        assert_eq!(encoded, data);
+    }
+    // Single chunks as well, the same TryFrom the parser ends with
+    let _ = FIFOChunkRX::try_from(data.to_vec());
+});
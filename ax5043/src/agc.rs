@@ -0,0 +1,180 @@
+// AGC target, hysteresis and digital min/max, adjustable while receiving.
+//
+// The [setN] sections only reach the radio on a full write. Tuning::apply() changes just these
+// four settings in the parameter sets RXPARAMSETS points at, leaving the loop gains and the rest
+// of each set alone, so a pass can follow a changed noise floor without a reload.
+use crate::{registers::*, Registers, Result, RX, TX};
+use std::fmt;
+
+/// What's on the radio for one parameter set
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub target: u8,
+    pub hyst: U3,
+    pub min: U3,
+    pub max: U3,
+}
+
+impl Settings {
+    pub fn read(radio: &mut Registers, set: RxParamSet) -> Result<Self> {
+        let (target, hyst, minmax) = match set {
+            RxParamSet::Set0 => (
+                radio.AGCTARGET0().read()?,
+                radio.AGCAHYST0().read()?,
+                radio.AGCMINMAX0().read()?,
+            ),
+            RxParamSet::Set1 => (
+                radio.AGCTARGET1().read()?,
+                radio.AGCAHYST1().read()?,
+                radio.AGCMINMAX1().read()?,
+            ),
+            RxParamSet::Set2 => (
+                radio.AGCTARGET2().read()?,
+                radio.AGCAHYST2().read()?,
+                radio.AGCMINMAX2().read()?,
+            ),
+            RxParamSet::Set3 => (
+                radio.AGCTARGET3().read()?,
+                radio.AGCAHYST3().read()?,
+                radio.AGCMINMAX3().read()?,
+            ),
+        };
+        Ok(Self {
+            target,
+            hyst: hyst.hyst,
+            min: minmax.min,
+            max: minmax.max,
+        })
+    }
+
+    fn write(&self, radio: &mut Registers, set: RxParamSet) -> Result<()> {
+        let hyst = AGCHyst { hyst: self.hyst };
+        let minmax = AGCMinMax {
+            min: self.min,
+            max: self.max,
+        };
+        match set {
+            RxParamSet::Set0 => {
+                radio.AGCTARGET0().write(self.target)?;
+                radio.AGCAHYST0().write(hyst)?;
+                radio.AGCMINMAX0().write(minmax)
+            }
+            RxParamSet::Set1 => {
+                radio.AGCTARGET1().write(self.target)?;
+                radio.AGCAHYST1().write(hyst)?;
+                radio.AGCMINMAX1().write(minmax)
+            }
+            RxParamSet::Set2 => {
+                radio.AGCTARGET2().write(self.target)?;
+                radio.AGCAHYST2().write(hyst)?;
+                radio.AGCMINMAX2().write(minmax)
+            }
+            RxParamSet::Set3 => {
+                radio.AGCTARGET3().write(self.target)?;
+                radio.AGCAHYST3().write(hyst)?;
+                radio.AGCMINMAX3().write(minmax)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "target {} hyst {} min {} max {}",
+            self.target,
+            self.hyst.get(),
+            self.min.get(),
+            self.max.get()
+        )
+    }
+}
+
+/// The settings to change, None leaves one as it is
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tuning {
+    pub target: Option<u8>,
+    pub hyst: Option<U3>,
+    pub min: Option<U3>,
+    pub max: Option<U3>,
+}
+
+impl Tuning {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `settings` with this applied on top
+    pub fn over(&self, settings: Settings) -> Settings {
+        Settings {
+            target: self.target.unwrap_or(settings.target),
+            hyst: self.hyst.unwrap_or(settings.hyst),
+            min: self.min.unwrap_or(settings.min),
+            max: self.max.unwrap_or(settings.max),
+        }
+    }
+
+    /// Changes every set in use, returning each one's settings before and after. Safe while
+    /// receiving, nothing else in the sets is written.
+    pub fn apply(&self, radio: &mut Registers) -> Result<Vec<(RxParamSet, Settings, Settings)>> {
+        let mut changed = Vec::new();
+        for set in active_sets(radio)? {
+            let old = Settings::read(radio, set)?;
+            let new = self.over(old);
+            new.write(radio, set)?;
+            changed.push((set, old, new));
+        }
+        Ok(changed)
+    }
+}
+
+/// The sets RXPARAMSETS points the receiver stages at, each once
+pub fn active_sets(radio: &mut Registers) -> Result<Vec<RxParamSet>> {
+    let RxParamSets(a, b, c, d) = radio.RXPARAMSETS().read()?;
+    let mut sets = Vec::with_capacity(4);
+    for set in [a, b, c, d] {
+        if !sets.contains(&set) {
+            sets.push(set);
+        }
+    }
+    Ok(sets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuning_only_touches_the_agc() {
+        let config: crate::config::Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+        let (port, _) = crate::sim::pair(crate::sim::Channel::default());
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(crate::Bus::Sim(port), &mut callback);
+        config.write(&mut radio).unwrap();
+
+        // [stages] uses Set0 and Set3
+        assert_eq!(
+            active_sets(&mut radio).unwrap(),
+            vec![RxParamSet::Set0, RxParamSet::Set3]
+        );
+        let before = Settings::read(&mut radio, RxParamSet::Set3).unwrap();
+        let gain = radio.AGCGAIN3().read().unwrap();
+
+        let tuning = Tuning {
+            target: Some(0x90),
+            max: Some(U3::new(5)),
+            ..Tuning::default()
+        };
+        let changed = tuning.apply(&mut radio).unwrap();
+        assert_eq!(changed.len(), 2);
+        let after = Settings::read(&mut radio, RxParamSet::Set3).unwrap();
+        assert_eq!(after.target, 0x90);
+        assert_eq!(after.max, U3::new(5));
+        assert_eq!((after.hyst, after.min), (before.hyst, before.min));
+        assert_eq!(changed[1], (RxParamSet::Set3, before, after));
+        assert_eq!(radio.AGCGAIN3().read().unwrap(), gain);
+        assert!(Tuning::default().is_empty());
+    }
+}
@@ -0,0 +1,353 @@
+// HMAC authentication of frames before they're transmitted.
+//
+// With an [auth] section in the config, every frame arriving on the beacon and downlink sockets
+// must end in a truncated HMAC-SHA256 over the rest of the frame:
+//
+//   [auth]
+//   key = "00112233445566778899aabbccddeeff"
+//
+// The tag is stripped before transmission. Frames that don't verify are logged and dropped, so
+// an open UDP port on the flight computer isn't an unauthenticated transmitter.
+//
+// Operator commands (see control) can be held to a key of their own with a [control] section,
+// for a daemon whose control socket is reachable from the station LAN:
+//
+//   [control]
+//   key = "8899aabbccddeeff0011223344556677"
+//   encrypt = true
+//
+// Each command datagram is then sealed:
+//
+//   counter (u64 BE)  flags (u8)  command  tag (TAG_LEN)
+//
+// counter is the sender's clock in unix milliseconds. It must be past the last one accepted and
+// within WINDOW of the daemon's clock, so a captured command can't be played back later. With
+// bit 0 of flags set the command is encrypted, XORed with HMAC-SHA256 blocks over the counter
+// and block number, and encrypt = true refuses commands that aren't. The tag covers everything
+// before it. Encryption and authentication use keys derived from `key`, never `key` itself.
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+/// Bytes of HMAC appended to each frame
+pub const TAG_LEN: usize = 16;
+/// How far a sealed command's counter may be from the daemon's clock
+pub const WINDOW: Duration = Duration::from_secs(30);
+
+const HEADER_LEN: usize = 9;
+const ENCRYPTED: u8 = 1;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, Deserialize, PartialEq, Eq)]
+pub struct Auth {
+    /// Hex encoded shared key
+    #[serde(deserialize_with = "hex")]
+    key: Vec<u8>,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum KeyError {
+    #[error("key is empty")]
+    Empty,
+    #[error("key is not hex: {0:?}")]
+    Hex(String),
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, KeyError> {
+    if s.is_empty() {
+        return Err(KeyError::Empty);
+    }
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err(KeyError::Hex(s.into()));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| KeyError::Hex(s.into())))
+        .collect()
+}
+
+fn hex<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_hex(&s).map_err(serde::de::Error::custom)
+}
+
+// Keeps the key out of logs, Config is printed with {:?} in places
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Auth").field("key", &"..").finish()
+    }
+}
+
+impl Auth {
+    pub fn new(key: &str) -> Result<Self, KeyError> {
+        Ok(Self {
+            key: parse_hex(key)?,
+        })
+    }
+
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes any key length");
+        mac.update(payload);
+        mac
+    }
+
+    /// Appends the tag, for the ground side and tests
+    pub fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        let tag = self.mac(payload).finalize().into_bytes();
+        [payload, &tag[..TAG_LEN]].concat()
+    }
+
+    /// The payload without its tag, or None if the tag doesn't match (compared in constant
+    /// time)
+    pub fn verify<'a>(&self, frame: &'a [u8]) -> Option<&'a [u8]> {
+        let split = frame.len().checked_sub(TAG_LEN)?;
+        let (payload, tag) = frame.split_at(split);
+        self.mac(payload).verify_truncated_left(tag).ok()?;
+        Some(payload)
+    }
+}
+
+/// The [control] section
+#[derive(Clone, Deserialize, PartialEq, Eq)]
+pub struct ControlAuth {
+    /// Hex encoded shared key
+    #[serde(deserialize_with = "hex")]
+    key: Vec<u8>,
+    /// Refuse commands sent in the clear
+    #[serde(default)]
+    pub encrypt: bool,
+}
+
+impl fmt::Debug for ControlAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ControlAuth")
+            .field("key", &"..")
+            .field("encrypt", &self.encrypt)
+            .finish()
+    }
+}
+
+/// Why a control datagram was refused
+#[derive(Error, Debug, PartialEq)]
+pub enum Refused {
+    #[error("too short to be sealed")]
+    Short,
+    #[error("tag doesn't verify")]
+    Tag,
+    #[error("not encrypted")]
+    Clear,
+    #[error("counter {0} already used")]
+    Replay(u64),
+    #[error("counter {0} is outside the window")]
+    Stale(u64),
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl ControlAuth {
+    pub fn new(key: &str, encrypt: bool) -> Result<Self, KeyError> {
+        Ok(Self {
+            key: parse_hex(key)?,
+            encrypt,
+        })
+    }
+
+    fn derive(&self, label: &[u8]) -> HmacSha256 {
+        let mut derived = HmacSha256::new_from_slice(&self.key).expect("HMAC takes any key length");
+        derived.update(label);
+        let key = derived.finalize().into_bytes();
+        HmacSha256::new_from_slice(&key).expect("HMAC takes any key length")
+    }
+
+    /// XORs `data` with the keystream for `counter`, both ways
+    fn crypt(&self, counter: u64, data: &mut [u8]) {
+        let stream = self.derive(b"ax5043 control encrypt");
+        for (block, chunk) in data.chunks_mut(32).enumerate() {
+            let mut mac = stream.clone();
+            mac.update(&counter.to_be_bytes());
+            mac.update(&(block as u32).to_be_bytes());
+            for (byte, key) in chunk.iter_mut().zip(mac.finalize().into_bytes()) {
+                *byte ^= key;
+            }
+        }
+    }
+
+    fn mac(&self, sealed: &[u8]) -> HmacSha256 {
+        let mut mac = self.derive(b"ax5043 control authenticate");
+        mac.update(sealed);
+        mac
+    }
+
+    /// A command datagram sent at `at`, encrypted if the section asks for it. For the ground
+    /// side, see examples/control.rs.
+    pub fn seal(&self, command: &str, at: SystemTime) -> Vec<u8> {
+        let counter = unix_ms(at);
+        let mut sealed = counter.to_be_bytes().to_vec();
+        sealed.push(if self.encrypt { ENCRYPTED } else { 0 });
+        let mut body = command.as_bytes().to_vec();
+        if self.encrypt {
+            self.crypt(counter, &mut body);
+        }
+        sealed.extend(body);
+        let tag = self.mac(&sealed).finalize().into_bytes();
+        sealed.extend(&tag[..TAG_LEN]);
+        sealed
+    }
+
+    /// The counter and command out of a sealed datagram, not yet checked against replays
+    fn open(&self, datagram: &[u8]) -> Result<(u64, Vec<u8>), Refused> {
+        let split = datagram
+            .len()
+            .checked_sub(TAG_LEN)
+            .filter(|&split| split >= HEADER_LEN)
+            .ok_or(Refused::Short)?;
+        let (sealed, tag) = datagram.split_at(split);
+        self.mac(sealed)
+            .verify_truncated_left(tag)
+            .map_err(|_| Refused::Tag)?;
+        let counter = u64::from_be_bytes(sealed[..8].try_into().unwrap());
+        let mut body = sealed[HEADER_LEN..].to_vec();
+        match sealed[8] & ENCRYPTED {
+            0 if self.encrypt => return Err(Refused::Clear),
+            0 => (),
+            _ => self.crypt(counter, &mut body),
+        }
+        Ok((counter, body))
+    }
+}
+
+/// Where the bins let commands through, checking them against [control] if there is one
+#[derive(Debug, Default)]
+pub struct ControlGate {
+    auth: Option<ControlAuth>,
+    /// Counter of the last sealed command accepted
+    last: u64,
+}
+
+impl ControlGate {
+    pub fn new(auth: Option<ControlAuth>) -> Self {
+        Self { auth, last: 0 }
+    }
+
+    /// A new [control] after a reload. Counters already used stay used.
+    pub fn set_auth(&mut self, auth: Option<ControlAuth>) {
+        self.auth = auth;
+    }
+
+    pub fn sealed(&self) -> bool {
+        self.auth.is_some()
+    }
+
+    /// The command text in `datagram`, received at `now`. Without a [control] section that's
+    /// the datagram as is.
+    pub fn open(&mut self, datagram: &[u8], now: SystemTime) -> Result<String, Refused> {
+        let Some(ref auth) = self.auth else {
+            return Ok(String::from_utf8_lossy(datagram).into_owned());
+        };
+        let (counter, command) = auth.open(datagram)?;
+        if counter <= self.last {
+            return Err(Refused::Replay(counter));
+        }
+        if unix_ms(now).abs_diff(counter) > WINDOW.as_millis() as u64 {
+            return Err(Refused::Stale(counter));
+        }
+        self.last = counter;
+        Ok(String::from_utf8_lossy(&command).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_verify() {
+        let auth = Auth::new("00112233445566778899aabbccddeeff").unwrap();
+        let frame = auth.sign(b"hello");
+        assert_eq!(frame.len(), 5 + TAG_LEN);
+        assert_eq!(auth.verify(&frame), Some(&b"hello"[..]));
+
+        let mut bad = frame.clone();
+        bad[0] ^= 1;
+        assert_eq!(auth.verify(&bad), None);
+        assert_eq!(auth.verify(&frame[..TAG_LEN - 1]), None);
+        assert_eq!(Auth::new("ff").unwrap().verify(&frame), None);
+        assert_eq!(auth.verify(&auth.sign(b"")), Some(&b""[..]));
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(Auth::new("").unwrap_err(), KeyError::Empty);
+        assert_eq!(Auth::new("abc").unwrap_err(), KeyError::Hex("abc".into()));
+        assert_eq!(Auth::new("zz").unwrap_err(), KeyError::Hex("zz".into()));
+        let auth: Auth = toml::from_str("key = \"0aFF\"").unwrap();
+        assert_eq!(auth, Auth::new("0aff").unwrap());
+        assert_eq!(format!("{:?}", auth), "Auth { key: \"..\" }");
+    }
+
+    #[test]
+    fn control() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_760_529_600);
+        let key = "8899aabbccddeeff0011223344556677";
+        let clear = ControlAuth::new(key, false).unwrap();
+        let encrypted = ControlAuth::new(key, true).unwrap();
+        let mut gate = ControlGate::new(Some(encrypted.clone()));
+
+        let sealed = encrypted.seal("freq 437000000", now);
+        assert_eq!(sealed.len(), HEADER_LEN + 14 + TAG_LEN);
+        assert!(!sealed.windows(4).any(|w| w == b"freq"));
+        assert_eq!(gate.open(&sealed, now).unwrap(), "freq 437000000");
+        assert_eq!(
+            gate.open(&sealed, now),
+            Err(Refused::Replay(1_760_529_600_000))
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            gate.open(&clear.seal("tx closed", later), later),
+            Err(Refused::Clear)
+        );
+        let mut forged = encrypted.seal("tx closed", later);
+        forged[HEADER_LEN] ^= 1;
+        assert_eq!(gate.open(&forged, later), Err(Refused::Tag));
+        assert_eq!(gate.open(&forged[..TAG_LEN], later), Err(Refused::Short));
+        let other = ControlAuth::new("00", true).unwrap();
+        assert_eq!(
+            gate.open(&other.seal("tx closed", later), later),
+            Err(Refused::Tag)
+        );
+        let early = encrypted.seal("reload", later + WINDOW + Duration::from_millis(1));
+        assert!(matches!(gate.open(&early, later), Err(Refused::Stale(_))));
+
+        // Commands in the clear once encryption isn't required, still authenticated
+        gate.set_auth(Some(clear.clone()));
+        let sealed = clear.seal("reload", later);
+        assert!(sealed.windows(6).any(|w| w == b"reload"));
+        assert_eq!(gate.open(&sealed, later).unwrap(), "reload");
+        assert_eq!(
+            gate.open(&encrypted.seal("tx open", later + WINDOW), later + WINDOW)
+                .unwrap(),
+            "tx open"
+        );
+
+        let mut open = ControlGate::default();
+        assert!(!open.sealed());
+        assert_eq!(open.open(b"reload", now).unwrap(), "reload");
+        let section: ControlAuth = toml::from_str(&format!("key = \"{}\"", key)).unwrap();
+        assert_eq!(section, clear);
+        assert_eq!(
+            format!("{:?}", encrypted),
+            "ControlAuth { key: \"..\", encrypt: true }"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+// Register shell for bring-up: read, write, dump and watch registers by name.
+//
+//   read PLLRANGINGA
+//   write FREQA 0x1234
+//   dump PKT*
+//   watch RSSI 100ms
+//   info AGCGAIN0
+//
+// One command per line on stdin, so `rlwrap ax-shell` gives history and editing. Works on the raw
+// bytes through ax5043::regmap, fields are printed under each read. Doesn't reset the radio unless
+// asked to, and doesn't touch the PA: nothing stops a `write PWRMODE 0x6D` from keying up.
+use anyhow::{bail, Context, Result};
+use ax5043::{
+    regmap::{Access, RegisterInfo},
+    Registers,
+};
+use clap::Parser;
+use mio_signals::{Signal, SignalSet, Signals};
+use std::{
+    io::{stdin, stdout, BufRead, Write},
+    thread,
+    time::Duration,
+};
+
+#[derive(Parser, Debug)]
+/// Try it out: `rlwrap ax-shell`, then `read REVISION`
+struct Args {
+    #[arg(short, long, default_value = "/dev/spidev0.0")]
+    spi: String,
+    /// Reset the radio first
+    #[arg(long)]
+    reset: bool,
+}
+
+const HELP: &str = "\
+read NAME           read a register and its fields
+write NAME VALUE    write a register, VALUE decimal or 0x hex
+dump PATTERN        read every register matching, * for any run of characters
+watch NAME [EVERY]  read every EVERY (100ms, 1s, default 500ms) until Ctrl-C
+info PATTERN        address, width, access and fields without reading
+quit";
+
+fn lookup(name: &str) -> Result<&'static RegisterInfo> {
+    Registers::info(name).with_context(|| format!("No register {}", name))
+}
+
+fn matching(pattern: &str) -> Result<Vec<&'static RegisterInfo>> {
+    let regs: Vec<_> = Registers::MAP
+        .iter()
+        .filter(|r| r.matches(pattern))
+        .collect();
+    if regs.is_empty() {
+        bail!("Nothing matches {}", pattern);
+    }
+    Ok(regs)
+}
+
+fn parse_value(s: &str) -> Result<i128> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i128::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .with_context(|| format!("Invalid value {}", s))?;
+    Ok(if negative { -value } else { value })
+}
+
+/// 100ms, 2s, or plain milliseconds
+fn parse_interval(s: &str) -> Result<Duration> {
+    let invalid = || format!("Invalid interval {}", s);
+    if let Some(ms) = s.strip_suffix("ms") {
+        return Ok(Duration::from_millis(ms.parse().with_context(invalid)?));
+    }
+    if let Some(secs) = s.strip_suffix('s') {
+        return Ok(Duration::from_secs_f64(secs.parse().with_context(invalid)?));
+    }
+    Ok(Duration::from_millis(s.parse().with_context(invalid)?))
+}
+
+fn hex(raw: &[u8]) -> String {
+    raw.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn read(radio: &mut Registers, reg: &RegisterInfo) -> Result<Vec<u8>> {
+    match reg.access {
+        Access::ReadOnly | Access::ReadWrite => {
+            let burst = radio.read_burst(reg.addr, reg.width)?;
+            Ok(burst.get(reg.addr, reg.width).unwrap_or_default().to_vec())
+        }
+        _ => bail!("{} can't be read", reg.name),
+    }
+}
+
+fn print(reg: &RegisterInfo, raw: &[u8]) {
+    println!("{:<16} {:03X} {}", reg.name, reg.addr, hex(raw));
+    for field in (reg.fields)() {
+        println!("    {:<16} {:#X}", field.name, field.get(raw));
+    }
+}
+
+fn write(radio: &mut Registers, reg: &RegisterInfo, value: i128) -> Result<()> {
+    if !matches!(reg.access, Access::ReadWrite | Access::WriteOnly) {
+        bail!("{} can't be written", reg.name);
+    }
+    let bits = reg.width * 8;
+    let (min, max) = (-(1i128 << (bits - 1)), (1i128 << bits) - 1);
+    if !(min..=max).contains(&value) {
+        bail!("{} doesn't fit in {} bytes", value, reg.width);
+    }
+    let bytes = value.to_be_bytes();
+    radio.write_burst(reg.addr, &bytes[bytes.len() - reg.width..])?;
+    Ok(())
+}
+
+fn watch(
+    radio: &mut Registers,
+    signals: &mut Signals,
+    reg: &RegisterInfo,
+    every: Duration,
+) -> Result<()> {
+    loop {
+        let raw = read(radio, reg)?;
+        let fields: Vec<_> = (reg.fields)()
+            .iter()
+            .map(|f| format!("{}={:#X}", f.name, f.get(&raw)))
+            .collect();
+        println!("{} {}", hex(&raw), fields.join(" "));
+        thread::sleep(every);
+        if signals.receive()?.is_some() {
+            return Ok(());
+        }
+    }
+}
+
+fn run(radio: &mut Registers, signals: &mut Signals, line: &str) -> Result<bool> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [] => (),
+        ["quit"] | ["exit"] => return Ok(false),
+        ["help"] => println!("{}", HELP),
+        ["read", name] => {
+            let reg = lookup(name)?;
+            print(reg, &read(radio, reg)?);
+        }
+        ["write", name, value] => write(radio, lookup(name)?, parse_value(value)?)?,
+        ["dump", pattern] => {
+            for reg in matching(pattern)? {
+                // Skips the write only registers and the FIFO
+                if let Ok(raw) = read(radio, reg) {
+                    print(reg, &raw);
+                }
+            }
+        }
+        ["watch", name] => watch(radio, signals, lookup(name)?, Duration::from_millis(500))?,
+        ["watch", name, every] => watch(radio, signals, lookup(name)?, parse_interval(every)?)?,
+        ["info", pattern] => {
+            for reg in matching(pattern)? {
+                println!("{}", reg);
+                for field in (reg.fields)() {
+                    println!("    {:<16} [{}+{}]", field.name, field.shift, field.bits);
+                }
+            }
+        }
+        _ => bail!("Unknown command, try help"),
+    }
+    Ok(true)
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let spi0 = ax5043::open(&args.spi)?;
+    let mut callback = |_: &_, _, _, _: &_| {};
+    let mut radio = Registers::new(spi0, &mut callback);
+    if args.reset {
+        radio.reset()?;
+    }
+    // Ends a watch. At the prompt Ctrl-C waits for the next line, use quit or Ctrl-D.
+    let mut signals = Signals::new(SignalSet::from(Signal::Interrupt))?;
+
+    let mut lines = stdin().lock().lines();
+    loop {
+        print!("> ");
+        stdout().flush()?;
+        let Some(line) = lines.next() else {
+            break;
+        };
+        if signals.receive()?.is_some() {
+            break;
+        }
+        match run(&mut radio, &mut signals, &line?) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => println!("{:#}", e),
+        }
+    }
+    Ok(())
+}
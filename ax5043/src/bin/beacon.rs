@@ -0,0 +1,220 @@
+// Periodically transmits a short beacon (callsign, sequence number and a telemetry snapshot)
+// through the UHF AX5043, keeping within a duty cycle budget.
+//
+// Run with the uhf service stopped, they share the radio and PA.
+use anyhow::{ensure, Context, Result};
+use ax5043::{
+    config,
+    gpio::{Input, Pin, Switch},
+    guard::Guard,
+    logging, power,
+    registers::*,
+    tx::{self, DutyCycle},
+    Registers, TX,
+};
+use clap::Parser;
+use mio::{unix::SourceFd, Events, Interest, Poll, Token};
+use mio_signals::{Signal, Signals};
+use std::{
+    fs::read_to_string,
+    os::fd::AsRawFd,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use timerfd::{SetTimeFlags, TimerFd, TimerState};
+use tracing::{info, warn};
+
+#[derive(Parser, Debug)]
+/// Try it out: `beacon --callsign N0CALL --interval 30`
+struct Args {
+    #[arg(short, long, default_value = "/dev/spidev0.0")]
+    spi: String,
+    #[arg(long, default_value = "c3-uhf-96000.toml")]
+    config: String,
+    /// Index of the [[channel]] to transmit on
+    #[arg(long, default_value = "1")]
+    channel: usize,
+    #[arg(long)]
+    callsign: String,
+    /// Seconds between beacons
+    #[arg(short, long, default_value = "60")]
+    interval: u64,
+    /// Maximum fraction of --window spent transmitting
+    #[arg(long, default_value = "0.1")]
+    duty: f64,
+    /// Duty cycle window, seconds
+    #[arg(long, default_value = "600")]
+    window: u64,
+    /// PA enable line, chip:line
+    #[arg(long, default_value = "gpiochip1:27")]
+    pa: Pin,
+    /// Antenna switch line, active while transmitting
+    #[arg(long)]
+    antsel: Option<Pin>,
+    /// TX inhibit line, chip:line. Nothing is keyed while it's asserted, see
+    /// ax5043::schedule::Inhibit.
+    #[arg(long)]
+    inhibit: Option<Pin>,
+    /// --inhibit is asserted low, a switch to ground with a pull-up
+    #[arg(long)]
+    inhibit_low: bool,
+    /// Output power in dBm, needs --power-table
+    #[arg(long, requires = "power_table", allow_hyphen_values = true)]
+    power: Option<f64>,
+    /// dBm table from powercal
+    #[arg(long)]
+    power_table: Option<String>,
+    /// Log one JSON object per line
+    #[arg(long)]
+    json: bool,
+}
+
+// Best effort, missing values are left out rather than failing the beacon
+fn snapshot() -> String {
+    let mut fields = Vec::new();
+    if let Ok(uptime) = read_to_string("/proc/uptime") {
+        if let Some(secs) = uptime.split_whitespace().next() {
+            fields.push(format!("up={}", secs));
+        }
+    }
+    if let Ok(temp) = read_to_string("/sys/class/thermal/thermal_zone0/temp") {
+        if let Ok(millic) = temp.trim().parse::<i64>() {
+            fields.push(format!("temp={}.{:03}", millic / 1000, millic.abs() % 1000));
+        }
+    }
+    fields.join(" ")
+}
+
+fn beacon(
+    radio: &mut Registers,
+    config: &config::Config,
+    channel: &config::ChannelParameters,
+    power: Option<(&power::Table, f64)>,
+    frame: &[u8],
+) -> Result<()> {
+    let tx = config.tx.context("Section [tx] required")?;
+    // PM p. 12: The FIFO should be emptied before the PWRMODE is set to POWERDOWN
+    radio.FIFOCMD().write(FIFOCmd {
+        mode: FIFOCmds::CLEAR_ERROR,
+        auto_commit: false,
+    })?;
+    radio.FIFOCMD().write(FIFOCmd {
+        mode: FIFOCmds::CLEAR_DATA,
+        auto_commit: false,
+    })?;
+    // See errata - PWRMODE must transition through off for FIFO to work
+    radio.PWRMODE().write(PwrMode {
+        flags: PwrFlags::XOEN | PwrFlags::REFEN,
+        mode: PwrModes::POWEROFF,
+    })?;
+    let channel = channel.write(radio, &config.board)?;
+    tx.write(radio, &config.board, &channel)?;
+    if let Some((table, dbm)) = power {
+        table.set(radio, dbm)?;
+    }
+    tx::transmit(radio, &channel.encode(frame)?)?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.json);
+    ensure!(args.interval > 0, "--interval must be positive");
+    ensure!(
+        (0.0..=1.0).contains(&args.duty),
+        "--duty is a fraction between 0 and 1"
+    );
+
+    let mut poll = Poll::new()?;
+    let registry = poll.registry();
+    let mut events = Events::with_capacity(16);
+
+    const SIGNAL: Token = Token(0);
+    let mut signals = Signals::new(Signal::Interrupt | Signal::Terminate)?;
+    registry.register(&mut signals, SIGNAL, Interest::READABLE)?;
+
+    // Disables the PA and resets the radio on every exit path, see guard.rs
+    let guard = Arc::new(Guard::new(&args.spi)?.with_pa(args.pa.output()?));
+    guard.install_panic_hook();
+    let antsel = args.antsel.as_ref().map(Pin::output).transpose()?;
+    let inhibit = args
+        .inhibit
+        .as_ref()
+        .map(|pin| pin.input(args.inhibit_low))
+        .transpose()?;
+
+    let mut tfd = TimerFd::new()?;
+    tfd.set_state(
+        TimerState::Periodic {
+            current: Duration::from_millis(1),
+            interval: Duration::from_secs(args.interval),
+        },
+        SetTimeFlags::Default,
+    );
+    const TIMER: Token = Token(1);
+    registry.register(&mut SourceFd(&tfd.as_raw_fd()), TIMER, Interest::READABLE)?;
+
+    let spi0 = ax5043::open(&args.spi)?;
+    let mut callback = |_: &_, _, _, _: &_| {};
+    let mut radio = Registers::new(spi0, &mut callback);
+    radio.reset()?;
+
+    let contents = read_to_string(&args.config)?;
+    let config: config::Config = toml::from_str(&contents)?;
+    radio.probe()?.found()?;
+    config.write(&mut radio)?;
+    let channel = *config
+        .channel
+        .get(args.channel)
+        .context("No such [[channel]]")?;
+    let table: Option<power::Table> = match args.power_table {
+        Some(ref path) => Some(toml::from_str(&read_to_string(path)?)?),
+        None => None,
+    };
+    let power = table.as_ref().zip(args.power);
+    if let Some((table, dbm)) = power {
+        // Fail at startup rather than on the first beacon
+        table
+            .coefficient(dbm)
+            .context("--power is outside the calibrated range")?;
+    }
+
+    radio.FIFOTHRESH().write(128)?; // Half the FIFO size
+
+    guard.enable_pa()?;
+
+    let mut duty = DutyCycle::new(Duration::from_secs(args.window), args.duty);
+    let mut seq: u32 = 0;
+
+    'outer: loop {
+        poll.poll(&mut events, None)?;
+        for event in events.iter() {
+            match event.token() {
+                TIMER => {
+                    tfd.read();
+                    let frame = format!("{} {} {}", args.callsign, seq, snapshot());
+                    let airtime = tx::airtime(channel.coded_len(frame.len()), channel.datarate);
+                    let now = Instant::now();
+                    if inhibit.as_ref().map(Input::asserted).transpose()? == Some(true) {
+                        warn!(target: "ax5043::packet", "BEACON {} skipped, TX inhibit", seq);
+                    } else if !duty.allows(now, airtime) {
+                        let used = duty.used(now);
+                        warn!(target: "ax5043::packet", "BEACON {} skipped, used {:?}", seq, used);
+                    } else {
+                        info!(target: "ax5043::packet", "BEACON SEND {}", frame);
+                        antsel.set(true)?;
+                        beacon(&mut radio, &config, &channel, power, frame.as_bytes())?;
+                        antsel.set(false)?;
+                        duty.record(now, airtime);
+                    }
+                    seq = seq.wrapping_add(1);
+                }
+                SIGNAL => break 'outer,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    guard.shutdown();
+    Ok(())
+}
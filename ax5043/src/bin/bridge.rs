@@ -0,0 +1,180 @@
+// Relays the telemetry stream to WebSocket clients as JSON, for a browser dashboard where the
+// terminal tui can't be run.
+//
+// Point the daemons' --telemetry here. Each datagram goes out as one text message:
+//
+//   {"from": "10.18.17.6:41234", "state": {"STATS": {...}}}
+//
+// from tells several daemons apart, state is CommState::to_json(). Clients only listen, anything
+// they send is ignored. With --forward the datagrams are also passed on untouched so the tui
+// keeps working next to the bridge.
+use anyhow::{Context, Result};
+use ax5043::{logging, tui};
+use clap::Parser;
+use mio::{net::UdpSocket, unix::SourceFd, Events, Interest, Poll, Token};
+use mio_signals::{Signal, Signals};
+use serde::Serialize;
+use serde_json::value::RawValue;
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::fd::AsRawFd,
+    time::Duration,
+};
+use tracing::{info, warn};
+use tungstenite::{protocol::WebSocketConfig, Message, WebSocket};
+
+#[derive(Parser, Debug)]
+/// Try it out: `bridge --websocket 0.0.0.0:8035`, then `uhf --telemetry 127.0.0.1:10035`
+struct Args {
+    /// Where the daemons send telemetry
+    #[arg(short, long, default_value = "0.0.0.0:10035")]
+    listen: SocketAddr,
+    /// WebSocket clients connect here
+    #[arg(short, long, default_value = "0.0.0.0:8035")]
+    websocket: SocketAddr,
+    /// Also pass each datagram on to this address, for example a tui at 127.0.0.1:10036
+    #[arg(short, long)]
+    forward: Option<SocketAddr>,
+    /// Log one JSON object per line
+    #[arg(long)]
+    json: bool,
+}
+
+/// Messages queued for a client that isn't reading, in bytes, before it's dropped. A few
+/// seconds of telemetry, SWEEPs included.
+const MAX_BACKLOG: usize = 1 << 20;
+
+#[derive(Serialize)]
+struct Forwarded<'a> {
+    from: SocketAddr,
+    state: &'a RawValue,
+}
+
+/// Upgrades a new connection, blocking for at most a second so a stalled client can't hold up
+/// the telemetry
+fn handshake(stream: TcpStream) -> Result<WebSocket<TcpStream>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let config = WebSocketConfig::default().max_write_buffer_size(MAX_BACKLOG);
+    let socket = tungstenite::accept_with_config(stream, Some(config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    socket.get_ref().set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Sends `text` to every client, dropping the ones that went away. A client too slow to keep
+/// up has its messages queued by tungstenite and flushed on the next send, until MAX_BACKLOG
+/// of them is waiting and it's dropped too.
+fn broadcast(clients: &mut Vec<(SocketAddr, WebSocket<TcpStream>)>, text: &str) {
+    clients.retain_mut(|(addr, client)| match client.send(Message::text(text)) {
+        Ok(()) => true,
+        Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => true,
+        Err(tungstenite::Error::WriteBufferFull(_)) => {
+            warn!("BRIDGE {} dropped, over {} bytes behind", addr, MAX_BACKLOG);
+            false
+        }
+        Err(e) => {
+            info!("BRIDGE {} gone: {}", addr, e);
+            false
+        }
+    });
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let _log = logging::init(args.json);
+
+    let mut poll = Poll::new()?;
+    let registry = poll.registry();
+
+    const TELEMETRY: Token = Token(0);
+    let mut telemetry = UdpSocket::bind(args.listen).context("Binding --listen")?;
+    registry.register(&mut telemetry, TELEMETRY, Interest::READABLE)?;
+
+    const WEBSOCKET: Token = Token(1);
+    let listener = TcpListener::bind(args.websocket).context("Binding --websocket")?;
+    listener.set_nonblocking(true)?;
+    registry.register(
+        &mut SourceFd(&listener.as_raw_fd()),
+        WEBSOCKET,
+        Interest::READABLE,
+    )?;
+
+    const SIGNAL: Token = Token(2);
+    let mut signals = Signals::new(Signal::Interrupt | Signal::Terminate)?;
+    registry.register(&mut signals, SIGNAL, Interest::READABLE)?;
+
+    info!(
+        "BRIDGE telemetry on {}, WebSocket on {}",
+        args.listen, args.websocket
+    );
+
+    let mut clients = Vec::new();
+    let mut events = Events::with_capacity(128);
+    // SWEEPs are bigger than the other states
+    let mut buf = vec![0; 65536];
+    loop {
+        poll.poll(&mut events, None)?;
+        for event in events.iter() {
+            match event.token() {
+                TELEMETRY => loop {
+                    let (amt, from) = match telemetry.recv_from(&mut buf) {
+                        Ok(received) => received,
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => Err(e)?,
+                    };
+                    if let Some(dest) = args.forward {
+                        match telemetry.send_to(&buf[..amt], dest) {
+                            Err(e) if e.kind() != ErrorKind::WouldBlock => {
+                                warn!("BRIDGE forward to {}: {}", dest, e)
+                            }
+                            _ => (),
+                        }
+                    }
+                    if clients.is_empty() {
+                        continue;
+                    }
+                    let state = match ciborium::de::from_reader::<tui::CommState, _>(&buf[..amt]) {
+                        Ok(state) => state.to_json()?,
+                        Err(e) => {
+                            warn!("BRIDGE undecodable datagram from {}: {}", from, e);
+                            continue;
+                        }
+                    };
+                    let state = RawValue::from_string(state)?;
+                    let text = serde_json::to_string(&Forwarded {
+                        from,
+                        state: &state,
+                    })?;
+                    broadcast(&mut clients, &text);
+                },
+                WEBSOCKET => loop {
+                    let (stream, addr) = match listener.accept() {
+                        Ok(accepted) => accepted,
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => Err(e)?,
+                    };
+                    match handshake(stream) {
+                        Ok(client) => {
+                            info!("BRIDGE {} connected", addr);
+                            clients.push((addr, client));
+                        }
+                        Err(e) => warn!("BRIDGE {} handshake failed: {}", addr, e),
+                    }
+                },
+                SIGNAL => {
+                    if signals.receive()?.is_some() {
+                        info!("BRIDGE shutting down, {} clients", clients.len());
+                        for (_, mut client) in clients {
+                            _ = client.close(None);
+                            _ = client.flush();
+                        }
+                        return Ok(());
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
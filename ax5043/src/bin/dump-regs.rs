@@ -0,0 +1,117 @@
+// Prints every readable register with its address, raw bytes and decoded fields.
+//
+// Doesn't reset the radio by default so it can look at a live (or wedged) radio. Reading does
+// clear sticky/request registers, see Registers::dump().
+use anyhow::Result;
+use ax5043::{RegisterDump, Registers};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{read_to_string, write},
+};
+
+#[derive(Parser, Debug)]
+/// Try it out: `dump-regs --save before.toml`, change something, `dump-regs --diff before.toml`
+struct Args {
+    #[arg(short, long, default_value = "/dev/spidev0.0")]
+    spi: String,
+    /// Reset the radio first, to check power on defaults
+    #[arg(long)]
+    reset: bool,
+    /// Save the dump to this file
+    #[arg(long)]
+    save: Option<String>,
+    /// Only print registers that differ from this saved dump
+    #[arg(long)]
+    diff: Option<String>,
+    /// Print each bit field's value under the register, see ax5043::regmap
+    #[arg(long)]
+    fields: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    register: Vec<RegisterDump>,
+}
+
+fn hex(raw: &[u8]) -> String {
+    raw.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn print_fields(reg: &RegisterDump) {
+    let Some(info) = Registers::info(&reg.name) else {
+        return;
+    };
+    for field in (info.fields)() {
+        println!(
+            "    {:<16} [{}+{}] {:#X}",
+            field.name,
+            field.shift,
+            field.bits,
+            field.get(&reg.raw)
+        );
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let spi0 = ax5043::open(&args.spi)?;
+    let mut callback = |_: &_, _, _, _: &_| {};
+    let mut radio = Registers::new(spi0, &mut callback);
+    if args.reset {
+        radio.reset()?;
+        for mismatch in radio.verify_reset()? {
+            eprintln!("{} not at its default", mismatch);
+        }
+    }
+    let regs = radio.dump()?;
+
+    match args.diff {
+        Some(ref path) => {
+            let saved: Saved = toml::from_str(&read_to_string(path)?)?;
+            let saved: HashMap<_, _> = saved
+                .register
+                .into_iter()
+                .map(|r| (r.name.clone(), r))
+                .collect();
+            for reg in &regs {
+                match saved.get(&reg.name) {
+                    Some(old) if old.raw == reg.raw => (),
+                    Some(old) => {
+                        println!(
+                            "{:<16} {:03X} {:>8} -> {:<8} {} -> {}",
+                            reg.name,
+                            reg.addr,
+                            hex(&old.raw),
+                            hex(&reg.raw),
+                            old.decoded,
+                            reg.decoded
+                        );
+                    }
+                    None => println!("{:<16} {:03X} not in {}", reg.name, reg.addr, path),
+                }
+            }
+        }
+        None => {
+            for reg in &regs {
+                println!(
+                    "{:<16} {:03X} {:>8} {}",
+                    reg.name,
+                    reg.addr,
+                    hex(&reg.raw),
+                    reg.decoded
+                );
+                if args.fields {
+                    print_fields(reg);
+                }
+            }
+        }
+    }
+
+    if let Some(path) = args.save {
+        write(path, toml::to_string(&Saved { register: regs })?)?;
+    }
+    Ok(())
+}
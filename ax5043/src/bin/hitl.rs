@@ -0,0 +1,142 @@
+// Hardware-in-the-loop regression gate for the flatsat, see ax5043::hitl. Needs the `hitl`
+// feature: `cargo build --features hitl --bin hitl`.
+//
+// Brings up both radios, runs every step in the script and prints one line per step. Run with
+// the uhf and lband services stopped, they share the radios and PA. Exits with an error if any
+// step failed.
+use anyhow::{bail, ensure, Context, Result};
+use ax5043::{
+    config,
+    gpio::Pin,
+    guard::Guard,
+    hitl::{self, Script, Side},
+    logging, Registers,
+};
+use clap::Parser;
+use std::{fs::read_to_string, sync::Arc};
+use tracing::{info, warn};
+
+#[derive(Parser, Debug)]
+/// Try it out: `hitl --script examples/hitl.toml --b-config c3-lband-96000.toml`
+struct Args {
+    #[arg(long, default_value = "examples/hitl.toml")]
+    script: String,
+    #[arg(long, default_value = "/dev/spidev0.0")]
+    a_spi: String,
+    #[arg(long, default_value = "c3-uhf-96000.toml")]
+    a_config: String,
+    /// PA enable line for radio A, chip:line
+    #[arg(long, default_value = "gpiochip1:27")]
+    a_pa: Pin,
+    #[arg(long, default_value = "/dev/spidev1.0")]
+    b_spi: String,
+    #[arg(long)]
+    b_config: String,
+    /// PA enable line for radio B, only needed for steps with `from = "b"`
+    #[arg(long)]
+    b_pa: Option<Pin>,
+    /// Antenna switch line, active while transmitting
+    #[arg(long)]
+    antsel: Option<Pin>,
+    /// Log one JSON object per line
+    #[arg(long)]
+    json: bool,
+}
+
+fn load_config(path: &str) -> Result<config::Config> {
+    let contents = read_to_string(path).with_context(|| format!("Reading {}", path))?;
+    let config: config::Config = toml::from_str(&contents)?;
+    ensure!(!config.channel.is_empty(), "{}: missing [[channel]]", path);
+    Ok(config)
+}
+
+fn bring_up(radio: &mut Registers, config: &config::Config) -> Result<()> {
+    radio.reset()?;
+    for mismatch in radio.verify_reset()? {
+        warn!("HITL RESET {} not at its default", mismatch);
+    }
+    radio.probe()?.found()?;
+    config.configure(radio)?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.json);
+
+    let script: Script = toml::from_str(
+        &read_to_string(&args.script).with_context(|| format!("Reading {}", args.script))?,
+    )?;
+    ensure!(!script.step.is_empty(), "{}: no [[step]]", args.script);
+    if script.step.iter().any(|s| s.from == Side::B) {
+        ensure!(args.b_pa.is_some(), "Steps from b need --b-pa");
+    }
+
+    // Disables the PAs and resets both radios on every exit path, see guard.rs
+    let a_guard = Arc::new(Guard::new(&args.a_spi)?.with_pa(args.a_pa.output()?));
+    a_guard.install_panic_hook();
+    let mut b_guard = Guard::new(&args.b_spi)?;
+    if let Some(ref pa) = args.b_pa {
+        b_guard = b_guard.with_pa(pa.output()?);
+    }
+    let b_guard = Arc::new(b_guard);
+    b_guard.install_panic_hook();
+    let antsel = args.antsel.as_ref().map(Pin::output).transpose()?;
+
+    let a_config = load_config(&args.a_config)?;
+    let mut a_callback = |_: &_, _, _, _: &_| {};
+    let mut a_radio = Registers::new(ax5043::open(&args.a_spi)?, &mut a_callback);
+    bring_up(&mut a_radio, &a_config)?;
+
+    let b_config = load_config(&args.b_config)?;
+    let mut b_callback = |_: &_, _, _, _: &_| {};
+    let mut b_radio = Registers::new(ax5043::open(&args.b_spi)?, &mut b_callback);
+    bring_up(&mut b_radio, &b_config)?;
+
+    let mut a = hitl::Radio {
+        registers: &mut a_radio,
+        config: &a_config,
+    };
+    let mut b = hitl::Radio {
+        registers: &mut b_radio,
+        config: &b_config,
+    };
+
+    let mut failed = Vec::new();
+    for step in &script.step {
+        info!(
+            "HITL {} from {:?}, {} x {} B",
+            step.name, step.from, step.count, step.size
+        );
+        let (tx, rx, guard) = match step.from {
+            Side::A => (&mut a, &mut b, &a_guard),
+            Side::B => (&mut b, &mut a, &b_guard),
+        };
+        guard.enable_pa()?;
+        let outcome = hitl::run(step, tx, rx, &antsel);
+        guard.disable_pa()?;
+        let outcome = outcome?;
+
+        let failures = step.expect.check(&outcome);
+        let verdict = if failures.is_empty() { "PASS" } else { "FAIL" };
+        println!("{} {}: {}", verdict, step.name, outcome);
+        for failure in &failures {
+            println!("    {}", failure);
+        }
+        if !failures.is_empty() {
+            failed.push(step.name.as_str());
+        }
+    }
+
+    a_guard.shutdown();
+    b_guard.shutdown();
+    if !failed.is_empty() {
+        bail!(
+            "{}/{} steps failed: {}",
+            failed.len(),
+            script.step.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -1,109 +1,523 @@
-use anyhow::{ensure, Result};
-use ax5043::{config, registers::*, tui, Registers, RX, TX};
+use anyhow::{ensure, Context, Result};
+use ax5043::{
+    agc,
+    auth::ControlGate,
+    capture::{self, Direction, FileCapture, Meta},
+    config,
+    control::{Command, Tunable},
+    discover,
+    fallback::{self, Monitor},
+    filter::{PacketFilter, Verdict},
+    gpio::Pin,
+    guard::Guard,
+    logging,
+    pass::{self, Pass},
+    registers::*,
+    rejects::RejectLog,
+    rx::{self, PacketAssembler, Stats},
+    spool::{Forwarder, Spool},
+    state::State,
+    supply::History,
+    telemetry::Telemetry,
+    tui,
+    watchdog::{Reason, Watchdog},
+    Registers, RX, TX,
+};
 use clap::Parser;
-use crc::{Crc, CRC_16_GENIBUS}; // TODO: this CRC works but is it correct?
-use gpiocdev::{line::EdgeDetection, Request};
 use mio::{unix::SourceFd, Events, Interest, Poll, Token};
 use mio_signals::{Signal, Signals};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
-use std::{fs::read_to_string, io::Write, os::fd::AsRawFd, time::Duration};
+use std::{
+    cell::Cell,
+    fs::read_to_string,
+    io::ErrorKind,
+    os::fd::AsRawFd,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 use timerfd::{SetTimeFlags, TimerFd, TimerState};
+use tracing::{error, info, warn};
 
-fn process_chunk(chunk: FIFOChunkRX, packet: &mut Vec<u8>, uplink: &mut UdpSocket) -> Result<()> {
-    if let FIFOChunkRX::DATA { flags, ref data } = chunk {
-        //println!("{:02X?}", chunk);
-        if flags.intersects(
-            FIFODataRXFlags::ABORT
-                | FIFODataRXFlags::SIZEFAIL
-                | FIFODataRXFlags::ADDRFAIL
-                | FIFODataRXFlags::CRCFAIL
-                | FIFODataRXFlags::RESIDUE,
-        ) {
-            println!(
-                "LBAND REJECTED {:?} {:02X?} ...+{}",
-                flags,
-                data[0],
-                data.len()
-            );
-            packet.clear();
-            return Ok(());
+#[allow(clippy::too_many_arguments)]
+fn read_packet(
+    radio: &mut Registers,
+    assembler: &mut PacketAssembler,
+    uplink: &mut Forwarder<UdpSocket>,
+    capture: &mut Option<FileCapture>,
+    rejects: &mut Option<RejectLog>,
+    pass: &mut Option<Pass>,
+    monitor: &mut Option<Monitor>,
+    filter: &mut Option<Box<dyn PacketFilter>>,
+    telemetry: &Option<Telemetry>,
+    config: &config::Config,
+) -> Result<()> {
+    // Only read for the outputs that record it, and only once something arrived
+    let wants_meta = capture.is_some()
+        || rejects.is_some()
+        || pass.is_some()
+        || monitor.is_some()
+        || filter.is_some()
+        || uplink.spool().is_some()
+        || telemetry.is_some();
+    let mut meta = None;
+    let mut read_meta = |radio: &mut Registers| -> Result<Meta> {
+        if meta.is_none() && wants_meta {
+            meta = Some(Meta::read(radio, &config.board)?);
         }
+        Ok(meta.unwrap_or_default())
+    };
 
-        if flags.contains(FIFODataRXFlags::PKTSTART) {
-            if !packet.is_empty() {
-                println!(
-                    "LBAND PKT RESTART rejecting {:02X?} ...+{}",
-                    packet[0],
-                    packet.len(),
-                );
+    assembler.drain_with(radio, |radio, packet, antenna, crc_ok| -> Result<()> {
+        let meta = Meta {
+            antenna,
+            crc_failed: !crc_ok,
+            ..read_meta(radio)?
+        };
+        let packet = match config.channel[0].decode(packet) {
+            Ok((payload, corrections)) => {
+                if corrections.any() {
+                    info!(target: "ax5043::packet", "LBAND RX PACKET corrected {}", corrections);
+                }
+                payload
+            }
+            Err(e) => {
+                warn!(target: "ax5043::packet", "LBAND RX PACKET {}: {:02X?}", e, packet);
+                if let Some(pass) = pass {
+                    pass.packet(SystemTime::now(), &meta, false);
+                }
+                if let Some(monitor) = monitor {
+                    monitor.packet(false, meta.rssi, Instant::now());
+                }
+                return Ok(());
             }
-            packet.clear();
+        };
+        let packet = &packet[..];
+        if let Some(pass) = pass {
+            pass.packet(SystemTime::now(), &meta, crc_ok);
+        }
+        if let Some(monitor) = monitor {
+            monitor.packet(crc_ok, meta.rssi, Instant::now());
+        }
+        let trailer = config.channel[0].crc_trailer;
+        let verdict = match filter {
+            Some(filter) => filter.inspect(packet, &meta),
+            None => Verdict::Forward,
+        };
+        match verdict {
+            Verdict::Forward => uplink.send(&trailer.uplink(packet, crc_ok), &meta)?,
+            Verdict::Drop => {
+                info!(target: "ax5043::packet", "LBAND RX PACKET filtered: {:02X?}", packet)
+            }
+            Verdict::Tag(ref tag) => {
+                let tagged = [tag, packet].concat();
+                uplink.send(&trailer.uplink(&tagged, crc_ok), &meta)?
+            }
+        }
+        if let Some(socket) = telemetry {
+            let frame = tui::Frame {
+                crc_failed: !crc_ok,
+                ..tui::Frame::new(packet.to_vec(), meta.rssi, None)
+            };
+            tui::CommState::PACKET(frame).send(socket)?;
+        }
+        if let Some(capture) = capture {
+            capture.write(Direction::Inbound, packet, &meta)?;
         }
+        match crc_ok {
+            true => info!(target: "ax5043::packet", "LBAND RX PACKET: {:02X?}", packet),
+            false => warn!(target: "ax5043::packet", "LBAND RX PACKET CRC failed: {:02X?}", packet),
+        }
+        Ok(())
+    })?;
 
-        if !flags.contains(FIFODataRXFlags::PKTSTART) && packet.is_empty() {
-            println!("Invalid continued chunk {:02X?}", chunk);
-            return Ok(());
+    if let Some(socket) = telemetry {
+        for abort in assembler.take_aborts() {
+            tui::CommState::ABORT(abort).send(socket)?;
         }
+    }
 
-        packet.write_all(data)?;
-        if flags.contains(FIFODataRXFlags::PKTEND) {
-            let bytes = packet.split_off(packet.len() - 2);
-            let checksum = u16::from_be_bytes([bytes[0], bytes[1]]);
-            let ccitt = Crc::<u16>::new(&CRC_16_GENIBUS);
-            let mut digest = ccitt.digest();
-            digest.update(packet);
-            let calculated = digest.finalize();
-
-            if calculated == checksum {
-                uplink.send(packet)?;
-                println!("LBAND RX PACKET: {:02X?}", packet);
-            } else {
-                println!(
-                    "Rejected CRC: received 0x{:x}, calculated 0x{:x}",
-                    checksum, calculated
-                );
-            }
-            packet.clear();
+    let rejected = assembler.take_rejected();
+    if rejected.is_empty() {
+        return Ok(());
+    }
+    let meta = read_meta(radio)?;
+    for rejected in rejected {
+        if let Some(pass) = pass {
+            pass.packet(SystemTime::now(), &meta, false);
         }
+        if let Some(monitor) = monitor.as_mut().filter(|_| rejected.reason.damaged()) {
+            monitor.packet(false, meta.rssi, Instant::now());
+        }
+        if let Some(rejects) = rejects {
+            rejects.write(&rejected, &meta)?;
+        }
+        if let Some(socket) = telemetry {
+            let frame = tui::Frame::new(rejected.data, meta.rssi, Some(rejected.reason));
+            tui::CommState::PACKET(frame).send(socket)?;
+        }
+    }
+    Ok(())
+}
+
+const CONFIG_PATH: &str = "c3-lband-60000.toml";
+
+fn retune(
+    radio: &mut Registers,
+    synth: &mut config::Synthesizer,
+    board: &config::Board,
+    freq: config::Hz,
+    fifo: rx::FifoPolicy,
+) -> Result<()> {
+    rx::leave(radio, board)?;
+    let previous = synth.freq_a;
+    if let Err(e) = synth.retune(radio, board, freq) {
+        error!("LBAND RETUNE to {} failed: {}", freq, e);
+        synth.retune(radio, board, previous)?;
+    } else {
+        info!("LBAND RETUNE {} -> {}", previous, freq);
     }
+    rx::enter(radio, board, fifo)?;
     Ok(())
 }
 
-fn read_packet(radio: &mut Registers, packet: &mut Vec<u8>, uplink: &mut UdpSocket) -> Result<()> {
-    let len = radio.FIFOCOUNT().read()?;
-    if len == 0 {
+/// The parsed config and the file contents, for the state file
+fn load_config(path: &str) -> Result<(config::Config, String)> {
+    let contents = read_to_string(path)?;
+    let config: config::Config = toml::from_str(&contents)?;
+    ensure!(!config.channel.is_empty(), "Missing [channel]");
+    ensure!(
+        config.scan.is_none(),
+        "[scan] is only followed by the station bin"
+    );
+    config.fifo.validate()?;
+    if let Some(ref fallback) = config.fallback {
+        fallback.validate(config.channel.len())?;
+    }
+    Ok((config, contents))
+}
+
+/// Checks the link once a second with a [fallback] section, not at all without. Again after
+/// each reload.
+fn arm_fallback(tfd: &mut TimerFd, config: &config::Config) {
+    let state = match config.fallback {
+        Some(_) => TimerState::Periodic {
+            current: Duration::new(1, 0),
+            interval: Duration::new(1, 0),
+        },
+        None => TimerState::Disarmed,
+    };
+    tfd.set_state(state, SetTimeFlags::Default);
+}
+
+/// Moves RX to or from the [fallback] channel, see ax5043::fallback
+fn switch_rate(
+    radio: &mut Registers,
+    config: &mut config::Config,
+    assembler: &mut PacketAssembler,
+    switch: fallback::Switch,
+    telemetry: &Option<Telemetry>,
+) -> Result<()> {
+    let Some(fallback) = config.fallback else {
         return Ok(());
+    };
+    warn!("LBAND FALLBACK {}", switch);
+    rx::leave(radio, &config.board)?;
+    config.swap_channel(radio, fallback.channel)?;
+    radio.RSSIREFERENCE().write(32)?; // Config::swap_channel writes the config file value
+    rx::enter(radio, &config.board, config.fifo)?;
+    assembler.set_max_len(config.channel[0].length.max_len());
+    assembler.set_trailer(config.channel[0].crc_trailer);
+    assembler.set_crc(config.channel[0].crc);
+    assembler.set_framing(config.channel[0].framing);
+    if let Some(socket) = telemetry {
+        tui::CommState::FALLBACK(switch).send(socket)?;
     }
+    Ok(())
+}
 
-    match radio.FIFODATARX().read(len.into()) {
-        Ok(chunks) => {
-            for chunk in chunks {
-                process_chunk(chunk, packet, uplink)?;
-            }
-        }
+/// Applies what it can from the config file to the running radio, a bad file is only logged
+fn reload(
+    radio: &mut Registers,
+    config: &mut config::Config,
+    path: &str,
+    applied: &mut String,
+) -> Result<()> {
+    let (new, contents) = match load_config(path) {
+        Ok(new) => new,
         Err(e) => {
-            // FIFO Errors are usually just overflow, non-fatal
-            println!("{}", e);
-            packet.clear();
+            error!("LBAND RELOAD {} failed: {:#}", path, e);
+            return Ok(());
         }
+    };
+
+    rx::leave(radio, &config.board)?;
+    let changes = config.reload(radio, new)?;
+    *applied = contents;
+    radio.RSSIREFERENCE().write(32)?; // Config::reload writes the config file value
+    rx::enter(radio, &config.board, config.fifo)?;
+
+    info!("LBAND RELOAD applied {:?}", changes.applied);
+    if !changes.reset.is_empty() {
+        warn!("LBAND RELOAD {:?} changed, restart to apply", changes.reset);
     }
     Ok(())
 }
 
+/// Resets and reconfigures a stuck radio, see ax5043::watchdog
+fn recover(
+    radio: &mut Registers,
+    config: &config::Config,
+    assembler: &mut PacketAssembler,
+    reason: Reason,
+    telemetry: &Option<Telemetry>,
+) -> Result<()> {
+    error!("LBAND WATCHDOG {}, resetting the radio", reason);
+    if let Some(socket) = telemetry {
+        tui::CommState::WATCHDOG(reason).send(socket)?;
+    }
+    radio.reset()?;
+    for mismatch in radio.verify_reset()? {
+        warn!("LBAND RESET {} not at its default", mismatch);
+    }
+    config.configure(radio)?;
+    assembler.clear();
+    rx::enter(radio, &config.board, config.fifo)?;
+    info!("LBAND WATCHDOG radio back in RX");
+    Ok(())
+}
+
+/// Logs and sends any supply trouble, see ax5043::supply. Returns why the radio should be reset,
+/// if it should.
+fn check_supply(
+    radio: &mut Registers,
+    supply: &mut History,
+    reset: bool,
+    telemetry: &Option<Telemetry>,
+) -> Result<Option<Reason>> {
+    for event in supply.sample(radio, SystemTime::now())? {
+        warn!("LBAND SUPPLY {}", event);
+        if let Some(socket) = telemetry {
+            tui::CommState::SUPPLY(event).send(socket)?;
+        }
+    }
+    Ok(supply.take_reset().filter(|_| reset).map(Reason::Supply))
+}
+
+fn tune(radio: &mut Registers, reg: Tunable, value: i64) -> Result<()> {
+    let old = reg.read(radio)?;
+    reg.write(radio, value)?;
+    info!("LBAND WRITE {} {} -> {}", reg, old, value);
+    Ok(())
+}
+
+fn tune_agc(radio: &mut Registers, tuning: agc::Tuning) -> Result<()> {
+    for (set, old, new) in tuning.apply(radio)? {
+        info!("LBAND AGC {:?} {} -> {}", set, old, new);
+    }
+    Ok(())
+}
+
+/// Rewrites only the packet engine for the new framing. Rejected (and left as it was) if the
+/// channel's modulation can't carry it. Lasts until the next reload.
+fn switch_framing(
+    radio: &mut Registers,
+    config: &mut config::Config,
+    assembler: &mut PacketAssembler,
+    framing: config::Framing,
+) -> Result<()> {
+    match config.channel[0].with_framing(framing) {
+        Ok(channel) => {
+            info!(
+                "LBAND FRAMING {:?} -> {:?}",
+                config.channel[0].framing, framing
+            );
+            channel.write_framing(
+                radio,
+                config.address.as_ref().map(config::AddressFilter::hardware),
+            )?;
+            assembler.clear();
+            assembler.set_crc(channel.crc);
+            assembler.set_framing(channel.framing);
+            config.channel[0] = channel;
+        }
+        Err(e) => warn!("LBAND FRAMING {:?} rejected: {}", framing, e),
+    }
+    Ok(())
+}
+
+/// The states the tui only gets once, sent at startup and again on each new TCP connection
+fn announce(radio: &mut Registers, config: &config::Config, socket: &Telemetry) -> Result<()> {
+    tui::CommState::HELLO(tui::PROTOCOL).send(socket)?;
+    tui::CommState::RADIO("L-band".to_string()).send(socket)?;
+    tui::CommState::BOARD(config.board).send(socket)?;
+    tui::CommState::REGISTERS(tui::StatusRegisters::new(radio)?).send(socket)?;
+    tui::CommState::CONFIG(tui::Config::new(radio, &config.board)?).send(socket)?;
+    Ok(())
+}
+
+/// The tui's console sends commands back on the telemetry socket, only those passing
+/// Command::remote() are accepted there
+fn tui_commands(socket: &UdpSocket, gate: &mut ControlGate) -> Result<Vec<Command>> {
+    let mut commands = Vec::new();
+    let mut buf = [0; 256];
+    loop {
+        match socket.recv(&mut buf) {
+            Ok(amt) => match gate
+                .open(&buf[..amt], SystemTime::now())
+                .map(|text| text.parse::<Command>())
+            {
+                Err(e) => warn!("LBAND TUI command refused: {}", e),
+                Ok(Err(e)) => warn!("Invalid command: {}", e),
+                Ok(Ok(command)) if command.remote() => commands.push(command),
+                Ok(Ok(other)) => warn!("LBAND {:?} not accepted over telemetry", other),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(commands),
+            // An earlier telemetry datagram found nobody listening
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => continue,
+            Err(e) => return Err(e).context("Telemetry socket read failed"),
+        }
+    }
+}
+
+/// The [filter] rules, if any, see ax5043::filter
+fn packet_filter(config: &config::Config) -> Option<Box<dyn PacketFilter>> {
+    config
+        .filter
+        .clone()
+        .map(|rules| Box::new(rules) as Box<dyn PacketFilter>)
+}
+
+/// Logs a finished pass and sends it on, see ax5043::pass
+fn end_pass(
+    report: pass::Report,
+    dir: &Option<String>,
+    telemetry: &Option<Telemetry>,
+) -> Result<()> {
+    info!("LBAND PASS {}", report);
+    if let Some(dir) = dir {
+        match report.write(Path::new(dir)) {
+            Ok(path) => info!("LBAND PASS report {}", path.display()),
+            Err(e) => warn!("LBAND PASS report not written: {}", e),
+        }
+    }
+    if let Some(socket) = telemetry {
+        tui::CommState::PASS(report).send(socket)?;
+    }
+    Ok(())
+}
+
+fn save_state(path: &Option<String>, state: &mut State, stats: &Stats) {
+    let Some(path) = path else {
+        return;
+    };
+    state.stats = *stats;
+    if let Err(e) = state.save(Path::new(path)) {
+        warn!("LBAND STATE not saved to {}: {}", path, e);
+    }
+}
+
 #[derive(Parser, Debug)]
 /// Try it out: `socat UDP-LISTEN:10025 STDOUT`
 struct Args {
     #[arg(short, long, default_value = "10025")]
     uplink: u16,
+    /// Operator commands, see ax5043::control
+    #[arg(short, long, default_value = "10027")]
+    control: u16,
+    /// Address the control socket listens on, 0.0.0.0 to take commands from the station LAN.
+    /// Set a [control] key first, see ax5043::auth.
+    #[arg(long, default_value = "127.0.0.1")]
+    control_listen: IpAddr,
     #[arg(short, long, default_value = "/dev/spidev1.1")]
     spi: String,
-    /// For example 10.18.17.6:10035
+    /// Radio IRQ line, chip:line
+    #[arg(long, default_value = "gpiochip0:30")]
+    irq: Pin,
+    /// Take --spi and --irq from the device tree node with this name or compatible string,
+    /// see ax5043::discover
+    #[arg(long)]
+    discover: Option<String>,
+    /// For example 10.18.17.6:10035, or tcp://10.18.17.6:10035 to connect and resend the
+    /// config whenever the connection comes back, see ax5043::telemetry
     #[arg(short, long)]
     telemetry: Option<String>,
+    /// Send demodulator tracking samples for the tui's constellation view, one every N
+    /// telemetry ticks (25 ms). 0 disables.
+    #[arg(long, default_value = "1")]
+    constellation: u32,
+    /// Log one JSON object per line
+    #[arg(long)]
+    json: bool,
+    /// Print the register writes the config file makes and exit, no hardware needed
+    #[arg(long)]
+    dry_run: bool,
+    /// Write every frame to this pcapng file
+    #[arg(long)]
+    capture: Option<String>,
+    /// pcapng link type for --capture, 3 for AX.25
+    #[arg(long, default_value_t = capture::LINKTYPE_USER0)]
+    linktype: u16,
+    /// Append every rejected chunk and frame to this file, see ax5043::rejects
+    #[arg(long)]
+    rejects: Option<String>,
+    /// Size in MiB at which --rejects is rotated
+    #[arg(long, default_value = "10")]
+    rejects_size: u64,
+    /// Keep packet totals and the last applied config here across restarts, saved with the
+    /// statistics
+    #[arg(long)]
+    state: Option<String>,
+    /// Seconds without a packet that end a pass, 0 to not summarize passes, see ax5043::pass
+    #[arg(long, default_value = "120")]
+    pass_gap: u64,
+    /// Write each pass summary to a JSON file in this directory
+    #[arg(long)]
+    pass_reports: Option<String>,
+    /// Keep received frames in this directory while nothing is reading --uplink and send them
+    /// once it's back, see ax5043::spool
+    #[arg(long)]
+    spool: Option<String>,
+    /// Size in MiB past which the oldest spooled frames are dropped
+    #[arg(long, default_value = "64")]
+    spool_size: u64,
+    /// Seconds between logged packet statistics, 0 to only log them at shutdown
+    #[arg(long, default_value = "300")]
+    stats: u64,
+    /// Reset the radio after this many seconds without an IRQ or status change, off by default
+    /// as a quiet channel looks the same, set it to hours. Also catches REVISION read failures
+    /// and persistent FIFO errors, see ax5043::watchdog
+    #[arg(long, default_value = "0")]
+    watchdog: u64,
+    /// Seconds between POWSTAT checks for brownouts, 0 to not check, see ax5043::supply
+    #[arg(long, default_value = "1")]
+    supply: u64,
+    /// Reset and reconfigure the radio once the supply is back after a brownout
+    #[arg(long)]
+    brownout_reset: bool,
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let log = logging::init(args.json);
+
+    if let Some(ref name) = args.discover {
+        let found = discover::find(Path::new("/sys"), name)?
+            .with_context(|| format!("No spidev for {} in the device tree", name))?;
+        args.spi = found.spi.to_string_lossy().into_owned();
+        if let Some(irq) = found.irq {
+            args.irq = irq;
+        }
+        info!("LBAND discovered {} irq {}", args.spi, args.irq);
+    }
+
+    if args.dry_run {
+        let (config, _) = load_config(CONFIG_PATH)?;
+        for write in ax5043::dry_run(|radio| config.configure(radio))? {
+            println!("{}", write);
+        }
+        return Ok(());
+    }
 
     let mut poll = Poll::new()?;
     let registry = poll.registry();
@@ -111,26 +525,38 @@ fn main() -> Result<()> {
 
     let src = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
     let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), args.uplink);
-    let mut uplink = UdpSocket::bind(src)?;
+    let uplink = UdpSocket::bind(src)?;
     uplink.connect(dest)?;
+    let spool = match args.spool {
+        Some(ref dir) => Some(Spool::open(dir, args.spool_size << 20)?),
+        None => None,
+    };
+    let mut uplink = Forwarder::new(uplink, spool);
 
-    let mut telemetry: Option<UdpSocket> = None;
-    if let Some(addr) = args.telemetry {
-        let dest: SocketAddr = addr.parse().unwrap();
-        let socket = UdpSocket::bind(src)?;
-        socket.connect(dest)?;
-        telemetry = Some(socket);
+    let telemetry = match args.telemetry {
+        Some(ref dest) => Some(Telemetry::open(dest).context("Invalid --telemetry")?),
+        None => None,
+    };
+    // Console commands from the tui, UDP only
+    if let Some(socket) = telemetry.as_ref().and_then(Telemetry::udp) {
+        registry.register(&mut SourceFd(&socket.as_raw_fd()), TUI, Interest::READABLE)?;
     }
+    const TUI: Token = Token(8);
+
+    let addr = SocketAddr::new(args.control_listen, args.control);
+    let mut control = mio::net::UdpSocket::bind(addr)?;
+    const CONTROL: Token = Token(2);
+    registry.register(&mut control, CONTROL, Interest::READABLE)?;
 
-    const SIGINT: Token = Token(3);
-    let mut signals = Signals::new(Signal::Interrupt.into())?;
-    registry.register(&mut signals, SIGINT, Interest::READABLE)?;
+    const SIGNAL: Token = Token(3);
+    let mut signals = Signals::new(Signal::Interrupt | Signal::Terminate | Signal::User1)?;
+    registry.register(&mut signals, SIGNAL, Interest::READABLE)?;
 
-    let lband_irq = Request::builder()
-        .on_chip("/dev/gpiochip0")
-        .with_line(30)
-        .with_edge_detection(EdgeDetection::RisingEdge)
-        .request()?;
+    // Resets the radio on every exit path, see guard.rs
+    let guard = Arc::new(Guard::new(&args.spi)?);
+    guard.install_panic_hook();
+
+    let lband_irq = args.irq.irq()?;
 
     const IRQ: Token = Token(4);
     registry.register(
@@ -164,11 +590,48 @@ fn main() -> Result<()> {
         Interest::READABLE,
     )?;
 
+    let mut stats_tfd = TimerFd::new()?;
+    if args.stats > 0 {
+        let interval = Duration::from_secs(args.stats);
+        stats_tfd.set_state(
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const STATS: Token = Token(6);
+    registry.register(
+        &mut SourceFd(&stats_tfd.as_raw_fd()),
+        STATS,
+        Interest::READABLE,
+    )?;
+
+    let mut watchdog_tfd = TimerFd::new()?;
+    if args.watchdog > 0 {
+        watchdog_tfd.set_state(
+            TimerState::Periodic {
+                current: Duration::new(1, 0),
+                interval: Duration::new(1, 0),
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const WATCHDOG: Token = Token(7);
+    registry.register(
+        &mut SourceFd(&watchdog_tfd.as_raw_fd()),
+        WATCHDOG,
+        Interest::READABLE,
+    )?;
+
     let spi0 = ax5043::open(args.spi)?;
     let mut status = ax5043::Status::empty();
+    let activity = Cell::new(Instant::now()); // Last status change, for the watchdog
     let mut callback = |_: &_, _addr, s, _val: &[u8]| {
         //println!("{:03X}: {:02X?}", addr, val);
         if s != status {
+            activity.set(Instant::now());
             if let Some(ref socket) = telemetry {
                 tui::CommState::STATUS(s).send(socket).unwrap();
             }
@@ -177,40 +640,42 @@ fn main() -> Result<()> {
     };
     let mut radio = ax5043::Registers::new(spi0, &mut callback);
     radio.reset()?;
+    for mismatch in radio.verify_reset()? {
+        warn!("LBAND RESET {} not at its default", mismatch);
+    }
 
-    let rev = radio.REVISION().read()?;
-    ensure!(
-        rev == 0x51,
-        "Unexpected revision {}, expected {}",
-        rev,
-        0x51,
-    );
-
-    let file_path = "c3-lband-60000.toml";
-    let contents = read_to_string(file_path)?;
-    let config: config::Config = toml::from_str(&contents)?;
-    config.write(&mut radio)?;
-
-    radio.FIFOTHRESH().write(128)?; // Half the FIFO size
-
-    radio.RSSIREFERENCE().write(32)?;
+    let mut state = match args.state {
+        Some(ref path) => State::load(Path::new(path))?.unwrap_or_default(),
+        None => State::default(),
+    };
+    let (mut config, contents) = load_config(CONFIG_PATH)?;
+    if !args.control_listen.is_loopback() && config.control.is_none() {
+        warn!(
+            "LBAND CONTROL open on {} without a [control] key",
+            args.control_listen
+        );
+    }
+    let mut control_gate = ControlGate::new(config.control.clone());
+    if !state.config.is_empty() && state.config != contents {
+        match toml::from_str::<config::Config>(&state.config) {
+            Ok(last) => info!(
+                "LBAND config changed since last run: {:?}",
+                last.diff(&config)
+            ),
+            Err(e) => warn!("LBAND last run's config no longer parses: {}", e),
+        }
+    }
+    state.config = contents;
+    radio.probe()?.found()?;
+    radio.check_spi(true)?;
+    config.configure(&mut radio)?;
 
     if let Some(ref socket) = telemetry {
-        tui::CommState::BOARD(config.board).send(socket)?;
-        tui::CommState::REGISTERS(tui::StatusRegisters::new(&mut radio)?).send(socket)?;
-        tui::CommState::CONFIG(tui::Config {
-            txparams: tui::TXParameters::new(&mut radio, &config.board)?,
-            rxparams: tui::RXParams::new(&mut radio, &config.board)?,
-            set0: tui::RXParameterSet::set0(&mut radio)?,
-            set1: tui::RXParameterSet::set1(&mut radio)?,
-            set2: tui::RXParameterSet::set2(&mut radio)?,
-            set3: tui::RXParameterSet::set3(&mut radio)?,
-            synthesizer: tui::Synthesizer::new(&mut radio, &config.board)?,
-            packet_controller: tui::PacketController::new(&mut radio)?,
-            packet_format: tui::PacketFormat::new(&mut radio)?,
-            channel: tui::ChannelParameters::new(&mut radio)?,
-        })
-        .send(socket)?;
+        announce(&mut radio, &config, socket)?;
+    }
+    if let Some(lna) = config.board.lna {
+        lna.set(&mut radio, true)?;
+        std::thread::sleep(lna.settle());
     }
     radio.PWRMODE().write(PwrMode {
         flags: PwrFlags::XOEN | PwrFlags::REFEN,
@@ -229,11 +694,114 @@ fn main() -> Result<()> {
         auto_commit: false,
     })?;
 
-    radio
-        .IRQMASK()
-        .write(ax5043::registers::IRQ::FIFONOTEMPTY)?;
-    let mut packet = Vec::new();
+    config.fifo.arm(&mut radio)?;
+
+    // Picks up the tail of packets that never reach the FIFO threshold
+    let mut flush_tfd = TimerFd::new()?;
+    if let Some(interval) = config.fifo.flush() {
+        flush_tfd.set_state(
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const FLUSH: Token = Token(9);
+    registry.register(
+        &mut SourceFd(&flush_tfd.as_raw_fd()),
+        FLUSH,
+        Interest::READABLE,
+    )?;
+
+    let mut capture = match args.capture {
+        Some(ref path) => Some(capture::create(path, args.linktype)?),
+        None => None,
+    };
+    let mut rejects = match args.rejects {
+        Some(ref path) => Some(RejectLog::open(path, args.rejects_size << 20)?),
+        None => None,
+    };
+    let mut filter = packet_filter(&config);
+    let mut pass = (args.pass_gap > 0).then(|| Pass::new(Duration::from_secs(args.pass_gap)));
+    let mut pass_tfd = TimerFd::new()?;
+    if pass.is_some() {
+        pass_tfd.set_state(
+            TimerState::Periodic {
+                current: Duration::new(1, 0),
+                interval: Duration::new(1, 0),
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const PASS: Token = Token(10);
+    registry.register(
+        &mut SourceFd(&pass_tfd.as_raw_fd()),
+        PASS,
+        Interest::READABLE,
+    )?;
+    let mut spool_tfd = TimerFd::new()?;
+    if uplink.spool().is_some() {
+        spool_tfd.set_state(
+            TimerState::Periodic {
+                current: Duration::new(1, 0),
+                interval: Duration::new(1, 0),
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const SPOOL: Token = Token(11);
+    registry.register(
+        &mut SourceFd(&spool_tfd.as_raw_fd()),
+        SPOOL,
+        Interest::READABLE,
+    )?;
+    let mut monitor = config.fallback.map(|f| Monitor::new(f, Instant::now()));
+    let mut fallback_tfd = TimerFd::new()?;
+    arm_fallback(&mut fallback_tfd, &config);
+    const FALLBACK: Token = Token(12);
+    registry.register(
+        &mut SourceFd(&fallback_tfd.as_raw_fd()),
+        FALLBACK,
+        Interest::READABLE,
+    )?;
+    let mut supply = History::default();
+    let mut supply_tfd = TimerFd::new()?;
+    if args.supply > 0 {
+        let interval = Duration::from_secs(args.supply);
+        supply_tfd.set_state(
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const SUPPLY: Token = Token(13);
+    registry.register(
+        &mut SourceFd(&supply_tfd.as_raw_fd()),
+        SUPPLY,
+        Interest::READABLE,
+    )?;
+    let mut assembler = PacketAssembler::resume(state.stats)
+        .rssi(config.board.rssi)
+        .accept(config.accept)
+        .addresses(config.address.clone())
+        .max_len(config.channel[0].length.max_len())
+        .trailer(config.channel[0].crc_trailer)
+        .crc(config.channel[0].crc)
+        .framing(config.channel[0].framing);
+    if rejects.is_some() || telemetry.is_some() {
+        assembler = assembler.keep_rejected();
+    }
+    if telemetry.is_some() {
+        assembler = assembler.report_aborts();
+    }
+    let mut watchdog = Watchdog::new(Duration::from_secs(args.watchdog), Instant::now());
 
+    let mut tracker = tui::Tracker::new(args.constellation);
+    let mut snapshot = tui::Snapshot::default();
+    let mut commands = Vec::new();
     'outer: loop {
         poll.poll(&mut events, None)?;
         for event in events.iter() {
@@ -241,24 +809,203 @@ fn main() -> Result<()> {
                 TELEMETRY => {
                     tfd.read();
                     if let Some(ref socket) = telemetry {
-                        tui::CommState::STATE(tui::RXState::new(&mut radio, &config.channel[0])?)
-                            .send(socket)?;
-                        tui::CommState::REGISTERS(tui::StatusRegisters::new(&mut radio)?)
-                            .send(socket)?;
+                        socket.flush()?;
+                        if socket.take_resync() {
+                            info!("LBAND TELEMETRY connected, resending config");
+                            announce(&mut radio, &config, socket)?;
+                        }
+                        tui::CommState::HELLO(tui::PROTOCOL).send(socket)?;
+                        tui::CommState::RADIO("L-band".to_string()).send(socket)?;
+                        tui::CommState::STATS(*assembler.stats()).send(socket)?;
+                        // A pending IRQ gets the bus first, see tui::Snapshot
+                        if !lband_irq.asserted()? {
+                            snapshot
+                                .step(&mut radio, &config.board, &config.channel[0], None)?
+                                .send(socket)?;
+                            if let Some(samples) = tracker.tick(&mut radio)? {
+                                tui::CommState::TRACKING(samples).send(socket)?;
+                            }
+                        }
+                    }
+                }
+                STATS => {
+                    stats_tfd.read();
+                    info!("LBAND STATS {} {}", assembler.stats(), supply.counts());
+                    save_state(&args.state, &mut state, assembler.stats());
+                }
+                PASS => {
+                    pass_tfd.read();
+                    if let Some(report) = pass.as_mut().and_then(|p| p.check(SystemTime::now())) {
+                        end_pass(report, &args.pass_reports, &telemetry)?;
+                    }
+                }
+                SPOOL => {
+                    spool_tfd.read();
+                    uplink.service().context("Uplink replay failed")?;
+                }
+                FALLBACK => {
+                    fallback_tfd.read();
+                    if let Some(switch) = monitor.as_mut().and_then(|m| m.check(Instant::now())) {
+                        switch_rate(&mut radio, &mut config, &mut assembler, switch, &telemetry)?;
+                    }
+                }
+                WATCHDOG => {
+                    watchdog_tfd.read();
+                    watchdog.feed(activity.get());
+                    let now = Instant::now();
+                    if let Some(reason) = watchdog.check(&mut radio, assembler.stats(), now) {
+                        recover(&mut radio, &config, &mut assembler, reason, &telemetry)?;
+                        watchdog.reset(assembler.stats(), Instant::now());
+                    }
+                }
+                SUPPLY => {
+                    supply_tfd.read();
+                    let reset = args.brownout_reset;
+                    if let Some(reason) = check_supply(&mut radio, &mut supply, reset, &telemetry)?
+                    {
+                        recover(&mut radio, &config, &mut assembler, reason, &telemetry)?;
+                        watchdog.reset(assembler.stats(), Instant::now());
                     }
                 }
                 IRQ => {
-                    while lband_irq.has_edge_event()? {
-                        lband_irq.read_edge_event()?;
-                        read_packet(&mut radio, &mut packet, &mut uplink)?;
+                    watchdog.feed(Instant::now());
+                    lband_irq.drain(|| {
+                        read_packet(
+                            &mut radio,
+                            &mut assembler,
+                            &mut uplink,
+                            &mut capture,
+                            &mut rejects,
+                            &mut pass,
+                            &mut monitor,
+                            &mut filter,
+                            &telemetry,
+                            &config,
+                        )
+                    })?;
+                }
+                FLUSH => {
+                    flush_tfd.read();
+                    read_packet(
+                        &mut radio,
+                        &mut assembler,
+                        &mut uplink,
+                        &mut capture,
+                        &mut rejects,
+                        &mut pass,
+                        &mut monitor,
+                        &mut filter,
+                        &telemetry,
+                        &config,
+                    )?;
+                }
+                CONTROL => {
+                    let mut buf = [0; 256];
+                    loop {
+                        match control.recv_from(&mut buf) {
+                            Ok((amt, src)) => {
+                                let text = match control_gate.open(&buf[..amt], SystemTime::now()) {
+                                    Ok(text) => text,
+                                    Err(e) => {
+                                        warn!("LBAND CONTROL refused from {}: {}", src, e);
+                                        continue;
+                                    }
+                                };
+                                match text.parse() {
+                                    Ok(command) => commands.push(command),
+                                    Err(e) => warn!("Invalid command: {}", e),
+                                }
+                            }
+                            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                            Err(e) => return Err(e).context("Control socket read failed"),
+                        }
+                    }
+                }
+                TUI => {
+                    if let Some(socket) = telemetry.as_ref().and_then(Telemetry::udp) {
+                        commands.extend(tui_commands(socket, &mut control_gate)?);
+                    }
+                }
+                SIGNAL => {
+                    while let Some(signal) = signals.receive()? {
+                        match signal {
+                            // SIGHUP isn't supported by mio-signals, see ExecReload in the unit
+                            Signal::User1 => {
+                                reload(&mut radio, &mut config, CONFIG_PATH, &mut state.config)?;
+                                assembler.set_accept(config.accept);
+                                assembler.set_addresses(config.address.clone());
+                                filter = packet_filter(&config);
+                                control_gate.set_auth(config.control.clone());
+                                assembler.set_max_len(config.channel[0].length.max_len());
+                                assembler.set_trailer(config.channel[0].crc_trailer);
+                                assembler.set_crc(config.channel[0].crc);
+                                assembler.set_framing(config.channel[0].framing);
+                                monitor = config.fallback.map(|f| Monitor::new(f, Instant::now()));
+                                arm_fallback(&mut fallback_tfd, &config);
+                            }
+                            _ => break 'outer,
+                        }
                     }
                 }
-                SIGINT => break 'outer,
                 _ => unreachable!(),
             }
+
+            for command in commands.drain(..) {
+                match command {
+                    Command::Frequency(freq) => retune(
+                        &mut radio,
+                        &mut config.synth,
+                        &config.board,
+                        freq,
+                        config.fifo,
+                    )?,
+                    Command::Reload => {
+                        reload(&mut radio, &mut config, CONFIG_PATH, &mut state.config)?;
+                        assembler.set_accept(config.accept);
+                        assembler.set_addresses(config.address.clone());
+                        filter = packet_filter(&config);
+                        control_gate.set_auth(config.control.clone());
+                        assembler.set_max_len(config.channel[0].length.max_len());
+                        assembler.set_trailer(config.channel[0].crc_trailer);
+                        assembler.set_crc(config.channel[0].crc);
+                        assembler.set_framing(config.channel[0].framing);
+                        monitor = config.fallback.map(|f| Monitor::new(f, Instant::now()));
+                        arm_fallback(&mut fallback_tfd, &config);
+                    }
+                    Command::Transmit(_)
+                    | Command::Inhibit(_)
+                    | Command::Test(_)
+                    | Command::Pattern(..)
+                    | Command::Beacon(_) => {
+                        warn!("LBAND doesn't transmit")
+                    }
+                    Command::Log(filter) => {
+                        if let Err(e) = log.set_filter(&filter) {
+                            warn!("Invalid log filter {}: {}", filter, e);
+                        }
+                    }
+                    Command::Write(reg, value) => tune(&mut radio, reg, value)?,
+                    Command::Agc(tuning) => tune_agc(&mut radio, tuning)?,
+                    Command::Framing(framing) => {
+                        switch_framing(&mut radio, &mut config, &mut assembler, framing)?
+                    }
+                    Command::Squelch(floor) => {
+                        info!("LBAND SQUELCH {:?} -> {:?} dBm", assembler.squelch(), floor);
+                        assembler.set_squelch(floor);
+                    }
+                }
+            }
         }
     }
 
-    radio.reset()?;
+    if let Some(report) = pass.as_mut().and_then(Pass::finish) {
+        end_pass(report, &args.pass_reports, &telemetry)?;
+    }
+    info!("LBAND STATS {} {}", assembler.stats(), supply.counts());
+    save_state(&args.state, &mut state, assembler.stats());
+    if let Some(ref socket) = telemetry {
+        tui::CommState::STATS(*assembler.stats()).send(socket)?;
+    }
+    guard.shutdown();
     Ok(())
 }
@@ -0,0 +1,119 @@
+// Calibrates TX output power against a detector on GPADC13 and writes the dBm table for
+// power::Table::set(), see beacon --power.
+//
+// Keys an unmodulated carrier at each TXPWRCOEFFB step, so run it into a dummy load with the
+// uhf service stopped. The detector's transfer curve comes from --intercept and --slope.
+use anyhow::{ensure, Context, Result};
+use ax5043::{
+    config,
+    gpio::Pin,
+    guard::Guard,
+    power::{Calibration, Detector},
+    Registers,
+};
+use clap::Parser;
+use std::{
+    fs::{read_to_string, write},
+    time::Duration,
+};
+
+#[derive(Parser, Debug)]
+/// Try it out: `powercal --intercept -30 --slope 0.05 --output power.toml`
+struct Args {
+    #[arg(short, long, default_value = "/dev/spidev0.0")]
+    spi: String,
+    #[arg(long, default_value = "c3-uhf-96000.toml")]
+    config: String,
+    /// Index of the [[channel]] to key up on
+    #[arg(long, default_value = "1")]
+    channel: usize,
+    /// First TXPWRCOEFFB
+    #[arg(long, default_value = "0x40", value_parser = parse_coeff)]
+    start: u16,
+    /// Last TXPWRCOEFFB (inclusive)
+    #[arg(long, default_value = "0xFFF", value_parser = parse_coeff)]
+    stop: u16,
+    #[arg(long, default_value = "0x40", value_parser = parse_coeff)]
+    step: u16,
+    /// Time keyed before sampling, ms
+    #[arg(long, default_value = "20")]
+    dwell: u64,
+    /// GPADC conversions per step
+    #[arg(long, default_value = "8")]
+    samples: usize,
+    /// Detector output at an ADC count of 0, dBm
+    #[arg(long, allow_hyphen_values = true)]
+    intercept: f64,
+    /// Detector dB per ADC count
+    #[arg(long, allow_hyphen_values = true)]
+    slope: f64,
+    #[arg(short, long, default_value = "power.toml")]
+    output: String,
+    /// PA enable line, chip:line
+    #[arg(long, default_value = "gpiochip1:27")]
+    pa: Pin,
+}
+
+fn parse_coeff(s: &str) -> Result<u16, std::num::ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    ensure!(args.start <= args.stop, "--start must not be above --stop");
+    ensure!(args.step > 0, "--step must be positive");
+
+    // Disables the PA and resets the radio on every exit path, see guard.rs
+    let guard = Guard::new(&args.spi)?.with_pa(args.pa.output()?);
+
+    let spi0 = ax5043::open(&args.spi)?;
+    let mut callback = |_: &_, _, _, _: &_| {};
+    let mut radio = Registers::new(spi0, &mut callback);
+    radio.reset()?;
+
+    let contents = read_to_string(&args.config)?;
+    let config: config::Config = toml::from_str(&contents)?;
+    radio.probe()?.found()?;
+    config.write(&mut radio)?;
+    let tx = config.tx.context("Section [tx] required")?;
+    let channel = config
+        .channel
+        .get(args.channel)
+        .context("No such [[channel]]")?
+        .write(&mut radio, &config.board)?;
+    tx.write(&mut radio, &config.board, &channel)?;
+
+    let calibration = Calibration {
+        start: args.start,
+        stop: args.stop,
+        step: args.step,
+        dwell: Duration::from_millis(args.dwell),
+        samples: args.samples,
+        detector: Detector {
+            intercept: args.intercept,
+            slope: args.slope,
+        },
+    };
+
+    guard.enable_pa()?;
+    println!("{:>6} {:>7}", "coeff", "dBm");
+    let table = calibration.run(&mut radio, |p| println!("{:#06X} {:>7.2}", p.coeff, p.dbm))?;
+    guard.disable_pa()?;
+
+    let (min, max) = table
+        .range()
+        .context("No usable points, is the detector connected?")?;
+    println!(
+        "{} points from {:.2} to {:.2} dBm",
+        table.points.len(),
+        min,
+        max
+    );
+    write(&args.output, toml::to_string(&table)?)?;
+
+    guard.shutdown();
+    Ok(())
+}
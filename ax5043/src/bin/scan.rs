@@ -0,0 +1,112 @@
+// Surveys RSSI across a frequency range for hunting interference around the station's bands.
+//
+// The config file sets up the RX chain (bandwidth, AGC, ...), the sweep only moves FREQA.
+// Run with the uhf/lband service stopped, they share the radio. With --telemetry each sweep is
+// also sent as CommState::SWEEP for the tui's waterfall.
+use anyhow::{ensure, Context, Result};
+use ax5043::{config, guard::Guard, spectrum::Sweep, telemetry::Telemetry, tui, Registers};
+use clap::Parser;
+use mio_signals::{Signal, Signals};
+use std::{fs::read_to_string, time::Duration};
+
+#[derive(Parser, Debug)]
+/// Try it out: `scan --start 435000000 --stop 438000000 --step 25000`
+struct Args {
+    #[arg(short, long, default_value = "/dev/spidev0.0")]
+    spi: String,
+    #[arg(short, long, default_value = "c3-uhf-96000.toml")]
+    config: String,
+    /// First carrier, Hz
+    #[arg(long)]
+    start: config::Hz,
+    /// Last carrier (inclusive), Hz
+    #[arg(long)]
+    stop: config::Hz,
+    /// Hz
+    #[arg(long, default_value = "25000")]
+    step: config::Hz,
+    /// Time spent on each channel, ms
+    #[arg(long, default_value = "20")]
+    dwell: u64,
+    /// RSSI samples per channel
+    #[arg(long, default_value = "8")]
+    samples: usize,
+    /// Print CSV instead of a table
+    #[arg(long)]
+    csv: bool,
+    /// Send each sweep here too, for example 10.18.17.6:10035
+    #[arg(short, long)]
+    telemetry: Option<String>,
+    /// Sweeps to run, 0 to keep going until interrupted
+    #[arg(long, default_value = "1")]
+    repeat: u32,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    ensure!(args.start <= args.stop, "--start must not be above --stop");
+    ensure!(args.step > 0, "--step must be positive");
+
+    let guard = Guard::new(&args.spi)?;
+    // Checked between sweeps so --repeat 0 still ends with the guard's reset
+    let mut signals = Signals::new(Signal::Interrupt | Signal::Terminate)?;
+
+    let telemetry = match args.telemetry {
+        Some(ref dest) => Some(Telemetry::open(dest).context("Invalid telemetry address")?),
+        None => None,
+    };
+
+    let spi0 = ax5043::open(&args.spi)?;
+    let mut callback = |_: &_, _, _, _: &_| {};
+    let mut radio = Registers::new(spi0, &mut callback);
+    radio.reset()?;
+
+    let contents = read_to_string(&args.config)?;
+    let mut config: config::Config = toml::from_str(&contents)?;
+    radio.probe()?.found()?;
+    config.write(&mut radio)?;
+
+    let sweep = Sweep {
+        start: args.start,
+        stop: args.stop,
+        step: args.step,
+        dwell: Duration::from_millis(args.dwell),
+        samples: args.samples,
+    };
+
+    if args.csv {
+        println!("freq,min,max,mean");
+    } else {
+        println!(
+            "{:>12} {:>7} {:>7} {:>7}",
+            "freq (Hz)", "min", "max", "mean"
+        );
+    }
+    let mut count = 0;
+    while args.repeat == 0 || count < args.repeat {
+        let mut readings = Vec::new();
+        sweep.run(&mut radio, &mut config.synth, &config.board, |r| {
+            if args.csv {
+                println!("{},{:.1},{:.1},{:.1}", r.freq, r.min, r.max, r.mean);
+            } else {
+                println!(
+                    "{:>12} {:>7.1} {:>7.1} {:>7.1}",
+                    r.freq, r.min, r.max, r.mean
+                );
+            }
+            readings.push(r);
+        })?;
+        if let Some(ref socket) = telemetry {
+            tui::CommState::HELLO(tui::PROTOCOL).send(socket)?;
+            tui::CommState::RADIO("scan".to_string()).send(socket)?;
+            tui::CommState::SWEEP(readings).send(socket)?;
+        }
+        count += 1;
+        if signals.receive()?.is_some() {
+            break;
+        }
+    }
+
+    guard.shutdown();
+    Ok(())
+}
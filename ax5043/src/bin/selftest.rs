@@ -0,0 +1,183 @@
+// Pre-pass health check: transmits numbered frames on one radio and listens for them on the
+// other (UHF -> L-band, or two UHF boards), then reports the packet success rate and RSSI.
+//
+// Both configs have to agree on the carrier and modulation. Run with the uhf and lband services
+// stopped, they share the radios and PA. Exits with an error if too few frames came through.
+use anyhow::{ensure, Context, Result};
+use ax5043::{
+    capture::Meta,
+    config,
+    gpio::{Pin, Switch},
+    guard::Guard,
+    logging,
+    rx::{self, PacketAssembler},
+    tx, Registers, TX,
+};
+use clap::Parser;
+use std::{
+    fs::read_to_string,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+#[derive(Parser, Debug)]
+/// Try it out: `selftest --tx-config c3-uhf-96000.toml --rx-spi /dev/spidev1.0 --rx-config c3-lband-96000.toml`
+struct Args {
+    #[arg(long, default_value = "/dev/spidev0.0")]
+    tx_spi: String,
+    #[arg(long, default_value = "c3-uhf-96000.toml")]
+    tx_config: String,
+    /// Index of the [[channel]] to transmit on
+    #[arg(long, default_value = "0")]
+    tx_channel: usize,
+    #[arg(long, default_value = "/dev/spidev1.0")]
+    rx_spi: String,
+    #[arg(long)]
+    rx_config: String,
+    /// PA enable line, chip:line
+    #[arg(long, default_value = "gpiochip1:27")]
+    pa: Pin,
+    /// Antenna switch line, active while transmitting
+    #[arg(long)]
+    antsel: Option<Pin>,
+    /// Frames to send
+    #[arg(short, long, default_value = "20")]
+    count: u32,
+    /// Frame length, bytes
+    #[arg(long, default_value = "64")]
+    size: usize,
+    /// Time to wait for each frame, ms
+    #[arg(long, default_value = "500")]
+    timeout: u64,
+    /// Fraction of frames that have to arrive
+    #[arg(long, default_value = "0.9")]
+    pass: f64,
+    /// Log one JSON object per line
+    #[arg(long)]
+    json: bool,
+}
+
+/// "SELFTEST <seq>" padded with a counting pattern so bit errors anywhere show up
+fn frame(seq: u32, size: usize) -> Vec<u8> {
+    let mut frame = format!("SELFTEST {:05} ", seq).into_bytes();
+    let pattern = (0..=u8::MAX).cycle().take(size.saturating_sub(frame.len()));
+    frame.extend(pattern);
+    frame
+}
+
+fn load_config(path: &str) -> Result<config::Config> {
+    let contents = read_to_string(path).with_context(|| format!("Reading {}", path))?;
+    let config: config::Config = toml::from_str(&contents)?;
+    ensure!(!config.channel.is_empty(), "{}: missing [[channel]]", path);
+    Ok(config)
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.json);
+    ensure!(args.count > 0, "--count must be positive");
+    ensure!(args.size > 0, "--size must be positive");
+
+    // Disables the PA and resets both radios on every exit path, see guard.rs
+    let tx_guard = Arc::new(Guard::new(&args.tx_spi)?.with_pa(args.pa.output()?));
+    tx_guard.install_panic_hook();
+    let rx_guard = Arc::new(Guard::new(&args.rx_spi)?);
+    rx_guard.install_panic_hook();
+    let antsel = args.antsel.as_ref().map(Pin::output).transpose()?;
+
+    let tx_config = load_config(&args.tx_config)?;
+    let spi = ax5043::open(&args.tx_spi)?;
+    let mut tx_callback = |_: &_, _, _, _: &_| {};
+    let mut tx_radio = Registers::new(spi, &mut tx_callback);
+    tx_radio.reset()?;
+    tx_radio.probe()?.found()?;
+    tx_radio.check_spi(true)?;
+    tx_config.write(&mut tx_radio)?;
+    let tx = tx_config.tx.context("--tx-config needs a [tx] section")?;
+    let channel = tx_config
+        .channel
+        .get(args.tx_channel)
+        .context("No such [[channel]] in --tx-config")?
+        .write(&mut tx_radio, &tx_config.board)?;
+    tx.write(&mut tx_radio, &tx_config.board, &channel)?;
+    tx_radio.FIFOTHRESH().write(128)?; // Half the FIFO size
+
+    let rx_config = load_config(&args.rx_config)?;
+    let spi = ax5043::open(&args.rx_spi)?;
+    let mut rx_callback = |_: &_, _, _, _: &_| {};
+    let mut rx_radio = Registers::new(spi, &mut rx_callback);
+    rx_radio.reset()?;
+    rx_radio.probe()?.found()?;
+    rx_radio.check_spi(true)?;
+    ensure!(rx_config.rx.is_some(), "--rx-config needs an [rx] section");
+    rx_config.write(&mut rx_radio)?;
+    rx_radio.RSSIREFERENCE().write(32)?;
+    rx::enter(&mut rx_radio, &rx_config.board, rx_config.fifo)?;
+
+    tx_guard.enable_pa()?;
+
+    let timeout = Duration::from_millis(args.timeout);
+    let mut assembler = PacketAssembler::new().accept(rx_config.accept);
+    let mut rssi = Vec::new();
+    let mut unexpected = 0;
+
+    for seq in 0..args.count {
+        let sent = frame(seq, args.size);
+        antsel.set(true)?;
+        tx::transmit(&mut tx_radio, &sent)?;
+        antsel.set(false)?;
+
+        let start = Instant::now();
+        let received = 'wait: loop {
+            for packet in assembler.drain(&mut rx_radio)? {
+                if packet == sent {
+                    break 'wait true;
+                }
+                // Late frames (already counted lost) or someone else on the channel
+                warn!(target: "ax5043::packet", "SELFTEST unexpected {:02X?}", packet);
+                unexpected += 1;
+            }
+            if start.elapsed() > timeout {
+                break false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        if received {
+            let meta = Meta::read(&mut rx_radio, &rx_config.board)?;
+            let dbm = meta.rssi.unwrap_or_default();
+            info!(target: "ax5043::packet", "SELFTEST {} ok rssi={:.1} dBm", seq, dbm);
+            rssi.push(dbm);
+        } else {
+            warn!(target: "ax5043::packet", "SELFTEST {} lost", seq);
+        }
+    }
+
+    rx::leave(&mut rx_radio, &rx_config.board)?;
+    tx_guard.shutdown();
+    rx_guard.shutdown();
+
+    let rate = rssi.len() as f64 / f64::from(args.count);
+    println!(
+        "received {}/{} ({:.0}%), {} unexpected",
+        rssi.len(),
+        args.count,
+        rate * 100.0,
+        unexpected
+    );
+    if !rssi.is_empty() {
+        let min = rssi.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = rssi.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mean = rssi.iter().sum::<f64>() / rssi.len() as f64;
+        println!("rssi min {:.1} max {:.1} mean {:.1} dBm", min, max, mean);
+    }
+    ensure!(
+        rate >= args.pass,
+        "Success rate {:.2} below --pass {:.2}",
+        rate,
+        args.pass
+    );
+    Ok(())
+}
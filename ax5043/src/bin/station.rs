@@ -0,0 +1,552 @@
+// Drives every radio listed in a station file from one poll loop, see ax5043::station.
+//
+// For boards carrying more radios than the uhf/lband pair. Each radio gets its own IRQ line,
+// downlink socket and telemetry stream, the poll tokens are allocated per radio (see token()).
+// A receiver whose config has a [scan] section hops between its carriers (see ax5043::scanlist)
+// and prefixes each packet it sends up the uplink with the carrier, see scanlist::tag(). The uhf
+// and lband bins don't scan and refuse the section.
+use anyhow::{ensure, Context, Result};
+use ax5043::{
+    config,
+    gpio::{Input, IrqDriver},
+    guard::Guard,
+    logging,
+    rx::{self, PacketAssembler},
+    scanlist::{self, Scanner},
+    station::{self, Role, Station},
+    telemetry::Telemetry,
+    tui, tx, Bus, Registers, Status, TX,
+};
+use clap::Parser;
+use mio::{unix::SourceFd, Events, Interest, Poll, Token};
+use mio_signals::{Signal, Signals};
+use std::{
+    fs::read_to_string,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    os::fd::AsRawFd,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use timerfd::{SetTimeFlags, TimerFd, TimerState};
+use tracing::{info, warn};
+
+#[derive(Parser, Debug)]
+/// Try it out: `station --station examples/station.toml`
+struct Args {
+    #[arg(long, default_value = "station.toml")]
+    station: String,
+    /// Log one JSON object per line
+    #[arg(long)]
+    json: bool,
+}
+
+const SIGNAL: Token = Token(0);
+const IRQ: usize = 0;
+const DOWNLINK: usize = 1;
+const TELEMETRY: usize = 2;
+const FLUSH: usize = 3;
+const SCAN: usize = 4;
+const KINDS: usize = 5;
+
+/// Tokens after SIGNAL go in blocks of KINDS per radio
+fn token(radio: usize, kind: usize) -> Token {
+    Token(1 + radio * KINDS + kind)
+}
+
+type Callback = Box<dyn FnMut(&Bus, u16, Status, &[u8])>;
+
+struct Radio<'a> {
+    station: station::Radio,
+    config: config::Config,
+    link: config::Link,
+    registers: Registers<'a>,
+    irq: IrqDriver,
+    uplink: UdpSocket,
+    downlink: Option<mio::net::UdpSocket>,
+    /// Shared with the status callback
+    telemetry: Option<Rc<Telemetry>>,
+    tfd: TimerFd,
+    snapshot: tui::Snapshot,
+    /// Drains what's below the FIFO threshold, see rx::FifoPolicy
+    flush_tfd: TimerFd,
+    assembler: PacketAssembler,
+    guard: Arc<Guard>,
+    /// Read before keying each frame, see station::Radio::inhibit
+    inhibit: Option<Input>,
+    /// None without a [scan] section
+    scanner: Option<Scanner>,
+    /// The dwell, or a HOLD while a frame is coming in
+    scan_tfd: TimerFd,
+}
+
+fn load_config(radio: &station::Radio) -> Result<(config::Config, config::Link)> {
+    let contents =
+        read_to_string(&radio.config).with_context(|| format!("Reading {}", radio.config))?;
+    let config: config::Config = toml::from_str(&contents)?;
+    config
+        .fifo
+        .validate()
+        .with_context(|| format!("{}: {}", radio.name, radio.config))?;
+    let link = config::Link::new(
+        &config,
+        radio.channel,
+        radio.tx_channel.unwrap_or(radio.channel),
+    )
+    .with_context(|| format!("{}: {}", radio.name, radio.config))?;
+    if radio.role == Role::Transceiver {
+        ensure!(config.tx.is_some(), "{}: Section [tx] required", radio.name);
+        // Hopping moves the synthesizer the TX channel keys on
+        ensure!(
+            config.scan.is_none(),
+            "{}: [scan] is for receivers, a transceiver would transmit on whichever carrier it \
+             was scanning",
+            radio.name
+        );
+    }
+    Ok((config, link))
+}
+
+fn bring_up(radio: &mut Radio) -> Result<()> {
+    let registers = &mut radio.registers;
+    registers.reset()?;
+    for mismatch in registers.verify_reset()? {
+        warn!(
+            "{} RESET {} not at its default",
+            radio.station.name, mismatch
+        );
+    }
+    registers
+        .probe()?
+        .found()
+        .with_context(|| radio.station.name.clone())?;
+    radio.config.write(registers)?;
+    radio.link.to_rx(registers, &radio.config)?;
+    registers.FIFOTHRESH().write(128)?; // Half the FIFO size
+    registers.RSSIREFERENCE().write(32)?;
+
+    if let Some(socket) = radio.telemetry.as_deref() {
+        announce(&radio.station.name, radio.config.board, socket)?;
+    }
+    radio.guard.enable_pa()?;
+    rx::enter(registers, &radio.config.board, radio.config.fifo)?;
+    if let Some(ref scanner) = radio.scanner {
+        hop(radio, scanner.current())?;
+    }
+    Ok(())
+}
+
+/// Retunes to `freq` and waits out its dwell
+fn hop(radio: &mut Radio, freq: config::Hz) -> Result<()> {
+    let Some(ref scanner) = radio.scanner else {
+        return Ok(());
+    };
+    let dwell = scanner.dwell();
+    radio.assembler.clear();
+    if let Err(e) = scanlist::hop(
+        &mut radio.registers,
+        &mut radio.config.synth,
+        &radio.config.board,
+        radio.config.fifo,
+        freq,
+    ) {
+        warn!("{} SCAN to {} failed: {}", radio.station.name, freq, e);
+    }
+    arm_scan(&mut radio.scan_tfd, dwell);
+    Ok(())
+}
+
+fn arm_scan(tfd: &mut TimerFd, after: Duration) {
+    tfd.set_state(TimerState::Oneshot(after), SetTimeFlags::Default);
+}
+
+/// The dwell ran out: on to the next carrier unless a frame is coming in. Whatever the FIFO
+/// holds is forwarded first, tagged with the carrier it was heard on, hop() drops the rest.
+fn scan(radio: &mut Radio) -> Result<()> {
+    radio.scan_tfd.read();
+    drain(radio)?;
+    let busy = scanlist::busy(&mut radio.registers, &radio.assembler)?;
+    let Some(ref mut scanner) = radio.scanner else {
+        return Ok(());
+    };
+    match scanner.step(busy) {
+        Some(freq) => hop(radio, freq),
+        None => {
+            arm_scan(&mut radio.scan_tfd, scanlist::HOLD);
+            Ok(())
+        }
+    }
+}
+
+fn read_packets(radio: &mut Radio) -> Result<()> {
+    let Radio {
+        irq,
+        assembler,
+        registers,
+        uplink,
+        station,
+        scanner,
+        link,
+        ..
+    } = radio;
+    let freq = scanner.as_ref().map(Scanner::current);
+    irq.drain(|| forward(registers, assembler, uplink, &station.name, freq, &link.rx))?;
+    Ok(())
+}
+
+fn flush(radio: &mut Radio) -> Result<()> {
+    radio.flush_tfd.read();
+    drain(radio)
+}
+
+/// Forwards whatever the FIFO holds, tagged with the carrier listened on now
+fn drain(radio: &mut Radio) -> Result<()> {
+    forward(
+        &mut radio.registers,
+        &mut radio.assembler,
+        &radio.uplink,
+        &radio.station.name,
+        radio.scanner.as_ref().map(Scanner::current),
+        &radio.link.rx,
+    )
+}
+
+/// Sends the packets completed by what's in the FIFO up the uplink, decoded for the RX
+/// `channel`, tagged with the carrier `freq` when scanning and flagged with the CRC result for
+/// CrcTrailer::Flag
+fn forward(
+    registers: &mut Registers,
+    assembler: &mut PacketAssembler,
+    uplink: &UdpSocket,
+    name: &str,
+    freq: Option<config::Hz>,
+    channel: &config::ChannelParameters,
+) -> Result<()> {
+    let trailer = channel.crc_trailer;
+    assembler.drain_with(registers, |_, packet, _, crc_ok| -> Result<()> {
+        let packet = match channel.decode(packet) {
+            Ok((payload, corrections)) => {
+                if corrections.any() {
+                    info!(target: "ax5043::packet", "{} RX PACKET corrected {}", name, corrections);
+                }
+                payload
+            }
+            Err(e) => {
+                warn!(target: "ax5043::packet", "{} RX PACKET {}: {:02X?}", name, e, packet);
+                return Ok(());
+            }
+        };
+        let packet = &packet[..];
+        let result = match crc_ok {
+            true => "",
+            false => " CRC failed",
+        };
+        match freq {
+            Some(freq) => {
+                uplink.send(&trailer.uplink(&scanlist::tag(freq, packet), crc_ok))?;
+                info!(target: "ax5043::packet", "{} RX PACKET on {}{}: {:02X?}", name, freq, result, packet);
+            }
+            None => {
+                uplink.send(&trailer.uplink(packet, crc_ok))?;
+                info!(target: "ax5043::packet", "{} RX PACKET{}: {:02X?}", name, result, packet);
+            }
+        }
+        Ok(())
+    })
+}
+
+fn transmit(radio: &mut Radio) -> Result<()> {
+    let Some(ref downlink) = radio.downlink else {
+        return Ok(());
+    };
+    let registers = &mut radio.registers;
+    let tracking = rx::Tracking::save(registers, Instant::now())?;
+    rx::leave(registers, &radio.config.board)?;
+    radio.link.to_tx(registers, &radio.config)?;
+
+    let mut buf = [0; 2048];
+    loop {
+        match downlink.recv_from(&mut buf) {
+            Ok((amt, src)) => {
+                let name = &radio.station.name;
+                let Some(frame) = radio
+                    .config
+                    .auth
+                    .as_ref()
+                    .map_or(Some(&buf[..amt]), |auth| auth.verify(&buf[..amt]))
+                else {
+                    warn!(
+                        target: "ax5043::packet", "{} AUTH REJECTED {} from {:?}",
+                        name, amt, src
+                    );
+                    continue;
+                };
+                info!(
+                    target: "ax5043::packet", "{} SEND {} from {:?}: {:02X?}",
+                    name,
+                    frame.len(),
+                    src,
+                    frame
+                );
+                if let Some(ref line) = radio.inhibit {
+                    if line.asserted()? {
+                        warn!(
+                            target: "ax5043::packet", "{} SEND {} dropped by TX inhibit",
+                            name, amt
+                        );
+                        continue;
+                    }
+                }
+                let frame = match radio.link.tx.encode(frame) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        warn!(target: "ax5043::packet", "{} SEND {} dropped: {}", name, amt, e);
+                        continue;
+                    }
+                };
+                tx::transmit(registers, &frame)?;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e).context("Downlink socket read failed"),
+        }
+    }
+
+    radio.link.to_rx(registers, &radio.config)?;
+    rx::enter(registers, &radio.config.board, radio.config.fifo)?;
+    tracking.restore(registers, Instant::now())?;
+    Ok(())
+}
+
+/// The states the tui only gets once, sent at bring up and again on each new TCP connection
+fn announce(name: &str, board: config::Board, socket: &Telemetry) -> Result<()> {
+    tui::CommState::HELLO(tui::PROTOCOL).send(socket)?;
+    tui::CommState::RADIO(name.to_string()).send(socket)?;
+    tui::CommState::BOARD(board).send(socket)?;
+    Ok(())
+}
+
+fn send_telemetry(radio: &mut Radio) -> Result<()> {
+    radio.tfd.read();
+    if let Some(socket) = radio.telemetry.as_deref() {
+        socket.flush()?;
+        if socket.take_resync() {
+            info!(
+                "{} TELEMETRY connected, resending board",
+                radio.station.name
+            );
+            announce(&radio.station.name, radio.config.board, socket)?;
+        }
+        let channel = &radio.link.rx;
+        tui::CommState::HELLO(tui::PROTOCOL).send(socket)?;
+        tui::CommState::RADIO(radio.station.name.clone()).send(socket)?;
+        tui::CommState::STATS(*radio.assembler.stats()).send(socket)?;
+        // A pending IRQ gets the bus first, see tui::Snapshot
+        if !radio.irq.asserted()? {
+            radio
+                .snapshot
+                .step(&mut radio.registers, &radio.config.board, channel, None)?
+                .send(socket)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.json);
+
+    let station: Station = toml::from_str(&read_to_string(&args.station)?)?;
+    station.validate()?;
+    let configs = station
+        .radio
+        .iter()
+        .map(load_config)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut poll = Poll::new()?;
+    let registry = poll.registry();
+    let mut events = Events::with_capacity(128);
+
+    let mut signals = Signals::new(Signal::Interrupt | Signal::Terminate)?;
+    registry.register(&mut signals, SIGNAL, Interest::READABLE)?;
+
+    let src = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+    let mut telemetry = Vec::new();
+    for entry in &station.radio {
+        telemetry.push(match entry.telemetry {
+            Some(ref dest) => Some(Rc::new(
+                Telemetry::open(dest).context("Invalid telemetry address")?,
+            )),
+            None => None,
+        });
+    }
+
+    // Registers borrows its status callback, so they all have to outlive the radios
+    let mut callbacks = Vec::new();
+    for socket in &telemetry {
+        let socket = socket.clone();
+        let mut status = Status::empty();
+        let callback: Callback = Box::new(move |_, _, s, _| {
+            if s != status {
+                if let Some(socket) = socket.as_deref() {
+                    tui::CommState::STATUS(s).send(socket).unwrap();
+                }
+                status = s;
+            }
+        });
+        callbacks.push(callback);
+    }
+
+    let mut radios = Vec::new();
+    for (i, ((entry, (config, link)), (callback, telemetry))) in station
+        .radio
+        .into_iter()
+        .zip(configs)
+        .zip(callbacks.iter_mut().zip(telemetry))
+        .enumerate()
+    {
+        // Disables the PA and resets the radio on every exit path, see guard.rs
+        let mut guard = Guard::new(&entry.spi)?;
+        if let Some(ref pa) = entry.pa {
+            guard = guard.with_pa(pa.output()?);
+        }
+        let guard = Arc::new(guard);
+        guard.install_panic_hook();
+        let inhibit = entry
+            .inhibit
+            .as_ref()
+            .map(|pin| pin.input(entry.inhibit_low))
+            .transpose()?;
+
+        let irq = entry.irq.irq()?;
+        registry.register(
+            &mut SourceFd(&irq.as_raw_fd()),
+            token(i, IRQ),
+            Interest::READABLE,
+        )?;
+
+        let uplink = UdpSocket::bind(src)?;
+        uplink.connect(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            entry.uplink,
+        ))?;
+
+        let downlink = match (entry.role, entry.downlink) {
+            (Role::Transceiver, Some(port)) => {
+                let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+                let mut socket = mio::net::UdpSocket::bind(addr)?;
+                registry.register(&mut socket, token(i, DOWNLINK), Interest::READABLE)?;
+                Some(socket)
+            }
+            _ => None,
+        };
+
+        let mut tfd = TimerFd::new()?;
+        if telemetry.is_some() {
+            tfd.set_state(
+                TimerState::Periodic {
+                    current: Duration::new(1, 0),
+                    interval: Duration::from_millis(25),
+                },
+                SetTimeFlags::Default,
+            );
+        }
+        registry.register(
+            &mut SourceFd(&tfd.as_raw_fd()),
+            token(i, TELEMETRY),
+            Interest::READABLE,
+        )?;
+
+        let mut flush_tfd = TimerFd::new()?;
+        if let Some(interval) = config.fifo.flush() {
+            flush_tfd.set_state(
+                TimerState::Periodic {
+                    current: interval,
+                    interval,
+                },
+                SetTimeFlags::Default,
+            );
+        }
+        registry.register(
+            &mut SourceFd(&flush_tfd.as_raw_fd()),
+            token(i, FLUSH),
+            Interest::READABLE,
+        )?;
+
+        let scanner = config
+            .scan
+            .clone()
+            .map(Scanner::new)
+            .transpose()
+            .with_context(|| entry.name.clone())?;
+        let scan_tfd = TimerFd::new()?;
+        registry.register(
+            &mut SourceFd(&scan_tfd.as_raw_fd()),
+            token(i, SCAN),
+            Interest::READABLE,
+        )?;
+
+        let accept = config.accept;
+        let address = config.address.clone();
+        let spi = ax5043::open(&entry.spi)?;
+        let mut radio = Radio {
+            registers: Registers::new(spi, callback.as_mut()),
+            station: entry,
+            config,
+            link,
+            irq,
+            uplink,
+            downlink,
+            telemetry,
+            tfd,
+            snapshot: tui::Snapshot::default(),
+            flush_tfd,
+            assembler: PacketAssembler::new()
+                .accept(accept)
+                .addresses(address)
+                .max_len(link.rx.length.max_len())
+                .trailer(link.rx.crc_trailer)
+                .crc(link.rx.crc)
+                .framing(link.rx.framing),
+            guard,
+            inhibit,
+            scanner,
+            scan_tfd,
+        };
+        bring_up(&mut radio)?;
+        info!(
+            "{} up on {} ({:?})",
+            radio.station.name, radio.station.spi, radio.station.role
+        );
+        radios.push(radio);
+    }
+
+    'outer: loop {
+        poll.poll(&mut events, None)?;
+        for event in events.iter() {
+            if event.token() == SIGNAL {
+                break 'outer;
+            }
+            let n = event.token().0 - 1;
+            let Some(radio) = radios.get_mut(n / KINDS) else {
+                warn!("Unexpected token {:?}", event.token());
+                continue;
+            };
+            match n % KINDS {
+                IRQ => read_packets(radio)?,
+                DOWNLINK => transmit(radio)?,
+                TELEMETRY => send_telemetry(radio)?,
+                FLUSH => flush(radio)?,
+                SCAN => scan(radio)?,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    for radio in &radios {
+        info!("{} STATS {}", radio.station.name, radio.assembler.stats());
+        radio.guard.shutdown();
+    }
+    Ok(())
+}
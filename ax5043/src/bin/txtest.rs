@@ -0,0 +1,96 @@
+// Keys up on a test pattern for occupied bandwidth and spurious emission measurements.
+//
+//   txtest --pattern pn9 --seconds 5
+//
+// Sends a carrier, 0101... or PN9 (see tx::Pattern) on one [[channel]] of the config, raw and
+// unencoded, back to back --count times. Run it into the analyzer or a dummy load with the uhf
+// service stopped.
+use anyhow::{ensure, Context, Result};
+use ax5043::{
+    config,
+    gpio::{Input, Pin},
+    guard::Guard,
+    tx::{self, Pattern},
+    Registers, TX,
+};
+use clap::Parser;
+use std::{fs::read_to_string, time::Duration};
+
+#[derive(Parser, Debug)]
+/// Try it out: `txtest --pattern alternating --seconds 2`
+struct Args {
+    #[arg(short, long, default_value = "/dev/spidev0.0")]
+    spi: String,
+    #[arg(long, default_value = "c3-uhf-96000.toml")]
+    config: String,
+    /// Index of the [[channel]] to transmit on
+    #[arg(long, default_value = "0")]
+    channel: usize,
+    /// carrier, alternating or pn9
+    #[arg(short, long, default_value = "carrier")]
+    pattern: Pattern,
+    /// Length of each transmission
+    #[arg(long, default_value = "1")]
+    seconds: f64,
+    /// Transmissions, one after the other
+    #[arg(long, default_value = "1")]
+    count: u32,
+    /// PA enable line, chip:line
+    #[arg(long, default_value = "gpiochip1:27")]
+    pa: Pin,
+    /// TX inhibit line, chip:line. The test stops before keying while it's asserted, see
+    /// ax5043::schedule::Inhibit.
+    #[arg(long)]
+    inhibit: Option<Pin>,
+    /// --inhibit is asserted low, a switch to ground with a pull-up
+    #[arg(long)]
+    inhibit_low: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    // Disables the PA and resets the radio on every exit path, see guard.rs
+    let guard = Guard::new(&args.spi)?.with_pa(args.pa.output()?);
+    let inhibit = args
+        .inhibit
+        .as_ref()
+        .map(|pin| pin.input(args.inhibit_low))
+        .transpose()?;
+    let inhibited =
+        || -> Result<bool> { Ok(inhibit.as_ref().map(Input::asserted).transpose()? == Some(true)) };
+
+    let spi0 = ax5043::open(&args.spi)?;
+    let mut callback = |_: &_, _, _, _: &_| {};
+    let mut radio = Registers::new(spi0, &mut callback);
+    radio.reset()?;
+
+    let contents = read_to_string(&args.config)?;
+    let config: config::Config = toml::from_str(&contents)?;
+    radio.probe()?.found()?;
+    config.write(&mut radio)?;
+    let tx = config.tx.context("Section [tx] required")?;
+    let channel = config
+        .channel
+        .get(args.channel)
+        .context("No such [[channel]]")?
+        .write(&mut radio, &config.board)?;
+    tx.write(&mut radio, &config.board, &channel)?;
+    radio.FIFOTHRESH().write(128)?;
+
+    let len = tx::pattern_len(Duration::from_secs_f64(args.seconds), channel.datarate);
+    println!(
+        "{} for {} s ({} B) on {}",
+        args.pattern, args.seconds, len, channel
+    );
+    ensure!(!inhibited()?, "TX inhibit asserted");
+    guard.enable_pa()?;
+    for _ in 0..args.count {
+        ensure!(!inhibited()?, "TX inhibit asserted");
+        tx::transmit_pattern(&mut radio, args.pattern, len)?;
+    }
+    guard.disable_pa()?;
+
+    guard.shutdown();
+    Ok(())
+}
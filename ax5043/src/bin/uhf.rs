@@ -1,221 +1,836 @@
 // Intended to be run on the C3v6, takes data from UDP port 10015
 // and transmits it through the UHF AX5043
-use anyhow::{bail, ensure, Context, Result};
-use ax5043::{config, registers, registers::*, tui, Registers, RX, TX};
-use clap::Parser;
-use crc::{Crc, CRC_16_GENIBUS}; // TODO: this CRC works but is it correct?
-use gpiocdev::{
-    line::{EdgeDetection, Value},
-    Request,
+use anyhow::{ensure, Context, Result};
+use ax5043::{
+    agc,
+    auth::ControlGate,
+    capture::{self, Direction, FileCapture, Meta},
+    config,
+    control::{Command, Tunable},
+    discover,
+    fallback::{self, Monitor, Rate},
+    filter::{PacketFilter, Verdict},
+    gpio::{Input, Pin, Switch},
+    guard::Guard,
+    image::{Delta, Image},
+    logging,
+    pass::{self, Pass},
+    power::PowerSensor,
+    pps,
+    registers::*,
+    rejects::RejectLog,
+    rx::{self, PacketAssembler, Stats},
+    schedule::{Gate, Inhibit, Mode, Schedule},
+    spool::{Forwarder, Spool},
+    state::State,
+    supply::History,
+    telemetry::Telemetry,
+    thermal, tui, tx,
+    watchdog::{Reason, Watchdog},
+    Registers, RX, TX,
 };
+use clap::Parser;
 use mio::net::UdpSocket;
 use mio::{unix::SourceFd, Events, Interest, Poll, Token};
 use mio_signals::{Signal, Signals};
 use std::{
+    cell::Cell,
+    collections::VecDeque,
     fs::read_to_string,
-    io::{ErrorKind, Write},
+    io::ErrorKind,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     os::fd::AsRawFd,
-    time::Duration,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 use timerfd::{SetTimeFlags, TimerFd, TimerState};
+use tracing::{error, info, warn};
 
-fn process_chunk(chunk: FIFOChunkRX, packet: &mut Vec<u8>, uplink: &mut UdpSocket) -> Result<()> {
-    if let FIFOChunkRX::DATA { flags, ref data } = chunk {
-        //println!("{:02X?}", chunk);
-        if flags.intersects(
-            FIFODataRXFlags::ABORT
-                | FIFODataRXFlags::SIZEFAIL
-                | FIFODataRXFlags::ADDRFAIL
-                | FIFODataRXFlags::CRCFAIL
-                | FIFODataRXFlags::RESIDUE,
-        ) {
-            println!(
-                "UHF REJECTED {:?} {:02X?} ...+{}",
-                flags,
-                data[0],
-                data.len()
-            );
-            packet.clear();
-            return Ok(());
+#[allow(clippy::too_many_arguments)]
+fn read_packet(
+    radio: &mut Registers,
+    assembler: &mut PacketAssembler,
+    uplink: &mut Forwarder<UdpSocket>,
+    capture: &mut Option<FileCapture>,
+    rejects: &mut Option<RejectLog>,
+    pass: &mut Option<Pass>,
+    monitor: &mut Option<Monitor>,
+    filter: &mut Option<Box<dyn PacketFilter>>,
+    telemetry: &Option<Telemetry>,
+    config: &config::Config,
+) -> Result<()> {
+    // Only read for the outputs that record it, and only once something arrived
+    let wants_meta = capture.is_some()
+        || rejects.is_some()
+        || pass.is_some()
+        || monitor.is_some()
+        || filter.is_some()
+        || uplink.spool().is_some()
+        || telemetry.is_some();
+    let mut meta = None;
+    let mut read_meta = |radio: &mut Registers| -> Result<Meta> {
+        if meta.is_none() && wants_meta {
+            meta = Some(Meta::read(radio, &config.board)?);
         }
+        Ok(meta.unwrap_or_default())
+    };
 
-        if flags.contains(FIFODataRXFlags::PKTSTART) {
-            if !packet.is_empty() {
-                println!(
-                    "UHF PKT RESTART rejecting {:02X?} ...+{}",
-                    data[0],
-                    data.len()
-                );
+    assembler.drain_with(radio, |radio, packet, antenna, crc_ok| -> Result<()> {
+        let meta = Meta {
+            antenna,
+            crc_failed: !crc_ok,
+            ..read_meta(radio)?
+        };
+        let packet = match config.channel[EDL_CHANNEL].decode(packet) {
+            Ok((payload, corrections)) => {
+                if corrections.any() {
+                    info!(target: "ax5043::packet", "UHF RX PACKET corrected {}", corrections);
+                }
+                payload
+            }
+            Err(e) => {
+                warn!(target: "ax5043::packet", "UHF RX PACKET {}: {:02X?}", e, packet);
+                if let Some(pass) = pass {
+                    pass.packet(SystemTime::now(), &meta, false);
+                }
+                if let Some(monitor) = monitor {
+                    monitor.packet(false, meta.rssi, Instant::now());
+                }
+                return Ok(());
             }
-            packet.clear();
+        };
+        let packet = &packet[..];
+        if let Some(pass) = pass {
+            pass.packet(SystemTime::now(), &meta, crc_ok);
+        }
+        if let Some(monitor) = monitor {
+            monitor.packet(crc_ok, meta.rssi, Instant::now());
         }
+        let trailer = config.channel[EDL_CHANNEL].crc_trailer;
+        let verdict = match filter {
+            Some(filter) => filter.inspect(packet, &meta),
+            None => Verdict::Forward,
+        };
+        match verdict {
+            Verdict::Forward => uplink.send(&trailer.uplink(packet, crc_ok), &meta)?,
+            Verdict::Drop => {
+                info!(target: "ax5043::packet", "UHF RX PACKET filtered: {:02X?}", packet)
+            }
+            Verdict::Tag(ref tag) => {
+                let tagged = [tag, packet].concat();
+                uplink.send(&trailer.uplink(&tagged, crc_ok), &meta)?
+            }
+        }
+        if let Some(socket) = telemetry {
+            let frame = tui::Frame {
+                crc_failed: !crc_ok,
+                ..tui::Frame::new(packet.to_vec(), meta.rssi, None)
+            };
+            tui::CommState::PACKET(frame).send(socket)?;
+        }
+        if let Some(capture) = capture {
+            capture.write(Direction::Inbound, packet, &meta)?;
+        }
+        match crc_ok {
+            true => info!(target: "ax5043::packet", "UHF RX PACKET: {:02X?}", packet),
+            false => warn!(target: "ax5043::packet", "UHF RX PACKET CRC failed: {:02X?}", packet),
+        }
+        Ok(())
+    })?;
 
-        if !flags.contains(FIFODataRXFlags::PKTSTART) && packet.is_empty() {
-            println!("Invalid continued chunk {:02X?}", chunk);
-            return Ok(());
+    if let Some(socket) = telemetry {
+        for abort in assembler.take_aborts() {
+            tui::CommState::ABORT(abort).send(socket)?;
         }
+    }
 
-        packet.write_all(data)?;
-        if flags.contains(FIFODataRXFlags::PKTEND) {
-            let bytes = packet.split_off(packet.len() - 2);
-            let checksum = u16::from_be_bytes([bytes[0], bytes[1]]);
-            let ccitt = Crc::<u16>::new(&CRC_16_GENIBUS);
-            let mut digest = ccitt.digest();
-            digest.update(packet);
-            let calculated = digest.finalize();
-
-            if calculated == checksum {
-                uplink.send(packet)?;
-                println!("UHF RX PACKET: {:02X?}", packet);
-            } else {
-                println!(
-                    "Rejected CRC: received 0x{:x}, calculated 0x{:x}",
-                    checksum, calculated
-                );
-            }
-            packet.clear();
+    let rejected = assembler.take_rejected();
+    if rejected.is_empty() {
+        return Ok(());
+    }
+    let meta = read_meta(radio)?;
+    for rejected in rejected {
+        if let Some(pass) = pass {
+            pass.packet(SystemTime::now(), &meta, false);
+        }
+        if let Some(monitor) = monitor.as_mut().filter(|_| rejected.reason.damaged()) {
+            monitor.packet(false, meta.rssi, Instant::now());
+        }
+        if let Some(rejects) = rejects {
+            rejects.write(&rejected, &meta)?;
+        }
+        if let Some(socket) = telemetry {
+            let frame = tui::Frame::new(rejected.data, meta.rssi, Some(rejected.reason));
+            tui::CommState::PACKET(frame).send(socket)?;
         }
     }
     Ok(())
 }
 
-fn read_packet(radio: &mut Registers, packet: &mut Vec<u8>, uplink: &mut UdpSocket) -> Result<()> {
-    let len = radio.FIFOCOUNT().read()?;
-    if len == 0 {
-        return Ok(());
+/// Everything waiting on the socket, so the frames can be counted as queued before sending
+fn receive(socket: &UdpSocket) -> std::io::Result<Vec<(Vec<u8>, SocketAddr)>> {
+    let mut frames = Vec::new();
+    let mut buf = [0; 2048];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((amt, src)) => frames.push((buf[..amt].to_vec(), src)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(frames),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// A frame waiting for its turn on the air
+struct Queued {
+    frame: Vec<u8>,
+    /// None for Command::Test frames, which are made here and skip auth
+    src: Option<SocketAddr>,
+    channel: usize,
+    /// Command::Pattern and its length in bytes, sent in place of the empty frame
+    pattern: Option<(tx::Pattern, usize)>,
+}
+
+/// The register writes between RX and TX on one channel, see Downlink::prepare()
+struct Turnaround {
+    to_tx: Delta,
+    to_rx: Delta,
+}
+
+/// Frames go out one at a time, each one started here and finished by the radio IRQ (see
+/// tx::Transmission) so the poll loop keeps running during a burst
+#[derive(Default)]
+struct Downlink {
+    queue: VecDeque<Queued>,
+    /// The frame on the air and its length
+    sending: Option<(tx::Transmission, usize)>,
+    /// Channel the radio is set up to transmit on, None while it's in RX
+    channel: Option<usize>,
+    /// By channel
+    turnaround: Vec<Turnaround>,
+    stats: tx::Stats,
+    /// Sampled around every frame, see ax5043::power
+    sensor: Option<Box<dyn PowerSensor>>,
+    /// Where RX was before the radio last left it, restored on the way back
+    tracking: Option<rx::Tracking>,
+    /// POWSTICKYSTAT as keying up read it, for supply::History::hold()
+    sticky: Option<PowStat>,
+    /// When the frame on the air has to be done by, see tx::deadline()
+    deadline: Option<Instant>,
+}
+
+impl Downlink {
+    fn push(&mut self, frame: Vec<u8>, src: Option<SocketAddr>, channel: usize) {
+        self.stats.queued += 1;
+        self.queue.push_back(Queued {
+            frame,
+            src,
+            channel,
+            pattern: None,
+        });
+    }
+
+    /// Queues `time` of `pattern` behind the frames already waiting, see tx::Transmission::pattern()
+    fn push_pattern(
+        &mut self,
+        pattern: tx::Pattern,
+        time: Duration,
+        channel: usize,
+        datarate: u64,
+    ) {
+        self.stats.queued += 1;
+        self.queue.push_back(Queued {
+            frame: Vec::new(),
+            src: None,
+            channel,
+            pattern: Some((pattern, tx::pattern_len(time, datarate))),
+        });
+    }
+
+    /// Works out what switching to TX on each channel and back to RX takes, so switching is a
+    /// few burst writes instead of rewriting the channel and TX parameters. Run again whenever
+    /// the config changes.
+    fn prepare(&mut self, config: &config::Config) -> Result<()> {
+        let tx = config.tx.context("Section [tx] required")?;
+        let rx = Image::record(|radio| config.configure(radio))?;
+        self.turnaround = (0..config.channel.len())
+            .map(|channel| {
+                let image = Image::record(|radio| {
+                    config.configure(radio)?;
+                    let params = config.channel[channel].write(radio, &config.board)?;
+                    tx.write(radio, &config.board, &params)?;
+                    Ok(())
+                })?;
+                let turnaround = Turnaround {
+                    to_tx: rx.delta(&image),
+                    to_rx: image.delta(&rx),
+                };
+                info!(
+                    "UHF TURNAROUND channel {}: {} to TX, {} to RX",
+                    channel, turnaround.to_tx, turnaround.to_rx
+                );
+                Ok(turnaround)
+            })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Starts the next queued frame unless one is already on the air. With the queue empty it
+    /// puts the radio back in RX on the EDL channel.
+    fn next(
+        &mut self,
+        radio: &mut Registers,
+        config: &config::Config,
+        antsel: &impl Switch,
+        capture: &mut Option<FileCapture>,
+    ) -> Result<()> {
+        if self.sending.is_some() {
+            return Ok(());
+        }
+        while let Some(Queued {
+            frame,
+            src,
+            channel,
+            pattern,
+        }) = self.queue.pop_front()
+        {
+            self.stats.queued = self.stats.queued.saturating_sub(1);
+            let buf = match (src, config.auth.as_ref()) {
+                (Some(src), Some(auth)) => match auth.verify(&frame) {
+                    Some(payload) => payload,
+                    None => {
+                        warn!(
+                            target: "ax5043::packet", "UHF AUTH REJECTED {} from {:?}",
+                            frame.len(), src
+                        );
+                        continue;
+                    }
+                },
+                _ => &frame,
+            };
+            if self.channel != Some(channel) {
+                match self.channel {
+                    None => {
+                        self.tracking = Some(rx::Tracking::save(radio, Instant::now())?);
+                        rx::leave(radio, &config.board)?;
+                        antsel.set(true)?;
+                    }
+                    Some(previous) => self.turnaround[previous].to_rx.apply(radio)?,
+                }
+                self.turnaround[channel].to_tx.apply(radio)?;
+                self.channel = Some(channel);
+            }
+            let idle = self.sample();
+            let (transmission, len, on_air) = match pattern {
+                Some((pattern, len)) => {
+                    info!(target: "ax5043::packet", "UHF PATTERN {} {} B", pattern, len);
+                    (tx::Transmission::pattern(radio, pattern, len)?, len, len)
+                }
+                None => {
+                    match src {
+                        Some(src) => info!(
+                            target: "ax5043::packet", "UHF SEND {} from {:?}: {:02X?}",
+                            buf.len(), src, buf
+                        ),
+                        None => info!(
+                            target: "ax5043::packet", "UHF TEST {}: {:02X?}", buf.len(), buf
+                        ),
+                    }
+                    if let Some(capture) = capture {
+                        capture.write(Direction::Outbound, buf, &Meta::default())?;
+                    }
+                    let frame = match config.channel[channel].encode(buf) {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            warn!(
+                                target: "ax5043::packet", "UHF SEND {} dropped: {}", buf.len(), e
+                            );
+                            continue;
+                        }
+                    };
+                    let transmission = tx::Transmission::start(radio, &frame)?;
+                    (transmission, buf.len(), frame.len())
+                }
+            };
+            let sticky = transmission.sticky();
+            self.sticky = Some(self.sticky.map_or(sticky, |held| held & sticky));
+            self.sending = Some((transmission, len));
+            let airtime = tx::deadline(on_air, config.channel[channel].datarate);
+            self.deadline = Some(Instant::now() + airtime);
+            if let (Some(idle), Some(keyed)) = (idle, self.sample()) {
+                let readings = ax5043::power::Readings { idle, keyed };
+                info!(target: "ax5043::packet", "UHF RF {}", readings);
+                self.stats.rf = Some(readings);
+            }
+            return Ok(());
+        }
+
+        if let Some(channel) = self.channel.take() {
+            self.turnaround[channel].to_rx.apply(radio)?;
+            antsel.set(false)?;
+            rx::enter(radio, &config.board, config.fifo)?;
+            if let Some(tracking) = self.tracking.take() {
+                tracking.restore(radio, Instant::now())?;
+            }
+        }
+        Ok(())
     }
 
-    match radio.FIFODATARX().read(len.into()) {
-        Ok(chunks) => {
-            for chunk in chunks {
-                process_chunk(chunk, packet, uplink)?;
+    /// A power sensor failure is only logged, it doesn't hold up the frame
+    fn sample(&mut self) -> Option<ax5043::power::RfPower> {
+        match self.sensor.as_mut()?.sample() {
+            Ok(power) => Some(power),
+            Err(e) => {
+                warn!("UHF POWER SENSOR {}", e);
+                None
             }
         }
-        Err(e) => {
-            // FIFO Errors are usually just overflow, non-fatal
-            println!("{}", e);
-            packet.clear();
+    }
+
+    /// Cuts off the frame on the air if it's past its deadline, the IRQ that should have ended
+    /// it never came, and goes on with the queue or back to RX
+    fn overdue(
+        &mut self,
+        radio: &mut Registers,
+        config: &config::Config,
+        antsel: &impl Switch,
+        capture: &mut Option<FileCapture>,
+        now: Instant,
+    ) -> Result<()> {
+        if self.deadline.is_none_or(|deadline| now < deadline) {
+            return Ok(());
+        }
+        self.deadline = None;
+        if let Some((transmission, len)) = self.sending.take() {
+            transmission.abort(radio)?;
+            warn!(target: "ax5043::packet", "UHF SEND {} timed out", len);
         }
+        self.next(radio, config, antsel, capture)
     }
+
+    /// Forgets the frame on the air after the radio was reset under it, the rest stay queued
+    fn abort(&mut self, antsel: &impl Switch) -> Result<()> {
+        self.deadline = None;
+        if let Some((_, len)) = self.sending.take() {
+            warn!(target: "ax5043::packet", "UHF SEND {} abandoned", len);
+        }
+        if self.channel.take().is_some() {
+            antsel.set(false)?;
+        }
+        // The radio was reset, these are from before
+        self.tracking = None;
+        Ok(())
+    }
+
+    /// Cuts off the frame on the air and drops the queue, then goes back to RX
+    fn inhibit(
+        &mut self,
+        radio: &mut Registers,
+        config: &config::Config,
+        antsel: &impl Switch,
+    ) -> Result<()> {
+        self.deadline = None;
+        if let Some((transmission, len)) = self.sending.take() {
+            transmission.abort(radio)?;
+            warn!(target: "ax5043::packet", "UHF SEND {} cut off by TX inhibit", len);
+        }
+        if !self.queue.is_empty() {
+            warn!(
+                target: "ax5043::packet", "UHF SEND {} queued dropped by TX inhibit",
+                self.queue.len()
+            );
+            self.queue.clear();
+            self.stats.queued = 0;
+        }
+        self.next(radio, config, antsel, &mut None)
+    }
+
+    /// Runs on each radio IRQ while a frame is on the air, true once it's gone
+    fn service(&mut self, radio: &mut Registers) -> Result<bool> {
+        let Some((ref mut transmission, len)) = self.sending else {
+            return Ok(false);
+        };
+        if !transmission.service(radio)? {
+            return Ok(false);
+        }
+        self.sending = None;
+        self.deadline = None;
+        self.stats.sent += 1;
+        self.stats.bytes += len as u64;
+        Ok(true)
+    }
+}
+
+const CONFIG_PATH: &str = "c3-uhf-96000.toml";
+const EDL_CHANNEL: usize = 0;
+const BEACON_CHANNEL: usize = 1;
+
+fn retune(
+    radio: &mut Registers,
+    synth: &mut config::Synthesizer,
+    board: &config::Board,
+    freq: config::Hz,
+    fifo: rx::FifoPolicy,
+) -> Result<()> {
+    rx::leave(radio, board)?;
+    let previous = synth.freq_a;
+    if let Err(e) = synth.retune(radio, board, freq) {
+        error!("UHF RETUNE to {} failed: {}", freq, e);
+        synth.retune(radio, board, previous)?;
+    } else {
+        info!("UHF RETUNE {} -> {}", previous, freq);
+    }
+    rx::enter(radio, board, fifo)?;
     Ok(())
 }
 
-fn transmit(radio: &mut Registers, buf: &[u8], src: SocketAddr) -> Result<()> {
-    radio.PWRMODE().write(PwrMode {
-        flags: PwrFlags::XOEN | PwrFlags::REFEN,
-        mode: PwrModes::TX,
-    })?;
+/// The parsed config and the file contents, for the state file
+fn load_config(path: &str) -> Result<(config::Config, String)> {
+    let contents = read_to_string(path)?;
+    let config: config::Config = toml::from_str(&contents)?;
+    ensure!(config.tx.is_some(), "Section [tx] required");
+    ensure!(
+        config.scan.is_none(),
+        "[scan] is only followed by the station bin"
+    );
+    ensure!(
+        config.channel.len() > BEACON_CHANNEL,
+        "Missing second [channel] (beacon)"
+    );
+    config.fifo.validate()?;
+    if let Some(ref thermal) = config.thermal {
+        thermal.validate()?;
+    }
+    if let Some(ref pps) = config.pps {
+        pps.validate()?;
+    }
+    if let Some(ref fallback) = config.fallback {
+        fallback.validate(config.channel.len())?;
+        ensure!(
+            fallback.channel != BEACON_CHANNEL,
+            "[fallback] channel is the beacon channel"
+        );
+    }
+    Ok((config, contents))
+}
 
-    _ = radio.PLLRANGINGA().read()?; // sticky lock bit ~ IRQPLLUNLIOCK, gate
-    _ = radio.POWSTICKYSTAT().read()?; // sticky lock bit ~ IRQPLLUNLIOCK, gate
-
-    // FIXME: I experienced some crashes that probably occured because FIFOTHRESH returned the
-    // wrong value. Once 0, once too big (but not measured). Lets hard code it for now to be safe
-    // for flight.
-    //let thresh: usize = radio.FIFOTHRESH().read()?.into();
-    let thresh: usize = 128;
-
-    let pa_on = FIFOChunkTX::TXCTRL(TXCtrl::SETPA | TXCtrl::PASTATE);
-    /* FIXME: this is the recommended preamble
-    let preamble = FIFOChunkTX::DATA {
-        flags: FIFODataTXFlags::RAW,
-        data: vec![0x11],
+/// Runs the thermal trim every [thermal] interval, or not at all without the section. Again
+/// after each reload, the interval is live.
+fn arm_thermal(tfd: &mut TimerFd, config: &config::Config) {
+    let state = match config.thermal {
+        Some(ref thermal) => {
+            let interval = Duration::from_secs(thermal.interval);
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            }
+        }
+        None => TimerState::Disarmed,
     };
-    */
-    let preamble = FIFOChunkTX::REPEATDATA {
-        flags: FIFODataTXFlags::RAW | FIFODataTXFlags::NOCRC,
-        count: 0x50,
-        data: 0x7E,
+    tfd.set_state(state, SetTimeFlags::Default);
+}
+
+/// Where EDL frames go out: on the fallback with [fallback] tx, otherwise on the usual
+/// channel, which Config::swap_channel moved to the fallback's index
+fn edl_channel(monitor: &Option<Monitor>) -> usize {
+    match monitor {
+        Some(m) if m.rate() == Rate::Fallback && !m.config().tx => m.config().channel,
+        _ => EDL_CHANNEL,
+    }
+}
+
+/// Checks the link once a second with a [fallback] section, not at all without. Again after
+/// each reload.
+fn arm_fallback(tfd: &mut TimerFd, config: &config::Config) {
+    let state = match config.fallback {
+        Some(_) => TimerState::Periodic {
+            current: Duration::new(1, 0),
+            interval: Duration::new(1, 0),
+        },
+        None => TimerState::Disarmed,
     };
+    tfd.set_state(state, SetTimeFlags::Default);
+}
 
-    let postamble = FIFOChunkTX::REPEATDATA {
-        flags: FIFODataTXFlags::RAW | FIFODataTXFlags::NOCRC,
-        count: 0x5,
-        data: 0x7E,
+/// Moves RX to or from the [fallback] channel, see ax5043::fallback
+fn switch_rate(
+    radio: &mut Registers,
+    config: &mut config::Config,
+    assembler: &mut PacketAssembler,
+    switch: fallback::Switch,
+    telemetry: &Option<Telemetry>,
+) -> Result<()> {
+    let Some(fallback) = config.fallback else {
+        return Ok(());
     };
+    warn!("UHF FALLBACK {}", switch);
+    rx::leave(radio, &config.board)?;
+    config.swap_channel(radio, fallback.channel)?;
+    radio.RSSIREFERENCE().write(32)?; // Config::swap_channel writes the config file value
+    rx::enter(radio, &config.board, config.fifo)?;
+    assembler.set_max_len(config.channel[0].length.max_len());
+    assembler.set_trailer(config.channel[0].crc_trailer);
+    assembler.set_crc(config.channel[0].crc);
+    assembler.set_framing(config.channel[0].framing);
+    if let Some(socket) = telemetry {
+        tui::CommState::FALLBACK(switch).send(socket)?;
+    }
+    Ok(())
+}
 
-    // TODO: integrate recv
-    // TODO: maybe FIFOChunkTX::to_data -> Vec? It knows how big it should be
-    let header_size = 3; // size of FIFOChunkTX::DATA header
-
-    // I witnessed thresh read as 0 once, which crashed the driver. Having thresh (FIFOTHRESH)
-    // return 0 makes no sense, but lets guard against it anyway.
-    let Some(chunksize) = thresh.checked_sub(header_size) else {
-        radio.PWRMODE().write(PwrMode {
-            flags: PwrFlags::XOEN | PwrFlags::REFEN,
-            mode: PwrModes::POWEROFF,
-        })?;
-        bail!("FIFOTHRESH returned 0. Weird");
+/// Applies what it can from the config file to the running radio, a bad file is only logged
+fn reload(
+    radio: &mut Registers,
+    config: &mut config::Config,
+    path: &str,
+    applied: &mut String,
+) -> Result<()> {
+    let (new, contents) = match load_config(path) {
+        Ok(new) => new,
+        Err(e) => {
+            error!("UHF RELOAD {} failed: {:#}", path, e);
+            return Ok(());
+        }
     };
-    let mut packet: Vec<FIFOChunkTX> = buf
-        .chunks(chunksize)
-        .map(|x| FIFOChunkTX::DATA {
-            flags: FIFODataTXFlags::empty(),
-            data: x.to_vec(),
-        })
-        .collect();
-    if let Some(FIFOChunkTX::DATA { ref mut flags, .. }) = packet.first_mut() {
-        *flags |= FIFODataTXFlags::PKTSTART;
-    }
-    if let Some(FIFOChunkTX::DATA { ref mut flags, .. }) = packet.last_mut() {
-        *flags |= FIFODataTXFlags::PKTEND;
-    }
-    let pa_off = FIFOChunkTX::TXCTRL(TXCtrl::SETPA);
-
-    radio.FIFODATATX().write(pa_on)?;
-    radio.FIFODATATX().write(preamble)?;
-
-    println!("UHF SEND {} from {:?}: {:X?}", buf.len(), src, packet);
-
-    'outer: for chunk in packet {
-        radio.FIFODATATX().write(chunk)?;
-        radio.FIFOCMD().write(FIFOCmd {
-            mode: FIFOCmds::COMMIT,
-            auto_commit: false,
-        })?;
-        // FIXME interrupt?
-        loop {
-            let stat = radio.FIFOSTAT().read()?;
-            if stat.contains(FIFOStat::OVER) || stat.contains(FIFOStat::UNDER) {
-                println!("chunk: {:?}", stat);
-                // FIXME: I saw this happen once and then hang? We should probably abandon ship
-                // here. Possibly set the abort bit?
-                radio.FIFOCMD().write(FIFOCmd {
-                    mode: FIFOCmds::CLEAR_ERROR,
-                    auto_commit: false,
-                })?;
-                radio.FIFOCMD().write(FIFOCmd {
-                    mode: FIFOCmds::CLEAR_DATA,
-                    auto_commit: false,
-                })?;
-                break 'outer;
-            }
-            if stat.contains(FIFOStat::FREE_THR) {
-                break;
-            }
+
+    rx::leave(radio, &config.board)?;
+    let changes = config.reload(radio, new)?;
+    *applied = contents;
+    radio.RSSIREFERENCE().write(32)?; // Config::reload writes the config file value
+    rx::enter(radio, &config.board, config.fifo)?;
+
+    info!("UHF RELOAD applied {:?}", changes.applied);
+    if !changes.reset.is_empty() {
+        warn!("UHF RELOAD {:?} changed, restart to apply", changes.reset);
+    }
+    Ok(())
+}
+
+fn load_schedule(path: &str) -> Result<Schedule> {
+    let contents = read_to_string(path).with_context(|| format!("Reading {}", path))?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// Keeps the old windows if the file is bad, like reload()
+fn reload_schedule(gate: &mut Gate, path: &Option<String>) {
+    let Some(path) = path else {
+        return;
+    };
+    match load_schedule(path) {
+        Ok(schedule) => {
+            info!("UHF SCHEDULE {} windows", schedule.window.len());
+            gate.schedule = schedule;
         }
+        Err(e) => error!("UHF SCHEDULE {} failed: {:#}", path, e),
     }
+}
 
-    radio.FIFODATATX().write(postamble)?;
+/// Applies a change to what's inhibiting TX. Coming on it cuts off the frame on the air, drops
+/// the queue and disables the PA, going off enables the PA again. The gate turns frames away in
+/// between.
+fn set_inhibit(
+    radio: &mut Registers,
+    gate: &mut Gate,
+    inhibit: Inhibit,
+    downlink_queue: &mut Downlink,
+    config: &config::Config,
+    antsel: &impl Switch,
+    guard: &Guard,
+) -> Result<()> {
+    let was = gate.inhibit.active();
+    gate.inhibit = inhibit;
+    match (was, inhibit.active()) {
+        (false, true) => {
+            warn!("UHF TX INHIBIT on, {}", inhibit);
+            downlink_queue.inhibit(radio, config, antsel)?;
+            guard.disable_pa()?;
+        }
+        (true, false) => {
+            info!("UHF TX INHIBIT off");
+            guard.enable_pa()?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
 
-    radio.FIFODATATX().write(pa_off)?;
-    radio.FIFOCMD().write(FIFOCmd {
-        mode: FIFOCmds::COMMIT,
-        auto_commit: false,
-    })?;
+/// Drains a socket while the transmit gate is closed
+fn reject(socket: &UdpSocket, gate: &Gate) -> Result<()> {
+    let mut buf = [0; 2048];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((amt, src)) if gate.inhibit.active() => warn!(
+                target: "ax5043::packet", "UHF TX REJECTED {} from {:?}, inhibit {}",
+                amt, src, gate.inhibit
+            ),
+            Ok((amt, src)) => warn!(
+                target: "ax5043::packet", "UHF TX REJECTED {} from {:?}, gate {:?}, next window {:?}",
+                amt,
+                src,
+                gate.mode,
+                gate.schedule.next(SystemTime::now())
+            ),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e).context("Socket read failed"),
+        }
+    }
+}
+
+/// Resets and reconfigures a stuck radio, see ax5043::watchdog
+fn recover(
+    radio: &mut Registers,
+    config: &config::Config,
+    assembler: &mut PacketAssembler,
+    reason: Reason,
+    telemetry: &Option<Telemetry>,
+) -> Result<()> {
+    error!("UHF WATCHDOG {}, resetting the radio", reason);
+    if let Some(socket) = telemetry {
+        tui::CommState::WATCHDOG(reason).send(socket)?;
+    }
+    radio.reset()?;
+    for mismatch in radio.verify_reset()? {
+        warn!("UHF RESET {} not at its default", mismatch);
+    }
+    config.configure(radio)?;
+    assembler.clear();
+    rx::enter(radio, &config.board, config.fifo)?;
+    info!("UHF WATCHDOG radio back in RX");
+    Ok(())
+}
 
-    while radio.RADIOSTATE().read()? != RadioState::IDLE {} // TODO: Interrupt of some sort
-    radio.PWRAMP().write(registers::PwrAmp::empty())?; // FIXME why isn't pa_off doing this?
-    while radio.PWRAMP().read()?.contains(registers::PwrAmp::PWRAMP) {} // TODO: Interrupt of some sort
+/// Logs and sends any supply trouble, see ax5043::supply. Returns why the radio should be reset,
+/// if it should.
+fn check_supply(
+    radio: &mut Registers,
+    supply: &mut History,
+    reset: bool,
+    telemetry: &Option<Telemetry>,
+) -> Result<Option<Reason>> {
+    for event in supply.sample(radio, SystemTime::now())? {
+        warn!("UHF SUPPLY {}", event);
+        if let Some(socket) = telemetry {
+            tui::CommState::SUPPLY(event).send(socket)?;
+        }
+    }
+    Ok(supply.take_reset().filter(|_| reset).map(Reason::Supply))
+}
 
-    radio.PWRMODE().write(PwrMode {
-        flags: PwrFlags::XOEN | PwrFlags::REFEN,
-        mode: PwrModes::POWEROFF,
-    })?;
+fn tune(radio: &mut Registers, reg: Tunable, value: i64) -> Result<()> {
+    let old = reg.read(radio)?;
+    reg.write(radio, value)?;
+    info!("UHF WRITE {} {} -> {}", reg, old, value);
+    Ok(())
+}
+
+fn tune_agc(radio: &mut Registers, tuning: agc::Tuning) -> Result<()> {
+    for (set, old, new) in tuning.apply(radio)? {
+        info!("UHF AGC {:?} {} -> {}", set, old, new);
+    }
+    Ok(())
+}
+
+/// Rewrites only the packet engine for the new framing. Rejected (and left as it was) if the
+/// channel's modulation can't carry it. Lasts until the next reload.
+fn switch_framing(
+    radio: &mut Registers,
+    config: &mut config::Config,
+    assembler: &mut PacketAssembler,
+    downlink: &mut Downlink,
+    framing: config::Framing,
+) -> Result<()> {
+    match config.channel[0].with_framing(framing) {
+        Ok(channel) => {
+            info!(
+                "UHF FRAMING {:?} -> {:?}",
+                config.channel[0].framing, framing
+            );
+            channel.write_framing(
+                radio,
+                config.address.as_ref().map(config::AddressFilter::hardware),
+            )?;
+            assembler.clear();
+            assembler.set_crc(channel.crc);
+            assembler.set_framing(channel.framing);
+            config.channel[0] = channel;
+            downlink.prepare(config)?;
+        }
+        Err(e) => warn!("UHF FRAMING {:?} rejected: {}", framing, e),
+    }
+    Ok(())
+}
+
+/// The states the tui only gets once, sent at startup and again on each new TCP connection
+fn announce(radio: &mut Registers, config: &config::Config, socket: &Telemetry) -> Result<()> {
+    tui::CommState::HELLO(tui::PROTOCOL).send(socket)?;
+    tui::CommState::RADIO("UHF".to_string()).send(socket)?;
+    tui::CommState::BOARD(config.board).send(socket)?;
+    tui::CommState::REGISTERS(tui::StatusRegisters::new(radio)?).send(socket)?;
+    tui::CommState::CONFIG(tui::Config::new(radio, &config.board)?).send(socket)?;
+    Ok(())
+}
+
+/// The tui's console sends commands back on the telemetry socket, only those passing
+/// Command::remote() are accepted there
+fn tui_commands(socket: &std::net::UdpSocket, gate: &mut ControlGate) -> Result<Vec<Command>> {
+    let mut commands = Vec::new();
+    let mut buf = [0; 256];
+    loop {
+        match socket.recv(&mut buf) {
+            Ok(amt) => match gate
+                .open(&buf[..amt], SystemTime::now())
+                .map(|text| text.parse::<Command>())
+            {
+                Err(e) => warn!("UHF TUI command refused: {}", e),
+                Ok(Err(e)) => warn!("Invalid command: {}", e),
+                Ok(Ok(command)) if command.remote() => commands.push(command),
+                Ok(Ok(other)) => warn!("UHF {:?} not accepted over telemetry", other),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(commands),
+            // An earlier telemetry datagram found nobody listening
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => continue,
+            Err(e) => return Err(e).context("Telemetry socket read failed"),
+        }
+    }
+}
+
+/// The [filter] rules, if any, see ax5043::filter
+fn packet_filter(config: &config::Config) -> Option<Box<dyn PacketFilter>> {
+    config
+        .filter
+        .clone()
+        .map(|rules| Box::new(rules) as Box<dyn PacketFilter>)
+}
+
+/// Logs a finished pass and sends it on, see ax5043::pass
+fn end_pass(
+    report: pass::Report,
+    dir: &Option<String>,
+    telemetry: &Option<Telemetry>,
+) -> Result<()> {
+    info!("UHF PASS {}", report);
+    if let Some(dir) = dir {
+        match report.write(Path::new(dir)) {
+            Ok(path) => info!("UHF PASS report {}", path.display()),
+            Err(e) => warn!("UHF PASS report not written: {}", e),
+        }
+    }
+    if let Some(socket) = telemetry {
+        tui::CommState::PASS(report).send(socket)?;
+    }
     Ok(())
 }
 
+fn save_state(path: &Option<String>, state: &mut State, stats: &Stats) {
+    let Some(path) = path else {
+        return;
+    };
+    state.stats = *stats;
+    if let Err(e) = state.save(Path::new(path)) {
+        warn!("UHF STATE not saved to {}: {}", path, e);
+    }
+}
+
 #[derive(Parser, Debug)]
 /// Try it out: `socat STDIO UDP:localhost:10015`
 ///             `socat UDP-LISTEN:10025 STDOUT`
@@ -226,15 +841,118 @@ struct Args {
     downlink: u16,
     #[arg(short, long, default_value = "10025")]
     uplink: u16,
+    /// Operator commands, see ax5043::control
+    #[arg(short, long, default_value = "10017")]
+    control: u16,
+    /// Address the control socket listens on, 0.0.0.0 to take commands from the station LAN.
+    /// Set a [control] key first, see ax5043::auth.
+    #[arg(long, default_value = "127.0.0.1")]
+    control_listen: IpAddr,
     #[arg(short, long, default_value = "/dev/spidev0.0")]
     spi: String,
-    /// For example 10.18.17.6:10035
+    /// Radio IRQ line, chip:line
+    #[arg(long, default_value = "gpiochip0:30")]
+    irq: Pin,
+    /// Take --spi and --irq from the device tree node with this name or compatible string,
+    /// see ax5043::discover
+    #[arg(long)]
+    discover: Option<String>,
+    /// PA enable line, chip:line
+    #[arg(long, default_value = "gpiochip1:27")]
+    pa: Pin,
+    /// Antenna switch line, active while transmitting
+    #[arg(long)]
+    antsel: Option<Pin>,
+    /// TX inhibit line, chip:line. Nothing is keyed while it's asserted and a frame on the air
+    /// is cut off, see ax5043::schedule::Inhibit.
+    #[arg(long)]
+    inhibit: Option<Pin>,
+    /// --inhibit is asserted low, a switch to ground with a pull-up
+    #[arg(long)]
+    inhibit_low: bool,
+    /// For example 10.18.17.6:10035, or tcp://10.18.17.6:10035 to connect and resend the
+    /// config whenever the connection comes back, see ax5043::telemetry
     #[arg(short, long)]
     telemetry: Option<String>,
+    /// Send demodulator tracking samples for the tui's constellation view, one every N
+    /// telemetry ticks (25 ms). 0 disables.
+    #[arg(long, default_value = "1")]
+    constellation: u32,
+    /// Log one JSON object per line
+    #[arg(long)]
+    json: bool,
+    /// Print the register writes the config file makes and exit, no hardware needed
+    #[arg(long)]
+    dry_run: bool,
+    /// Write every frame to this pcapng file
+    #[arg(long)]
+    capture: Option<String>,
+    /// pcapng link type for --capture, 3 for AX.25
+    #[arg(long, default_value_t = capture::LINKTYPE_USER0)]
+    linktype: u16,
+    /// Append every rejected chunk and frame to this file, see ax5043::rejects
+    #[arg(long)]
+    rejects: Option<String>,
+    /// Size in MiB at which --rejects is rotated
+    #[arg(long, default_value = "10")]
+    rejects_size: u64,
+    /// Only transmit inside the pass windows listed in this file, see ax5043::schedule
+    #[arg(long)]
+    schedule: Option<String>,
+    /// Keep packet totals and the last applied config here across restarts, saved with the
+    /// statistics
+    #[arg(long)]
+    state: Option<String>,
+    /// Seconds without a packet that end a pass, 0 to not summarize passes, see ax5043::pass
+    #[arg(long, default_value = "120")]
+    pass_gap: u64,
+    /// Write each pass summary to a JSON file in this directory
+    #[arg(long)]
+    pass_reports: Option<String>,
+    /// Keep received frames in this directory while nothing is reading --uplink and send them
+    /// once it's back, see ax5043::spool
+    #[arg(long)]
+    spool: Option<String>,
+    /// Size in MiB past which the oldest spooled frames are dropped
+    #[arg(long, default_value = "64")]
+    spool_size: u64,
+    /// Seconds between logged packet statistics, 0 to only log them at shutdown
+    #[arg(long, default_value = "300")]
+    stats: u64,
+    /// Reset the radio after this many seconds without an IRQ or status change, off by default
+    /// as a quiet channel looks the same, set it to hours. Also catches REVISION read failures
+    /// and persistent FIFO errors, see ax5043::watchdog
+    #[arg(long, default_value = "0")]
+    watchdog: u64,
+    /// Seconds between POWSTAT checks for brownouts, 0 to not check, see ax5043::supply
+    #[arg(long, default_value = "1")]
+    supply: u64,
+    /// Reset and reconfigure the radio once the supply is back after a brownout
+    #[arg(long)]
+    brownout_reset: bool,
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let log = logging::init(args.json);
+
+    if let Some(ref name) = args.discover {
+        let found = discover::find(Path::new("/sys"), name)?
+            .with_context(|| format!("No spidev for {} in the device tree", name))?;
+        args.spi = found.spi.to_string_lossy().into_owned();
+        if let Some(irq) = found.irq {
+            args.irq = irq;
+        }
+        info!("UHF discovered {} irq {}", args.spi, args.irq);
+    }
+
+    if args.dry_run {
+        let (config, _) = load_config(CONFIG_PATH)?;
+        for write in ax5043::dry_run(|radio| config.configure(radio))? {
+            println!("{}", write);
+        }
+        return Ok(());
+    }
 
     let mut poll = Poll::new()?;
     let registry = poll.registry();
@@ -252,32 +970,59 @@ fn main() -> Result<()> {
 
     let src = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
     let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), args.uplink);
-    let mut uplink = UdpSocket::bind(src)?;
+    let uplink = UdpSocket::bind(src)?;
     uplink.connect(dest)?;
+    let spool = match args.spool {
+        Some(ref dir) => Some(Spool::open(dir, args.spool_size << 20)?),
+        None => None,
+    };
+    let mut uplink = Forwarder::new(uplink, spool);
 
-    let mut telemetry: Option<std::net::UdpSocket> = None;
-    if let Some(addr) = args.telemetry {
-        let dest: SocketAddr = addr.parse().unwrap();
-        let socket = std::net::UdpSocket::bind(src)?;
-        socket.connect(dest)?;
-        telemetry = Some(socket);
+    let telemetry = match args.telemetry {
+        Some(ref dest) => Some(Telemetry::open(dest).context("Invalid --telemetry")?),
+        None => None,
+    };
+    // Console commands from the tui, UDP only
+    if let Some(socket) = telemetry.as_ref().and_then(Telemetry::udp) {
+        registry.register(&mut SourceFd(&socket.as_raw_fd()), TUI, Interest::READABLE)?;
     }
+    const TUI: Token = Token(8);
+
+    let addr = SocketAddr::new(args.control_listen, args.control);
+    let mut control = mio::net::UdpSocket::bind(addr)?;
+    const CONTROL: Token = Token(2);
+    registry.register(&mut control, CONTROL, Interest::READABLE)?;
+
+    const SIGNAL: Token = Token(3);
+    let mut signals = Signals::new(Signal::Interrupt | Signal::Terminate | Signal::User1)?;
+    registry.register(&mut signals, SIGNAL, Interest::READABLE)?;
 
-    const SIGINT: Token = Token(3);
-    let mut signals = Signals::new(Signal::Interrupt.into())?;
-    registry.register(&mut signals, SIGINT, Interest::READABLE)?;
+    // Disables the PA and resets the radio on every exit path, see guard.rs
+    let guard = Arc::new(Guard::new(&args.spi)?.with_pa(args.pa.output()?));
+    guard.install_panic_hook();
 
-    let pa_enable = Request::builder()
-        .on_chip("/dev/gpiochip1")
-        .with_line(27)
-        .as_output(Value::Inactive)
-        .request()?;
+    let antsel = args.antsel.as_ref().map(Pin::output).transpose()?;
 
-    let uhf_irq = Request::builder()
-        .on_chip("/dev/gpiochip0")
-        .with_line(30)
-        .with_edge_detection(EdgeDetection::RisingEdge)
-        .request()?;
+    let inhibit_line = args
+        .inhibit
+        .as_ref()
+        .map(|pin| pin.input(args.inhibit_low))
+        .transpose()?;
+    const INHIBIT: Token = Token(12);
+    if let Some(ref line) = inhibit_line {
+        registry.register(
+            &mut SourceFd(&line.as_raw_fd()),
+            INHIBIT,
+            Interest::READABLE,
+        )?;
+    }
+    let inhibited = inhibit_line
+        .as_ref()
+        .map(Input::asserted)
+        .transpose()?
+        .unwrap_or(false);
+
+    let uhf_irq = args.irq.irq()?;
 
     const IRQ: Token = Token(4);
     registry.register(&mut SourceFd(&uhf_irq.as_raw_fd()), IRQ, Interest::READABLE)?;
@@ -307,11 +1052,47 @@ fn main() -> Result<()> {
         Interest::READABLE,
     )?;
 
+    let mut stats_tfd = TimerFd::new()?;
+    if args.stats > 0 {
+        let interval = Duration::from_secs(args.stats);
+        stats_tfd.set_state(
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const STATS: Token = Token(6);
+    registry.register(
+        &mut SourceFd(&stats_tfd.as_raw_fd()),
+        STATS,
+        Interest::READABLE,
+    )?;
+
+    // Ticks with the watchdog off too, it checks the TX deadline
+    let mut watchdog_tfd = TimerFd::new()?;
+    watchdog_tfd.set_state(
+        TimerState::Periodic {
+            current: Duration::new(1, 0),
+            interval: Duration::new(1, 0),
+        },
+        SetTimeFlags::Default,
+    );
+    const WATCHDOG: Token = Token(7);
+    registry.register(
+        &mut SourceFd(&watchdog_tfd.as_raw_fd()),
+        WATCHDOG,
+        Interest::READABLE,
+    )?;
+
     let spi0 = ax5043::open(args.spi)?;
     let mut status = ax5043::Status::empty();
+    let activity = Cell::new(Instant::now()); // Last status change, for the watchdog
     let mut callback = |_: &_, _addr, s, _data: &_| {
         //println!("{:03X}: {:02X?}", addr, data);
         if s != status {
+            activity.set(Instant::now());
             if let Some(ref socket) = telemetry {
                 tui::CommState::STATUS(s).send(socket).unwrap();
             }
@@ -321,53 +1102,50 @@ fn main() -> Result<()> {
 
     let mut radio = ax5043::Registers::new(spi0, &mut callback);
     radio.reset()?;
+    for mismatch in radio.verify_reset()? {
+        warn!("UHF RESET {} not at its default", mismatch);
+    }
 
-    let rev = radio.REVISION().read()?;
-    ensure!(
-        rev == 0x51,
-        "Unexpected revision {}, expected {}",
-        rev,
-        0x51
-    );
-
-    let file_path = "c3-uhf-96000.toml";
-    let contents = read_to_string(file_path)?;
-    let config: config::Config = toml::from_str(&contents)?;
-    config.write(&mut radio)?;
-    let config_tx = config.tx.expect("Section [tx] required");
-    let channel_edl = config
-        .channel
-        .first()
-        .expect("Missing first [channel] (edl)");
-    let channel_beacon = config
-        .channel
-        .get(1)
-        .expect("Missing second [channel] (beacon)");
-
-    radio.FIFOTHRESH().write(128)?; // Half the FIFO size
-
-    radio.RSSIREFERENCE().write(32)?;
+    let mut state = match args.state {
+        Some(ref path) => State::load(Path::new(path))?.unwrap_or_default(),
+        None => State::default(),
+    };
+    let (mut config, contents) = load_config(CONFIG_PATH)?;
+    if !args.control_listen.is_loopback() && config.control.is_none() {
+        warn!(
+            "UHF CONTROL open on {} without a [control] key",
+            args.control_listen
+        );
+    }
+    let mut control_gate = ControlGate::new(config.control.clone());
+    if !state.config.is_empty() && state.config != contents {
+        match toml::from_str::<config::Config>(&state.config) {
+            Ok(last) => info!(
+                "UHF config changed since last run: {:?}",
+                last.diff(&config)
+            ),
+            Err(e) => warn!("UHF last run's config no longer parses: {}", e),
+        }
+    }
+    state.config = contents;
+    radio.probe()?.found()?;
+    radio.check_spi(true)?;
+    config.configure(&mut radio)?;
 
-    pa_enable.set_value(27, Value::Active)?;
+    if inhibited {
+        warn!("UHF TX INHIBIT on at startup, PA left disabled");
+    } else {
+        guard.enable_pa()?;
+    }
 
     if let Some(ref socket) = telemetry {
-        tui::CommState::BOARD(config.board).send(socket)?;
-        tui::CommState::REGISTERS(tui::StatusRegisters::new(&mut radio)?).send(socket)?;
-        tui::CommState::CONFIG(tui::Config {
-            txparams: tui::TXParameters::new(&mut radio, &config.board)?,
-            rxparams: tui::RXParams::new(&mut radio, &config.board)?,
-            set0: tui::RXParameterSet::set0(&mut radio)?,
-            set1: tui::RXParameterSet::set1(&mut radio)?,
-            set2: tui::RXParameterSet::set2(&mut radio)?,
-            set3: tui::RXParameterSet::set3(&mut radio)?,
-            synthesizer: tui::Synthesizer::new(&mut radio, &config.board)?,
-            packet_controller: tui::PacketController::new(&mut radio)?,
-            packet_format: tui::PacketFormat::new(&mut radio)?,
-            channel: tui::ChannelParameters::new(&mut radio)?,
-        })
-        .send(socket)?;
+        announce(&mut radio, &config, socket)?;
     }
 
+    if let Some(lna) = config.board.lna {
+        lna.set(&mut radio, true)?;
+        std::thread::sleep(lna.settle());
+    }
     radio.PWRMODE().write(PwrMode {
         flags: PwrFlags::XOEN | PwrFlags::REFEN,
         mode: PwrModes::RX,
@@ -385,12 +1163,149 @@ fn main() -> Result<()> {
         auto_commit: false,
     })?;
 
-    radio
-        .IRQMASK()
-        .write(ax5043::registers::IRQ::FIFONOTEMPTY)?;
+    config.fifo.arm(&mut radio)?;
 
-    let mut packet = Vec::new();
+    // Picks up the tail of packets that never reach the FIFO threshold
+    let mut flush_tfd = TimerFd::new()?;
+    if let Some(interval) = config.fifo.flush() {
+        flush_tfd.set_state(
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const FLUSH: Token = Token(9);
+    registry.register(
+        &mut SourceFd(&flush_tfd.as_raw_fd()),
+        FLUSH,
+        Interest::READABLE,
+    )?;
 
+    // Keeps the carrier centered as the crystal warms and cools, see ax5043::thermal
+    let mut thermal_tfd = TimerFd::new()?;
+    arm_thermal(&mut thermal_tfd, &config);
+    const THERMAL: Token = Token(10);
+    registry.register(
+        &mut SourceFd(&thermal_tfd.as_raw_fd()),
+        THERMAL,
+        Interest::READABLE,
+    )?;
+    let mut trim = thermal::Trim::default();
+
+    // LPOSC calibration and capture timestamps against a GPS pulse per second, see ax5043::pps
+    let pps_line = config.pps.as_ref().map(|pps| pps.pin.pps()).transpose()?;
+    const PPS: Token = Token(11);
+    if let Some(ref line) = pps_line {
+        registry.register(&mut SourceFd(&line.as_raw_fd()), PPS, Interest::READABLE)?;
+    }
+    let mut calibrator = pps::Calibrator::default();
+    let mut clock = pps::Clock::default();
+
+    let mut capture = match args.capture {
+        Some(ref path) => Some(capture::create(path, args.linktype)?),
+        None => None,
+    };
+    let mut rejects = match args.rejects {
+        Some(ref path) => Some(RejectLog::open(path, args.rejects_size << 20)?),
+        None => None,
+    };
+    let mut filter = packet_filter(&config);
+    let mut pass = (args.pass_gap > 0).then(|| Pass::new(Duration::from_secs(args.pass_gap)));
+    let mut pass_tfd = TimerFd::new()?;
+    if pass.is_some() {
+        pass_tfd.set_state(
+            TimerState::Periodic {
+                current: Duration::new(1, 0),
+                interval: Duration::new(1, 0),
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const PASS: Token = Token(13);
+    registry.register(
+        &mut SourceFd(&pass_tfd.as_raw_fd()),
+        PASS,
+        Interest::READABLE,
+    )?;
+    let mut spool_tfd = TimerFd::new()?;
+    if uplink.spool().is_some() {
+        spool_tfd.set_state(
+            TimerState::Periodic {
+                current: Duration::new(1, 0),
+                interval: Duration::new(1, 0),
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const SPOOL: Token = Token(14);
+    registry.register(
+        &mut SourceFd(&spool_tfd.as_raw_fd()),
+        SPOOL,
+        Interest::READABLE,
+    )?;
+    let mut monitor = config.fallback.map(|f| Monitor::new(f, Instant::now()));
+    let mut fallback_tfd = TimerFd::new()?;
+    arm_fallback(&mut fallback_tfd, &config);
+    const FALLBACK: Token = Token(15);
+    registry.register(
+        &mut SourceFd(&fallback_tfd.as_raw_fd()),
+        FALLBACK,
+        Interest::READABLE,
+    )?;
+    let mut supply = History::default();
+    let mut supply_tfd = TimerFd::new()?;
+    if args.supply > 0 {
+        let interval = Duration::from_secs(args.supply);
+        supply_tfd.set_state(
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            },
+            SetTimeFlags::Default,
+        );
+    }
+    const SUPPLY: Token = Token(16);
+    registry.register(
+        &mut SourceFd(&supply_tfd.as_raw_fd()),
+        SUPPLY,
+        Interest::READABLE,
+    )?;
+    let mut assembler = PacketAssembler::resume(state.stats)
+        .rssi(config.board.rssi)
+        .accept(config.accept)
+        .addresses(config.address.clone())
+        .max_len(config.channel[0].length.max_len())
+        .trailer(config.channel[0].crc_trailer)
+        .crc(config.channel[0].crc)
+        .framing(config.channel[0].framing);
+    let mut downlink_queue = Downlink {
+        sensor: config
+            .power_sensor
+            .clone()
+            .map(|sensor| Box::new(sensor) as Box<dyn PowerSensor>),
+        ..Downlink::default()
+    };
+    downlink_queue.prepare(&config)?;
+    if rejects.is_some() || telemetry.is_some() {
+        assembler = assembler.keep_rejected();
+    }
+    if telemetry.is_some() {
+        assembler = assembler.report_aborts();
+    }
+    let mut gate = match args.schedule {
+        Some(ref path) => Gate::new(load_schedule(path)?),
+        None => Gate::open(),
+    };
+    gate.inhibit.line = inhibited;
+    let mut watchdog = Watchdog::new(Duration::from_secs(args.watchdog), Instant::now());
+
+    let mut tracker = tui::Tracker::new(args.constellation);
+    let mut snapshot = tui::Snapshot::default();
+    let mut beacon_on = true;
+    let mut test_seq = 0;
+    let mut commands = Vec::new();
     'outer: loop {
         poll.poll(&mut events, None)?;
         for event in events.iter() {
@@ -398,105 +1313,428 @@ fn main() -> Result<()> {
                 TELEMETRY => {
                     tfd.read();
                     if let Some(ref socket) = telemetry {
-                        tui::CommState::STATE(tui::RXState::new(&mut radio, channel_edl)?)
-                            .send(socket)?;
-                        tui::CommState::REGISTERS(tui::StatusRegisters::new(&mut radio)?)
-                            .send(socket)?;
+                        socket.flush()?;
+                        if socket.take_resync() {
+                            info!("UHF TELEMETRY connected, resending config");
+                            announce(&mut radio, &config, socket)?;
+                        }
+                        tui::CommState::HELLO(tui::PROTOCOL).send(socket)?;
+                        tui::CommState::RADIO("UHF".to_string()).send(socket)?;
+                        tui::CommState::STATS(*assembler.stats()).send(socket)?;
+                        // A pending IRQ gets the bus first, see tui::Snapshot
+                        if !uhf_irq.asserted()? {
+                            snapshot
+                                .step(
+                                    &mut radio,
+                                    &config.board,
+                                    &config.channel[EDL_CHANNEL],
+                                    Some(&downlink_queue.stats),
+                                )?
+                                .send(socket)?;
+                            if let Some(samples) = tracker.tick(&mut radio)? {
+                                tui::CommState::TRACKING(samples).send(socket)?;
+                            }
+                        }
+                    }
+                }
+                STATS => {
+                    stats_tfd.read();
+                    info!("UHF STATS {} {}", assembler.stats(), supply.counts());
+                    save_state(&args.state, &mut state, assembler.stats());
+                }
+                PASS => {
+                    pass_tfd.read();
+                    if let Some(report) = pass.as_mut().and_then(|p| p.check(SystemTime::now())) {
+                        end_pass(report, &args.pass_reports, &telemetry)?;
+                    }
+                }
+                SPOOL => {
+                    spool_tfd.read();
+                    uplink.service().context("Uplink replay failed")?;
+                }
+                FALLBACK => {
+                    fallback_tfd.read();
+                    // Between frames only, the next tick will do
+                    let idle = downlink_queue.sending.is_none();
+                    if let Some(switch) = monitor
+                        .as_mut()
+                        .filter(|_| idle)
+                        .and_then(|m| m.check(Instant::now()))
+                    {
+                        switch_rate(&mut radio, &mut config, &mut assembler, switch, &telemetry)?;
+                        downlink_queue.prepare(&config)?;
+                    }
+                }
+                WATCHDOG => {
+                    watchdog_tfd.read();
+                    let now = Instant::now();
+                    downlink_queue.overdue(&mut radio, &config, &antsel, &mut capture, now)?;
+                    if args.watchdog == 0 {
+                        continue;
+                    }
+                    watchdog.feed(activity.get());
+                    if let Some(reason) = watchdog.check(&mut radio, assembler.stats(), now) {
+                        recover(&mut radio, &config, &mut assembler, reason, &telemetry)?;
+                        trim.clear();
+                        calibrator.restart();
+                        watchdog.reset(assembler.stats(), Instant::now());
+                        downlink_queue.abort(&antsel)?;
+                        downlink_queue.next(&mut radio, &config, &antsel, &mut capture)?;
+                    }
+                }
+                // Between frames only, reading POWSTICKYSTAT would open the BROWN_GATE
+                SUPPLY if downlink_queue.sending.is_some() => {
+                    supply_tfd.read();
+                }
+                SUPPLY => {
+                    supply_tfd.read();
+                    if let Some(sticky) = downlink_queue.sticky.take() {
+                        supply.hold(sticky);
+                    }
+                    let reset = args.brownout_reset;
+                    if let Some(reason) = check_supply(&mut radio, &mut supply, reset, &telemetry)?
+                    {
+                        recover(&mut radio, &config, &mut assembler, reason, &telemetry)?;
+                        trim.clear();
+                        calibrator.restart();
+                        watchdog.reset(assembler.stats(), Instant::now());
+                        downlink_queue.next(&mut radio, &config, &antsel, &mut capture)?;
                     }
                 }
+                BEACON if !beacon_on => {
+                    let frames = receive(&beacon).context("Ping socket read failed")?;
+                    info!("UHF BEACON off, dropped {}", frames.len());
+                }
+                BEACON if !gate.allows(SystemTime::now()) => reject(&beacon, &gate)?,
                 BEACON => {
-                    radio.IRQMASK().write(ax5043::registers::IRQ::empty())?;
-                    // PM p. 12: The FIFO should be emptied before the PWRMODE is set to POWERDOWN
-                    radio.FIFOCMD().write(FIFOCmd {
-                        mode: FIFOCmds::CLEAR_ERROR,
-                        auto_commit: false,
-                    })?;
-                    radio.FIFOCMD().write(FIFOCmd {
-                        mode: FIFOCmds::CLEAR_DATA,
-                        auto_commit: false,
-                    })?;
-                    // See errata - PWRMODE must transition through off for FIFO to work
-                    radio.PWRMODE().write(PwrMode {
-                        flags: PwrFlags::XOEN | PwrFlags::REFEN,
-                        mode: PwrModes::POWEROFF,
-                    })?;
-
-                    channel_beacon.write(&mut radio, &config.board)?;
-                    config_tx.write(&mut radio, &config.board, channel_beacon)?;
-
-                    let mut buf = [0; 2048];
-                    loop {
-                        match beacon.recv_from(&mut buf) {
-                            Ok((amt, src)) => transmit(&mut radio, &buf[..amt], src)?,
-                            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
-                            Err(e) => return Err(e).context("Ping socket read failed"),
-                        }
+                    for (frame, src) in receive(&beacon).context("Ping socket read failed")? {
+                        downlink_queue.push(frame, Some(src), BEACON_CHANNEL);
                     }
-
-                    channel_edl.write(&mut radio, &config.board)?;
-
-                    radio.PWRMODE().write(PwrMode {
-                        flags: PwrFlags::XOEN | PwrFlags::REFEN,
-                        mode: PwrModes::RX,
-                    })?;
-                    _ = radio.PLLRANGINGA().read()?; // sticky lock bit ~ IRQPLLUNLIOCK, gate
-                    _ = radio.POWSTICKYSTAT().read()?; // clear sticky power flags for PWR_GOOD
-                    radio
-                        .IRQMASK()
-                        .write(ax5043::registers::IRQ::FIFONOTEMPTY)?;
+                    downlink_queue.next(&mut radio, &config, &antsel, &mut capture)?;
                 }
+                DOWNLINK if !gate.allows(SystemTime::now()) => reject(&downlink, &gate)?,
                 DOWNLINK => {
-                    radio.IRQMASK().write(ax5043::registers::IRQ::empty())?;
-                    // PM p. 12: The FIFO should be emptied before the PWRMODE is set to POWERDOWN
-                    radio.FIFOCMD().write(FIFOCmd {
-                        mode: FIFOCmds::CLEAR_ERROR,
-                        auto_commit: false,
-                    })?;
-                    radio.FIFOCMD().write(FIFOCmd {
-                        mode: FIFOCmds::CLEAR_DATA,
-                        auto_commit: false,
-                    })?;
-                    // See errata - PWRMODE must transition through off for FIFO to work
-                    radio.PWRMODE().write(PwrMode {
-                        flags: PwrFlags::XOEN | PwrFlags::REFEN,
-                        mode: PwrModes::POWEROFF,
+                    for (frame, src) in receive(&downlink).context("Downlink socket read failed")? {
+                        downlink_queue.push(frame, Some(src), edl_channel(&monitor));
+                    }
+                    downlink_queue.next(&mut radio, &config, &antsel, &mut capture)?;
+                }
+                IRQ => {
+                    watchdog.feed(Instant::now());
+                    uhf_irq.drain(|| -> Result<()> {
+                        if downlink_queue.sending.is_none() {
+                            return read_packet(
+                                &mut radio,
+                                &mut assembler,
+                                &mut uplink,
+                                &mut capture,
+                                &mut rejects,
+                                &mut pass,
+                                &mut monitor,
+                                &mut filter,
+                                &telemetry,
+                                &config,
+                            );
+                        }
+                        if downlink_queue.service(&mut radio)? {
+                            if let Some(ref socket) = telemetry {
+                                tui::CommState::TXSTATE(tui::TXState::new(
+                                    &mut radio,
+                                    &downlink_queue.stats,
+                                )?)
+                                .send(socket)?;
+                            }
+                            downlink_queue.next(&mut radio, &config, &antsel, &mut capture)?;
+                        }
+                        Ok(())
                     })?;
-
-                    channel_edl.write(&mut radio, &config.board)?;
-                    config_tx.write(&mut radio, &config.board, channel_edl)?;
-
-                    let mut buf = [0; 2048];
+                }
+                FLUSH => {
+                    flush_tfd.read();
+                    if downlink_queue.sending.is_none() {
+                        read_packet(
+                            &mut radio,
+                            &mut assembler,
+                            &mut uplink,
+                            &mut capture,
+                            &mut rejects,
+                            &mut pass,
+                            &mut monitor,
+                            &mut filter,
+                            &telemetry,
+                            &config,
+                        )?;
+                    }
+                }
+                THERMAL => {
+                    thermal_tfd.read();
+                    if let (None, Some(ref compensation)) =
+                        (&downlink_queue.sending, &config.thermal)
+                    {
+                        let before = trim.applied();
+                        if let Some(ppm) =
+                            trim.run(compensation, &mut radio, &config.synth, &config.board)?
+                        {
+                            info!("UHF THERMAL trim {:+.2} -> {:+.2} ppm", before, ppm);
+                        }
+                    }
+                }
+                PPS => {
+                    if let (Some(line), Some(discipline)) = (&pps_line, &config.pps) {
+                        let edges = line.edges()?;
+                        if let Some(&at) = edges.last() {
+                            clock.edge(at);
+                            if let Some(ref mut capture) = capture {
+                                capture.set_clock(clock);
+                            }
+                        }
+                        let measured =
+                            calibrator.service(&mut radio, discipline, &config.board, &edges)?;
+                        match measured {
+                            Some(m) if m.plausible() => {
+                                info!(
+                                    "UHF PPS crystal {:+.2} ppm, LPOSC {:.3} Hz, LPOSCREF {}",
+                                    m.ppm, m.lposc, m.lposcref
+                                );
+                                pps::apply(&mut radio, &m)?;
+                            }
+                            Some(m) => warn!("UHF PPS crystal {:+.0} ppm is a miscount", m.ppm),
+                            None => (),
+                        }
+                    }
+                }
+                INHIBIT => {
+                    if let Some(ref line) = inhibit_line {
+                        let inhibit = Inhibit {
+                            line: line.changed()?,
+                            ..gate.inhibit
+                        };
+                        set_inhibit(
+                            &mut radio,
+                            &mut gate,
+                            inhibit,
+                            &mut downlink_queue,
+                            &config,
+                            &antsel,
+                            &guard,
+                        )?;
+                    }
+                }
+                CONTROL => {
+                    let mut buf = [0; 256];
                     loop {
-                        match downlink.recv_from(&mut buf) {
-                            Ok((amt, src)) => transmit(&mut radio, &buf[..amt], src)?,
+                        match control.recv_from(&mut buf) {
+                            Ok((amt, src)) => {
+                                let text = match control_gate.open(&buf[..amt], SystemTime::now()) {
+                                    Ok(text) => text,
+                                    Err(e) => {
+                                        warn!("UHF CONTROL refused from {}: {}", src, e);
+                                        continue;
+                                    }
+                                };
+                                match text.parse() {
+                                    // Can't wait for the queue to drain like the others
+                                    Ok(Command::Inhibit(on)) => {
+                                        let inhibit = Inhibit {
+                                            operator: on,
+                                            ..gate.inhibit
+                                        };
+                                        set_inhibit(
+                                            &mut radio,
+                                            &mut gate,
+                                            inhibit,
+                                            &mut downlink_queue,
+                                            &config,
+                                            &antsel,
+                                            &guard,
+                                        )?;
+                                    }
+                                    Ok(command) => commands.push(command),
+                                    Err(e) => warn!("Invalid command: {}", e),
+                                }
+                            }
                             Err(e) if e.kind() == ErrorKind::WouldBlock => break,
-                            Err(e) => return Err(e).context("Downlink socket read failed"),
+                            Err(e) => return Err(e).context("Control socket read failed"),
                         }
                     }
-
-                    radio.PWRMODE().write(PwrMode {
-                        flags: PwrFlags::XOEN | PwrFlags::REFEN,
-                        mode: PwrModes::RX,
-                    })?;
-                    _ = radio.PLLRANGINGA().read()?; // sticky lock bit ~ IRQPLLUNLIOCK, gate
-                    _ = radio.POWSTICKYSTAT().read()?; // clear sticky power flags for PWR_GOOD
-                    radio
-                        .IRQMASK()
-                        .write(ax5043::registers::IRQ::FIFONOTEMPTY)?;
                 }
-                IRQ => {
-                    while uhf_irq.has_edge_event()? {
-                        uhf_irq.read_edge_event()?;
-                        read_packet(&mut radio, &mut packet, &mut uplink)?;
+                TUI => {
+                    if let Some(socket) = telemetry.as_ref().and_then(Telemetry::udp) {
+                        commands.extend(tui_commands(socket, &mut control_gate)?);
+                    }
+                }
+                SIGNAL => {
+                    while let Some(signal) = signals.receive()? {
+                        match signal {
+                            // SIGHUP isn't supported by mio-signals, see ExecReload in the unit
+                            Signal::User1 => {
+                                reload(&mut radio, &mut config, CONFIG_PATH, &mut state.config)?;
+                                assembler.set_accept(config.accept);
+                                assembler.set_addresses(config.address.clone());
+                                filter = packet_filter(&config);
+                                control_gate.set_auth(config.control.clone());
+                                assembler.set_max_len(config.channel[0].length.max_len());
+                                assembler.set_trailer(config.channel[0].crc_trailer);
+                                assembler.set_crc(config.channel[0].crc);
+                                assembler.set_framing(config.channel[0].framing);
+                                monitor = config.fallback.map(|f| Monitor::new(f, Instant::now()));
+                                arm_fallback(&mut fallback_tfd, &config);
+                                downlink_queue.prepare(&config)?;
+                                arm_thermal(&mut thermal_tfd, &config);
+                                reload_schedule(&mut gate, &args.schedule);
+                            }
+                            _ => break 'outer,
+                        }
                     }
                 }
-                SIGINT => break 'outer,
                 _ => unreachable!(),
             }
+
+            // Retuning or reloading under a transmission would garble it, hold off until the
+            // queue has drained
+            if downlink_queue.sending.is_some() {
+                continue;
+            }
+            for command in commands.drain(..) {
+                match command {
+                    Command::Frequency(freq) => {
+                        retune(
+                            &mut radio,
+                            &mut config.synth,
+                            &config.board,
+                            freq,
+                            config.fifo,
+                        )?;
+                        trim.clear();
+                    }
+                    Command::Reload => {
+                        reload(&mut radio, &mut config, CONFIG_PATH, &mut state.config)?;
+                        assembler.set_accept(config.accept);
+                        assembler.set_addresses(config.address.clone());
+                        filter = packet_filter(&config);
+                        control_gate.set_auth(config.control.clone());
+                        assembler.set_max_len(config.channel[0].length.max_len());
+                        assembler.set_trailer(config.channel[0].crc_trailer);
+                        assembler.set_crc(config.channel[0].crc);
+                        assembler.set_framing(config.channel[0].framing);
+                        monitor = config.fallback.map(|f| Monitor::new(f, Instant::now()));
+                        arm_fallback(&mut fallback_tfd, &config);
+                        downlink_queue.prepare(&config)?;
+                        arm_thermal(&mut thermal_tfd, &config);
+                        reload_schedule(&mut gate, &args.schedule);
+                    }
+                    // Gate::open() has an empty schedule, following it would close TX for good
+                    Command::Transmit(Mode::Scheduled) if args.schedule.is_none() => {
+                        warn!("UHF TX gate schedule rejected, started without --schedule")
+                    }
+                    Command::Transmit(mode) => {
+                        info!("UHF TX gate {:?} -> {:?}", gate.mode, mode);
+                        gate.mode = mode;
+                    }
+                    // Applied as it arrives, see CONTROL
+                    Command::Inhibit(_) => (),
+                    Command::Log(filter) => {
+                        if let Err(e) = log.set_filter(&filter) {
+                            warn!("Invalid log filter {}: {}", filter, e);
+                        }
+                    }
+                    Command::Write(reg, value) => tune(&mut radio, reg, value)?,
+                    Command::Agc(tuning) => tune_agc(&mut radio, tuning)?,
+                    Command::Framing(framing) => switch_framing(
+                        &mut radio,
+                        &mut config,
+                        &mut assembler,
+                        &mut downlink_queue,
+                        framing,
+                    )?,
+                    Command::Test(_) | Command::Pattern(..) if !gate.allows(SystemTime::now()) => {
+                        warn!(
+                            "UHF TEST rejected, gate {:?}, next window {:?}",
+                            gate.mode,
+                            gate.schedule.next(SystemTime::now())
+                        )
+                    }
+                    Command::Pattern(pattern, time) => {
+                        let channel = edl_channel(&monitor);
+                        let datarate = config.channel[channel].datarate;
+                        downlink_queue.push_pattern(pattern, time, channel, datarate);
+                        downlink_queue.next(&mut radio, &config, &antsel, &mut capture)?;
+                    }
+                    Command::Test(len) => {
+                        downlink_queue.push(
+                            tx::test_frame(test_seq, len),
+                            None,
+                            edl_channel(&monitor),
+                        );
+                        test_seq += 1;
+                        downlink_queue.next(&mut radio, &config, &antsel, &mut capture)?;
+                    }
+                    Command::Beacon(on) => {
+                        info!("UHF BEACON {} -> {}", beacon_on, on);
+                        beacon_on = on;
+                    }
+                    Command::Squelch(floor) => {
+                        info!("UHF SQUELCH {:?} -> {:?} dBm", assembler.squelch(), floor);
+                        assembler.set_squelch(floor);
+                    }
+                }
+            }
         }
     }
 
-    pa_enable.set_value(27, Value::Inactive)?;
-    radio.reset()?;
+    if let Some(report) = pass.as_mut().and_then(Pass::finish) {
+        end_pass(report, &args.pass_reports, &telemetry)?;
+    }
+    info!("UHF STATS {} {}", assembler.stats(), supply.counts());
+    save_state(&args.state, &mut state, assembler.stats());
+    if let Some(ref socket) = telemetry {
+        tui::CommState::STATS(*assembler.stats()).send(socket)?;
+    }
+    guard.shutdown();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_framing_reworks_the_beacon_turnaround() {
+        let mut config: config::Config = toml::from_str(include_str!("c3-uhf-96000.toml")).unwrap();
+        let mut downlink = Downlink::default();
+        downlink.prepare(&config).unwrap();
+        let mut assembler = PacketAssembler::new();
+        let framing = |delta: &Delta| {
+            let writes = ax5043::dry_run(|radio| delta.apply(radio)).unwrap();
+            writes.to("FRAMING").last().map(|w| w.data.clone())
+        };
+        // Both channels are HDLC, nothing to switch
+        assert_eq!(framing(&downlink.turnaround[BEACON_CHANNEL].to_tx), None);
+
+        ax5043::dry_run(|radio| {
+            switch_framing(
+                radio,
+                &mut config,
+                &mut assembler,
+                &mut downlink,
+                config::Framing::Raw,
+            )
+            .unwrap();
+            Ok(())
+        })
+        .unwrap();
+
+        let rx = ax5043::dry_run(|radio| config.configure(radio)).unwrap();
+        let beacon = ax5043::dry_run(|radio| {
+            config.configure(radio)?;
+            config.channel[BEACON_CHANNEL].write(radio, &config.board)?;
+            Ok(())
+        })
+        .unwrap();
+        let last = |writes: &ax5043::Writes| writes.to("FRAMING").last().map(|w| w.data.clone());
+        let turnaround = &downlink.turnaround[BEACON_CHANNEL];
+        assert_ne!(last(&rx), last(&beacon));
+        assert_eq!(framing(&turnaround.to_tx), last(&beacon));
+        assert_eq!(framing(&turnaround.to_rx), last(&rx));
+    }
+}
@@ -0,0 +1,273 @@
+// Minimal pcapng writer so passes can be looked at in Wireshark afterwards.
+//
+// One Section Header Block and one Interface Description Block up front, then one Enhanced
+// Packet Block per frame. RSSI, RF frequency offset and the antenna don't have standard pcapng
+// options so they go in the per-packet comment, direction goes in epb_flags.
+//
+// See https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html
+use crate::{config::Board, rx::AntennaRssi, Registers, RX};
+use std::{
+    fs::File,
+    io::{BufWriter, Result, Write},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+/// Frames are AX.25 without the HDLC flags/FCS
+pub const LINKTYPE_AX25: u16 = 3;
+/// Frames are whatever the bins pass along (the default)
+pub const LINKTYPE_USER0: u16 = 147;
+
+const SHB: u32 = 0x0A0D_0D0A;
+const IDB: u32 = 0x0000_0001;
+const EPB: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const EPB_FLAGS: u16 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Radio state at the time a frame was seen, recorded in the packet comment
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Meta {
+    /// dBm, calibrated per board, see config::RssiCalibration
+    pub rssi: Option<f64>,
+    /// TRKRFFREQ converted to Hz
+    pub rf_offset: Option<i64>,
+    /// Per packet with antenna diversity on, see rx::AntennaRssi
+    pub antenna: Option<AntennaRssi>,
+    /// Passed on though it failed the CRC, crc_trailer Flag or [accept] crc_failed
+    pub crc_failed: bool,
+}
+
+impl Meta {
+    /// Samples RSSI and TRKRFFREQ, best done right after draining a packet from the FIFO
+    pub fn read(radio: &mut Registers, board: &Board) -> crate::Result<Self> {
+        let rssi = radio.RSSI().read()?;
+        let rffreq = i64::from(radio.TRKRFFREQ().read()?.0);
+        Ok(Self {
+            rssi: Some(board.rssi.dbm(f64::from(rssi))),
+            rf_offset: Some(rffreq * board.xtal.freq as i64 / (1 << 24)),
+            antenna: None,
+            crc_failed: false,
+        })
+    }
+
+    fn comment(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(rssi) = self.rssi {
+            parts.push(format!("rssi={:.1} dBm", rssi));
+        }
+        if let Some(offset) = self.rf_offset {
+            parts.push(format!("rf_offset={} Hz", offset));
+        }
+        if let Some(antenna) = self.antenna {
+            parts.push(format!(
+                "antenna={} ant1={} dB ant2={} dB",
+                antenna.selected(),
+                antenna.ant1,
+                antenna.ant2
+            ));
+        }
+        if self.crc_failed {
+            parts.push("crc=failed".to_string());
+        }
+        parts.join(" ")
+    }
+}
+
+pub struct Capture<W: Write> {
+    out: W,
+    /// Corrected to a pulse per second when there is one
+    clock: crate::pps::Clock,
+}
+
+pub type FileCapture = Capture<BufWriter<File>>;
+
+/// Creates (truncates) `path` and writes the pcapng header
+pub fn create<P: AsRef<Path>>(path: P, linktype: u16) -> Result<FileCapture> {
+    Capture::new(BufWriter::new(File::create(path)?), linktype)
+}
+
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend(code.to_ne_bytes());
+    buf.extend((value.len() as u16).to_ne_bytes());
+    buf.extend(value);
+    buf.resize(pad4(buf.len()), 0);
+}
+
+impl<W: Write> Capture<W> {
+    pub fn new(out: W, linktype: u16) -> Result<Self> {
+        let mut capture = Self {
+            out,
+            clock: Default::default(),
+        };
+
+        let mut shb = Vec::new();
+        shb.extend(BYTE_ORDER_MAGIC.to_ne_bytes());
+        shb.extend(1u16.to_ne_bytes()); // major
+        shb.extend(0u16.to_ne_bytes()); // minor
+        shb.extend((-1i64).to_ne_bytes()); // section length unknown
+        capture.block(SHB, &shb)?;
+
+        // Default if_tsresol is microseconds
+        let mut idb = Vec::new();
+        idb.extend(linktype.to_ne_bytes());
+        idb.extend(0u16.to_ne_bytes()); // reserved
+        idb.extend(0u32.to_ne_bytes()); // no snaplen
+        capture.block(IDB, &idb)?;
+
+        capture.out.flush()?;
+        Ok(capture)
+    }
+
+    fn block(&mut self, kind: u32, body: &[u8]) -> Result<()> {
+        let len = (12 + pad4(body.len())) as u32;
+        self.out.write_all(&kind.to_ne_bytes())?;
+        self.out.write_all(&len.to_ne_bytes())?;
+        self.out.write_all(body)?;
+        self.out
+            .write_all(&[0; 3][..pad4(body.len()) - body.len()])?;
+        self.out.write_all(&len.to_ne_bytes())
+    }
+
+    /// Stamps frames from here on with the system clock corrected to a pulse per second
+    pub fn set_clock(&mut self, clock: crate::pps::Clock) {
+        self.clock = clock;
+    }
+
+    /// Appends one frame stamped with the current time. Flushes so a crash mid-pass still
+    /// leaves a readable file.
+    pub fn write(&mut self, direction: Direction, data: &[u8], meta: &Meta) -> Result<()> {
+        let now = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.write_at(now.as_micros() as u64, direction, data, meta)?;
+        self.out.flush()
+    }
+
+    fn write_at(
+        &mut self,
+        micros: u64,
+        direction: Direction,
+        data: &[u8],
+        meta: &Meta,
+    ) -> Result<()> {
+        let mut epb = Vec::with_capacity(data.len() + 64);
+        epb.extend(0u32.to_ne_bytes()); // interface id
+        epb.extend(((micros >> 32) as u32).to_ne_bytes());
+        epb.extend((micros as u32).to_ne_bytes());
+        epb.extend((data.len() as u32).to_ne_bytes()); // captured
+        epb.extend((data.len() as u32).to_ne_bytes()); // original
+        epb.extend(data);
+        epb.resize(pad4(epb.len()), 0);
+
+        let flags: u32 = match direction {
+            Direction::Inbound => 0b01,
+            Direction::Outbound => 0b10,
+        };
+        option(&mut epb, EPB_FLAGS, &flags.to_ne_bytes());
+        let comment = meta.comment();
+        if !comment.is_empty() {
+            option(&mut epb, OPT_COMMENT, comment.as_bytes());
+        }
+        option(&mut epb, OPT_ENDOFOPT, &[]);
+
+        self.block(EPB, &epb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(buf: &[u8], at: usize) -> u32 {
+        u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap())
+    }
+
+    // Walks the blocks, checking both length fields agree and everything is 4 byte aligned
+    fn blocks(buf: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut out = Vec::new();
+        let mut at = 0;
+        while at < buf.len() {
+            let len = u32_at(buf, at + 4) as usize;
+            assert_eq!(len % 4, 0);
+            assert_eq!(u32_at(buf, at + len - 4) as usize, len);
+            out.push((u32_at(buf, at), &buf[at + 8..at + len - 4]));
+            at += len;
+        }
+        assert_eq!(at, buf.len());
+        out
+    }
+
+    #[test]
+    fn header() {
+        let capture = Capture::new(Vec::new(), LINKTYPE_AX25).unwrap();
+        let b = blocks(&capture.out);
+        assert_eq!(b.len(), 2);
+        assert_eq!(b[0].0, SHB);
+        assert_eq!(u32_at(b[0].1, 0), BYTE_ORDER_MAGIC);
+        assert_eq!(b[1].0, IDB);
+        assert_eq!(&b[1].1[..2], &LINKTYPE_AX25.to_ne_bytes());
+    }
+
+    #[test]
+    fn packet() {
+        let mut capture = Capture::new(Vec::new(), LINKTYPE_USER0).unwrap();
+        let meta = Meta {
+            rssi: Some(-80.0),
+            rf_offset: Some(1200),
+            antenna: None,
+            crc_failed: true,
+        };
+        let micros = 0x1_0000_0002;
+        capture
+            .write_at(micros, Direction::Outbound, &[1, 2, 3, 4, 5], &meta)
+            .unwrap();
+        let b = blocks(&capture.out);
+        assert_eq!(b.len(), 3);
+
+        let (kind, epb) = b[2];
+        assert_eq!(kind, EPB);
+        assert_eq!(u32_at(epb, 4), 1);
+        assert_eq!(u32_at(epb, 8), 2);
+        assert_eq!(u32_at(epb, 12), 5);
+        assert_eq!(u32_at(epb, 16), 5);
+        assert_eq!(&epb[20..25], &[1, 2, 3, 4, 5]);
+
+        // epb_flags, padded data ends at 28
+        assert_eq!(
+            &epb[28..32],
+            [&EPB_FLAGS.to_ne_bytes()[..], &4u16.to_ne_bytes()].concat()
+        );
+        assert_eq!(u32_at(epb, 32), 0b10);
+
+        let comment = b"rssi=-80.0 dBm rf_offset=1200 Hz crc=failed";
+        assert_eq!(&epb[36..38], &OPT_COMMENT.to_ne_bytes());
+        assert_eq!(&epb[40..40 + comment.len()], comment);
+        assert_eq!(&epb[epb.len() - 4..], &[0; 4]);
+    }
+
+    #[test]
+    fn no_comment() {
+        let mut capture = Capture::new(Vec::new(), LINKTYPE_USER0).unwrap();
+        capture
+            .write_at(0, Direction::Inbound, &[0; 4], &Meta::default())
+            .unwrap();
+        let b = blocks(&capture.out);
+        // header, 4 bytes of data, flags option, end of options
+        assert_eq!(b[2].1.len(), 20 + 4 + 8 + 4);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::*;
 use serde::{Deserialize, Serialize};
-use std::cmp::max;
+use std::{borrow::Cow, cmp::max, fmt};
 
 #[cfg(test)]
 use proptest::prelude::*;
@@ -24,7 +24,7 @@ proptest! {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub enum SysClk {
     Zero,
     One,
@@ -71,7 +71,7 @@ impl From<SysClk> for PFSysClkMode {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub enum DClk {
     Zero,
     One,
@@ -98,7 +98,7 @@ impl From<DClk> for PFDClkMode {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub enum Data {
     Zero,
     One,
@@ -127,7 +127,55 @@ impl From<Data> for PFDataMode {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+/// DCLK and DATA for the usual ways of hooking an external modem or decoder up to the chip, see
+/// the wire mode section of the PM. Board::wiring sets both pin modes from one of these.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ModemWiring {
+    /// Bit clock out, raw modem bits in for TX and out for RX
+    SyncModem,
+    /// Bit clock out, bits in and out above the on-chip framing (HDLC, CRC)
+    SyncFrame,
+    /// Bit clock from the external modem, raw modem bits in and out
+    ExternalClock,
+    /// Bit clock and demodulator output, RX still goes through the FIFO. For an external
+    /// decoder or a scope alongside the normal packet path.
+    Demodulator,
+    /// No clock, asynchronous bits in and out
+    Async,
+}
+
+impl ModemWiring {
+    #[rustfmt::skip]
+    pub fn modes(self) -> (DClk, Data) {
+        match self {
+            ModemWiring::SyncModem     => (DClk::Out, Data::ModemIO),
+            ModemWiring::SyncFrame     => (DClk::Out, Data::FrameIO),
+            ModemWiring::ExternalClock => (DClk::In,  Data::ModemIO),
+            ModemWiring::Demodulator   => (DClk::Out, Data::ModemOut),
+            ModemWiring::Async         => (DClk::Z,   Data::AsyncIO),
+        }
+    }
+}
+
+/// Catches DCLK and DATA combinations that can't work, whether from a ModemWiring or set by hand
+pub fn check_pins(dclk: DClk, data: Data) -> Result<()> {
+    let clocked = matches!(dclk, DClk::In | DClk::Out);
+    match data {
+        Data::ModemIO | Data::FrameIO if !clocked => {
+            Err(Error::Pins("synchronous DATA needs DCLK in or out"))
+        }
+        Data::ModemOut if dclk == DClk::In => {
+            Err(Error::Pins("DATA modem out drives its own clock on DCLK"))
+        }
+        Data::AsyncIO if clocked => Err(Error::Pins("asynchronous DATA has no DCLK")),
+        _ if dclk == DClk::In && !matches!(data, Data::ModemIO | Data::FrameIO) => {
+            Err(Error::Pins("DCLK in only clocks DATA modem or frame I/O"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub enum PwrAmp {
     Zero,
     One,
@@ -158,7 +206,7 @@ impl From<PwrAmp> for PFPwrAmpMode {
     }
 }
 
-#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
 pub enum IRQ {
     Zero,
     One,
@@ -181,7 +229,7 @@ impl From<IRQ> for PFIRQMode {
     }
 }
 
-#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
 pub enum AntSel {
     Zero,
     One,
@@ -214,7 +262,7 @@ impl From<AntSel> for PFAntSelMode {
 // - 0, 1, test are special (no inv/pull)?
 // - configure pullup/invert in a different way?
 // enum{ zero, one, z, test, func(T) }
-#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Pin<T> {
     pub mode: T,
     pub pullup: bool,
@@ -234,7 +282,7 @@ impl<T> From<Pin<T>> for PFFlags {
     }
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum XtalPin {
     #[default]
     None,
@@ -246,7 +294,7 @@ pub type Hz = u64;
 #[allow(non_camel_case_types)]
 pub type pF = f64; // TODO: newtype and XtalLoadCap::new(), TryFrom/From, make internal type u8
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum XtalKind {
     // FIXME rename to Oscillator? ExtOsc?
     XO {
@@ -256,14 +304,40 @@ pub enum XtalKind {
     TCXO,
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Xtal {
     pub kind: XtalKind,
+    /// Nominal, the frequency the part is sold as
     pub freq: Hz,
     pub enable: XtalPin,
+    /// Measured error of this particular oscillator, ppm, positive when it runs fast. FREQA/FREQB
+    /// and the IF are computed against the corrected frequency, see corrected().
+    #[serde(default)]
+    pub ppm_correction: f64,
+}
+
+impl fmt::Display for Xtal {
+    /// "TCXO 48.000000 MHz +1.20 ppm"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            XtalKind::XO { load_cap } => write!(f, "XO ({} pF)", load_cap)?,
+            XtalKind::TCXO => write!(f, "TCXO")?,
+        }
+        write!(f, " {:.6} MHz", self.freq as f64 / 1e6)?;
+        if self.ppm_correction != 0.0 {
+            write!(f, " {:+.2} ppm", self.ppm_correction)?;
+        }
+        Ok(())
+    }
 }
 
 impl Xtal {
+    /// What the oscillator actually runs at, `freq` trimmed by `ppm_correction`
+    #[must_use]
+    pub fn corrected(&self) -> Hz {
+        (self.freq as f64 * (1.0 + self.ppm_correction / 1e6)).round() as Hz
+    }
+
     #[must_use]
     pub fn div(&self) -> u64 {
         if self.freq < 24_800_000 {
@@ -274,7 +348,7 @@ impl Xtal {
     }
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum VCO {
     #[default]
     Internal, // VCO1
@@ -282,7 +356,7 @@ pub enum VCO {
     External, // Bypassed
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum Filter {
     // TODO: values?
     #[default]
@@ -290,14 +364,14 @@ pub enum Filter {
     External,
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum Antenna {
     #[default]
     SingleEnded,
     Differential,
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum DACPin {
     #[default]
     None,
@@ -305,13 +379,13 @@ pub enum DACPin {
     AntSel,
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct DAC {
     pub pin: DACPin,
     // TODO: initial output?
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum ADC {
     #[default]
     None,
@@ -320,12 +394,103 @@ pub enum ADC {
     Both,
 }
 
-#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize)]
+/// Chip pins that can be driven high or low from a register write
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum LnaPin {
+    AntSel,
+    DClk,
+    Data,
+}
+
+/// An external LNA's enable line on one of the chip's pins. The chip has no pin function that
+/// follows RX by itself, so rx::enter() and rx::leave() switch it. The pin is taken over from
+/// whatever the board otherwise says about it.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Lna {
+    pub pin: LnaPin,
+    /// Enabled by driving the pin low
+    #[serde(default)]
+    pub active_low: bool,
+    /// Time from enable to entering RX, µs
+    #[serde(default)]
+    pub settle_us: u64,
+}
+
+impl Lna {
+    pub fn set(&self, radio: &mut Registers, on: bool) -> Result<()> {
+        let high = on != self.active_low;
+        let flags = PFFlags::empty();
+        match self.pin {
+            LnaPin::AntSel => radio.PINFUNCANTSEL().write(PFAntSel {
+                mode: if high {
+                    PFAntSelMode::ONE
+                } else {
+                    PFAntSelMode::ZERO
+                },
+                flags,
+            }),
+            LnaPin::DClk => radio.PINFUNCDCLK().write(PFDClk {
+                mode: if high {
+                    PFDClkMode::ONE
+                } else {
+                    PFDClkMode::ZERO
+                },
+                flags,
+            }),
+            LnaPin::Data => radio.PINFUNCDATA().write(PFData {
+                mode: if high {
+                    PFDataMode::ONE
+                } else {
+                    PFDataMode::ZERO
+                },
+                flags,
+            }),
+        }
+    }
+
+    pub fn settle(&self) -> std::time::Duration {
+        std::time::Duration::from_micros(self.settle_us)
+    }
+}
+
+/// RSSI register to dBm at the antenna connector. The register is dB against a reference that
+/// depends on everything in front of the chip (LNA, filters, cabling), so each board needs its own
+/// line fitted through a few signal generator levels. The default passes the register through.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RssiCalibration {
+    /// dBm at a register value of 0
+    pub offset: f64,
+    /// dB per register step
+    pub gain: f64,
+}
+
+impl Default for RssiCalibration {
+    fn default() -> Self {
+        Self {
+            offset: 0.0,
+            gain: 1.0,
+        }
+    }
+}
+
+impl RssiCalibration {
+    /// `rssi` is a register value, or an average of them
+    pub fn dbm(&self, rssi: f64) -> f64 {
+        self.offset + self.gain * rssi
+    }
+}
+
+#[derive(Default, Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[rustfmt::skip]
 pub struct Board {
     pub sysclk: Pin<SysClk>, // FIXME: sysclk doesn't have invert
+    #[serde(default)]
     pub dclk:   Pin<DClk>,
+    #[serde(default)]
     pub data:   Pin<Data>,
+    /// Sets the dclk and data modes, their pullup and invert still apply
+    #[serde(default)]
+    pub wiring: Option<ModemWiring>,
     pub pwramp: Pin<PwrAmp>,
     pub irq:    Pin<IRQ>,
     pub antsel: Pin<AntSel>,
@@ -335,6 +500,14 @@ pub struct Board {
     pub filter: Filter,
     pub dac: DAC,
     pub adc: ADC,
+    #[serde(default)]
+    pub rssi: RssiCalibration,
+    #[serde(default)]
+    pub lna: Option<Lna>,
+    /// Two antennas switched by the ANTSEL pin (mode AntSel). The radio measures both during
+    /// the preamble and keeps the stronger, each packet comes with an rx::AntennaRssi.
+    #[serde(default)]
+    pub diversity: bool,
 }
 
 fn set_load_cap(radio: &mut Registers, load_cap: f64) -> Result<()> {
@@ -352,6 +525,44 @@ fn set_load_cap(radio: &mut Registers, load_cap: f64) -> Result<()> {
 }
 
 impl Board {
+    /// DCLK and DATA as written to the chip, with the wiring applied and checked
+    pub fn pins(&self) -> Result<(Pin<DClk>, Pin<Data>)> {
+        let (mut dclk, mut data) = (self.dclk, self.data);
+        if let Some(wiring) = self.wiring {
+            let (dclk_mode, data_mode) = wiring.modes();
+            if (dclk.mode != DClk::Z && dclk.mode != dclk_mode)
+                || (data.mode != Data::Z && data.mode != data_mode)
+            {
+                return Err(Error::Pins("dclk or data mode contradicts the wiring"));
+            }
+            dclk.mode = dclk_mode;
+            data.mode = data_mode;
+            if matches!(
+                self.lna,
+                Some(Lna {
+                    pin: LnaPin::DClk | LnaPin::Data,
+                    ..
+                })
+            ) {
+                return Err(Error::Pins("the LNA enable is on a pin the wiring uses"));
+            }
+        }
+        check_pins(dclk.mode, data.mode)?;
+        if self.diversity
+            && (self.antsel.mode != AntSel::AntSel
+                || matches!(
+                    self.lna,
+                    Some(Lna {
+                        pin: LnaPin::AntSel,
+                        ..
+                    })
+                ))
+        {
+            return Err(Error::Pins("diversity needs ANTSEL in AntSel mode"));
+        }
+        Ok((dclk, data))
+    }
+
     pub fn write(self, radio: &mut Registers) -> Result<Self> {
         // TODO: check that dac pin is set correctly
         // TODO: check that tcxo_en is set correctly
@@ -360,13 +571,14 @@ impl Board {
             mode: self.sysclk.mode.into(),
             pullup: self.sysclk.pullup,
         })?;
+        let (dclk, data) = self.pins()?;
         radio.PINFUNCDCLK().write(PFDClk {
-            mode: self.dclk.mode.into(),
-            flags: self.dclk.into(),
+            mode: dclk.mode.into(),
+            flags: dclk.into(),
         })?;
         radio.PINFUNCDATA().write(PFData {
-            mode: self.data.mode.into(),
-            flags: self.data.into(),
+            mode: data.mode.into(),
+            flags: data.into(),
         })?;
         radio.PINFUNCIRQ().write(PFIRQ {
             mode: self.irq.mode.into(),
@@ -380,6 +592,12 @@ impl Board {
             mode: self.pwramp.mode.into(),
             flags: self.pwramp.into(),
         })?;
+        if let Some(lna) = self.lna {
+            lna.set(radio, false)?;
+        }
+        if self.diversity {
+            radio.DIVERSITY().write(Diversity::DIVENA)?;
+        }
 
         if self.xtal.freq < 24_800_000 {
             radio.PERF_F35().write(PerfF35::FreqLT24p8MHz)?;
@@ -434,7 +652,7 @@ impl Board {
  * Synthesizer configuration
  */
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
 pub enum LoopFilter {
     External,
     Internalx1,
@@ -454,7 +672,7 @@ impl From<LoopFilter> for FLT {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct PLL {
     // TODO: Can I time how long it takes to settle?
     pub filter_bandwidth: LoopFilter, //TODO: Hz, Depends on CPI, internal/external filt (PLLLOOP::FLT)
@@ -466,7 +684,7 @@ pub struct PLL {
                                  // what would FILTEN 1, DIRECT 0 do?
 }
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
 pub enum LockDetector {
     Delay6ns,
     Delay9ns,
@@ -486,7 +704,7 @@ impl From<LockDetector> for LockDetDly {
     }
 }
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
 pub enum RangingClock {
     XtalDiv256,
     XtalDiv512,
@@ -523,7 +741,7 @@ impl From<RangingClock> for PLLRngClk {
  * bandwidth (register PLLLOOP)
  */
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
 pub enum FreqReg {
     A,
     B,
@@ -538,13 +756,13 @@ impl From<FreqReg> for FreqSel {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum Control<T> {
     Automatic,
     Manual(T),
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct Synthesizer {
     pub freq_a: Hz,
     pub freq_b: Hz,
@@ -558,11 +776,26 @@ pub struct Synthesizer {
     // Table 8. Depends also on vcosel
     // PLLVCODIV::{VCOSEL,VCO2INT} from phys layout
     // PLLRANGING::VCOR{A,B} saved, otherwise 8. also has status
-    pub vco_current: Control<u8>, //depends on VCO, auto or manual, readback VCOIR, see AND9858/D for manual cal
+    pub vco_current: Control<U6>, //depends on VCO, auto or manual, readback VCOIR, see AND9858/D for manual cal
     pub lock_detector_delay: Control<LockDetector>, // auto or manual, readback PLLLOCKDET::LOCKDETDLYR
     pub ranging_clock: RangingClock, // less than one tenth the loop filter bandwidth. Derive?
 }
 
+impl fmt::Display for Synthesizer {
+    /// "A 436.500000 MHz (active), B 437.000000 MHz"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let active = |reg| if self.active == reg { " (active)" } else { "" };
+        write!(
+            f,
+            "A {:.6} MHz{}, B {:.6} MHz{}",
+            self.freq_a as f64 / 1e6,
+            active(FreqReg::A),
+            self.freq_b as f64 / 1e6,
+            active(FreqReg::B),
+        )
+    }
+}
+
 fn to_freq(carrier: u64, xtal: u64) -> u32 {
     // PM Table 75: FREQA = fcarrier/fxtal * 2^24 + 1/2
     // It is not recommended to use an RF frequency that is an integer multiple of the reference
@@ -593,8 +826,7 @@ impl Synthesizer {
 
         // TODO: cross check synth.{pll,boost}.filter_bandwidth with board.filter
 
-        radio.FREQA().write(to_freq(self.freq_a, board.xtal.freq))?;
-        radio.FREQB().write(to_freq(self.freq_b, board.xtal.freq))?;
+        self.write_freq(radio, board)?;
 
         radio.PLLLOOP().write(PLLLoop {
             filter: self.pll.filter_bandwidth.into(),
@@ -646,7 +878,7 @@ impl Synthesizer {
                 flags: PLLVCOIFlags::MANUAL,
             },
             Control::Automatic => PLLVCOI {
-                bias: 0,
+                bias: U6::new(0),
                 flags: PLLVCOIFlags::AUTOMATIC,
             },
         })?;
@@ -671,6 +903,30 @@ impl Synthesizer {
         Ok(self)
     }
 
+    /// Just FREQA and FREQB, for trims small enough to stay inside the ranged VCO, see
+    /// thermal::Compensation
+    pub fn write_freq(&self, radio: &mut Registers, board: &Board) -> Result<()> {
+        radio
+            .FREQA()
+            .write(to_freq(self.freq_a, board.xtal.corrected()))?;
+        radio
+            .FREQB()
+            .write(to_freq(self.freq_b, board.xtal.corrected()))
+    }
+
+    /// Moves FREQA to `freq` and re-runs autoranging, leaving the radio in POWEROFF.
+    ///
+    /// The caller is responsible for emptying the FIFO first and restoring PWRMODE after.
+    pub fn retune(&mut self, radio: &mut Registers, board: &Board, freq: Hz) -> Result<()> {
+        // DS Table 8: 27 MHz to 1050 MHz, anything else won't fit the VCO
+        if !(27_000_000..=1_050_000_000).contains(&freq) {
+            return Err(Error::Invalid);
+        }
+        self.freq_a = freq;
+        self.write(radio, board)?;
+        self.autorange(radio)
+    }
+
     pub fn autorange(&self, radio: &mut Registers) -> Result<()> {
         /* If both frequency register sets FREQA and FREQB are used, then both
          * frequencies must be auto-ranged by first starting auto-ranging in
@@ -711,7 +967,7 @@ impl Synthesizer {
         while radio.XTALSTATUS().read()? != XtalStatus::XTAL_RUN {} // TODO: IRQXTALREADY
 
         radio.PLLRANGINGA().write(PLLRanging {
-            vcor: 0x08,
+            vcor: U4::new(0x08),
             flags: PLLRangingFlags::RNG_START,
         })?; // TODO: cache or pre-calc VCORA/B?
 
@@ -742,7 +998,7 @@ impl Synthesizer {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum ADCKind {
     ADC13,
     ADC1,
@@ -750,14 +1006,14 @@ pub enum ADCKind {
     ADC3,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct ADCcfg {
     pub sext: bool,
     pub offs: bool,
     pub kind: ADCKind,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum SlowRamp {
     Bits1,
     Bits2,
@@ -790,7 +1046,7 @@ impl TryFrom<BT> for ModCfgF {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum Modulation {
     ASK,
     ASKCoherent, // FIXME part of ASK, relevent to detection only? has a fifo cmd
@@ -833,6 +1089,27 @@ pub enum Modulation {
     //  AM?
 }
 
+impl fmt::Display for Modulation {
+    /// The scheme with its deviation and BT, "GFSK ±2400 Hz BT 0.5"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Modulation::ASK => write!(f, "ASK"),
+            Modulation::ASKCoherent => write!(f, "coherent ASK"),
+            Modulation::PSK { .. } => write!(f, "PSK"),
+            Modulation::OQPSK { .. } => write!(f, "OQPSK"),
+            Modulation::MSK { .. } => write!(f, "MSK"),
+            Modulation::GMSK { bt, .. } => write!(f, "GMSK BT {}", bt.0),
+            Modulation::FSK { deviation, .. } => write!(f, "FSK ±{} Hz", deviation),
+            Modulation::GFSK { deviation, bt, .. } => {
+                write!(f, "GFSK ±{} Hz BT {}", deviation, bt.0)
+            }
+            Modulation::FSK4 { deviation, .. } => write!(f, "4-FSK ±{} Hz", deviation),
+            Modulation::AFSK { deviation, .. } => write!(f, "AFSK ±{} Hz", deviation),
+            Modulation::FM { deviation, .. } => write!(f, "FM ±{} Hz", deviation),
+        }
+    }
+}
+
 // ENCODING NOSYNC only relevent in 4-fsk
 // Encoding in general looks dependent on modulation?
 // FEC only works with HDLC
@@ -843,12 +1120,12 @@ pub enum Modulation {
 // In Raw modes, the choice depends on the legacy
 // system to be implemented
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct FEC {
     // FIXME: stuff
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum Framing {
     Raw,
     RawSoft,
@@ -859,7 +1136,7 @@ pub enum Framing {
 }
 
 #[rustfmt::skip]
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum CRC {
     None,
     CCITT {initial: u16},
@@ -868,13 +1145,31 @@ pub enum CRC {
     CRC32 {initial: u32},
 }
 
+impl Framing {
+    /// Whether the packet controller delimits packets, the raw modes are a plain bit stream
+    pub fn packets(&self) -> bool {
+        !matches!(self, Framing::Raw | Framing::RawSoft)
+    }
+}
+
+impl CRC {
+    /// Bytes the packet controller appends to each packet
+    pub fn bytes(&self) -> usize {
+        match self {
+            CRC::None => 0,
+            CRC::CCITT { .. } | CRC::CRC16 { .. } | CRC::DNP { .. } => 2,
+            CRC::CRC32 { .. } => 4,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum BitOrder {
     LSBFirst,
     MSBFirst,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct ChannelParameters {
     pub modulation: Modulation,
     // If BROWN GATE is set, the transmitter is disabled
@@ -888,6 +1183,65 @@ pub struct ChannelParameters {
     pub crc: CRC,
     pub datarate: u64, // FIXME: Rename to bitrate
     pub bitorder: BitOrder,
+    #[serde(default)]
+    pub length: PacketLength,
+    /// What received frames are passed on with, see rx::CrcTrailer
+    #[serde(default)]
+    pub crc_trailer: crate::rx::CrcTrailer,
+    /// Outer code over the framing, done in software, see ax5043::reedsolomon
+    #[serde(default)]
+    pub reed_solomon: Option<crate::reedsolomon::ReedSolomon>,
+    /// Inner code, also in software, see ax5043::convolutional. Doubles coded_len().
+    #[serde(default)]
+    pub convolutional: Option<crate::convolutional::Convolutional>,
+}
+
+/// What ChannelParameters::decode() fixed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Corrections {
+    /// Coded bits, by the convolutional code
+    pub bits: usize,
+    /// Bytes, by Reed-Solomon
+    pub symbols: usize,
+}
+
+impl Corrections {
+    pub fn any(&self) -> bool {
+        self.bits > 0 || self.symbols > 0
+    }
+}
+
+impl fmt::Display for Corrections {
+    /// "12 bits, 3 RS symbols"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bits, {} RS symbols", self.bits, self.symbols)
+    }
+}
+
+impl fmt::Display for ChannelParameters {
+    /// "9600 bit/s GFSK ±2400 Hz BT 0.5, NRZISCR, HDLC, CCITT CRC, MSB first"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bit/s {}, {}, ",
+            self.datarate, self.modulation, self.encoding
+        )?;
+        match self.framing {
+            Framing::HDLC { .. } => write!(f, "HDLC")?,
+            framing => write!(f, "{:?} framing", framing)?,
+        }
+        match self.crc {
+            CRC::None => write!(f, ", no CRC")?,
+            CRC::CCITT { .. } => write!(f, ", CCITT CRC")?,
+            CRC::CRC16 { .. } => write!(f, ", CRC16")?,
+            CRC::DNP { .. } => write!(f, ", DNP CRC")?,
+            CRC::CRC32 { .. } => write!(f, ", CRC32")?,
+        }
+        match self.bitorder {
+            BitOrder::LSBFirst => write!(f, ", LSB first"),
+            BitOrder::MSBFirst => write!(f, ", MSB first"),
+        }
+    }
 }
 
 impl ChannelParameters {
@@ -926,22 +1280,166 @@ impl ChannelParameters {
         }
 
         radio.ENCODING().write(self.encoding)?;
+        self.write_frame_mode(radio)?;
+
+        Ok(self)
+    }
+
+    /// The frame that goes to the radio for `payload`: Reed-Solomon, then convolutional coding
+    /// and interleaving, whichever the channel has, then a Dynamic length field outside the
+    /// coding where the packet engine can read it
+    pub fn encode<'a>(&self, payload: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let mut frame = Cow::Borrowed(payload);
+        if let Some(rs) = self.reed_solomon {
+            frame = Cow::Owned(rs.encode(&frame)?);
+        }
+        if let Some(code) = self.convolutional {
+            frame = Cow::Owned(code.encode(&frame)?);
+        }
+        if self.length.field_len() > 0 {
+            frame = Cow::Owned(self.length.frame(&frame)?);
+        }
+        Ok(frame)
+    }
+
+    /// encode() undone on a received frame
+    pub fn decode<'a>(&self, frame: &'a [u8]) -> Result<(Cow<'a, [u8]>, Corrections)> {
+        let mut payload = Cow::Borrowed(frame);
+        if self.length.field_len() > 0 {
+            let stripped = self.length.payload(frame);
+            payload = Cow::Owned(stripped.ok_or(Error::Length("length field doesn't match"))?);
+        }
+        let mut corrections = Corrections::default();
+        if let Some(code) = self.convolutional {
+            let (decoded, bits) = code.decode(&payload)?;
+            payload = Cow::Owned(decoded);
+            corrections.bits = bits;
+        }
+        if let Some(rs) = self.reed_solomon {
+            let (decoded, symbols) = rs.decode(&payload)?;
+            payload = Cow::Owned(decoded);
+            corrections.symbols = symbols;
+        }
+        Ok((payload, corrections))
+    }
+
+    /// Bytes on the air for a `len` byte payload
+    pub fn coded_len(&self, len: usize) -> usize {
+        let len = len + self.reed_solomon.map_or(0, |rs| rs.overhead());
+        let len = match self.convolutional {
+            Some(_) => crate::convolutional::Convolutional::coded_len(len),
+            None => len,
+        };
+        len + self.length.field_len()
+    }
+
+    /// Whether the framing works with the rest of the channel
+    pub fn validate_framing(&self) -> Result<()> {
+        if let Some(rs) = self.reed_solomon {
+            rs.validate()?;
+        }
+        if let Some(code) = self.convolutional {
+            code.validate()?;
+        }
+        match (self.framing, self.crc) {
+            (Framing::Raw | Framing::RawSoft, CRC::None) => (),
+            (Framing::Raw | Framing::RawSoft, _) => {
+                return Err(Error::Framing(
+                    "raw framing has no packets to check a CRC on",
+                ))
+            }
+            _ => (),
+        }
+        match (self.framing, self.modulation) {
+            (
+                Framing::RawSoft,
+                Modulation::ASK | Modulation::ASKCoherent | Modulation::FSK4 { .. },
+            ) => Err(Error::Framing(
+                "soft bits need a two level FSK or PSK modulation",
+            )),
+            (
+                Framing::MBus | Framing::MBus4t6,
+                Modulation::FSK { .. } | Modulation::GFSK { .. },
+            ) => Ok(()),
+            (Framing::MBus | Framing::MBus4t6, _) => {
+                Err(Error::Framing("wireless M-Bus is (G)FSK only"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// This channel with `framing` instead, checked against the modulation, for switching a
+    /// running radio between traffic types with write_framing(). The raw modes carry no CRC,
+    /// going back to packets from one brings CCITT back.
+    pub fn with_framing(&self, framing: Framing) -> Result<Self> {
+        let crc = match (framing, self.crc) {
+            (Framing::Raw | Framing::RawSoft, _) => CRC::None,
+            (_, CRC::None) => CRC::CCITT { initial: 0xFFFF },
+            (_, crc) => crc,
+        };
+        let channel = Self {
+            framing,
+            crc,
+            ..*self
+        };
+        channel.validate_framing()?;
+        Ok(channel)
+    }
+
+    /// FRAMING and the packet engine registers (CRCINIT, PKT*) only. The modem, synthesizer and
+    /// RX parameter sets are left alone, so this is safe on a running radio.
+    pub fn write_framing(
+        &self,
+        radio: &mut Registers,
+        address: Option<PacketAddress>,
+    ) -> Result<()> {
+        self.write_frame_mode(radio)?;
+        PacketConfig {
+            address,
+            length: self.length,
+        }
+        .write(radio, self)
+    }
+
+    fn write_frame_mode(&self, radio: &mut Registers) -> Result<()> {
+        self.validate_framing()?;
+        let frmmode = match self.framing {
+            Framing::Raw => FrameMode::RAW,
+            Framing::RawSoft => FrameMode::RAW_SOFT_BITS,
+            Framing::HDLC { .. } => FrameMode::HDLC,
+            Framing::RawPattern => FrameMode::RAW_PATTERN_MATCH,
+            Framing::MBus => FrameMode::WIRELESS_MBUS,
+            Framing::MBus4t6 => FrameMode::WIRELESS_MBUS_4TO6,
+        };
+        let crcmode = match self.crc {
+            CRC::None => CRCMode::OFF,
+            CRC::CCITT { .. } => CRCMode::CCITT,
+            CRC::CRC16 { .. } => CRCMode::CRC16,
+            CRC::DNP { .. } => CRCMode::DNP,
+            CRC::CRC32 { .. } => CRCMode::CRC32,
+        };
         radio.FRAMING().write(crate::Framing {
-            frmmode: FrameMode::HDLC,
-            crcmode: CRCMode::CCITT,
+            frmmode,
+            crcmode,
             flags: FramingFlags::empty(),
         })?;
+        // FIXME: the configured initial value, once it's clear how a 16 bit CRC uses CRCINIT
         radio.CRCINIT().write(0xFFFF_FFFF)?;
-
-        Ok(self)
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+/// How the PA output follows the data. TXPWRCOEFFA-E form the predistortion polynomial
+/// a + b x + c x^2 + d x^3 + e x^4 applied to the shaped amplitude, b alone sets the power.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum AmplitudeShaping {
-    None {
-        b: u16,
-    }, // Datasheet says shaping should always be used? even in AM modes?
+    /// Hard keying: the raised cosine shaper and the predistortion are both bypassed and the PA
+    /// switches straight to b. For OOK and PAs that misbehave on slow ramps.
+    #[serde(alias = "None")]
+    Hard { b: u16 },
+    /// Raised cosine shaping without predistortion, conventional linear output
+    Linear { b: u16 },
+    /// Raised cosine shaping with the full predistortion polynomial
     RaisedCosine {
         a: u16,
         b: u16,
@@ -950,112 +1448,115 @@ pub enum AmplitudeShaping {
         e: u16,
     },
 }
-/* FIXME`
-For conventional (non-predistorted output), α0 = α2 = α3
-= α4 = 0 and 0 ≤ α1 ≤ 1 controls the output power. If hard
-amplitude shaping is selected, both the raised cosine
-amplitude shaper and the predistortion is bypassed, and α1
-used.
-*/
-#[derive(Clone, Copy, Debug, Deserialize)]
+
+impl AmplitudeShaping {
+    fn flags(self) -> ModCfgAFlags {
+        match self {
+            AmplitudeShaping::Hard { .. } => ModCfgAFlags::empty(),
+            AmplitudeShaping::Linear { .. } | AmplitudeShaping::RaisedCosine { .. } => {
+                ModCfgAFlags::AMPLSHAPE
+            }
+        }
+    }
+
+    /// TXPWRCOEFFA-E
+    fn coefficients(self) -> [u16; 5] {
+        match self {
+            AmplitudeShaping::Hard { b } | AmplitudeShaping::Linear { b } => [0, b, 0, 0, 0],
+            AmplitudeShaping::RaisedCosine { a, b, c, d, e } => [a, b, c, d, e],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct TXParameters {
     pub antenna: Antenna,
     pub amp: AmplitudeShaping,
+    /// PA ramp up at the start of a transmission, for ASK. The FSK family takes it from the
+    /// modulation instead. Defaults to 1 bit.
+    #[serde(default)]
+    pub ramp: Option<SlowRamp>,
     pub plllock_gate: bool,
     pub brownout_gate: bool,
 }
 
 impl TXParameters {
+    /// Checks the shaping against the channel's modulation
+    pub fn validate(&self, channel: &ChannelParameters) -> Result<()> {
+        match (channel.modulation, self.ramp) {
+            (Modulation::ASK, _) | (_, None) => (),
+            (_, Some(_)) => return Err(Error::Shaping("ramp is set in the modulation")),
+        }
+        let off = match self.amp {
+            AmplitudeShaping::Hard { b } | AmplitudeShaping::Linear { b } => b == 0,
+            AmplitudeShaping::RaisedCosine { a, b, c, d, e } => [a, b, c, d, e] == [0; 5],
+        };
+        if off {
+            return Err(Error::Shaping("all coefficients zero, no output"));
+        }
+        Ok(())
+    }
+
     pub fn write(
         self,
         radio: &mut Registers,
         board: &Board,
         channel: &ChannelParameters,
     ) -> Result<Self> {
+        self.validate(channel)?;
         // MODULATION,
         // FSKDEV,
         // MODCFGA,
         // MODCFGF,
-        match channel.modulation {
+        let ramp = match channel.modulation {
             Modulation::GFSK { ramp, bt, .. } | Modulation::GMSK { ramp, bt } => {
                 radio.MODCFGF().write(bt.try_into().unwrap())?;
-                let cfga = ModCfgA {
-                    slowramp: ramp.into(),
-                    flags: match self.antenna {
-                        Antenna::SingleEnded => ModCfgAFlags::TXSE,
-                        Antenna::Differential => ModCfgAFlags::TXDIFF,
-                    } | match self.amp {
-                        AmplitudeShaping::RaisedCosine { .. } => ModCfgAFlags::AMPLSHAPE,
-                        AmplitudeShaping::None { .. } => ModCfgAFlags::empty(),
-                    } | if self.plllock_gate {
-                        ModCfgAFlags::PLLLCK_GATE
-                    } else {
-                        ModCfgAFlags::empty()
-                    } | if self.brownout_gate {
-                        ModCfgAFlags::BROWN_GATE
-                    } else {
-                        ModCfgAFlags::empty()
-                    },
-                };
-                radio.MODCFGA().write(cfga)?;
-            }
-            Modulation::ASK => {
-                let cfga = ModCfgA {
-                    slowramp: SlowRamp::Bits1.into(),
-                    flags: match self.antenna {
-                        Antenna::SingleEnded => ModCfgAFlags::TXSE,
-                        Antenna::Differential => ModCfgAFlags::TXDIFF,
-                    } | match self.amp {
-                        AmplitudeShaping::RaisedCosine { .. } => ModCfgAFlags::AMPLSHAPE,
-                        AmplitudeShaping::None { .. } => ModCfgAFlags::empty(),
-                    } | if self.plllock_gate {
-                        ModCfgAFlags::PLLLCK_GATE
-                    } else {
-                        ModCfgAFlags::empty()
-                    } | if self.brownout_gate {
-                        ModCfgAFlags::BROWN_GATE
-                    } else {
-                        ModCfgAFlags::empty()
-                    },
-                };
-                radio.MODCFGA().write(cfga)?;
+                ramp
             }
+            Modulation::ASK => self.ramp.unwrap_or(SlowRamp::Bits1),
             _ => unimplemented!(),
-        }
+        };
+        radio.MODCFGA().write(ModCfgA {
+            slowramp: ramp.into(),
+            flags: match self.antenna {
+                Antenna::SingleEnded => ModCfgAFlags::TXSE,
+                Antenna::Differential => ModCfgAFlags::TXDIFF,
+            } | self.amp.flags()
+                | if self.plllock_gate {
+                    ModCfgAFlags::PLLLCK_GATE
+                } else {
+                    ModCfgAFlags::empty()
+                }
+                | if self.brownout_gate {
+                    ModCfgAFlags::BROWN_GATE
+                } else {
+                    ModCfgAFlags::empty()
+                },
+        })?;
 
         radio.TXRATE().write(
             div_nearest(channel.datarate * 2_u64.pow(24), board.xtal.freq)
                 .try_into()
                 .unwrap(),
         )?;
-        match self.amp {
-            AmplitudeShaping::RaisedCosine { a, b, c, d, e } => {
-                radio.TXPWRCOEFFA().write(a)?;
-                radio.TXPWRCOEFFB().write(b)?;
-                radio.TXPWRCOEFFC().write(c)?;
-                radio.TXPWRCOEFFD().write(d)?;
-                radio.TXPWRCOEFFE().write(e)?;
-            }
-            AmplitudeShaping::None { b } => {
-                radio.TXPWRCOEFFA().write(0)?;
-                radio.TXPWRCOEFFB().write(b)?;
-                radio.TXPWRCOEFFC().write(0)?;
-                radio.TXPWRCOEFFD().write(0)?;
-                radio.TXPWRCOEFFE().write(0)?;
-            }
-        }
+        let [a, b, c, d, e] = self.amp.coefficients();
+        radio.TXPWRCOEFFA().write(a)?;
+        radio.TXPWRCOEFFB().write(b)?;
+        radio.TXPWRCOEFFC().write(c)?;
+        radio.TXPWRCOEFFD().write(d)?;
+        radio.TXPWRCOEFFE().write(e)?;
 
         Ok(self)
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum FreqOffsetCorrection {
     AtFirstLO,
     AtSecondLO,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub enum RXParameters {
     MSK {
         // MODULATION::RX_HALFSPEED
@@ -1063,10 +1564,30 @@ pub enum RXParameters {
         freq_offs_corr: FreqOffsetCorrection,
         ampl_filter: u8,
         frequency_leak: u8,
+        /// Receiver bandwidth in Hz instead of the nominal 1.5 * datarate, wider for transmitters
+        /// that drift. DECIMATION, the IF and MAXRFOFFSET follow it.
+        #[serde(default)]
+        bandwidth: Option<u64>,
     },
 }
 
 impl RXParameters {
+    /// What decimation() aims for: the override, or Carson's rule for the channel
+    pub fn bandwidth(&self, channel: &ChannelParameters) -> u64 {
+        match self {
+            Self::MSK {
+                bandwidth: Some(bandwidth),
+                ..
+            } => *bandwidth,
+            Self::MSK { .. } => {
+                // modulation index: m = 0.5;
+                // bandwidth = (1+m) * datarate (Carson's rule)
+                // TODO: Radiolab lists bandwidth always as 1.5*datarate, does not compensate for m
+                3 * channel.datarate / 2
+            }
+        }
+    }
+
     pub fn decimation(&self, board: &Board, channel: &ChannelParameters) -> u64 {
         match self {
             Self::MSK { .. } => {
@@ -1077,10 +1598,7 @@ impl RXParameters {
                 // index, so receiver bandwidth must be greater than that.
                 // See also
                 // https://en.wikipedia.org/wiki/Downsampling_(signal_processing)#Downsampling_by_an_integer_factor
-                // modulation index: m = 0.5;
-                // bandwidth = (1+m) * datarate (Carson's rule)
-                // TODO: Radiolab lists bandwidth always as 1.5*datarate, does not compensate for m
-                let bandwidth = 3 * channel.datarate / 2;
+                let bandwidth = self.bandwidth(channel);
                 // FIXME PHASEGAIN::FILTERIDX but translated through table 116. Note that column
                 // names are swapped. Label -3dB BW should be nominal BW.
                 // Radiolab calculates -3dB BW as nominal BW * 1.1, but TODO where does 1.1 come from?
@@ -1123,9 +1641,16 @@ impl RXParameters {
                 ref freq_offs_corr,
                 ampl_filter,
                 frequency_leak,
+                bandwidth: wanted,
             } => {
                 let decimation = self.decimation(board, channel);
-                radio.DECIMATION().write(decimation.try_into().unwrap())?; // TODO: 7bits max
+                // DECIMATION is 7 bits. A bandwidth narrower than the datarate can't pass it.
+                if !(1..0x80).contains(&decimation)
+                    || wanted.is_some_and(|bw| bw < channel.datarate)
+                {
+                    return Err(Error::Bandwidth(self.bandwidth(channel)));
+                }
+                radio.DECIMATION().write(decimation.try_into().unwrap())?;
 
                 // Now that we have a fixed fbaseband, we can re-determine the bandwidth
                 let fbaseband = board.xtal.freq / (board.xtal.div() * 2_u64.pow(4) * decimation);
@@ -1142,10 +1667,18 @@ impl RXParameters {
                     //(bandwidth + 325) * 5 / 37
                     (40 * channel.datarate + 8673) / 49
                 };
+                // A wider filter needs the IF at least half of it up, so the image stays out
+                let if_freq = match wanted {
+                    Some(_) => max(if_freq, bandwidth / 2),
+                    None => if_freq,
+                };
                 radio.IFFREQ().write(
-                    div_nearest(if_freq * board.xtal.div() * 2_u64.pow(20), board.xtal.freq)
-                        .try_into()
-                        .unwrap(),
+                    div_nearest(
+                        if_freq * board.xtal.div() * 2_u64.pow(20),
+                        board.xtal.corrected(),
+                    )
+                    .try_into()
+                    .unwrap(),
                 )?;
 
                 //radio.IFFREQ().write(0x0E78)?;
@@ -1212,14 +1745,14 @@ const AGCGAIN_LOOP_SCALE: [u64; 15] = [
     1_647_150,
 ];
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct RXParameterAGC {
-    attack: u8,
-    decay: u8,
+    attack: U4,
+    decay: U4,
     target: u8,
-    ahyst: u8,
-    min: u8,
-    max: u8,
+    ahyst: U3,
+    min: U3,
+    max: U3,
 }
 
 impl RXParameterAGC {
@@ -1247,72 +1780,72 @@ impl RXParameterAGC {
         }
 
         Self {
-            attack: u8::try_from(attack).unwrap(),
-            decay: u8::try_from(decay).unwrap(),
+            attack: U4::new(u8::try_from(attack).unwrap()),
+            decay: U4::new(u8::try_from(decay).unwrap()),
             target: 0x84, // RadioLAB always picks this, seems reasonable?
-            ahyst: 0,
-            min: 0,
-            max: 0,
+            ahyst: U3::new(0),
+            min: U3::new(0),
+            max: U3::new(0),
         }
     }
 
     pub fn off() -> Self {
         Self {
             // attack/decay value F disables AGC
-            attack: 0xF,
-            decay: 0xF,
+            attack: U4::new(0xF),
+            decay: U4::new(0xF),
             target: 0x84,
-            ahyst: 0,
-            min: 0,
-            max: 0,
+            ahyst: U3::new(0),
+            min: U3::new(0),
+            max: U3::new(0),
         }
     }
 
     pub fn radiolab() -> Self {
         Self {
-            // Radiolab always chooses these values
-            attack: 0x93,
-            decay: 0x93,
+            // Radiolab always chooses AGCGAIN 0x93
+            attack: U4::new(0x3),
+            decay: U4::new(0x9),
             target: 0x84,
-            ahyst: 0,
-            min: 0,
-            max: 0,
+            ahyst: U3::new(0),
+            min: U3::new(0),
+            max: U3::new(0),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct RXParameterFreq {
-    pub phase: u8,
-    pub freq: u8,
+    pub phase: U5, // Only 4 bits for baseband
+    pub freq: U5,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct RXParameterGain {
     pub time_corr_frac: u32, // should be at least 4. bit sampling timing, see pm p 16
     pub datarate_corr_frac: u32, // should be at least 64,
-    pub phase: u8,
-    pub filter: u8,
+    pub phase: U4,
+    pub filter: U2,
     pub baseband: Option<RXParameterFreq>,
     pub rf: Option<RXParameterFreq>,
-    pub amplitude: u8,
+    pub amplitude: U4,
     pub deviation_update: bool, // FIXME below decay?
     pub ampl_agc_jump_correction: bool,
     pub ampl_averaging: bool,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct RXParameterBasebandOffset {
-    pub a: u8,
-    pub b: u8,
+    pub a: U4,
+    pub b: U4,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct RXParameterSet {
     pub agc: Control<RXParameterAGC>,
     pub gain: RXParameterGain,
     pub freq_dev: Option<u16>,
-    pub decay: u8,
+    pub decay: U4,
     pub baseband_offset: RXParameterBasebandOffset,
 }
 
@@ -1362,7 +1895,7 @@ impl RXParameterSet {
         })?;
         if let Some(RXParameterFreq { phase, freq }) = self.gain.baseband {
             radio.FREQGAINA0().write(FreqGainA {
-                gain: phase,
+                gain: U4::try_from(phase.get())?,
                 flags: FreqGainAFlags::empty(),
             })?;
             radio.FREQGAINB0().write(FreqGainB {
@@ -1371,11 +1904,11 @@ impl RXParameterSet {
             })?;
         } else {
             radio.FREQGAINA0().write(FreqGainA {
-                gain: 0x0F,
+                gain: U4::new(0x0F),
                 flags: FreqGainAFlags::empty(),
             })?;
             radio.FREQGAINB0().write(FreqGainB {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 flags: FreqGainBFlags::empty(),
             })?;
         }
@@ -1388,9 +1921,11 @@ impl RXParameterSet {
                 freeze: false,
             })?;
         } else {
-            radio.FREQGAINC0().write(FreqGainC { gain: 0x1F })?;
+            radio.FREQGAINC0().write(FreqGainC {
+                gain: U5::new(0x1F),
+            })?;
             radio.FREQGAIND0().write(FreqGainD {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 freeze: false,
             })?;
         }
@@ -1474,7 +2009,7 @@ impl RXParameterSet {
 
         if let Some(RXParameterFreq { phase, freq }) = self.gain.baseband {
             radio.FREQGAINA1().write(FreqGainA {
-                gain: phase,
+                gain: U4::try_from(phase.get())?,
                 flags: FreqGainAFlags::empty(),
             })?;
             radio.FREQGAINB1().write(FreqGainB {
@@ -1483,11 +2018,11 @@ impl RXParameterSet {
             })?;
         } else {
             radio.FREQGAINA1().write(FreqGainA {
-                gain: 0b1111,
+                gain: U4::new(0b1111),
                 flags: FreqGainAFlags::empty(),
             })?;
             radio.FREQGAINB1().write(FreqGainB {
-                gain: 0b1_1111,
+                gain: U5::new(0b1_1111),
                 flags: FreqGainBFlags::empty(),
             })?;
         }
@@ -1499,9 +2034,11 @@ impl RXParameterSet {
                 freeze: false,
             })?;
         } else {
-            radio.FREQGAINC1().write(FreqGainC { gain: 0x1F })?;
+            radio.FREQGAINC1().write(FreqGainC {
+                gain: U5::new(0x1F),
+            })?;
             radio.FREQGAIND1().write(FreqGainD {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 freeze: false,
             })?;
         }
@@ -1574,7 +2111,7 @@ impl RXParameterSet {
 
         if let Some(RXParameterFreq { phase, freq }) = self.gain.baseband {
             radio.FREQGAINA3().write(FreqGainA {
-                gain: phase,
+                gain: U4::try_from(phase.get())?,
                 flags: FreqGainAFlags::empty(),
             })?;
             radio.FREQGAINB3().write(FreqGainB {
@@ -1583,11 +2120,11 @@ impl RXParameterSet {
             })?;
         } else {
             radio.FREQGAINA3().write(FreqGainA {
-                gain: 0b1111,
+                gain: U4::new(0b1111),
                 flags: FreqGainAFlags::empty(),
             })?;
             radio.FREQGAINB3().write(FreqGainB {
-                gain: 0b1_1111,
+                gain: U5::new(0b1_1111),
                 flags: FreqGainBFlags::empty(),
             })?;
         }
@@ -1599,9 +2136,11 @@ impl RXParameterSet {
                 freeze: false,
             })?;
         } else {
-            radio.FREQGAINC3().write(FreqGainC { gain: 0x1F })?;
+            radio.FREQGAINC3().write(FreqGainC {
+                gain: U5::new(0x1F),
+            })?;
             radio.FREQGAIND3().write(FreqGainD {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 freeze: false,
             })?;
         }
@@ -1636,10 +2175,10 @@ impl RXParameterSet {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct PatternMatch0 {
     pub pat: u32,
-    pub len: u8,
+    pub len: U5,
     pub raw: bool,
     pub min: u8,
     pub max: u8,
@@ -1651,13 +2190,13 @@ impl PatternMatch0 {
         // more than MATCHxMAX positions or less than MATCHxMIN positions. I assume non-contiguous
         // MIN = 1 would mean match in exatly 0 positions witch is unlikely, probably an inverted
         // pattern sequence.
-        if self.len > 31 {
+        if self.len > U5::new(31) {
             return Err(Error::Invalid);
         }
-        if self.min > self.len {
+        if self.min > self.len.get() {
             return Err(Error::Invalid);
         }
-        if self.max > self.len {
+        if self.max > self.len.get() {
             return Err(Error::Invalid);
         }
         if self.min > self.max {
@@ -1681,10 +2220,10 @@ impl PatternMatch0 {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct PatternMatch1 {
     pub pat: u16,
-    pub len: u8,
+    pub len: U5,
     pub raw: bool,
     pub min: u8,
     pub max: u8,
@@ -1692,13 +2231,13 @@ pub struct PatternMatch1 {
 
 impl PatternMatch1 {
     pub fn write(&self, radio: &mut Registers) -> Result<()> {
-        if self.len > 15 {
+        if self.len > U5::new(15) {
             return Err(Error::Invalid);
         }
-        if self.min > self.len {
+        if self.min > self.len.get() {
             return Err(Error::Invalid);
         }
-        if self.max > self.len {
+        if self.max > self.len.get() {
             return Err(Error::Invalid);
         }
         if self.min > self.max {
@@ -1719,20 +2258,20 @@ impl PatternMatch1 {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct Preamble1 {
     pub timeout: Float5, // between 0 and 3968 bits
     pub set: RxParamSet,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct Preamble2 {
     pub pattern: PatternMatch1,
     pub timeout: Float5, // between 0 and 3968 bits
     pub set: RxParamSet,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct Preamble3 {
     pub pattern: PatternMatch0,
     pub timeout: Float5, // between 0 and 3968 bits
@@ -1742,7 +2281,7 @@ pub struct Preamble3 {
 // see PM pg 19 Figure 13. FIXME: what is TXPREAMBLE1? only mentioned in this diagram. Is it
 // missing -MGR-?
 // TODO: TMGRX{AGC,RSSI} units PKTMISC flag
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct RXParameterStages {
     // TODO: Should this just be merged with RXParameters?
     pub preamble1: Option<Preamble1>,
@@ -1795,9 +2334,9 @@ impl RXParameterStages {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct PacketAddress {
-    pub pos: u8,
+    pub pos: U4,
     pub addr: u32,
     pub mask: u32,
 }
@@ -1812,7 +2351,6 @@ impl PacketAddress {
                 PktAddrCfgFlags::empty()
             };
 
-        // FIXME: assert pos < 16
         radio.PKTADDRCFG().write(PktAddrCfg {
             addr_pos: self.pos,
             flags,
@@ -1823,14 +2361,99 @@ impl PacketAddress {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+fn all_bits() -> u32 {
+    u32::MAX
+}
+
+/// The [address] section: frames only pass addressed to one of several destinations, say the
+/// station's callsign, broadcast and an alias.
+///
+///   [address]
+///   pos = 1
+///   addresses = [0x8A6E6896, 0xFFFFFFFF]
+///
+/// Addresses are up to four bytes at `pos`, the first byte in the low bits as in PKTADDR. The
+/// radio matches one address under one mask, so it's given the bits every address agrees on
+/// and passes any frame that has those. When that's exactly the listed addresses (one
+/// address, or ones differing in a bit or two whose every combination is listed)
+/// PacketAssembler has nothing to add, otherwise it checks each frame against the list.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct AddressFilter {
+    pub pos: U4,
+    /// Address bits that count, all of them by default
+    #[serde(default = "all_bits")]
+    pub mask: u32,
+    pub addresses: Vec<u32>,
+}
+
+impl AddressFilter {
+    pub fn validate(&self) -> Result<()> {
+        if self.addresses.is_empty() {
+            return Err(Error::Address("no addresses"));
+        }
+        if self.mask == 0 {
+            return Err(Error::Address("mask has no bits set"));
+        }
+        Ok(())
+    }
+
+    /// PKTADDR and PKTADDRMASK for the bits all the addresses agree on
+    pub fn hardware(&self) -> PacketAddress {
+        let first = self.addresses.first().copied().unwrap_or_default();
+        let differ = self
+            .addresses
+            .iter()
+            .fold(0, |differ, addr| differ | (addr ^ first));
+        let mask = self.mask & !differ;
+        PacketAddress {
+            pos: self.pos,
+            addr: first & mask,
+            mask,
+        }
+    }
+
+    /// Whether hardware() passes only the listed addresses
+    pub fn exact(&self) -> bool {
+        let free = (self.mask & !self.hardware().mask).count_ones();
+        let mut distinct: Vec<u32> = self.addresses.iter().map(|a| a & self.mask).collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        free < u32::BITS && distinct.len() == 1 << free
+    }
+
+    /// Whether `packet` carries one of the addresses. Too short to hold the address is a miss.
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        let pos = usize::from(self.pos.get());
+        let bytes = (u32::BITS - self.mask.leading_zeros()).div_ceil(8) as usize;
+        let Some(field) = packet.get(pos..pos + bytes) else {
+            return false;
+        };
+        let received = field
+            .iter()
+            .rev()
+            .fold(0u32, |acc, &b| acc << 8 | u32::from(b));
+        self.addresses
+            .iter()
+            .any(|addr| (addr ^ received) & self.mask == 0)
+    }
+}
+
+/// How the packet engine finds where a packet ends, `length` in a [[channel]]
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
 pub enum PacketLength {
+    /// Until the framing says it's over (HDLC flags), any length
+    #[default]
     Arbitrary,
+    /// A length field `bits` wide at byte `pos`. Over 8 bits it takes two bytes, most
+    /// significant first, for packets past 255 bytes. The field plus `offset` is the packet's
+    /// length in bytes, field included, CRC not.
     Dynamic {
-        pos: u8,
-        bits: u8,
+        pos: U4,
+        bits: U4,
         offset: i8,
-        max: u8,
+        /// Longest packet, bytes. PKTMAXLEN holds 8 bits, so above 255 the radio flags longer
+        /// packets SIZEFAIL and rx::PacketAssembler::max_len() does the checking instead.
+        max: u16,
     },
     Fixed {
         len: i8,
@@ -1838,14 +2461,88 @@ pub enum PacketLength {
 }
 
 impl PacketLength {
+    /// Bytes taken by the length field
+    pub fn field_len(&self) -> usize {
+        match self {
+            PacketLength::Dynamic { bits, .. } if bits.get() > 8 => 2,
+            PacketLength::Dynamic { .. } => 1,
+            _ => 0,
+        }
+    }
+
+    /// The longest packet the radio will take, None with arbitrary lengths
+    pub fn max_len(&self) -> Option<usize> {
+        match *self {
+            PacketLength::Arbitrary => None,
+            PacketLength::Dynamic { max, .. } => Some(usize::from(max)),
+            PacketLength::Fixed { len } => Some(len.max(0) as usize),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let PacketLength::Dynamic {
+            bits, offset, max, ..
+        } = *self
+        {
+            if bits.get() == 0 {
+                return Err(Error::Length("a 0 bit length field, use Fixed"));
+            }
+            if i64::from(max) > (1i64 << bits.get()) - 1 + i64::from(offset) {
+                return Err(Error::Length("max doesn't fit in the length field"));
+            }
+        }
+        Ok(())
+    }
+
+    /// `payload` with the length field put in at its position, ready to transmit, see
+    /// ChannelParameters::encode()
+    pub fn frame(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        let PacketLength::Dynamic {
+            pos, offset, max, ..
+        } = *self
+        else {
+            return Ok(payload.to_vec());
+        };
+        let pos = usize::from(pos.get());
+        let len = payload.len() + self.field_len();
+        if pos > payload.len() || len > usize::from(max) {
+            return Err(Error::Length("payload doesn't fit"));
+        }
+        let field = (len as i64 - i64::from(offset)) as u16;
+        let field = match self.field_len() {
+            2 => field.to_be_bytes().to_vec(),
+            _ => vec![field as u8],
+        };
+        Ok([&payload[..pos], &field, &payload[pos..]].concat())
+    }
+
+    /// The payload of a received packet, the length field taken out again. None if the field
+    /// doesn't match what arrived. See ChannelParameters::decode().
+    pub fn payload(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let PacketLength::Dynamic { pos, offset, .. } = *self else {
+            return Some(packet.to_vec());
+        };
+        let pos = usize::from(pos.get());
+        let field = packet.get(pos..pos + self.field_len())?;
+        let value = field.iter().fold(0i64, |acc, b| acc << 8 | i64::from(*b));
+        if value + i64::from(offset) != packet.len() as i64 {
+            return None;
+        }
+        Some([&packet[..pos], &packet[pos + field.len()..]].concat())
+    }
+
     pub fn write(&self, radio: &mut Registers) -> Result<()> {
+        self.validate()?;
         match self {
             PacketLength::Arbitrary => {
                 // See note under Table 154
-                radio.PKTLENCFG().write(PktLenCfg { pos: 0, bits: 0xF })?;
+                radio.PKTLENCFG().write(PktLenCfg {
+                    pos: U4::new(0),
+                    bits: U4::new(0xF),
+                })?;
                 radio.PKTLENOFFSET().write(0)?;
                 radio.PKTMAXLEN().write(0xFF)?;
-                // FIXME: ensure PktAcceptFlags::LRGP
+                // Needs rx::AcceptancePolicy::large for anything over PKTCHUNKSIZE
             }
             PacketLength::Dynamic {
                 pos,
@@ -1859,10 +2556,15 @@ impl PacketLength {
                 })?;
                 radio.PKTLENOFFSET().write(*offset)?;
                 // FIXME: Check that max >= offset
-                radio.PKTMAXLEN().write(*max)?;
+                radio
+                    .PKTMAXLEN()
+                    .write(u8::try_from(*max).unwrap_or(0xFF))?;
             }
             PacketLength::Fixed { len } => {
-                radio.PKTLENCFG().write(PktLenCfg { pos: 0, bits: 0x0 })?;
+                radio.PKTLENCFG().write(PktLenCfg {
+                    pos: U4::new(0),
+                    bits: U4::new(0x0),
+                })?;
                 radio.PKTLENOFFSET().write(*len)?;
                 radio.PKTMAXLEN().write((*len).try_into().unwrap())?;
             }
@@ -1872,7 +2574,7 @@ impl PacketLength {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct PacketConfig {
     pub address: Option<PacketAddress>,
     pub length: PacketLength,
@@ -1884,7 +2586,7 @@ impl PacketConfig {
             address
         } else {
             PacketAddress {
-                pos: 0,
+                pos: U4::new(0),
                 addr: 0,
                 mask: 0,
             }
@@ -1896,14 +2598,14 @@ impl PacketConfig {
 }
 
 #[allow(non_snake_case)]
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 pub struct Raw {
     pub FREQA: Option<u32>,
     pub PLLVCODIV: Option<PLLVCODiv>,
     pub PERF_F35: Option<PerfF35>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, PartialEq)]
 pub struct Config {
     pub board: Board,
     pub synth: Synthesizer,
@@ -1916,65 +2618,869 @@ pub struct Config {
     pub set3: Option<RXParameterSet>,
     pub stages: Option<RXParameterStages>,
     pub overwrite: Option<Raw>,
+    /// Frames to transmit must be signed, see ax5043::auth
+    pub auth: Option<crate::auth::Auth>,
+    /// How RX drains the FIFO, see rx::FifoPolicy
+    #[serde(default)]
+    pub fifo: crate::rx::FifoPolicy,
+    /// Failed packets to pass on anyway, see rx::AcceptancePolicy
+    #[serde(default)]
+    pub accept: crate::rx::AcceptancePolicy,
+    /// Carrier trim against crystal drift, see thermal::Trim
+    #[serde(default)]
+    pub thermal: Option<crate::thermal::Compensation>,
+    /// Carriers to time-slice the receiver across, see scanlist::Scanner
+    #[serde(default)]
+    pub scan: Option<crate::scanlist::ScanList>,
+    /// Forward and reflected power around each transmission, see power::PowerSensor
+    #[serde(default)]
+    pub power_sensor: Option<crate::power::IioSensor>,
+    /// LPOSC calibration and timestamps against a GPS pulse per second, see pps::Calibrator
+    #[serde(default)]
+    pub pps: Option<crate::pps::Discipline>,
+    /// Destinations to pass frames for, see AddressFilter
+    #[serde(default)]
+    pub address: Option<AddressFilter>,
+    /// What received frames go up the uplink, see filter::Rules
+    #[serde(default)]
+    pub filter: Option<crate::filter::Rules>,
+    /// Operator commands must be sealed with this key, see auth::ControlAuth
+    #[serde(default)]
+    pub control: Option<crate::auth::ControlAuth>,
+    /// Slower channel for a marginal link, see fallback::Monitor
+    #[serde(default)]
+    pub fallback: Option<crate::fallback::Fallback>,
 }
 
 impl Config {
     pub fn write(&self, radio: &mut Registers) -> Result<()> {
         self.board.write(radio)?;
         self.synth.write(radio, &self.board)?;
-        let default_channel = &self.channel[0];
-        default_channel.write(radio, &self.board)?;
+        self.channel[0].write(radio, &self.board)?;
         self.synth.autorange(radio)?;
-
-        if let Some(tx) = self.tx {
-            tx.write(radio, &self.board, default_channel)?;
+        self.write_parameters(radio)?;
+        if let Some(ref pps) = self.pps {
+            pps.write(radio, &self.board)?;
         }
 
-        if let Some(rx) = self.rx {
-            rx.write(radio, &self.board, &self.synth, default_channel)?;
-            if let Some(set) = self.set0 {
-                set.write0(radio, &self.board, default_channel, &rx)?;
-            }
-            if let Some(set) = self.set1 {
-                set.write1(radio, &self.board, default_channel, &rx)?;
-            }
-            if let Some(_set) = self.set2 {
-                //FIXME
-                //set.write2(radio, &self.board, default_channel, &rx)?;
+        if let Some(overwrite) = self.overwrite {
+            if let Some(val) = overwrite.FREQA {
+                radio.FREQA().write(val)?;
             }
-            if let Some(set) = self.set3 {
-                set.write3(radio, &self.board, default_channel, &rx)?;
+            if let Some(val) = overwrite.PLLVCODIV {
+                radio.PLLVCODIV().write(val)?;
             }
-            if let Some(stages) = self.stages {
-                stages.write(radio)?;
+            if let Some(val) = overwrite.PERF_F35 {
+                radio.PERF_F35().write(val)?;
             }
+        }
+        Ok(())
+    }
 
-            PacketConfig {
-                address: None,
-                length: PacketLength::Arbitrary,
-            }
-            .write(radio, default_channel)?;
+    /// Everything written to the radio at startup before entering RX, and again after every
+    /// reset: the config, then the FIFO threshold and RSSI reference the daemons run with
+    pub fn configure(&self, radio: &mut Registers) -> Result<()> {
+        self.write(radio)?;
+        radio.FIFOTHRESH().write(128)?; // Half the FIFO size
+        radio.RSSIREFERENCE().write(32)?;
+        Ok(())
+    }
+
+    /// Everything that depends on the default channel, safe to rewrite without autoranging
+    fn write_parameters(&self, radio: &mut Registers) -> Result<()> {
+        let default_channel = &self.channel[0];
+        if let Some(tx) = self.tx {
+            tx.write(radio, &self.board, default_channel)?;
+        }
+
+        if self.rx.is_some() {
+            self.write_rx_parameters(radio, default_channel)?;
 
             radio.PERF_F18().write(0x02)?; // TODO set by radiolab during RX
             radio.PERF_F26().write(0x96)?;
 
             radio.PKTCHUNKSIZE().write(PktChunkSize::B128)?;
-            radio.PKTACCEPTFLAGS().write(PktAcceptFlags::LRGP)?;
+            self.accept.write(radio)?;
+            if self.board.diversity {
+                radio.PKTSTOREFLAGS().write(PktStoreFlags::ANT_RSSI)?;
+            }
 
             radio.RSSIREFERENCE().write(0)?;
         }
+        Ok(())
+    }
 
-        if let Some(overwrite) = self.overwrite {
-            if let Some(val) = overwrite.FREQA {
-                radio.FREQA().write(val)?;
+    /// The [rx] parameters and sets that follow the channel's datarate and modulation
+    fn write_rx_parameters(
+        &self,
+        radio: &mut Registers,
+        channel: &ChannelParameters,
+    ) -> Result<()> {
+        let Some(rx) = self.rx else {
+            return Ok(());
+        };
+        rx.write(radio, &self.board, &self.synth, channel)?;
+        if let Some(set) = self.set0 {
+            set.write0(radio, &self.board, channel, &rx)?;
+        }
+        if let Some(set) = self.set1 {
+            set.write1(radio, &self.board, channel, &rx)?;
+        }
+        if let Some(_set) = self.set2 {
+            //FIXME
+            //set.write2(radio, &self.board, channel, &rx)?;
+        }
+        if let Some(set) = self.set3 {
+            set.write3(radio, &self.board, channel, &rx)?;
+        }
+        if let Some(stages) = self.stages {
+            stages.write(radio)?;
+        }
+
+        if channel.length.max_len() > Some(0xFF) && !self.accept.size_failed {
+            return Err(Error::Length(
+                "over 255 bytes needs [accept] size_failed, PKTMAXLEN is one byte",
+            ));
+        }
+        // A CRC over the coded frame fails on the errors the outer code is there to correct
+        let damaged = channel.crc == CRC::None
+            || channel.crc_trailer == crate::rx::CrcTrailer::Flag
+            || self.accept.crc_failed;
+        if channel.reed_solomon.is_some() && !damaged {
+            return Err(Error::Framing(
+                "reed_solomon needs crc None, crc_trailer Flag or [accept] crc_failed",
+            ));
+        }
+        if channel.convolutional.is_some() && !damaged {
+            return Err(Error::Framing(
+                "convolutional needs crc None, crc_trailer Flag or [accept] crc_failed",
+            ));
+        }
+        if let Some(ref address) = self.address {
+            address.validate()?;
+        }
+        PacketConfig {
+            address: self.address.as_ref().map(AddressFilter::hardware),
+            length: channel.length,
+        }
+        .write(radio, channel)
+    }
+
+    /// Compares against a freshly loaded config, sorting the sections that differ by whether
+    /// they can be applied to a running radio.
+    pub fn diff(&self, new: &Config) -> Changes {
+        let mut changes = Changes::default();
+        let mut check = |name, changed, live| {
+            if changed {
+                if live {
+                    changes.applied.push(name);
+                } else {
+                    changes.reset.push(name);
+                }
             }
-            if let Some(val) = overwrite.PLLVCODIV {
-                radio.PLLVCODIV().write(val)?;
+        };
+        // The board and synthesizer need autoranging, which in turn needs the radio powered
+        // down and reconfigured from scratch.
+        check("board", self.board != new.board, false);
+        check("synth", self.synth != new.synth, false);
+        check("overwrite", self.overwrite != new.overwrite, false);
+        // The daemons set up their flush timer once at startup
+        check("fifo", self.fifo != new.fifo, false);
+        check("channel", self.channel != new.channel, true);
+        check("tx", self.tx != new.tx, true);
+        check("rx", self.rx != new.rx, true);
+        check("set0", self.set0 != new.set0, true);
+        check("set1", self.set1 != new.set1, true);
+        check("set2", self.set2 != new.set2, true);
+        check("set3", self.set3 != new.set3, true);
+        check("stages", self.stages != new.stages, true);
+        check("auth", self.auth != new.auth, true);
+        check("accept", self.accept != new.accept, true);
+        check("address", self.address != new.address, true);
+        check("filter", self.filter != new.filter, true);
+        check("control", self.control != new.control, true);
+        check("fallback", self.fallback != new.fallback, true);
+        check("thermal", self.thermal != new.thermal, true);
+        // The station bin sets up its scanner and the uhf bin its power sensor and PPS input
+        // once at startup
+        check("scan", self.scan != new.scan, false);
+        check("power_sensor", self.power_sensor != new.power_sensor, false);
+        check("pps", self.pps != new.pps, false);
+        changes
+    }
+
+    /// Applies the live-safe sections of `new` to the radio and adopts them. Sections listed
+    /// in Changes::reset are left as they were, both here and on the radio.
+    ///
+    /// The radio must be in POWEROFF with an empty FIFO, the caller restores PWRMODE after.
+    pub fn reload(&mut self, radio: &mut Registers, new: Config) -> Result<Changes> {
+        if new.channel.is_empty() {
+            return Err(Error::Invalid);
+        }
+        // Only applied after a reset, but refused now rather than then
+        new.fifo.validate()?;
+        let changes = self.diff(&new);
+        if changes.applied.is_empty() {
+            return Ok(changes);
+        }
+
+        self.channel = new.channel;
+        self.tx = new.tx;
+        self.rx = new.rx;
+        self.set0 = new.set0;
+        self.set1 = new.set1;
+        self.set2 = new.set2;
+        self.set3 = new.set3;
+        self.stages = new.stages;
+        self.auth = new.auth;
+        self.accept = new.accept;
+        self.address = new.address;
+        self.filter = new.filter;
+        self.control = new.control;
+        self.fallback = new.fallback;
+        self.thermal = new.thermal;
+
+        self.channel[0].write(radio, &self.board)?;
+        self.write_parameters(radio)?;
+        Ok(changes)
+    }
+
+    /// Swaps [[channel]] `index` in as channel 0 and writes it with the [tx] and [rx]
+    /// parameters that follow it, for changing the datarate of a running radio (see
+    /// fallback). The same call again swaps back. As with reload() the radio must be in
+    /// POWEROFF with an empty FIFO.
+    pub fn swap_channel(&mut self, radio: &mut Registers, index: usize) -> Result<()> {
+        if index == 0 || index >= self.channel.len() {
+            return Err(Error::NoChannel(index));
+        }
+        self.channel.swap(0, index);
+        self.channel[0].write(radio, &self.board)?;
+        self.write_parameters(radio)
+    }
+}
+
+/// An uplink and downlink that aren't symmetric: RX on one [[channel]] and TX on another, both
+/// on the config's board and synthesizer. Switching rewrites only what follows the channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Link {
+    pub rx: ChannelParameters,
+    pub tx: ChannelParameters,
+}
+
+impl Link {
+    /// `rx` and `tx` index config.channel, the same index for a symmetric link
+    pub fn new(config: &Config, rx: usize, tx: usize) -> Result<Self> {
+        let channel = |i| config.channel.get(i).copied().ok_or(Error::NoChannel(i));
+        Ok(Self {
+            rx: channel(rx)?,
+            tx: channel(tx)?,
+        })
+    }
+
+    /// Sets up the TX channel and [tx] for tx::transmit(). The radio has to be out of RX, see
+    /// rx::leave().
+    pub fn to_tx(&self, radio: &mut Registers, config: &Config) -> Result<()> {
+        let tx = config.tx.ok_or(Error::Section("tx"))?;
+        let channel = self.tx.write(radio, &config.board)?;
+        tx.write(radio, &config.board, &channel)?;
+        Ok(())
+    }
+
+    /// Back to the RX channel and the [rx] parameters for it, ready for rx::enter()
+    pub fn to_rx(&self, radio: &mut Registers, config: &Config) -> Result<()> {
+        let channel = self.rx.write(radio, &config.board)?;
+        config.write_rx_parameters(radio, &channel)
+    }
+}
+
+/// Result of comparing two configs, see Config::reload
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// Sections that were (or can be) written to the live radio
+    pub applied: Vec<&'static str>,
+    /// Sections that only take effect after a reset and full Config::write
+    pub reset: Vec<&'static str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Config {
+        toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap()
+    }
+
+    #[test]
+    fn diff_unchanged() {
+        assert_eq!(example().diff(&example()), Changes::default());
+    }
+
+    #[test]
+    fn diff_sorts_sections() {
+        let mut new = example();
+        new.channel[0].datarate += 1;
+        new.synth.freq_a += 1;
+        assert_eq!(
+            example().diff(&new),
+            Changes {
+                applied: vec!["channel"],
+                reset: vec!["synth"],
             }
-            if let Some(val) = overwrite.PERF_F35 {
-                radio.PERF_F35().write(val)?;
+        );
+    }
+
+    #[test]
+    fn fifo_policy() {
+        assert_eq!(example().fifo, crate::rx::FifoPolicy::NotEmpty);
+        let contents = include_str!("../examples/rpi-uhf-96000.toml").to_string()
+            + "\n[fifo.Threshold]\nthreshold = 131\nflush_ms = 20\n";
+        let new: Config = toml::from_str(&contents).unwrap();
+        assert_eq!(new.fifo.flush(), Some(std::time::Duration::from_millis(20)));
+        assert_eq!(
+            example().diff(&new),
+            Changes {
+                applied: vec![],
+                reset: vec!["fifo"],
             }
+        );
+
+        let contents = include_str!("../examples/rpi-uhf-96000.toml").to_string()
+            + "\n[fifo.Threshold]\nthreshold = 256\nflush_ms = 20\n";
+        let full: Config = toml::from_str(&contents).unwrap();
+        let mut config = example();
+        assert!(matches!(
+            crate::dry_run(|radio| config.reload(radio, full).map(drop)),
+            Err(Error::Fifo(_))
+        ));
+    }
+
+    #[test]
+    fn ppm_correction() {
+        let mut config = example();
+        let nominal = crate::dry_run(|radio| config.write(radio)).unwrap();
+        assert_eq!(config.board.xtal.corrected(), config.board.xtal.freq);
+
+        // A TCXO 2 ppm fast needs a proportionally smaller FREQA for the same carrier
+        config.board.xtal.ppm_correction = 2.0;
+        let trimmed = crate::dry_run(|radio| config.write(radio)).unwrap();
+        let freqa = |writes: &crate::Writes| {
+            let w = writes.to("FREQA")[0];
+            u32::from_be_bytes(w.data[..].try_into().unwrap())
+        };
+        let ratio = f64::from(freqa(&trimmed)) / f64::from(freqa(&nominal));
+        assert!((ratio - 1.0 / 1.000_002).abs() < 1e-8, "{}", ratio);
+        // IFFREQ steps are a few hundred ppm of the IF, it takes a big error to move it
+        config.board.xtal.ppm_correction = -500.0;
+        let off = crate::dry_run(|radio| config.write(radio)).unwrap();
+        assert_ne!(nominal.to("IFFREQ"), off.to("IFFREQ"));
+        // The nominal frequency in the config is left alone
+        assert_eq!(config.board.xtal.freq, example().board.xtal.freq);
+    }
+
+    #[test]
+    fn rx_bandwidth() {
+        let mut config: Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-60000.toml")).unwrap();
+        let write = |config: &Config| crate::dry_run(|radio| config.write(radio));
+        let nominal = write(&config).unwrap();
+        let set_bandwidth = |config: &mut Config, hz| {
+            let Some(RXParameters::MSK { bandwidth, .. }) = config.rx.as_mut() else {
+                unreachable!()
+            };
+            *bandwidth = Some(hz);
+        };
+
+        // Twice the nominal 90 kHz: 1.5 MHz baseband instead of 500 kHz, the IF follows
+        set_bandwidth(&mut config, 180_000);
+        let wide = write(&config).unwrap();
+        assert_eq!(nominal.to("DECIMATION")[0].data, [3]);
+        assert_eq!(wide.to("DECIMATION")[0].data, [1]);
+        assert_ne!(nominal.to("IFFREQ")[0].data, wide.to("IFFREQ")[0].data);
+        assert_ne!(
+            nominal.to("RXDATARATE")[0].data,
+            wide.to("RXDATARATE")[0].data
+        );
+
+        for hz in [500_000, 50_000] {
+            set_bandwidth(&mut config, hz);
+            assert!(matches!(write(&config), Err(Error::Bandwidth(b)) if b == hz));
         }
-        Ok(())
+    }
+
+    #[test]
+    fn asymmetric_link() {
+        let mut config: Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-60000.toml")).unwrap();
+        let mut uplink = config.channel[0];
+        uplink.datarate = 9600;
+        config.channel.push(uplink);
+        assert!(matches!(Link::new(&config, 0, 2), Err(Error::NoChannel(2))));
+
+        let link = Link::new(&config, 1, 0).unwrap();
+        let tx = crate::dry_run(|radio| link.to_tx(radio, &config)).unwrap();
+        let rx = crate::dry_run(|radio| link.to_rx(radio, &config)).unwrap();
+        assert!(tx.to("RXDATARATE").is_empty());
+        assert!(!tx.to("TXRATE").is_empty());
+        assert!(rx.to("TXRATE").is_empty());
+        assert!(!rx.to("PKTADDRCFG").is_empty());
+
+        // Each side matches a symmetric link on its own channel
+        let downlink = Link::new(&config, 0, 0).unwrap();
+        let up = Link::new(&config, 1, 1).unwrap();
+        let expected = crate::dry_run(|radio| downlink.to_tx(radio, &config)).unwrap();
+        assert_eq!(tx.to("TXRATE")[0].data, expected.to("TXRATE")[0].data);
+        let expected = crate::dry_run(|radio| up.to_rx(radio, &config)).unwrap();
+        assert_eq!(
+            rx.to("RXDATARATE")[0].data,
+            expected.to("RXDATARATE")[0].data
+        );
+        let other = crate::dry_run(|radio| downlink.to_rx(radio, &config)).unwrap();
+        assert_ne!(rx.to("RXDATARATE")[0].data, other.to("RXDATARATE")[0].data);
+
+        config.tx = None;
+        assert!(matches!(
+            link.to_tx(
+                &mut Registers::new(crate::Bus::Sink, &mut |_: &_, _, _, _: &_| {}),
+                &config
+            ),
+            Err(Error::Section("tx"))
+        ));
+    }
+
+    #[test]
+    fn swap_channel() {
+        let mut config: Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-60000.toml")).unwrap();
+        let primary = config.channel[0];
+        let fallback = ChannelParameters {
+            datarate: 9600,
+            ..primary
+        };
+        config.channel.push(fallback);
+        let before = crate::dry_run(|radio| config.swap_channel(radio, 0));
+        assert!(matches!(before, Err(Error::NoChannel(0))));
+
+        let slow = crate::dry_run(|radio| config.swap_channel(radio, 1)).unwrap();
+        assert_eq!(config.channel, [fallback, primary]);
+        let fast = crate::dry_run(|radio| config.swap_channel(radio, 1)).unwrap();
+        assert_eq!(config.channel, [primary, fallback]);
+        assert_ne!(slow.to("RXDATARATE")[0].data, fast.to("RXDATARATE")[0].data);
+        assert_ne!(slow.to("TXRATE")[0].data, fast.to("TXRATE")[0].data);
+        let expected = crate::dry_run(|radio| config.write(radio)).unwrap();
+        assert_eq!(
+            fast.to("RXDATARATE")[0].data,
+            expected.to("RXDATARATE")[0].data
+        );
+    }
+
+    #[test]
+    fn long_packets() {
+        let mut config = example();
+        let length = PacketLength::Dynamic {
+            pos: U4::new(0),
+            bits: U4::new(11),
+            offset: 0,
+            max: 1024,
+        };
+        config.channel[0].length = length;
+        // PKTMAXLEN can't say 1024, the radio's SIZEFAIL has to be let through
+        assert!(matches!(
+            crate::dry_run(|radio| config.write(radio)),
+            Err(Error::Length(_))
+        ));
+        config.accept.size_failed = true;
+        let writes = crate::dry_run(|radio| config.write(radio)).unwrap();
+        assert_eq!(writes.to("PKTLENCFG").last().unwrap().data, [0xB0]);
+        assert_eq!(writes.to("PKTMAXLEN").last().unwrap().data, [0xFF]);
+
+        let payload: Vec<u8> = (0..600).map(|i| i as u8).collect();
+        let framed = length.frame(&payload).unwrap();
+        assert_eq!(framed[..2], 602u16.to_be_bytes());
+        assert_eq!(length.payload(&framed), Some(payload.clone()));
+        assert_eq!(length.payload(&framed[..500]), None);
+        assert!(matches!(length.frame(&[0; 1023]), Err(Error::Length(_))));
+        let narrow = PacketLength::Dynamic {
+            pos: U4::new(1),
+            bits: U4::new(8),
+            offset: 0,
+            max: 1024,
+        };
+        assert!(matches!(narrow.validate(), Err(Error::Length(_))));
+
+        // Through the simulator, split into several chunks both ways
+        let (a, b) = crate::sim::pair(crate::sim::Channel::default());
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut tx = Registers::new(crate::Bus::Sim(a), &mut ca);
+        let mut rx = Registers::new(crate::Bus::Sim(b), &mut cb);
+        crate::rx::start(&mut rx).unwrap();
+        let channel = config.channel[0];
+        assert_eq!(channel.coded_len(payload.len()), framed.len());
+        crate::tx::transmit(&mut tx, &channel.encode(&payload).unwrap()).unwrap();
+        let mut assembler = crate::rx::PacketAssembler::new()
+            .accept(config.accept)
+            .max_len(length.max_len());
+        let received = assembler.drain(&mut rx).unwrap();
+        assert_eq!(received[0], framed);
+        assert_eq!(channel.decode(&received[0]).unwrap().0, payload);
+        assert!(matches!(
+            channel.decode(&received[0][..500]),
+            Err(Error::Length(_))
+        ));
+
+        crate::tx::transmit(&mut tx, &[0; 1100]).unwrap();
+        assert!(assembler.drain(&mut rx).unwrap().is_empty());
+        assert_eq!(assembler.stats().size_fail, 1);
+    }
+
+    #[test]
+    fn coding() {
+        let mut channel = example().channel[0];
+        assert_eq!(channel.encode(b"plain").unwrap(), &b"plain"[..]);
+
+        channel.reed_solomon = Some(Default::default());
+        channel.convolutional = Some(crate::convolutional::Convolutional { interleave: 16 });
+        let payload = [0xA5; 100];
+        let mut frame = channel.encode(&payload).unwrap().into_owned();
+        assert_eq!(frame.len(), channel.coded_len(payload.len()));
+        assert_eq!(frame.len(), 2 * (100 + 32) + 2);
+        frame[10] ^= 0xFF;
+        let (decoded, corrections) = channel.decode(&frame).unwrap();
+        assert_eq!(&decoded[..], &payload[..]);
+        assert_eq!(corrections.bits, 8);
+        assert_eq!(corrections.symbols, 0);
+
+        channel.convolutional = Some(crate::convolutional::Convolutional { interleave: 0 });
+        assert!(matches!(
+            channel.validate_framing(),
+            Err(Error::Convolutional(_))
+        ));
+
+        // The CRC would drop every frame the code could have corrected
+        let mut config = example();
+        config.channel[0].reed_solomon = Some(Default::default());
+        assert!(matches!(
+            crate::dry_run(|radio| config.write(radio)),
+            Err(Error::Framing(_))
+        ));
+        config.channel[0].crc = CRC::None;
+        assert!(crate::dry_run(|radio| config.write(radio)).is_ok());
+        config.channel[0].reed_solomon = None;
+        config.channel[0].crc = CRC::CCITT { initial: 0xFFFF };
+        config.channel[0].convolutional = Some(Default::default());
+        assert!(matches!(
+            crate::dry_run(|radio| config.write(radio)),
+            Err(Error::Framing(_))
+        ));
+        config.accept.crc_failed = true;
+        assert!(crate::dry_run(|radio| config.write(radio)).is_ok());
+    }
+
+    #[test]
+    fn address_filter() {
+        let callsign = 0x8A6E6896;
+        let filter = |addresses: &[u32], mask| AddressFilter {
+            pos: U4::new(1),
+            mask,
+            addresses: addresses.to_vec(),
+        };
+
+        let own = filter(&[callsign], u32::MAX);
+        assert_eq!(own.hardware().mask, u32::MAX);
+        assert_eq!(own.hardware().addr, callsign);
+        assert!(own.exact());
+
+        // Differing in one bit, the radio takes both exactly
+        let pair = filter(&[0x10, 0x11], 0xFF);
+        assert_eq!((pair.hardware().addr, pair.hardware().mask), (0x10, 0xFE));
+        assert!(pair.exact());
+        assert!(!filter(&[0x10, 0x13], 0xFF).exact());
+
+        // Broadcast shares few bits with the callsign, software has to finish the job
+        let both = filter(&[callsign, 0xFFFFFFFF], u32::MAX);
+        assert_eq!(both.hardware().mask, callsign);
+        assert!(!both.exact());
+        assert!(both.matches(&[0x00, 0x96, 0x68, 0x6E, 0x8A, 0x60]));
+        assert!(both.matches(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF]));
+        assert!(!both.matches(&[0x00, 0x96, 0x68, 0x6E, 0x8B]));
+        assert!(!both.matches(&[0x00, 0x96, 0x68]));
+        // Only the masked bytes need to be there
+        assert!(filter(&[0x96], 0xFF).matches(&[0x00, 0x96]));
+
+        assert!(matches!(
+            filter(&[], u32::MAX).validate(),
+            Err(Error::Address(_))
+        ));
+        assert!(matches!(
+            filter(&[callsign], 0).validate(),
+            Err(Error::Address(_))
+        ));
+
+        let contents = include_str!("../examples/rpi-uhf-96000.toml").to_string()
+            + "\n[address]\npos = 1\naddresses = [0x8A6E6896, 0xFFFFFFFF]\n";
+        let config: Config = toml::from_str(&contents).unwrap();
+        assert_eq!(config.address, Some(both));
+        let writes = crate::dry_run(|radio| config.write(radio)).unwrap();
+        assert_eq!(
+            writes.to("PKTADDR").last().unwrap().data,
+            callsign.to_be_bytes()
+        );
+        assert_eq!(
+            writes.to("PKTADDRMASK").last().unwrap().data,
+            callsign.to_be_bytes()
+        );
+        assert_eq!(
+            example().diff(&config),
+            Changes {
+                applied: vec!["address"],
+                reset: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn framing_switch() {
+        let hdlc = example().channel[0];
+        let raw = hdlc.with_framing(Framing::Raw).unwrap();
+        assert_eq!(raw.crc, CRC::None);
+        let writes = crate::dry_run(|radio| raw.write_framing(radio, None)).unwrap();
+        assert_eq!(
+            writes.names(),
+            [
+                "FRAMING",
+                "CRCINIT",
+                "PKTADDRCFG",
+                "PKTADDR",
+                "PKTADDRMASK",
+                "PKTLENCFG",
+                "PKTLENOFFSET",
+                "PKTMAXLEN"
+            ]
+        );
+        let framing =
+            crate::Framing::try_from(Reg8::from(writes.to("FRAMING")[0].data[0])).unwrap();
+        assert_eq!(
+            (framing.frmmode, framing.crcmode),
+            (FrameMode::RAW, CRCMode::OFF)
+        );
+
+        // Back to packets gets a CRC again
+        assert_eq!(
+            raw.with_framing(hdlc.framing).unwrap().crc,
+            CRC::CCITT { initial: 0xFFFF }
+        );
+        assert!(matches!(
+            hdlc.with_framing(Framing::MBus),
+            Err(Error::Framing(_))
+        ));
+        let ook =
+            toml::from_str::<Config>(include_str!("../examples/c3-uhf-carrier.toml")).unwrap();
+        assert!(matches!(
+            ook.channel[0].with_framing(Framing::RawSoft),
+            Err(Error::Framing(_))
+        ));
+        let crc = ChannelParameters {
+            crc: CRC::CRC32 { initial: 0 },
+            ..raw
+        };
+        assert!(matches!(
+            crc.write_framing(
+                &mut Registers::new(crate::Bus::Sink, &mut |_: &_, _, _, _: &_| {}),
+                None
+            ),
+            Err(Error::Framing(_))
+        ));
+    }
+
+    #[test]
+    fn display() {
+        let config: Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-60000.toml")).unwrap();
+        assert_eq!(
+            config.channel[0].to_string(),
+            "60000 bit/s GMSK BT 0.5, NRZISCR, HDLC, CCITT CRC, MSB first"
+        );
+        assert_eq!(
+            config.synth.to_string(),
+            "A 436.500000 MHz (active), B 0.000000 MHz"
+        );
+        let mut xtal = config.board.xtal;
+        assert_eq!(xtal.to_string(), "TCXO 48.000000 MHz");
+        xtal.ppm_correction = -1.5;
+        assert_eq!(xtal.to_string(), "TCXO 48.000000 MHz -1.50 ppm");
+    }
+
+    #[test]
+    fn modem_wiring() {
+        let mut board = example().board;
+        board.dclk.invert = true;
+        board.wiring = Some(ModemWiring::SyncModem);
+        let writes = crate::dry_run(|radio| board.write(radio).map(|_| ())).unwrap();
+        let dclk = PFDClk::try_from(Reg8::from(writes.to("PINFUNCDCLK")[0].data[0])).unwrap();
+        assert_eq!(dclk.mode, PFDClkMode::OUT);
+        assert!(dclk.flags.contains(PFFlags::INVERT));
+        let data = PFData::try_from(Reg8::from(writes.to("PINFUNCDATA")[0].data[0])).unwrap();
+        assert_eq!(data.mode, PFDataMode::MODEM_IO);
+
+        for wiring in [
+            ModemWiring::SyncModem,
+            ModemWiring::SyncFrame,
+            ModemWiring::ExternalClock,
+            ModemWiring::Demodulator,
+            ModemWiring::Async,
+        ] {
+            let (dclk, data) = wiring.modes();
+            assert!(check_pins(dclk, data).is_ok(), "{:?}", wiring);
+        }
+        assert!(check_pins(DClk::Z, Data::Z).is_ok());
+        assert!(check_pins(DClk::Z, Data::ModemIO).is_err());
+        assert!(check_pins(DClk::In, Data::ModemOut).is_err());
+        assert!(check_pins(DClk::Out, Data::AsyncIO).is_err());
+        assert!(check_pins(DClk::In, Data::Z).is_err());
+
+        // A hand set mode that disagrees with the wiring
+        board.data.mode = Data::AsyncIO;
+        assert!(matches!(board.pins(), Err(Error::Pins(_))));
+
+        // Diversity drives ANTSEL itself
+        let mut board = example().board;
+        board.diversity = true;
+        assert!(matches!(board.pins(), Err(Error::Pins(_))));
+        board.antsel.mode = AntSel::AntSel;
+        let writes = crate::dry_run(|radio| board.write(radio).map(|_| ())).unwrap();
+        assert_eq!(writes.to("DIVERSITY")[0].data, [Diversity::DIVENA.bits()]);
+    }
+
+    #[test]
+    fn amplitude_shaping() {
+        let ook: Config = toml::from_str(&include_str!("../examples/c3-uhf-carrier.toml").replace(
+            "amp.RaisedCosine = { a = 0, b = 0x700, c = 0, d = 0, e = 0 }",
+            "amp.Hard = { b = 0x700 }\nramp = \"Bits4\"",
+        ))
+        .unwrap();
+        let writes = crate::dry_run(|radio| ook.write(radio)).unwrap();
+        let cfga = writes.iter().rfind(|w| w.name == "MODCFGA").unwrap();
+        let cfga = ModCfgA::try_from(Reg8::from(cfga.data[0])).unwrap();
+        assert_eq!(cfga.slowramp, crate::SlowRamp::STARTUP_4b);
+        assert!(!cfga.flags.contains(ModCfgAFlags::AMPLSHAPE));
+
+        // The FSK family has its ramp in the modulation
+        let channel = &example().channel[0];
+        let mut tx = ook.tx.unwrap();
+        assert!(matches!(tx.validate(channel), Err(Error::Shaping(_))));
+        tx.ramp = None;
+        assert!(tx.validate(channel).is_ok());
+        tx.amp = AmplitudeShaping::Linear { b: 0 };
+        assert!(matches!(tx.validate(channel), Err(Error::Shaping(_))));
+
+        // Configs from before Hard still load
+        let old: AmplitudeShaping = toml::from_str::<toml::Table>("amp.None = { b = 1 }").unwrap()
+            ["amp"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(old, AmplitudeShaping::Hard { b: 1 });
+    }
+
+    #[test]
+    fn dry_run_writes() {
+        let config = example();
+        let writes = crate::dry_run(|radio| config.write(radio)).unwrap();
+        assert!(writes.iter().all(|w| w.name != "?"));
+        let freqa = writes.iter().find(|w| w.name == "FREQA").unwrap();
+        assert_eq!(freqa.addr, 0x034);
+        // Autoranging got past its polling loops
+        assert!(writes.iter().any(|w| w.name == "PLLRANGINGA"));
+    }
+
+    /// The register file after configure(): the last value written to each register, in
+    /// address order, formatted like RegisterWrite
+    fn configured(preset: &str) -> String {
+        let config: Config = toml::from_str(preset).unwrap();
+        let writes = crate::dry_run(|radio| config.configure(radio)).unwrap();
+        writes.last().values().map(|w| format!("{}\n", w)).collect()
+    }
+
+    /// Comments and blank lines dropped, the rest compared as is
+    fn golden(dump: &str) -> String {
+        dump.lines()
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| format!("{}\n", l))
+            .collect()
+    }
+
+    #[test]
+    fn golden_uhf() {
+        assert_eq!(
+            configured(include_str!("bin/c3-uhf-96000.toml")),
+            golden(include_str!("bin/c3-uhf-96000.regs"))
+        );
+    }
+
+    #[test]
+    fn golden_uhf_order() {
+        let config: Config = toml::from_str(include_str!("bin/c3-uhf-96000.toml")).unwrap();
+        let writes = crate::dry_run(|radio| config.configure(radio)).unwrap();
+        assert_eq!(
+            writes.to_string(),
+            golden(include_str!("bin/c3-uhf-96000.writes"))
+        );
+    }
+
+    #[test]
+    fn set_order() {
+        let config: Config = toml::from_str(include_str!("bin/c3-uhf-96000.toml")).unwrap();
+        let writes = crate::dry_run(|radio| config.write(radio)).unwrap();
+        // Ranging before the parameter sets, and each set in its own order
+        assert!(writes.in_order(&["PLLRANGINGA", "AGCGAIN0", "TIMEGAIN0", "AMPLGAIN0"]));
+        assert!(!writes.in_order(&["AGCGAIN0", "PLLRANGINGA"]));
+        assert_eq!(writes.to("AGCGAIN0").len(), 1);
+        assert_eq!(writes.first_difference(&writes), None);
+
+        let mut changed: Config = toml::from_str(include_str!("bin/c3-uhf-96000.toml")).unwrap();
+        changed.set0.as_mut().unwrap().decay = U4::new(0x7);
+        let other = crate::dry_run(|radio| changed.write(radio)).unwrap();
+        let (i, a, b) = writes.first_difference(&other).unwrap();
+        assert_eq!(a.map(|w| w.name), b.map(|w| w.name));
+        assert_ne!(a, b);
+        assert_eq!(writes.names()[..i], other.names()[..i]);
+    }
+
+    #[test]
+    fn golden_lband() {
+        assert_eq!(
+            configured(include_str!("bin/c3-lband-60000.toml")),
+            golden(include_str!("bin/c3-lband-60000.regs"))
+        );
+    }
+
+    #[test]
+    fn radiolab_agc() {
+        let agc = RXParameterAGC::radiolab();
+        let gain = Reg8::from(AGCGain {
+            attack: agc.attack,
+            decay: agc.decay,
+        });
+        assert_eq!(gain[0], 0x93);
+        // Out of range fields are rejected when the config is parsed
+        let mut set: toml::Table = toml::from_str(
+            r#"
+            agc = "Automatic"
+            decay = 6
+            baseband_offset = { a = 0, b = 0 }
+            [gain]
+            time_corr_frac = 4
+            datarate_corr_frac = 255
+            phase = 3
+            filter = 3
+            amplitude = 6
+            deviation_update = true
+            ampl_agc_jump_correction = false
+            ampl_averaging = false
+            "#,
+        )
+        .unwrap();
+        assert!(set.clone().try_into::<RXParameterSet>().is_ok());
+        set.insert("decay".into(), 0x16.into());
+        assert!(set.try_into::<RXParameterSet>().is_err());
     }
 }
@@ -0,0 +1,526 @@
+// Operator commands for the running bins, sent as one line of text per UDP datagram:
+//
+//   `socat STDIO UDP:localhost:10017`
+//   freq 437000000
+//   log info,ax5043::spi=trace
+//   reload
+//   tx closed
+//   inhibit on
+//   write RSSIREFERENCE 40
+//   test 64
+//   pattern pn9 2
+//   beacon off
+//   squelch -90
+//   agc target 140 max 5
+//   framing raw
+//
+// Kept separate from the bins so uhf and lband agree on the syntax. The tui's console sends the
+// commands that pass Command::remote() over the telemetry socket, `write` only reaches the
+// registers in Tunable.
+//
+// The control socket only listens on localhost unless --control-listen says otherwise. With a
+// [control] section in the config, commands on either socket have to be sealed with its key,
+// see auth::ControlAuth and examples/control.rs.
+use crate::{
+    agc::Tuning,
+    config::{Framing, Hz, FEC},
+    schedule::Mode,
+    tx::Pattern,
+    Registers, Result as RadioResult, RX, TX,
+};
+use std::{fmt, str::FromStr, time::Duration};
+use thiserror::Error;
+
+/// Registers that can be changed while the radio is running, plain numbers that only affect
+/// RX gain and thresholds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tunable {
+    RSSIREFERENCE,
+    RSSIABSTHR,
+    BGNDRSSIGAIN,
+    BGNDRSSITHR,
+    /// AGCTARGET0..3, one per RX parameter set
+    AGCTARGET(u8),
+}
+
+impl Tunable {
+    fn range(self) -> (i64, i64) {
+        match self {
+            Tunable::RSSIREFERENCE | Tunable::RSSIABSTHR => (i8::MIN.into(), i8::MAX.into()),
+            _ => (u8::MIN.into(), u8::MAX.into()),
+        }
+    }
+
+    pub fn read(self, radio: &mut Registers) -> RadioResult<i64> {
+        Ok(match self {
+            Tunable::RSSIREFERENCE => radio.RSSIREFERENCE().read()?.into(),
+            Tunable::RSSIABSTHR => radio.RSSIABSTHR().read()?.into(),
+            Tunable::BGNDRSSIGAIN => radio.BGNDRSSIGAIN().read()?.into(),
+            Tunable::BGNDRSSITHR => radio.BGNDRSSITHR().read()?.into(),
+            Tunable::AGCTARGET(0) => radio.AGCTARGET0().read()?.into(),
+            Tunable::AGCTARGET(1) => radio.AGCTARGET1().read()?.into(),
+            Tunable::AGCTARGET(2) => radio.AGCTARGET2().read()?.into(),
+            Tunable::AGCTARGET(_) => radio.AGCTARGET3().read()?.into(),
+        })
+    }
+
+    /// `value` has already been range checked by Command::from_str
+    pub fn write(self, radio: &mut Registers, value: i64) -> RadioResult<()> {
+        let signed = || i8::try_from(value).map_err(|_| crate::Error::Invalid);
+        let unsigned = || u8::try_from(value).map_err(|_| crate::Error::Invalid);
+        match self {
+            Tunable::RSSIREFERENCE => radio.RSSIREFERENCE().write(signed()?),
+            Tunable::RSSIABSTHR => radio.RSSIABSTHR().write(signed()?),
+            Tunable::BGNDRSSIGAIN => radio.BGNDRSSIGAIN().write(unsigned()?),
+            Tunable::BGNDRSSITHR => radio.BGNDRSSITHR().write(unsigned()?),
+            Tunable::AGCTARGET(0) => radio.AGCTARGET0().write(unsigned()?),
+            Tunable::AGCTARGET(1) => radio.AGCTARGET1().write(unsigned()?),
+            Tunable::AGCTARGET(2) => radio.AGCTARGET2().write(unsigned()?),
+            Tunable::AGCTARGET(_) => radio.AGCTARGET3().write(unsigned()?),
+        }
+    }
+}
+
+impl FromStr for Tunable {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_uppercase().as_str() {
+            "RSSIREFERENCE" => Tunable::RSSIREFERENCE,
+            "RSSIABSTHR" => Tunable::RSSIABSTHR,
+            "BGNDRSSIGAIN" => Tunable::BGNDRSSIGAIN,
+            "BGNDRSSITHR" => Tunable::BGNDRSSITHR,
+            "AGCTARGET0" => Tunable::AGCTARGET(0),
+            "AGCTARGET1" => Tunable::AGCTARGET(1),
+            "AGCTARGET2" => Tunable::AGCTARGET(2),
+            "AGCTARGET3" => Tunable::AGCTARGET(3),
+            _ => return Err(ParseError::NotTunable(s.into())),
+        })
+    }
+}
+
+impl fmt::Display for Tunable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tunable::AGCTARGET(set) => write!(f, "AGCTARGET{}", set),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Retune FREQA to the given carrier
+    Frequency(Hz),
+    /// Replace the log filter, see ax5043::logging
+    Log(String),
+    /// Re-read the config file, see config::Config::reload
+    Reload,
+    /// Force the transmit gate open or closed, or back to the schedule, see ax5043::schedule.
+    /// Back to the schedule is refused by a daemon started without one.
+    Transmit(Mode),
+    /// Hold transmit off, or release the operator's hold, see schedule::Inhibit. Applied as soon
+    /// as it arrives, a frame on the air is cut off.
+    Inhibit(bool),
+    /// Write one of the allowed registers, see Tunable
+    Write(Tunable, i64),
+    /// Send a tx::test_frame of the given length, still subject to the transmit gate
+    Test(usize),
+    /// Key up on a test pattern for this long, queued and gated like Test, see tx::Pattern
+    Pattern(Pattern, Duration),
+    /// Turn beacon forwarding on or off, frames arriving while off are dropped
+    Beacon(bool),
+    /// Drop packets below this RSSI (dBm), None to turn it off, see rx::PacketAssembler
+    Squelch(Option<f64>),
+    /// Change the AGC of the RX parameter sets in use, see agc::Tuning
+    Agc(Tuning),
+    /// Switch the default channel's framing, see config::ChannelParameters::with_framing
+    Framing(Framing),
+}
+
+/// Test frames with no length given
+pub const TEST_LEN: usize = 32;
+/// Longest test frame, what fits in one downlink datagram
+pub const TEST_MAX: usize = 2048;
+/// Test patterns with no length given
+pub const PATTERN_TIME: Duration = Duration::from_secs(1);
+/// Longest test pattern
+pub const PATTERN_MAX: Duration = Duration::from_secs(10);
+
+impl Command {
+    /// Accepted from the tui over the telemetry socket. The rest change the daemon itself and
+    /// stay on the local control socket.
+    pub fn remote(&self) -> bool {
+        matches!(
+            self,
+            Command::Write(..)
+                | Command::Test(_)
+                | Command::Pattern(..)
+                | Command::Beacon(_)
+                | Command::Squelch(_)
+                | Command::Agc(_)
+                | Command::Framing(_)
+        )
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("Empty command")]
+    Empty,
+    #[error("Unknown command: {0}")]
+    Unknown(String),
+    #[error("Missing argument for {0}")]
+    Missing(&'static str),
+    #[error("Invalid argument: {0}")]
+    Invalid(String),
+    #[error("Unexpected argument: {0}")]
+    Extra(String),
+    #[error("Register {0} can't be written while running")]
+    NotTunable(String),
+}
+
+impl FromStr for Command {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let cmd = match words.next().ok_or(ParseError::Empty)? {
+            "freq" => {
+                let arg = words.next().ok_or(ParseError::Missing("freq"))?;
+                Command::Frequency(arg.parse().map_err(|_| ParseError::Invalid(arg.into()))?)
+            }
+            "log" => Command::Log(words.next().ok_or(ParseError::Missing("log"))?.into()),
+            "reload" => Command::Reload,
+            "tx" => match words.next().ok_or(ParseError::Missing("tx"))? {
+                "open" => Command::Transmit(Mode::Open),
+                "closed" => Command::Transmit(Mode::Closed),
+                "schedule" => Command::Transmit(Mode::Scheduled),
+                other => return Err(ParseError::Invalid(other.into())),
+            },
+            "inhibit" => match words.next().ok_or(ParseError::Missing("inhibit"))? {
+                "on" => Command::Inhibit(true),
+                "off" => Command::Inhibit(false),
+                other => return Err(ParseError::Invalid(other.into())),
+            },
+            "write" => {
+                let reg: Tunable = words.next().ok_or(ParseError::Missing("write"))?.parse()?;
+                let arg = words.next().ok_or(ParseError::Missing("write"))?;
+                let (min, max) = reg.range();
+                match arg.parse() {
+                    Ok(value) if (min..=max).contains(&value) => Command::Write(reg, value),
+                    _ => return Err(ParseError::Invalid(arg.into())),
+                }
+            }
+            "test" => match words.next() {
+                None => Command::Test(TEST_LEN),
+                Some(arg) => match arg.parse() {
+                    Ok(len) if (1..=TEST_MAX).contains(&len) => Command::Test(len),
+                    _ => return Err(ParseError::Invalid(arg.into())),
+                },
+            },
+            "pattern" => {
+                let arg = words.next().ok_or(ParseError::Missing("pattern"))?;
+                let pattern = arg.parse().map_err(|_| ParseError::Invalid(arg.into()))?;
+                let time = match words.next() {
+                    None => PATTERN_TIME,
+                    Some(arg) => match arg.parse().map(Duration::try_from_secs_f64) {
+                        Ok(Ok(time)) if !time.is_zero() && time <= PATTERN_MAX => time,
+                        _ => return Err(ParseError::Invalid(arg.into())),
+                    },
+                };
+                Command::Pattern(pattern, time)
+            }
+            "beacon" => match words.next().ok_or(ParseError::Missing("beacon"))? {
+                "on" => Command::Beacon(true),
+                "off" => Command::Beacon(false),
+                other => return Err(ParseError::Invalid(other.into())),
+            },
+            "squelch" => match words.next().ok_or(ParseError::Missing("squelch"))? {
+                "off" => Command::Squelch(None),
+                arg => Command::Squelch(Some(
+                    arg.parse::<f64>()
+                        .ok()
+                        .filter(|dbm| dbm.is_finite())
+                        .ok_or_else(|| ParseError::Invalid(arg.into()))?,
+                )),
+            },
+            "agc" => {
+                let mut tuning = Tuning::default();
+                while let Some(field) = words.next() {
+                    let arg = words.next().ok_or(ParseError::Missing("agc"))?;
+                    let invalid = || ParseError::Invalid(arg.into());
+                    let bits = || arg.parse::<u8>().ok().and_then(|v| v.try_into().ok());
+                    match field {
+                        "target" => tuning.target = Some(arg.parse().map_err(|_| invalid())?),
+                        "hyst" => tuning.hyst = Some(bits().ok_or_else(invalid)?),
+                        "min" => tuning.min = Some(bits().ok_or_else(invalid)?),
+                        "max" => tuning.max = Some(bits().ok_or_else(invalid)?),
+                        other => return Err(ParseError::Invalid(other.into())),
+                    }
+                }
+                if tuning.is_empty() {
+                    return Err(ParseError::Missing("agc"));
+                }
+                Command::Agc(tuning)
+            }
+            "framing" => match words.next().ok_or(ParseError::Missing("framing"))? {
+                "hdlc" => Command::Framing(Framing::HDLC { fec: FEC {} }),
+                "raw" => Command::Framing(Framing::Raw),
+                other => return Err(ParseError::Invalid(other.into())),
+            },
+            other => return Err(ParseError::Unknown(other.into())),
+        };
+        if let Some(extra) = words.next() {
+            return Err(ParseError::Extra(extra.into()));
+        }
+        Ok(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_freq() {
+        assert_eq!(
+            "freq 437000000\n".parse(),
+            Ok(Command::Frequency(437_000_000))
+        );
+        assert_eq!("  freq   1\t".parse(), Ok(Command::Frequency(1)));
+    }
+
+    #[test]
+    fn parse_log() {
+        assert_eq!(
+            "log info,ax5043::fifo=debug".parse(),
+            Ok(Command::Log("info,ax5043::fifo=debug".into()))
+        );
+        assert_eq!("log".parse::<Command>(), Err(ParseError::Missing("log")));
+    }
+
+    #[test]
+    fn parse_reload() {
+        assert_eq!("reload\n".parse(), Ok(Command::Reload));
+        assert_eq!(
+            "reload now".parse::<Command>(),
+            Err(ParseError::Extra("now".into()))
+        );
+    }
+
+    #[test]
+    fn parse_tx() {
+        assert_eq!("tx closed".parse(), Ok(Command::Transmit(Mode::Closed)));
+        assert_eq!(
+            "tx schedule".parse(),
+            Ok(Command::Transmit(Mode::Scheduled))
+        );
+        assert_eq!("tx".parse::<Command>(), Err(ParseError::Missing("tx")));
+        assert_eq!(
+            "tx on".parse::<Command>(),
+            Err(ParseError::Invalid("on".into()))
+        );
+        assert_eq!("inhibit on".parse(), Ok(Command::Inhibit(true)));
+        assert_eq!("inhibit off".parse(), Ok(Command::Inhibit(false)));
+        assert_eq!(
+            "inhibit".parse::<Command>(),
+            Err(ParseError::Missing("inhibit"))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("".parse::<Command>(), Err(ParseError::Empty));
+        assert_eq!("freq".parse::<Command>(), Err(ParseError::Missing("freq")));
+        assert_eq!(
+            "freq 437M".parse::<Command>(),
+            Err(ParseError::Invalid("437M".into()))
+        );
+        assert_eq!(
+            "freq 1 2".parse::<Command>(),
+            Err(ParseError::Extra("2".into()))
+        );
+        assert_eq!(
+            "power 1".parse::<Command>(),
+            Err(ParseError::Unknown("power".into()))
+        );
+    }
+
+    #[test]
+    fn parse_write() {
+        assert_eq!(
+            "write RSSIREFERENCE -8".parse(),
+            Ok(Command::Write(Tunable::RSSIREFERENCE, -8))
+        );
+        assert_eq!(
+            "write agctarget3 137".parse(),
+            Ok(Command::Write(Tunable::AGCTARGET(3), 137))
+        );
+        assert_eq!(
+            "write PWRMODE 0".parse::<Command>(),
+            Err(ParseError::NotTunable("PWRMODE".into()))
+        );
+        assert_eq!(
+            "write RSSIABSTHR 200".parse::<Command>(),
+            Err(ParseError::Invalid("200".into()))
+        );
+        assert_eq!(
+            "write AGCTARGET0 -1".parse::<Command>(),
+            Err(ParseError::Invalid("-1".into()))
+        );
+        assert_eq!(
+            "write RSSIREFERENCE".parse::<Command>(),
+            Err(ParseError::Missing("write"))
+        );
+        assert_eq!(Tunable::AGCTARGET(2).to_string(), "AGCTARGET2");
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!("test".parse(), Ok(Command::Test(TEST_LEN)));
+        assert_eq!("test 200".parse(), Ok(Command::Test(200)));
+        assert_eq!(
+            "test 0".parse::<Command>(),
+            Err(ParseError::Invalid("0".into()))
+        );
+        assert_eq!(
+            "test 4096".parse::<Command>(),
+            Err(ParseError::Invalid("4096".into()))
+        );
+    }
+
+    #[test]
+    fn parse_pattern() {
+        assert_eq!(
+            "pattern pn9".parse(),
+            Ok(Command::Pattern(Pattern::PN9, PATTERN_TIME))
+        );
+        assert_eq!(
+            "pattern Carrier 2.5".parse(),
+            Ok(Command::Pattern(
+                Pattern::Carrier,
+                Duration::from_millis(2500)
+            ))
+        );
+        assert_eq!(
+            "pattern".parse::<Command>(),
+            Err(ParseError::Missing("pattern"))
+        );
+        assert_eq!(
+            "pattern noise".parse::<Command>(),
+            Err(ParseError::Invalid("noise".into()))
+        );
+        for time in ["0", "-1", "11", "NaN"] {
+            assert_eq!(
+                format!("pattern pn9 {}", time).parse::<Command>(),
+                Err(ParseError::Invalid(time.into()))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_beacon_squelch() {
+        assert_eq!("beacon off".parse(), Ok(Command::Beacon(false)));
+        assert_eq!("beacon on".parse(), Ok(Command::Beacon(true)));
+        assert_eq!(
+            "beacon".parse::<Command>(),
+            Err(ParseError::Missing("beacon"))
+        );
+        assert_eq!("squelch -90".parse(), Ok(Command::Squelch(Some(-90.0))));
+        assert_eq!("squelch -112.5".parse(), Ok(Command::Squelch(Some(-112.5))));
+        assert_eq!("squelch off".parse(), Ok(Command::Squelch(None)));
+        assert_eq!(
+            "squelch loud".parse::<Command>(),
+            Err(ParseError::Invalid("loud".into()))
+        );
+        assert_eq!(
+            "squelch NaN".parse::<Command>(),
+            Err(ParseError::Invalid("NaN".into()))
+        );
+    }
+
+    #[test]
+    fn parse_agc() {
+        assert_eq!(
+            "agc target 140 max 5".parse(),
+            Ok(Command::Agc(Tuning {
+                target: Some(140),
+                max: Some(crate::registers::U3::new(5)),
+                ..Tuning::default()
+            }))
+        );
+        assert_eq!("agc".parse::<Command>(), Err(ParseError::Missing("agc")));
+        assert_eq!(
+            "agc hyst".parse::<Command>(),
+            Err(ParseError::Missing("agc"))
+        );
+        assert_eq!(
+            "agc min 8".parse::<Command>(),
+            Err(ParseError::Invalid("8".into()))
+        );
+        assert_eq!(
+            "agc gain 1".parse::<Command>(),
+            Err(ParseError::Invalid("gain".into()))
+        );
+    }
+
+    #[test]
+    fn parse_framing() {
+        assert_eq!("framing raw".parse(), Ok(Command::Framing(Framing::Raw)));
+        assert_eq!(
+            "framing hdlc".parse(),
+            Ok(Command::Framing(Framing::HDLC { fec: FEC {} }))
+        );
+        assert_eq!(
+            "framing".parse::<Command>(),
+            Err(ParseError::Missing("framing"))
+        );
+        assert_eq!(
+            "framing mbus".parse::<Command>(),
+            Err(ParseError::Invalid("mbus".into()))
+        );
+    }
+
+    #[test]
+    fn remote() {
+        for (line, remote) in [
+            ("write RSSIREFERENCE 1", true),
+            ("test", true),
+            ("pattern pn9", true),
+            ("beacon on", true),
+            ("squelch off", true),
+            ("agc target 140", true),
+            ("framing hdlc", true),
+            ("freq 437000000", false),
+            ("log debug", false),
+            ("reload", false),
+            ("tx open", false),
+            ("inhibit off", false),
+        ] {
+            assert_eq!(
+                line.parse::<Command>().unwrap().remote(),
+                remote,
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn tunable_round_trip() {
+        let writes = crate::dry_run(|radio| {
+            Tunable::RSSIREFERENCE.write(radio, -8)?;
+            Tunable::AGCTARGET(1).write(radio, 137)
+        })
+        .unwrap();
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes[0].name, "RSSIREFERENCE");
+        assert_eq!(writes[0].data, vec![0xF8]);
+        assert_eq!(writes[1].name, "AGCTARGET1");
+
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(crate::Bus::Sink, &mut callback);
+        assert_eq!(Tunable::RSSIABSTHR.read(&mut radio).unwrap(), 0);
+    }
+}
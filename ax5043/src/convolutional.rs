@@ -0,0 +1,238 @@
+// Rate 1/2, K=7 convolutional coding with a Viterbi decoder and bit interleaver, in software.
+//
+// For channels where the radio's own HDLC FEC doesn't fit: raw or non-HDLC framing, or a far
+// end that speaks the CCSDS code. Generators are 171 and 133 octal with the second output
+// inverted as in CCSDS 131.0-B, the encoder is flushed with six zero bits so the decoder ends
+// in a known state. Decoding is hard decision, the radio only hands over bytes.
+//
+//   [[channel]]
+//   ...
+//   convolutional = { interleave = 16 }
+//
+// A frame of n bytes goes out as 2n + 2: two symbols a bit, twelve more for the flush and four
+// bits of padding. The interleaver spreads the coded bits `interleave` apart so a burst the
+// length of a byte or two comes out of the deinterleaver as isolated bit errors, which Viterbi
+// handles far better. `datarate` stays the rate on the air, ChannelParameters::coded_len() is
+// what a payload takes on it. See also reedsolomon, which goes inside this.
+//
+// The radio's CRC covers the coded frame, so a single bit error the decoder would fix fails it.
+// Set `crc` to None, `crc_trailer` to Flag or [accept] `crc_failed` so the decoder gets to see
+// damaged frames, the config is refused otherwise.
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Constraint length
+pub const K: usize = 7;
+const TAIL: usize = K - 1;
+const STATES: usize = 1 << TAIL;
+/// Taps with the newest bit at the top, 171 and 133 octal
+const G1: u8 = 0o171;
+const G2: u8 = 0o133;
+
+/// The two symbols for register contents `reg`, the newest bit at bit 6
+fn symbols(reg: u8) -> (u8, u8) {
+    let parity = |taps: u8| ((reg & taps).count_ones() & 1) as u8;
+    (parity(G1), parity(G2) ^ 1)
+}
+
+fn bits(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|b| (0..8).rev().map(move |i| b >> i & 1))
+        .collect()
+}
+
+fn bytes(bits: &[u8]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .chain(std::iter::repeat(&0))
+                .take(8)
+                .fold(0, |acc, b| acc << 1 | b)
+        })
+        .collect()
+}
+
+/// Coded bits for `data`, flushed
+fn encode_bits(data: &[u8]) -> Vec<u8> {
+    let mut state = 0u8;
+    let mut coded = Vec::with_capacity(2 * (data.len() + TAIL));
+    for &bit in data.iter().chain(&[0; TAIL]) {
+        let reg = bit << TAIL | state;
+        let (a, b) = symbols(reg);
+        coded.extend([a, b]);
+        state = reg >> 1;
+    }
+    coded
+}
+
+/// The most likely input for hard decision `coded` symbols, which end flushed
+fn viterbi(coded: &[u8]) -> Vec<u8> {
+    let steps = coded.len() / 2;
+    let mut metrics = [u32::MAX; STATES];
+    metrics[0] = 0;
+    // Bit s of decisions[t] is the dropped bit on the best path into state s
+    let mut decisions = Vec::with_capacity(steps);
+    for pair in coded.chunks_exact(2) {
+        let mut next = [u32::MAX; STATES];
+        let mut decided = 0u64;
+        for (state, metric) in next.iter_mut().enumerate() {
+            let bit = (state >> (TAIL - 1)) as u8;
+            for dropped in 0..2 {
+                let previous = (state << 1) & (STATES - 1) | dropped;
+                if metrics[previous] == u32::MAX {
+                    continue;
+                }
+                let (a, b) = symbols(bit << TAIL | previous as u8);
+                let distance = u32::from(a ^ pair[0]) + u32::from(b ^ pair[1]);
+                let candidate = metrics[previous] + distance;
+                if candidate < *metric {
+                    *metric = candidate;
+                    decided = decided & !(1 << state) | (dropped as u64) << state;
+                }
+            }
+        }
+        metrics = next;
+        decisions.push(decided);
+    }
+
+    let mut state = 0;
+    let mut decoded = vec![0; steps];
+    for (t, decided) in decisions.iter().enumerate().rev() {
+        decoded[t] = (state >> (TAIL - 1)) as u8;
+        state = (state << 1) & (STATES - 1) | (decided >> state & 1) as usize;
+    }
+    decoded.truncate(steps.saturating_sub(TAIL));
+    decoded
+}
+
+/// Every `depth`th bit, starting from each of the first `depth` in turn
+fn interleave(bits: &[u8], depth: usize) -> Vec<u8> {
+    (0..depth)
+        .flat_map(|start| bits.iter().skip(start).step_by(depth).copied())
+        .collect()
+}
+
+fn deinterleave(bits: &[u8], depth: usize) -> Vec<u8> {
+    let mut out = vec![0; bits.len()];
+    let positions = (0..depth).flat_map(|start| (start..bits.len()).step_by(depth));
+    for (bit, position) in bits.iter().zip(positions) {
+        out[position] = *bit;
+    }
+    out
+}
+
+fn one() -> u16 {
+    1
+}
+
+/// `convolutional` in a [[channel]]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Convolutional {
+    /// Coded bits between neighbours on the air, 1 for no interleaving
+    #[serde(default = "one")]
+    pub interleave: u16,
+}
+
+impl Default for Convolutional {
+    fn default() -> Self {
+        Self { interleave: 1 }
+    }
+}
+
+impl Convolutional {
+    pub fn validate(&self) -> Result<()> {
+        if self.interleave == 0 {
+            return Err(Error::Convolutional("interleave must be positive"));
+        }
+        Ok(())
+    }
+
+    /// Bytes on the air for `len` bytes in
+    pub fn coded_len(len: usize) -> usize {
+        2 * len + 2
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.validate()?;
+        let coded = encode_bits(&bits(data));
+        Ok(bytes(&interleave(&coded, self.interleave.into())))
+    }
+
+    /// The data out of an encode()d frame, and how many coded bits were wrong going by the
+    /// re-encoded result
+    pub fn decode(&self, frame: &[u8]) -> Result<(Vec<u8>, usize)> {
+        self.validate()?;
+        if frame.len() < 4 || !frame.len().is_multiple_of(2) {
+            return Err(Error::Convolutional("frame isn't a coded length"));
+        }
+        let len = frame.len() / 2 - 1;
+        // The padding went on after interleaving
+        let mut received = bits(frame);
+        received.truncate(2 * (8 * len + TAIL));
+        let received = deinterleave(&received, self.interleave.into());
+        let decoded = viterbi(&received);
+        let errors = encode_bits(&decoded)
+            .iter()
+            .zip(&received)
+            .filter(|(a, b)| a != b)
+            .count();
+        Ok((bytes(&decoded), errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 73 + 5) as u8).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let code = Convolutional::default();
+        let payload = data(100);
+        let frame = code.encode(&payload).unwrap();
+        assert_eq!(frame.len(), Convolutional::coded_len(100));
+        assert_eq!(code.decode(&frame).unwrap(), (payload, 0));
+        // A zero input is all ones on G2 and zeros on G1
+        assert_eq!(code.encode(&[0]).unwrap(), [0x55, 0x55, 0x55, 0x50]);
+        assert!(code.decode(&[0; 3]).is_err());
+        assert!(Convolutional { interleave: 0 }.validate().is_err());
+    }
+
+    #[test]
+    fn corrects() {
+        let code = Convolutional::default();
+        let payload = data(64);
+        let mut frame = code.encode(&payload).unwrap();
+        // Isolated bit errors well apart
+        for i in (0..frame.len()).step_by(10) {
+            frame[i] ^= 0x10;
+        }
+        let (decoded, errors) = code.decode(&frame).unwrap();
+        assert_eq!(decoded, payload);
+        assert_eq!(errors, frame.len().div_ceil(10));
+
+        // A two byte burst needs the interleaver
+        let burst = |code: Convolutional| {
+            let mut frame = code.encode(&payload).unwrap();
+            frame[40] = !frame[40];
+            frame[41] = !frame[41];
+            code.decode(&frame).unwrap().0
+        };
+        assert_ne!(burst(Convolutional::default()), payload);
+        assert_eq!(burst(Convolutional { interleave: 32 }), payload);
+    }
+
+    #[test]
+    fn interleaver() {
+        let bits: Vec<u8> = (0..11).collect();
+        let interleaved = interleave(&bits, 4);
+        assert_eq!(interleaved, [0, 4, 8, 1, 5, 9, 2, 6, 10, 3, 7]);
+        assert_eq!(deinterleave(&interleaved, 4), bits);
+        assert_eq!(interleave(&bits, 1), bits);
+    }
+}
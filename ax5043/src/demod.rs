@@ -0,0 +1,87 @@
+// What the demodulator is tracking, in one read.
+//
+// DemodSnapshot::read() takes RADIOSTATE, then SIGNALSTR through RXTRACKING in a single burst so
+// the amplitude, phase, RF frequency and datarate all come from the same moment, then
+// RXDATARATE, which the datarate tracking is relative to. The telemetry STATE is built from it,
+// and anything steering the receiver on what it hears (AGC, datarate or frequency corrections)
+// should read it rather than the TRK* registers one by one.
+use crate::{config, registers::*, Registers, Result, RX};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DemodSnapshot {
+    pub radio_state: RadioState,
+    pub signal: SignalStr,
+    pub track: RXTracking,
+    /// RXDATARATE when the snapshot was taken
+    pub rxdatarate: u32,
+}
+
+impl DemodSnapshot {
+    pub fn read(radio: &mut Registers) -> Result<Self> {
+        let radio_state = radio.RADIOSTATE().read()?;
+        let burst = radio.read_burst(0x040, 0x15)?; // SIGNALSTR to the end of RXTRACKING
+        Ok(Self {
+            radio_state,
+            signal: radio.SIGNALSTR().read_from(&burst)?,
+            track: radio.RXTRACKING().read_from(&burst)?,
+            rxdatarate: radio.RXDATARATE().read()?,
+        })
+    }
+
+    /// Whether the tracking values mean anything: outside RX they hold whatever the last
+    /// reception left behind
+    pub fn receiving(&self) -> bool {
+        self.radio_state == RadioState::RX
+    }
+
+    /// TRKRFFREQ in Hz, positive when the signal is above the channel
+    pub fn rf_offset(&self, xtal: &config::Xtal) -> i64 {
+        i64::from(self.track.rffreq.0) * xtal.freq as i64 / (1 << 24)
+    }
+
+    /// TRKPHASE in radians
+    pub fn phase(&self) -> f64 {
+        f64::from(self.track.phase.0) * std::f64::consts::PI / 2048.0
+    }
+
+    /// Far end datarate minus the channel's, bits/s. 0 before RXDATARATE is set.
+    pub fn datarate_error(&self, channel: &config::ChannelParameters) -> f64 {
+        // TRKDATARATE is in RXDATARATE units, more of it means longer bits
+        match self.rxdatarate {
+            0 => 0.0,
+            rxdatarate => {
+                -(channel.datarate as f64) * f64::from(self.track.datarate) / f64::from(rxdatarate)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot() {
+        let config: config::Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+        let (port, _) = crate::sim::pair(crate::sim::Channel::default());
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(crate::Bus::Sim(port), &mut callback);
+        config.write(&mut radio).unwrap();
+
+        let mut snapshot = DemodSnapshot::read(&mut radio).unwrap();
+        assert_eq!(snapshot.rxdatarate, radio.RXDATARATE().read().unwrap());
+        assert_ne!(snapshot.rxdatarate, 0);
+
+        snapshot.radio_state = RadioState::RX;
+        snapshot.track.rffreq = TrkRFFreq(-350);
+        snapshot.track.phase = TrkPhase(1024);
+        snapshot.track.datarate = snapshot.rxdatarate as i32 / 100;
+        assert!(snapshot.receiving());
+        // 48 MHz TCXO, 2.86 Hz per step
+        assert_eq!(snapshot.rf_offset(&config.board.xtal), -1001);
+        assert!((snapshot.phase() - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        let error = snapshot.datarate_error(&config.channel[0]);
+        assert!((error + 960.0).abs() < 1.0, "{}", error);
+    }
+}
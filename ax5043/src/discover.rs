@@ -0,0 +1,179 @@
+// Finds a radio's spidev node and IRQ line from the device tree, so deployment images can ship
+// the same command line for every host.
+//
+// The overlay describes each radio as a SPI child bound to spidev, named after the radio (or
+// with a compatible string) and with an `irq-gpios` property:
+//
+//     uhf@0 {
+//         compatible = "uniclogs,ax5043-uhf", "onsemi,ax5043";
+//         reg = <0>;
+//         irq-gpios = <&gpio 30 GPIO_ACTIVE_HIGH>;
+//     };
+//
+// SPI devices are matched through /sys/bus/spi/devices/*/of_node, the irq-gpios phandle is
+// resolved by comparing it to each gpiochip's of_node/phandle.
+use crate::gpio::Pin;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Discovered {
+    /// /dev/spidevB.C
+    pub spi: PathBuf,
+    /// None if the node has no irq-gpios or its controller couldn't be found
+    pub irq: Option<Pin>,
+}
+
+fn strings(raw: &[u8]) -> impl Iterator<Item = &str> {
+    raw.split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| std::str::from_utf8(s).ok())
+}
+
+fn cells(raw: &[u8]) -> Vec<u32> {
+    raw.chunks_exact(4)
+        .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+/// Node name without the unit address, `uhf@0` -> `uhf`
+fn node_name(of_node: &Path) -> Option<String> {
+    let raw = fs::read(of_node.join("name")).ok()?;
+    let name = strings(&raw).next()?;
+    Some(name.split('@').next().unwrap_or(name).to_string())
+}
+
+fn matches(of_node: &Path, name: &str) -> bool {
+    if node_name(of_node).as_deref() == Some(name) {
+        return true;
+    }
+    match fs::read(of_node.join("compatible")) {
+        Ok(raw) => strings(&raw).any(|c| c == name),
+        Err(_) => false,
+    }
+}
+
+fn gpiochip(sys: &Path, phandle: u32) -> io::Result<Option<String>> {
+    let dir = match fs::read_dir(sys.join("bus/gpio/devices")) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    for entry in dir {
+        let entry = entry?;
+        let of_node = entry.path().join("of_node");
+        if let Ok(raw) = fs::read(of_node.join("phandle")) {
+            if cells(&raw).first() == Some(&phandle) {
+                return Ok(Some(entry.file_name().to_string_lossy().into_owned()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Looks for a spidev whose device tree node is named `name` or has it as a compatible
+/// string. `sys` is normally /sys. Ok(None) if there's no such device.
+pub fn find(sys: &Path, name: &str) -> io::Result<Option<Discovered>> {
+    let dir = match fs::read_dir(sys.join("bus/spi/devices")) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut devices: Vec<_> = dir.collect::<io::Result<_>>()?;
+    devices.sort_by_key(|d| d.file_name());
+
+    for device in devices {
+        let path = device.path();
+        let of_node = path.join("of_node");
+        if !matches(&of_node, name) {
+            continue;
+        }
+        // spi0.0 -> spidev0.0, only there if spidev is bound
+        let Some(spidev) = fs::read_dir(path.join("spidev"))
+            .ok()
+            .and_then(|mut d| d.next())
+            .and_then(|e| e.ok())
+        else {
+            continue;
+        };
+        let spi = Path::new("/dev").join(spidev.file_name());
+
+        let irq = match fs::read(of_node.join("irq-gpios")) {
+            Ok(raw) => match cells(&raw)[..] {
+                [phandle, line, ..] => gpiochip(sys, phandle)?.map(|chip| Pin {
+                    chip: format!("/dev/{}", chip),
+                    line,
+                }),
+                _ => None,
+            },
+            Err(_) => None,
+        };
+        return Ok(Some(Discovered { spi, irq }));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: PathBuf, contents: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn be(cells: &[u32]) -> Vec<u8> {
+        cells.iter().flat_map(|c| c.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn find_fake_sysfs() {
+        let sys = std::env::temp_dir().join(format!("ax5043-discover-{}", std::process::id()));
+        _ = fs::remove_dir_all(&sys);
+
+        let uhf = sys.join("bus/spi/devices/spi0.0");
+        write(uhf.join("of_node/name"), b"uhf\0");
+        write(
+            uhf.join("of_node/compatible"),
+            b"uniclogs,ax5043-uhf\0onsemi,ax5043\0",
+        );
+        write(uhf.join("of_node/irq-gpios"), &be(&[0x42, 30, 0]));
+        fs::create_dir_all(uhf.join("spidev/spidev0.0")).unwrap();
+
+        let lband = sys.join("bus/spi/devices/spi1.1");
+        write(lband.join("of_node/name"), b"lband@1\0");
+        fs::create_dir_all(lband.join("spidev/spidev1.1")).unwrap();
+
+        write(
+            sys.join("bus/gpio/devices/gpiochip0/of_node/phandle"),
+            &be(&[0x42]),
+        );
+
+        assert_eq!(
+            find(&sys, "uniclogs,ax5043-uhf").unwrap(),
+            Some(Discovered {
+                spi: "/dev/spidev0.0".into(),
+                irq: Some(Pin {
+                    chip: "/dev/gpiochip0".into(),
+                    line: 30
+                }),
+            })
+        );
+        assert_eq!(
+            find(&sys, "uhf").unwrap(),
+            find(&sys, "onsemi,ax5043").unwrap()
+        );
+        assert_eq!(
+            find(&sys, "lband").unwrap(),
+            Some(Discovered {
+                spi: "/dev/spidev1.1".into(),
+                irq: None,
+            })
+        );
+        assert_eq!(find(&sys, "sband").unwrap(), None);
+
+        fs::remove_dir_all(&sys).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+// Dropping to a slower [[channel]] preset when the link gets marginal and going back once it
+// recovers, to keep the low end of a pass productive.
+//
+//   [fallback]
+//   channel = 2
+//   window = 20
+//   crc_failed = 0.3
+//   rssi = -112.0
+//   recover_crc_failed = 0.1
+//   recover_rssi = -106.0
+//   hold = 30
+//   idle = 60
+//   tx = true
+//
+// Monitor looks at the last `window` packets. Once at least crc_failed of them failed (bad CRC,
+// outer codes that didn't decode, or rejected by the assembler) or their mean RSSI is below
+// `rssi`, the bins swap `channel` in as channel 0 (Config::swap_channel) and RX carries on at
+// its rate. They swap back when no more than recover_crc_failed fail and the mean RSSI is at
+// least recover_rssi, by default half the failures and 6 dB better than falling back took. The
+// gap between the two is the hysteresis, `hold` seconds between switches and a full window on
+// the new rate before judging it keep a burst of interference from flapping the rate.
+//
+// Both ends have to follow, so `tx` moves the uhf bin's EDL transmissions to the fallback as
+// well, and with no packet at all for `idle` seconds on the fallback it goes back in case the far
+// end never switched. Each switch is logged and sent as CommState::FALLBACK.
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Default recover_rssi above `rssi`, dB
+pub const RECOVER_MARGIN: f64 = 6.0;
+
+fn window() -> usize {
+    20
+}
+
+fn hold() -> u64 {
+    30
+}
+
+fn idle() -> u64 {
+    60
+}
+
+/// The [fallback] config section
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fallback {
+    /// The slower [[channel]], by index
+    pub channel: usize,
+    /// Packets the statistics cover
+    #[serde(default = "window")]
+    pub window: usize,
+    /// Fraction of the window failing that falls back
+    #[serde(default)]
+    pub crc_failed: Option<f64>,
+    /// Mean RSSI that falls back, dBm
+    #[serde(default)]
+    pub rssi: Option<f64>,
+    #[serde(default)]
+    pub recover_crc_failed: Option<f64>,
+    #[serde(default)]
+    pub recover_rssi: Option<f64>,
+    /// Seconds at least between switches
+    #[serde(default = "hold")]
+    pub hold: u64,
+    /// Seconds without a packet on the fallback before going back, 0 to stay
+    #[serde(default = "idle")]
+    pub idle: u64,
+    /// Transmit on the fallback as well
+    #[serde(default)]
+    pub tx: bool,
+}
+
+impl Fallback {
+    /// Against a config with `channels` [[channel]]s
+    pub fn validate(&self, channels: usize) -> Result<()> {
+        if self.channel == 0 || self.channel >= channels {
+            return Err(Error::Fallback(
+                "channel must be one of the other [[channel]]s",
+            ));
+        }
+        if self.window == 0 {
+            return Err(Error::Fallback("window must be positive"));
+        }
+        if self.crc_failed.is_none() && self.rssi.is_none() {
+            return Err(Error::Fallback("set crc_failed, rssi or both"));
+        }
+        let fraction = |f: Option<f64>| f.is_none_or(|f| (0.0..=1.0).contains(&f));
+        if !fraction(self.crc_failed) || !fraction(self.recover_crc_failed) {
+            return Err(Error::Fallback("crc_failed is a fraction, 0 to 1"));
+        }
+        if self
+            .crc_failed
+            .is_some_and(|f| self.recover_crc_failed() > f)
+            || self.rssi.is_some_and(|r| self.recover_rssi() < r)
+        {
+            return Err(Error::Fallback(
+                "recovering must take a better link than falling back",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn recover_crc_failed(&self) -> f64 {
+        self.recover_crc_failed
+            .or(self.crc_failed.map(|f| f / 2.0))
+            .unwrap_or(1.0)
+    }
+
+    pub fn recover_rssi(&self) -> f64 {
+        self.recover_rssi
+            .or(self.rssi.map(|r| r + RECOVER_MARGIN))
+            .unwrap_or(f64::MIN)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rate {
+    #[default]
+    Primary,
+    Fallback,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Reason {
+    /// What the window looked like
+    Link {
+        packets: usize,
+        failed: f64,
+        rssi: Option<f64>,
+    },
+    /// Nothing received on the fallback for this long
+    Idle(Duration),
+}
+
+/// A change of rate Monitor decided on
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Switch {
+    pub to: Rate,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Switch {
+    /// "to Fallback, 35% of 20 packets failed, mean RSSI -114.2 dBm"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "to {:?}", self.to)?;
+        match self.reason {
+            Reason::Link {
+                packets,
+                failed,
+                rssi,
+            } => {
+                write!(f, ", {:.0}% of {} packets failed", failed * 100.0, packets)?;
+                if let Some(rssi) = rssi {
+                    write!(f, ", mean RSSI {:.1} dBm", rssi)?;
+                }
+                Ok(())
+            }
+            Reason::Idle(quiet) => write!(f, " after {} s without a packet", quiet.as_secs()),
+        }
+    }
+}
+
+/// Keeps the statistics and decides when to switch
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    config: Fallback,
+    rate: Rate,
+    /// Whether each packet in the window was good, and its RSSI
+    window: VecDeque<(bool, Option<f64>)>,
+    switched: Instant,
+    last: Instant,
+}
+
+impl Monitor {
+    /// On the primary rate as of `now`
+    pub fn new(config: Fallback, now: Instant) -> Self {
+        Self {
+            config,
+            rate: Rate::Primary,
+            window: VecDeque::with_capacity(config.window),
+            switched: now,
+            last: now,
+        }
+    }
+
+    pub fn config(&self) -> &Fallback {
+        &self.config
+    }
+
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
+    /// A packet at `now`, `ok` if it was passed on
+    pub fn packet(&mut self, ok: bool, rssi: Option<f64>, now: Instant) {
+        if self.window.len() == self.config.window {
+            self.window.pop_front();
+        }
+        self.window.push_back((ok, rssi));
+        self.last = now;
+    }
+
+    /// Fraction failed and mean RSSI once the window is full
+    fn statistics(&self) -> Option<(f64, Option<f64>)> {
+        if self.window.len() < self.config.window {
+            return None;
+        }
+        let failed = self.window.iter().filter(|(ok, _)| !ok).count();
+        let rssi: Vec<f64> = self.window.iter().filter_map(|(_, rssi)| *rssi).collect();
+        let mean = (!rssi.is_empty()).then(|| rssi.iter().sum::<f64>() / rssi.len() as f64);
+        Some((failed as f64 / self.window.len() as f64, mean))
+    }
+
+    /// The switch to make at `now`, if any. The caller makes it, the statistics start over.
+    pub fn check(&mut self, now: Instant) -> Option<Switch> {
+        if now.duration_since(self.switched) < Duration::from_secs(self.config.hold) {
+            return None;
+        }
+        let quiet = now.duration_since(self.last.max(self.switched));
+        let reason = match (self.rate, self.statistics()) {
+            (Rate::Fallback, _) if self.config.idle > 0 && quiet.as_secs() >= self.config.idle => {
+                Some(Reason::Idle(quiet))
+            }
+            (_, None) => None,
+            (Rate::Primary, Some((failed, rssi))) => {
+                let crc = self.config.crc_failed.is_some_and(|f| failed >= f);
+                let weak =
+                    matches!((self.config.rssi, rssi), (Some(floor), Some(rssi)) if rssi < floor);
+                (crc || weak).then_some(Reason::Link {
+                    packets: self.window.len(),
+                    failed,
+                    rssi,
+                })
+            }
+            (Rate::Fallback, Some((failed, rssi))) => {
+                let crc = failed <= self.config.recover_crc_failed();
+                let strong = match (self.config.rssi, rssi) {
+                    (None, _) => true,
+                    (Some(_), Some(rssi)) => rssi >= self.config.recover_rssi(),
+                    (Some(_), None) => false,
+                };
+                (crc && strong).then_some(Reason::Link {
+                    packets: self.window.len(),
+                    failed,
+                    rssi,
+                })
+            }
+        }?;
+        self.rate = match self.rate {
+            Rate::Primary => Rate::Fallback,
+            Rate::Fallback => Rate::Primary,
+        };
+        self.window.clear();
+        self.switched = now;
+        Some(Switch {
+            to: self.rate,
+            reason,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Fallback {
+        toml::from_str(
+            r#"
+            channel = 2
+            window = 10
+            crc_failed = 0.3
+            rssi = -112.0
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn validate() {
+        let config = config();
+        assert!(config.validate(3).is_ok());
+        assert!(config.validate(2).is_err());
+        assert_eq!((config.hold, config.idle, config.tx), (30, 60, false));
+        assert_eq!(config.recover_crc_failed(), 0.15);
+        assert_eq!(config.recover_rssi(), -106.0);
+        let nothing = Fallback {
+            crc_failed: None,
+            rssi: None,
+            ..config
+        };
+        assert!(nothing.validate(3).is_err());
+        let inverted = Fallback {
+            recover_rssi: Some(-115.0),
+            ..config
+        };
+        assert!(inverted.validate(3).is_err());
+        assert!(Fallback {
+            channel: 0,
+            ..config
+        }
+        .validate(3)
+        .is_err());
+    }
+
+    #[test]
+    fn switches() {
+        let start = Instant::now();
+        let at = |s: u64| start + Duration::from_secs(s);
+        let mut monitor = Monitor::new(config(), start);
+
+        // Six in ten failing, but not before the hold is up
+        for i in 0..10 {
+            monitor.packet(i % 5 > 2, Some(-105.0), at(1));
+        }
+        assert_eq!(monitor.check(at(29)), None);
+        let switch = monitor.check(at(30)).unwrap();
+        assert_eq!(switch.to, Rate::Fallback);
+        assert_eq!(
+            switch.to_string(),
+            "to Fallback, 60% of 10 packets failed, mean RSSI -105.0 dBm"
+        );
+        assert_eq!(monitor.rate(), Rate::Fallback);
+
+        // Clean but weak isn't enough to go back, the hysteresis wants -106
+        for _ in 0..10 {
+            monitor.packet(true, Some(-108.0), at(40));
+        }
+        assert_eq!(monitor.check(at(61)), None);
+        for _ in 0..10 {
+            monitor.packet(true, Some(-104.0), at(62));
+        }
+        assert_eq!(monitor.check(at(62)).unwrap().to, Rate::Primary);
+
+        // Weak alone falls back
+        for _ in 0..10 {
+            monitor.packet(true, Some(-113.0), at(100));
+        }
+        assert_eq!(monitor.check(at(100)).unwrap().to, Rate::Fallback);
+        // and nothing at all goes back again
+        assert_eq!(monitor.check(at(159)), None);
+        let switch = monitor.check(at(160)).unwrap();
+        assert_eq!(
+            switch,
+            Switch {
+                to: Rate::Primary,
+                reason: Reason::Idle(Duration::from_secs(60))
+            }
+        );
+        assert_eq!(switch.to_string(), "to Primary after 60 s without a packet");
+    }
+}
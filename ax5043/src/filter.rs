@@ -0,0 +1,142 @@
+// A hook between the receiver and the uplink socket: every frame that came through the CRC and
+// any outer codes is shown to a PacketFilter, which forwards it as is, drops it, or forwards it
+// with a tag in front.
+//
+// Applications with their own logic implement PacketFilter, closures included. The bins take
+// Rules from the [filter] config section, first match wins:
+//
+//   [filter]
+//   default = "Drop"
+//
+//   [[filter.rule]]
+//   pos = 1
+//   bytes = [0x96, 0x68, 0x6E, 0x8A]
+//   action = "Forward"
+//
+//   [[filter.rule]]
+//   pos = 0
+//   bytes = [0x03]
+//   action = { Tag = [0x01] }
+//
+// Capture and telemetry still see every frame, the filter only decides what goes up the
+// uplink. Filtering on the radio's address match is cheaper where it fits, see
+// config::AddressFilter.
+use crate::capture::Meta;
+use serde::Deserialize;
+use std::fmt;
+
+/// What happens to a frame
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum Verdict {
+    #[default]
+    Forward,
+    Drop,
+    /// Forward with these bytes in front
+    Tag(Vec<u8>),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Forward => write!(f, "forward"),
+            Verdict::Drop => write!(f, "drop"),
+            Verdict::Tag(tag) => write!(f, "tag {:02X?}", tag),
+        }
+    }
+}
+
+/// Called for every validated frame before it's forwarded, from the poll loop
+pub trait PacketFilter {
+    fn inspect(&mut self, packet: &[u8], meta: &Meta) -> Verdict;
+}
+
+impl<F: FnMut(&[u8], &Meta) -> Verdict> PacketFilter for F {
+    fn inspect(&mut self, packet: &[u8], meta: &Meta) -> Verdict {
+        self(packet, meta)
+    }
+}
+
+/// `bytes` at `pos` in the frame
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Rule {
+    pub pos: usize,
+    pub bytes: Vec<u8>,
+    pub action: Verdict,
+}
+
+impl Rule {
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        packet
+            .get(self.pos..)
+            .is_some_and(|rest| rest.starts_with(&self.bytes))
+    }
+}
+
+/// The [filter] config section
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct Rules {
+    #[serde(default)]
+    pub rule: Vec<Rule>,
+    /// For frames no rule matches
+    #[serde(default)]
+    pub default: Verdict,
+}
+
+impl PacketFilter for Rules {
+    fn inspect(&mut self, packet: &[u8], _: &Meta) -> Verdict {
+        self.rule
+            .iter()
+            .find(|rule| rule.matches(packet))
+            .map_or(&self.default, |rule| &rule.action)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let mut rules: Rules = toml::from_str(
+            r#"
+            default = "Drop"
+            [[rule]]
+            pos = 1
+            bytes = [0x96, 0x68]
+            action = "Forward"
+            [[rule]]
+            pos = 0
+            bytes = [0x03]
+            action = { Tag = [0x01] }
+            "#,
+        )
+        .unwrap();
+        let meta = Meta::default();
+        assert_eq!(rules.inspect(&[0, 0x96, 0x68, 5], &meta), Verdict::Forward);
+        assert_eq!(
+            rules.inspect(&[3, 0x96, 0x69], &meta),
+            Verdict::Tag(vec![1])
+        );
+        assert_eq!(rules.inspect(&[0, 0x96], &meta), Verdict::Drop);
+        assert_eq!(Verdict::Tag(vec![1]).to_string(), "tag [01]");
+
+        let mut none = Rules::default();
+        assert_eq!(none.inspect(&[], &meta), Verdict::Forward);
+    }
+
+    #[test]
+    fn closure() {
+        let mut quiet = |_: &[u8], meta: &Meta| match meta.rssi {
+            Some(rssi) if rssi < -120.0 => Verdict::Drop,
+            _ => Verdict::Forward,
+        };
+        let weak = Meta {
+            rssi: Some(-125.0),
+            ..Meta::default()
+        };
+        let filter: &mut dyn PacketFilter = &mut quiet;
+        assert_eq!(filter.inspect(&[1], &weak), Verdict::Drop);
+        assert_eq!(filter.inspect(&[1], &Meta::default()), Verdict::Forward);
+    }
+}
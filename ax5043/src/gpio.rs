@@ -0,0 +1,515 @@
+// GPIO lines around the radio: the IRQ input, PA enable and antenna switches, and a GPS pulse
+// per second and a TX inhibit input when there are.
+//
+// Carrier boards wire these to different chips and offsets, so the bins take them as
+// `chip:line` arguments (see Pin) instead of hard coding them. Outputs go through the Switch
+// trait so boards without a given line (no external PA, fixed antenna) can pass NoSwitch.
+// The IRQ input goes through the IrqLine trait and drain(), the loop every bin needs.
+//
+// Pin, Output and IrqDriver are the Linux gpiochip backend. Switch and IrqLine only speak
+// std::io errors, so another platform (or a test) implements them over whatever it has and
+// hands them to Guard, drain() and hitl::run() the same way. With the embedded-hal feature
+// HalSwitch and HalIrq do that for any embedded-hal 1.0 OutputPin and InputPin.
+use gpiocdev::{
+    line::{EdgeDetection, EventClock, Offset, Value},
+    Request,
+};
+use serde::Deserialize;
+use std::{
+    fmt, io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tracing::warn;
+
+/// A line on a gpiochip, written `gpiochip1:27` or `/dev/gpiochip1:27`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pin {
+    pub chip: String,
+    pub line: Offset,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("expected chip:line, got {0:?}")]
+    Format(String),
+    #[error("invalid line number {0:?}")]
+    Line(String),
+}
+
+impl FromStr for Pin {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (chip, line) = s
+            .rsplit_once(':')
+            .filter(|(chip, _)| !chip.is_empty())
+            .ok_or_else(|| ParseError::Format(s.into()))?;
+        let line = line.parse().map_err(|_| ParseError::Line(line.into()))?;
+        let chip = match chip.starts_with('/') {
+            true => chip.into(),
+            false => format!("/dev/{}", chip),
+        };
+        Ok(Self { chip, line })
+    }
+}
+
+impl TryFrom<String> for Pin {
+    type Error = ParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.chip, self.line)
+    }
+}
+
+impl Pin {
+    /// Requests the line as an output, initially inactive
+    pub fn output(&self) -> gpiocdev::Result<Output> {
+        let request = Request::builder()
+            .on_chip(&self.chip)
+            .with_line(self.line)
+            .as_output(Value::Inactive)
+            .request()?;
+        Ok(Output {
+            request,
+            line: self.line,
+        })
+    }
+
+    /// Requests the line as a rising edge input, for the radio IRQ. Register the driver's fd
+    /// with the poll loop.
+    pub fn irq(&self) -> gpiocdev::Result<IrqDriver> {
+        let request = Request::builder()
+            .on_chip(&self.chip)
+            .with_line(self.line)
+            .with_edge_detection(EdgeDetection::RisingEdge)
+            .request()?;
+        Ok(IrqDriver {
+            request,
+            line: self.line,
+        })
+    }
+
+    /// Requests the line as a rising edge input stamped with the system clock, for a GPS
+    /// pulse per second. Register the driver's fd with the poll loop, see pps::Calibrator.
+    pub fn pps(&self) -> gpiocdev::Result<PpsDriver> {
+        let request = Request::builder()
+            .on_chip(&self.chip)
+            .with_line(self.line)
+            .with_edge_detection(EdgeDetection::RisingEdge)
+            .with_event_clock(EventClock::Realtime)
+            .request()?;
+        Ok(PpsDriver { request })
+    }
+
+    /// Requests the line as an input reporting both edges, for an interlock like the TX
+    /// inhibit. `active_low` for a switch to ground with a pull-up. Register the fd with the
+    /// poll loop.
+    pub fn input(&self, active_low: bool) -> gpiocdev::Result<Input> {
+        let mut builder = Request::builder();
+        builder
+            .on_chip(&self.chip)
+            .with_line(self.line)
+            .with_edge_detection(EdgeDetection::BothEdges);
+        if active_low {
+            builder.as_active_low();
+        }
+        Ok(Input {
+            request: builder.request()?,
+            line: self.line,
+        })
+    }
+}
+
+/// An input watched for changes either way
+pub struct Input {
+    request: Request,
+    line: Offset,
+}
+
+impl Input {
+    /// Whether the line is asserted right now
+    pub fn asserted(&self) -> io::Result<bool> {
+        Ok(self.request.value(self.line).map_err(io_error)? == Value::Active)
+    }
+
+    /// Call when the fd is readable: discards the queued edges and returns the level after
+    /// them, so a bouncing switch settles on where it ended up
+    pub fn changed(&self) -> io::Result<bool> {
+        while self.request.has_edge_event().map_err(io_error)? {
+            self.request.read_edge_event().map_err(io_error)?;
+        }
+        self.asserted()
+    }
+}
+
+impl AsRawFd for Input {
+    fn as_raw_fd(&self) -> RawFd {
+        self.request.as_raw_fd()
+    }
+}
+
+/// A pulse per second input
+pub struct PpsDriver {
+    request: Request,
+}
+
+impl PpsDriver {
+    /// When each queued pulse arrived, by the kernel's stamp rather than when it was read
+    pub fn edges(&self) -> io::Result<Vec<SystemTime>> {
+        let mut edges = Vec::new();
+        while self.request.has_edge_event().map_err(io_error)? {
+            let event = self.request.read_edge_event().map_err(io_error)?;
+            edges.push(UNIX_EPOCH + Duration::from_nanos(event.timestamp_ns));
+        }
+        Ok(edges)
+    }
+}
+
+impl AsRawFd for PpsDriver {
+    fn as_raw_fd(&self) -> RawFd {
+        self.request.as_raw_fd()
+    }
+}
+
+/// The radio IRQ input. The AX5043 holds IRQ high for as long as any unmasked source is
+/// pending, but the kernel only reports rising edges, so a source that shows up while another
+/// is being serviced never makes a new edge. drain() checks the level after every pass instead.
+pub struct IrqDriver {
+    request: Request,
+    line: Offset,
+}
+
+impl IrqDriver {
+    /// Passes before drain() gives up on a line that won't go low, see drain()
+    pub const MAX_PASSES: usize = 32;
+
+    /// Whether the radio is asserting IRQ right now
+    pub fn asserted(&self) -> io::Result<bool> {
+        IrqLine::asserted(self)
+    }
+
+    /// Call when the fd is readable, see drain()
+    pub fn drain<E: From<io::Error>>(
+        &self,
+        service: impl FnMut() -> Result<(), E>,
+    ) -> Result<usize, E> {
+        drain(self, service)
+    }
+}
+
+impl AsFd for IrqDriver {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.request.as_fd()
+    }
+}
+
+impl AsRawFd for IrqDriver {
+    fn as_raw_fd(&self) -> RawFd {
+        self.request.as_raw_fd()
+    }
+}
+
+fn io_error(e: gpiocdev::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+/// The radio IRQ input, what drain() needs from it
+pub trait IrqLine {
+    /// Discards the edges seen so far
+    fn clear_edges(&self) -> io::Result<()>;
+    /// Whether the radio is asserting IRQ right now
+    fn asserted(&self) -> io::Result<bool>;
+}
+
+impl IrqLine for IrqDriver {
+    fn clear_edges(&self) -> io::Result<()> {
+        while self.request.has_edge_event().map_err(io_error)? {
+            self.request.read_edge_event().map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    fn asserted(&self) -> io::Result<bool> {
+        Ok(self.request.value(self.line).map_err(io_error)? == Value::Active)
+    }
+}
+
+/// Call when the line's fd is readable: discards the queued edges, then runs `service` until
+/// IRQ reads low, returning how many passes that took. A line still high after
+/// IrqDriver::MAX_PASSES is logged and left to the watchdog rather than spinning the poll loop.
+pub fn drain<E: From<io::Error>>(
+    line: &impl IrqLine,
+    mut service: impl FnMut() -> Result<(), E>,
+) -> Result<usize, E> {
+    // Edges from before this point are covered by the first pass, ones that come in during a
+    // pass stay queued and cost one extra (empty) pass on the next wakeup
+    line.clear_edges()?;
+    for pass in 1..=IrqDriver::MAX_PASSES {
+        service()?;
+        if !line.asserted()? {
+            return Ok(pass);
+        }
+    }
+    warn!(
+        target: "ax5043::irq",
+        "IRQ still asserted after {} passes", IrqDriver::MAX_PASSES
+    );
+    Ok(IrqDriver::MAX_PASSES)
+}
+
+/// Something that can be turned on and off: PA enable, antenna switch
+pub trait Switch: Send + Sync {
+    fn set(&self, on: bool) -> io::Result<()>;
+}
+
+pub struct Output {
+    request: Request,
+    line: Offset,
+}
+
+impl Switch for Output {
+    fn set(&self, on: bool) -> io::Result<()> {
+        let value = match on {
+            true => Value::Active,
+            false => Value::Inactive,
+        };
+        self.request.set_value(self.line, value).map_err(io_error)?;
+        Ok(())
+    }
+}
+
+/// For boards without the line
+pub struct NoSwitch;
+
+impl Switch for NoSwitch {
+    fn set(&self, _on: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: Switch> Switch for Option<S> {
+    fn set(&self, on: bool) -> io::Result<()> {
+        match self {
+            Some(switch) => switch.set(on),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Any embedded-hal OutputPin as a Switch, on driving the pin high
+#[cfg(feature = "embedded-hal")]
+pub struct HalSwitch<P>(std::sync::Mutex<P>);
+
+#[cfg(feature = "embedded-hal")]
+impl<P: embedded_hal::digital::OutputPin> HalSwitch<P> {
+    pub fn new(pin: P) -> Self {
+        Self(std::sync::Mutex::new(pin))
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+impl<P: embedded_hal::digital::OutputPin + Send> Switch for HalSwitch<P> {
+    fn set(&self, on: bool) -> io::Result<()> {
+        let mut pin = self
+            .0
+            .lock()
+            .map_err(|_| io::Error::other("switch pin poisoned"))?;
+        match on {
+            true => pin.set_high(),
+            false => pin.set_low(),
+        }
+        .map_err(hal_error)
+    }
+}
+
+/// Any embedded-hal InputPin as the IRQ line, asserted while the pin reads high. embedded-hal
+/// has no edge events, so the caller decides when to drain() (a poll timer, its own interrupt)
+/// and clear_edges() has nothing to do.
+#[cfg(feature = "embedded-hal")]
+pub struct HalIrq<P>(std::cell::RefCell<P>);
+
+#[cfg(feature = "embedded-hal")]
+impl<P: embedded_hal::digital::InputPin> HalIrq<P> {
+    pub fn new(pin: P) -> Self {
+        Self(std::cell::RefCell::new(pin))
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+impl<P: embedded_hal::digital::InputPin> IrqLine for HalIrq<P> {
+    fn clear_edges(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn asserted(&self) -> io::Result<bool> {
+        self.0.borrow_mut().is_high().map_err(hal_error)
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+fn hal_error(e: impl embedded_hal::digital::Error) -> io::Error {
+    io::Error::other(format!("{:?}", e.kind()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Stays asserted for `high` more level reads
+    struct Fake {
+        high: Cell<usize>,
+        cleared: Cell<bool>,
+    }
+
+    impl IrqLine for Fake {
+        fn clear_edges(&self) -> io::Result<()> {
+            self.cleared.set(true);
+            Ok(())
+        }
+
+        fn asserted(&self) -> io::Result<bool> {
+            let high = self.high.get();
+            self.high.set(high.saturating_sub(1));
+            Ok(high > 0)
+        }
+    }
+
+    fn fake(high: usize) -> Fake {
+        Fake {
+            high: Cell::new(high),
+            cleared: Cell::new(false),
+        }
+    }
+
+    #[test]
+    fn drain_until_low() {
+        let line = fake(0);
+        let mut serviced = 0;
+        let passes = drain(&line, || {
+            assert!(line.cleared.get(), "edges cleared before servicing");
+            serviced += 1;
+            Ok::<_, io::Error>(())
+        });
+        assert_eq!(passes.unwrap(), 1);
+        assert_eq!(serviced, 1);
+
+        // Something new arrived during the first two passes
+        let line = fake(2);
+        assert_eq!(drain(&line, || Ok::<_, io::Error>(())).unwrap(), 3);
+
+        let line = fake(usize::MAX);
+        assert_eq!(
+            drain(&line, || Ok::<_, io::Error>(())).unwrap(),
+            IrqDriver::MAX_PASSES
+        );
+    }
+
+    #[test]
+    fn drain_stops_on_error() {
+        #[derive(Debug, PartialEq)]
+        struct Stop;
+        impl From<io::Error> for Stop {
+            fn from(_: io::Error) -> Self {
+                Stop
+            }
+        }
+
+        let line = fake(5);
+        let mut serviced = 0;
+        let result = drain(&line, || {
+            serviced += 1;
+            Err(Stop)
+        });
+        assert_eq!(result, Err(Stop));
+        assert_eq!(serviced, 1);
+    }
+
+    #[test]
+    fn parse_pin() {
+        let pin: Pin = "gpiochip1:27".parse().unwrap();
+        assert_eq!(
+            pin,
+            Pin {
+                chip: "/dev/gpiochip1".into(),
+                line: 27
+            }
+        );
+        assert_eq!(pin.to_string(), "/dev/gpiochip1:27");
+        assert_eq!("/dev/gpiochip0:30".parse::<Pin>().unwrap().line, 30);
+        assert_eq!(
+            "gpiochip1".parse::<Pin>(),
+            Err(ParseError::Format("gpiochip1".into()))
+        );
+        assert_eq!(":1".parse::<Pin>(), Err(ParseError::Format(":1".into())));
+        assert_eq!(
+            "gpiochip1:x".parse::<Pin>(),
+            Err(ParseError::Line("x".into()))
+        );
+    }
+
+    #[cfg(feature = "embedded-hal")]
+    #[test]
+    fn embedded_hal_pins() {
+        use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+        use std::{convert::Infallible, sync::Arc, sync::Mutex};
+
+        /// Records what was driven, reads back `high` until that runs out
+        #[derive(Default)]
+        struct Mock {
+            driven: Arc<Mutex<Vec<bool>>>,
+            high: usize,
+        }
+
+        impl ErrorType for Mock {
+            type Error = Infallible;
+        }
+
+        impl OutputPin for Mock {
+            fn set_low(&mut self) -> Result<(), Infallible> {
+                self.driven.lock().unwrap().push(false);
+                Ok(())
+            }
+
+            fn set_high(&mut self) -> Result<(), Infallible> {
+                self.driven.lock().unwrap().push(true);
+                Ok(())
+            }
+        }
+
+        impl InputPin for Mock {
+            fn is_high(&mut self) -> Result<bool, Infallible> {
+                self.high = self.high.saturating_sub(1);
+                Ok(self.high > 0)
+            }
+
+            fn is_low(&mut self) -> Result<bool, Infallible> {
+                self.is_high().map(|high| !high)
+            }
+        }
+
+        let pin = Mock::default();
+        let driven = pin.driven.clone();
+        let switch = HalSwitch::new(pin);
+        switch.set(true).unwrap();
+        switch.set(false).unwrap();
+        Some(switch).set(true).unwrap();
+        assert_eq!(*driven.lock().unwrap(), [true, false, true]);
+
+        let irq = HalIrq::new(Mock {
+            high: 3,
+            ..Default::default()
+        });
+        assert_eq!(drain(&irq, || Ok::<_, io::Error>(())).unwrap(), 3);
+    }
+}
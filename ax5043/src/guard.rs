@@ -0,0 +1,84 @@
+// Puts the radio back into a safe state no matter how the driver exits.
+//
+// The bins used to disable the PA and reset the radio at the bottom of main(), which only ran
+// on a clean SIGINT. An early `?` return, a SIGTERM from systemd, or a panic would leave the
+// pa_enable GPIO high and the AX5043 configured (possibly mid transmission).
+//
+// Guard holds its own handle to the spidev node and the PA GPIO request, and makes them safe
+// on Drop (normal return, error return, unwinding) and from the panic hook (covers panics
+// in other threads and panic = "abort").
+use crate::{gpio::Switch, Registers};
+use spidev::Spidev;
+use std::{
+    panic,
+    path::Path,
+    sync::{Arc, Mutex, TryLockError},
+};
+
+pub struct Guard {
+    spi: Mutex<Option<Spidev>>,
+    pa: Option<Box<dyn Switch>>,
+}
+
+impl Guard {
+    /// Opens a second handle to the radio at `path` for resetting it on exit.
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self {
+            spi: Mutex::new(Some(crate::open(path)?)),
+            pa: None,
+        })
+    }
+
+    /// Takes ownership of the PA enable switch, see gpio::Pin::output().
+    pub fn with_pa(mut self, pa: impl Switch + 'static) -> Self {
+        self.pa = Some(Box::new(pa));
+        self
+    }
+
+    pub fn enable_pa(&self) -> std::io::Result<()> {
+        self.pa.as_ref().map_or(Ok(()), |pa| pa.set(true))
+    }
+
+    pub fn disable_pa(&self) -> std::io::Result<()> {
+        self.pa.as_ref().map_or(Ok(()), |pa| pa.set(false))
+    }
+
+    /// Disables the PA and resets the radio. Errors are ignored, there's nothing left to do
+    /// about them at this point. Safe to call more than once, the reset only happens once.
+    pub fn shutdown(&self) {
+        _ = self.disable_pa();
+
+        // WouldBlock means a shutdown is already in progress (we panicked inside it). A panic
+        // while holding the lock still leaves a usable Spidev.
+        let mut spi = match self.spi.try_lock() {
+            Ok(spi) => spi,
+            Err(TryLockError::WouldBlock) => return,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        };
+        if let Some(spi) = spi.take() {
+            let mut callback = |_: &_, _, _, _: &_| {};
+            let mut radio = Registers::new(spi, &mut callback);
+            _ = radio.reset();
+        }
+    }
+
+    /// Runs shutdown() from the panic hook before the previous hook prints the message.
+    ///
+    /// The hook only holds a weak reference so dropping the last Arc still runs Drop.
+    pub fn install_panic_hook(self: &Arc<Self>) {
+        let guard = Arc::downgrade(self);
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(guard) = guard.upgrade() {
+                guard.shutdown();
+            }
+            previous(info);
+        }));
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -0,0 +1,414 @@
+// Hardware-in-the-loop regression checks, built with the `hitl` feature.
+//
+// A script (see examples/hitl.toml) lists steps, each one transmitting numbered tx::test_frames
+// from one of two attached radios and draining the other's FIFO. What came through is compared
+// against the step's Expect: packet count, CRC failures and the RSSI range. The hitl bin runs a
+// script on the flatsat and exits with an error if any step failed, so it can gate a release.
+use crate::{
+    capture::Meta,
+    config::Config,
+    gpio::Switch,
+    rx::{self, PacketAssembler},
+    tx, Registers,
+};
+use serde::Deserialize;
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::{info, warn};
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Script {
+    pub step: Vec<Step>,
+}
+
+/// Which of the two radios transmits, the other one receives
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    #[default]
+    A,
+    B,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Step {
+    pub name: String,
+    #[serde(default)]
+    pub from: Side,
+    /// Index of the [[channel]] both radios are set to
+    #[serde(default)]
+    pub channel: usize,
+    pub count: u32,
+    /// Frame length, bytes
+    pub size: usize,
+    /// Time to wait for each frame, ms
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+fn default_timeout() -> u64 {
+    500
+}
+
+/// Limits a step has to stay within, anything left out isn't checked
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Expect {
+    /// Fraction of the frames that have to arrive intact
+    #[serde(default = "default_pass")]
+    pub pass: f64,
+    /// Frames the receiver dropped for a bad CRC
+    pub crc_fail: Option<u64>,
+    /// Inclusive range every received frame's RSSI (dBm) has to fall in
+    pub rssi: Option<(f64, f64)>,
+    /// Frames that weren't the one being waited for
+    pub unexpected: Option<u32>,
+}
+
+fn default_pass() -> f64 {
+    1.0
+}
+
+impl Default for Expect {
+    fn default() -> Self {
+        Self {
+            pass: default_pass(),
+            crc_fail: None,
+            rssi: None,
+            unexpected: None,
+        }
+    }
+}
+
+/// What one step saw
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Outcome {
+    pub sent: u32,
+    pub received: u32,
+    pub crc_fail: u64,
+    pub unexpected: u32,
+    /// One per received frame, dBm
+    pub rssi: Vec<f64>,
+}
+
+impl Outcome {
+    pub fn rate(&self) -> f64 {
+        f64::from(self.received) / f64::from(self.sent.max(1))
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "received {}/{} crc {} unexpected {}",
+            self.received, self.sent, self.crc_fail, self.unexpected
+        )?;
+        if !self.rssi.is_empty() {
+            let min = self.rssi.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = self.rssi.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            write!(f, " rssi {:.1}..{:.1} dBm", min, max)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Failure {
+    #[error("success rate {rate:.2} below {pass:.2}")]
+    Rate { rate: f64, pass: f64 },
+    #[error("{count} CRC failures, at most {max} allowed")]
+    CRC { count: u64, max: u64 },
+    #[error("RSSI {rssi:.1} dBm outside {min:.1}..={max:.1}")]
+    Rssi { rssi: f64, min: f64, max: f64 },
+    #[error("{count} unexpected frames, at most {max} allowed")]
+    Unexpected { count: u32, max: u32 },
+}
+
+impl Expect {
+    /// Everything the outcome got wrong, empty if the step passed
+    pub fn check(&self, outcome: &Outcome) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        if outcome.rate() < self.pass {
+            failures.push(Failure::Rate {
+                rate: outcome.rate(),
+                pass: self.pass,
+            });
+        }
+        if let Some(max) = self.crc_fail.filter(|&max| outcome.crc_fail > max) {
+            failures.push(Failure::CRC {
+                count: outcome.crc_fail,
+                max,
+            });
+        }
+        if let Some((min, max)) = self.rssi {
+            // The one furthest out is enough to go on
+            let distance = |r: &f64| (min - r).max(r - max);
+            let worst = outcome
+                .rssi
+                .iter()
+                .copied()
+                .filter(|r| distance(r) > 0.0)
+                .max_by(|a, b| distance(a).total_cmp(&distance(b)));
+            if let Some(rssi) = worst {
+                failures.push(Failure::Rssi { rssi, min, max });
+            }
+        }
+        if let Some(max) = self.unexpected.filter(|&max| outcome.unexpected > max) {
+            failures.push(Failure::Unexpected {
+                count: outcome.unexpected,
+                max,
+            });
+        }
+        failures
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Radio(#[from] crate::Error),
+    #[error("Antenna switch: {0}")]
+    GPIO(#[from] std::io::Error),
+    #[error("{0}: no [[channel]] {1}")]
+    NoChannel(String, usize),
+    #[error("{0}: the transmitting radio needs a [tx] section")]
+    NoTX(String),
+}
+
+/// One of the two radios under test, already reset and configured
+pub struct Radio<'r, 'a> {
+    pub registers: &'r mut Registers<'a>,
+    pub config: &'r Config,
+}
+
+/// Runs `step` from `tx` to `rx`. `antsel` is switched around each transmission, the PA has to
+/// be enabled by the caller.
+pub fn run(
+    step: &Step,
+    tx: &mut Radio,
+    rx: &mut Radio,
+    antsel: &impl Switch,
+) -> Result<Outcome, Error> {
+    let channel = |radio: &Radio| {
+        radio
+            .config
+            .channel
+            .get(step.channel)
+            .copied()
+            .ok_or_else(|| Error::NoChannel(step.name.clone(), step.channel))
+    };
+    let tx_params = tx.config.tx.ok_or_else(|| Error::NoTX(step.name.clone()))?;
+    let params = channel(tx)?.write(tx.registers, &tx.config.board)?;
+    tx_params.write(tx.registers, &tx.config.board, &params)?;
+
+    rx::leave(rx.registers, &rx.config.board)?;
+    channel(rx)?.write(rx.registers, &rx.config.board)?;
+    rx::enter(rx.registers, &rx.config.board, rx.config.fifo)?;
+
+    let timeout = Duration::from_millis(step.timeout);
+    let mut assembler = PacketAssembler::new().accept(rx.config.accept);
+    let mut outcome = Outcome::default();
+    for seq in 0..step.count {
+        let sent = tx::test_frame(seq, step.size);
+        antsel.set(true)?;
+        tx::transmit(tx.registers, &sent)?;
+        antsel.set(false)?;
+        outcome.sent += 1;
+
+        let start = Instant::now();
+        let received = 'wait: loop {
+            for packet in assembler.drain(rx.registers)? {
+                if packet == sent {
+                    break 'wait true;
+                }
+                // Late frames (already counted lost) or someone else on the channel
+                warn!(target: "ax5043::packet", "HITL {} unexpected {:02X?}", step.name, packet);
+                outcome.unexpected += 1;
+            }
+            if start.elapsed() > timeout {
+                break false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        if received {
+            let rssi = Meta::read(rx.registers, &rx.config.board)?
+                .rssi
+                .unwrap_or_default();
+            info!(target: "ax5043::packet", "HITL {} {} ok rssi={:.1} dBm", step.name, seq, rssi);
+            outcome.received += 1;
+            outcome.rssi.push(rssi);
+        } else {
+            warn!(target: "ax5043::packet", "HITL {} {} lost", step.name, seq);
+        }
+    }
+    outcome.crc_fail = assembler.stats().crc_fail;
+    rx::leave(rx.registers, &rx.config.board)?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_script() {
+        let script: Script = toml::from_str(
+            r#"
+            [[step]]
+            name = "uplink"
+            count = 20
+            size = 64
+            expect = { pass = 0.9, crc_fail = 2, rssi = [-100, -20] }
+
+            [[step]]
+            name = "downlink"
+            from = "b"
+            channel = 1
+            count = 5
+            size = 200
+            timeout = 1000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(script.step.len(), 2);
+        assert_eq!(script.step[0].from, Side::A);
+        assert_eq!(script.step[0].timeout, 500);
+        assert_eq!(script.step[0].expect.rssi, Some((-100.0, -20.0)));
+        assert_eq!(script.step[1].from, Side::B);
+        assert_eq!(script.step[1].expect, Expect::default());
+
+        assert!(toml::from_str::<Script>(
+            "[[step]]\nname = \"x\"\ncount = 1\nsize = 1\nexpect = { rate = 1 }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn check() {
+        let outcome = Outcome {
+            sent: 10,
+            received: 8,
+            crc_fail: 3,
+            unexpected: 1,
+            rssi: vec![-60.0, -75.0, -30.0],
+        };
+        assert_eq!(
+            Expect {
+                pass: 0.8,
+                ..Expect::default()
+            }
+            .check(&outcome),
+            vec![]
+        );
+        assert_eq!(
+            Expect {
+                pass: 0.9,
+                crc_fail: Some(2),
+                rssi: Some((-80.0, -40.0)),
+                unexpected: Some(1),
+            }
+            .check(&outcome),
+            vec![
+                Failure::Rate {
+                    rate: 0.8,
+                    pass: 0.9
+                },
+                Failure::CRC { count: 3, max: 2 },
+                Failure::Rssi {
+                    rssi: -30.0,
+                    min: -80.0,
+                    max: -40.0
+                },
+            ]
+        );
+        assert_eq!(
+            outcome.to_string(),
+            "received 8/10 crc 3 unexpected 1 rssi -75.0..-30.0 dBm"
+        );
+    }
+
+    #[test]
+    fn run_without_hardware() {
+        let config: Config = toml::from_str(include_str!("bin/c3-uhf-96000.toml")).unwrap();
+        let step = Step {
+            name: "sink".into(),
+            from: Side::A,
+            channel: 0,
+            count: 2,
+            size: 16,
+            timeout: 0,
+            expect: Expect::default(),
+        };
+        let (mut a, mut b) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut tx_regs = Registers::new(crate::Bus::Sink, &mut a);
+        let mut rx_regs = Registers::new(crate::Bus::Sink, &mut b);
+        let mut tx = Radio {
+            registers: &mut tx_regs,
+            config: &config,
+        };
+        let mut rx = Radio {
+            registers: &mut rx_regs,
+            config: &config,
+        };
+        let outcome = run(&step, &mut tx, &mut rx, &crate::gpio::NoSwitch).unwrap();
+        assert_eq!((outcome.sent, outcome.received), (2, 0));
+        assert_eq!(step.expect.check(&outcome).len(), 1);
+
+        let step = Step { channel: 5, ..step };
+        assert!(matches!(
+            run(&step, &mut tx, &mut rx, &crate::gpio::NoSwitch),
+            Err(Error::NoChannel(_, 5))
+        ));
+    }
+
+    #[test]
+    fn run_over_sim() {
+        let config: Config = toml::from_str(include_str!("bin/c3-uhf-96000.toml")).unwrap();
+        let (a, b) = crate::sim::pair(crate::sim::Channel {
+            loss: 0.2,
+            rssi: -70,
+            ..Default::default()
+        });
+        let stats = a.clone();
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut tx_regs = Registers::new(crate::Bus::Sim(a), &mut ca);
+        let mut rx_regs = Registers::new(crate::Bus::Sim(b), &mut cb);
+        config.configure(&mut tx_regs).unwrap();
+        config.configure(&mut rx_regs).unwrap();
+        let step = Step {
+            name: "sim".into(),
+            from: Side::A,
+            channel: 0,
+            count: 20,
+            size: 64,
+            timeout: 0,
+            expect: Expect {
+                pass: 0.5,
+                crc_fail: Some(0),
+                rssi: Some((-80.0, -60.0)),
+                unexpected: Some(0),
+            },
+        };
+        let mut tx = Radio {
+            registers: &mut tx_regs,
+            config: &config,
+        };
+        let mut rx = Radio {
+            registers: &mut rx_regs,
+            config: &config,
+        };
+        let outcome = run(&step, &mut tx, &mut rx, &crate::gpio::NoSwitch).unwrap();
+        let stats = stats.stats();
+        assert_eq!(u64::from(outcome.sent), stats.sent);
+        assert_eq!(u64::from(outcome.received), stats.delivered);
+        assert!(stats.lost > 0);
+        assert_eq!(step.expect.check(&outcome), []);
+    }
+}
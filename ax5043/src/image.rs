@@ -0,0 +1,225 @@
+// Precomputed register writes for switching a configured radio between two setups, e.g. RX on
+// the EDL channel and TX on the beacon channel.
+//
+// Both setups are recorded with dry_run() into an Image, the last value written to each
+// register. Image::delta() keeps only the registers whose value differs and groups them into
+// runs of consecutive addresses, so the switch is a handful of burst writes instead of the
+// register by register path that built them. Record the images again whenever the config
+// behind them changes.
+use crate::{dry_run, Registers, Result};
+use std::{collections::BTreeMap, fmt};
+
+/// Registers that do something when written rather than just holding a value: PWRMODE,
+/// FIFOCMD, FIFODATA and the PLLRANGING start bits. A delta never includes them.
+const SIDE_EFFECTS: [u16; 5] = [0x002, 0x028, 0x029, 0x033, 0x03B];
+
+/// The last value written to each register by some setup, keyed by address
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Image(BTreeMap<u16, Vec<u8>>);
+
+impl Image {
+    /// Runs `setup` against a sink and keeps what it left in each register
+    pub fn record<F>(setup: F) -> Result<Self>
+    where
+        F: FnOnce(&mut Registers) -> Result<()>,
+    {
+        let writes = dry_run(setup)?;
+        Ok(Self(
+            writes
+                .last()
+                .into_iter()
+                .map(|(addr, write)| (addr, write.data.clone()))
+                .collect(),
+        ))
+    }
+
+    pub fn get(&self, addr: u16) -> Option<&[u8]> {
+        self.0.get(&addr).map(Vec::as_slice)
+    }
+
+    /// What to write to a radio set up like self to make it match `to`. Registers `to` never
+    /// wrote keep their value. Unchanged registers between two changed ones in a run are
+    /// written again rather than splitting the burst.
+    pub fn delta(&self, to: &Image) -> Delta {
+        let mut runs = Vec::new();
+        let mut group: Vec<(u16, &[u8], bool)> = Vec::new();
+        for (&addr, data) in to.0.iter() {
+            if SIDE_EFFECTS.contains(&addr) {
+                Delta::close(&mut group, &mut runs);
+                continue;
+            }
+            let follows = group
+                .last()
+                .is_some_and(|&(last, bytes, _)| last + bytes.len() as u16 == addr);
+            if !follows {
+                Delta::close(&mut group, &mut runs);
+            }
+            group.push((addr, data, self.get(addr) != Some(data)));
+        }
+        Delta::close(&mut group, &mut runs);
+        Delta { runs }
+    }
+}
+
+/// One burst write of consecutive registers
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+/// The burst writes that take a radio from one Image to another, see Image::delta()
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Delta {
+    runs: Vec<Run>,
+}
+
+impl Delta {
+    /// Ends a group of consecutive registers, trimmed to the changed ones at either end
+    fn close(group: &mut Vec<(u16, &[u8], bool)>, runs: &mut Vec<Run>) {
+        let first = group.iter().position(|&(_, _, changed)| changed);
+        let last = group.iter().rposition(|&(_, _, changed)| changed);
+        if let (Some(first), Some(last)) = (first, last) {
+            runs.push(Run {
+                addr: group[first].0,
+                data: group[first..=last]
+                    .iter()
+                    .flat_map(|&(_, bytes, _)| bytes.iter().copied())
+                    .collect(),
+            });
+        }
+        group.clear();
+    }
+
+    /// One SPI transaction per run
+    pub fn apply(&self, radio: &mut Registers) -> Result<()> {
+        for run in &self.runs {
+            radio.write_burst(run.addr, &run.data)?;
+        }
+        Ok(())
+    }
+
+    pub fn runs(&self) -> &[Run] {
+        &self.runs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Bytes written in total, registers included again to bridge a run count too
+    pub fn bytes(&self) -> usize {
+        self.runs.iter().map(|run| run.data.len()).sum()
+    }
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} runs, {} bytes", self.runs.len(), self.bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, sim, Bus};
+
+    fn image(regs: &[(u16, &[u8])]) -> Image {
+        Image(regs.iter().map(|&(a, d)| (a, d.to_vec())).collect())
+    }
+
+    #[test]
+    fn runs() {
+        let from = image(&[
+            (0x010, &[1]),
+            (0x011, &[2]),
+            (0x012, &[3, 4]),
+            (0x014, &[5]),
+            (0x020, &[6]),
+            (0x002, &[0x09]),
+        ]);
+        let to = image(&[
+            (0x010, &[1]),    // unchanged, trimmed off the front
+            (0x011, &[9]),    // changed
+            (0x012, &[3, 4]), // unchanged, bridged
+            (0x014, &[8]),    // changed
+            (0x020, &[6]),    // unchanged on its own
+            (0x030, &[7, 7]), // never written by `from`
+            (0x002, &[0x0D]), // PWRMODE, left out
+        ]);
+        let delta = from.delta(&to);
+        assert_eq!(
+            delta.runs(),
+            [
+                Run {
+                    addr: 0x011,
+                    data: vec![9, 3, 4, 8]
+                },
+                Run {
+                    addr: 0x030,
+                    data: vec![7, 7]
+                },
+            ]
+        );
+        assert_eq!(delta.bytes(), 6);
+        assert!(to.delta(&to).is_empty());
+    }
+
+    /// Switching a configured radio by delta leaves it like configuring it for the other
+    /// setup from scratch
+    #[test]
+    fn matches_full_configure() {
+        let config: Config = toml::from_str(include_str!("bin/c3-uhf-96000.toml")).unwrap();
+        let tx = config.tx.unwrap();
+        let rx_image = Image::record(|radio| config.configure(radio)).unwrap();
+        let beacon = |radio: &mut Registers| {
+            config.configure(radio)?;
+            let params = config.channel[1].write(radio, &config.board)?;
+            tx.write(radio, &config.board, &params)?;
+            Ok(())
+        };
+        let tx_image = Image::record(beacon).unwrap();
+        let to_tx = rx_image.delta(&tx_image);
+        let to_rx = tx_image.delta(&rx_image);
+        assert!(!to_tx.is_empty());
+
+        let (a, b) = sim::pair(sim::Channel::default());
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut switched = Registers::new(Bus::Sim(a), &mut ca);
+        let mut direct = Registers::new(Bus::Sim(b), &mut cb);
+        config.configure(&mut switched).unwrap();
+        beacon(&mut direct).unwrap();
+
+        let writes = dry_run(|radio| to_tx.apply(radio)).unwrap();
+        assert_eq!(writes.len(), to_tx.runs().len());
+        to_tx.apply(&mut switched).unwrap();
+        for (&addr, data) in tx_image.0.iter() {
+            if SIDE_EFFECTS.contains(&addr) {
+                continue;
+            }
+            let len = data.len();
+            assert_eq!(
+                switched.read_burst(addr, len).unwrap(),
+                direct.read_burst(addr, len).unwrap(),
+                "{:?}",
+                Registers::name(addr)
+            );
+        }
+
+        // And back, for everything RX set up
+        to_rx.apply(&mut switched).unwrap();
+        config.configure(&mut direct).unwrap();
+        for (&addr, data) in rx_image.0.iter() {
+            if SIDE_EFFECTS.contains(&addr) {
+                continue;
+            }
+            let len = data.len();
+            assert_eq!(
+                switched.read_burst(addr, len).unwrap(),
+                direct.read_burst(addr, len).unwrap(),
+                "{:?}",
+                Registers::name(addr)
+            );
+        }
+    }
+}
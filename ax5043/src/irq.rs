@@ -0,0 +1,362 @@
+// What the radio raises its IRQ line for, and routing each source to a handler once it does.
+//
+// IRQMASK has a bit per source, several of which only mean something with a second register
+// set up: the FIFO thresholds compare against FIFOTHRESH, RADIOCTRL fires for the radio events
+// in RADIOEVENTMASK and POWER for the supplies in POWIRQMASK. IrqConfig writes all of them
+// together, IRQMASK last so nothing fires half configured:
+//
+//   IrqConfig {
+//       fifo: IRQ::FIFOTHRCNT | IRQ::FIFOERROR,
+//       fifo_threshold: Some(128),
+//       power: PowIRQMask::VMODEM | PowIRQMask::VANA,
+//       ..IrqConfig::default()
+//   }
+//   .write(radio)?;
+//
+// Dispatcher then reads IRQREQUEST on each edge and calls the handler registered for every
+// pending source with an Event carrying what goes with it. Reading the status register that
+// explains a source clears it for RADIOCTRL (RADIOEVENTREQ) and POWER (POWSTICKYSTAT), so
+// those are read here and handed over rather than left for the handler. The FIFO, PLL and
+// crystal sources are levels: they stay pending until the FIFO is drained or the handler
+// masks them. Pending sources without a handler are counted in Dispatcher::unhandled().
+use crate::{registers::*, Error, Registers, Result, RX, TX};
+use std::fmt;
+
+/// The FIFO bits of IRQ
+pub const FIFO: IRQ = IRQ::FIFONOTEMPTY
+    .union(IRQ::FIFONOTFULL)
+    .union(IRQ::FIFOTHRCNT)
+    .union(IRQ::FIFOTHRFREE)
+    .union(IRQ::FIFOERROR);
+
+/// Every interrupt source, the FIFO ones together
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Source {
+    Fifo,
+    PllUnlock,
+    RadioCtrl,
+    Power,
+    XtalReady,
+    WakeupTimer,
+    Lposc,
+    Gpadc,
+    PllRangeDone,
+}
+
+impl Source {
+    pub const ALL: [Source; 9] = [
+        Source::Fifo,
+        Source::PllUnlock,
+        Source::RadioCtrl,
+        Source::Power,
+        Source::XtalReady,
+        Source::WakeupTimer,
+        Source::Lposc,
+        Source::Gpadc,
+        Source::PllRangeDone,
+    ];
+
+    /// Its bits in IRQMASK and IRQREQUEST
+    pub fn irq(self) -> IRQ {
+        match self {
+            Source::Fifo => FIFO,
+            Source::PllUnlock => IRQ::PLLUNLOCK,
+            Source::RadioCtrl => IRQ::RADIOCTRL,
+            Source::Power => IRQ::POWER,
+            Source::XtalReady => IRQ::XTALREADY,
+            Source::WakeupTimer => IRQ::WAKEUPTIMER,
+            Source::Lposc => IRQ::LPOSC,
+            Source::Gpadc => IRQ::GPADC,
+            Source::PllRangeDone => IRQ::PLLRNGDONE,
+        }
+    }
+}
+
+/// Everything that decides what raises the IRQ line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IrqConfig {
+    /// Any of the FIFO bits
+    pub fifo: IRQ,
+    /// FIFOTHRESH for FIFOTHRCNT and FIFOTHRFREE, bytes
+    pub fifo_threshold: Option<u16>,
+    pub pll_unlock: bool,
+    /// RADIOCTRL for these events, none for RADIOCTRL off
+    pub radio: RadioEvent,
+    /// POWER for these supplies, none for POWER off
+    pub power: PowIRQMask,
+    pub xtal_ready: bool,
+    pub wakeup_timer: bool,
+    pub lposc: bool,
+    pub gpadc: bool,
+    pub pll_range_done: bool,
+}
+
+impl Default for IrqConfig {
+    /// Nothing enabled
+    fn default() -> Self {
+        Self {
+            fifo: IRQ::empty(),
+            fifo_threshold: None,
+            pll_unlock: false,
+            radio: RadioEvent::empty(),
+            power: PowIRQMask::empty(),
+            xtal_ready: false,
+            wakeup_timer: false,
+            lposc: false,
+            gpadc: false,
+            pll_range_done: false,
+        }
+    }
+}
+
+impl IrqConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !FIFO.contains(self.fifo) {
+            return Err(Error::Irq("fifo takes only the FIFO bits"));
+        }
+        let thresholds = self.fifo.intersects(IRQ::FIFOTHRCNT | IRQ::FIFOTHRFREE);
+        match self.fifo_threshold {
+            None if thresholds => Err(Error::Irq("FIFO threshold IRQs need fifo_threshold")),
+            Some(t) if t == 0 || usize::from(t) >= crate::rx::FIFO_SIZE => {
+                Err(Error::Irq("fifo_threshold must be inside the FIFO"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// IRQMASK for this
+    pub fn mask(&self) -> IRQ {
+        let mut mask = self.fifo;
+        let flags = [
+            (self.pll_unlock, IRQ::PLLUNLOCK),
+            (!self.radio.is_empty(), IRQ::RADIOCTRL),
+            (!self.power.is_empty(), IRQ::POWER),
+            (self.xtal_ready, IRQ::XTALREADY),
+            (self.wakeup_timer, IRQ::WAKEUPTIMER),
+            (self.lposc, IRQ::LPOSC),
+            (self.gpadc, IRQ::GPADC),
+            (self.pll_range_done, IRQ::PLLRNGDONE),
+        ];
+        for (on, irq) in flags {
+            mask.set(irq, on);
+        }
+        mask
+    }
+
+    pub fn write(&self, radio: &mut Registers) -> Result<()> {
+        self.validate()?;
+        if let Some(threshold) = self.fifo_threshold {
+            radio.FIFOTHRESH().write(threshold)?;
+        }
+        radio.RADIOEVENTMASK().write(self.radio)?;
+        radio.POWIRQMASK().write(self.power)?;
+        radio.IRQMASK().write(self.mask())?;
+        Ok(())
+    }
+}
+
+/// A pending source and what was read to go with it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The FIFO bits that were pending
+    Fifo(IRQ),
+    PllUnlock,
+    /// RADIOEVENTREQ, cleared by reading it
+    RadioCtrl(RadioEvent),
+    /// POWSTICKYSTAT, cleared by reading it, and POWSTAT right after
+    Power {
+        sticky: PowStat,
+        now: PowStat,
+    },
+    XtalReady,
+    WakeupTimer,
+    /// LPOSCSTATUS
+    Lposc(u8),
+    /// GPADC13VALUE
+    Gpadc(u16),
+    PllRangeDone,
+}
+
+impl Event {
+    pub fn source(&self) -> Source {
+        match self {
+            Event::Fifo(_) => Source::Fifo,
+            Event::PllUnlock => Source::PllUnlock,
+            Event::RadioCtrl(_) => Source::RadioCtrl,
+            Event::Power { .. } => Source::Power,
+            Event::XtalReady => Source::XtalReady,
+            Event::WakeupTimer => Source::WakeupTimer,
+            Event::Lposc(_) => Source::Lposc,
+            Event::Gpadc(_) => Source::Gpadc,
+            Event::PllRangeDone => Source::PllRangeDone,
+        }
+    }
+
+    /// Reads what goes with `source`, `pending` being IRQREQUEST
+    fn read(radio: &mut Registers, source: Source, pending: IRQ) -> Result<Self> {
+        Ok(match source {
+            Source::Fifo => Event::Fifo(pending & FIFO),
+            Source::PllUnlock => Event::PllUnlock,
+            Source::RadioCtrl => Event::RadioCtrl(radio.RADIOEVENTREQ().read()?),
+            Source::Power => Event::Power {
+                sticky: radio.POWSTICKYSTAT().read()?,
+                now: radio.POWSTAT().read()?,
+            },
+            Source::XtalReady => Event::XtalReady,
+            Source::WakeupTimer => Event::WakeupTimer,
+            Source::Lposc => Event::Lposc(radio.LPOSCSTATUS().read()?),
+            Source::Gpadc => Event::Gpadc(radio.GPADC13VALUE().read()?),
+            Source::PllRangeDone => Event::PllRangeDone,
+        })
+    }
+}
+
+type Handler = Box<dyn FnMut(&mut Registers, Event) -> Result<()>>;
+
+/// Routes pending sources to their handlers, one handler per source
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: Vec<(Source, Handler)>,
+    unhandled: u64,
+}
+
+impl fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sources: Vec<_> = self.handlers.iter().map(|(source, _)| source).collect();
+        f.debug_struct("Dispatcher")
+            .field("handlers", &sources)
+            .field("unhandled", &self.unhandled)
+            .finish()
+    }
+}
+
+impl Dispatcher {
+    /// Calls `handler` for `source` from now on, replacing any handler it had
+    pub fn on<F>(mut self, source: Source, handler: F) -> Self
+    where
+        F: FnMut(&mut Registers, Event) -> Result<()> + 'static,
+    {
+        self.handlers.retain(|(s, _)| *s != source);
+        self.handlers.push((source, Box::new(handler)));
+        self
+    }
+
+    /// The sources with a handler, to check an IrqConfig against
+    pub fn handled(&self) -> IRQ {
+        self.handlers
+            .iter()
+            .fold(IRQ::empty(), |irq, (source, _)| irq | source.irq())
+    }
+
+    /// Pending sources that had no handler, since the start
+    pub fn unhandled(&self) -> u64 {
+        self.unhandled
+    }
+
+    /// Reads IRQREQUEST and handles what's pending, in Source::ALL order. Returns what was
+    /// pending, empty for an edge that had already been dealt with.
+    pub fn dispatch(&mut self, radio: &mut Registers) -> Result<IRQ> {
+        let pending = radio.IRQREQUEST().read()?;
+        self.route(radio, pending)?;
+        Ok(pending)
+    }
+
+    fn route(&mut self, radio: &mut Registers, pending: IRQ) -> Result<()> {
+        for source in Source::ALL {
+            if !pending.intersects(source.irq()) {
+                continue;
+            }
+            let event = Event::read(radio, source, pending)?;
+            match self.handlers.iter_mut().find(|(s, _)| *s == source) {
+                Some((_, handler)) => handler(radio, event)?,
+                None => self.unhandled += 1,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn config() {
+        let config = IrqConfig {
+            fifo: IRQ::FIFOTHRCNT | IRQ::FIFOERROR,
+            fifo_threshold: Some(128),
+            radio: RadioEvent::DONE,
+            power: PowIRQMask::VMODEM,
+            lposc: true,
+            ..IrqConfig::default()
+        };
+        assert_eq!(
+            config.mask(),
+            IRQ::FIFOTHRCNT | IRQ::FIFOERROR | IRQ::RADIOCTRL | IRQ::POWER | IRQ::LPOSC
+        );
+        let writes = crate::dry_run(|radio| config.write(radio)).unwrap();
+        assert_eq!(writes.to("FIFOTHRESH")[0].data, [0x00, 0x80]);
+        assert_eq!(writes.to("RADIOEVENTMASK")[0].data, [0x00, 0x01]);
+        assert_eq!(writes.to("POWIRQMASK")[0].data, [0x08]);
+        assert_eq!(writes.to("IRQMASK")[0].data, [0x04, 0xD4]);
+        assert!(writes.in_order(&["FIFOTHRESH", "POWIRQMASK", "IRQMASK"]));
+
+        let off = crate::dry_run(|radio| IrqConfig::default().write(radio)).unwrap();
+        assert_eq!(off.to("IRQMASK")[0].data, [0, 0]);
+        assert!(off.to("FIFOTHRESH").is_empty());
+
+        let no_threshold = IrqConfig {
+            fifo: IRQ::FIFOTHRFREE,
+            ..IrqConfig::default()
+        };
+        assert!(no_threshold.validate().is_err());
+        let not_fifo = IrqConfig {
+            fifo: IRQ::POWER,
+            ..IrqConfig::default()
+        };
+        assert!(not_fifo.validate().is_err());
+    }
+
+    #[test]
+    fn dispatch() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let (fifo, power) = (seen.clone(), seen.clone());
+        let mut dispatcher = Dispatcher::default()
+            .on(Source::Fifo, |_, _| Ok(()))
+            .on(Source::Fifo, move |_, event| {
+                fifo.borrow_mut().push(event);
+                Ok(())
+            })
+            .on(Source::Power, move |_, event| {
+                power.borrow_mut().push(event);
+                Ok(())
+            });
+        assert_eq!(dispatcher.handled(), FIFO | IRQ::POWER);
+
+        let pending = IRQ::FIFONOTEMPTY | IRQ::FIFOTHRCNT | IRQ::POWER | IRQ::GPADC;
+        let writes = crate::dry_run(|radio| dispatcher.route(radio, pending)).unwrap();
+        assert!(writes.names().is_empty());
+        assert_eq!(
+            *seen.borrow(),
+            [
+                Event::Fifo(IRQ::FIFONOTEMPTY | IRQ::FIFOTHRCNT),
+                Event::Power {
+                    sticky: PowStat::empty(),
+                    now: PowStat::empty()
+                },
+            ]
+        );
+        assert_eq!(dispatcher.unhandled(), 1);
+
+        // An idle radio has nothing pending
+        seen.borrow_mut().clear();
+        crate::dry_run(|radio| {
+            assert_eq!(dispatcher.dispatch(radio)?, IRQ::empty());
+            Ok(())
+        })
+        .unwrap();
+        assert!(seen.borrow().is_empty());
+        assert_eq!(Event::Gpadc(7).source(), Source::Gpadc);
+    }
+}
@@ -1,16 +1,51 @@
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 use spidev::{SpiModeFlags, Spidev, SpidevOptions, SpidevTransfer};
-use std::{
-    collections::VecDeque, convert::TryFrom, fmt::Debug, io::Read, marker::PhantomData, path::Path,
-};
+use std::{convert::TryFrom, fmt::Debug, marker::PhantomData, path::Path};
 use thiserror::Error;
+use tracing::{debug, trace};
 
 use registers::*;
 
+pub mod agc;
+pub mod auth;
+pub mod capture;
 pub mod config;
+pub mod control;
+pub mod convolutional;
+pub mod demod;
+pub mod discover;
+pub mod fallback;
+pub mod filter;
+pub mod gpio;
+pub mod guard;
+#[cfg(feature = "hitl")]
+pub mod hitl;
+pub mod image;
+pub mod irq;
+pub mod logging;
+pub mod pass;
+pub mod power;
+pub mod pps;
+pub mod recording;
+pub mod reedsolomon;
 pub mod registers;
+pub mod regmap;
+pub mod rejects;
+pub mod rx;
+pub mod scanlist;
+pub mod schedule;
+pub mod sim;
+pub mod spectrum;
+pub mod spool;
+pub mod state;
+pub mod station;
+pub mod supply;
+pub mod telemetry;
+pub mod thermal;
 pub mod tui;
+pub mod tx;
+pub mod watchdog;
 
 // TODO: repurpose for fs/ccsds?
 // GOALS: device state tracking, bind transport to state tracker
@@ -87,6 +122,34 @@ bitflags! {
     }
 }
 
+impl std::fmt::Display for Status {
+    /// The flags that are set, in words: "ready, PLL locked, FIFO empty, power good, crystal
+    /// running"
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        registers::write_words(
+            f,
+            self,
+            &[
+                (Status::READY, "ready"),
+                (Status::PLL_LOCK, "PLL locked"),
+                (Status::FIFO_OVER, "FIFO overflow"),
+                (Status::FIFO_UNDER, "FIFO underflow"),
+                (Status::THRESHOLD_FREE, "FIFO free above threshold"),
+                (Status::THRESHOLD_COUNT, "FIFO count above threshold"),
+                (Status::FIFO_FULL, "FIFO full"),
+                (Status::FIFO_EMPTY, "FIFO empty"),
+                (Status::PWR_GOOD, "power good"),
+                (Status::PWR_INTERRUPT, "power interrupt"),
+                (Status::RADIO_EVENT, "radio event"),
+                (Status::XTAL_OSC_RUNNING, "crystal running"),
+                (Status::WAKEUP_INTERRUPT, "wakeup interrupt"),
+                (Status::LPOSC_INTERRUPT, "LPOSC interrupt"),
+                (Status::GPADC_INTERRUPT, "GPADC interrupt"),
+            ],
+        )
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("SPI communication failed")]
@@ -101,14 +164,217 @@ pub enum Error {
     Status([u8; 2]),
     #[error("Autoranging failed")]
     Autorange, // TODO: A vs B
+    #[error("Radio probe: {0}")]
+    Probe(Probe),
+    #[error("SPI self-test: {0}")]
+    Spi(SpiFault),
+    #[error("Register {0:#05X} isn't in the burst read")]
+    NotInBurst(u16),
+    #[error("TX amplitude shaping: {0}")]
+    Shaping(&'static str),
+    #[error("{0} dBm is outside the TX power calibration")]
+    PowerRange(f64),
+    #[error("Thermal compensation: {0}")]
+    Thermal(&'static str),
+    #[error("DCLK/DATA pins: {0}")]
+    Pins(&'static str),
+    #[error("Transmit slot missed by {0:?}")]
+    Late(std::time::Duration),
+    #[error("RX bandwidth {0} Hz doesn't fit the datarate and crystal")]
+    Bandwidth(u64),
+    #[error("Framing: {0}")]
+    Framing(&'static str),
+    #[error("Packet length: {0}")]
+    Length(&'static str),
+    #[error("Scan list: {0}")]
+    Scan(&'static str),
+    #[error("PPS: {0}")]
+    Pps(&'static str),
+    #[error("Reed-Solomon: {0}")]
+    ReedSolomon(&'static str),
+    #[error("Convolutional code: {0}")]
+    Convolutional(&'static str),
+    #[error("Address filter: {0}")]
+    Address(&'static str),
+    #[error("Fallback: {0}")]
+    Fallback(&'static str),
+    #[error("IRQ config: {0}")]
+    Irq(&'static str),
+    #[error("FIFO policy: {0}")]
+    Fifo(&'static str),
+    #[error("No [[channel]] {0}")]
+    NoChannel(usize),
+    #[error("Section [{0}] required")]
+    Section(&'static str),
+    #[error("Invalid config setting: {0}")]
+    OutOfRange(#[from] registers::OutOfRange),
     #[error("Invalid config setting")]
     Invalid, // FIXME: this is a generic catchall, should always be made specific
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// What Registers talks to: a real spidev, a sink for running configuration code without
+/// hardware (see dry_run()), or one of a pair of simulated radios (see sim::pair())
+pub enum Bus {
+    Spidev(Spidev),
+    /// Drops writes and answers reads as an idle, freshly reset radio would, enough that
+    /// polling loops like autoranging complete
+    Sink,
+    Sim(sim::Port),
+}
+
+impl From<Spidev> for Bus {
+    fn from(spi: Spidev) -> Self {
+        Bus::Spidev(spi)
+    }
+}
+
+impl Bus {
+    /// One transaction: the address header clocks out the status, then the data
+    fn transfer(
+        &self,
+        addr: &[u8],
+        stat: &mut [u8],
+        tx: &[u8],
+        rx: &mut [u8],
+    ) -> std::io::Result<()> {
+        match self {
+            Bus::Spidev(spi) => spi.transfer_multiple(&mut [
+                SpidevTransfer::read_write(addr, stat),
+                SpidevTransfer::read_write(tx, rx),
+            ]),
+            Bus::Sink => {
+                stat.fill(0);
+                rx.fill(0);
+                // Reads have the top (write) bit clear, bursts (see read_burst()) cover
+                // consecutive registers
+                if addr[0] & 0x80 == 0 {
+                    let start = u16::from_be_bytes([addr[0], addr[1]]) & 0x0FFF;
+                    for (reg, b) in (start..).zip(rx.iter_mut()) {
+                        *b = match reg {
+                            0x000 => 0x51,                        // REVISION
+                            0x01D => XtalStatus::XTAL_RUN.bits(), // XTALSTATUS
+                            // FIFOSTAT, empty so tx::transmit doesn't wait for room
+                            0x028 => (FIFOStat::EMPTY | FIFOStat::FREE_THR).bits(),
+                            _ => 0,
+                        };
+                    }
+                }
+                Ok(())
+            }
+            Bus::Sim(port) => port.transfer(addr, stat, tx, rx),
+        }
+    }
+}
+
+/// One register write as seen by dry_run()
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterWrite {
+    pub name: &'static str,
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Display for RegisterWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:<16} {:03X} ", self.name, self.addr)?;
+        for b in &self.data {
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every register write a dry_run() made, in order. Compare two of them (see first_difference())
+/// to check a refactor of the config code doesn't change what the radio sees.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Writes(pub Vec<RegisterWrite>);
+
+impl Writes {
+    /// Register names in write order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.0.iter().map(|w| w.name).collect()
+    }
+
+    /// The writes to one register, in order
+    pub fn to(&self, name: &str) -> Vec<&RegisterWrite> {
+        self.0.iter().filter(|w| w.name == name).collect()
+    }
+
+    /// Whether `names` were written in this order, not necessarily back to back
+    pub fn in_order(&self, names: &[&str]) -> bool {
+        let mut written = self.0.iter();
+        names.iter().all(|&name| written.any(|w| w.name == name))
+    }
+
+    /// The register file once done: the last write to each register, in address order
+    pub fn last(&self) -> std::collections::BTreeMap<u16, &RegisterWrite> {
+        self.0.iter().map(|w| (w.addr, w)).collect()
+    }
+
+    /// Index of the first write that differs from `other`'s, with what each side wrote there
+    /// (None past the end of the shorter one)
+    pub fn first_difference<'w>(
+        &'w self,
+        other: &'w Writes,
+    ) -> Option<(usize, Option<&'w RegisterWrite>, Option<&'w RegisterWrite>)> {
+        (0..self.0.len().max(other.0.len()))
+            .map(|i| (i, self.0.get(i), other.0.get(i)))
+            .find(|(_, a, b)| a != b)
+    }
+}
+
+impl std::ops::Deref for Writes {
+    type Target = [RegisterWrite];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl IntoIterator for Writes {
+    type Item = RegisterWrite;
+    type IntoIter = std::vec::IntoIter<RegisterWrite>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// One write per line, the format of the golden files in src/bin
+impl std::fmt::Display for Writes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for write in &self.0 {
+            writeln!(f, "{}", write)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `configure` against a sink instead of a radio and returns every register write it made,
+/// in order, for reviewing a config without hardware. Reads return idle values, see Bus::Sink.
+pub fn dry_run<F>(configure: F) -> Result<Writes>
+where
+    F: FnOnce(&mut Registers) -> Result<()>,
+{
+    let mut writes = Vec::new();
+    let mut callback = |_: &_, addr: u16, _, data: &[u8]| {
+        if addr & 0x8000 != 0 {
+            let addr = addr & 0x0FFF;
+            writes.push(RegisterWrite {
+                name: Registers::name(addr).unwrap_or("?"),
+                addr,
+                data: data.to_vec(),
+            });
+        }
+    };
+    let mut radio = Registers::new(Bus::Sink, &mut callback);
+    configure(&mut radio)?;
+    drop(radio);
+    Ok(Writes(writes))
+}
+
 pub trait IO {
-    fn spi(&self) -> &Spidev;
+    fn spi(&self) -> &Bus;
     fn addr(&self) -> u16;
     fn on_status(&mut self, addr: u16, status: Status, data: &[u8]);
 }
@@ -116,21 +382,48 @@ pub trait IO {
 pub trait RX<const S: usize>: IO {
     type Value: TryFrom<Reg<S>>;
     fn read(&mut self) -> Result<Self::Value> {
+        self.read_raw()?.try_into().map_err(|_| Error::Decode)
+    }
+
+    /// Reads the register without decoding it, for when the bytes themselves are of interest
+    fn read_raw(&mut self) -> Result<Reg<S>> {
         let addr = (self.addr() | 0x7000).to_be_bytes();
         let mut stat = [0; 2];
 
         let tx = [0; S];
         let mut rx = [0; S];
-        self.spi().transfer_multiple(&mut [
-            SpidevTransfer::read_write(&addr, &mut stat),
-            SpidevTransfer::read_write(&tx, &mut rx),
-        ])?;
+        self.spi().transfer(&addr, &mut stat, &tx, &mut rx)?;
 
         let status = Status::from_bits(u16::from_be_bytes(stat)).ok_or(Error::Status(stat))?;
-        let data = Reg(rx).try_into().map_err(|_| Error::Decode)?;
 
+        trace!(target: "ax5043::spi", "read {:03X}: {:02X?} {:?}", self.addr(), rx, status);
         self.on_status(u16::from_be_bytes(addr), status, &rx);
-        Ok(data)
+        Ok(Reg(rx))
+    }
+
+    /// Decodes the register out of a Registers::read_burst() covering it, no SPI involved
+    fn read_from(&mut self, burst: &Burst) -> Result<Self::Value> {
+        let raw = burst
+            .get(self.addr(), S)
+            .ok_or(Error::NotInBurst(self.addr()))?;
+        let mut rx = [0; S];
+        rx.copy_from_slice(raw);
+        Reg(rx).try_into().map_err(|_| Error::Decode)
+    }
+}
+
+/// Consecutive registers read in one transaction by Registers::read_burst()
+#[derive(Clone, Debug, PartialEq)]
+pub struct Burst {
+    addr: u16,
+    data: Vec<u8>,
+}
+
+impl Burst {
+    /// The `len` bytes of the register at `addr`, if the burst covers all of them
+    pub fn get(&self, addr: u16, len: usize) -> Option<&[u8]> {
+        let start = usize::from(addr.checked_sub(self.addr)?);
+        self.data.get(start..start + len)
     }
 }
 
@@ -143,13 +436,11 @@ pub trait TX<const S: usize>: IO {
         let tx = value.into().0;
         let mut rx: [u8; S] = [0; S];
 
-        self.spi().transfer_multiple(&mut [
-            SpidevTransfer::read_write(&addr, &mut stat),
-            SpidevTransfer::read_write(&tx, &mut rx),
-        ])?;
+        self.spi().transfer(&addr, &mut stat, &tx, &mut rx)?;
         //assert_eq!(rx, [0; S]); fails TODO: what does this return? Old value? check that it
         //matches our previous known state?
         let status = Status::from_bits(u16::from_be_bytes(stat)).ok_or(Error::Status(stat))?;
+        trace!(target: "ax5043::spi", "write {:03X}: {:02X?} {:?}", self.addr(), tx, status);
         self.on_status(u16::from_be_bytes(addr), status, &tx);
         Ok(())
     }
@@ -157,13 +448,13 @@ pub trait TX<const S: usize>: IO {
 
 pub struct ReadWrite<'a, const S: usize, V: TryFrom<Reg<S>> + Into<Reg<S>>> {
     data: PhantomData<V>,
-    spi: &'a Spidev,
+    spi: &'a Bus,
     addr: u16,
-    on_status: &'a mut dyn FnMut(&Spidev, u16, Status, &[u8]),
+    on_status: &'a mut dyn FnMut(&Bus, u16, Status, &[u8]),
 }
 
 impl<const S: usize, V: TryFrom<Reg<S>> + Into<Reg<S>>> IO for ReadWrite<'_, S, V> {
-    fn spi(&self) -> &Spidev {
+    fn spi(&self) -> &Bus {
         self.spi
     }
     fn addr(&self) -> u16 {
@@ -184,13 +475,13 @@ impl<const S: usize, V: TryFrom<Reg<S>> + Into<Reg<S>>> TX<S> for ReadWrite<'_,
 
 pub struct ReadOnly<'a, const S: usize, V: TryFrom<Reg<S>>> {
     data: PhantomData<V>,
-    spi: &'a Spidev,
+    spi: &'a Bus,
     addr: u16,
-    on_status: &'a mut dyn FnMut(&Spidev, u16, Status, &[u8]),
+    on_status: &'a mut dyn FnMut(&Bus, u16, Status, &[u8]),
 }
 
 impl<const S: usize, V: TryFrom<Reg<S>>> IO for ReadOnly<'_, S, V> {
-    fn spi(&self) -> &Spidev {
+    fn spi(&self) -> &Bus {
         self.spi
     }
     fn addr(&self) -> u16 {
@@ -207,13 +498,13 @@ impl<const S: usize, V: TryFrom<Reg<S>>> RX<S> for ReadOnly<'_, S, V> {
 
 pub struct WriteOnly<'a, const S: usize, V: Into<Reg<S>>> {
     data: PhantomData<V>,
-    spi: &'a Spidev,
+    spi: &'a Bus,
     addr: u16,
-    on_status: &'a mut dyn FnMut(&Spidev, u16, Status, &[u8]),
+    on_status: &'a mut dyn FnMut(&Bus, u16, Status, &[u8]),
 }
 
 impl<const S: usize, V: Into<Reg<S>>> IO for WriteOnly<'_, S, V> {
-    fn spi(&self) -> &Spidev {
+    fn spi(&self) -> &Bus {
         self.spi
     }
     fn addr(&self) -> u16 {
@@ -228,11 +519,64 @@ impl<const S: usize, V: Into<Reg<S>>> TX<S> for WriteOnly<'_, S, V> {
     type Value = V;
 }
 
+/// Length of the chunk at the start of `bytes`, header included. Doesn't check that the rest
+/// of the chunk made it into `bytes`.
+pub fn chunk_len(bytes: &[u8]) -> Result<usize> {
+    let Some(&header) = bytes.first() else {
+        return Err(Error::DecodeBytes(Vec::new()));
+    };
+    #[rustfmt::skip]
+    let len = match FIFOChunkHeaderRX::try_from(header) {
+        Ok(FIFOChunkHeaderRX::RSSI)       => 2,
+        Ok(FIFOChunkHeaderRX::FREQOFFS)   => 3,
+        Ok(FIFOChunkHeaderRX::ANTRSSI2)   => 3,
+        Ok(FIFOChunkHeaderRX::TIMER)      => 4,
+        Ok(FIFOChunkHeaderRX::RFFREQOFFS) => 4,
+        Ok(FIFOChunkHeaderRX::DATARATE)   => 4,
+        Ok(FIFOChunkHeaderRX::ANTRSSI3)   => 4,
+        // The length byte may not have made it into this read
+        Ok(FIFOChunkHeaderRX::DATA)       => match bytes.get(1) {
+            Some(&length) => usize::from(length) + 2,
+            None => return Err(Error::DecodeBytes(bytes.to_vec())),
+        },
+        Err(_) => return Err(Error::FIFOHeader(bytes.to_vec())),
+    };
+    Ok(len)
+}
+
+/// Splits bytes read from FIFODATA into chunks. The bytes come straight off the radio, so
+/// anything (truncated chunks, bad headers, noise) has to come back as an error instead of a
+/// panic, see fuzz/fuzz_targets/fifo_chunk_rx.rs.
+pub fn split_chunks<V: TryFrom<Vec<u8>>>(rx: &[u8]) -> Result<Vec<V>> {
+    let mut chunks: Vec<V> = Vec::new();
+
+    let mut rest = rx;
+    while !rest.is_empty() {
+        let chunksize = chunk_len(rest).inspect_err(|e| {
+            if let Error::FIFOHeader(_) = e {
+                debug!(target: "ax5043::fifo", "bad header, FIFO contents {:02X?}", rx);
+            }
+        })?;
+        if rest.len() < chunksize {
+            return Err(Error::DecodeBytes(rest.to_vec()));
+        }
+        let (chunk, tail) = rest.split_at(chunksize);
+        chunks.push(
+            chunk
+                .to_vec()
+                .try_into()
+                .map_err(|_| Error::DecodeBytes(chunk.to_vec()))?,
+        );
+        rest = tail;
+    }
+    Ok(chunks)
+}
+
 pub struct ReadFIFO<'a, const S: usize, V: TryFrom<Vec<u8>>> {
     data: PhantomData<V>,
-    spi: &'a Spidev,
+    spi: &'a Bus,
     addr: u16,
-    on_status: &'a mut dyn FnMut(&Spidev, u16, Status, &[u8]),
+    on_status: &'a mut dyn FnMut(&Bus, u16, Status, &[u8]),
 }
 
 impl<const S: usize, V: TryFrom<Vec<u8>>> ReadFIFO<'_, S, V> {
@@ -244,9 +588,6 @@ impl<const S: usize, V: TryFrom<Vec<u8>>> ReadFIFO<'_, S, V> {
     where
         <V as TryFrom<Vec<u8>>>::Error: Debug,
     {
-        let addr = (self.addr | 0x7000).to_be_bytes();
-        let mut stat = [0; 2];
-
         // All RX chunks are either a fixed length of at least 2 bytes or
         // a variable length with the length in the second byte, so I'd
         // like to do a read of 2 and then read the remaining chunk, but the
@@ -254,53 +595,33 @@ impl<const S: usize, V: TryFrom<Vec<u8>>> ReadFIFO<'_, S, V> {
         // remaining chunk. Instead this reads all the bytes that FIFOCOUNT
         // says are available and then breaks it into one or more chunks.
         // TODO: figure out why and if we can just read one chunk at a time.
-        let tx = vec![0; len];
         let mut rx = vec![0; len];
+        self.read_raw(&mut rx)?;
+        split_chunks(&rx)
+    }
 
-        self.spi.transfer_multiple(&mut [
-            SpidevTransfer::read_write(&addr, &mut stat),
-            SpidevTransfer::read_write(&tx, &mut rx),
-        ])?;
-        let mut chunks: Vec<V> = Vec::new();
-
-        let mut bytes = VecDeque::from(rx.clone());
-        while !bytes.is_empty() {
-            #[rustfmt::skip]
-            let chunksize: usize = match FIFOChunkHeaderRX::try_from(bytes[0]) {
-                Ok(FIFOChunkHeaderRX::RSSI)       => 2,
-                Ok(FIFOChunkHeaderRX::FREQOFFS)   => 3,
-                Ok(FIFOChunkHeaderRX::ANTRSSI2)   => 3,
-                Ok(FIFOChunkHeaderRX::TIMER)      => 4,
-                Ok(FIFOChunkHeaderRX::RFFREQOFFS) => 4,
-                Ok(FIFOChunkHeaderRX::DATARATE)   => 4,
-                Ok(FIFOChunkHeaderRX::ANTRSSI3)   => 4,
-                Ok(FIFOChunkHeaderRX::DATA)       => usize::from(bytes[1]) + 2,
-                Err(_) => return Err(Error::FIFOHeader(bytes.clone().into())),
-            };
-
-            if bytes.len() < chunksize {
-                return Err(Error::DecodeBytes(bytes.clone().into()));
-            }
-            let mut chunk = vec![0; chunksize];
-            bytes.read_exact(&mut chunk)?;
-            chunks.push(
-                chunk
-                    .clone()
-                    .try_into()
-                    .map_err(|_| Error::DecodeBytes(chunk.clone()))?,
-            );
+    /// Fills `rx` straight from the FIFO without splitting it into chunks or allocating, for
+    /// the RX hot path. Anything past the 256 byte FIFO takes another transfer.
+    pub fn read_raw(&mut self, rx: &mut [u8]) -> Result<()> {
+        const ZEROS: [u8; 256] = [0; 256];
+        let addr = (self.addr | 0x7000).to_be_bytes();
+        for rx in rx.chunks_mut(ZEROS.len()) {
+            let mut stat = [0; 2];
+            self.spi
+                .transfer(&addr, &mut stat, &ZEROS[..rx.len()], rx)?;
+            let status = Status::from_bits(u16::from_be_bytes(stat)).ok_or(Error::Status(stat))?;
+            trace!(target: "ax5043::fifo", "read {:02X?} {:?}", rx, status);
+            self.on_status(u16::from_be_bytes(addr), status, rx);
         }
-        let status = Status::from_bits(u16::from_be_bytes(stat)).ok_or(Error::Status(stat))?;
-        self.on_status(u16::from_be_bytes(addr), status, &rx);
-        Ok(chunks)
+        Ok(())
     }
 }
 
 pub struct WriteFIFO<'a, const S: usize, V: Into<Vec<u8>>> {
     data: PhantomData<V>,
-    spi: &'a Spidev,
+    spi: &'a Bus,
     addr: u16,
-    on_status: &'a mut dyn FnMut(&Spidev, u16, Status, &[u8]),
+    on_status: &'a mut dyn FnMut(&Bus, u16, Status, &[u8]),
 }
 
 impl<const S: usize, V: Into<Vec<u8>>> WriteFIFO<'_, S, V> {
@@ -315,18 +636,125 @@ impl<const S: usize, V: Into<Vec<u8>>> WriteFIFO<'_, S, V> {
         let tx = &value.into()[..];
         let rx: &mut [u8] = &mut vec![0; tx.len()];
 
-        self.spi.transfer_multiple(&mut [
-            SpidevTransfer::read_write(&addr, &mut stat),
-            SpidevTransfer::read_write(tx, rx),
-        ])?;
+        self.spi.transfer(&addr, &mut stat, tx, rx)?;
         //assert_eq!(rx, [0; S]); fails TODO: what does this return? Old value? check that it
         //matches our previous known state?
         let status = Status::from_bits(u16::from_be_bytes(stat)).ok_or(Error::Status(stat))?;
+        trace!(target: "ax5043::fifo", "write {:02X?} {:?}", tx, status);
         self.on_status(u16::from_be_bytes(addr), status, tx);
         Ok(())
     }
 }
 
+/// One register as read by Registers::dump()
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegisterDump {
+    pub name: String,
+    pub addr: u16,
+    pub raw: Vec<u8>,
+    /// Debug formatting of the typed register, or the decode error
+    pub decoded: String,
+}
+
+// Lets the registers! macro skip anything that can't be read on its own
+trait Dump {
+    fn dump(&mut self, _name: &str) -> Option<Result<RegisterDump>> {
+        None
+    }
+}
+
+fn dump_register<const S: usize, R: RX<S>>(reg: &mut R, name: &str) -> Result<RegisterDump>
+where
+    R::Value: Debug,
+{
+    let raw = reg.read_raw()?;
+    let bytes = raw.0.to_vec();
+    let decoded = match R::Value::try_from(raw) {
+        Ok(value) => format!("{:?}", value),
+        Err(_) => "<invalid>".to_string(),
+    };
+    Ok(RegisterDump {
+        name: name.into(),
+        addr: reg.addr(),
+        raw: bytes,
+        decoded,
+    })
+}
+
+/// A register that didn't read back its reset value, see Registers::verify_reset()
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResetMismatch {
+    pub name: String,
+    pub addr: u16,
+    pub expected: Vec<u8>,
+    pub actual: Vec<u8>,
+}
+
+impl std::fmt::Display for ResetMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<16} {:03X} {:02X?} expected {:02X?}",
+            self.name, self.addr, self.actual, self.expected
+        )
+    }
+}
+
+/// Read/write registers that don't hold still after a reset: PWRMODE (reset() itself leaves the
+/// flags cleared), FIFODATA (reading pops the FIFO) and the live BGNDRSSI, AGCCOUNTER,
+/// TRKRFFREQ and TRKFREQ
+const RESET_VOLATILE: [u16; 6] = [0x002, 0x029, 0x041, 0x043, 0x04D, 0x050];
+
+// Lets the registers! macro check only the read/write registers against their reset values,
+// the read only ones are status
+trait Verify<V> {
+    fn verify(&mut self, _name: &str, _expected: V) -> Option<Result<Option<ResetMismatch>>> {
+        None
+    }
+}
+
+impl<const S: usize, V: TryFrom<Reg<S>> + Into<Reg<S>>> Verify<V> for ReadWrite<'_, S, V> {
+    fn verify(&mut self, name: &str, expected: V) -> Option<Result<Option<ResetMismatch>>> {
+        if RESET_VOLATILE.contains(&self.addr) {
+            return None;
+        }
+        let expected = expected.into();
+        Some(self.read_raw().map(|actual| {
+            (actual != expected).then(|| ResetMismatch {
+                name: name.into(),
+                addr: self.addr,
+                expected: expected.0.to_vec(),
+                actual: actual.0.to_vec(),
+            })
+        }))
+    }
+}
+
+impl<const S: usize, V: TryFrom<Reg<S>>> Verify<V> for ReadOnly<'_, S, V> {}
+impl<const S: usize, V: Into<Reg<S>>> Verify<V> for WriteOnly<'_, S, V> {}
+impl<const S: usize, V: TryFrom<Vec<u8>>> Verify<V> for ReadFIFO<'_, S, V> {}
+impl<const S: usize, V: Into<Vec<u8>>> Verify<V> for WriteFIFO<'_, S, V> {}
+
+impl<const S: usize, V: TryFrom<Reg<S>> + Into<Reg<S>> + Debug> Dump for ReadWrite<'_, S, V> {
+    fn dump(&mut self, name: &str) -> Option<Result<RegisterDump>> {
+        // Reading FIFODATA pops a byte off the FIFO
+        if self.addr == 0x029 {
+            return None;
+        }
+        Some(dump_register(self, name))
+    }
+}
+
+impl<const S: usize, V: TryFrom<Reg<S>> + Debug> Dump for ReadOnly<'_, S, V> {
+    fn dump(&mut self, name: &str) -> Option<Result<RegisterDump>> {
+        Some(dump_register(self, name))
+    }
+}
+
+impl<const S: usize, V: Into<Reg<S>>> Dump for WriteOnly<'_, S, V> {}
+impl<const S: usize, V: TryFrom<Vec<u8>>> Dump for ReadFIFO<'_, S, V> {}
+impl<const S: usize, V: Into<Vec<u8>>> Dump for WriteFIFO<'_, S, V> {}
+
 // Name: Type [Addr, Width, Access],
 macro_rules! registers {
     (
@@ -337,13 +765,30 @@ macro_rules! registers {
 
         #[allow(non_snake_case)]
         pub struct $name<'a> {
-            spi: Spidev,
-            on_status: &'a mut dyn FnMut(&Spidev, u16, Status, &[u8]),
+            spi: Bus,
+            on_status: &'a mut dyn FnMut(&Bus, u16, Status, &[u8]),
             $(pub $reg: $T,)*
         }
 
         #[allow(non_snake_case)]
         impl $name<'_> {
+            /// The table itself, see ax5043::regmap
+            pub const MAP: &'static [regmap::RegisterInfo] = &[
+                $(regmap::RegisterInfo {
+                    name: stringify!($reg),
+                    addr: $addr,
+                    width: $width,
+                    access: regmap::Access::$access,
+                    value: stringify!($T),
+                    fields: <$T as regmap::Layout>::fields,
+                },)*
+            ];
+
+            /// Looks a register up by name, case insensitive
+            pub fn info(name: &str) -> Option<&'static regmap::RegisterInfo> {
+                Self::MAP.iter().find(|r| r.name.eq_ignore_ascii_case(name))
+            }
+
             $(
                 pub fn $reg(&mut self) -> $access<$width, $T> {
                     $access {
@@ -354,6 +799,44 @@ macro_rules! registers {
                     }
                 }
             )*
+
+            /// Reads every readable register in table order, skipping write only registers
+            /// and the FIFO data port. Note that reading clears sticky and request registers
+            /// like POWSTICKYSTAT.
+            pub fn dump(&mut self) -> Result<Vec<RegisterDump>> {
+                let mut regs = Vec::new();
+                $(
+                    if let Some(reg) = self.$reg().dump(stringify!($reg)) {
+                        regs.push(reg?);
+                    }
+                )*
+                Ok(regs)
+            }
+
+            /// Reads back every read/write register and returns those not at the reset value
+            /// from PM Table 22 (as filled in by new()). Run it right after reset(): a chip that
+            /// didn't really reset, e.g. on marginal power, still holds its old settings.
+            pub fn verify_reset(&mut self) -> Result<Vec<ResetMismatch>> {
+                let mut noop = |_: &_, _, _, _: &_| {};
+                let defaults = $name::new(Bus::Sink, &mut noop);
+                let mut mismatches = Vec::new();
+                $(
+                    if let Some(mismatch) = self.$reg().verify(stringify!($reg), defaults.$reg) {
+                        mismatches.extend(mismatch?);
+                    }
+                )*
+                Ok(mismatches)
+            }
+
+            /// Register name for an address, the first in table order where they share one
+            pub fn name(addr: u16) -> Option<&'static str> {
+                $(
+                    if addr == $addr {
+                        return Some(stringify!($reg));
+                    }
+                )*
+                None
+            }
         }
     }
 }
@@ -581,11 +1064,11 @@ registers! {
         PKTACCEPTFLAGS: PktAcceptFlags [0x233, 1, ReadWrite], // Packet Controller Accept Flags
         /* Special Functions */
         /* General Purpose ADC */
-        GPADCCTRL:      u8          [0x300, 1, ReadWrite], // General Purpose ADC Control
+        GPADCCTRL:      GPADCCtrl   [0x300, 1, ReadWrite], // General Purpose ADC Control
         GPADCPERIOD:    u8          [0x301, 1, ReadWrite], // GPADC Sampling Period
         GPADC13VALUE:   u16         [0x308, 2, ReadOnly ], // GPADC13 Value
         /* Low Power Oscillator Calibration */
-        LPOSCCONFIG:    u8          [0x310, 1, ReadWrite], // Low Power Oscillator Configuration
+        LPOSCCONFIG:    LPOscConfig [0x310, 1, ReadWrite], // Low Power Oscillator Configuration
         LPOSCSTATUS:    u8          [0x311, 1, ReadOnly ], // Low Power Oscillator Status
         LPOSCKFILT:     u16         [0x312, 2, ReadWrite], // Low Power Oscillator Calibration Filter Constant
         LPOSCREF:       u16         [0x314, 2, ReadWrite], // Low Power Oscillator Calibration Reference
@@ -625,12 +1108,12 @@ pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Spidev> {
 
 impl Registers<'_> {
     pub fn new(
-        spi: Spidev,
-        on_status: &mut dyn FnMut(&Spidev, u16, Status, &[u8]),
+        spi: impl Into<Bus>,
+        on_status: &mut dyn FnMut(&Bus, u16, Status, &[u8]),
     ) -> Registers<'_> {
         // Default vaules from PM Table 22
         Registers {
-            spi,
+            spi: spi.into(),
             on_status,
 
             REVISION: 0b0101_0001,
@@ -660,7 +1143,7 @@ impl Registers<'_> {
             CRCINIT: 0xFFFF_FFFF,
             FEC: FEC {
                 flags: FECFlags::empty(),
-                inpshift: 0,
+                inpshift: U3::new(0),
             },
             FECSYNC: 0b0110_0010,
             FECSTATUS: FECStatus {
@@ -717,7 +1200,7 @@ impl Registers<'_> {
                 flags: PLLVCODivFlags::empty(),
             },
             PLLRANGINGA: PLLRanging {
-                vcor: 8,
+                vcor: U4::new(8),
                 flags: PLLRangingFlags::empty(),
             },
             FREQA: 0x3934_CCCD,
@@ -728,7 +1211,7 @@ impl Registers<'_> {
             },
             PLLCPIBOOST: 0xC8,
             PLLRANGINGB: PLLRanging {
-                vcor: 8,
+                vcor: U4::new(8),
                 flags: PLLRangingFlags::empty(),
             },
             FREQB: 0x3934_CCCD,
@@ -790,148 +1273,172 @@ impl Registers<'_> {
                 special: 0,
             },
             AGCGAIN0: AGCGain {
-                attack: 0x4,
-                decay: 0xB,
+                attack: U4::new(0x4),
+                decay: U4::new(0xB),
             },
             AGCTARGET0: 0x76,
-            AGCAHYST0: AGCHyst { hyst: 0 },
-            AGCMINMAX0: AGCMinMax { min: 0, max: 0 },
+            AGCAHYST0: AGCHyst { hyst: U3::new(0) },
+            AGCMINMAX0: AGCMinMax {
+                min: U3::new(0),
+                max: U3::new(0),
+            },
             TIMEGAIN0: Float4 { e: 0x8, m: 0xF },
             DRGAIN0: Float4 { e: 2, m: 0xF },
-            PHASEGAIN0: PhaseGain { gain: 3, filter: 3 },
+            PHASEGAIN0: PhaseGain {
+                gain: U4::new(3),
+                filter: U2::new(3),
+            },
             FREQGAINA0: FreqGainA {
-                gain: 0xF,
+                gain: U4::new(0xF),
                 flags: FreqGainAFlags::empty(),
             },
             FREQGAINB0: FreqGainB {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 flags: FreqGainBFlags::empty(),
             },
-            FREQGAINC0: FreqGainC { gain: 0xA },
+            FREQGAINC0: FreqGainC { gain: U5::new(0xA) },
             FREQGAIND0: FreqGainD {
-                gain: 0xA,
+                gain: U5::new(0xA),
                 freeze: false,
             },
             AMPLGAIN0: AmplGain {
-                gain: 6,
+                gain: U4::new(6),
                 flags: AmplGainFlags::AGC,
             },
             FREQDEV0: 0x0020,
             FOURFSK0: FourFSK {
-                decay: 6,
+                decay: U4::new(6),
                 update: true,
             },
             BBOFFSRES0: BBOffsRes {
-                res_int_a: 8,
-                res_int_b: 8,
+                res_int_a: U4::new(8),
+                res_int_b: U4::new(8),
             },
             AGCGAIN1: AGCGain {
-                attack: 0x4,
-                decay: 0xB,
+                attack: U4::new(0x4),
+                decay: U4::new(0xB),
             },
             AGCTARGET1: 0x76,
-            AGCAHYST1: AGCHyst { hyst: 0 },
-            AGCMINMAX1: AGCMinMax { min: 0, max: 0 },
+            AGCAHYST1: AGCHyst { hyst: U3::new(0) },
+            AGCMINMAX1: AGCMinMax {
+                min: U3::new(0),
+                max: U3::new(0),
+            },
             TIMEGAIN1: Float4 { e: 0x6, m: 0xF },
             DRGAIN1: Float4 { e: 0x1, m: 0xF },
-            PHASEGAIN1: PhaseGain { gain: 3, filter: 3 },
+            PHASEGAIN1: PhaseGain {
+                gain: U4::new(3),
+                filter: U2::new(3),
+            },
             FREQGAINA1: FreqGainA {
-                gain: 0xF,
+                gain: U4::new(0xF),
                 flags: FreqGainAFlags::empty(),
             },
             FREQGAINB1: FreqGainB {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 flags: FreqGainBFlags::empty(),
             },
-            FREQGAINC1: FreqGainC { gain: 0xB },
+            FREQGAINC1: FreqGainC { gain: U5::new(0xB) },
             FREQGAIND1: FreqGainD {
-                gain: 0xB,
+                gain: U5::new(0xB),
                 freeze: false,
             },
             AMPLGAIN1: AmplGain {
-                gain: 6,
+                gain: U4::new(6),
                 flags: AmplGainFlags::AGC,
             },
             FREQDEV1: 0x20,
             FOURFSK1: FourFSK {
-                decay: 0x8,
+                decay: U4::new(0x8),
                 update: true,
             },
             BBOFFSRES1: BBOffsRes {
-                res_int_a: 8,
-                res_int_b: 8,
+                res_int_a: U4::new(8),
+                res_int_b: U4::new(8),
             },
             AGCGAIN2: AGCGain {
-                attack: 0xF,
-                decay: 0xF,
+                attack: U4::new(0xF),
+                decay: U4::new(0xF),
             },
             AGCTARGET2: 0x76,
-            AGCAHYST2: AGCHyst { hyst: 0 },
-            AGCMINMAX2: AGCMinMax { min: 0, max: 0 },
+            AGCAHYST2: AGCHyst { hyst: U3::new(0) },
+            AGCMINMAX2: AGCMinMax {
+                min: U3::new(0),
+                max: U3::new(0),
+            },
             TIMEGAIN2: Float4 { e: 0x5, m: 0xF },
             DRGAIN2: Float4 { e: 0x0, m: 0xF },
-            PHASEGAIN2: PhaseGain { gain: 3, filter: 3 },
+            PHASEGAIN2: PhaseGain {
+                gain: U4::new(3),
+                filter: U2::new(3),
+            },
             FREQGAINA2: FreqGainA {
-                gain: 0xF,
+                gain: U4::new(0xF),
                 flags: FreqGainAFlags::empty(),
             },
             FREQGAINB2: FreqGainB {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 flags: FreqGainBFlags::empty(),
             },
-            FREQGAINC2: FreqGainC { gain: 0xD },
+            FREQGAINC2: FreqGainC { gain: U5::new(0xD) },
             FREQGAIND2: FreqGainD {
-                gain: 0xD,
+                gain: U5::new(0xD),
                 freeze: false,
             },
             AMPLGAIN2: AmplGain {
-                gain: 6,
+                gain: U4::new(6),
                 flags: AmplGainFlags::AGC,
             },
             FREQDEV2: 0x20,
             FOURFSK2: FourFSK {
-                decay: 0xA,
+                decay: U4::new(0xA),
                 update: true,
             },
             BBOFFSRES2: BBOffsRes {
-                res_int_a: 8,
-                res_int_b: 8,
+                res_int_a: U4::new(8),
+                res_int_b: U4::new(8),
             },
             AGCGAIN3: AGCGain {
-                attack: 0xF,
-                decay: 0xF,
+                attack: U4::new(0xF),
+                decay: U4::new(0xF),
             },
             AGCTARGET3: 0x76,
-            AGCAHYST3: AGCHyst { hyst: 0 },
-            AGCMINMAX3: AGCMinMax { min: 0, max: 0 },
+            AGCAHYST3: AGCHyst { hyst: U3::new(0) },
+            AGCMINMAX3: AGCMinMax {
+                min: U3::new(0),
+                max: U3::new(0),
+            },
             TIMEGAIN3: Float4 { e: 0x5, m: 0xF },
             DRGAIN3: Float4 { e: 0x0, m: 0xF },
-            PHASEGAIN3: PhaseGain { gain: 3, filter: 3 },
+            PHASEGAIN3: PhaseGain {
+                gain: U4::new(3),
+                filter: U2::new(3),
+            },
             FREQGAINA3: FreqGainA {
-                gain: 0xF,
+                gain: U4::new(0xF),
                 flags: FreqGainAFlags::empty(),
             },
             FREQGAINB3: FreqGainB {
-                gain: 0x1F,
+                gain: U5::new(0x1F),
                 flags: FreqGainBFlags::empty(),
             },
-            FREQGAINC3: FreqGainC { gain: 0xD },
+            FREQGAINC3: FreqGainC { gain: U5::new(0xD) },
             FREQGAIND3: FreqGainD {
-                gain: 0xD,
+                gain: U5::new(0xD),
                 freeze: false,
             },
             AMPLGAIN3: AmplGain {
-                gain: 6,
+                gain: U4::new(6),
                 flags: AmplGainFlags::AGC,
             },
             FREQDEV3: 0x20,
             FOURFSK3: FourFSK {
-                decay: 0xA,
+                decay: U4::new(0xA),
                 update: true,
             },
             BBOFFSRES3: BBOffsRes {
-                res_int_a: 8,
-                res_int_b: 8,
+                res_int_a: U4::new(8),
+                res_int_b: U4::new(8),
             },
             MODCFGF: ModCfgF::UNSHAPED,
             FSKDEV: 0x00_0A3D,
@@ -946,7 +1453,7 @@ impl Registers<'_> {
             TXPWRCOEFFD: 0x0000,
             TXPWRCOEFFE: 0x0000,
             PLLVCOI: PLLVCOI {
-                bias: 0x12,
+                bias: U6::new(0x12),
                 flags: PLLVCOIFlags::empty(),
             },
             PLLVCOIR: 0,
@@ -960,20 +1467,29 @@ impl Registers<'_> {
             BBTUNE: 9,
             BBOFFSCAP: 0x77,
             PKTADDRCFG: PktAddrCfg {
-                addr_pos: 0,
+                addr_pos: U4::new(0),
                 flags: PktAddrCfgFlags::FEC_SYNC_DIS,
             },
-            PKTLENCFG: PktLenCfg { bits: 0, pos: 0 },
+            PKTLENCFG: PktLenCfg {
+                bits: U4::new(0),
+                pos: U4::new(0),
+            },
             PKTLENOFFSET: 0,
             PKTMAXLEN: 0,
             PKTADDR: 0,
             PKTADDRMASK: 0,
             MATCH0PAT: 0,
-            MATCH0LEN: MatchLen { len: 0, raw: false },
+            MATCH0LEN: MatchLen {
+                len: U5::new(0),
+                raw: false,
+            },
             MATCH0MIN: 0,
             MATCH0MAX: 0x1F,
             MATCH1PAT: 0,
-            MATCH1LEN: MatchLen { len: 0, raw: false },
+            MATCH1LEN: MatchLen {
+                len: U5::new(0),
+                raw: false,
+            },
             MATCH1MIN: 0,
             MATCH1MAX: 0xF,
             TMGTXBOOST: Float5 { e: 1, m: 0x12 },
@@ -995,10 +1511,10 @@ impl Registers<'_> {
             PKTMISCFLAGS: PktMiscFlags::empty(),
             PKTSTOREFLAGS: PktStoreFlags::empty(),
             PKTACCEPTFLAGS: PktAcceptFlags::empty(),
-            GPADCCTRL: 0,
+            GPADCCTRL: GPADCCtrl::empty(),
             GPADCPERIOD: 0x3F,
             GPADC13VALUE: 0,
-            LPOSCCONFIG: 0,
+            LPOSCCONFIG: LPOscConfig::empty(),
             LPOSCSTATUS: 0,
             LPOSCKFILT: 0x20C4,
             LPOSCREF: 0x61A8,
@@ -1037,4 +1553,393 @@ impl Registers<'_> {
 
         Ok(())
     }
+
+    /// Reads REVISION and SCRATCH to tell what's on the other end of the bus. Only meaningful
+    /// straight after reset(), it relies on SCRATCH's reset value.
+    pub fn probe(&mut self) -> Result<Probe> {
+        let revision = self.REVISION().read()?;
+        let scratch = self.SCRATCH().read()?;
+        Ok(
+            if revision == scratch && (revision == 0x00 || revision == 0xFF) {
+                Probe::NoChip { revision, scratch }
+            } else if scratch != SCRATCH_RESET {
+                Probe::Wiring { scratch }
+            } else if revision != REVISION {
+                Probe::Unexpected { revision }
+            } else {
+                Probe::Found
+            },
+        )
+    }
+
+    /// Writes test patterns (all zeros and ones, alternating, walking one and zero) to SCRATCH
+    /// and reads each back, for stuck or shorted data lines and marginal level shifters. With
+    /// `walk` it also gives the WALKED registers distinct values and checks each kept its own,
+    /// for corrupted address bits. Run it before configuring: SCRATCH is left at its reset
+    /// value and the walked registers as they were, but only if the test passes.
+    pub fn check_spi(&mut self, walk: bool) -> Result<()> {
+        let patterns = [0x00, 0xFF, 0x55, 0xAA]
+            .into_iter()
+            .chain((0..8).map(|bit| 1 << bit))
+            .chain((0..8).map(|bit| !(1 << bit)));
+        for wrote in patterns {
+            self.SCRATCH().write(wrote)?;
+            let read = self.SCRATCH().read()?;
+            if read != wrote {
+                return Err(Error::Spi(SpiFault::Data { wrote, read }));
+            }
+        }
+        self.SCRATCH().write(SCRATCH_RESET)?;
+        if !walk {
+            return Ok(());
+        }
+
+        let pattern = |addr: u16| u32::from(addr) << 16 | u32::from(!addr);
+        let mut saved = Vec::new();
+        for addr in WALKED {
+            saved.push(self.walked(addr).read()?);
+        }
+        for addr in WALKED {
+            self.walked(addr).write(pattern(addr))?;
+        }
+        for addr in WALKED {
+            let read = self.walked(addr).read()?;
+            if read != pattern(addr) {
+                return Err(Error::Spi(SpiFault::Address {
+                    addr,
+                    wrote: pattern(addr),
+                    read,
+                }));
+            }
+        }
+        for (addr, value) in WALKED.into_iter().zip(saved) {
+            self.walked(addr).write(value)?;
+        }
+        Ok(())
+    }
+
+    /// One GPADC13 conversion, GPADC1 against GPADC3. A conversion takes a few µs, the poll is
+    /// bounded in case the ADC never comes back.
+    pub fn sample_gpadc13(&mut self) -> Result<u16> {
+        self.GPADCCTRL().write(GPADCCtrl::BUSY | GPADCCtrl::CH13)?;
+        for _ in 0..100 {
+            if !self.GPADCCTRL().read()?.contains(GPADCCtrl::BUSY) {
+                return self.GPADC13VALUE().read();
+            }
+        }
+        Err(Error::Invalid)
+    }
+
+    /// Reads the `len` registers from `addr` on in one burst, the radio steps through the
+    /// addresses by itself. Cheaper than a read per register for telemetry, pick them out with
+    /// RX::read_from(). Registers that clear on read (RADIOEVENTREQ, POWSTICKYSTAT, the sticky
+    /// PLL lock bits) are cleared all the same, so leave them out unless they'd be read anyway.
+    /// FIFODATA can't be part of a burst, every byte read from it pops the FIFO.
+    pub fn read_burst(&mut self, addr: u16, len: usize) -> Result<Burst> {
+        if (addr..addr.saturating_add(len as u16)).contains(&FIFODATA) {
+            return Err(Error::Invalid);
+        }
+        let header = (addr | 0x7000).to_be_bytes();
+        let mut stat = [0; 2];
+        let tx = vec![0; len];
+        let mut rx = vec![0; len];
+        self.spi.transfer(&header, &mut stat, &tx, &mut rx)?;
+        let status = Status::from_bits(u16::from_be_bytes(stat)).ok_or(Error::Status(stat))?;
+        trace!(target: "ax5043::spi", "read {:03X}+{}: {:02X?} {:?}", addr, len, rx, status);
+        (self.on_status)(&self.spi, u16::from_be_bytes(header), status, &rx);
+        Ok(Burst { addr, data: rx })
+    }
+
+    /// Writes `data` to the registers from `addr` on in one burst, see read_burst(). Only for
+    /// registers without side effects on write, see image::Delta.
+    pub fn write_burst(&mut self, addr: u16, data: &[u8]) -> Result<()> {
+        if (addr..addr.saturating_add(data.len() as u16)).contains(&FIFODATA) {
+            return Err(Error::Invalid);
+        }
+        let header = (addr | 0xF000).to_be_bytes();
+        let mut stat = [0; 2];
+        let mut rx = vec![0; data.len()];
+        self.spi.transfer(&header, &mut stat, data, &mut rx)?;
+        let status = Status::from_bits(u16::from_be_bytes(stat)).ok_or(Error::Status(stat))?;
+        trace!(target: "ax5043::spi", "write {:03X}+{}: {:02X?} {:?}", addr, data.len(), data, status);
+        (self.on_status)(&self.spi, u16::from_be_bytes(header), status, data);
+        Ok(())
+    }
+
+    fn walked(&mut self, addr: u16) -> ReadWrite<'_, 4, u32> {
+        ReadWrite {
+            data: PhantomData,
+            spi: &self.spi,
+            addr,
+            on_status: &mut self.on_status,
+        }
+    }
+}
+
+const SCRATCH_RESET: u8 = 0xC5;
+
+/// What REVISION reads on an AX5043
+pub const REVISION: u8 = 0x51;
+
+/// The FIFO data port, never part of a read_burst()
+const FIFODATA: u16 = 0x029;
+
+/// 32 bit registers without reserved bits, in the short and long address ranges, that
+/// check_spi() walks: CRCINIT, FREQA, FREQB, PKTADDR and MATCH0PAT
+const WALKED: [u16; 5] = [0x014, 0x034, 0x03C, 0x204, 0x210];
+
+/// What check_spi() found wrong
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SpiFault {
+    /// A pattern written to SCRATCH came back different
+    Data { wrote: u8, read: u8 },
+    /// A register didn't keep its value, another write probably landed on it
+    Address { addr: u16, wrote: u32, read: u32 },
+}
+
+impl std::fmt::Display for SpiFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SpiFault::Data { wrote, read } => write!(
+                f,
+                "SCRATCH read {:#04X} after writing {:#04X}, bits {:#04X} differ (data lines?)",
+                read,
+                wrote,
+                read ^ wrote
+            ),
+            SpiFault::Address { addr, wrote, read } => write!(
+                f,
+                "{} ({:03X}) read {:#010X} after writing {:#010X} (address bits?)",
+                Registers::name(*addr).unwrap_or("?"),
+                addr,
+                read,
+                wrote
+            ),
+        }
+    }
+}
+
+/// What probe() found
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Probe {
+    Found,
+    /// Every read comes back the same all zeros or all ones: nothing is driving MISO
+    NoChip {
+        revision: u8,
+        scratch: u8,
+    },
+    /// Something answers but SCRATCH is garbled: clock, MOSI or chip select trouble
+    Wiring {
+        scratch: u8,
+    },
+    /// The bus works but the silicon isn't an AX5043
+    Unexpected {
+        revision: u8,
+    },
+}
+
+impl Probe {
+    /// Error::Probe unless the chip was found
+    pub fn found(self) -> Result<()> {
+        match self {
+            Probe::Found => Ok(()),
+            other => Err(Error::Probe(other)),
+        }
+    }
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Probe::Found => write!(f, "AX5043"),
+            Probe::NoChip { revision, scratch } => write!(
+                f,
+                "no chip answering, REVISION {:#04X} SCRATCH {:#04X} (power, CS or MISO?)",
+                revision, scratch
+            ),
+            Probe::Wiring { scratch } => write!(
+                f,
+                "SCRATCH {:#04X} after reset, expected {:#04X} (SPI wiring or clock?)",
+                scratch, SCRATCH_RESET
+            ),
+            Probe::Unexpected { revision } => {
+                write!(f, "REVISION {:#04X}, expected {:#04X}", revision, REVISION)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_reset() {
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(Bus::Sink, &mut callback);
+        // The sink reads zero, so everything with a non-zero reset value stands out
+        let mismatches = radio.verify_reset().unwrap();
+        let scratch = mismatches.iter().find(|m| m.name == "SCRATCH").unwrap();
+        assert_eq!(
+            (scratch.addr, scratch.expected.clone()),
+            (0x001, vec![0xC5])
+        );
+        assert_eq!(scratch.actual, vec![0]);
+        assert_eq!(
+            scratch.to_string(),
+            "SCRATCH          001 [00] expected [C5]"
+        );
+        assert!(mismatches
+            .iter()
+            .all(|m| m.expected.iter().any(|&b| b != 0)));
+        // Status and live registers aren't checked
+        for name in ["REVISION", "PWRMODE", "XTALSTATUS", "FIFODATA", "TRKRFFREQ"] {
+            assert!(mismatches.iter().all(|m| m.name != name), "{}", name);
+        }
+        assert!(mismatches.iter().any(|m| m.name == "FREQA"));
+    }
+
+    #[test]
+    fn split_chunks() {
+        let chunks: Vec<FIFOChunkRX> =
+            super::split_chunks(&[0x31, 0xF6, 0xE1, 0x04, 0x03, 0xAA, 0xBB, 0xCC]).unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                FIFOChunkRX::RSSI(-10),
+                FIFOChunkRX::DATA {
+                    flags: FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND,
+                    data: vec![0xAA, 0xBB, 0xCC]
+                },
+            ]
+        );
+        // Cut off after the DATA header, this used to index past the end
+        assert!(matches!(
+            super::split_chunks::<FIFOChunkRX>(&[0x31, 0xF6, 0xE1]),
+            Err(Error::DecodeBytes(b)) if b == [0xE1]
+        ));
+        assert!(matches!(
+            super::split_chunks::<FIFOChunkRX>(&[0xE1, 0x09, 0x03]),
+            Err(Error::DecodeBytes(_))
+        ));
+        assert!(matches!(
+            super::split_chunks::<FIFOChunkRX>(&[0x00]),
+            Err(Error::FIFOHeader(_))
+        ));
+    }
+
+    proptest::proptest! {
+        // Same as the fuzz target, for when cargo-fuzz isn't around
+        #[test]
+        fn split_chunks_any(bytes in proptest::collection::vec(proptest::prelude::any::<u8>(), 0..300)) {
+            if let Ok(chunks) = super::split_chunks::<FIFOChunkRX>(&bytes) {
+                let encoded: Vec<u8> = chunks.into_iter().flat_map(Vec::from).collect();
+                proptest::prop_assert_eq!(encoded, bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn probe() {
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(Bus::Sink, &mut callback);
+        // The sink answers REVISION but reads zero for SCRATCH
+        let probe = radio.probe().unwrap();
+        assert_eq!(probe, Probe::Wiring { scratch: 0 });
+        assert_eq!(
+            probe.found().unwrap_err().to_string(),
+            "Radio probe: SCRATCH 0x00 after reset, expected 0xC5 (SPI wiring or clock?)"
+        );
+        assert!(Probe::Found.found().is_ok());
+
+        assert_eq!(
+            Probe::Unexpected { revision: 0x52 }.to_string(),
+            "REVISION 0x52, expected 0x51"
+        );
+    }
+
+    #[test]
+    fn read_burst() {
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let (port, _) = sim::pair(sim::Channel::default());
+        let mut radio = Registers::new(Bus::Sim(port), &mut callback);
+        radio.FREQA().write(0x1234_5678).unwrap();
+        radio.PLLCPI().write(0x08).unwrap();
+        let burst = radio.read_burst(0x030, 0x10).unwrap();
+        assert_eq!(burst.get(0x034, 4), Some(&[0x12, 0x34, 0x56, 0x78][..]));
+        assert_eq!(radio.FREQA().read_from(&burst).unwrap(), 0x1234_5678);
+        assert_eq!(radio.PLLCPI().read_from(&burst).unwrap(), 0x08);
+        assert!(matches!(
+            radio.SCRATCH().read_from(&burst),
+            Err(Error::NotInBurst(0x001))
+        ));
+        // FREQB runs one past the end
+        assert_eq!(burst.get(0x03C, 5), None);
+        assert!(radio.read_burst(0x028, 4).is_err());
+
+        // The sink answers per register within a burst too
+        let mut radio = Registers::new(Bus::Sink, &mut callback);
+        let burst = radio.read_burst(0x000, 2).unwrap();
+        assert_eq!(radio.REVISION().read_from(&burst).unwrap(), 0x51);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Status::empty().to_string(), "none");
+        assert_eq!(
+            (Status::READY | Status::PLL_LOCK | Status::FIFO_EMPTY).to_string(),
+            "ready, PLL locked, FIFO empty"
+        );
+        let mode = PwrMode {
+            mode: PwrModes::RX,
+            flags: PwrFlags::XOEN | PwrFlags::REFEN,
+        };
+        assert_eq!(mode.to_string(), "RX (crystal enabled, reference enabled)");
+        assert_eq!(
+            RadioState::RX_PREAMBLE_1.to_string(),
+            "RX, looking for preamble"
+        );
+        assert_eq!(
+            (FIFODataRXFlags::PKTSTART | FIFODataRXFlags::CRCFAIL).to_string(),
+            "start, CRC failed"
+        );
+        assert_eq!(Encoding::NRZI.to_string(), "NRZI");
+        assert_eq!((Encoding::INV | Encoding::MANCH).to_string(), "INV | MANCH");
+    }
+
+    #[test]
+    fn check_spi() {
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(Bus::Sink, &mut callback);
+        // All zeros gets through the sink, all ones doesn't
+        let err = radio.check_spi(false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Spi(SpiFault::Data {
+                wrote: 0xFF,
+                read: 0x00
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "SPI self-test: SCRATCH read 0x00 after writing 0xFF, bits 0xFF differ (data lines?)"
+        );
+
+        let (port, _) = sim::pair(sim::Channel::default());
+        let mut radio = Registers::new(Bus::Sim(port), &mut callback);
+        radio.FREQA().write(0x1234_5678).unwrap();
+        radio.check_spi(true).unwrap();
+        assert_eq!(radio.SCRATCH().read().unwrap(), 0xC5);
+        assert_eq!(radio.FREQA().read().unwrap(), 0x1234_5678);
+
+        let fault = SpiFault::Address {
+            addr: 0x204,
+            wrote: 0x0204_FDFB,
+            read: 0x0210_FDEF,
+        };
+        assert_eq!(
+            fault.to_string(),
+            "PKTADDR (204) read 0x0210FDEF after writing 0x0204FDFB (address bits?)"
+        );
+    }
 }
@@ -0,0 +1,37 @@
+// tracing setup shared by the bins.
+//
+// Targets:
+// - ax5043::spi    every register read/write (trace)
+// - ax5043::fifo   raw FIFO transfers (trace), malformed chunks (debug)
+// - ax5043::packet received/rejected/transmitted frames (info/warn)
+//
+// The filter uses EnvFilter syntax (`info,ax5043::spi=trace`), starts from RUST_LOG (default
+// `info`) and can be replaced while running through Handle, e.g. from a control command.
+use tracing_subscriber::{filter::ParseError, fmt, prelude::*, reload, EnvFilter, Registry};
+
+pub struct Handle(reload::Handle<EnvFilter, Registry>);
+
+impl Handle {
+    /// Swaps in a new filter, see the EnvFilter directive syntax
+    pub fn set_filter(&self, directives: &str) -> Result<(), ParseError> {
+        let filter = EnvFilter::try_new(directives)?;
+        // Only fails if the subscriber was dropped, which init() never does
+        _ = self.0.reload(filter);
+        Ok(())
+    }
+}
+
+/// Installs the global subscriber, one JSON object per line if `json` is set.
+///
+/// Panics if called more than once.
+pub fn init(json: bool) -> Handle {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+    if json {
+        registry.with(fmt::layer().json()).init();
+    } else {
+        registry.with(fmt::layer()).init();
+    }
+    Handle(handle)
+}
@@ -0,0 +1,289 @@
+// A summary of each pass from the received packets: how many made it, the RSSI spread and how
+// the RF offset moved, for comparing passes without digging through the logs.
+//
+// A pass starts with the first packet and ends once none have come in for the gap (--pass-gap),
+// or at shutdown. The bins then log the Report, send it as CommState::PASS and, with
+// --pass-reports, write it to pass-<first packet unix seconds>.json in that directory:
+//
+//   {"first": 1760529612.25, "last": 1760530131.5, "ok": 212, "failed": 9,
+//    "rssi": {"min": -118.5, "max": -92.0, "mean": -104.2,
+//             "histogram": [{"dbm": -120, "count": 14}, ...]},
+//    "offset": [[0.0, 812.0], [1.0, 805.5], ...], "drift": -3.1}
+//
+// Failed counts frames with a bad CRC, ones the outer codes couldn't decode, and the assembler's
+// rejects. The histogram has HISTOGRAM_STEP dB buckets labelled by their lower edge, offset is
+// seconds since the first packet against Hz averaged per OFFSET_STEP (tui::OffsetTrack's step),
+// and drift the least squares slope through that, Hz/s.
+use crate::{
+    capture::Meta,
+    tui::{slope, OFFSET_POINTS, OFFSET_STEP},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::Result,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// dB per RSSI histogram bucket
+pub const HISTOGRAM_STEP: f64 = 5.0;
+
+fn unix(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    /// Lower edge, dBm
+    pub dbm: i32,
+    pub count: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rssi {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub histogram: Vec<Bucket>,
+}
+
+/// One finished pass
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// Unix seconds of the first and last packet
+    pub first: f64,
+    pub last: f64,
+    pub ok: u64,
+    pub failed: u64,
+    /// None if no packet had an RSSI reading
+    pub rssi: Option<Rssi>,
+    /// (seconds since first, Hz)
+    pub offset: Vec<(f64, f64)>,
+    /// Hz/s, None with fewer than two offset points
+    pub drift: Option<f64>,
+}
+
+impl Report {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64((self.last - self.first).max(0.0))
+    }
+
+    /// Writes pass-<first>.json into `dir`
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(format!("pass-{}.json", self.first as u64));
+        fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for Report {
+    /// "221 packets over 519 s, 9 failed, RSSI -118.5..-92.0 dBm mean -104.2, drift -3.10 Hz/s"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} packets over {} s, {} failed",
+            self.ok + self.failed,
+            self.duration().as_secs(),
+            self.failed
+        )?;
+        if let Some(ref rssi) = self.rssi {
+            write!(
+                f,
+                ", RSSI {:.1}..{:.1} dBm mean {:.1}",
+                rssi.min, rssi.max, rssi.mean
+            )?;
+        }
+        if let Some(drift) = self.drift {
+            write!(f, ", drift {:+.2} Hz/s", drift)?;
+        }
+        Ok(())
+    }
+}
+
+/// What's been seen of the pass in progress
+#[derive(Clone, Debug)]
+struct Current {
+    first: SystemTime,
+    last: SystemTime,
+    ok: u64,
+    failed: u64,
+    rssi: Vec<f64>,
+    /// Step and the offsets in it so far
+    offset: BTreeMap<u64, (f64, u32)>,
+}
+
+impl Current {
+    fn report(self) -> Report {
+        let rssi = (!self.rssi.is_empty()).then(|| {
+            let mut histogram = BTreeMap::new();
+            for dbm in &self.rssi {
+                let edge = ((dbm / HISTOGRAM_STEP).floor() * HISTOGRAM_STEP) as i32;
+                *histogram.entry(edge).or_insert(0) += 1;
+            }
+            Rssi {
+                min: self.rssi.iter().copied().fold(f64::MAX, f64::min),
+                max: self.rssi.iter().copied().fold(f64::MIN, f64::max),
+                mean: self.rssi.iter().sum::<f64>() / self.rssi.len() as f64,
+                histogram: histogram
+                    .into_iter()
+                    .map(|(dbm, count)| Bucket { dbm, count })
+                    .collect(),
+            }
+        });
+        let offset: Vec<(f64, f64)> = self
+            .offset
+            .iter()
+            .map(|(step, (sum, count))| (*step as f64 * OFFSET_STEP, sum / f64::from(*count)))
+            .collect();
+        Report {
+            first: unix(self.first),
+            last: unix(self.last),
+            ok: self.ok,
+            failed: self.failed,
+            rssi,
+            drift: slope(offset.iter()),
+            offset,
+        }
+    }
+}
+
+/// Splits packets into passes
+#[derive(Clone, Debug)]
+pub struct Pass {
+    gap: Duration,
+    current: Option<Current>,
+}
+
+impl Pass {
+    pub fn new(gap: Duration) -> Self {
+        Self { gap, current: None }
+    }
+
+    /// A packet received at `at`, `ok` if it was passed on
+    pub fn packet(&mut self, at: SystemTime, meta: &Meta, ok: bool) {
+        let current = self.current.get_or_insert_with(|| Current {
+            first: at,
+            last: at,
+            ok: 0,
+            failed: 0,
+            rssi: Vec::new(),
+            offset: BTreeMap::new(),
+        });
+        current.last = current.last.max(at);
+        match ok {
+            true => current.ok += 1,
+            false => current.failed += 1,
+        }
+        if let Some(rssi) = meta.rssi {
+            current.rssi.push(rssi);
+        }
+        if let Some(hz) = meta.rf_offset {
+            let since = at.duration_since(current.first).unwrap_or_default();
+            let step = (since.as_secs_f64() / OFFSET_STEP) as u64;
+            if current.offset.len() < OFFSET_POINTS || current.offset.contains_key(&step) {
+                let (sum, count) = current.offset.entry(step).or_default();
+                *sum += hz as f64;
+                *count += 1;
+            }
+        }
+    }
+
+    /// The pass once `now` is the gap past its last packet
+    pub fn check(&mut self, now: SystemTime) -> Option<Report> {
+        let last = self.current.as_ref()?.last;
+        match now.duration_since(last) {
+            Ok(quiet) if quiet >= self.gap => self.finish(),
+            _ => None,
+        }
+    }
+
+    /// The pass so far, if there was one, ended now
+    pub fn finish(&mut self) -> Option<Report> {
+        self.current.take().map(Current::report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(rssi: f64, offset: i64) -> Meta {
+        Meta {
+            rssi: Some(rssi),
+            rf_offset: Some(offset),
+            antenna: None,
+            crc_failed: false,
+        }
+    }
+
+    #[test]
+    fn report() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_760_529_600);
+        let mut pass = Pass::new(Duration::from_secs(60));
+        assert_eq!(pass.check(start), None);
+        // Two packets a second for a minute, the offset falling 2 Hz/s
+        for i in 0..120u64 {
+            let at = start + Duration::from_millis(500 * i);
+            let rssi = -100.0 - (i % 10) as f64;
+            pass.packet(at, &meta(rssi, 800 - i as i64), i % 20 != 0);
+        }
+        let last = start + Duration::from_millis(500 * 119);
+        assert_eq!(pass.check(last + Duration::from_secs(59)), None);
+        let report = pass.check(last + Duration::from_secs(60)).unwrap();
+        assert_eq!(pass.finish(), None);
+
+        assert_eq!(report.first, 1_760_529_600.0);
+        assert_eq!(report.duration(), Duration::from_millis(59_500));
+        assert_eq!((report.ok, report.failed), (114, 6));
+        let rssi = report.rssi.as_ref().unwrap();
+        assert_eq!((rssi.min, rssi.max, rssi.mean), (-109.0, -100.0, -104.5));
+        assert_eq!(
+            rssi.histogram,
+            [
+                Bucket {
+                    dbm: -110,
+                    count: 48
+                },
+                Bucket {
+                    dbm: -105,
+                    count: 60
+                },
+                Bucket {
+                    dbm: -100,
+                    count: 12
+                },
+            ]
+        );
+        assert_eq!(report.offset.len(), 60);
+        assert_eq!(report.offset[0], (0.0, 799.5));
+        assert!((report.drift.unwrap() + 2.0).abs() < 1e-9);
+        assert_eq!(
+            report.to_string(),
+            "120 packets over 59 s, 6 failed, RSSI -109.0..-100.0 dBm mean -104.5, drift -2.00 Hz/s"
+        );
+
+        let dir = std::env::temp_dir().join(format!("ax5043-pass-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = report.write(&dir).unwrap();
+        assert_eq!(path.file_name().unwrap(), "pass-1760529600.json");
+        let read: Report = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(read, report);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn without_meta() {
+        let mut pass = Pass::new(Duration::from_secs(60));
+        pass.packet(UNIX_EPOCH, &Meta::default(), false);
+        let report = pass.finish().unwrap();
+        assert_eq!((report.ok, report.failed), (0, 1));
+        assert_eq!(report.rssi, None);
+        assert!(report.offset.is_empty());
+        assert_eq!(report.drift, None);
+        assert_eq!(report.to_string(), "1 packets over 0 s, 1 failed");
+    }
+}
@@ -0,0 +1,370 @@
+// TX output power in dBm, calibrated against an external power detector.
+//
+// Calibration::run() keys a carrier at a series of TXPWRCOEFFB values and samples a detector on
+// GPADC13 once each one has settled. The resulting Table maps dBm back to a coefficient for
+// Table::set(). Only b is swept, so the [tx] shaping should be Hard or Linear. The radio needs a
+// full config with [tx] written first and the PA enable GPIO active, see guard.rs.
+//
+// Outside the radio, a PowerSensor reads forward and reflected power from a directional coupler.
+// The uhf bin samples it just before and just after keying up for every frame and reports both
+// with the TX telemetry (tx::Stats::rf), so PA output and antenna VSWR can be followed pass by
+// pass. IioSensor reads the detectors through a Linux IIO ADC, `[power_sensor]` in the config.
+use crate::{registers::*, Error, Registers, Result, RX, TX};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::PathBuf, thread, time::Duration};
+
+/// Carrier chunks queued per step, each one 255 bytes of air time. The dwell and the samples
+/// have to fit inside them.
+const CARRIER_CHUNKS: usize = 8;
+
+/// Log detectors (AD8314 and friends) are close to linear in dB, two points off the bench or the
+/// datasheet curve are enough
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Detector {
+    /// dBm at an ADC count of 0
+    pub intercept: f64,
+    /// dB per ADC count, negative for detectors whose output falls with power
+    pub slope: f64,
+}
+
+impl Detector {
+    pub fn dbm(&self, count: f64) -> f64 {
+        self.intercept + self.slope * count
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    /// TXPWRCOEFFB
+    pub coeff: u16,
+    pub dbm: f64,
+}
+
+/// Calibration points in coefficient order with strictly rising power. Points past PA
+/// saturation, where more coefficient no longer means more power, are dropped.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub points: Vec<Point>,
+}
+
+impl Table {
+    pub fn new(mut points: Vec<Point>) -> Self {
+        points.sort_by_key(|p| p.coeff);
+        let mut table = Self::default();
+        for p in points {
+            if table.points.last().is_none_or(|last| p.dbm > last.dbm) {
+                table.points.push(p);
+            }
+        }
+        table
+    }
+
+    /// The calibrated range, dBm
+    pub fn range(&self) -> Option<(f64, f64)> {
+        Some((self.points.first()?.dbm, self.points.last()?.dbm))
+    }
+
+    /// Interpolates between the two points around `dbm`, None outside the calibrated range
+    pub fn coefficient(&self, dbm: f64) -> Option<u16> {
+        let (min, max) = self.range()?;
+        if !(min..=max).contains(&dbm) {
+            return None;
+        }
+        let upper = self.points.iter().position(|p| p.dbm >= dbm)?;
+        let hi = self.points[upper];
+        let Some(lo) = upper.checked_sub(1).map(|i| self.points[i]) else {
+            return Some(hi.coeff);
+        };
+        let frac = (dbm - lo.dbm) / (hi.dbm - lo.dbm);
+        let coeff = f64::from(lo.coeff) + frac * f64::from(hi.coeff - lo.coeff);
+        Some(coeff.round() as u16)
+    }
+
+    /// Sets TXPWRCOEFFB for `dbm`. Takes effect from the next transmission.
+    pub fn set(&self, radio: &mut Registers, dbm: f64) -> Result<u16> {
+        let coeff = self.coefficient(dbm).ok_or(Error::PowerRange(dbm))?;
+        radio.TXPWRCOEFFB().write(coeff)?;
+        Ok(coeff)
+    }
+}
+
+/// Forward and reflected power at the antenna port, dBm
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RfPower {
+    pub forward: f64,
+    pub reflected: f64,
+}
+
+impl RfPower {
+    /// dB, the larger the better matched
+    pub fn return_loss(&self) -> f64 {
+        self.forward - self.reflected
+    }
+
+    /// Infinite with as much coming back as going out
+    pub fn vswr(&self) -> f64 {
+        let gamma = 10f64.powf(-self.return_loss() / 20.0);
+        if gamma >= 1.0 {
+            return f64::INFINITY;
+        }
+        (1.0 + gamma) / (1.0 - gamma)
+    }
+}
+
+/// Forward and reflected power from outside the radio. Called from the poll loop around every
+/// transmission, so a sample should take a few milliseconds at most.
+pub trait PowerSensor {
+    fn sample(&mut self) -> io::Result<RfPower>;
+}
+
+/// A PowerSensor's samples around one frame
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Readings {
+    /// Just before keying up, the detectors' floor
+    pub idle: RfPower,
+    /// Just after, while the preamble goes out
+    pub keyed: RfPower,
+}
+
+impl fmt::Display for Readings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "forward {:.1} dBm reflected {:.1} dBm VSWR {:.2} (idle {:.1} dBm)",
+            self.keyed.forward,
+            self.keyed.reflected,
+            self.keyed.vswr(),
+            self.idle.forward
+        )
+    }
+}
+
+/// Two detectors on channels of a Linux IIO ADC (an ADS1015 on I2C, an MCP3202 on SPI, ...), read
+/// through sysfs. The `[power_sensor]` config section.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IioSensor {
+    /// The forward detector's raw value, e.g. /sys/bus/iio/devices/iio:device0/in_voltage0_raw
+    pub forward: PathBuf,
+    pub reflected: PathBuf,
+    pub forward_detector: Detector,
+    pub reflected_detector: Detector,
+}
+
+impl IioSensor {
+    fn read(path: &PathBuf) -> io::Result<f64> {
+        let raw = fs::read_to_string(path)?;
+        raw.trim().parse().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {:?} {}", path.display(), raw.trim(), e),
+            )
+        })
+    }
+}
+
+impl PowerSensor for IioSensor {
+    fn sample(&mut self) -> io::Result<RfPower> {
+        Ok(RfPower {
+            forward: self.forward_detector.dbm(Self::read(&self.forward)?),
+            reflected: self.reflected_detector.dbm(Self::read(&self.reflected)?),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    pub start: u16,
+    pub stop: u16,
+    pub step: u16,
+    /// Time keyed before sampling, long enough for the PA and the detector to settle
+    pub dwell: Duration,
+    /// GPADC conversions per step, each one dwell / samples apart
+    pub samples: usize,
+    pub detector: Detector,
+}
+
+impl Calibration {
+    pub fn coefficients(&self) -> impl Iterator<Item = u16> {
+        (self.start..=self.stop).step_by(self.step.max(1) as usize)
+    }
+
+    /// Runs the sweep, handing each Point to `on_point` as it completes. TXPWRCOEFFB is restored
+    /// afterwards and the radio left in POWEROFF, also when the sweep fails partway.
+    pub fn run(&self, radio: &mut Registers, on_point: impl FnMut(Point)) -> Result<Table> {
+        let original = radio.TXPWRCOEFFB().read()?;
+        let points = self.sweep(radio, on_point);
+        // An error mid-point leaves the carrier up, it comes down before the error goes anywhere
+        let restored = unkey(radio).and_then(|()| radio.TXPWRCOEFFB().write(original));
+        let points = points?;
+        restored?;
+        Ok(Table::new(points))
+    }
+
+    fn sweep(&self, radio: &mut Registers, mut on_point: impl FnMut(Point)) -> Result<Vec<Point>> {
+        let samples = self.samples.max(1);
+        let interval = self.dwell / samples as u32;
+        let mut points = Vec::new();
+
+        for coeff in self.coefficients() {
+            radio.TXPWRCOEFFB().write(coeff)?;
+            key(radio)?;
+            thread::sleep(interval);
+
+            let mut sum = 0.0;
+            for _ in 0..samples {
+                sum += f64::from(radio.sample_gpadc13()?);
+                thread::sleep(interval);
+            }
+            let point = Point {
+                coeff,
+                dbm: self.detector.dbm(sum / samples as f64),
+            };
+            on_point(point);
+            points.push(point);
+
+            unkey(radio)?;
+        }
+        Ok(points)
+    }
+}
+
+/// Unmodulated carrier, the same as examples/carrier.rs
+fn key(radio: &mut Registers) -> Result<()> {
+    radio.PWRMODE().write(PwrMode {
+        flags: PwrFlags::XOEN | PwrFlags::REFEN,
+        mode: PwrModes::TX,
+    })?;
+    radio
+        .FIFODATATX()
+        .write(FIFOChunkTX::TXCTRL(TXCtrl::SETPA | TXCtrl::PASTATE))?;
+    for _ in 0..CARRIER_CHUNKS {
+        radio.FIFODATATX().write(FIFOChunkTX::REPEATDATA {
+            flags: FIFODataTXFlags::UNENC,
+            count: 0xFF,
+            data: 0xFF,
+        })?;
+    }
+    radio
+        .FIFODATATX()
+        .write(FIFOChunkTX::TXCTRL(TXCtrl::SETPA))?;
+    radio.FIFOCMD().write(FIFOCmd {
+        mode: FIFOCmds::COMMIT,
+        auto_commit: false,
+    })
+}
+
+fn unkey(radio: &mut Registers) -> Result<()> {
+    // PM p. 12: The FIFO should be emptied before the PWRMODE is set to POWERDOWN
+    radio.FIFOCMD().write(FIFOCmd {
+        mode: FIFOCmds::CLEAR_DATA,
+        auto_commit: false,
+    })?;
+    radio.PWRMODE().write(PwrMode {
+        flags: PwrFlags::XOEN | PwrFlags::REFEN,
+        mode: PwrModes::POWEROFF,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(coeff: u16, dbm: f64) -> Point {
+        Point { coeff, dbm }
+    }
+
+    #[test]
+    fn table_interpolates() {
+        // Out of order, and saturating past 0x600
+        let table = Table::new(vec![
+            point(0x400, 20.0),
+            point(0x200, 10.0),
+            point(0x600, 24.0),
+            point(0x700, 23.5),
+        ]);
+        assert_eq!(table.points.len(), 3);
+        assert_eq!(table.range(), Some((10.0, 24.0)));
+        assert_eq!(table.coefficient(10.0), Some(0x200));
+        assert_eq!(table.coefficient(15.0), Some(0x300));
+        assert_eq!(table.coefficient(24.0), Some(0x600));
+        assert_eq!(table.coefficient(9.9), None);
+        assert_eq!(table.coefficient(24.1), None);
+        assert_eq!(Table::default().coefficient(0.0), None);
+    }
+
+    #[test]
+    fn sensor() {
+        let matched = RfPower {
+            forward: 30.0,
+            reflected: 10.0,
+        };
+        assert!((matched.vswr() - 1.2222).abs() < 1e-3);
+        let open = RfPower {
+            forward: 30.0,
+            reflected: 30.0,
+        };
+        assert_eq!(open.vswr(), f64::INFINITY);
+
+        let dir = std::env::temp_dir().join(format!("ax5043-power-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("in_voltage0_raw"), "1200\n").unwrap();
+        fs::write(dir.join("in_voltage1_raw"), "200\n").unwrap();
+        let detector = Detector {
+            intercept: -10.0,
+            slope: 0.025,
+        };
+        let mut sensor = IioSensor {
+            forward: dir.join("in_voltage0_raw"),
+            reflected: dir.join("in_voltage1_raw"),
+            forward_detector: detector,
+            reflected_detector: detector,
+        };
+        let power = sensor.sample().unwrap();
+        assert_eq!((power.forward, power.reflected), (20.0, -5.0));
+        fs::write(dir.join("in_voltage1_raw"), "busy").unwrap();
+        let e = sensor.sample().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn calibration_sweep() {
+        let calibration = Calibration {
+            start: 0x100,
+            stop: 0x400,
+            step: 0x100,
+            dwell: Duration::ZERO,
+            samples: 2,
+            detector: Detector {
+                intercept: -10.0,
+                slope: 0.05,
+            },
+        };
+        assert_eq!(
+            calibration.coefficients().collect::<Vec<_>>(),
+            vec![0x100, 0x200, 0x300, 0x400]
+        );
+
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(crate::Bus::Sink, &mut callback);
+        let mut seen = Vec::new();
+        let table = calibration.run(&mut radio, |p| seen.push(p)).unwrap();
+        // The sink's ADC always reads 0, the detector doesn't move
+        assert_eq!(seen.len(), 4);
+        assert!(seen.iter().all(|p| p.dbm == -10.0));
+        assert_eq!(table.points, vec![point(0x100, -10.0)]);
+        assert!(matches!(
+            table.set(&mut radio, 0.0),
+            Err(Error::PowerRange(_))
+        ));
+        assert_eq!(table.set(&mut radio, -10.0).unwrap(), 0x100);
+        // The simulator's GPADC never finishes converting, the first sample fails with the
+        // carrier up
+        let (a, _b) = crate::sim::pair(crate::sim::Channel::default());
+        let mut radio = Registers::new(crate::Bus::Sim(a), &mut callback);
+        radio.TXPWRCOEFFB().write(0x123).unwrap();
+        assert!(calibration.run(&mut radio, |_| ()).is_err());
+        assert_eq!(radio.PWRMODE().read().unwrap().mode, PwrModes::POWEROFF);
+        assert_eq!(radio.TXPWRCOEFFB().read().unwrap(), 0x123);
+    }
+}
@@ -0,0 +1,369 @@
+// LPOSC calibration and timestamps against a GPS pulse per second.
+//
+// The LPOSC (640 Hz, or 10.24 kHz with `fast`) clocks the wakeup timer that wake on radio sleeps
+// on. Free running it's good to a percent or so. With CALIBF/CALIBR the radio locks it to the
+// reference clock, the crystal over Xtal::div(), at LPOSCREF reference periods per LPOSC period,
+// which makes it exactly as good as the crystal.
+//
+//   [pps]
+//   pin = "gpiochip0:18"
+//   interval = 60
+//
+// Calibrator::service() reads WAKEUPTIMER on every pulse and counts LPOSC ticks across
+// `interval` whole seconds. The poll loop gets to a pulse a few ms after the kernel stamped its
+// edge, tens of ppm over a window, so the reading is taken back to the edge at the nominal LPOSC
+// rate (at_edge()). That rate is off by LPOSCREF's rounding at most, nothing over a few ms, and a
+// pulse served more than LATE after its edge starts the count over instead. The count times
+// LPOSCREF is the reference clock as the GPS sees it, so it measures the crystal's error (logged,
+// and a starting point for Xtal::ppm_correction) and gives the LPOSCREF that puts the LPOSC back
+// on 640 Hz. One tick over the window is the resolution: 26 ppm over 60 s at 640 Hz, 1.6 ppm at
+// 10.24 kHz. LPOSCREF itself only gets the LPOSC to within half a reference period, 13 ppm at
+// 640 Hz and 210 ppm at 10.24 kHz from a 48 MHz crystal, so `fast` measures the crystal better
+// and slow runs the wakeup timer closer.
+//
+// The pulse also pins down the system clock's fraction of a second: Clock keeps the offset from
+// the kernel's stamp of the last pulse to the nearest whole second. That's right as long as the
+// system clock is within half a second, from an RTC say, with no network time to steer it.
+use crate::{config, registers::*, Error, Registers, Result, RX, TX};
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Pulses further apart than this, or closer, start the count over
+const JITTER: Duration = Duration::from_millis(100);
+
+/// A pulse served this long after its edge isn't timed
+pub const LATE: Duration = Duration::from_millis(100);
+
+/// A measured crystal error past this is a miscount (a missed pulse, a reset), not the crystal
+pub const MAX_PPM: f64 = 50.0;
+
+fn default_interval() -> u32 {
+    60
+}
+
+/// The [pps] config section
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Discipline {
+    pub pin: crate::gpio::Pin,
+    /// Seconds of pulses per measurement
+    #[serde(default = "default_interval")]
+    pub interval: u32,
+    /// Run the LPOSC at 10.24 kHz rather than 640 Hz
+    #[serde(default)]
+    pub fast: bool,
+}
+
+impl Discipline {
+    pub fn validate(&self) -> Result<()> {
+        if self.interval == 0 {
+            return Err(Error::Pps("interval must be positive"));
+        }
+        Ok(())
+    }
+
+    /// Nominal LPOSC frequency, Hz
+    pub fn lposc(&self) -> f64 {
+        match self.fast {
+            true => 10_240.0,
+            false => 640.0,
+        }
+    }
+
+    /// LPOSCREF for `reference` Hz, unclamped
+    fn periods(&self, reference: f64) -> f64 {
+        (reference / self.lposc()).round()
+    }
+
+    /// Starts the LPOSC calibrated against the crystal as configured, before any pulses
+    pub fn write(&self, radio: &mut Registers, board: &config::Board) -> Result<()> {
+        let reference = board.xtal.corrected() as f64 / board.xtal.div() as f64;
+        let periods = self.periods(reference);
+        if !(1.0..=f64::from(u16::MAX)).contains(&periods) {
+            return Err(Error::Pps("LPOSCREF out of range for this crystal"));
+        }
+        radio.LPOSCREF().write(periods as u16)?;
+        let mut flags = LPOscConfig::ENA | LPOscConfig::CALIBF | LPOscConfig::CALIBR;
+        if self.fast {
+            flags |= LPOscConfig::FAST;
+        }
+        radio.LPOSCCONFIG().write(flags)
+    }
+}
+
+/// One window of pulses worked out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    /// LPOSC as counted against the pulses, Hz
+    pub lposc: f64,
+    /// The crystal, Hz
+    pub xtal: f64,
+    /// The crystal's error against its nominal frequency, positive when it runs fast
+    pub ppm: f64,
+    /// LPOSCREF for the nominal LPOSC frequency, only worth writing if plausible()
+    pub lposcref: u16,
+}
+
+impl Measurement {
+    pub fn plausible(&self) -> bool {
+        self.ppm.abs() <= MAX_PPM
+    }
+}
+
+/// Ticks counted across consecutive pulses
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Calibrator {
+    /// The last pulse and WAKEUPTIMER then
+    last: Option<(SystemTime, u16)>,
+    ticks: u64,
+    seconds: u32,
+}
+
+impl Calibrator {
+    pub fn restart(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Adds a pulse at `at` with WAKEUPTIMER reading `timer`. Returns the ticks and seconds of a
+    /// full window of `interval` seconds, then starts the next one from this pulse.
+    pub fn edge(&mut self, at: SystemTime, timer: u16, interval: u32) -> Option<(u64, u32)> {
+        let consecutive = self.last.and_then(|(last, _)| at.duration_since(last).ok());
+        match (consecutive, self.last) {
+            (Some(gap), Some((_, previous))) if gap.abs_diff(Duration::from_secs(1)) <= JITTER => {
+                // 16 bits is over 6 s of ticks even when fast
+                self.ticks += u64::from(timer.wrapping_sub(previous));
+                self.seconds += 1;
+            }
+            _ => {
+                self.ticks = 0;
+                self.seconds = 0;
+            }
+        }
+        self.last = Some((at, timer));
+        if self.seconds < interval {
+            return None;
+        }
+        let window = (self.ticks, self.seconds);
+        self.ticks = 0;
+        self.seconds = 0;
+        Some(window)
+    }
+
+    /// Call when the PPS line's fd is readable. Only the newest pulse is timed, if several
+    /// queued up the reads after them are late and the count starts over.
+    pub fn service(
+        &mut self,
+        radio: &mut Registers,
+        discipline: &Discipline,
+        board: &config::Board,
+        edges: &[SystemTime],
+    ) -> Result<Option<Measurement>> {
+        let Some(&at) = edges.last() else {
+            return Ok(None);
+        };
+        let timer = radio.WAKEUPTIMER().read()?;
+        let read = SystemTime::now();
+        if edges.len() > 1 {
+            self.restart();
+        }
+        let latency = match read.duration_since(at) {
+            Ok(latency) if latency <= LATE => latency,
+            _ => {
+                self.restart();
+                return Ok(None);
+            }
+        };
+        let timer = at_edge(timer, latency, discipline.lposc());
+        let Some((ticks, seconds)) = self.edge(at, timer, discipline.interval) else {
+            return Ok(None);
+        };
+        let lposcref = radio.LPOSCREF().read()?;
+        Ok(Some(measure(
+            discipline,
+            &board.xtal,
+            ticks,
+            seconds,
+            lposcref,
+        )))
+    }
+}
+
+/// WAKEUPTIMER `latency` before it read `timer`, counting at `lposc` Hz
+pub fn at_edge(timer: u16, latency: Duration, lposc: f64) -> u16 {
+    timer.wrapping_sub((latency.as_secs_f64() * lposc).round() as u16)
+}
+
+/// What `ticks` LPOSC periods in `seconds` say about the crystal, the LPOSC locked to it at
+/// `lposcref`
+pub fn measure(
+    discipline: &Discipline,
+    xtal: &config::Xtal,
+    ticks: u64,
+    seconds: u32,
+    lposcref: u16,
+) -> Measurement {
+    let lposc = ticks as f64 / f64::from(seconds);
+    let reference = lposc * f64::from(lposcref);
+    let measured = reference * xtal.div() as f64;
+    Measurement {
+        lposc,
+        xtal: measured,
+        ppm: (measured / xtal.freq as f64 - 1.0) * 1e6,
+        lposcref: discipline
+            .periods(reference)
+            .clamp(1.0, f64::from(u16::MAX)) as u16,
+    }
+}
+
+/// Rewrites LPOSCREF from a plausible measurement
+pub fn apply(radio: &mut Registers, measurement: &Measurement) -> Result<()> {
+    radio.LPOSCREF().write(measurement.lposcref)
+}
+
+/// The system clock corrected to the last pulse
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clock {
+    /// Nanoseconds to add to the system clock
+    offset: i64,
+}
+
+impl Clock {
+    /// A pulse stamped `at` by the system clock, which marks a whole second
+    pub fn edge(&mut self, at: SystemTime) {
+        let ns = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as i64;
+        let second = (ns + 500_000_000).div_euclid(1_000_000_000) * 1_000_000_000;
+        self.offset = second - ns;
+    }
+
+    /// Nanoseconds to add to the system clock, 0 before the first pulse
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    pub fn correct(&self, time: SystemTime) -> SystemTime {
+        let offset = Duration::from_nanos(self.offset.unsigned_abs());
+        match self.offset >= 0 {
+            true => time + offset,
+            false => time - offset,
+        }
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.correct(SystemTime::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> config::Config {
+        toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap()
+    }
+
+    fn discipline(fast: bool) -> Discipline {
+        Discipline {
+            pin: "gpiochip0:18".parse().unwrap(),
+            interval: 60,
+            fast,
+        }
+    }
+
+    /// Pulses at whole seconds from 1000 s, WAKEUPTIMER counting at `lposc` Hz
+    fn pulse(second: u32, lposc: f64) -> (SystemTime, u16) {
+        let at = UNIX_EPOCH + Duration::from_secs(1000 + u64::from(second));
+        (at, (f64::from(second) * lposc + 60_000.0) as u64 as u16)
+    }
+
+    #[test]
+    fn calibrate() {
+        let config = config();
+        let xtal = config.board.xtal;
+        let pps = discipline(true);
+        let writes = crate::dry_run(|radio| pps.write(radio, &config.board)).unwrap();
+        // 48 MHz over 2 over 10.24 kHz, rounded
+        assert_eq!(writes.to("LPOSCREF")[0].data, 2344u16.to_be_bytes());
+        assert_eq!(writes.to("LPOSCCONFIG")[0].data, [0x33]);
+
+        // The crystal 2 ppm fast, the LPOSC locked to it
+        let reference = xtal.freq as f64 / 2.0 * (1.0 + 2e-6);
+        let lposc = reference / 2344.0;
+        let mut calibrator = Calibrator::default();
+        let mut window = None;
+        for second in 0..=60 {
+            let (at, timer) = pulse(second, lposc);
+            assert_eq!(window, None);
+            window = calibrator.edge(at, timer, pps.interval);
+        }
+        let (ticks, seconds) = window.unwrap();
+        assert_eq!(seconds, 60);
+        let measured = measure(&pps, &xtal, ticks, seconds, 2344);
+        assert!((measured.ppm - 2.0).abs() < 1.6, "{:?}", measured);
+        assert!(measured.plausible());
+        assert_eq!(measured.lposcref, 2344);
+
+        let slow = measure(&discipline(false), &xtal, 640 * 60, 60, 37_500);
+        assert_eq!(slow.ppm, 0.0);
+        assert_eq!(slow.lposcref, 37_500);
+        // WAKEUPTIMER stuck, as without a radio
+        assert!(!measure(&pps, &xtal, 0, 60, 2344).plausible());
+    }
+
+    #[test]
+    fn missed_pulse() {
+        let pps = Discipline {
+            interval: 3,
+            ..discipline(false)
+        };
+        let mut calibrator = Calibrator::default();
+        let mut count = |second| {
+            let (at, timer) = pulse(second, 640.0);
+            calibrator.edge(at, timer, pps.interval)
+        };
+        assert_eq!(count(0), None);
+        assert_eq!(count(1), None);
+        // 2 never came, 3 starts over
+        assert_eq!(count(3), None);
+        assert_eq!(count(4), None);
+        assert_eq!(count(5), None);
+        assert_eq!(count(6), Some((3 * 640, 3)));
+        // and the next window follows straight on, across WAKEUPTIMER wrapping
+        assert_eq!(count(7), None);
+        assert_eq!(count(8), None);
+        assert_eq!(count(9), Some((3 * 640, 3)));
+    }
+
+    #[test]
+    fn latency() {
+        let pps = Discipline {
+            interval: 2,
+            ..discipline(true)
+        };
+        let mut calibrator = Calibrator::default();
+        // Read 3 to 9 ms after each edge, 31 to 92 ticks late at 10.24 kHz
+        let mut window = None;
+        for (second, ms) in [(0, 3), (1, 9), (2, 5)] {
+            let (at, timer) = pulse(second, pps.lposc());
+            let late = Duration::from_millis(ms);
+            let read = timer.wrapping_add((late.as_secs_f64() * pps.lposc()).round() as u16);
+            window = calibrator.edge(at, at_edge(read, late, pps.lposc()), pps.interval);
+        }
+        assert_eq!(window, Some((2 * 10_240, 2)));
+        assert_eq!(at_edge(10, Duration::from_millis(2), 10_240.0), 65_526);
+    }
+
+    #[test]
+    fn clock() {
+        let mut clock = Clock::default();
+        let stamp = UNIX_EPOCH + Duration::from_micros(1_000_000_150);
+        assert_eq!(clock.correct(stamp), stamp);
+        clock.edge(stamp);
+        assert_eq!(clock.offset(), -150_000);
+        assert_eq!(
+            clock.correct(stamp + Duration::from_millis(20)),
+            UNIX_EPOCH + Duration::from_millis(1_000_020)
+        );
+        // Late the other way
+        clock.edge(UNIX_EPOCH + Duration::from_micros(999_999_900));
+        assert_eq!(clock.offset(), 100_000);
+    }
+}
@@ -0,0 +1,132 @@
+// Telemetry sessions on disk, so a pass can be reviewed in the tui afterwards or the UI demoed
+// without hardware.
+//
+// The datagrams are stored as received (CBOR CommState, see tui.rs), each with its arrival
+// time so playback keeps the original pacing:
+//
+//   "AX5043TL" magic, then per datagram: u64 LE unix microseconds, u32 LE length, data
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Result, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAGIC: &[u8; 8] = b"AX5043TL";
+
+pub struct Recorder<W: Write> {
+    out: W,
+}
+
+/// Creates (truncates) `path` and writes the header
+pub fn create<P: AsRef<Path>>(path: P) -> Result<Recorder<BufWriter<File>>> {
+    Recorder::new(BufWriter::new(File::create(path)?))
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut out: W) -> Result<Self> {
+        out.write_all(MAGIC)?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+
+    /// Appends one datagram stamped with the current time. Flushes so a crash still leaves a
+    /// readable file.
+    pub fn write(&mut self, datagram: &[u8]) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.write_at(now.as_micros() as u64, datagram)?;
+        self.out.flush()
+    }
+
+    fn write_at(&mut self, micros: u64, datagram: &[u8]) -> Result<()> {
+        self.out.write_all(&micros.to_le_bytes())?;
+        self.out.write_all(&(datagram.len() as u32).to_le_bytes())?;
+        self.out.write_all(datagram)
+    }
+}
+
+/// One recorded datagram
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Unix microseconds when it arrived
+    pub micros: u64,
+    pub datagram: Vec<u8>,
+}
+
+pub struct Player<R: Read> {
+    input: R,
+}
+
+/// Opens a recording made by create(), checking the header
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Player<BufReader<File>>> {
+    Player::new(BufReader::new(File::open(path)?))
+}
+
+impl<R: Read> Player<R> {
+    pub fn new(mut input: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "not a telemetry recording",
+            ));
+        }
+        Ok(Self { input })
+    }
+
+    /// The next datagram, None at the end. A record cut short (recorder killed mid write)
+    /// also ends the recording.
+    pub fn next_record(&mut self) -> Result<Option<Record>> {
+        let mut header = [0; 12];
+        match self.input.read_exact(&mut header) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            other => other?,
+        }
+        let micros = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let len = u32::from_le_bytes(header[8..].try_into().unwrap());
+        let mut datagram = vec![0; len as usize];
+        match self.input.read_exact(&mut datagram) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            other => other?,
+        }
+        Ok(Some(Record { micros, datagram }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        recorder.write_at(1_000_000, b"first").unwrap();
+        recorder.write_at(1_250_000, b"").unwrap();
+        recorder.write_at(2_000_000, &[0xA1; 300]).unwrap();
+        let mut bytes = recorder.out;
+
+        let mut player = Player::new(&bytes[..]).unwrap();
+        let micros: Vec<_> = std::iter::from_fn(|| player.next_record().unwrap())
+            .map(|r| (r.micros, r.datagram.len()))
+            .collect();
+        assert_eq!(
+            micros,
+            vec![(1_000_000, 5), (1_250_000, 0), (2_000_000, 300)]
+        );
+
+        // Truncated last record
+        bytes.truncate(bytes.len() - 1);
+        let mut player = Player::new(&bytes[..]).unwrap();
+        assert_eq!(
+            player.next_record().unwrap().unwrap().datagram,
+            b"first".to_vec()
+        );
+        player.next_record().unwrap().unwrap();
+        assert_eq!(player.next_record().unwrap(), None);
+
+        assert!(Player::new(&b"pcapng.."[..]).is_err());
+    }
+}
@@ -0,0 +1,418 @@
+// CCSDS Reed-Solomon (255,223) in software, an outer code over whatever framing the channel uses.
+//
+// CCSDS 131.0-B: GF(256) from x^8 + x^7 + x^2 + x + 1, generator roots α^(11j) for j = 112..143,
+// symbols in the dual (Berlekamp) basis on the air. 32 parity bytes correct up to 16 bad bytes
+// per codeword. Shorter frames are shortened codewords, the missing leading bytes taken as zero.
+// With `interleave` I the frame carries I codewords byte by byte, so a burst of up to 16·I bad
+// bytes in a row is still corrected, and the payload can be up to 223·I.
+//
+//   [[channel]]
+//   ...
+//   reed_solomon = { interleave = 2 }
+//
+// The radio's framing and CRC still wrap the result. A frame with a few bad bytes fails the CRC,
+// so set `crc` to None (there's no trailer then), `crc_trailer` to Flag or [accept]
+// `crc_failed` to let the decoder see it, the config is refused otherwise. The uhf bin
+// encodes what it sends and decodes what it receives on a channel with this set, the lband bin
+// decodes.
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Codeword length
+pub const N: usize = 255;
+/// Data bytes in a full codeword
+pub const K: usize = 223;
+/// Parity bytes per codeword
+pub const PARITY: usize = N - K;
+
+/// First consecutive root, as a power of α^PRIM
+const FCR: usize = 112;
+const PRIM: usize = 11;
+
+const fn tables() -> ([u8; 256], [u8; 256]) {
+    let mut exp = [0u8; 256];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x187;
+        }
+        i += 1;
+    }
+    exp[255] = exp[0];
+    (exp, log)
+}
+
+const EXP: [u8; 256] = tables().0;
+const LOG: [u8; 256] = tables().1;
+
+/// CCSDS 131.0-B Annex F, conventional to dual basis by rows
+const TAL: [u8; 8] = [0x8D, 0xEF, 0xEC, 0x86, 0xFA, 0x99, 0xAF, 0x7B];
+
+const fn dual_tables() -> ([u8; 256], [u8; 256]) {
+    let mut to_dual = [0u8; 256];
+    let mut from_dual = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut k = 0;
+        while k < 8 {
+            if i & (1 << k) != 0 {
+                to_dual[i] ^= TAL[7 - k];
+            }
+            k += 1;
+        }
+        from_dual[to_dual[i] as usize] = i as u8;
+        i += 1;
+    }
+    (to_dual, from_dual)
+}
+
+const TO_DUAL: [u8; 256] = dual_tables().0;
+const FROM_DUAL: [u8; 256] = dual_tables().1;
+
+fn mul(a: u8, b: u8) -> u8 {
+    match (a, b) {
+        (0, _) | (_, 0) => 0,
+        _ => EXP[(usize::from(LOG[a as usize]) + usize::from(LOG[b as usize])) % 255],
+    }
+}
+
+fn div(a: u8, b: u8) -> u8 {
+    match a {
+        0 => 0,
+        _ => EXP[(usize::from(LOG[a as usize]) + 255 - usize::from(LOG[b as usize])) % 255],
+    }
+}
+
+/// α^e for any e
+fn pow(e: usize) -> u8 {
+    EXP[e % 255]
+}
+
+/// γ^e, γ = α^PRIM, for e from -255·255 up
+fn gamma(e: isize) -> u8 {
+    pow((e * PRIM as isize).rem_euclid(255) as usize)
+}
+
+/// Generator polynomial in the conventional basis, highest power first
+fn generator() -> [u8; PARITY + 1] {
+    let mut g = [0u8; PARITY + 1];
+    g[0] = 1;
+    for j in 0..PARITY {
+        let root = gamma((FCR + j) as isize);
+        // g = g·(x - root), from the constant term up
+        for i in (1..=j + 1).rev() {
+            g[i] ^= mul(g[i - 1], root);
+        }
+    }
+    g
+}
+
+/// Parity for one codeword of conventional basis `data`, shortened to its length
+fn parity(data: &[u8]) -> [u8; PARITY] {
+    let g = generator();
+    let mut parity = [0u8; PARITY];
+    for &byte in data {
+        let feedback = byte ^ parity[0];
+        parity.copy_within(1.., 0);
+        parity[PARITY - 1] = 0;
+        if feedback != 0 {
+            for (p, &g) in parity.iter_mut().zip(&g[1..]) {
+                *p ^= mul(feedback, g);
+            }
+        }
+    }
+    parity
+}
+
+/// Corrects one conventional basis codeword, shortened to its length, in place. Returns how many
+/// bytes were wrong.
+fn correct(codeword: &mut [u8]) -> Option<usize> {
+    let len = codeword.len();
+    // S_j = r(γ^(FCR + j)), the first byte is the highest power
+    let mut syndromes = [0u8; PARITY];
+    for (j, s) in syndromes.iter_mut().enumerate() {
+        let x = gamma((FCR + j) as isize);
+        *s = codeword.iter().fold(0, |acc, &r| mul(acc, x) ^ r);
+    }
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey for the error locator Λ, lowest power first
+    let mut lambda = vec![1u8];
+    let mut previous = vec![1u8];
+    let (mut errors, mut shift, mut last) = (0, 1, 1u8);
+    for n in 0..PARITY {
+        let discrepancy = (1..=errors).fold(syndromes[n], |d, i| {
+            d ^ mul(*lambda.get(i).unwrap_or(&0), syndromes[n - i])
+        });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = div(discrepancy, last);
+        let mut next = lambda.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &b) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(scale, b);
+        }
+        if 2 * errors <= n {
+            previous = std::mem::replace(&mut lambda, next);
+            errors = n + 1 - errors;
+            last = discrepancy;
+            shift = 1;
+        } else {
+            lambda = next;
+            shift += 1;
+        }
+    }
+    if errors > PARITY / 2 {
+        return None;
+    }
+
+    // Ω = S·Λ mod x^PARITY
+    let mut omega = [0u8; PARITY];
+    for (i, o) in omega.iter_mut().enumerate() {
+        for (k, &l) in lambda.iter().enumerate().take(i + 1) {
+            *o ^= mul(l, syndromes[i - k]);
+        }
+    }
+    let eval = |poly: &[u8], x: u8| poly.iter().rev().fold(0, |acc, &c| mul(acc, x) ^ c);
+
+    // Chien search over the positions the codeword has, then Forney for the values
+    let mut found = 0;
+    for (i, byte) in codeword.iter_mut().enumerate() {
+        let power = (len - 1 - i) as isize;
+        let inverse = gamma(-power);
+        if eval(&lambda, inverse) != 0 {
+            continue;
+        }
+        // Λ' keeps the odd terms
+        let derivative: Vec<u8> = lambda
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(k, &l)| if k % 2 == 1 { l } else { 0 })
+            .collect();
+        let denominator = eval(&derivative, inverse);
+        if denominator == 0 {
+            return None;
+        }
+        let value = mul(
+            gamma(power * (1 - FCR as isize)),
+            div(eval(&omega, inverse), denominator),
+        );
+        *byte ^= value;
+        found += 1;
+    }
+    // Roots outside the codeword, in the shortened part, mean too many errors
+    if found != errors {
+        return None;
+    }
+    Some(found)
+}
+
+fn one() -> u8 {
+    1
+}
+
+/// `reed_solomon` in a [[channel]]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReedSolomon {
+    /// Codewords per frame, byte interleaved. CCSDS allows 1 to 5 and 8.
+    #[serde(default = "one")]
+    pub interleave: u8,
+}
+
+impl Default for ReedSolomon {
+    fn default() -> Self {
+        Self { interleave: 1 }
+    }
+}
+
+impl ReedSolomon {
+    pub fn validate(&self) -> Result<()> {
+        if !matches!(self.interleave, 1..=5 | 8) {
+            return Err(Error::ReedSolomon("interleave must be 1 to 5 or 8"));
+        }
+        Ok(())
+    }
+
+    fn depth(&self) -> usize {
+        usize::from(self.interleave)
+    }
+
+    /// Longest payload encode() takes
+    pub fn max_payload(&self) -> usize {
+        K * self.depth()
+    }
+
+    /// How much longer encode() makes a payload
+    pub fn overhead(&self) -> usize {
+        PARITY * self.depth()
+    }
+
+    /// `payload` followed by its parity, interleaved. The payload has to be a whole number of
+    /// bytes per codeword.
+    pub fn encode(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.validate()?;
+        let depth = self.depth();
+        if payload.is_empty() || payload.len() > self.max_payload() {
+            return Err(Error::ReedSolomon("payload too long for the codewords"));
+        }
+        if !payload.len().is_multiple_of(depth) {
+            return Err(Error::ReedSolomon(
+                "payload not a multiple of the interleave",
+            ));
+        }
+        let mut frame = payload.to_vec();
+        frame.resize(payload.len() + self.overhead(), 0);
+        for codeword in 0..depth {
+            let data: Vec<u8> = payload
+                .iter()
+                .skip(codeword)
+                .step_by(depth)
+                .map(|&b| FROM_DUAL[b as usize])
+                .collect();
+            for (i, p) in parity(&data).iter().enumerate() {
+                frame[payload.len() + i * depth + codeword] = TO_DUAL[*p as usize];
+            }
+        }
+        Ok(frame)
+    }
+
+    /// The payload out of an encode()d frame with up to 16 bad bytes per codeword, and how many
+    /// were corrected
+    pub fn decode(&self, frame: &[u8]) -> Result<(Vec<u8>, usize)> {
+        self.validate()?;
+        let depth = self.depth();
+        if frame.len() <= self.overhead()
+            || frame.len() > N * depth
+            || !frame.len().is_multiple_of(depth)
+        {
+            return Err(Error::ReedSolomon(
+                "frame isn't a whole number of codewords",
+            ));
+        }
+        let mut payload = frame[..frame.len() - self.overhead()].to_vec();
+        let mut corrected = 0;
+        for codeword in 0..depth {
+            let mut symbols: Vec<u8> = frame
+                .iter()
+                .skip(codeword)
+                .step_by(depth)
+                .map(|&b| FROM_DUAL[b as usize])
+                .collect();
+            corrected += correct(&mut symbols).ok_or(Error::ReedSolomon("uncorrectable"))?;
+            for (i, &b) in symbols[..symbols.len() - PARITY].iter().enumerate() {
+                payload[i * depth + codeword] = TO_DUAL[b as usize];
+            }
+        }
+        Ok((payload, corrected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 37 + 11) as u8).collect()
+    }
+
+    #[test]
+    fn code() {
+        // The roots come in inverse pairs, so the generator reads the same both ways
+        let g = generator();
+        assert!(g.iter().eq(g.iter().rev()));
+        assert!((0..=255).all(|i| FROM_DUAL[TO_DUAL[i] as usize] == i as u8));
+        assert_eq!(TO_DUAL[0x01], 0x7B);
+        // Zeros encode to zeros in either basis
+        let zeros = ReedSolomon::default().encode(&[0; K]).unwrap();
+        assert_eq!(zeros, [0; N]);
+    }
+
+    /// Tr(a), in GF(2)
+    fn trace(a: u8) -> u8 {
+        let (mut sum, mut power) = (0, a);
+        for _ in 0..8 {
+            sum ^= power;
+            power = mul(power, power);
+        }
+        sum
+    }
+
+    #[test]
+    fn known_answer() {
+        // The dual basis as the standard defines it, bit k (first on the air) Tr(a·β^k) with
+        // β = α^117, agrees with the TAL matrix
+        for a in 0..=255u8 {
+            let dual = (0..8).fold(0, |dual, k| dual | trace(mul(a, pow(117 * k))) << (7 - k));
+            assert_eq!(TO_DUAL[a as usize], dual);
+        }
+
+        // Payload 0, 1, ..., 222. The parity was worked out separately, by long division by the
+        // generator in the conventional basis with the trace definition above for the dual
+        // basis, so a wrong generator, root or basis here doesn't reproduce it.
+        let data: Vec<u8> = (0..K as u8).collect();
+        let frame = ReedSolomon::default().encode(&data).unwrap();
+        assert_eq!(
+            frame[K..],
+            [
+                0x4F, 0xFB, 0x92, 0xDD, 0x55, 0x7E, 0xC6, 0x7F, 0x27, 0xFB, 0x89, 0x82, 0xCF, 0x58,
+                0xF8, 0xFD, 0x02, 0x8A, 0xD1, 0x17, 0xFC, 0xEF, 0x6B, 0x27, 0x93, 0xD0, 0x41, 0x88,
+                0x26, 0x57, 0x86, 0x51,
+            ]
+        );
+    }
+
+    #[test]
+    fn corrects() {
+        let rs = ReedSolomon::default();
+        let data = payload(K);
+        let mut frame = rs.encode(&data).unwrap();
+        assert_eq!(frame.len(), N);
+        assert_eq!(frame[..K], data[..]);
+        assert_eq!(rs.decode(&frame).unwrap(), (data.clone(), 0));
+
+        for i in 0..16 {
+            frame[i * 15 + 3] ^= 0x5A + i as u8;
+        }
+        assert_eq!(rs.decode(&frame).unwrap(), (data.clone(), 16));
+        frame[250] ^= 0xFF;
+        assert!(matches!(rs.decode(&frame), Err(Error::ReedSolomon(_))));
+
+        // Shortened
+        let short = payload(40);
+        let mut frame = rs.encode(&short).unwrap();
+        assert_eq!(frame.len(), 40 + PARITY);
+        frame[0] = !frame[0];
+        frame[71] ^= 1;
+        assert_eq!(rs.decode(&frame).unwrap(), (short, 2));
+    }
+
+    #[test]
+    fn interleaved() {
+        let rs = ReedSolomon { interleave: 2 };
+        let data = payload(300);
+        let mut frame = rs.encode(&data).unwrap();
+        assert_eq!(frame.len(), 300 + 2 * PARITY);
+        // 32 in a row is 16 in each codeword
+        for b in &mut frame[100..132] {
+            *b = !*b;
+        }
+        assert_eq!(rs.decode(&frame).unwrap(), (data, 32));
+
+        assert!(rs.encode(&payload(301)).is_err());
+        assert!(rs.encode(&payload(2 * K + 2)).is_err());
+        assert!(ReedSolomon { interleave: 6 }.validate().is_err());
+        assert!(rs.decode(&[0; 2 * PARITY]).is_err());
+        let parsed: ReedSolomon = toml::from_str("").unwrap();
+        assert_eq!(parsed, ReedSolomon::default());
+    }
+}
@@ -162,42 +162,42 @@ impl From<i32> for Reg32 {
 proptest! {
     #[test]
     fn reg8_u8_inverse(n: u8) {
-        assert_eq!(n, Reg8::from(n).try_into().unwrap());
+        assert_eq!(n, u8::try_from(Reg8::from(n)).unwrap());
     }
 
     #[test]
     fn reg8_i8_inverse(n: i8) {
-        assert_eq!(n, Reg8::from(n).try_into().unwrap());
+        assert_eq!(n, i8::try_from(Reg8::from(n)).unwrap());
     }
 
     #[test]
     fn reg16_u16_inverse(n: u16) {
-        assert_eq!(n, Reg16::from(n).try_into().unwrap());
+        assert_eq!(n, u16::try_from(Reg16::from(n)).unwrap());
     }
 
     #[test]
     fn reg16_i16_inverse(n: i16) {
-        assert_eq!(n, Reg16::from(n).try_into().unwrap());
+        assert_eq!(n, i16::try_from(Reg16::from(n)).unwrap());
     }
 
     #[test] // FIXME: Test whole range, possibly fallible conversion?
     fn reg24_u32_inverse(n in 0..2_u32.pow(24)) {
-        assert_eq!(n, Reg24::from(n).try_into().unwrap());
+        assert_eq!(n, u32::try_from(Reg24::from(n)).unwrap());
     }
 
     #[test] // FIXME: Test whole range, possibly fallible conversion?
     fn reg24_i32_inverse(n in -2_i32.pow(23)..2_i32.pow(23)) {
-        assert_eq!(n, Reg24::from(n).try_into().unwrap());
+        assert_eq!(n, i32::try_from(Reg24::from(n)).unwrap());
     }
 
     #[test]
     fn reg32_u32_inverse(n: u32) {
-        assert_eq!(n, Reg32::from(n).try_into().unwrap());
+        assert_eq!(n, u32::try_from(Reg32::from(n)).unwrap());
     }
 
     #[test]
     fn reg32_i32_inverse(n: i32) {
-        assert_eq!(n, Reg32::from(n).try_into().unwrap());
+        assert_eq!(n, i32::try_from(Reg32::from(n)).unwrap());
     }
 }
 
@@ -277,24 +277,130 @@ proptest! {
     #[test]
     fn float4_convert(n: u64) {
         let shift = (u64::BITS - n.leading_zeros()).saturating_sub(4);
-        assert_eq!(n & 0xF << shift, Float4::new(n).into());
+        assert_eq!(n & 0xF << shift, u64::from(Float4::new(n)));
     }
 
     #[test]
     fn float5_convert(n: u64) {
         let shift = (u64::BITS - n.leading_zeros()).saturating_sub(5);
-        assert_eq!(n & 0x1F << shift, Float5::new(n).into());
+        assert_eq!(n & 0x1F << shift, u64::from(Float5::new(n)));
     }
 }
 
 #[test]
 fn float4_zero() {
-    assert_eq!(0u64, Float4::new(0).into());
+    assert_eq!(0u64, u64::from(Float4::new(0)));
 }
 
 #[test]
 fn float5_zero() {
-    assert_eq!(0u64, Float5::new(0).into());
+    assert_eq!(0u64, u64::from(Float5::new(0)));
+}
+
+/// An unsigned field `N` bits wide, sharing a register with other fields. Values that don't fit
+/// are rejected when the field is built instead of spilling into the neighbouring field on write.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct Bits<const N: u8>(u8);
+
+pub type U2 = Bits<2>;
+pub type U3 = Bits<3>;
+pub type U4 = Bits<4>;
+pub type U5 = Bits<5>;
+pub type U6 = Bits<6>;
+pub type U7 = Bits<7>;
+
+impl<const N: u8> Bits<N> {
+    pub const MAX: u8 = ((1u16 << N) - 1) as u8;
+
+    /// For constants: out of range fails to compile in a const context and panics otherwise.
+    /// Use try_from() for anything computed.
+    pub const fn new(value: u8) -> Self {
+        assert!(
+            value <= Self::MAX,
+            "value doesn't fit in the register field"
+        );
+        Self(value)
+    }
+
+    /// The low `N` bits of `value`, for decoding
+    pub const fn mask(value: u8) -> Self {
+        Self(value & Self::MAX)
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    /// The same value in a field at least as wide
+    pub const fn widen<const M: u8>(self) -> Bits<M> {
+        const { assert!(M >= N, "narrowing needs try_from()") };
+        Bits(self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, thiserror::Error)]
+#[error("{value} doesn't fit in {bits} bits")]
+pub struct OutOfRange {
+    pub value: u8,
+    pub bits: u8,
+}
+
+impl<const N: u8> TryFrom<u8> for Bits<N> {
+    type Error = OutOfRange;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value > Self::MAX {
+            return Err(OutOfRange { value, bits: N });
+        }
+        Ok(Self(value))
+    }
+}
+
+impl<const N: u8> From<Bits<N>> for u8 {
+    fn from(item: Bits<N>) -> Self {
+        item.0
+    }
+}
+
+// Plain numbers, so register dumps read the same as before
+impl<const N: u8> fmt::Debug for Bits<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<const N: u8> fmt::Display for Bits<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[test]
+fn bits_range() {
+    assert_eq!(U4::MAX, 0x0F);
+    assert_eq!(U7::MAX, 0x7F);
+    assert_eq!(U4::try_from(0x0F).map(U4::get), Ok(0x0F));
+    assert_eq!(
+        U4::try_from(0x93),
+        Err(OutOfRange {
+            value: 0x93,
+            bits: 4
+        })
+    );
+    assert_eq!(U3::mask(0xFF), U3::new(7));
+    assert_eq!(U4::new(9).widen::<5>(), U5::new(9));
+    assert_eq!(format!("{:?}", U5::new(17)), "17");
+    assert!(std::panic::catch_unwind(|| U2::new(4)).is_err());
+
+    #[derive(Debug, Deserialize)]
+    struct Field {
+        gain: U4,
+    }
+    assert_eq!(
+        toml::from_str::<Field>("gain = 15").unwrap().gain,
+        U4::new(15)
+    );
+    assert!(toml::from_str::<Field>("gain = 16").is_err());
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive, Serialize, Deserialize)]
@@ -313,6 +419,41 @@ pub enum PwrModes{
     TX        = 0b1101,
 }
 
+/// Writes the description of each flag in `table` that's set, comma separated, or "none"
+pub(crate) fn write_words<F: bitflags::Flags + Copy>(
+    f: &mut fmt::Formatter,
+    flags: &F,
+    table: &[(F, &str)],
+) -> fmt::Result {
+    let mut words = table
+        .iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, word)| word);
+    match words.next() {
+        None => write!(f, "none"),
+        Some(first) => {
+            write!(f, "{}", first)?;
+            words.try_for_each(|word| write!(f, ", {}", word))
+        }
+    }
+}
+
+impl fmt::Display for PwrModes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PwrModes::POWEROFF => "powered off",
+            PwrModes::DEEPSLEEP => "deep sleep",
+            PwrModes::XOEN => "crystal on",
+            PwrModes::FIFOEN => "FIFO on",
+            PwrModes::SYNTHRX => "synthesizer on for RX",
+            PwrModes::RX => "RX",
+            PwrModes::WORRX => "wake on radio RX",
+            PwrModes::SYNTHTX => "synthesizer on for TX",
+            PwrModes::TX => "TX",
+        })
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
     pub struct PwrFlags: u8 {
@@ -345,6 +486,24 @@ impl From<PwrMode> for Reg8 {
     }
 }
 
+impl fmt::Display for PwrMode {
+    /// "RX (crystal enabled, reference enabled)"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (", self.mode)?;
+        write_words(
+            f,
+            &self.flags,
+            &[
+                (PwrFlags::RST, "reset"),
+                (PwrFlags::XOEN, "crystal enabled"),
+                (PwrFlags::REFEN, "reference enabled"),
+                (PwrFlags::WDS, "wakeup from deep sleep"),
+            ],
+        )?;
+        write!(f, ")")
+    }
+}
+
 #[cfg(test)]
 proptest! {
     #[test]
@@ -378,6 +537,12 @@ impl TryFrom<Reg8> for PowStat {
     }
 }
 
+impl From<PowStat> for Reg8 {
+    fn from(item: PowStat) -> Self {
+        item.bits().into()
+    }
+}
+
 #[cfg(test)]
 proptest! {
     #[test]
@@ -561,6 +726,19 @@ bitflags! {
     }
 }
 
+impl fmt::Display for Encoding {
+    /// The named combination if there is one (NRZI rather than INV | DIFF)
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match <Encoding as bitflags::Flags>::FLAGS
+            .iter()
+            .find(|flag| flag.value() == self)
+        {
+            Some(flag) => f.write_str(flag.name()),
+            None => bitflags::parser::to_writer(self, f),
+        }
+    }
+}
+
 impl TryFrom<Reg8> for Encoding {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
@@ -668,7 +846,7 @@ bitflags! {
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FEC {
     pub flags: FECFlags,
-    pub inpshift: u8,
+    pub inpshift: U3,
 }
 
 impl TryFrom<Reg8> for FEC {
@@ -676,14 +854,14 @@ impl TryFrom<Reg8> for FEC {
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
             flags: FECFlags::from_bits(item[0] & 0xF1).ok_or(item)?,
-            inpshift: item[0] & 0x0E,
+            inpshift: U3::mask(item[0] >> 1),
         })
     }
 }
 
 impl From<FEC> for Reg8 {
     fn from(item: FEC) -> Self {
-        ((item.inpshift & 0x7) << 1 | item.flags.bits()).into()
+        (item.inpshift.get() << 1 | item.flags.bits()).into()
     }
 }
 
@@ -703,7 +881,13 @@ impl TryFrom<Reg8> for FECStatus {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, TryFromPrimitive, Serialize, Deserialize)]
+impl From<FECStatus> for Reg8 {
+    fn from(item: FECStatus) -> Self {
+        (item.max_metric | if item.inv { 0x80 } else { 0 }).into()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive, Serialize, Deserialize)]
 #[repr(u8)]
 #[allow(non_camel_case_types)]
 #[rustfmt::skip]
@@ -728,6 +912,30 @@ impl TryFrom<Reg8> for RadioState {
     }
 }
 
+impl From<RadioState> for Reg8 {
+    fn from(item: RadioState) -> Self {
+        u8::from(item).into()
+    }
+}
+
+impl fmt::Display for RadioState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RadioState::IDLE => "idle",
+            RadioState::POWERDOWN => "powered down",
+            RadioState::TX_PLL_SETTINGS => "TX, PLL settling",
+            RadioState::TX => "transmitting",
+            RadioState::TX_TAIL => "TX tail",
+            RadioState::RX_PLL_SETTINGS => "RX, PLL settling",
+            RadioState::RX_ANTENNA_SELECTION => "RX, selecting antenna",
+            RadioState::RX_PREAMBLE_1 => "RX, looking for preamble",
+            RadioState::RX_PREAMBLE_2 => "RX, preamble 2",
+            RadioState::RX_PREAMBLE_3 => "RX, preamble 3",
+            RadioState::RX => "receiving",
+        })
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq)]
     pub struct XtalStatus: u8 {
@@ -742,6 +950,12 @@ impl TryFrom<Reg8> for XtalStatus {
     }
 }
 
+impl From<XtalStatus> for Reg8 {
+    fn from(item: XtalStatus) -> Self {
+        item.bits().into()
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq)]
     pub struct PinState: u8 {
@@ -761,6 +975,12 @@ impl TryFrom<Reg8> for PinState {
     }
 }
 
+impl From<PinState> for Reg8 {
+    fn from(item: PinState) -> Self {
+        item.bits().into()
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 #[allow(non_camel_case_types)]
@@ -1015,7 +1235,7 @@ impl From<PwrAmp> for Reg8 {
 }
 
 bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
     pub struct FIFOStat: u8 {
         const EMPTY       = 1 << 0;
         const FULL        = 1 << 1;
@@ -1034,6 +1254,30 @@ impl TryFrom<Reg8> for FIFOStat {
     }
 }
 
+impl From<FIFOStat> for Reg8 {
+    fn from(item: FIFOStat) -> Self {
+        item.bits().into()
+    }
+}
+
+impl fmt::Display for FIFOStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_words(
+            f,
+            self,
+            &[
+                (FIFOStat::EMPTY, "empty"),
+                (FIFOStat::FULL, "full"),
+                (FIFOStat::UNDER, "underflow"),
+                (FIFOStat::OVER, "overflow"),
+                (FIFOStat::CNT_THR, "count above threshold"),
+                (FIFOStat::FREE_THR, "free above threshold"),
+                (FIFOStat::AUTO_COMMIT, "auto commit"),
+            ],
+        )
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 #[allow(non_camel_case_types)]
@@ -1059,7 +1303,17 @@ impl From<FIFOCmd> for Reg8 {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, TryFromPrimitive)]
+impl TryFrom<Reg8> for FIFOCmd {
+    type Error = Reg8;
+    fn try_from(item: Reg8) -> Result<Self, Self::Error> {
+        Ok(Self {
+            mode: FIFOCmds::try_from(item[0] & 0x7F).or(Err(item))?,
+            auto_commit: item[0] & 0x80 != 0,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 #[rustfmt::skip]
 pub enum FIFOChunkHeaderRX {
@@ -1087,6 +1341,24 @@ bitflags! {
     }
 }
 
+impl fmt::Display for FIFODataRXFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_words(
+            f,
+            self,
+            &[
+                (FIFODataRXFlags::PKTSTART, "start"),
+                (FIFODataRXFlags::PKTEND, "end"),
+                (FIFODataRXFlags::RESIDUE, "residue"),
+                (FIFODataRXFlags::CRCFAIL, "CRC failed"),
+                (FIFODataRXFlags::ADDRFAIL, "address failed"),
+                (FIFODataRXFlags::SIZEFAIL, "size failed"),
+                (FIFODataRXFlags::ABORT, "aborted"),
+            ],
+        )
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum FIFOChunkRX {
     // NOP, ?
@@ -1196,7 +1468,66 @@ impl TryFrom<Vec<u8>> for FIFOChunkRX {
     }
 }
 
-#[derive(IntoPrimitive)]
+impl From<FIFOChunkRX> for Vec<u8> {
+    fn from(item: FIFOChunkRX) -> Self {
+        let header = |h: FIFOChunkHeaderRX| vec![h.into()];
+        match item {
+            FIFOChunkRX::RSSI(rssi) => {
+                [header(FIFOChunkHeaderRX::RSSI), rssi.to_be_bytes().to_vec()].concat()
+            }
+            FIFOChunkRX::FREQOFFS(offset) => [
+                header(FIFOChunkHeaderRX::FREQOFFS),
+                offset.to_be_bytes().to_vec(),
+            ]
+            .concat(),
+            FIFOChunkRX::ANTRSSI2 { rssi, bgndnoise } => [
+                header(FIFOChunkHeaderRX::ANTRSSI2),
+                vec![rssi.to_be_bytes()[0], bgndnoise],
+            ]
+            .concat(),
+            FIFOChunkRX::TIMER(timer) => [
+                header(FIFOChunkHeaderRX::TIMER),
+                Reg24::from(timer).0.to_vec(),
+            ]
+            .concat(),
+            FIFOChunkRX::RFFREQOFFS(offset) => [
+                header(FIFOChunkHeaderRX::RFFREQOFFS),
+                Reg24::from(offset).0.to_vec(),
+            ]
+            .concat(),
+            FIFOChunkRX::DATARATE(rate) => [
+                header(FIFOChunkHeaderRX::DATARATE),
+                Reg24::from(rate).0.to_vec(),
+            ]
+            .concat(),
+            FIFOChunkRX::ANTRSSI3 {
+                ant1rssi,
+                ant2rssi,
+                bgndnoise,
+            } => [
+                header(FIFOChunkHeaderRX::ANTRSSI3),
+                vec![
+                    ant1rssi.to_be_bytes()[0],
+                    ant2rssi.to_be_bytes()[0],
+                    bgndnoise,
+                ],
+            ]
+            .concat(),
+            FIFOChunkRX::DATA { flags, data } => {
+                // length includes the flags byte
+                let length = u8::try_from(data.len() + 1).unwrap();
+                [
+                    header(FIFOChunkHeaderRX::DATA),
+                    vec![length, flags.bits()],
+                    data,
+                ]
+                .concat()
+            }
+        }
+    }
+}
+
+#[derive(IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 #[rustfmt::skip]
 pub enum FIFOChunkHeaderTX {
@@ -1290,6 +1621,43 @@ impl From<FIFOChunkTX> for Vec<u8> {
     }
 }
 
+impl TryFrom<Vec<u8>> for FIFOChunkTX {
+    type Error = Vec<u8>;
+    fn try_from(item: Vec<u8>) -> Result<Self, Self::Error> {
+        let Some(&header) = item.first() else {
+            return Err(item);
+        };
+        let header = FIFOChunkHeaderTX::try_from(header).or(Err(item.clone()))?;
+        let u16_at = |i: usize| u16::from_be_bytes([item[i], item[i + 1]]);
+        match (header, item.len()) {
+            (FIFOChunkHeaderTX::NOP, 1) => Ok(FIFOChunkTX::NOP),
+            (FIFOChunkHeaderTX::TXCTRL, 2) => Ok(FIFOChunkTX::TXCTRL(
+                TXCtrl::from_bits(item[1]).ok_or(item.clone())?,
+            )),
+            (FIFOChunkHeaderTX::REPEATDATA, 4) => Ok(FIFOChunkTX::REPEATDATA {
+                flags: FIFODataTXFlags::from_bits(item[1]).ok_or(item.clone())?,
+                count: item[2],
+                data: item[3],
+            }),
+            // length includes the flags byte
+            (FIFOChunkHeaderTX::DATA, len) if len >= 3 && usize::from(item[1]) + 2 == len => {
+                Ok(FIFOChunkTX::DATA {
+                    flags: FIFODataTXFlags::from_bits(item[2]).ok_or(item.clone())?,
+                    data: item[3..].to_vec(),
+                })
+            }
+            (FIFOChunkHeaderTX::TXPWR, 11) => Ok(FIFOChunkTX::TXPWR {
+                a: u16_at(1),
+                b: u16_at(3),
+                c: u16_at(5),
+                d: u16_at(7),
+                e: u16_at(9),
+            }),
+            _ => Err(item),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive, Serialize, Deserialize)]
 #[repr(u8)]
 #[allow(non_camel_case_types)]
@@ -1397,7 +1765,7 @@ bitflags! {
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PLLRanging {
-    pub vcor: u8,
+    pub vcor: U4,
     pub flags: PLLRangingFlags,
 }
 
@@ -1405,7 +1773,7 @@ impl TryFrom<Reg8> for PLLRanging {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            vcor: item[0] & 0x0f,
+            vcor: U4::mask(item[0]),
             flags: PLLRangingFlags::from_bits(item[0] & 0xf0).ok_or(item)?,
         })
     }
@@ -1413,7 +1781,7 @@ impl TryFrom<Reg8> for PLLRanging {
 
 impl From<PLLRanging> for Reg8 {
     fn from(item: PLLRanging) -> Self {
-        (item.vcor & 0x0f | item.flags.bits()).into()
+        (item.vcor.get() | item.flags.bits()).into()
     }
 }
 
@@ -1458,6 +1826,17 @@ impl TryFrom<Reg<4>> for SignalStr {
     }
 }
 
+impl From<SignalStr> for Reg<4> {
+    fn from(item: SignalStr) -> Self {
+        Self([
+            Reg8::from(item.rssi)[0],
+            item.bgndrssi,
+            Reg8::from(item.diversity)[0],
+            Reg8::from(item.agccounter)[0],
+        ])
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TrkPhase(pub i16); // TRKPHASE is a signed 12 bit value
 
@@ -1553,6 +1932,22 @@ impl TryFrom<Reg<16>> for RXTracking {
     }
 }
 
+impl From<RXTracking> for Reg<16> {
+    fn from(item: RXTracking) -> Self {
+        let bytes = [
+            &Reg24::from(item.datarate).0[..],
+            &Reg16::from(item.ampl).0,
+            &Reg16::from(item.phase).0,
+            &Reg24::from(item.rffreq).0,
+            &Reg16::from(item.freq).0,
+            &Reg16::from(item.fskdemod).0,
+            &Reg16::from(item.afskdemod).0,
+        ]
+        .concat();
+        Self(bytes.try_into().unwrap())
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct MaxRFOffset {
     pub offset: u32,
@@ -1639,88 +2034,94 @@ impl TryFrom<Reg8> for RxParamCurSet {
     }
 }
 
+impl From<RxParamCurSet> for Reg8 {
+    fn from(item: RxParamCurSet) -> Self {
+        (item.index | u8::from(item.number) << 2 | item.special << 4).into()
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct AGCGain {
-    pub attack: u8,
-    pub decay: u8,
+    pub attack: U4,
+    pub decay: U4,
 }
 
 impl TryFrom<Reg8> for AGCGain {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            attack: item[0] & 0x0F,
-            decay: (item[0] & 0xF0) >> 4,
+            attack: U4::mask(item[0]),
+            decay: U4::mask(item[0] >> 4),
         })
     }
 }
 
 impl From<AGCGain> for Reg8 {
     fn from(item: AGCGain) -> Self {
-        (item.attack | item.decay << 4).into()
+        (item.attack.get() | item.decay.get() << 4).into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct AGCHyst {
-    pub hyst: u8,
+    pub hyst: U3,
 }
 
 impl TryFrom<Reg8> for AGCHyst {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            hyst: item[0] & 0x07,
+            hyst: U3::mask(item[0]),
         })
     }
 }
 
 impl From<AGCHyst> for Reg8 {
     fn from(item: AGCHyst) -> Self {
-        item.hyst.into()
+        item.hyst.get().into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct AGCMinMax {
-    pub min: u8,
-    pub max: u8,
+    pub min: U3,
+    pub max: U3,
 }
 
 impl TryFrom<Reg8> for AGCMinMax {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            min: item[0] & 0x07,
-            max: (item[0] >> 3) & 0x07,
+            min: U3::mask(item[0]),
+            max: U3::mask(item[0] >> 3),
         })
     }
 }
 impl From<AGCMinMax> for Reg8 {
     fn from(item: AGCMinMax) -> Self {
-        (item.min | item.max << 3).into()
+        (item.min.get() | item.max.get() << 3).into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PhaseGain {
-    pub gain: u8,
-    pub filter: u8,
+    pub gain: U4,
+    pub filter: U2,
 }
 
 impl TryFrom<Reg8> for PhaseGain {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            gain: item[0] & 0x0F,
-            filter: (item[0] >> 6) & 0x03,
+            gain: U4::mask(item[0]),
+            filter: U2::mask(item[0] >> 6),
         })
     }
 }
 
 impl From<PhaseGain> for Reg8 {
     fn from(item: PhaseGain) -> Self {
-        (item.gain | item.filter << 6).into()
+        (item.gain.get() | item.filter.get() << 6).into()
     }
 }
 
@@ -1736,7 +2137,7 @@ bitflags! {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FreqGainA {
-    pub gain: u8,
+    pub gain: U4,
     pub flags: FreqGainAFlags,
 }
 
@@ -1744,7 +2145,7 @@ impl TryFrom<Reg8> for FreqGainA {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            gain: item[0] & 0x0F,
+            gain: U4::mask(item[0]),
             flags: FreqGainAFlags::from_bits(item[0] & 0xF0).ok_or(item)?,
         })
     }
@@ -1752,7 +2153,7 @@ impl TryFrom<Reg8> for FreqGainA {
 
 impl From<FreqGainA> for Reg8 {
     fn from(item: FreqGainA) -> Self {
-        (item.gain | item.flags.bits()).into()
+        (item.gain.get() | item.flags.bits()).into()
     }
 }
 
@@ -1766,7 +2167,7 @@ bitflags! {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FreqGainB {
-    pub gain: u8,
+    pub gain: U5,
     pub flags: FreqGainBFlags,
 }
 
@@ -1774,7 +2175,7 @@ impl TryFrom<Reg8> for FreqGainB {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            gain: item[0] & 0x1F,
+            gain: U5::mask(item[0]),
             flags: FreqGainBFlags::from_bits(item[0] & 0xC0).ok_or(item)?,
         })
     }
@@ -1782,33 +2183,33 @@ impl TryFrom<Reg8> for FreqGainB {
 
 impl From<FreqGainB> for Reg8 {
     fn from(item: FreqGainB) -> Self {
-        (item.gain | item.flags.bits()).into()
+        (item.gain.get() | item.flags.bits()).into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FreqGainC {
-    pub gain: u8,
+    pub gain: U5,
 }
 
 impl TryFrom<Reg8> for FreqGainC {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            gain: item[0] & 0x1F,
+            gain: U5::mask(item[0]),
         })
     }
 }
 
 impl From<FreqGainC> for Reg8 {
     fn from(item: FreqGainC) -> Self {
-        item.gain.into()
+        item.gain.get().into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FreqGainD {
-    pub gain: u8,
+    pub gain: U5,
     pub freeze: bool,
 }
 
@@ -1816,7 +2217,7 @@ impl TryFrom<Reg8> for FreqGainD {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            gain: item[0] & 0x1F,
+            gain: U5::mask(item[0]),
             freeze: item[0] & 0x80 > 0,
         })
     }
@@ -1824,7 +2225,7 @@ impl TryFrom<Reg8> for FreqGainD {
 
 impl From<FreqGainD> for Reg8 {
     fn from(item: FreqGainD) -> Self {
-        (item.gain | if item.freeze { 0x80 } else { 0 }).into()
+        (item.gain.get() | if item.freeze { 0x80 } else { 0 }).into()
     }
 }
 
@@ -1838,7 +2239,7 @@ bitflags! {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct AmplGain {
-    pub gain: u8,
+    pub gain: U4,
     pub flags: AmplGainFlags,
 }
 
@@ -1846,7 +2247,7 @@ impl TryFrom<Reg8> for AmplGain {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            gain: item[0] & 0x0F,
+            gain: U4::mask(item[0]),
             flags: AmplGainFlags::from_bits(item[0] & 0xC0).ok_or(item)?,
         })
     }
@@ -1854,13 +2255,13 @@ impl TryFrom<Reg8> for AmplGain {
 
 impl From<AmplGain> for Reg8 {
     fn from(item: AmplGain) -> Self {
-        (item.gain | item.flags.bits()).into()
+        (item.gain.get() | item.flags.bits()).into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FourFSK {
-    pub decay: u8,
+    pub decay: U4,
     pub update: bool,
 }
 
@@ -1868,7 +2269,7 @@ impl TryFrom<Reg8> for FourFSK {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            decay: item[0] & 0x0F,
+            decay: U4::mask(item[0]),
             update: item[0] & 0x10 > 0,
         })
     }
@@ -1876,29 +2277,29 @@ impl TryFrom<Reg8> for FourFSK {
 
 impl From<FourFSK> for Reg8 {
     fn from(item: FourFSK) -> Self {
-        (item.decay | if item.update { 0x10 } else { 0 }).into()
+        (item.decay.get() | if item.update { 0x10 } else { 0 }).into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct BBOffsRes {
-    pub res_int_a: u8,
-    pub res_int_b: u8,
+    pub res_int_a: U4,
+    pub res_int_b: U4,
 }
 
 impl TryFrom<Reg8> for BBOffsRes {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            res_int_a: item[0] & 0x0F,
-            res_int_b: (item[0] & 0xF0) >> 4,
+            res_int_a: U4::mask(item[0]),
+            res_int_b: U4::mask(item[0] >> 4),
         })
     }
 }
 
 impl From<BBOffsRes> for Reg8 {
     fn from(item: BBOffsRes) -> Self {
-        (item.res_int_a | item.res_int_b << 4).into()
+        (item.res_int_a.get() | item.res_int_b.get() << 4).into()
     }
 }
 
@@ -1979,7 +2380,7 @@ bitflags! {
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PLLVCOI {
-    pub bias: u8,
+    pub bias: U6,
     pub flags: PLLVCOIFlags,
 }
 
@@ -1987,7 +2388,7 @@ impl TryFrom<Reg8> for PLLVCOI {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            bias: item[0] & 0x3F,
+            bias: U6::mask(item[0]),
             flags: PLLVCOIFlags::from_bits(item[0] & 0x80).ok_or(item)?,
         })
     }
@@ -1995,7 +2396,7 @@ impl TryFrom<Reg8> for PLLVCOI {
 
 impl From<PLLVCOI> for Reg8 {
     fn from(item: PLLVCOI) -> Self {
-        (item.bias | item.flags.bits()).into()
+        (item.bias.get() | item.flags.bits()).into()
     }
 }
 
@@ -2081,7 +2482,7 @@ bitflags! {
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PktAddrCfg {
-    pub addr_pos: u8,
+    pub addr_pos: U4,
     pub flags: PktAddrCfgFlags,
 }
 
@@ -2089,7 +2490,7 @@ impl TryFrom<Reg8> for PktAddrCfg {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            addr_pos: item[0] & 0xF,
+            addr_pos: U4::mask(item[0]),
             flags: PktAddrCfgFlags::from_bits(item[0] & 0xF0).ok_or(item)?,
         })
     }
@@ -2097,35 +2498,35 @@ impl TryFrom<Reg8> for PktAddrCfg {
 
 impl From<PktAddrCfg> for Reg8 {
     fn from(item: PktAddrCfg) -> Self {
-        (item.addr_pos | item.flags.bits()).into()
+        (item.addr_pos.get() | item.flags.bits()).into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PktLenCfg {
-    pub pos: u8,
-    pub bits: u8,
+    pub pos: U4,
+    pub bits: U4,
 }
 
 impl TryFrom<Reg8> for PktLenCfg {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            pos: item[0] & 0xF,
-            bits: (item[0] & 0xF0) >> 4,
+            pos: U4::mask(item[0]),
+            bits: U4::mask(item[0] >> 4),
         })
     }
 }
 
 impl From<PktLenCfg> for Reg8 {
     fn from(item: PktLenCfg) -> Self {
-        (item.pos | item.bits << 4).into()
+        (item.pos.get() | item.bits.get() << 4).into()
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct MatchLen {
-    pub len: u8, // FIXME 4bit/5bit
+    pub len: U5, // FIXME 4bit/5bit
     pub raw: bool,
 }
 
@@ -2133,7 +2534,7 @@ impl TryFrom<Reg8> for MatchLen {
     type Error = Reg8;
     fn try_from(item: Reg8) -> Result<Self, Self::Error> {
         Ok(Self {
-            len: item[0] & 0x1F,
+            len: U5::mask(item[0]),
             raw: (item[0] & 0x80) > 0,
         })
     }
@@ -2141,7 +2542,7 @@ impl TryFrom<Reg8> for MatchLen {
 
 impl From<MatchLen> for Reg8 {
     fn from(item: MatchLen) -> Self {
-        (item.len | if item.raw { 0x80 } else { 0 }).into()
+        (item.len.get() | if item.raw { 0x80 } else { 0 }).into()
     }
 }
 
@@ -2281,6 +2682,58 @@ impl From<PktAcceptFlags> for Reg8 {
     }
 }
 
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct GPADCCtrl: u8 {
+        const CONT  = 1 << 0;
+        const CH13  = 1 << 2;
+        const BUSY  = 1 << 7;
+    }
+}
+
+impl TryFrom<Reg8> for GPADCCtrl {
+    type Error = Reg8;
+    fn try_from(item: Reg8) -> Result<Self, Self::Error> {
+        Self::from_bits(item[0]).ok_or(item)
+    }
+}
+
+impl From<GPADCCtrl> for Reg8 {
+    fn from(item: GPADCCtrl) -> Self {
+        item.bits().into()
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct LPOscConfig: u8 {
+        const ENA        = 1 << 0;
+        /// 10.24 kHz instead of 640 Hz
+        const FAST       = 1 << 1;
+        const IRQR       = 1 << 2;
+        const IRQF       = 1 << 3;
+        /// Calibrate against the reference clock on falling edges
+        const CALIBF     = 1 << 4;
+        /// and on rising edges
+        const CALIBR     = 1 << 5;
+        const OSC_DOUBLE = 1 << 6;
+        const OSC_INVERT = 1 << 7;
+    }
+}
+
+impl TryFrom<Reg8> for LPOscConfig {
+    type Error = Reg8;
+    fn try_from(item: Reg8) -> Result<Self, Self::Error> {
+        Self::from_bits(item[0]).ok_or(item)
+    }
+}
+
+impl From<LPOscConfig> for Reg8 {
+    fn from(item: LPOscConfig) -> Self {
+        item.bits().into()
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 #[rustfmt::skip]
@@ -2368,3 +2821,174 @@ impl From<PerfF35> for Reg8 {
         u8::from(item).into()
     }
 }
+
+// Every type in the register table has to decode what it encodes. Checked from the raw side so
+// nothing needs an Arbitrary impl: all 256 values for 8 bit registers, sampled for wider ones.
+#[cfg(test)]
+mod round_trip {
+    use super::*;
+
+    macro_rules! exhaustive {
+        ($($test:ident: $T:ty;)*) => {
+            $(
+                #[test]
+                fn $test() {
+                    let mut decoded = 0;
+                    for raw in 0..=u8::MAX {
+                        if let Ok(value) = <$T>::try_from(Reg8::from(raw)) {
+                            assert_eq!(Ok(value), <$T>::try_from(Reg8::from(value)), "{:#04X}", raw);
+                            decoded += 1;
+                        }
+                    }
+                    assert!(decoded > 0);
+                }
+            )*
+        };
+    }
+
+    macro_rules! sampled {
+        ($($test:ident: $T:ty, $S:literal;)*) => {
+            proptest! {
+                $(
+                    #[test]
+                    fn $test(raw: [u8; $S]) {
+                        if let Ok(value) = <$T>::try_from(Reg::<$S>(raw)) {
+                            prop_assert_eq!(Ok(value), <$T>::try_from(Reg::<$S>::from(value)));
+                        }
+                    }
+                )*
+            }
+        };
+    }
+
+    exhaustive! {
+        u8: u8;
+        pwrmode: PwrMode;
+        powstat: PowStat;
+        powirqmask: PowIRQMask;
+        modulation: Modulation;
+        encoding: Encoding;
+        framing: Framing;
+        fec: FEC;
+        fecstatus: FECStatus;
+        radiostate: RadioState;
+        xtalstatus: XtalStatus;
+        pinstate: PinState;
+        pfsysclk: PFSysClk;
+        pfdclk: PFDClk;
+        pfdata: PFData;
+        pfirq: PFIRQ;
+        pfantsel: PFAntSel;
+        pfpwramp: PFPwrAmp;
+        pwramp: PwrAmp;
+        fifostat: FIFOStat;
+        fifocmd: FIFOCmd;
+        pllloop: PLLLoop;
+        pllvcodiv: PLLVCODiv;
+        pllranging: PLLRanging;
+        i8: i8;
+        diversity: Diversity;
+        rxparamsets: RxParamSets;
+        rxparamcurset: RxParamCurSet;
+        agcgain: AGCGain;
+        agchyst: AGCHyst;
+        agcminmax: AGCMinMax;
+        float4: Float4;
+        phasegain: PhaseGain;
+        freqgaina: FreqGainA;
+        freqgainb: FreqGainB;
+        freqgainc: FreqGainC;
+        freqgaind: FreqGainD;
+        amplgain: AmplGain;
+        fourfsk: FourFSK;
+        bboffsres: BBOffsRes;
+        modcfgf: ModCfgF;
+        modcfga: ModCfgA;
+        pllvcoi: PLLVCOI;
+        plllockdet: PLLLockDet;
+        pllrngclk: PLLRngClk;
+        pktaddrcfg: PktAddrCfg;
+        pktlencfg: PktLenCfg;
+        matchlen: MatchLen;
+        float5: Float5;
+        pktchunksize: PktChunkSize;
+        pktmiscflags: PktMiscFlags;
+        pktstoreflags: PktStoreFlags;
+        pktacceptflags: PktAcceptFlags;
+        gpadcctrl: GPADCCtrl;
+        perff10: PerfF10;
+        perff11: PerfF11;
+        perff34: PerfF34;
+        perff35: PerfF35;
+    }
+
+    sampled! {
+        irq: IRQ, 2;
+        radioevent: RadioEvent, 2;
+        u32_4: u32, 4;
+        u16: u16, 2;
+        signalstr: SignalStr, 4;
+        rxtracking: RXTracking, 16;
+        i32: i32, 3;
+        trkphase: TrkPhase, 2;
+        trkrffreq: TrkRFFreq, 3;
+        i16: i16, 2;
+        trkfskdemod: TrkFSKDemod, 2;
+        u32_3: u32, 3;
+        maxrfoffset: MaxRFOffset, 3;
+    }
+
+    fn chunk_rx() -> impl Strategy<Value = FIFOChunkRX> {
+        let flags = any::<u8>().prop_map(FIFODataRXFlags::from_bits_truncate);
+        prop_oneof![
+            any::<i8>().prop_map(FIFOChunkRX::RSSI),
+            any::<u16>().prop_map(FIFOChunkRX::FREQOFFS),
+            any::<(i8, u8)>()
+                .prop_map(|(rssi, bgndnoise)| FIFOChunkRX::ANTRSSI2 { rssi, bgndnoise }),
+            (0..1u32 << 24).prop_map(FIFOChunkRX::TIMER),
+            (-1i32 << 23..1 << 23).prop_map(FIFOChunkRX::RFFREQOFFS),
+            (0..1u32 << 24).prop_map(FIFOChunkRX::DATARATE),
+            any::<(i8, i8, u8)>().prop_map(|(ant1rssi, ant2rssi, bgndnoise)| {
+                FIFOChunkRX::ANTRSSI3 {
+                    ant1rssi,
+                    ant2rssi,
+                    bgndnoise,
+                }
+            }),
+            // The shortest DATA chunk the decoder accepts carries 2 bytes
+            (flags, prop::collection::vec(any::<u8>(), 2..254))
+                .prop_map(|(flags, data)| FIFOChunkRX::DATA { flags, data }),
+        ]
+    }
+
+    fn chunk_tx() -> impl Strategy<Value = FIFOChunkTX> {
+        let flags = || any::<u8>().prop_map(FIFODataTXFlags::from_bits_truncate);
+        prop_oneof![
+            Just(FIFOChunkTX::NOP),
+            any::<u8>().prop_map(|b| FIFOChunkTX::TXCTRL(TXCtrl::from_bits_truncate(b))),
+            (flags(), any::<u8>(), any::<u8>())
+                .prop_map(|(flags, count, data)| FIFOChunkTX::REPEATDATA { flags, count, data }),
+            (flags(), prop::collection::vec(any::<u8>(), 0..254))
+                .prop_map(|(flags, data)| FIFOChunkTX::DATA { flags, data }),
+            any::<(u16, u16, u16, u16, u16)>().prop_map(|(a, b, c, d, e)| FIFOChunkTX::TXPWR {
+                a,
+                b,
+                c,
+                d,
+                e
+            }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn fifo_chunk_rx(chunk in chunk_rx()) {
+            prop_assert_eq!(Ok(chunk.clone()), FIFOChunkRX::try_from(Vec::from(chunk)));
+        }
+
+        #[test]
+        fn fifo_chunk_tx(chunk in chunk_tx()) {
+            prop_assert_eq!(Ok(chunk.clone()), FIFOChunkTX::try_from(Vec::from(chunk)));
+        }
+    }
+}
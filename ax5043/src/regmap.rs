@@ -0,0 +1,330 @@
+// The register table as data, for tools that work on any register by name.
+//
+// Registers::MAP lists every entry of the registers! table with its address, width, access and
+// value type, and the bit fields of that type as laid out by its Reg conversions. Accessor
+// methods stay the way to read and write typed values; this is for dump-regs, the tui and
+// anything else that takes a register name from the user. Fields are counted from the least
+// significant bit of the big endian register, multi-byte fields included.
+use crate::registers::*;
+use bitflags::Flags;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+    WriteOnly,
+    /// FIFODATARX, a stream of chunks rather than a value
+    ReadFIFO,
+    WriteFIFO,
+}
+
+impl Access {
+    pub fn readable(self) -> bool {
+        matches!(
+            self,
+            Access::ReadOnly | Access::ReadWrite | Access::ReadFIFO
+        )
+    }
+
+    pub fn writable(self) -> bool {
+        matches!(
+            self,
+            Access::ReadWrite | Access::WriteOnly | Access::WriteFIFO
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    /// Lowest bit
+    pub shift: u8,
+    pub bits: u8,
+}
+
+impl Field {
+    const fn new(name: &'static str, shift: u8, bits: u8) -> Self {
+        Self { name, shift, bits }
+    }
+
+    /// This field out of a register's raw bytes
+    pub fn get(&self, raw: &[u8]) -> u128 {
+        let value = raw.iter().fold(0u128, |acc, b| acc << 8 | u128::from(*b));
+        (value >> self.shift) & ((1u128 << self.bits) - 1)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RegisterInfo {
+    pub name: &'static str,
+    pub addr: u16,
+    /// Bytes
+    pub width: usize,
+    pub access: Access,
+    /// The value type, as written in the table
+    pub value: &'static str,
+    /// Empty when the register holds a single number
+    pub fields: fn() -> Vec<Field>,
+}
+
+impl RegisterInfo {
+    /// Shell style: `*` matches any run of characters, case insensitive. `PKT*`, `*GAIN0`.
+    pub fn matches(&self, pattern: &str) -> bool {
+        glob(
+            pattern.to_ascii_uppercase().as_bytes(),
+            self.name.as_bytes(),
+        )
+    }
+}
+
+impl fmt::Display for RegisterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:03X} {} {:?} {}",
+            self.name, self.addr, self.width, self.access, self.value
+        )
+    }
+}
+
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(&pattern[1..], name) || (!name.is_empty() && glob(pattern, &name[1..]))
+        }
+        (Some(p), Some(n)) if p == n => glob(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// The bit fields of a register value type
+pub trait Layout {
+    fn fields() -> Vec<Field> {
+        Vec::new()
+    }
+}
+
+// The whole register is one number or one enum
+macro_rules! whole {
+    ($($T:ty),*) => {
+        $(impl Layout for $T {})*
+    };
+}
+
+whole!(
+    u8,
+    u16,
+    u32,
+    i8,
+    i16,
+    i32,
+    RadioState,
+    ModCfgF,
+    PLLRngClk,
+    PktChunkSize,
+    PerfF10,
+    PerfF11,
+    PerfF34,
+    PerfF35,
+    FIFOChunkRX,
+    FIFOChunkTX
+);
+
+/// One field per flag. Flags that are 0 (LockDetFlags::AUTOMATIC) or combine earlier ones
+/// (Encoding::NRZI) are left out.
+fn flags<F: Flags>() -> Vec<Field>
+where
+    F::Bits: Into<u16>,
+{
+    let mut used = 0;
+    let mut fields = Vec::new();
+    for flag in F::FLAGS {
+        let bits: u16 = flag.value().bits().into();
+        if bits == 0 || bits & used != 0 {
+            continue;
+        }
+        used |= bits;
+        fields.push(Field::new(
+            flag.name(),
+            bits.trailing_zeros() as u8,
+            bits.count_ones() as u8,
+        ));
+    }
+    fields
+}
+
+macro_rules! flags {
+    ($($T:ty),*) => {
+        $(impl Layout for $T {
+            fn fields() -> Vec<Field> {
+                flags::<$T>()
+            }
+        })*
+    };
+}
+
+flags!(
+    PowStat,
+    PowIRQMask,
+    IRQ,
+    RadioEvent,
+    Encoding,
+    XtalStatus,
+    PinState,
+    PwrAmp,
+    FIFOStat,
+    Diversity,
+    PktMiscFlags,
+    PktStoreFlags,
+    PktAcceptFlags,
+    GPADCCtrl,
+    LPOscConfig
+);
+
+// Named fields, then any flags type sharing the register
+macro_rules! layout {
+    ($($T:ty { $($name:literal: $shift:literal, $bits:literal),* $(; $F:ty)? })*) => {
+        $(impl Layout for $T {
+            fn fields() -> Vec<Field> {
+                #[allow(unused_mut)]
+                let mut fields = vec![$(Field::new($name, $shift, $bits)),*];
+                $(fields.extend(flags::<$F>());)?
+                fields.sort_by_key(|f| f.shift);
+                fields
+            }
+        })*
+    };
+}
+
+layout! {
+    Float4 { "e": 0, 4, "m": 4, 4 }
+    Float5 { "m": 0, 5, "e": 5, 3 }
+    PwrMode { "mode": 0, 4; PwrFlags }
+    Modulation { "mode": 0, 4, "halfspeed": 4, 1 }
+    Framing { "frmmode": 1, 3, "crcmode": 4, 3; FramingFlags }
+    FEC { "inpshift": 1, 3; FECFlags }
+    FECStatus { "max_metric": 0, 7, "inv": 7, 1 }
+    PFSysClk { "mode": 0, 5, "pullup": 7, 1 }
+    PFDClk { "mode": 0, 3; PFFlags }
+    PFData { "mode": 0, 4; PFFlags }
+    PFIRQ { "mode": 0, 3; PFFlags }
+    PFAntSel { "mode": 0, 3; PFFlags }
+    PFPwrAmp { "mode": 0, 4; PFFlags }
+    FIFOCmd { "mode": 0, 7, "auto_commit": 7, 1 }
+    PLLLoop { "filter": 0, 2, "freqsel": 7, 1; PLLLoopFlags }
+    PLLVCODiv { "refdiv": 0, 2; PLLVCODivFlags }
+    PLLRanging { "vcor": 0, 4; PLLRangingFlags }
+    SignalStr { "agccounter": 0, 8, "diversity": 8, 8, "bgndrssi": 16, 8, "rssi": 24, 8 }
+    TrkPhase { "phase": 0, 12 }
+    TrkRFFreq { "rffreq": 0, 20 }
+    TrkFSKDemod { "fskdemod": 0, 14 }
+    RXTracking {
+        "afskdemod": 0, 16,
+        "fskdemod": 16, 16,
+        "freq": 32, 16,
+        "rffreq": 48, 24,
+        "phase": 72, 16,
+        "ampl": 88, 16,
+        "datarate": 104, 24
+    }
+    MaxRFOffset { "offset": 0, 20, "correction": 23, 1 }
+    RxParamSets { "0": 0, 2, "1": 2, 2, "2": 4, 2, "3": 6, 2 }
+    RxParamCurSet { "index": 0, 2, "number": 2, 2, "special": 4, 4 }
+    AGCGain { "attack": 0, 4, "decay": 4, 4 }
+    AGCHyst { "hyst": 0, 3 }
+    AGCMinMax { "min": 0, 3, "max": 3, 3 }
+    PhaseGain { "gain": 0, 4, "filter": 6, 2 }
+    FreqGainA { "gain": 0, 4; FreqGainAFlags }
+    FreqGainB { "gain": 0, 5; FreqGainBFlags }
+    FreqGainC { "gain": 0, 5 }
+    FreqGainD { "gain": 0, 5, "freeze": 7, 1 }
+    AmplGain { "gain": 0, 4; AmplGainFlags }
+    FourFSK { "decay": 0, 4, "update": 4, 1 }
+    BBOffsRes { "res_int_a": 0, 4, "res_int_b": 4, 4 }
+    ModCfgA { "slowramp": 4, 2; ModCfgAFlags }
+    PLLVCOI { "bias": 0, 6; PLLVCOIFlags }
+    PLLLockDet { "delay": 0, 2, "readback": 6, 2; LockDetFlags }
+    PktAddrCfg { "addr_pos": 0, 4; PktAddrCfgFlags }
+    PktLenCfg { "pos": 0, 4, "bits": 4, 4 }
+    MatchLen { "len": 0, 5, "raw": 7, 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Registers, TX};
+
+    fn info(name: &str) -> &'static RegisterInfo {
+        Registers::info(name).unwrap()
+    }
+
+    #[test]
+    fn lookup() {
+        let freqa = info("freqa");
+        assert_eq!((freqa.addr, freqa.width), (0x034, 4));
+        assert_eq!(freqa.access, Access::ReadWrite);
+        assert!((freqa.fields)().is_empty());
+        assert_eq!(info("FIFOCMD").access, Access::WriteOnly);
+        assert!(Registers::info("NOPE").is_none());
+        assert_eq!(Registers::MAP.len(), 212);
+
+        let pkt: Vec<_> = Registers::MAP
+            .iter()
+            .filter(|r| r.matches("pkt*"))
+            .map(|r| r.name)
+            .collect();
+        assert!(pkt.contains(&"PKTADDRCFG") && pkt.contains(&"PKTACCEPTFLAGS"));
+        assert!(pkt.iter().all(|n| n.starts_with("PKT")));
+        assert!(info("AGCGAIN0").matches("*GAIN0"));
+        assert!(!info("AGCGAIN0").matches("*GAIN"));
+    }
+
+    #[test]
+    fn fields_match_the_conversions() {
+        // Fields don't overlap and fit the width
+        for reg in Registers::MAP {
+            let mut used = 0u128;
+            for field in (reg.fields)() {
+                assert!(
+                    usize::from(field.shift + field.bits) <= reg.width * 8,
+                    "{} {}",
+                    reg.name,
+                    field.name
+                );
+                let mask = ((1u128 << field.bits) - 1) << field.shift;
+                assert_eq!(used & mask, 0, "{} {}", reg.name, field.name);
+                used |= mask;
+            }
+        }
+
+        // Spot checks against the typed accessors
+        let writes = crate::dry_run(|radio| {
+            radio.AGCMINMAX0().write(AGCMinMax {
+                min: U3::new(2),
+                max: U3::new(5),
+            })?;
+            radio.PKTADDRCFG().write(PktAddrCfg {
+                addr_pos: U4::new(3),
+                flags: PktAddrCfgFlags::MSB_FIRST,
+            })
+        })
+        .unwrap();
+        let get = |reg: &str, field: &str, raw: &[u8]| {
+            (info(reg).fields)()
+                .iter()
+                .find(|f| f.name == field)
+                .unwrap()
+                .get(raw)
+        };
+        let minmax = &writes.to("AGCMINMAX0")[0].data;
+        assert_eq!(get("AGCMINMAX0", "min", minmax), 2);
+        assert_eq!(get("AGCMINMAX0", "max", minmax), 5);
+        let addrcfg = &writes.to("PKTADDRCFG")[0].data;
+        assert_eq!(get("PKTADDRCFG", "addr_pos", addrcfg), 3);
+        assert_eq!(get("PKTADDRCFG", "MSB_FIRST", addrcfg), 1);
+        assert_eq!(get("PKTADDRCFG", "CRC_SKIP_FIRST", addrcfg), 0);
+    }
+}
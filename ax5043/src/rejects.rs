@@ -0,0 +1,170 @@
+// Writes what PacketAssembler drops to disk, so after a pass CRC-marginal frames can be told
+// apart from interference bursts.
+//
+// One tab separated line per rejection:
+//
+//   time  reason  rssi  rf_offset  len  data
+//
+// time is unix seconds, rssi dBm and rf_offset Hz (empty if not sampled), data is hex. When the
+// file grows past the size limit it's renamed to path.1 (path.1 to path.2 and so on, KEEP files
+// in all) and a new one is started.
+use crate::{capture::Meta, rx::Rejected};
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Result, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Rotated files kept besides the current one
+pub const KEEP: usize = 4;
+
+const HEADER: &str = "# time\treason\trssi\trf_offset\tlen\tdata\n";
+
+pub struct RejectLog {
+    path: PathBuf,
+    limit: u64,
+    out: BufWriter<File>,
+    len: u64,
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    name.into()
+}
+
+fn line(time: f64, rejected: &Rejected, meta: &Meta) -> String {
+    let mut line = format!("{:.3}\t{}\t", time, rejected.reason);
+    if let Some(rssi) = meta.rssi {
+        write!(line, "{:.1}", rssi).unwrap();
+    }
+    line.push('\t');
+    if let Some(offset) = meta.rf_offset {
+        write!(line, "{}", offset).unwrap();
+    }
+    write!(line, "\t{}\t", rejected.data.len()).unwrap();
+    for b in &rejected.data {
+        write!(line, "{:02X}", b).unwrap();
+    }
+    line.push('\n');
+    line
+}
+
+impl RejectLog {
+    /// Appends to `path`, rotating once it's over `limit` bytes
+    pub fn open<P: AsRef<Path>>(path: P, limit: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        let mut log = Self {
+            path,
+            limit,
+            out: BufWriter::new(file),
+            len,
+        };
+        if log.len == 0 {
+            log.append(HEADER)?;
+        }
+        Ok(log)
+    }
+
+    fn append(&mut self, s: &str) -> Result<()> {
+        self.out.write_all(s.as_bytes())?;
+        self.out.flush()?;
+        self.len += s.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        for n in (1..KEEP).rev() {
+            match fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => (),
+            }
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))?;
+        self.out = BufWriter::new(File::create(&self.path)?);
+        self.len = 0;
+        self.append(HEADER)
+    }
+
+    /// Appends one rejection stamped with the current time. `meta` is the radio state when it
+    /// was drained, Meta::default() if it wasn't sampled.
+    pub fn write(&mut self, rejected: &Rejected, meta: &Meta) -> Result<()> {
+        if self.len >= self.limit {
+            self.rotate()?;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.append(&line(now.as_secs_f64(), rejected, meta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rx::Reason;
+
+    fn rejected() -> Rejected {
+        Rejected {
+            reason: Reason::CRC {
+                received: 0x1234,
+                calculated: 0xabcd,
+            },
+            data: vec![0xde, 0xad, 0x12, 0x34],
+        }
+    }
+
+    #[test]
+    fn format() {
+        let meta = Meta {
+            rssi: Some(-90.0),
+            rf_offset: Some(1200),
+            antenna: None,
+            crc_failed: false,
+        };
+        assert_eq!(
+            line(1.5, &rejected(), &meta),
+            "1.500\tcrc 0x1234 != 0xabcd\t-90.0\t1200\t4\tDEAD1234\n"
+        );
+        assert_eq!(
+            line(2.0, &rejected(), &Meta::default()),
+            "2.000\tcrc 0x1234 != 0xabcd\t\t\t4\tDEAD1234\n"
+        );
+    }
+
+    #[test]
+    fn rotate() {
+        let dir = std::env::temp_dir().join(format!("ax5043-rejects-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rejects.tsv");
+
+        let mut log = RejectLog::open(&path, 100).unwrap();
+        for _ in 0..20 {
+            log.write(&rejected(), &Meta::default()).unwrap();
+        }
+        drop(log);
+
+        for n in 1..=KEEP {
+            let rotated = fs::read_to_string(rotated(&path, n)).unwrap();
+            assert!(rotated.starts_with(HEADER));
+        }
+        assert!(!rotated(&path, KEEP + 1).exists());
+        let current = fs::read_to_string(&path).unwrap();
+        assert!(current.starts_with(HEADER));
+        assert!(current.len() < 100 + HEADER.len());
+
+        // Reopening appends instead of adding another header
+        let mut log = RejectLog::open(&path, 1 << 20).unwrap();
+        log.write(&rejected(), &Meta::default()).unwrap();
+        let reopened = fs::read_to_string(&path).unwrap();
+        assert_eq!(reopened.matches(HEADER).count(), 1);
+        assert!(reopened.len() > current.len());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
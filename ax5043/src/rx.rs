@@ -0,0 +1,1469 @@
+// Reassembles packets from FIFO chunks, shared by the bins.
+//
+// The packet controller splits packets into DATA chunks (PKTCHUNKSIZE) flagged PKTSTART and
+// PKTEND. Bad chunks, restarts and CRC failures are logged on the ax5043::packet target and
+// dropped; only complete packets with a good CRC come out. With keep_rejected() the dropped
+// data is also kept for the bins to write out, see ax5043::rejects. With a squelch set, packets
+// that arrive below it are dropped the same way. With report_aborts() frames the packet
+// controller gave up on come out as RxAbort events too. The [accept] config section
+// (AcceptancePolicy) picks the failures the radio passes on anyway, and the assembler passes
+// those same packets on instead of dropping them. What happens to the CRC bytes on the end is
+// the channel's CrcTrailer.
+use crate::{registers::*, Registers, RX, TX};
+use crc::{Crc, CRC_16_GENIBUS}; // TODO: this CRC works but is it correct?
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fmt,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Running counts of what came out of the FIFO, reported over telemetry and at shutdown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Packets that passed the CRC
+    pub packets: u64,
+    /// Payload bytes in those packets
+    pub bytes: u64,
+    pub crc_fail: u64,
+    pub abort: u64,
+    /// Aborted frames passed on as far as they got with [accept] aborted, not in packets
+    #[serde(default)]
+    pub partial: u64,
+    pub size_fail: u64,
+    pub addr_fail: u64,
+    pub residue: u64,
+    /// Packets cut short by a new PKTSTART, continued chunks without a start, runts and packets
+    /// below the squelch
+    pub dropped: u64,
+    /// FIFODATARX reads that failed, usually overflow
+    pub fifo_errors: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ok {} ({} B) crc {} abort {} partial {} size {} addr {} residue {} dropped {} fifo {}",
+            self.packets,
+            self.bytes,
+            self.crc_fail,
+            self.abort,
+            self.partial,
+            self.size_fail,
+            self.addr_fail,
+            self.residue,
+            self.dropped,
+            self.fifo_errors
+        )
+    }
+}
+
+/// Why PacketAssembler dropped something
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Reason {
+    /// The radio flagged the chunk (ABORT, SIZEFAIL, ADDRFAIL, CRCFAIL, RESIDUE)
+    Flags(FIFODataRXFlags),
+    /// A new PKTSTART before the previous packet ended
+    Restart,
+    /// A continued chunk without a PKTSTART
+    Orphan,
+    /// Too short to hold a CRC
+    Runt,
+    CRC {
+        received: u16,
+        calculated: u16,
+    },
+    /// FIFODATARX read failed
+    FIFO(String),
+    /// RSSI (dBm) below the squelch
+    Squelch(f64),
+    /// Longer (bytes) than PacketAssembler::max_len()
+    Oversize(usize),
+    /// None of the addresses() the radio couldn't tell apart
+    Address,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Flags(flags) => write!(f, "flags {:?}", flags),
+            Reason::Restart => write!(f, "restart"),
+            Reason::Orphan => write!(f, "orphan"),
+            Reason::Runt => write!(f, "runt"),
+            Reason::CRC {
+                received,
+                calculated,
+            } => write!(f, "crc 0x{:04x} != 0x{:04x}", received, calculated),
+            Reason::FIFO(e) => write!(f, "fifo {}", e),
+            Reason::Squelch(dbm) => write!(f, "squelch {:.1} dBm", dbm),
+            Reason::Oversize(len) => write!(f, "oversize {} B", len),
+            Reason::Address => write!(f, "address"),
+        }
+    }
+}
+
+impl Reason {
+    /// A frame that arrived damaged: a failed CRC or one the radio flagged as failed. Frames for
+    /// another address or below the squelch say nothing about the link, see fallback::Monitor.
+    pub fn damaged(&self) -> bool {
+        let failed = FIFODataRXFlags::ABORT
+            | FIFODataRXFlags::SIZEFAIL
+            | FIFODataRXFlags::CRCFAIL
+            | FIFODataRXFlags::RESIDUE;
+        match self {
+            Reason::CRC { .. } => true,
+            Reason::Flags(flags) => flags.intersects(failed),
+            _ => false,
+        }
+    }
+}
+
+/// A dropped chunk or frame: whatever had been assembled plus the chunk that ended it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejected {
+    pub reason: Reason,
+    pub data: Vec<u8>,
+}
+
+/// Where in the frame the packet controller gave up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbortStage {
+    /// On the frame's first chunk, e.g. a preamble or sync timeout right after the start
+    Start,
+    /// After earlier chunks of the frame had come through
+    Payload,
+}
+
+/// A frame ended by an ABORT chunk, see PacketAssembler::report_aborts()
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RxAbort {
+    pub stage: AbortStage,
+    /// Bytes of the frame received, the aborting chunk included
+    pub partial: usize,
+    /// All the flags on the aborting chunk, the radio can set CRCFAIL etc. alongside
+    pub flags: FIFODataRXFlags,
+}
+
+impl fmt::Display for RxAbort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "abort at {:?} after {} B {:?}",
+            self.stage, self.partial, self.flags
+        )
+    }
+}
+
+/// The FIFO size, neither a read nor FIFOTHRESH can be more
+pub const FIFO_SIZE: usize = 256;
+/// Starting room for a packet, it only grows past this once
+const PACKET_SIZE: usize = 1024;
+
+/// RSSI on each antenna while the radio was choosing between them, from the ANTRSSI3 chunk the
+/// packet controller stores ahead of each packet with diversity on, see config::Board::diversity.
+/// Raw dB like the RSSI register.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AntennaRssi {
+    pub ant1: i8,
+    pub ant2: i8,
+}
+
+impl AntennaRssi {
+    /// The antenna the radio stayed on for the packet, 1 or 2. It picks the stronger one.
+    pub fn selected(&self) -> u8 {
+        if self.ant2 > self.ant1 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// From the front of the raw FIFO, if an ANTRSSI3 chunk is next
+    fn from_chunk(chunk: &[u8]) -> Option<Self> {
+        match chunk {
+            [header, ant1, ant2, _bgndnoise, ..]
+                if FIFOChunkHeaderRX::try_from(*header) == Ok(FIFOChunkHeaderRX::ANTRSSI3) =>
+            {
+                Some(Self {
+                    ant1: *ant1 as i8,
+                    ant2: *ant2 as i8,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PacketAssembler {
+    /// The packet so far, kept (cleared, not replaced) between packets so it doesn't
+    /// reallocate
+    packet: Vec<u8>,
+    /// Where drain_with() reads the FIFO to, at least FIFO_SIZE long
+    fifo: Vec<u8>,
+    stats: Stats,
+    /// None unless keep_rejected() was called
+    rejected: Option<Vec<Rejected>>,
+    /// None unless report_aborts() was called
+    aborts: Option<Vec<RxAbort>>,
+    /// RSSI floor in dBm, see set_squelch()
+    squelch: Option<f64>,
+    /// Turns the RSSI register into dBm for the squelch, see rssi()
+    calibration: crate::config::RssiCalibration,
+    /// From the ANTRSSI3 chunk ahead of the packet in progress
+    antenna: Option<AntennaRssi>,
+    /// Failures passed on rather than dropped, see accept()
+    accept: AcceptancePolicy,
+    /// Longest packet passed on, CRC not included, see max_len()
+    max_len: Option<usize>,
+    trailer: CrcTrailer,
+    /// The channel's `crc`, which decides the length of the trailer and how it's checked
+    crc: crate::config::CRC,
+    /// The channel's `framing`, raw data comes without packet boundaries
+    framing: crate::config::Framing,
+    /// Whether the packet last completed passed its CRC, see crc_ok()
+    crc_ok: bool,
+    /// Checked here when the radio's address match passes more, see addresses()
+    address: Option<crate::config::AddressFilter>,
+}
+
+impl Default for PacketAssembler {
+    fn default() -> Self {
+        Self {
+            packet: Vec::with_capacity(PACKET_SIZE),
+            fifo: vec![0; FIFO_SIZE],
+            stats: Stats::default(),
+            rejected: None,
+            aborts: None,
+            squelch: None,
+            calibration: crate::config::RssiCalibration::default(),
+            antenna: None,
+            accept: AcceptancePolicy::default(),
+            max_len: None,
+            trailer: CrcTrailer::default(),
+            crc: crate::config::CRC::CCITT { initial: 0xFFFF },
+            framing: crate::config::Framing::HDLC {
+                fec: crate::config::FEC {},
+            },
+            crc_ok: true,
+            address: None,
+        }
+    }
+}
+
+impl PacketAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops any partial packet, e.g. after a FIFO error
+    pub fn clear(&mut self) {
+        self.packet.clear();
+    }
+
+    /// Continues counting from earlier totals, see ax5043::state
+    pub fn resume(stats: Stats) -> Self {
+        Self {
+            stats,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the start of a packet is waiting for the rest
+    pub fn in_packet(&self) -> bool {
+        !self.packet.is_empty()
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Keep what gets dropped until take_rejected(), otherwise it's only logged
+    pub fn keep_rejected(mut self) -> Self {
+        self.rejected = Some(Vec::new());
+        self
+    }
+
+    /// What was dropped since the last call, empty unless keep_rejected() was called
+    pub fn take_rejected(&mut self) -> Vec<Rejected> {
+        self.rejected
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Keep an RxAbort for each aborted frame until take_aborts(). They're counted in
+    /// Stats::abort either way.
+    pub fn report_aborts(mut self) -> Self {
+        self.aborts = Some(Vec::new());
+        self
+    }
+
+    /// The aborts since the last call, empty unless report_aborts() was called
+    pub fn take_aborts(&mut self) -> Vec<RxAbort> {
+        self.aborts.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Pass on the packets `policy` has the radio accept, the same policy written to
+    /// PKTACCEPTFLAGS. Their failures are still counted.
+    pub fn accept(mut self, policy: AcceptancePolicy) -> Self {
+        self.accept = policy;
+        self
+    }
+
+    /// accept() on a running assembler, after a config reload
+    pub fn set_accept(&mut self, policy: AcceptancePolicy) {
+        self.accept = policy;
+    }
+
+    /// What to do with the CRC on the end of each packet, the channel's `crc_trailer`
+    pub fn trailer(mut self, trailer: CrcTrailer) -> Self {
+        self.trailer = trailer;
+        self
+    }
+
+    /// trailer() on a running assembler, after a config reload
+    pub fn set_trailer(&mut self, trailer: CrcTrailer) {
+        self.trailer = trailer;
+    }
+
+    /// The CRC the packet controller appends, the channel's `crc`. CCITT is checked here as
+    /// well, the others only by the radio (CRCFAIL). CCITT unless set.
+    pub fn crc(mut self, crc: crate::config::CRC) -> Self {
+        self.crc = crc;
+        self
+    }
+
+    /// crc() on a running assembler, after a config reload or a framing switch
+    pub fn set_crc(&mut self, crc: crate::config::CRC) {
+        self.crc = crc;
+    }
+
+    /// The channel's `framing`. With the raw modes every DATA chunk is passed on as it came,
+    /// there are no packets to assemble. HDLC unless set.
+    pub fn framing(mut self, framing: crate::config::Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// framing() on a running assembler, after a config reload or a framing switch
+    pub fn set_framing(&mut self, framing: crate::config::Framing) {
+        self.framing = framing;
+    }
+
+    /// Drops packets for none of the `filter` addresses, the config's [address]. Only needed
+    /// when the radio's own match (AddressFilter::hardware()) lets other addresses through, so
+    /// an exact one is ignored. Counted as addr_fail, and passed on with [accept] addr_failed.
+    pub fn addresses(mut self, filter: Option<crate::config::AddressFilter>) -> Self {
+        self.set_addresses(filter);
+        self
+    }
+
+    /// addresses() on a running assembler, after a config reload
+    pub fn set_addresses(&mut self, filter: Option<crate::config::AddressFilter>) {
+        self.address = filter.filter(|filter| !filter.exact());
+    }
+
+    /// Whether the packet last returned by push() passed its CRC. Only ever false with
+    /// CrcTrailer::Flag, [accept] crc_failed or [accept] aborted.
+    pub fn crc_ok(&self) -> bool {
+        self.crc_ok
+    }
+
+    /// Drops packets longer than `max` bytes, for lengths past what PKTMAXLEN can hold (see
+    /// config::PacketLength::Dynamic). None passes any length.
+    pub fn max_len(mut self, max: Option<usize>) -> Self {
+        self.max_len = max;
+        self
+    }
+
+    /// max_len() on a running assembler, after a config reload
+    pub fn set_max_len(&mut self, max: Option<usize>) {
+        self.max_len = max;
+    }
+
+    /// The board's RSSI calibration, which the squelch compares in
+    pub fn rssi(mut self, calibration: crate::config::RssiCalibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Drops packets received below `floor` dBm, None to pass everything
+    pub fn set_squelch(&mut self, floor: Option<f64>) {
+        self.squelch = floor;
+    }
+
+    pub fn squelch(&self) -> Option<f64> {
+        self.squelch
+    }
+
+    /// Applies the squelch to a packet received at RSSI register value `rssi`, true if it was
+    /// dropped
+    fn squelched(&mut self, rssi: i8, packet: &[u8]) -> bool {
+        let dbm = self.calibration.dbm(rssi.into());
+        match self.squelch {
+            Some(floor) if dbm < floor => {
+                warn!(
+                    target: "ax5043::packet", "SQUELCHED {:.1} dBm < {:.1} dBm {:02X?}",
+                    dbm, floor, packet
+                );
+                self.stats.dropped += 1;
+                Self::reject(&mut self.rejected, Reason::Squelch(dbm), packet);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Copies `data` out only if it's being kept, so dropping allocates nothing otherwise
+    fn reject(rejected: &mut Option<Vec<Rejected>>, reason: Reason, data: &[u8]) {
+        if let Some(ref mut rejected) = rejected {
+            rejected.push(Rejected {
+                reason,
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    /// Rejects the partial packet and starts over
+    fn drop_partial(&mut self, reason: Reason) {
+        Self::reject(&mut self.rejected, reason, &self.packet);
+        self.packet.clear();
+    }
+
+    /// Feeds one chunk, returning the packet (CRC checked and removed) if this completed one
+    pub fn push(&mut self, chunk: FIFOChunkRX) -> Option<Vec<u8>> {
+        let FIFOChunkRX::DATA { flags, ref data } = chunk else {
+            return None;
+        };
+        if !self.feed(flags, data) {
+            return None;
+        }
+        let packet = self.packet.clone();
+        self.packet.clear();
+        Some(packet)
+    }
+
+    /// Feeds the contents of one DATA chunk. True if it completed a packet, which is then left
+    /// in self.packet (CRC checked and removed) for the caller to take and clear.
+    fn feed(&mut self, flags: FIFODataRXFlags, data: &[u8]) -> bool {
+        let stats = &mut self.stats;
+        let failed = flags.intersects(
+            FIFODataRXFlags::ABORT
+                | FIFODataRXFlags::SIZEFAIL
+                | FIFODataRXFlags::ADDRFAIL
+                | FIFODataRXFlags::CRCFAIL
+                | FIFODataRXFlags::RESIDUE,
+        );
+        if failed {
+            // The radio can set several, count each
+            for (flag, count) in [
+                (FIFODataRXFlags::ABORT, &mut stats.abort),
+                (FIFODataRXFlags::SIZEFAIL, &mut stats.size_fail),
+                (FIFODataRXFlags::ADDRFAIL, &mut stats.addr_fail),
+                (FIFODataRXFlags::CRCFAIL, &mut stats.crc_fail),
+                (FIFODataRXFlags::RESIDUE, &mut stats.residue),
+            ] {
+                if flags.contains(flag) {
+                    *count += 1;
+                }
+            }
+            if let Some(ref mut aborts) = self.aborts {
+                if flags.contains(FIFODataRXFlags::ABORT) {
+                    aborts.push(RxAbort {
+                        stage: match self.packet.is_empty() {
+                            true => AbortStage::Start,
+                            false => AbortStage::Payload,
+                        },
+                        partial: self.packet.len() + data.len(),
+                        flags,
+                    });
+                }
+            }
+            if !self.accept.passes(flags) {
+                warn!(
+                    target: "ax5043::packet", "REJECTED {:?} {:02X?} ...+{}",
+                    flags,
+                    data.first(),
+                    data.len()
+                );
+                self.packet.extend_from_slice(data);
+                self.drop_partial(Reason::Flags(flags));
+                return false;
+            }
+            warn!(
+                target: "ax5043::packet", "ACCEPTED {:?} {:02X?} ...+{}",
+                flags,
+                data.first(),
+                data.len()
+            );
+        }
+
+        if !self.framing.packets() {
+            self.packet.clear();
+            self.packet.extend_from_slice(data);
+            self.crc_ok = true;
+            self.stats.packets += 1;
+            self.stats.bytes += data.len() as u64;
+            return !data.is_empty();
+        }
+
+        if flags.contains(FIFODataRXFlags::PKTSTART) && !self.packet.is_empty() {
+            warn!(
+                target: "ax5043::packet", "PKT RESTART rejecting {:02X?} ...+{}",
+                self.packet[0],
+                self.packet.len(),
+            );
+            stats.dropped += 1;
+            self.drop_partial(Reason::Restart);
+        }
+
+        if !flags.contains(FIFODataRXFlags::PKTSTART) && self.packet.is_empty() {
+            warn!(
+                target: "ax5043::packet", "Invalid continued chunk {:?} {:02X?}",
+                flags, data
+            );
+            self.stats.dropped += 1;
+            Self::reject(&mut self.rejected, Reason::Orphan, data);
+            return false;
+        }
+
+        self.packet.extend_from_slice(data);
+        // An accepted abort ends the frame wherever the packet controller gave up. There's no
+        // CRC on the end of that to check or strip, it goes on whole.
+        if flags.contains(FIFODataRXFlags::ABORT) {
+            self.crc_ok = false;
+            self.stats.partial += 1;
+            return true;
+        }
+        if !flags.contains(FIFODataRXFlags::PKTEND) {
+            return false;
+        }
+
+        let trailer = self.crc.bytes();
+        if self.packet.len() < trailer.max(1) {
+            warn!(target: "ax5043::packet", "Runt packet {:02X?}", self.packet);
+            self.stats.dropped += 1;
+            self.drop_partial(Reason::Runt);
+            return false;
+        }
+        let len = self.packet.len() - trailer;
+        if let Some(max) = self.max_len.filter(|max| len > *max) {
+            warn!(
+                target: "ax5043::packet", "Oversize packet {} > {} {:02X?} ...",
+                len, max, self.packet[0]
+            );
+            self.stats.size_fail += 1;
+            self.drop_partial(Reason::Oversize(len));
+            return false;
+        }
+        let (packet, bytes) = self.packet.split_at(len);
+        let (checksum, calculated) = match self.crc {
+            crate::config::CRC::CCITT { .. } => {
+                let ccitt = Crc::<u16>::new(&CRC_16_GENIBUS);
+                let mut digest = ccitt.digest();
+                digest.update(packet);
+                (u16::from_be_bytes([bytes[0], bytes[1]]), digest.finalize())
+            }
+            // Left to the radio's CRCFAIL
+            _ => (0, 0),
+        };
+
+        // The radio's own flags already said what's wrong with an accepted failure
+        let passed = calculated == checksum;
+        if !passed && !failed && !self.accept.crc_failed && self.trailer != CrcTrailer::Flag {
+            warn!(
+                target: "ax5043::packet", "Rejected CRC: received 0x{:x}, calculated 0x{:x}",
+                checksum, calculated
+            );
+            self.stats.crc_fail += 1;
+            self.drop_partial(Reason::CRC {
+                received: checksum,
+                calculated,
+            });
+            return false;
+        }
+        if !passed && !failed {
+            warn!(
+                target: "ax5043::packet", "ACCEPTED CRC: received 0x{:x}, calculated 0x{:x}",
+                checksum, calculated
+            );
+            self.stats.crc_fail += 1;
+        }
+        if let Some(ref address) = self.address {
+            if !address.matches(&self.packet) {
+                self.stats.addr_fail += 1;
+                if !self.accept.addr_failed {
+                    warn!(target: "ax5043::packet", "Rejected address {:02X?}", self.packet);
+                    self.drop_partial(Reason::Address);
+                    return false;
+                }
+            }
+        }
+        self.crc_ok = passed && !flags.contains(FIFODataRXFlags::CRCFAIL);
+        if self.trailer != CrcTrailer::Keep {
+            self.packet.truncate(len);
+        }
+        self.stats.packets += 1;
+        self.stats.bytes += len as u64;
+        true
+    }
+
+    /// Empties the FIFO, returning the packets completed by what was in it. FIFO errors are
+    /// logged and drop the partial packet rather than failing. Doesn't say which packets
+    /// failed their CRC, drain_with() does.
+    pub fn drain(&mut self, radio: &mut Registers) -> crate::Result<Vec<Vec<u8>>> {
+        let mut packets = Vec::new();
+        self.drain_with(radio, |_, packet, _, _| {
+            packets.push(packet.to_vec());
+            Ok::<_, crate::Error>(())
+        })?;
+        Ok(packets)
+    }
+
+    /// Like drain(), but hands each packet to `on_packet` straight out of the assembler's own
+    /// buffers, along with its antenna RSSI if the radio stored one and whether it passed its
+    /// CRC (see crc_ok()). Nothing is allocated per IRQ unless something is rejected with
+    /// keep_rejected() on, so this is the one for the IRQ handler.
+    pub fn drain_with<E: From<crate::Error>>(
+        &mut self,
+        radio: &mut Registers,
+        mut on_packet: impl FnMut(&mut Registers, &[u8], Option<AntennaRssi>, bool) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let len = usize::from(radio.FIFOCOUNT().read()?);
+        if len == 0 {
+            return Ok(());
+        }
+        // Only the simulator's FIFO holds more than the radio's
+        if self.fifo.len() < len {
+            self.fifo.resize(len, 0);
+        }
+
+        let mut fifo = std::mem::take(&mut self.fifo);
+        let result = self.drain_from(radio, &mut fifo[..len], &mut on_packet);
+        self.fifo = fifo;
+        result
+    }
+
+    fn drain_from<E: From<crate::Error>>(
+        &mut self,
+        radio: &mut Registers,
+        fifo: &mut [u8],
+        on_packet: &mut impl FnMut(&mut Registers, &[u8], Option<AntennaRssi>, bool) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if let Err(e) = radio.FIFODATARX().read_raw(fifo) {
+            self.fifo_error(e);
+            return Ok(());
+        }
+
+        let mut rssi = None;
+        let mut rest = &fifo[..];
+        while !rest.is_empty() {
+            if let Some(antenna) = AntennaRssi::from_chunk(rest) {
+                self.antenna = Some(antenna);
+            }
+            let (flags, data) = match data_chunk(&mut rest) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => continue,
+                Err(e) => {
+                    self.fifo_error(e);
+                    return Ok(());
+                }
+            };
+            if !self.feed(flags, data) {
+                continue;
+            }
+            if self.squelch.is_some() {
+                let rssi = match rssi {
+                    Some(rssi) => rssi,
+                    None => *rssi.insert(radio.RSSI().read()?),
+                };
+                let packet = std::mem::take(&mut self.packet);
+                let squelched = self.squelched(rssi, &packet);
+                self.packet = packet;
+                if squelched {
+                    self.packet.clear();
+                    continue;
+                }
+            }
+            let result = on_packet(radio, &self.packet, self.antenna.take(), self.crc_ok);
+            self.packet.clear();
+            result?;
+        }
+        Ok(())
+    }
+
+    fn fifo_error(&mut self, e: crate::Error) {
+        // FIFO Errors are usually just overflow, non-fatal
+        warn!(target: "ax5043::fifo", "{}", e);
+        self.stats.fifo_errors += 1;
+        self.drop_partial(Reason::FIFO(e.to_string()));
+    }
+}
+
+/// Takes the next chunk off the front of `rest`, returning the flags and data if it's a DATA
+/// chunk. Checks the same things FIFOChunkRX::try_from() does, without copying the data out.
+fn data_chunk<'a>(rest: &mut &'a [u8]) -> crate::Result<Option<(FIFODataRXFlags, &'a [u8])>> {
+    let len = crate::chunk_len(rest)?;
+    if rest.len() < len {
+        return Err(crate::Error::DecodeBytes(rest.to_vec()));
+    }
+    let (chunk, tail) = rest.split_at(len);
+    *rest = tail;
+    if !matches!(
+        FIFOChunkHeaderRX::try_from(chunk[0]),
+        Ok(FIFOChunkHeaderRX::DATA)
+    ) {
+        return Ok(None);
+    }
+    if chunk.len() <= 4 {
+        return Err(crate::Error::DecodeBytes(chunk.to_vec()));
+    }
+    let flags = FIFODataRXFlags::from_bits(chunk[2])
+        .ok_or_else(|| crate::Error::DecodeBytes(chunk.to_vec()))?;
+    Ok(Some((flags, &chunk[3..])))
+}
+
+/// Leaves RX with the FIFO cleared and the IRQ masked, ready for TX or reconfiguration
+pub fn stop(radio: &mut Registers) -> crate::Result<()> {
+    radio.IRQMASK().write(IRQ::empty())?;
+    // PM p. 12: The FIFO should be emptied before the PWRMODE is set to POWERDOWN
+    radio.FIFOCMD().write(FIFOCmd {
+        mode: FIFOCmds::CLEAR_ERROR,
+        auto_commit: false,
+    })?;
+    radio.FIFOCMD().write(FIFOCmd {
+        mode: FIFOCmds::CLEAR_DATA,
+        auto_commit: false,
+    })?;
+    // See errata - PWRMODE must transition through off for FIFO to work
+    radio.PWRMODE().write(PwrMode {
+        flags: PwrFlags::XOEN | PwrFlags::REFEN,
+        mode: PwrModes::POWEROFF,
+    })?;
+    Ok(())
+}
+
+/// When the FIFO IRQ fires, and so how much each drain() gets to read. `[fifo]` in the config.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum FifoPolicy {
+    /// FIFONOTEMPTY: an IRQ as soon as anything arrives, usually one chunk per drain. Lowest
+    /// latency and plenty at 9600 baud.
+    #[default]
+    NotEmpty,
+    /// FIFOTHRCNT: no IRQ until more than `threshold` bytes wait, so at 60 kbps and up a
+    /// packet comes out in a few large reads instead of two transactions per chunk. Best as
+    /// whole chunks (PKTCHUNKSIZE plus 3 bytes of chunk header) below the 256 byte FIFO. The
+    /// tail of a packet that never reaches the threshold is left for the caller to drain every
+    /// `flush_ms`.
+    Threshold { threshold: u16, flush_ms: u64 },
+}
+
+impl FifoPolicy {
+    pub fn irq(&self) -> IRQ {
+        match self {
+            Self::NotEmpty => IRQ::FIFONOTEMPTY,
+            Self::Threshold { .. } => IRQ::FIFOTHRCNT | IRQ::FIFOERROR,
+        }
+    }
+
+    /// How often the caller should drain() without an IRQ, None if it never needs to
+    pub fn flush(&self) -> Option<Duration> {
+        match self {
+            Self::NotEmpty => None,
+            Self::Threshold { flush_ms, .. } => Some(Duration::from_millis(*flush_ms)),
+        }
+    }
+
+    /// Call when loading the config, arm() refuses the same values on the radio
+    pub fn validate(&self) -> crate::Result<()> {
+        match *self {
+            Self::NotEmpty => Ok(()),
+            Self::Threshold { threshold: 0, .. } => {
+                Err(crate::Error::Fifo("threshold must be positive"))
+            }
+            Self::Threshold { threshold, .. } if usize::from(threshold) >= FIFO_SIZE => Err(
+                crate::Error::Fifo("threshold must be below the 256 byte FIFO"),
+            ),
+            Self::Threshold { flush_ms: 0, .. } => {
+                Err(crate::Error::Fifo("flush_ms must be positive"))
+            }
+            Self::Threshold { .. } => Ok(()),
+        }
+    }
+
+    /// Writes the threshold and unmasks the IRQ. TX changes FIFOTHRESH, so this belongs after
+    /// every return to RX.
+    pub fn arm(&self, radio: &mut Registers) -> crate::Result<()> {
+        self.validate()?;
+        if let Self::Threshold { threshold, .. } = *self {
+            radio.FIFOTHRESH().write(threshold)?;
+        }
+        radio.IRQMASK().write(self.irq())?;
+        Ok(())
+    }
+}
+
+/// What happens to the CRC bytes on the end of a received frame, `crc_trailer` in a
+/// [[channel]]. The CRC is checked either way.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum CrcTrailer {
+    /// Frames failing it are dropped, the rest passed on without it
+    #[default]
+    Strip,
+    /// Frames failing it are dropped, the rest passed on with it, for links that forward the
+    /// frame as it was on the air
+    Keep,
+    /// Nothing is dropped for a bad CRC: every frame is passed on without it, flagged with
+    /// whether it passed (see PacketAssembler::drain_with()), for decoders that can make use
+    /// of damaged frames. On the uplink each frame has a status byte in front, see uplink().
+    Flag,
+}
+
+impl CrcTrailer {
+    /// Status byte in front of a Flag frame on the uplink, it passed the CRC
+    pub const CRC_OK: u8 = 0;
+    /// Status byte in front of a Flag frame on the uplink, it failed the CRC
+    pub const CRC_FAILED: u8 = 1;
+
+    /// The uplink datagram for a frame: as it is, or with Flag CRC_OK or CRC_FAILED in front
+    pub fn uplink<'a>(&self, frame: &'a [u8], crc_ok: bool) -> Cow<'a, [u8]> {
+        match (self, crc_ok) {
+            (CrcTrailer::Flag, true) => Cow::Owned([&[Self::CRC_OK], frame].concat()),
+            (CrcTrailer::Flag, false) => Cow::Owned([&[Self::CRC_FAILED], frame].concat()),
+            _ => Cow::Borrowed(frame),
+        }
+    }
+}
+
+fn default_large() -> bool {
+    true
+}
+
+/// Which failed packets the packet controller puts in the FIFO anyway (PKTACCEPTFLAGS), and
+/// PacketAssembler passes on rather than drops. `[accept]` in the config, everything off but
+/// `large` by default.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct AcceptancePolicy {
+    /// Frames ending in a partial byte (HDLC)
+    #[serde(default)]
+    pub residue: bool,
+    /// Frames the packet controller gave up on part way, passed on as far as they got without
+    /// a CRC check (crc_ok() false) and counted in Stats::partial
+    #[serde(default)]
+    pub aborted: bool,
+    /// Bad CRC, checked by the radio and again by PacketAssembler
+    #[serde(default)]
+    pub crc_failed: bool,
+    /// Address not matching PKTADDR under PKTADDRMASK
+    #[serde(default)]
+    pub addr_failed: bool,
+    /// Length outside PKTMAXLEN
+    #[serde(default)]
+    pub size_failed: bool,
+    /// Packets longer than one PKTCHUNKSIZE chunk. Without it the radio drops anything the
+    /// chunk size can't hold in one piece.
+    #[serde(default = "default_large")]
+    pub large: bool,
+}
+
+impl Default for AcceptancePolicy {
+    fn default() -> Self {
+        Self {
+            residue: false,
+            aborted: false,
+            crc_failed: false,
+            addr_failed: false,
+            size_failed: false,
+            large: default_large(),
+        }
+    }
+}
+
+impl AcceptancePolicy {
+    pub fn flags(&self) -> PktAcceptFlags {
+        let mut flags = PktAcceptFlags::empty();
+        for (on, flag) in [
+            (self.residue, PktAcceptFlags::RESIDUE),
+            (self.aborted, PktAcceptFlags::ABRT),
+            (self.crc_failed, PktAcceptFlags::CRCF),
+            (self.addr_failed, PktAcceptFlags::ADDRF),
+            (self.size_failed, PktAcceptFlags::SZF),
+            (self.large, PktAcceptFlags::LRGP),
+        ] {
+            flags.set(flag, on);
+        }
+        flags
+    }
+
+    pub fn write(&self, radio: &mut Registers) -> crate::Result<()> {
+        radio.PKTACCEPTFLAGS().write(self.flags())
+    }
+
+    /// Whether every failure flagged on a chunk is one this accepts
+    fn passes(&self, flags: FIFODataRXFlags) -> bool {
+        [
+            (FIFODataRXFlags::RESIDUE, self.residue),
+            (FIFODataRXFlags::ABORT, self.aborted),
+            (FIFODataRXFlags::CRCFAIL, self.crc_failed),
+            (FIFODataRXFlags::ADDRFAIL, self.addr_failed),
+            (FIFODataRXFlags::SIZEFAIL, self.size_failed),
+        ]
+        .iter()
+        .all(|(flag, on)| *on || !flags.contains(*flag))
+    }
+}
+
+/// Enters RX with FIFONOTEMPTY as the only IRQ
+pub fn start(radio: &mut Registers) -> crate::Result<()> {
+    start_with(radio, FifoPolicy::NotEmpty)
+}
+
+/// Enters RX with the FIFO IRQ chosen by `policy`
+pub fn start_with(radio: &mut Registers, policy: FifoPolicy) -> crate::Result<()> {
+    radio.PWRMODE().write(PwrMode {
+        flags: PwrFlags::XOEN | PwrFlags::REFEN,
+        mode: PwrModes::RX,
+    })?;
+    _ = radio.PLLRANGINGA().read()?; // sticky lock bit ~ IRQPLLUNLIOCK, gate
+                                     // POWSTICKYSTAT is left for supply::History, RX has no BROWN_GATE to arm
+    policy.arm(radio)
+}
+
+/// start_with() with the board's external LNA switched on and settled first, see config::Lna
+pub fn enter(
+    radio: &mut Registers,
+    board: &crate::config::Board,
+    policy: FifoPolicy,
+) -> crate::Result<()> {
+    if let Some(lna) = board.lna {
+        lna.set(radio, true)?;
+        std::thread::sleep(lna.settle());
+    }
+    start_with(radio, policy)
+}
+
+/// stop(), then the LNA off
+pub fn leave(radio: &mut Registers, board: &crate::config::Board) -> crate::Result<()> {
+    stop(radio)?;
+    if let Some(lna) = board.lna {
+        lna.set(radio, false)?;
+    }
+    Ok(())
+}
+
+/// Tracking saved longer ago than this isn't restored, the far end's Doppler and level have
+/// moved on and starting from zero is as good
+pub const TRACKING_MAX_AGE: Duration = Duration::from_secs(10);
+
+/// The AGC gain and frequency tracking as the last RX left them. Saved before switching to TX
+/// and restored once back in RX, so a half-duplex reply isn't spent re-acquiring from scratch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tracking {
+    pub agc: i8,
+    pub rffreq: TrkRFFreq,
+    pub freq: i16,
+    pub at: Instant,
+}
+
+impl Tracking {
+    /// Call while still in RX, before leave()
+    pub fn save(radio: &mut Registers, at: Instant) -> crate::Result<Self> {
+        Ok(Self {
+            agc: radio.AGCCOUNTER().read()?,
+            rffreq: radio.TRKRFFREQ().read()?,
+            freq: radio.TRKFREQ().read()?,
+            at,
+        })
+    }
+
+    /// Call after enter(), the tracking loops carry on from here. Does nothing and returns
+    /// false if the values are past TRACKING_MAX_AGE.
+    pub fn restore(&self, radio: &mut Registers, now: Instant) -> crate::Result<bool> {
+        if now.saturating_duration_since(self.at) > TRACKING_MAX_AGE {
+            return Ok(false);
+        }
+        radio.AGCCOUNTER().write(self.agc)?;
+        radio.TRKRFFREQ().write(self.rffreq)?;
+        radio.TRKFREQ().write(self.freq)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_crc(data: &[u8]) -> Vec<u8> {
+        let crc = Crc::<u16>::new(&CRC_16_GENIBUS).checksum(data);
+        [data, &crc.to_be_bytes()].concat()
+    }
+
+    fn chunk(flags: FIFODataRXFlags, data: &[u8]) -> FIFOChunkRX {
+        FIFOChunkRX::DATA {
+            flags,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn single_and_split() {
+        let mut asm = PacketAssembler::new();
+        let raw = with_crc(b"hello world");
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND;
+        assert_eq!(asm.push(chunk(flags, &raw)), Some(b"hello world".to_vec()));
+
+        let (a, b) = raw.split_at(4);
+        assert_eq!(asm.push(chunk(FIFODataRXFlags::PKTSTART, a)), None);
+        assert_eq!(
+            asm.push(chunk(FIFODataRXFlags::PKTEND, b)),
+            Some(b"hello world".to_vec())
+        );
+    }
+
+    #[test]
+    fn rejects() {
+        let mut asm = PacketAssembler::new();
+        let mut raw = with_crc(b"hello");
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND;
+
+        // Continuation without a start
+        assert_eq!(asm.push(chunk(FIFODataRXFlags::PKTEND, &raw)), None);
+        // Radio flagged error
+        assert_eq!(asm.push(chunk(flags | FIFODataRXFlags::ABORT, &raw)), None);
+        // Runt
+        assert_eq!(asm.push(chunk(flags, &[1])), None);
+        // Bad CRC
+        raw[0] ^= 1;
+        assert_eq!(asm.push(chunk(flags, &raw)), None);
+        // Restart discards the partial packet
+        raw[0] ^= 1;
+        assert_eq!(asm.push(chunk(FIFODataRXFlags::PKTSTART, b"junk")), None);
+        assert_eq!(asm.push(chunk(flags, &raw)), Some(b"hello".to_vec()));
+
+        let stats = asm.stats();
+        assert_eq!((stats.packets, stats.bytes), (1, 5));
+        assert_eq!((stats.abort, stats.crc_fail, stats.dropped), (1, 1, 3));
+    }
+
+    #[test]
+    fn acceptance() {
+        assert_eq!(AcceptancePolicy::default().flags(), PktAcceptFlags::LRGP);
+        let policy: AcceptancePolicy = toml::from_str("crc_failed = true\naborted = true").unwrap();
+        assert_eq!(
+            policy.flags(),
+            PktAcceptFlags::CRCF | PktAcceptFlags::ABRT | PktAcceptFlags::LRGP
+        );
+
+        let mut asm = PacketAssembler::new().accept(policy);
+        let mut raw = with_crc(b"hello");
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND;
+        // Flagged by the radio or only failing the CRC here, passed on and counted either way
+        raw[0] ^= 1;
+        assert_eq!(
+            asm.push(chunk(flags | FIFODataRXFlags::CRCFAIL, &raw)),
+            Some(b"iello".to_vec())
+        );
+        assert_eq!(asm.push(chunk(flags, &raw)), Some(b"iello".to_vec()));
+        // An abort part way ends the packet where it stopped, nothing taken off the end
+        assert_eq!(asm.push(chunk(FIFODataRXFlags::PKTSTART, b"hel")), None);
+        assert_eq!(
+            asm.push(chunk(FIFODataRXFlags::ABORT, b"lo")),
+            Some(b"hello".to_vec())
+        );
+        assert!(!asm.crc_ok());
+        // Anything else flagged is still dropped
+        let addr = flags | FIFODataRXFlags::ADDRFAIL | FIFODataRXFlags::CRCFAIL;
+        assert_eq!(asm.push(chunk(addr, &raw)), None);
+
+        let stats = asm.stats();
+        assert_eq!((stats.packets, stats.partial), (2, 1));
+        assert_eq!((stats.crc_fail, stats.abort, stats.addr_fail), (3, 1, 1));
+    }
+
+    #[test]
+    fn crc_trailer() {
+        let raw = with_crc(b"hello");
+        let mut bad = raw.clone();
+        bad[0] ^= 1;
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND;
+
+        let mut asm = PacketAssembler::new().trailer(CrcTrailer::Keep);
+        assert_eq!(asm.push(chunk(flags, &raw)), Some(raw.clone()));
+        assert_eq!(asm.push(chunk(flags, &bad)), None);
+        assert_eq!(asm.stats().bytes, 5);
+
+        let mut asm = PacketAssembler::new().trailer(CrcTrailer::Flag);
+        assert_eq!(asm.push(chunk(flags, &bad)), Some(b"iello".to_vec()));
+        assert!(!asm.crc_ok());
+        assert_eq!(asm.push(chunk(flags, &raw)), Some(b"hello".to_vec()));
+        assert!(asm.crc_ok());
+        assert_eq!((asm.stats().packets, asm.stats().crc_fail), (2, 1));
+        assert_eq!(CrcTrailer::Flag.uplink(b"iello", false)[..], *b"\x01iello");
+        assert_eq!(CrcTrailer::Flag.uplink(b"hello", true)[..], *b"\x00hello");
+        assert_eq!(CrcTrailer::Strip.uplink(b"hello", true)[..], *b"hello");
+
+        let channel: crate::config::ChannelParameters = toml::from_str(
+            r#"
+            modulation.GMSK = { ramp = "Bits1", bt = 0.5 }
+            encoding = "NRZISCR"
+            framing.HDLC.fec = { }
+            crc.CCITT = { initial = 0xFFFF }
+            datarate = 9600
+            bitorder = "MSBFirst"
+            crc_trailer = "Flag"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(channel.crc_trailer, CrcTrailer::Flag);
+    }
+
+    #[test]
+    fn channel_crc() {
+        use crate::config::{Framing, CRC};
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND;
+
+        // The radio checks CRC32, the four bytes come off
+        let mut asm = PacketAssembler::new().crc(CRC::CRC32 { initial: 0 });
+        assert_eq!(
+            asm.push(chunk(flags, b"hello\x01\x02\x03\x04")),
+            Some(b"hello".to_vec())
+        );
+        assert!(asm.crc_ok());
+        assert_eq!(
+            asm.push(chunk(flags | FIFODataRXFlags::CRCFAIL, b"hello1234")),
+            None
+        );
+
+        let mut asm = PacketAssembler::new().crc(CRC::None);
+        assert_eq!(asm.push(chunk(flags, b"hello")), Some(b"hello".to_vec()));
+
+        // Raw data has no packets, every chunk goes on whole
+        let mut asm = PacketAssembler::new().crc(CRC::None).framing(Framing::Raw);
+        assert_eq!(
+            asm.push(chunk(FIFODataRXFlags::empty(), b"bits")),
+            Some(b"bits".to_vec())
+        );
+        assert_eq!(asm.push(chunk(flags, b"more")), Some(b"more".to_vec()));
+        assert_eq!((asm.stats().packets, asm.stats().bytes), (2, 8));
+    }
+
+    #[test]
+    fn aborts() {
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::ABORT;
+        let mut asm = PacketAssembler::new();
+        asm.push(chunk(flags, b"he"));
+        assert_eq!(asm.take_aborts(), vec![]);
+
+        let mut asm = PacketAssembler::new().report_aborts();
+        asm.push(chunk(flags, b"he"));
+        asm.push(chunk(FIFODataRXFlags::PKTSTART, b"hel"));
+        asm.push(chunk(FIFODataRXFlags::ABORT, b"lo"));
+        // Not an abort
+        asm.push(chunk(
+            FIFODataRXFlags::PKTSTART | FIFODataRXFlags::CRCFAIL,
+            b"x",
+        ));
+        assert_eq!(
+            asm.take_aborts(),
+            vec![
+                RxAbort {
+                    stage: AbortStage::Start,
+                    partial: 2,
+                    flags,
+                },
+                RxAbort {
+                    stage: AbortStage::Payload,
+                    partial: 5,
+                    flags: FIFODataRXFlags::ABORT,
+                },
+            ]
+        );
+        assert_eq!(asm.take_aborts(), vec![]);
+        assert_eq!(asm.stats().abort, 2);
+    }
+
+    #[test]
+    fn kept_rejects() {
+        let mut asm = PacketAssembler::new();
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND;
+        asm.push(chunk(flags, &[1]));
+        assert_eq!(asm.take_rejected(), vec![]);
+
+        let mut asm = PacketAssembler::new().keep_rejected();
+        let mut raw = with_crc(b"hello");
+        raw[0] ^= 1;
+        asm.push(chunk(FIFODataRXFlags::PKTSTART, b"he"));
+        asm.push(chunk(FIFODataRXFlags::CRCFAIL, b"llo"));
+        asm.push(chunk(flags, &raw));
+        assert_eq!(
+            asm.take_rejected(),
+            vec![
+                Rejected {
+                    reason: Reason::Flags(FIFODataRXFlags::CRCFAIL),
+                    data: b"hello".to_vec()
+                },
+                Rejected {
+                    reason: Reason::CRC {
+                        received: u16::from_be_bytes([raw[5], raw[6]]),
+                        calculated: Crc::<u16>::new(&CRC_16_GENIBUS).checksum(&raw[..5]),
+                    },
+                    data: raw.clone()
+                },
+            ]
+        );
+        assert_eq!(asm.take_rejected(), vec![]);
+    }
+
+    #[test]
+    fn squelch() {
+        let mut asm = PacketAssembler::new()
+            .keep_rejected()
+            .rssi(crate::config::RssiCalibration {
+                offset: -120.0,
+                gain: 0.5,
+            });
+        assert!(!asm.squelched(-100, b"weak"));
+
+        // -120 + 0.5 * 40 = -100 dBm
+        asm.set_squelch(Some(-100.0));
+        assert!(!asm.squelched(40, b"ok"));
+        assert!(asm.squelched(39, b"weak"));
+        assert_eq!(
+            asm.take_rejected(),
+            vec![Rejected {
+                reason: Reason::Squelch(-100.5),
+                data: b"weak".to_vec()
+            }]
+        );
+        assert_eq!(Reason::Squelch(-100.5).to_string(), "squelch -100.5 dBm");
+        assert!(!Reason::Squelch(-100.5).damaged());
+        assert!(Reason::Flags(FIFODataRXFlags::CRCFAIL | FIFODataRXFlags::PKTEND).damaged());
+        assert!(!Reason::Flags(FIFODataRXFlags::ADDRFAIL | FIFODataRXFlags::PKTEND).damaged());
+        assert_eq!(asm.stats().dropped, 1);
+    }
+
+    #[test]
+    fn lna_follows_rx() {
+        use crate::config::{Board, Lna, LnaPin};
+        let board = Board {
+            lna: Some(Lna {
+                pin: LnaPin::AntSel,
+                active_low: true,
+                settle_us: 0,
+            }),
+            ..Board::default()
+        };
+        let writes = crate::dry_run(|radio| {
+            enter(radio, &board, FifoPolicy::NotEmpty)?;
+            leave(radio, &board)
+        })
+        .unwrap();
+        let antsel: Vec<_> = writes
+            .to("PINFUNCANTSEL")
+            .iter()
+            .map(|w| PFAntSel::try_from(Reg8::from(w.data[0])).unwrap().mode)
+            .collect();
+        // Active low: on is ZERO
+        assert_eq!(antsel, [PFAntSelMode::ZERO, PFAntSelMode::ONE]);
+        // On before RX, off only once the radio is out of it
+        assert!(writes.in_order(&["PINFUNCANTSEL", "PWRMODE", "PWRMODE", "PINFUNCANTSEL"]));
+        assert_eq!(writes.names().last(), Some(&"PINFUNCANTSEL"));
+    }
+
+    #[test]
+    fn antenna_rssi() {
+        let channel = crate::sim::Channel {
+            rssi: -70,
+            ..Default::default()
+        };
+        let (a, b) = crate::sim::pair(channel);
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut tx_regs = Registers::new(crate::Bus::Sim(a), &mut ca);
+        let mut rx_regs = Registers::new(crate::Bus::Sim(b), &mut cb);
+        let mut asm = PacketAssembler::new();
+        let mut received = |rx_regs: &mut Registers, asm: &mut PacketAssembler| {
+            crate::tx::transmit(&mut tx_regs, b"diversity").unwrap();
+            let mut antennas = Vec::new();
+            asm.drain_with(rx_regs, |_, _, antenna, _| {
+                antennas.push(antenna);
+                Ok::<_, crate::Error>(())
+            })
+            .unwrap();
+            antennas
+        };
+
+        start(&mut rx_regs).unwrap();
+        assert_eq!(received(&mut rx_regs, &mut asm), [None]);
+
+        rx_regs
+            .PKTSTOREFLAGS()
+            .write(PktStoreFlags::ANT_RSSI)
+            .unwrap();
+        let antenna = AntennaRssi {
+            ant1: -70,
+            ant2: -70,
+        };
+        assert_eq!(received(&mut rx_regs, &mut asm), [Some(antenna)]);
+        assert_eq!(antenna.selected(), 1);
+        assert_eq!(
+            AntennaRssi {
+                ant1: -90,
+                ant2: -80
+            }
+            .selected(),
+            2
+        );
+    }
+
+    #[test]
+    fn antenna_per_packet() {
+        let (a, b) = crate::sim::pair(crate::sim::Channel {
+            rssi: -90,
+            ant2: Some(-80),
+            ..Default::default()
+        });
+        let port = a.clone();
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut tx_regs = Registers::new(crate::Bus::Sim(a), &mut ca);
+        let mut rx_regs = Registers::new(crate::Bus::Sim(b), &mut cb);
+        let mut asm = PacketAssembler::new();
+        start(&mut rx_regs).unwrap();
+        rx_regs
+            .PKTSTOREFLAGS()
+            .write(PktStoreFlags::ANT_RSSI)
+            .unwrap();
+
+        // Both packets are in the FIFO before it's drained, each keeps its own antenna
+        crate::tx::transmit(&mut tx_regs, b"first").unwrap();
+        rx_regs.RSSI().read().unwrap(); // delivers it
+        port.set_channel(crate::sim::Channel {
+            rssi: -70,
+            ..Default::default()
+        });
+        crate::tx::transmit(&mut tx_regs, b"second").unwrap();
+        let mut received = Vec::new();
+        asm.drain_with(&mut rx_regs, |_, packet, antenna, _| {
+            received.push((packet.to_vec(), antenna.map(|a| a.selected())));
+            Ok::<_, crate::Error>(())
+        })
+        .unwrap();
+        assert_eq!(
+            received,
+            [(b"first".to_vec(), Some(2)), (b"second".to_vec(), Some(1))]
+        );
+    }
+
+    #[test]
+    fn fifo_policy() {
+        let writes = crate::dry_run(start).unwrap();
+        assert!(writes.to("FIFOTHRESH").is_empty());
+        assert_eq!(writes.to("IRQMASK")[0].data, vec![0x00, 0x01]);
+
+        let policy = FifoPolicy::Threshold {
+            threshold: 131,
+            flush_ms: 20,
+        };
+        let writes = crate::dry_run(|radio| start_with(radio, policy)).unwrap();
+        assert!(writes.in_order(&["PWRMODE", "FIFOTHRESH", "IRQMASK"]));
+        assert_eq!(writes.to("FIFOTHRESH")[0].data, vec![0x00, 131]);
+        assert_eq!(writes.to("IRQMASK")[0].data, vec![0x00, 0x14]);
+        assert_eq!(policy.flush(), Some(Duration::from_millis(20)));
+
+        let full = FifoPolicy::Threshold {
+            threshold: 256,
+            flush_ms: 20,
+        };
+        assert!(matches!(full.validate(), Err(crate::Error::Fifo(_))));
+        assert!(crate::dry_run(|radio| start_with(radio, full)).is_err());
+        let never = FifoPolicy::Threshold {
+            threshold: 131,
+            flush_ms: 0,
+        };
+        assert!(matches!(never.validate(), Err(crate::Error::Fifo(_))));
+        assert_eq!(FifoPolicy::NotEmpty.validate().ok(), Some(()));
+
+        let parsed: FifoPolicy =
+            toml::from_str("[Threshold]\nthreshold = 131\nflush_ms = 20").unwrap();
+        assert_eq!(parsed, policy);
+    }
+
+    #[test]
+    fn tracking() {
+        let at = Instant::now();
+        let saved = Tracking {
+            agc: 42,
+            rffreq: TrkRFFreq(-1000),
+            freq: 300,
+            at,
+        };
+        let writes = crate::dry_run(|radio| {
+            assert!(saved.restore(radio, at + Duration::from_secs(2))?);
+            Ok(())
+        })
+        .unwrap();
+        assert!(writes.in_order(&["AGCCOUNTER", "TRKRFFREQ", "TRKFREQ"]));
+        assert_eq!(writes.to("AGCCOUNTER")[0].data, [42]);
+        assert_eq!(writes.to("TRKRFFREQ")[0].data, [0xFF, 0xFC, 0x18]);
+        assert_eq!(writes.to("TRKFREQ")[0].data, 300u16.to_be_bytes());
+
+        let stale = crate::dry_run(|radio| {
+            assert!(!saved.restore(radio, at + TRACKING_MAX_AGE + Duration::from_secs(1))?);
+            Ok(())
+        })
+        .unwrap();
+        assert!(stale.names().is_empty());
+    }
+
+    #[test]
+    fn addresses() {
+        let filter = crate::config::AddressFilter {
+            pos: U4::new(0),
+            mask: u32::MAX,
+            addresses: vec![0x8A6E6896, 0xFFFFFFFF],
+        };
+        let mut asm = PacketAssembler::new()
+            .keep_rejected()
+            .addresses(Some(filter.clone()));
+        let flags = FIFODataRXFlags::PKTSTART | FIFODataRXFlags::PKTEND;
+        let own = [0x96, 0x68, 0x6E, 0x8A, 1, 2];
+        let broadcast = [0xFF, 0xFF, 0xFF, 0xFF, 3];
+        let other = [0x96, 0x68, 0x6E, 0x8B, 4];
+        assert_eq!(asm.push(chunk(flags, &with_crc(&own))), Some(own.to_vec()));
+        assert_eq!(
+            asm.push(chunk(flags, &with_crc(&broadcast))),
+            Some(broadcast.to_vec())
+        );
+        assert_eq!(asm.push(chunk(flags, &with_crc(&other))), None);
+        assert_eq!(asm.stats().addr_fail, 1);
+        assert_eq!(asm.take_rejected()[0].reason, Reason::Address);
+        assert!(!Reason::Address.damaged());
+
+        // Passed on but counted with [accept] addr_failed
+        let mut accepting = PacketAssembler::new()
+            .accept(AcceptancePolicy {
+                addr_failed: true,
+                ..Default::default()
+            })
+            .addresses(Some(filter));
+        assert_eq!(
+            accepting.push(chunk(flags, &with_crc(&other))),
+            Some(other.to_vec())
+        );
+        assert_eq!(accepting.stats().addr_fail, 1);
+
+        // An exact hardware match leaves nothing to check here
+        let exact = crate::config::AddressFilter {
+            pos: U4::new(0),
+            mask: u32::MAX,
+            addresses: vec![0x8A6E6896],
+        };
+        let mut trusting = PacketAssembler::new().addresses(Some(exact));
+        assert_eq!(
+            trusting.push(chunk(flags, &with_crc(&other))),
+            Some(other.to_vec())
+        );
+    }
+}
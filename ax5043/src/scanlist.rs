@@ -0,0 +1,268 @@
+// Time-slicing one receiver across several carriers, for following more than one satellite
+// with one radio.
+//
+// The [scan] config section lists the carriers and how long to listen on each:
+//
+//   [scan]
+//   freqs = [436_500_000, 437_100_000, 435_800_000]
+//   dwell = 2000
+//   priority = 436_500_000
+//
+// With a priority carrier every other hop goes back to it, so it's never more than one dwell
+// away: 436.5, 437.1, 436.5, 435.8, 436.5, ... Scanner::step() holds on a carrier while a frame
+// is coming in, for at most one more dwell, and hop() retunes with autoranging each time. The
+// station bin tags every packet it forwards with the carrier it was heard on, see tag(). Only
+// receivers scan, hopping moves the carrier a transmitter would key on.
+use crate::{config, registers::*, rx, Error, Registers, Result, RX};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often a held Scanner checks again whether the frame is done
+pub const HOLD: Duration = Duration::from_millis(100);
+
+/// The [scan] config section
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScanList {
+    /// Hz, visited in order
+    pub freqs: Vec<config::Hz>,
+    /// Time on each carrier, ms
+    pub dwell: u64,
+    /// Revisited between each of the others, Hz. Needn't be in `freqs`.
+    #[serde(default)]
+    pub priority: Option<config::Hz>,
+    /// Time on the priority carrier, ms, `dwell` if not set
+    #[serde(default)]
+    pub priority_dwell: Option<u64>,
+}
+
+impl ScanList {
+    pub fn validate(&self) -> Result<()> {
+        if self.freqs.is_empty() && self.priority.is_none() {
+            return Err(Error::Scan("no carriers"));
+        }
+        if self.dwell == 0 || self.priority_dwell == Some(0) {
+            return Err(Error::Scan("dwell must be positive"));
+        }
+        // DS Table 8, see Synthesizer::retune()
+        let synth = 27_000_000..=1_050_000_000;
+        if !self
+            .freqs
+            .iter()
+            .chain(&self.priority)
+            .all(|f| synth.contains(f))
+        {
+            return Err(Error::Scan("carrier outside 27 MHz to 1050 MHz"));
+        }
+        Ok(())
+    }
+}
+
+/// Where a ScanList is up to
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scanner {
+    list: ScanList,
+    /// Index into list.freqs of the next carrier that isn't the priority one
+    next: usize,
+    current: config::Hz,
+    /// HOLDs spent on the current carrier past its dwell
+    held: u32,
+}
+
+impl Scanner {
+    /// Starts on the priority carrier, or the first one without
+    pub fn new(list: ScanList) -> Result<Self> {
+        list.validate()?;
+        let mut scanner = Self {
+            current: list.priority.unwrap_or_else(|| list.freqs[0]),
+            next: 0,
+            held: 0,
+            list,
+        };
+        if scanner.list.priority.is_none() {
+            scanner.next = 1 % scanner.list.freqs.len();
+        }
+        Ok(scanner)
+    }
+
+    /// The carrier listened on now, Hz
+    pub fn current(&self) -> config::Hz {
+        self.current
+    }
+
+    fn on_priority(&self) -> bool {
+        self.list.priority == Some(self.current)
+    }
+
+    /// How long to stay on the current carrier
+    pub fn dwell(&self) -> Duration {
+        let ms = match self.list.priority_dwell {
+            Some(ms) if self.on_priority() => ms,
+            _ => self.list.dwell,
+        };
+        Duration::from_millis(ms)
+    }
+
+    /// The carrier after the current one, without moving there
+    fn following(&self) -> (config::Hz, usize) {
+        let others: Vec<_> = self
+            .list
+            .freqs
+            .iter()
+            .copied()
+            .filter(|f| Some(*f) != self.list.priority)
+            .collect();
+        match (self.list.priority, others.len()) {
+            (Some(priority), 0) => (priority, 0),
+            (Some(priority), _) if !self.on_priority() => (priority, self.next),
+            (_, len) => (others[self.next % len], (self.next + 1) % len),
+        }
+    }
+
+    /// Call when the dwell (or a HOLD) runs out, `busy` if a frame is coming in. Returns the
+    /// carrier to hop() to and wait dwell() on, or None to stay and check again after HOLD.
+    pub fn step(&mut self, busy: bool) -> Option<config::Hz> {
+        let holds = (self.dwell().as_millis() / HOLD.as_millis()).max(1) as u32;
+        if busy && self.held < holds {
+            self.held += 1;
+            return None;
+        }
+        self.held = 0;
+        let (freq, next) = self.following();
+        self.current = freq;
+        self.next = next;
+        Some(freq)
+    }
+}
+
+/// Whether hopping now would cut a frame short: the packet engine is past the preamble, or
+/// the assembler has the start of a packet
+pub fn busy(radio: &mut Registers, assembler: &rx::PacketAssembler) -> Result<bool> {
+    Ok(assembler.in_packet()
+        || matches!(
+            radio.RADIOSTATE().read()?,
+            RadioState::RX_PREAMBLE_2 | RadioState::RX_PREAMBLE_3 | RadioState::RX
+        ))
+}
+
+/// Moves the receiver to `freq`: out of RX, retuned with autoranging, and back in. On an
+/// autoranging failure the previous carrier is restored and the error returned. Drain the FIFO
+/// first, what's left in it was heard on the previous carrier.
+pub fn hop(
+    radio: &mut Registers,
+    synth: &mut config::Synthesizer,
+    board: &config::Board,
+    fifo: rx::FifoPolicy,
+    freq: config::Hz,
+) -> Result<()> {
+    rx::leave(radio, board)?;
+    let previous = synth.freq_a;
+    let result = synth.retune(radio, board, freq);
+    if result.is_err() {
+        synth.retune(radio, board, previous)?;
+    }
+    rx::enter(radio, board, fifo)?;
+    result
+}
+
+/// `packet` with the carrier it was heard on in front, 4 bytes big endian Hz
+pub fn tag(freq: config::Hz, packet: &[u8]) -> Vec<u8> {
+    [&(freq as u32).to_be_bytes(), packet].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(priority: Option<config::Hz>) -> ScanList {
+        ScanList {
+            freqs: vec![436_500_000, 437_100_000, 435_800_000],
+            dwell: 2000,
+            priority,
+            priority_dwell: Some(500),
+        }
+    }
+
+    #[test]
+    fn order() {
+        let mut scanner = Scanner::new(list(None)).unwrap();
+        assert_eq!(scanner.current(), 436_500_000);
+        assert_eq!(scanner.dwell(), Duration::from_millis(2000));
+        let hops: Vec<_> = (0..4).map(|_| scanner.step(false).unwrap()).collect();
+        assert_eq!(hops, [437_100_000, 435_800_000, 436_500_000, 437_100_000]);
+
+        // The priority carrier is skipped in freqs and comes back every other hop
+        let mut scanner = Scanner::new(list(Some(436_500_000))).unwrap();
+        assert_eq!(scanner.current(), 436_500_000);
+        assert_eq!(scanner.dwell(), Duration::from_millis(500));
+        let hops: Vec<_> = (0..5).map(|_| scanner.step(false).unwrap()).collect();
+        assert_eq!(
+            hops,
+            [
+                437_100_000,
+                436_500_000,
+                435_800_000,
+                436_500_000,
+                437_100_000
+            ]
+        );
+        assert_eq!(scanner.dwell(), Duration::from_millis(2000));
+
+        let only = ScanList {
+            freqs: Vec::new(),
+            ..list(Some(145_800_000))
+        };
+        let mut scanner = Scanner::new(only).unwrap();
+        assert_eq!(scanner.step(false), Some(145_800_000));
+    }
+
+    #[test]
+    fn holds_for_a_frame() {
+        let mut scanner = Scanner::new(list(None)).unwrap();
+        // 2000 ms dwell, 20 HOLDs at most
+        for _ in 0..20 {
+            assert_eq!(scanner.step(true), None);
+        }
+        assert_eq!(scanner.step(true), Some(437_100_000));
+        assert_eq!(scanner.step(true), None);
+        assert_eq!(scanner.step(false), Some(435_800_000));
+    }
+
+    #[test]
+    fn invalid() {
+        let empty = ScanList {
+            freqs: Vec::new(),
+            ..list(None)
+        };
+        assert!(matches!(empty.validate(), Err(Error::Scan(_))));
+        let still = ScanList {
+            dwell: 0,
+            ..list(None)
+        };
+        assert!(matches!(Scanner::new(still), Err(Error::Scan(_))));
+        let low = ScanList {
+            freqs: vec![436_500_000, 10_000_000],
+            ..list(None)
+        };
+        assert!(matches!(low.validate(), Err(Error::Scan(_))));
+        let parsed: ScanList = toml::from_str("freqs = [436_500_000]\ndwell = 1000").unwrap();
+        assert_eq!(parsed.validate().ok(), Some(()));
+        assert_eq!(
+            tag(436_500_000, &[0xAA])[..],
+            [0x1A, 0x04, 0x76, 0x20, 0xAA]
+        );
+    }
+
+    #[test]
+    fn hop_retunes() {
+        let config: config::Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+        let mut synth = config.synth;
+        let writes =
+            crate::dry_run(|radio| hop(radio, &mut synth, &config.board, config.fifo, 437_100_000))
+                .unwrap();
+        assert_eq!(synth.freq_a, 437_100_000);
+        assert_eq!(writes.to("FREQA").len(), 1);
+        let pwrmode = writes.to("PWRMODE");
+        assert_eq!(pwrmode.last().unwrap().data[0] & 0x0F, 0x09); // RX
+    }
+}
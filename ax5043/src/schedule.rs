@@ -0,0 +1,202 @@
+// Transmit gating on authorized pass windows.
+//
+// The schedule file lists windows in unix seconds, typically generated from pass predictions:
+//
+//   [[window]]
+//   start = 1760529600
+//   end = 1760530200
+//
+// The operator can also force the gate open or closed over the control socket ("tx open",
+// "tx closed", back to the schedule with "tx schedule"), see control::Command::Transmit.
+//
+// An inhibit overrides all of that, "tx open" included: a GPIO line wired to a rotor limit, another
+// transmitter's PTT or a safe switch at the station, or "inhibit on" over the control socket.
+// The uhf bin cuts off the frame on the air when it comes on, see Inhibit.
+use serde::Deserialize;
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub struct Window {
+    /// Unix seconds, inclusive
+    pub start: u64,
+    /// Unix seconds, exclusive
+    pub end: u64,
+}
+
+impl Window {
+    pub fn contains(&self, secs: u64) -> bool {
+        (self.start..self.end).contains(&secs)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct Schedule {
+    #[serde(default)]
+    pub window: Vec<Window>,
+}
+
+fn unix(time: SystemTime) -> u64 {
+    // Before 1970 is never inside a window
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl Schedule {
+    pub fn contains(&self, time: SystemTime) -> bool {
+        let secs = unix(time);
+        self.window.iter().any(|w| w.contains(secs))
+    }
+
+    /// The first window that hasn't ended yet, for logging when the next pass is
+    pub fn next(&self, time: SystemTime) -> Option<Window> {
+        let secs = unix(time);
+        self.window
+            .iter()
+            .filter(|w| w.end > secs)
+            .min_by_key(|w| w.start)
+            .copied()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Always transmit, how the bins behave without a schedule
+    Open,
+    /// Never transmit
+    Closed,
+    /// Only inside a schedule window
+    Scheduled,
+}
+
+/// What's holding transmit off, whatever the gate's mode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Inhibit {
+    /// The inhibit GPIO line is asserted
+    pub line: bool,
+    /// "inhibit on" from the control socket
+    pub operator: bool,
+}
+
+impl Inhibit {
+    pub fn active(&self) -> bool {
+        self.line || self.operator
+    }
+}
+
+impl fmt::Display for Inhibit {
+    /// "line", "operator", "line and operator" or "off"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.operator) {
+            (true, true) => write!(f, "line and operator"),
+            (true, false) => write!(f, "line"),
+            (false, true) => write!(f, "operator"),
+            (false, false) => write!(f, "off"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gate {
+    pub mode: Mode,
+    pub schedule: Schedule,
+    pub inhibit: Inhibit,
+}
+
+impl Gate {
+    /// Follows `schedule`
+    pub fn new(schedule: Schedule) -> Self {
+        Self {
+            mode: Mode::Scheduled,
+            schedule,
+            inhibit: Inhibit::default(),
+        }
+    }
+
+    /// Always open, for running without a schedule file
+    pub fn open() -> Self {
+        Self {
+            mode: Mode::Open,
+            schedule: Schedule::default(),
+            inhibit: Inhibit::default(),
+        }
+    }
+
+    pub fn allows(&self, time: SystemTime) -> bool {
+        if self.inhibit.active() {
+            return false;
+        }
+        match self.mode {
+            Mode::Open => true,
+            Mode::Closed => false,
+            Mode::Scheduled => self.schedule.contains(time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn windows() {
+        let schedule: Schedule = toml::from_str(
+            "[[window]]\nstart = 100\nend = 200\n[[window]]\nstart = 300\nend = 400\n",
+        )
+        .unwrap();
+        assert!(!schedule.contains(at(99)));
+        assert!(schedule.contains(at(100)));
+        assert!(!schedule.contains(at(200)));
+        assert!(schedule.contains(at(350)));
+        assert_eq!(
+            schedule.next(at(250)),
+            Some(Window {
+                start: 300,
+                end: 400
+            })
+        );
+        assert_eq!(
+            schedule.next(at(150)),
+            Some(Window {
+                start: 100,
+                end: 200
+            })
+        );
+        assert_eq!(schedule.next(at(400)), None);
+        assert_eq!(toml::from_str::<Schedule>("").unwrap(), Schedule::default());
+    }
+
+    #[test]
+    fn gate_modes() {
+        let mut gate = Gate::new(Schedule {
+            window: vec![Window { start: 10, end: 20 }],
+        });
+        assert!(gate.allows(at(15)));
+        assert!(!gate.allows(at(25)));
+        gate.mode = Mode::Open;
+        assert!(gate.allows(at(25)));
+        gate.mode = Mode::Closed;
+        assert!(!gate.allows(at(15)));
+        assert!(Gate::open().allows(at(0)));
+    }
+
+    #[test]
+    fn inhibit() {
+        let mut gate = Gate::open();
+        gate.inhibit.line = true;
+        assert!(!gate.allows(at(0)));
+        assert_eq!(gate.inhibit.to_string(), "line");
+        gate.inhibit.operator = true;
+        gate.inhibit.line = false;
+        assert!(!gate.allows(at(0)));
+        gate.inhibit.operator = false;
+        assert!(gate.allows(at(0)));
+        assert_eq!(gate.inhibit.to_string(), "off");
+    }
+}
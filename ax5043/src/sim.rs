@@ -0,0 +1,445 @@
+// Two simulated radios joined by a channel model, for end-to-end tests without hardware.
+//
+// pair() returns a Bus for each radio. Like Bus::Sink each one answers as an idle radio would,
+// but it also keeps what was written and has a FIFO: DATA chunks committed while in TX are
+// framed (the CRC appended unless NOCRC, as the radio does) and put on the air, where the
+// Channel can lose them, flip bits and hold them back. Once a frame's delay has passed it's
+// delivered to the other radio's FIFO as DATA chunks, provided that one is in RX by then. With
+// PKTSTOREFLAGS ANT_RSSI set an ANTRSSI3 chunk goes ahead, with the channel's RSSI on each antenna.
+// Chunks committed in SYNTHTX wait in the FIFO until PWRMODE goes to TX. Only the FIFO and
+// power mode are modelled: no modem, no datarate, no PLL, no IRQs.
+use crate::{registers::*, Bus};
+use crc::{Crc, CRC_16_GENIBUS};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Largest DATA chunk delivered to the RX FIFO, longer frames are split
+const CHUNK: usize = 200;
+
+/// What happens to frames between the two radios
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Channel {
+    /// Probability a frame is lost outright
+    pub loss: f64,
+    /// Probability each bit of a frame (CRC included) is flipped
+    pub ber: f64,
+    /// Time from the end of transmission until the frame reaches the receiver's FIFO
+    pub delay: Duration,
+    /// What the receiver reads from RSSI, dB
+    pub rssi: i8,
+    /// The second antenna's RSSI in the ANTRSSI3 chunk, None for the same as `rssi`
+    pub ant2: Option<i8>,
+    /// Seeds the loss and bit errors so a test sees the same ones every run
+    pub seed: u64,
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self {
+            loss: 0.0,
+            ber: 0.0,
+            delay: Duration::ZERO,
+            rssi: -60,
+            ant2: None,
+            seed: 1,
+        }
+    }
+}
+
+/// Counts kept by the channel, shared by both radios
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Frames put on the air
+    pub sent: u64,
+    pub lost: u64,
+    /// Frames with at least one bit flipped, still delivered
+    pub corrupted: u64,
+    pub delivered: u64,
+    /// Frames that arrived while the receiver wasn't in RX
+    pub missed: u64,
+}
+
+struct Radio {
+    regs: Vec<u8>,
+    /// DATA committed since the last PKTSTART
+    packet: Vec<u8>,
+    fifo: VecDeque<u8>,
+    /// Chunks committed in SYNTHTX, sent on entering TX
+    staged: Vec<Vec<u8>>,
+}
+
+impl Radio {
+    fn new() -> Self {
+        let mut regs = vec![0; 0x1000];
+        regs[0x000] = 0x51; // REVISION
+        regs[0x001] = 0xC5; // SCRATCH
+        regs[0x01D] = XtalStatus::XTAL_RUN.bits(); // XTALSTATUS
+        Self {
+            regs,
+            packet: Vec::new(),
+            fifo: VecDeque::new(),
+            staged: Vec::new(),
+        }
+    }
+
+    fn mode(&self, mode: PwrModes) -> bool {
+        self.regs[0x002] & 0x0F == u8::from(mode)
+    }
+
+    fn read(&mut self, addr: usize, rx: &mut [u8], rssi: i8) {
+        // FIFODATA doesn't step through addresses, every byte comes off the FIFO
+        if addr == 0x029 {
+            for b in rx.iter_mut() {
+                *b = self.fifo.pop_front().unwrap_or(0);
+            }
+            return;
+        }
+        let count = u16::try_from(self.fifo.len())
+            .unwrap_or(u16::MAX)
+            .to_be_bytes();
+        for (reg, b) in (addr..).zip(rx.iter_mut()) {
+            *b = match reg {
+                // FIFOSTAT, there's always room since frames leave as soon as they're committed
+                0x028 => {
+                    let mut stat = FIFOStat::FREE_THR;
+                    stat.set(FIFOStat::EMPTY, self.fifo.is_empty());
+                    stat.bits()
+                }
+                0x02A => count[0],
+                0x02B => count[1],
+                0x040 => rssi as u8,
+                _ => self.regs.get(reg).copied().unwrap_or(0),
+            };
+        }
+    }
+
+    /// Returns a frame if this write finished one
+    fn write(&mut self, addr: usize, tx: &[u8]) -> Option<Vec<u8>> {
+        match addr {
+            // FIFOCMD
+            0x028 => {
+                if tx[0] & 0x7F == u8::from(FIFOCmds::CLEAR_DATA) {
+                    self.fifo.clear();
+                    self.packet.clear();
+                    self.staged.clear();
+                }
+                None
+            }
+            0x029 => self.commit(tx),
+            _ => {
+                for (i, &b) in tx.iter().enumerate() {
+                    if let Some(reg) = self.regs.get_mut(addr + i) {
+                        *reg = b;
+                    }
+                }
+                // PLLRANGINGA/B: autoranging finishes immediately
+                if addr == 0x033 || addr == 0x03B {
+                    self.regs[addr] &= !PLLRangingFlags::RNG_START.bits();
+                }
+                if addr == 0x002 && self.mode(PwrModes::TX) {
+                    let staged = std::mem::take(&mut self.staged);
+                    return staged.iter().filter_map(|tx| self.commit(tx)).last();
+                }
+                None
+            }
+        }
+    }
+
+    fn commit(&mut self, tx: &[u8]) -> Option<Vec<u8>> {
+        // Preamble, postamble and PA control don't make it into the frame
+        let Ok(FIFOChunkTX::DATA { flags, data }) = FIFOChunkTX::try_from(tx.to_vec()) else {
+            return None;
+        };
+        if self.mode(PwrModes::SYNTHTX) {
+            self.staged.push(tx.to_vec());
+            return None;
+        }
+        if !self.mode(PwrModes::TX) {
+            return None;
+        }
+        if flags.contains(FIFODataTXFlags::PKTSTART) {
+            self.packet.clear();
+        }
+        self.packet.extend_from_slice(&data);
+        if !flags.contains(FIFODataTXFlags::PKTEND) {
+            return None;
+        }
+        let mut frame = std::mem::take(&mut self.packet);
+        if !flags.contains(FIFODataTXFlags::NOCRC) {
+            let crc = Crc::<u16>::new(&CRC_16_GENIBUS).checksum(&frame);
+            frame.extend_from_slice(&crc.to_be_bytes());
+        }
+        Some(frame)
+    }
+
+    fn receive(&mut self, frame: &[u8], rssi: i8, ant2: i8) {
+        if self.regs[0x232] & PktStoreFlags::ANT_RSSI.bits() != 0 {
+            let chunk = FIFOChunkRX::ANTRSSI3 {
+                ant1rssi: rssi,
+                ant2rssi: ant2,
+                bgndnoise: 0,
+            };
+            self.fifo.extend(Vec::<u8>::from(chunk));
+        }
+        let chunks = frame.chunks(CHUNK);
+        let last = chunks.len() - 1;
+        for (i, data) in chunks.enumerate() {
+            let mut flags = FIFODataRXFlags::empty();
+            flags.set(FIFODataRXFlags::PKTSTART, i == 0);
+            flags.set(FIFODataRXFlags::PKTEND, i == last);
+            let chunk = FIFOChunkRX::DATA {
+                flags,
+                data: data.to_vec(),
+            };
+            self.fifo.extend(Vec::<u8>::from(chunk));
+        }
+    }
+}
+
+struct InFlight {
+    due: Instant,
+    to: usize,
+    frame: Vec<u8>,
+}
+
+struct Air {
+    channel: Channel,
+    rng: u64,
+    radios: [Radio; 2],
+    in_flight: VecDeque<InFlight>,
+    stats: Stats,
+}
+
+impl Air {
+    /// xorshift64*, uniform in [0, 1)
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn send(&mut self, from: usize, mut frame: Vec<u8>) {
+        self.stats.sent += 1;
+        if self.random() < self.channel.loss {
+            self.stats.lost += 1;
+            return;
+        }
+        let mut corrupted = false;
+        if self.channel.ber > 0.0 {
+            for byte in frame.iter_mut() {
+                for bit in 0..8 {
+                    if self.random() < self.channel.ber {
+                        *byte ^= 1 << bit;
+                        corrupted = true;
+                    }
+                }
+            }
+        }
+        if corrupted {
+            self.stats.corrupted += 1;
+        }
+        self.in_flight.push_back(InFlight {
+            due: Instant::now() + self.channel.delay,
+            to: 1 - from,
+            frame,
+        });
+    }
+
+    fn deliver(&mut self, now: Instant) {
+        let rssi = self.channel.rssi;
+        let ant2 = self.channel.ant2.unwrap_or(rssi);
+        while self.in_flight.front().is_some_and(|f| f.due <= now) {
+            let Some(InFlight { to, frame, .. }) = self.in_flight.pop_front() else {
+                break;
+            };
+            let radio = &mut self.radios[to];
+            if radio.mode(PwrModes::RX) {
+                radio.receive(&frame, rssi, ant2);
+                self.stats.delivered += 1;
+            } else {
+                self.stats.missed += 1;
+            }
+        }
+    }
+}
+
+/// One side of a pair(), cloned to look at the channel after the Bus has gone to Registers
+#[derive(Clone)]
+pub struct Port {
+    air: Arc<Mutex<Air>>,
+    side: usize,
+}
+
+impl Port {
+    pub fn stats(&self) -> Stats {
+        self.air.lock().map(|air| air.stats).unwrap_or_default()
+    }
+
+    /// Changes the channel for frames sent from now on, keeping the random sequence going
+    pub fn set_channel(&self, channel: Channel) {
+        if let Ok(mut air) = self.air.lock() {
+            air.channel = channel;
+        }
+    }
+
+    pub(crate) fn transfer(
+        &self,
+        addr: &[u8],
+        stat: &mut [u8],
+        tx: &[u8],
+        rx: &mut [u8],
+    ) -> io::Result<()> {
+        let mut air = self
+            .air
+            .lock()
+            .map_err(|_| io::Error::other("simulated channel poisoned"))?;
+        air.deliver(Instant::now());
+        stat.fill(0);
+        rx.fill(0);
+        let header = u16::from_be_bytes([addr[0], addr[1]]);
+        let reg = usize::from(header & 0x0FFF);
+        let rssi = air.channel.rssi;
+        let radio = &mut air.radios[self.side];
+        // Reads have the top (write) bit clear
+        if header & 0x8000 == 0 {
+            radio.read(reg, rx, rssi);
+        } else if let Some(frame) = radio.write(reg, tx) {
+            air.send(self.side, frame);
+        }
+        Ok(())
+    }
+}
+
+impl From<Port> for Bus {
+    fn from(port: Port) -> Self {
+        Bus::Sim(port)
+    }
+}
+
+/// Two radios that hear each other through `channel`
+pub fn pair(channel: Channel) -> (Port, Port) {
+    let air = Arc::new(Mutex::new(Air {
+        channel,
+        rng: channel.seed.max(1),
+        radios: [Radio::new(), Radio::new()],
+        in_flight: VecDeque::new(),
+        stats: Stats::default(),
+    }));
+    (
+        Port {
+            air: air.clone(),
+            side: 0,
+        },
+        Port { air, side: 1 },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        rx::{self, PacketAssembler},
+        tx, Probe, Registers, RX, TX,
+    };
+    use std::thread;
+
+    /// Sends `frames` from a to b and drains b once everything is due
+    fn run(channel: Channel, frames: &[Vec<u8>]) -> (Vec<Vec<u8>>, rx::Stats, Stats) {
+        let (a, b) = pair(channel);
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut tx_regs = Registers::new(Bus::Sim(a.clone()), &mut ca);
+        let mut rx_regs = Registers::new(Bus::Sim(b), &mut cb);
+        rx::start(&mut rx_regs).unwrap();
+        for frame in frames {
+            tx::transmit(&mut tx_regs, frame).unwrap();
+        }
+        thread::sleep(channel.delay);
+        let mut assembler = PacketAssembler::new();
+        let received = assembler.drain(&mut rx_regs).unwrap();
+        (received, *assembler.stats(), a.stats())
+    }
+
+    fn frames(count: u32, len: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|seq| tx::test_frame(seq, len)).collect()
+    }
+
+    #[test]
+    fn clean_channel() {
+        // 300 bytes spans several TX and RX chunks
+        let sent = [frames(3, 16), frames(2, 300)].concat();
+        let (received, rx_stats, stats) = run(Channel::default(), &sent);
+        assert_eq!(received, sent);
+        assert_eq!(rx_stats.packets, 5);
+        assert_eq!(rx_stats.crc_fail, 0);
+        assert_eq!((stats.sent, stats.delivered), (5, 5));
+    }
+
+    #[test]
+    fn loss() {
+        let channel = Channel {
+            loss: 0.5,
+            ..Default::default()
+        };
+        let sent = frames(40, 32);
+        let (received, _, stats) = run(channel, &sent);
+        assert!(stats.lost > 0 && stats.lost < 40, "{:?}", stats);
+        assert_eq!(received.len() as u64, stats.delivered);
+        assert_eq!(stats.lost + stats.delivered, 40);
+        assert!(received.iter().all(|r| sent.contains(r)));
+    }
+
+    #[test]
+    fn bit_errors() {
+        let channel = Channel {
+            ber: 1e-3,
+            ..Default::default()
+        };
+        let (received, rx_stats, stats) = run(channel, &frames(40, 100));
+        assert!(stats.corrupted > 0, "{:?}", stats);
+        assert_eq!(rx_stats.crc_fail, stats.corrupted);
+        assert_eq!(received.len() as u64, 40 - stats.corrupted);
+    }
+
+    #[test]
+    fn delay() {
+        let channel = Channel {
+            delay: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let (a, b) = pair(channel);
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut tx_regs = Registers::new(Bus::Sim(a.clone()), &mut ca);
+        let mut rx_regs = Registers::new(Bus::Sim(b), &mut cb);
+        let mut assembler = PacketAssembler::new();
+
+        rx::start(&mut rx_regs).unwrap();
+        tx::transmit(&mut tx_regs, b"late").unwrap();
+        assert!(assembler.drain(&mut rx_regs).unwrap().is_empty());
+        thread::sleep(channel.delay);
+        assert_eq!(assembler.drain(&mut rx_regs).unwrap(), [b"late"]);
+
+        // Stopped before it arrived
+        tx::transmit(&mut tx_regs, b"missed").unwrap();
+        rx::stop(&mut rx_regs).unwrap();
+        thread::sleep(channel.delay);
+        assert!(assembler.drain(&mut rx_regs).unwrap().is_empty());
+        assert_eq!((a.stats().delivered, a.stats().missed), (1, 1));
+    }
+
+    #[test]
+    fn registers() {
+        let (a, b) = pair(Channel::default());
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut radio = Registers::new(Bus::Sim(a), &mut ca);
+        let mut other = Registers::new(Bus::Sim(b), &mut cb);
+        radio.FIFOTHRESH().write(128).unwrap();
+        assert_eq!(radio.FIFOTHRESH().read().unwrap(), 128);
+        assert_eq!(other.FIFOTHRESH().read().unwrap(), 0);
+        assert_eq!(radio.probe().unwrap(), Probe::Found);
+    }
+}
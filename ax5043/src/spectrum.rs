@@ -0,0 +1,160 @@
+// RSSI survey across a range of carriers.
+//
+// Each step retunes FREQA (autoranging included), drops into RX with the board's LNA on (see
+// rx::enter()) and samples the RSSI register evenly across the dwell time, the first sample late
+// enough for the AGC to settle. The radio needs a full config written first so the RX chain
+// (filters, AGC, datarate) is set up, the channel bandwidth is whatever that config says.
+use crate::{
+    config::{Board, Hz, RssiCalibration, Synthesizer},
+    rx::{self, FifoPolicy},
+    Registers, Result, RX,
+};
+use serde::{Deserialize, Serialize};
+use std::{thread, time::Duration};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Reading {
+    pub freq: Hz,
+    /// dBm, see config::RssiCalibration
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Reading {
+    /// None if there are no samples
+    pub fn new(freq: Hz, samples: &[i8], calibration: &RssiCalibration) -> Option<Self> {
+        let min = *samples.iter().min()?;
+        let max = *samples.iter().max()?;
+        let sum: f64 = samples.iter().map(|&s| f64::from(s)).sum();
+        Some(Self {
+            freq,
+            min: calibration.dbm(f64::from(min)),
+            max: calibration.dbm(f64::from(max)),
+            mean: calibration.dbm(sum / samples.len() as f64),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sweep {
+    pub start: Hz,
+    pub stop: Hz,
+    pub step: Hz,
+    /// Time in RX per step, the whole of it. The first read comes dwell / samples in, which
+    /// should be long enough for the AGC to settle.
+    pub dwell: Duration,
+    /// RSSI reads per step, each one dwell / samples apart
+    pub samples: usize,
+}
+
+impl Sweep {
+    pub fn freqs(&self) -> impl Iterator<Item = Hz> {
+        (self.start..=self.stop).step_by(self.step.max(1) as usize)
+    }
+
+    /// Runs the sweep, handing each Reading to `on_reading` as it completes. FREQA is restored
+    /// afterwards and the radio left in POWEROFF with the LNA off, also when a step fails.
+    pub fn run(
+        &self,
+        radio: &mut Registers,
+        synth: &mut Synthesizer,
+        board: &Board,
+        on_reading: impl FnMut(Reading),
+    ) -> Result<()> {
+        let original = synth.freq_a;
+        let swept = self.steps(radio, synth, board, on_reading);
+        // A failed step can leave the radio in RX on some other carrier
+        let restored = rx::leave(radio, board).and_then(|()| synth.retune(radio, board, original));
+        swept?;
+        restored
+    }
+
+    fn steps(
+        &self,
+        radio: &mut Registers,
+        synth: &mut Synthesizer,
+        board: &Board,
+        mut on_reading: impl FnMut(Reading),
+    ) -> Result<()> {
+        let samples = self.samples.max(1);
+        let interval = self.dwell / samples as u32;
+        let mut rssi = Vec::with_capacity(samples);
+
+        for freq in self.freqs() {
+            synth.retune(radio, board, freq)?;
+            rx::enter(radio, board, FifoPolicy::NotEmpty)?;
+
+            rssi.clear();
+            for _ in 0..samples {
+                thread::sleep(interval);
+                rssi.push(radio.RSSI().read()?);
+            }
+            on_reading(Reading::new(freq, &rssi, &board.rssi).unwrap());
+
+            rx::leave(radio, board)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_stats() {
+        let raw = RssiCalibration::default();
+        let r = Reading::new(435_000_000, &[-100, -90, -80], &raw).unwrap();
+        assert_eq!((r.min, r.max, r.mean), (-100.0, -80.0, -90.0));
+        assert_eq!(Reading::new(0, &[], &raw), None);
+
+        let calibration = RssiCalibration {
+            offset: -40.0,
+            gain: 0.5,
+        };
+        let r = Reading::new(435_000_000, &[-100, -90, -80], &calibration).unwrap();
+        assert_eq!((r.min, r.max, r.mean), (-90.0, -80.0, -85.0));
+    }
+
+    #[test]
+    fn freqs_inclusive() {
+        let sweep = Sweep {
+            start: 100,
+            stop: 130,
+            step: 10,
+            dwell: Duration::ZERO,
+            samples: 1,
+        };
+        assert_eq!(sweep.freqs().collect::<Vec<_>>(), vec![100, 110, 120, 130]);
+    }
+
+    #[test]
+    fn restores_after_a_failed_step() {
+        let mut config: crate::config::Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+        let original = config.synth.freq_a;
+        // The last carrier is past the top of the VCO range, retune() refuses it
+        let sweep = Sweep {
+            start: 1_049_999_000,
+            stop: 1_050_001_000,
+            step: 1_000,
+            dwell: Duration::ZERO,
+            samples: 1,
+        };
+        let mut readings = 0;
+        let writes = crate::dry_run(|radio| {
+            assert!(sweep
+                .run(radio, &mut config.synth, &config.board, |_| readings += 1)
+                .is_err());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(readings, 2);
+        assert_eq!(config.synth.freq_a, original);
+        let freqa = writes.to("FREQA");
+        let restored =
+            crate::dry_run(|radio| config.synth.write(radio, &config.board).map(|_| ())).unwrap();
+        assert_eq!(freqa.last().unwrap().data, restored.to("FREQA")[0].data);
+    }
+}
@@ -0,0 +1,560 @@
+// Keeps received frames on disk while whatever reads the uplink isn't there, so restarting the
+// dashboard mid-pass doesn't lose them.
+//
+// The uplink is a connected UDP socket, so a consumer that's gone shows up as ConnectionRefused
+// on a later send, from the ICMP the one before it drew. From then on Forwarder appends every
+// frame to the Spool instead and, from service() once a second, sends the oldest as a probe. If
+// no refusal came back by the next second the consumer is back: the probe comes off the spool
+// and the rest is replayed, REPLAY frames a second with new frames queued behind them so the
+// order holds. The frame that drew the first refusal is lost, UDP can't tell which one it was.
+//
+// The spool is a directory (--spool) of numbered segment files, one line per frame:
+//
+//   time  rssi  rf_offset  data  [crc_failed]
+//
+// as in rejects, with crc_failed on the end of frames passed on despite a failed CRC, so what's
+// left after an outage can be read without the daemon. A segment is closed at a quarter of the
+// size limit (at most SEGMENT) and whole segments are deleted, oldest first, to stay under the
+// limit and as they're replayed. A restart picks up the segments left behind and may send part
+// of one of them twice.
+use crate::capture::Meta;
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+/// Largest segment file, bytes
+pub const SEGMENT: u64 = 1 << 20;
+/// Spooled frames sent per service() once the consumer is back
+pub const REPLAY: usize = 256;
+
+const EXTENSION: &str = "spool";
+
+/// One spooled frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Unix seconds when it was spooled
+    pub time: f64,
+    pub rssi: Option<f64>,
+    pub rf_offset: Option<i64>,
+    pub data: Vec<u8>,
+    /// See Meta::crc_failed
+    pub crc_failed: bool,
+}
+
+impl Record {
+    pub fn new(data: &[u8], meta: &Meta) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            time: now.as_secs_f64(),
+            rssi: meta.rssi,
+            rf_offset: meta.rf_offset,
+            data: data.to_vec(),
+            crc_failed: meta.crc_failed,
+        }
+    }
+
+    fn line(&self) -> String {
+        let mut line = format!("{:.3}\t", self.time);
+        if let Some(rssi) = self.rssi {
+            write!(line, "{:.1}", rssi).unwrap();
+        }
+        line.push('\t');
+        if let Some(offset) = self.rf_offset {
+            write!(line, "{}", offset).unwrap();
+        }
+        line.push('\t');
+        for b in &self.data {
+            write!(line, "{:02X}", b).unwrap();
+        }
+        if self.crc_failed {
+            line.push_str("\tcrc_failed");
+        }
+        line.push('\n');
+        line
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let time = fields.next()?.parse().ok()?;
+        let rssi = optional(fields.next()?)?;
+        let rf_offset = optional(fields.next()?)?;
+        let hex = fields.next()?;
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        let data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<_>>()?;
+        let crc_failed = match fields.next() {
+            None => false,
+            Some("crc_failed") => true,
+            Some(_) => return None,
+        };
+        Some(Self {
+            time,
+            rssi,
+            rf_offset,
+            data,
+            crc_failed,
+        })
+    }
+}
+
+/// An empty field is None
+fn optional<T: std::str::FromStr>(field: &str) -> Option<Option<T>> {
+    match field {
+        "" => Some(None),
+        _ => field.parse().ok().map(Some),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    number: u64,
+    /// Not yet popped
+    frames: usize,
+    bytes: u64,
+}
+
+/// A bounded queue of Records in a directory
+pub struct Spool {
+    dir: PathBuf,
+    limit: u64,
+    segment: u64,
+    /// Oldest first, pushes go to the last
+    segments: VecDeque<Segment>,
+    out: Option<BufWriter<File>>,
+    /// What's left of the first segment once it's been read
+    head: Option<VecDeque<Record>>,
+    dropped: u64,
+}
+
+impl Spool {
+    /// Spools into `dir`, keeping it under `limit` bytes. Frames left there by an earlier run
+    /// come first.
+    pub fn open<P: AsRef<Path>>(dir: P, limit: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != EXTENSION) {
+                continue;
+            }
+            let Some(number) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok())
+            else {
+                continue;
+            };
+            let frames = BufReader::new(File::open(&path)?).lines().count();
+            let bytes = fs::metadata(&path)?.len();
+            segments.push(Segment {
+                number,
+                frames,
+                bytes,
+            });
+        }
+        segments.sort_by_key(|s| s.number);
+        Ok(Self {
+            dir,
+            limit,
+            segment: (limit / 4).clamp(1, SEGMENT),
+            segments: segments.into(),
+            out: None,
+            head: None,
+            dropped: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, number: u64) -> PathBuf {
+        self.dir.join(format!("{:010}.{}", number, EXTENSION))
+    }
+
+    /// Frames waiting
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.frames).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Frames deleted unsent to stay under the limit
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn bytes(&self) -> u64 {
+        self.segments.iter().map(|s| s.bytes).sum()
+    }
+
+    fn remove_first(&mut self) -> Result<()> {
+        if let Some(first) = self.segments.pop_front() {
+            self.head = None;
+            if self.segments.is_empty() {
+                self.out = None;
+            }
+            match fs::remove_file(self.path(first.number)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, record: &Record) -> Result<()> {
+        let full = self.segments.back().is_none_or(|s| s.bytes >= self.segment);
+        if self.out.is_none() || full {
+            let number = self.segments.back().map_or(0, |s| s.number + 1);
+            self.out = Some(BufWriter::new(File::create(self.path(number))?));
+            self.segments.push_back(Segment {
+                number,
+                frames: 0,
+                bytes: 0,
+            });
+        }
+        let line = record.line();
+        let out = self.out.as_mut().unwrap();
+        out.write_all(line.as_bytes())?;
+        out.flush()?;
+        let last = self.segments.back_mut().unwrap();
+        last.frames += 1;
+        last.bytes += line.len() as u64;
+
+        while self.bytes() > self.limit && self.segments.len() > 1 {
+            let frames = self.segments[0].frames;
+            self.dropped += frames as u64;
+            warn!(
+                "UPLINK spool over {} bytes, dropped {} frames",
+                self.limit, frames
+            );
+            self.remove_first()?;
+        }
+        Ok(())
+    }
+
+    /// The oldest frame, left in place until pop()
+    pub fn peek(&mut self) -> Result<Option<&Record>> {
+        while self.head.is_none() {
+            let Some(first) = self.segments.front().copied() else {
+                return Ok(None);
+            };
+            // Stop appending to it, anything new goes in the next segment
+            if self.segments.len() == 1 {
+                self.out = None;
+            }
+            let mut records = VecDeque::new();
+            for line in BufReader::new(File::open(self.path(first.number))?).lines() {
+                match Record::parse(&line?) {
+                    Some(record) => records.push_back(record),
+                    None => warn!("UPLINK spool segment {} has a bad line", first.number),
+                }
+            }
+            match records.is_empty() {
+                true => self.remove_first()?,
+                false => {
+                    self.segments[0].frames = records.len();
+                    self.head = Some(records);
+                }
+            }
+        }
+        Ok(self.head.as_ref().and_then(VecDeque::front))
+    }
+
+    /// Removes the oldest frame, deleting its segment once that's empty
+    pub fn pop(&mut self) -> Result<Option<Record>> {
+        if self.peek()?.is_none() {
+            return Ok(None);
+        }
+        let record = self.head.as_mut().and_then(VecDeque::pop_front);
+        self.segments[0].frames -= 1;
+        if self.segments[0].frames == 0 {
+            self.remove_first()?;
+        }
+        Ok(record)
+    }
+}
+
+/// The socket frames are forwarded on
+pub trait Uplink {
+    fn send(&self, data: &[u8]) -> Result<usize>;
+    fn take_error(&self) -> Result<Option<Error>>;
+}
+
+impl Uplink for std::net::UdpSocket {
+    fn send(&self, data: &[u8]) -> Result<usize> {
+        std::net::UdpSocket::send(self, data)
+    }
+
+    fn take_error(&self) -> Result<Option<Error>> {
+        std::net::UdpSocket::take_error(self)
+    }
+}
+
+impl Uplink for mio::net::UdpSocket {
+    fn send(&self, data: &[u8]) -> Result<usize> {
+        mio::net::UdpSocket::send(self, data)
+    }
+
+    fn take_error(&self) -> Result<Option<Error>> {
+        mio::net::UdpSocket::take_error(self)
+    }
+}
+
+fn refused(e: &Error) -> bool {
+    e.kind() == ErrorKind::ConnectionRefused
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Consumer {
+    Up,
+    Down,
+    /// The oldest spooled frame went out at the last service()
+    Probed,
+}
+
+/// Sends frames up the uplink, through a Spool while the consumer is down
+pub struct Forwarder<S> {
+    socket: S,
+    spool: Option<Spool>,
+    consumer: Consumer,
+}
+
+impl<S: Uplink> Forwarder<S> {
+    /// Without a spool frames are sent straight away and a refusal is an error, as before
+    pub fn new(socket: S, spool: Option<Spool>) -> Self {
+        let consumer = match spool {
+            Some(ref spool) if !spool.is_empty() => Consumer::Down,
+            _ => Consumer::Up,
+        };
+        Self {
+            socket,
+            spool,
+            consumer,
+        }
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn spool(&self) -> Option<&Spool> {
+        self.spool.as_ref()
+    }
+
+    pub fn send(&mut self, data: &[u8], meta: &Meta) -> Result<()> {
+        let Some(ref mut spool) = self.spool else {
+            return self.socket.send(data).map(|_| ());
+        };
+        if self.consumer == Consumer::Up && spool.is_empty() {
+            match self.socket.send(data) {
+                Err(e) if refused(&e) => {
+                    warn!("UPLINK unreachable, spooling to {}", spool.dir().display());
+                    self.consumer = Consumer::Down;
+                }
+                result => return result.map(|_| ()),
+            }
+        }
+        spool.push(&Record::new(data, meta))
+    }
+
+    /// Probes the consumer while it's down and replays the spool once it's back, call about
+    /// once a second
+    pub fn service(&mut self) -> Result<()> {
+        let Some(ref mut spool) = self.spool else {
+            return Ok(());
+        };
+        let refusal = match self.socket.take_error()? {
+            Some(e) if refused(&e) => true,
+            Some(e) => return Err(e),
+            None => false,
+        };
+        match (self.consumer, refusal) {
+            (Consumer::Up, true) => {
+                warn!("UPLINK unreachable, spooling to {}", spool.dir().display());
+                self.consumer = Consumer::Down;
+                return Ok(());
+            }
+            (Consumer::Probed, true) => {
+                self.consumer = Consumer::Down;
+                return Ok(());
+            }
+            (Consumer::Probed, false) => {
+                spool.pop()?;
+                info!("UPLINK back, replaying {} spooled frames", spool.len());
+                self.consumer = Consumer::Up;
+            }
+            (Consumer::Down, _) => {
+                self.consumer = match spool.peek()? {
+                    None => Consumer::Up,
+                    Some(record) => match self.socket.send(&record.data) {
+                        Err(e) if refused(&e) => Consumer::Down,
+                        Err(e) => return Err(e),
+                        Ok(_) => Consumer::Probed,
+                    },
+                };
+                return Ok(());
+            }
+            (Consumer::Up, false) => (),
+        }
+        for _ in 0..REPLAY {
+            let Some(record) = spool.peek()? else {
+                break;
+            };
+            match self.socket.send(&record.data) {
+                Err(e) if refused(&e) => {
+                    warn!("UPLINK unreachable again, {} frames spooled", spool.len());
+                    self.consumer = Consumer::Down;
+                    break;
+                }
+                Err(e) => return Err(e),
+                Ok(_) => _ = spool.pop()?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ax5043-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record(i: u8) -> Record {
+        Record {
+            time: 1_760_529_600.25,
+            rssi: i.is_multiple_of(2).then_some(-101.5),
+            rf_offset: Some(-800 + i64::from(i)),
+            data: vec![i; 10],
+            crc_failed: false,
+        }
+    }
+
+    #[test]
+    fn spool() {
+        let line = record(0).line();
+        assert_eq!(line, "1760529600.250\t-101.5\t-800\t00000000000000000000\n");
+        assert_eq!(Record::parse(line.trim_end()), Some(record(0)));
+        assert_eq!(Record::parse("1.0\t\t\tABC"), None);
+        let failed = Record {
+            crc_failed: true,
+            ..record(1)
+        };
+        let line = failed.line();
+        assert!(line.ends_with("01\tcrc_failed\n"));
+        assert_eq!(Record::parse(line.trim_end()), Some(failed));
+        assert_eq!(Record::parse("1.0\t\t\tAB\tok"), None);
+        let line = record(0).line();
+
+        // Segments of two lines, six in all
+        let dir = dir("spool");
+        let limit = 6 * line.len() as u64;
+        let mut spool = Spool::open(&dir, limit).unwrap();
+        for i in 0..5 {
+            spool.push(&record(i)).unwrap();
+        }
+        assert_eq!(spool.len(), 5);
+        assert_eq!(spool.pop().unwrap(), Some(record(0)));
+
+        // A restart picks up where it left off, the rest of a read segment comes again
+        drop(spool);
+        let mut spool = Spool::open(&dir, limit).unwrap();
+        assert_eq!(spool.len(), 5);
+        assert_eq!(spool.peek().unwrap(), Some(&record(0)));
+        for i in 5..9 {
+            spool.push(&record(i)).unwrap();
+        }
+        // Nine over six lines, the first two segments are gone
+        assert_eq!(spool.dropped(), 4);
+        let left: Vec<_> = std::iter::from_fn(|| spool.pop().unwrap()).collect();
+        assert_eq!(left, (4..9).map(record).collect::<Vec<_>>());
+        assert!(spool.is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Refuses the send after one the consumer wasn't up for, like a connected UDP socket
+    #[derive(Default)]
+    struct Socket {
+        up: Cell<bool>,
+        pending: Cell<bool>,
+        received: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl Uplink for Socket {
+        fn send(&self, data: &[u8]) -> Result<usize> {
+            if self.pending.take() {
+                return Err(ErrorKind::ConnectionRefused.into());
+            }
+            match self.up.get() {
+                true => self.received.borrow_mut().push(data.to_vec()),
+                false => self.pending.set(true),
+            }
+            Ok(data.len())
+        }
+
+        fn take_error(&self) -> Result<Option<Error>> {
+            Ok(self
+                .pending
+                .take()
+                .then(|| ErrorKind::ConnectionRefused.into()))
+        }
+    }
+
+    #[test]
+    fn forwarder() {
+        let dir = dir("forwarder");
+        let socket = Socket::default();
+        socket.up.set(true);
+        let spool = Spool::open(&dir, 1 << 20).unwrap();
+        let mut uplink = Forwarder::new(socket, Some(spool));
+        let meta = Meta::default();
+        uplink.send(&[1], &meta).unwrap();
+
+        uplink.socket().up.set(false);
+        // Lost, the refusal only shows up on the next send
+        uplink.send(&[2], &meta).unwrap();
+        uplink.send(&[3], &meta).unwrap();
+        uplink.send(&[4], &meta).unwrap();
+        assert_eq!(uplink.spool().unwrap().len(), 2);
+        // The probe is refused
+        uplink.service().unwrap();
+        uplink.service().unwrap();
+        assert_eq!(uplink.consumer, Consumer::Down);
+
+        uplink.socket().up.set(true);
+        uplink.service().unwrap();
+        assert_eq!(uplink.consumer, Consumer::Probed);
+        uplink.send(&[5], &meta).unwrap();
+        uplink.service().unwrap();
+        assert_eq!(uplink.consumer, Consumer::Up);
+        assert!(uplink.spool().unwrap().is_empty());
+        uplink.send(&[6], &meta).unwrap();
+        assert_eq!(
+            *uplink.socket().received.borrow(),
+            [[1], [3], [4], [5], [6]]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+// Daemon state kept across restarts: packet totals and the last applied config file.
+//
+// Saved as TOML alongside the stats log interval and at shutdown. Writes go to a temporary file
+// that's synced and renamed over the old one, then the directory is synced, so a power cut
+// mid-write leaves either the previous state or the new one.
+use crate::rx::Stats;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    /// Totals since the state file was created
+    pub stats: Stats,
+    /// Contents of the last config file that was applied
+    pub config: String,
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("State file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid state file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Can't serialize state: {0}")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+impl State {
+    /// Ok(None) if there's no state file yet
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(toml::from_str(&contents)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(toml::to_string(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        // The rename itself is only durable once the directory entry is
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("ax5043-state-{}.toml", std::process::id()));
+        _ = fs::remove_file(&path);
+        assert!(State::load(&path).unwrap().is_none());
+
+        let state = State {
+            stats: Stats {
+                packets: 3,
+                bytes: 300,
+                crc_fail: 1,
+                ..Stats::default()
+            },
+            config: "[board]\nvco = \"Internal\"\n".into(),
+        };
+        state.save(&path).unwrap();
+        assert_eq!(State::load(&path).unwrap(), Some(state));
+
+        fs::write(&path, "stats = 1").unwrap();
+        assert!(matches!(State::load(&path), Err(Error::Parse(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
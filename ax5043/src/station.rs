@@ -0,0 +1,165 @@
+// Station file for driving several radios from one daemon (the station bin).
+//
+// Each [[radio]] names its hardware (spidev, IRQ and PA lines), its radio config file (see
+// config::Config) and the localhost ports it talks to. Receivers forward packets to `uplink`,
+// transceivers also transmit whatever arrives on `downlink`. See examples/station.toml.
+use crate::gpio::Pin;
+use serde::Deserialize;
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Receiver,
+    Transceiver,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Radio {
+    pub name: String,
+    pub role: Role,
+    pub spi: String,
+    pub irq: Pin,
+    /// PA enable, required for transceivers
+    pub pa: Option<Pin>,
+    /// TX inhibit line, nothing is keyed while it's asserted, see schedule::Inhibit
+    pub inhibit: Option<Pin>,
+    /// `inhibit` is asserted low, a switch to ground with a pull-up
+    #[serde(default)]
+    pub inhibit_low: bool,
+    /// Radio config file
+    pub config: String,
+    /// Index of the [[channel]] received on, and transmitted on unless tx_channel is set
+    #[serde(default)]
+    pub channel: usize,
+    /// Index of the [[channel]] transmitted on when the downlink differs from the uplink, see
+    /// config::Link
+    #[serde(default)]
+    pub tx_channel: Option<usize>,
+    /// Port received packets are sent to
+    pub uplink: u16,
+    /// Port frames to transmit arrive on, required for transceivers
+    pub downlink: Option<u16>,
+    /// For example 10.18.17.6:10035 or tcp://10.18.17.6:10035, see tui::CommState and
+    /// ax5043::telemetry
+    pub telemetry: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Station {
+    pub radio: Vec<Radio>,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("no [[radio]]")]
+    Empty,
+    #[error("{0} {1} used by more than one radio")]
+    Duplicate(&'static str, String),
+    #[error("transceiver {0} needs {1}")]
+    Missing(String, &'static str),
+    #[error("receiver {0} has no use for {1}")]
+    Unused(String, &'static str),
+}
+
+impl Station {
+    /// Catches what would otherwise fail halfway through bringing the radios up
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.radio.is_empty() {
+            return Err(Error::Empty);
+        }
+        let mut names = HashSet::new();
+        let mut spis = HashSet::new();
+        let mut pins = HashSet::new();
+        let mut ports = HashSet::new();
+        for radio in &self.radio {
+            if !names.insert(&radio.name) {
+                return Err(Error::Duplicate("name", radio.name.clone()));
+            }
+            if !spis.insert(&radio.spi) {
+                return Err(Error::Duplicate("spi", radio.spi.clone()));
+            }
+            let lines = [Some(&radio.irq), radio.pa.as_ref(), radio.inhibit.as_ref()];
+            for pin in lines.into_iter().flatten() {
+                if !pins.insert(pin) {
+                    return Err(Error::Duplicate("gpio", pin.to_string()));
+                }
+            }
+            if let Some(port) = radio.downlink {
+                if !ports.insert(port) {
+                    return Err(Error::Duplicate("downlink", port.to_string()));
+                }
+            }
+            if radio.role == Role::Receiver && radio.tx_channel.is_some() {
+                return Err(Error::Unused(radio.name.clone(), "tx_channel"));
+            }
+            if radio.role == Role::Receiver && radio.inhibit.is_some() {
+                return Err(Error::Unused(radio.name.clone(), "inhibit"));
+            }
+            if radio.role == Role::Transceiver {
+                if radio.pa.is_none() {
+                    return Err(Error::Missing(radio.name.clone(), "pa"));
+                }
+                if radio.downlink.is_none() {
+                    return Err(Error::Missing(radio.name.clone(), "downlink"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Station {
+        toml::from_str(include_str!("../examples/station.toml")).unwrap()
+    }
+
+    #[test]
+    fn example_valid() {
+        let station = example();
+        assert_eq!(station.validate(), Ok(()));
+        assert_eq!(station.radio[0].role, Role::Transceiver);
+        assert_eq!(station.radio[1].irq.to_string(), "/dev/gpiochip0:31");
+        assert_eq!(station.radio[1].downlink, None);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut station = example();
+        station.radio[1].irq = station.radio[0].irq.clone();
+        assert_eq!(
+            station.validate(),
+            Err(Error::Duplicate("gpio", "/dev/gpiochip0:30".into()))
+        );
+
+        let mut station = example();
+        station.radio[0].pa = None;
+        assert_eq!(station.validate(), Err(Error::Missing("uhf".into(), "pa")));
+
+        let mut station = example();
+        station.radio[1].tx_channel = Some(1);
+        assert_eq!(
+            station.validate(),
+            Err(Error::Unused("lband".into(), "tx_channel"))
+        );
+
+        let mut station = example();
+        station.radio[0].inhibit = station.radio[0].pa.clone();
+        assert_eq!(
+            station.validate(),
+            Err(Error::Duplicate("gpio", "/dev/gpiochip1:27".into()))
+        );
+        station.radio[0].inhibit = None;
+        station.radio[1].inhibit = Some("gpiochip0:5".parse().unwrap());
+        assert_eq!(
+            station.validate(),
+            Err(Error::Unused("lband".into(), "inhibit"))
+        );
+
+        assert_eq!(Station { radio: vec![] }.validate(), Err(Error::Empty));
+    }
+}
@@ -0,0 +1,279 @@
+// Keeps a history of the radio's supply trouble: brownouts and power-good going away.
+//
+// POWSTAT is the supplies as they are, POWSTICKYSTAT the same bits held low once they dropped
+// until it's read. Reading both once a second is enough to catch a dip that has long recovered
+// by the time anyone looks. The bins clear POWSTICKYSTAT once at startup and rx::start_with()
+// leaves it alone, but keying up has to read it to arm the BROWN_GATE: tx::Transmission keeps
+// that reading (Transmission::sticky()) for History::hold(), and the next sample() counts it
+// with its own. History::sample() reads them, History::record() takes values read elsewhere,
+// e.g. irq::Event::Power. Either returns what changed:
+// - Brownout: a BEVANA, BEVMODEM or VIO brownout detector dropped. These are the bits that
+//   gate the transmitter with BROWN_GATE, and the registers may no longer hold the config.
+// - PowerGoodLost: SUM, all supplies good, dropped
+// - PowerGoodRestored: SUM is back
+//
+// The bins sample between frames, send each event as CommState::SUPPLY, count them in the
+// statistics they log and with --brownout-reset reset and reconfigure the radio the same as the
+// watchdog does, once the supply is good again after a brownout or power-good loss (see
+// History::take_reset()). Only sample while the radio is powered up, the regulators are off in
+// POWERDOWN and DEEPSLEEP.
+use crate::{registers::*, Registers, Result, RX};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Events History keeps, the oldest go first
+pub const HISTORY: usize = 32;
+
+/// The brownout detectors, low in POWSTICKYSTAT after a brownout
+pub const BROWNOUT: PowStat = PowStat::BEVANA.union(PowStat::BEVMODEM).union(PowStat::VIO);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Kind {
+    /// These detectors dropped
+    Brownout(PowStat),
+    PowerGoodLost,
+    PowerGoodRestored,
+}
+
+/// "BEVMODEM | VIO"
+fn names(flags: PowStat) -> String {
+    let names: Vec<_> = flags.iter_names().map(|(name, _)| name).collect();
+    names.join(" | ")
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Brownout(dropped) => write!(f, "brownout on {}", names(*dropped)),
+            Kind::PowerGoodLost => write!(f, "power good lost"),
+            Kind::PowerGoodRestored => write!(f, "power good restored"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    /// When it was read, it happened at most a sample interval before
+    pub at: SystemTime,
+    pub kind: Kind,
+    /// POWSTICKYSTAT
+    pub sticky: PowStat,
+    /// POWSTAT
+    pub now: PowStat,
+}
+
+impl fmt::Display for Event {
+    /// "brownout on VIO at 1760529600.250, POWSTAT SUM | REF"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = self.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "{} at {:.3}, POWSTAT {}",
+            self.kind,
+            at.as_secs_f64(),
+            names(self.now)
+        )
+    }
+}
+
+/// Events since the start, kept with the packet statistics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Counts {
+    pub brownouts: u64,
+    pub power_good_lost: u64,
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "brownouts {} power lost {}",
+            self.brownouts, self.power_good_lost
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    events: VecDeque<Event>,
+    counts: Counts,
+    /// SUM as last seen
+    good: bool,
+    /// The first event since take_reset() that may have cost the radio its config
+    reset: Option<Kind>,
+    /// POWSTICKYSTAT read elsewhere since the last sample(), see hold()
+    #[serde(skip)]
+    held: Option<PowStat>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            events: VecDeque::with_capacity(HISTORY),
+            counts: Counts::default(),
+            good: true,
+            reset: None,
+            held: None,
+        }
+    }
+}
+
+impl History {
+    /// The last HISTORY events, oldest first
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter()
+    }
+
+    pub fn counts(&self) -> Counts {
+        self.counts
+    }
+
+    /// Why the radio should be reconfigured, once the supply is good again
+    pub fn take_reset(&mut self) -> Option<Kind> {
+        match self.good {
+            true => self.reset.take(),
+            false => None,
+        }
+    }
+
+    /// Keeps a POWSTICKYSTAT read elsewhere for the next sample(), e.g. on keying up, see
+    /// tx::Transmission::sticky()
+    pub fn hold(&mut self, sticky: PowStat) {
+        self.held = Some(self.held.map_or(sticky, |held| held & sticky));
+    }
+
+    /// Reads POWSTICKYSTAT, which clears it, with anything held, and POWSTAT
+    pub fn sample(&mut self, radio: &mut Registers, at: SystemTime) -> Result<Vec<Event>> {
+        let sticky = radio.POWSTICKYSTAT().read()? & self.held.take().unwrap_or(PowStat::all());
+        let now = radio.POWSTAT().read()?;
+        Ok(self.record(sticky, now, at))
+    }
+
+    /// The new events in POWSTICKYSTAT and POWSTAT read at `at`
+    pub fn record(&mut self, sticky: PowStat, now: PowStat, at: SystemTime) -> Vec<Event> {
+        let mut kinds = Vec::new();
+        let dropped = BROWNOUT.difference(sticky);
+        if !dropped.is_empty() {
+            kinds.push(Kind::Brownout(dropped));
+        }
+        // A dip shorter than the interval shows in the sticky bit only
+        if self.good && !(sticky & now).contains(PowStat::SUM) {
+            kinds.push(Kind::PowerGoodLost);
+            self.good = false;
+        }
+        if !self.good && now.contains(PowStat::SUM) {
+            kinds.push(Kind::PowerGoodRestored);
+            self.good = true;
+        }
+        let events: Vec<_> = kinds
+            .into_iter()
+            .map(|kind| Event {
+                at,
+                kind,
+                sticky,
+                now,
+            })
+            .collect();
+        for event in &events {
+            if event.kind != Kind::PowerGoodRestored {
+                self.reset.get_or_insert(event.kind);
+            }
+            self.push(*event);
+        }
+        events
+    }
+
+    /// Keeps an event from elsewhere, e.g. CommState::SUPPLY on the tui side
+    pub fn push(&mut self, event: Event) {
+        match event.kind {
+            Kind::Brownout(_) => self.counts.brownouts += 1,
+            Kind::PowerGoodLost => self.counts.power_good_lost += 1,
+            Kind::PowerGoodRestored => (),
+        }
+        if self.events.len() == HISTORY {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn history() {
+        let at = |s: u64| UNIX_EPOCH + Duration::from_secs(1_760_529_600 + s);
+        let good = PowStat::all();
+        let mut history = History::default();
+        assert!(history.record(good, good, at(0)).is_empty());
+
+        // VIO dipped between samples, POWSTAT has long recovered
+        let events = history.record(good - PowStat::VIO - PowStat::SUM, good, at(1));
+        let kinds: Vec<_> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                Kind::Brownout(PowStat::VIO),
+                Kind::PowerGoodLost,
+                Kind::PowerGoodRestored
+            ]
+        );
+        assert_eq!(
+            events[0].to_string(),
+            "brownout on VIO at 1760529601.000, POWSTAT SUM | REF | VREF | VANA | VMODEM | \
+             BEVANA | BEVMODEM | VIO"
+        );
+        assert_eq!(history.take_reset(), Some(Kind::Brownout(PowStat::VIO)));
+        assert_eq!(history.take_reset(), None);
+
+        // Down for a while, then back
+        let down = good - PowStat::SUM - PowStat::VANA;
+        assert_eq!(
+            history.record(down, down, at(2))[0].kind,
+            Kind::PowerGoodLost
+        );
+        assert!(history.record(down, down, at(3)).is_empty());
+        assert_eq!(history.take_reset(), None);
+        let back = history.record(down, good, at(4));
+        assert_eq!(back[0].kind, Kind::PowerGoodRestored);
+        assert_eq!(history.take_reset(), Some(Kind::PowerGoodLost));
+
+        assert_eq!(
+            history.counts(),
+            Counts {
+                brownouts: 1,
+                power_good_lost: 2
+            }
+        );
+        assert_eq!(history.counts().to_string(), "brownouts 1 power lost 2");
+        assert_eq!(history.events().count(), 5);
+        for s in 0..HISTORY as u64 {
+            history.record(good - PowStat::BEVANA, good, at(10 + s));
+        }
+        assert_eq!(history.events().count(), HISTORY);
+        assert_eq!(history.events().next().unwrap().at, at(10));
+
+        // Reads the sticky register first, the sink has every supply down
+        let writes = crate::dry_run(|radio| {
+            let events = History::default().sample(radio, at(0))?;
+            assert_eq!(events[0].kind, Kind::Brownout(BROWNOUT));
+            Ok(())
+        })
+        .unwrap();
+        assert!(writes.names().is_empty());
+
+        // A brownout keying up read off before the sample is counted with it
+        let mut history = History::default();
+        history.hold(good - PowStat::BEVMODEM);
+        history.hold(good);
+        assert_eq!(history.held, Some(good - PowStat::BEVMODEM));
+        crate::dry_run(|radio| history.sample(radio, at(0)).map(drop)).unwrap();
+        assert_eq!(history.held, None);
+    }
+}
@@ -0,0 +1,317 @@
+// Where the daemons send their CommState stream (see tui.rs).
+//
+// By default it's UDP, one datagram per state and lost if nobody is listening. That also loses
+// the BOARD/CONFIG sent once at startup, so with a `tcp://host:port` destination the daemon
+// connects to the dashboard instead:
+// - each state is framed as u32 LE length, then the CBOR
+// - the connection is made in the background and retried every RETRY while it's down
+// - states are buffered (up to BUFFER bytes, newer ones dropped past that) until it's up
+// - take_resync() tells the daemon to resend BOARD/CONFIG after each new connection
+//
+// Commands from the tui's console only come back over UDP.
+use mio::net::TcpStream;
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{Error, ErrorKind, Result, Write},
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// Time between connection attempts
+pub const RETRY: Duration = Duration::from_secs(5);
+/// Bytes buffered while disconnected
+pub const BUFFER: usize = 1 << 20;
+
+/// Something a CommState can be sent to
+pub trait Sink {
+    fn send_state(&self, data: &[u8]) -> Result<()>;
+}
+
+impl Sink for UdpSocket {
+    fn send_state(&self, data: &[u8]) -> Result<()> {
+        self.send(data).map(|_| ())
+    }
+}
+
+/// Length prefixed, as sent over TCP
+pub fn frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + data.len());
+    frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// Splits a TCP stream back into states
+#[derive(Debug, Default)]
+pub struct Frames {
+    buf: Vec<u8>,
+}
+
+impl Frames {
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let len = u32::from_le_bytes(self.buf.get(..4)?.try_into().unwrap()) as usize;
+        let end = 4 + len;
+        if self.buf.len() < end {
+            return None;
+        }
+        let data = self.buf[4..end].to_vec();
+        self.buf.drain(..end);
+        Some(data)
+    }
+}
+
+#[derive(Debug)]
+struct Connection {
+    stream: Option<TcpStream>,
+    /// The connect finished, it's started in the background
+    up: bool,
+    /// Next connection attempt
+    retry: Instant,
+    /// Frames waiting to go out, the first `sent` bytes of the front one already have
+    pending: VecDeque<Vec<u8>>,
+    sent: usize,
+    buffered: usize,
+    resync: bool,
+    dropped: u64,
+}
+
+#[derive(Debug)]
+pub struct TcpSink {
+    addr: SocketAddr,
+    conn: RefCell<Connection>,
+}
+
+impl TcpSink {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            conn: RefCell::new(Connection {
+                stream: None,
+                up: false,
+                retry: Instant::now(),
+                pending: VecDeque::new(),
+                sent: 0,
+                buffered: 0,
+                resync: false,
+                dropped: 0,
+            }),
+        }
+    }
+
+    /// States dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.conn.borrow().dropped
+    }
+
+    /// Connected and nothing waiting
+    pub fn is_idle(&self) -> bool {
+        let conn = self.conn.borrow();
+        conn.up && conn.pending.is_empty()
+    }
+
+    /// Writes what's buffered, connecting first if it's time to try again
+    pub fn flush(&self) -> Result<()> {
+        let mut conn = self.conn.borrow_mut();
+        let conn = &mut *conn;
+        if conn.stream.is_none() && Instant::now() >= conn.retry {
+            conn.retry = Instant::now() + RETRY;
+            conn.stream = TcpStream::connect(self.addr).ok();
+        }
+        let Some(ref mut stream) = conn.stream else {
+            return Ok(());
+        };
+
+        if !conn.up {
+            match (stream.take_error(), stream.peer_addr()) {
+                (Ok(None), Ok(_)) => {
+                    conn.up = true;
+                    conn.resync = true;
+                }
+                (Ok(None), Err(e)) if e.kind() == ErrorKind::NotConnected => return Ok(()),
+                _ => {
+                    conn.stream = None;
+                    return Ok(());
+                }
+            }
+        }
+
+        while let Some(front) = conn.pending.front() {
+            match stream.write(&front[conn.sent..]) {
+                Ok(n) => conn.sent += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    // Gone, the other end can't pick up halfway through a frame
+                    if conn.sent > 0 {
+                        conn.buffered -= front.len();
+                        conn.pending.pop_front();
+                        conn.sent = 0;
+                    }
+                    conn.stream = None;
+                    conn.up = false;
+                    return Ok(());
+                }
+            }
+            if conn.sent == front.len() {
+                conn.buffered -= front.len();
+                conn.pending.pop_front();
+                conn.sent = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// True once after each new connection, time to resend the one-shot states
+    pub fn take_resync(&self) -> bool {
+        std::mem::take(&mut self.conn.borrow_mut().resync)
+    }
+}
+
+impl Sink for TcpSink {
+    fn send_state(&self, data: &[u8]) -> Result<()> {
+        {
+            let mut conn = self.conn.borrow_mut();
+            let frame = frame(data);
+            if conn.buffered + frame.len() > BUFFER {
+                conn.dropped += 1;
+            } else {
+                conn.buffered += frame.len();
+                conn.pending.push_back(frame);
+            }
+        }
+        self.flush()
+    }
+}
+
+/// A daemon's --telemetry destination
+#[derive(Debug)]
+pub enum Telemetry {
+    Udp(UdpSocket),
+    Tcp(TcpSink),
+}
+
+impl Telemetry {
+    /// `host:port` for UDP, `tcp://host:port` for TCP. The UDP socket is nonblocking and
+    /// connected, so the tui's console commands can be read from it.
+    pub fn open(dest: &str) -> Result<Self> {
+        let (tcp, addr) = match dest.strip_prefix("tcp://") {
+            Some(addr) => (true, addr),
+            None => (false, dest),
+        };
+        let addr: SocketAddr = addr
+            .parse()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{}: {}", dest, e)))?;
+        if tcp {
+            return Ok(Telemetry::Tcp(TcpSink::new(addr)));
+        }
+        let any: SocketAddr = match addr {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = UdpSocket::bind(any)?;
+        socket.connect(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Telemetry::Udp(socket))
+    }
+
+    /// The UDP socket, None over TCP
+    pub fn udp(&self) -> Option<&UdpSocket> {
+        match self {
+            Telemetry::Udp(socket) => Some(socket),
+            Telemetry::Tcp(_) => None,
+        }
+    }
+
+    /// Retries the TCP connection and writes what's buffered, call it periodically
+    pub fn flush(&self) -> Result<()> {
+        match self {
+            Telemetry::Udp(_) => Ok(()),
+            Telemetry::Tcp(sink) => sink.flush(),
+        }
+    }
+
+    /// True once after each new TCP connection, see TcpSink::take_resync
+    pub fn take_resync(&self) -> bool {
+        match self {
+            Telemetry::Udp(_) => false,
+            Telemetry::Tcp(sink) => sink.take_resync(),
+        }
+    }
+}
+
+impl Sink for Telemetry {
+    fn send_state(&self, data: &[u8]) -> Result<()> {
+        match self {
+            Telemetry::Udp(socket) => socket.send_state(data),
+            Telemetry::Tcp(sink) => sink.send_state(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, net::TcpListener};
+
+    #[test]
+    fn frames() {
+        let mut frames = Frames::default();
+        let mut stream = frame(b"one");
+        stream.extend(frame(b""));
+        stream.extend(frame(b"three"));
+        frames.push(&stream[..5]);
+        assert_eq!(frames.next(), None);
+        frames.push(&stream[5..stream.len() - 1]);
+        assert_eq!(frames.next(), Some(b"one".to_vec()));
+        assert_eq!(frames.next(), Some(Vec::new()));
+        assert_eq!(frames.next(), None);
+        frames.push(&stream[stream.len() - 1..]);
+        assert_eq!(frames.next(), Some(b"three".to_vec()));
+    }
+
+    #[test]
+    fn tcp_buffers_until_connected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sink = TcpSink::new(listener.local_addr().unwrap());
+        sink.send_state(b"early").unwrap();
+        sink.send_state(b"later").unwrap();
+
+        let (mut peer, _) = listener.accept().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !sink.is_idle() {
+            assert!(Instant::now() < deadline, "never connected");
+            sink.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(sink.take_resync());
+        assert!(!sink.take_resync());
+
+        let mut expected = frame(b"early");
+        expected.extend(frame(b"later"));
+        let mut received = vec![0; expected.len()];
+        peer.read_exact(&mut received).unwrap();
+        assert_eq!(received, expected);
+        assert_eq!(sink.dropped(), 0);
+    }
+
+    #[test]
+    fn open() {
+        assert!(matches!(
+            Telemetry::open("127.0.0.1:10035"),
+            Ok(Telemetry::Udp(_))
+        ));
+        assert!(matches!(
+            Telemetry::open("tcp://127.0.0.1:10035"),
+            Ok(Telemetry::Tcp(_))
+        ));
+        assert!(Telemetry::open("tcp://nowhere").is_err());
+    }
+}
@@ -0,0 +1,205 @@
+// Carrier trim against the crystal's drift with temperature.
+//
+// A thermistor (or any sensor with a linear output) on GPADC13 gives the enclosure temperature,
+// the user supplied curve gives the crystal's error at that temperature. Trim::run() folds it
+// into Xtal::ppm_correction and rewrites FREQA/FREQB between packets. A few ppm stays well inside
+// the ranged VCO, so no autoranging. IFFREQ keeps the untrimmed value until the next full write,
+// at narrow channel spacing the carrier is what matters.
+use crate::{config, registers::*, Error, Registers, Result, RX};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sensor {
+    /// °C at an ADC count of 0
+    pub intercept: f64,
+    /// °C per ADC count
+    pub slope: f64,
+}
+
+impl Sensor {
+    pub fn celsius(&self, count: f64) -> f64 {
+        self.intercept + self.slope * count
+    }
+}
+
+fn default_hysteresis() -> f64 {
+    0.1
+}
+
+fn default_interval() -> u64 {
+    10
+}
+
+/// The [thermal] config section
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Compensation {
+    pub sensor: Sensor,
+    /// (°C, ppm) in rising temperature order, ppm positive when the crystal runs fast. Held flat
+    /// past either end.
+    pub curve: Vec<(f64, f64)>,
+    /// Smallest change worth rewriting the synthesizer for, ppm
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: f64,
+    /// Seconds between trims
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+impl Compensation {
+    pub fn validate(&self) -> Result<()> {
+        if self.curve.is_empty() {
+            return Err(Error::Thermal("empty curve"));
+        }
+        if self.curve.windows(2).any(|w| w[0].0 >= w[1].0) {
+            return Err(Error::Thermal("curve temperatures must rise"));
+        }
+        if self.interval == 0 {
+            return Err(Error::Thermal("interval must be positive"));
+        }
+        Ok(())
+    }
+
+    /// The crystal's error at `celsius`, interpolated between the two curve points around it
+    pub fn ppm(&self, celsius: f64) -> Option<f64> {
+        let first = self.curve.first()?;
+        let last = self.curve.last()?;
+        if celsius <= first.0 {
+            return Some(first.1);
+        }
+        if celsius >= last.0 {
+            return Some(last.1);
+        }
+        let upper = self.curve.iter().position(|p| p.0 >= celsius)?;
+        let (hi, lo) = (self.curve[upper], self.curve[upper - 1]);
+        let frac = (celsius - lo.0) / (hi.0 - lo.0);
+        Some(lo.1 + frac * (hi.1 - lo.1))
+    }
+
+    pub fn temperature(&self, radio: &mut Registers) -> Result<f64> {
+        Ok(self.sensor.celsius(f64::from(radio.sample_gpadc13()?)))
+    }
+}
+
+/// The trim on the radio, on top of the board's ppm_correction
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Trim {
+    applied: f64,
+}
+
+impl Trim {
+    pub fn applied(&self) -> f64 {
+        self.applied
+    }
+
+    /// After anything that wrote FREQA/FREQB from the config (reset, reload, retune)
+    pub fn clear(&mut self) {
+        self.applied = 0.0;
+    }
+
+    /// Samples the temperature and moves FREQA/FREQB if the curve has moved by at least the
+    /// hysteresis since the last trim. Only between packets: the radio has to be idle or
+    /// still looking for a preamble. Returns the new trim if it wrote one.
+    pub fn run(
+        &mut self,
+        compensation: &Compensation,
+        radio: &mut Registers,
+        synth: &config::Synthesizer,
+        board: &config::Board,
+    ) -> Result<Option<f64>> {
+        if !matches!(
+            radio.RADIOSTATE().read()?,
+            RadioState::IDLE | RadioState::POWERDOWN | RadioState::RX_PREAMBLE_1
+        ) {
+            return Ok(None);
+        }
+        let celsius = compensation.temperature(radio)?;
+        let ppm = compensation
+            .ppm(celsius)
+            .ok_or(Error::Thermal("empty curve"))?;
+        if (ppm - self.applied).abs() < compensation.hysteresis {
+            return Ok(None);
+        }
+
+        let mut trimmed = *board;
+        trimmed.xtal.ppm_correction += ppm;
+        synth.write_freq(radio, &trimmed)?;
+        self.applied = ppm;
+        Ok(Some(ppm))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compensation() -> Compensation {
+        Compensation {
+            sensor: Sensor {
+                intercept: 20.0,
+                slope: 0.1,
+            },
+            curve: vec![(-20.0, 4.0), (20.0, 0.0), (60.0, -6.0)],
+            hysteresis: 0.1,
+            interval: 10,
+        }
+    }
+
+    #[test]
+    fn curve_interpolates() {
+        let c = compensation();
+        assert!(c.validate().is_ok());
+        assert_eq!(c.ppm(-40.0), Some(4.0));
+        assert_eq!(c.ppm(0.0), Some(2.0));
+        assert_eq!(c.ppm(20.0), Some(0.0));
+        assert_eq!(c.ppm(40.0), Some(-3.0));
+        assert_eq!(c.ppm(85.0), Some(-6.0));
+
+        let unsorted = Compensation {
+            curve: vec![(20.0, 0.0), (-20.0, 4.0)],
+            ..compensation()
+        };
+        assert!(matches!(unsorted.validate(), Err(Error::Thermal(_))));
+        let empty = Compensation {
+            curve: vec![],
+            ..compensation()
+        };
+        assert_eq!(empty.ppm(20.0), None);
+        assert!(matches!(empty.validate(), Err(Error::Thermal(_))));
+    }
+
+    #[test]
+    fn trim_writes_only_the_carrier() {
+        let config: config::Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+        // The sink's ADC reads 0, 20 °C with this sensor
+        let c = Compensation {
+            curve: vec![(0.0, 2.0), (40.0, 2.0)],
+            ..compensation()
+        };
+        let mut trim = Trim::default();
+        let writes = crate::dry_run(|radio| {
+            assert_eq!(
+                trim.run(&c, radio, &config.synth, &config.board)?,
+                Some(2.0)
+            );
+            // Inside the hysteresis now
+            assert_eq!(trim.run(&c, radio, &config.synth, &config.board)?, None);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            writes.names(),
+            vec!["GPADCCTRL", "FREQA", "FREQB", "GPADCCTRL"]
+        );
+        assert_eq!(trim.applied(), 2.0);
+
+        // Same as a board whose crystal is 2 ppm fast
+        let mut board = config.board;
+        board.xtal.ppm_correction = 2.0;
+        let expected = crate::dry_run(|radio| config.synth.write_freq(radio, &board)).unwrap();
+        assert_eq!(writes.to("FREQA")[0].data, expected.to("FREQA")[0].data);
+
+        trim.clear();
+        assert_eq!(trim.applied(), 0.0);
+    }
+}
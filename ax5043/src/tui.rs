@@ -1,14 +1,29 @@
-use crate::{config, registers::*, Registers, Status, RX};
+use crate::{
+    config, demod::DemodSnapshot, registers::*, rx, spectrum, telemetry::Sink, tx, watchdog,
+    Registers, Status, RX,
+};
 use anyhow::Result;
 use bitflags::Flags;
 use ciborium;
 use ratatui::{
     prelude::*,
     style::Style,
-    widgets::{Block, Borders, Cell, Row, Table},
+    symbols,
+    widgets::{
+        Axis, Block, Borders, Cell, Chart, Dataset, Gauge, GraphType, Paragraph, Row, Table,
+    },
 };
 use serde::{Deserialize, Serialize};
-use std::{io::ErrorKind, net::UdpSocket};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    io::ErrorKind,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the CommState encoding. Bump it whenever a variant or anything it carries changes
+/// shape or meaning; adding a variant doesn't need it since those are matched by name.
+pub const PROTOCOL: u16 = 10;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CommState {
@@ -20,15 +35,46 @@ pub enum CommState {
     REGISTERS(StatusRegisters),
     BOARD(config::Board),
     CONFIG(Config),
+    STATS(rx::Stats),
+    /// The radio was reset and reconfigured, see watchdog.rs
+    WATCHDOG(watchdog::Reason),
+    /// One complete RSSI sweep, see spectrum::Sweep and Waterfall
+    SWEEP(Vec<spectrum::Reading>),
+    /// A received or rejected frame, see PacketLog
+    PACKET(Frame),
+    TXSTATE(TXState),
+    /// The sender's PROTOCOL, sent at startup and with every periodic update so a tui started
+    /// later still learns it. Keep this one as is so every version can read it.
+    HELLO(u16),
+    /// Demodulator tracking samples, see Tracker and Constellation
+    TRACKING(Vec<TrackSample>),
+    /// Which radio the sender drives ("UHF", a station [[radio]] name), sent with HELLO to
+    /// label the tui's tabs
+    RADIO(String),
+    /// A frame the packet controller aborted, see rx::PacketAssembler::report_aborts()
+    ABORT(rx::RxAbort),
+    /// A pass ended, see pass::Pass
+    PASS(crate::pass::Report),
+    /// RX moved to or from the fallback channel, see fallback::Monitor
+    FALLBACK(crate::fallback::Switch),
+    /// Supply trouble, see supply::History
+    SUPPLY(crate::supply::Event),
 }
 
 impl CommState {
-    pub fn send(&self, socket: &UdpSocket) -> Result<()> {
+    /// The same state as JSON for the WebSocket bridge, variant name as the only key:
+    /// `{"STATS": {"packets": 3, ...}}`
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn send<S: Sink + ?Sized>(&self, socket: &S) -> Result<()> {
         let mut buf = Vec::<u8>::new();
         ciborium::ser::into_writer(self, &mut buf)?;
-        if let Err(e) = socket.send(&buf) {
+        if let Err(e) = socket.send_state(&buf) {
             match e.kind() {
-                ErrorKind::ConnectionRefused => Ok(()),
+                // Nobody listening, or the socket's buffer is full and this one is dropped
+                ErrorKind::ConnectionRefused | ErrorKind::WouldBlock => Ok(()),
                 _ => Err(e),
             }?
         }
@@ -36,7 +82,66 @@ impl CommState {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The receiving end of the telemetry stream. Datagrams that don't decode (a variant or struct
+/// this side doesn't know) are counted and skipped instead of ending the session, and the
+/// sender's HELLO is kept so a mismatch can be shown instead of trusting the numbers.
+#[derive(Debug, Default)]
+pub struct Link {
+    /// PROTOCOL of the sender, None until its first HELLO
+    pub protocol: Option<u16>,
+    pub undecoded: u64,
+}
+
+impl Link {
+    pub fn decode(&mut self, buf: &[u8]) -> Option<CommState> {
+        match ciborium::de::from_reader(buf) {
+            Ok(state) => {
+                if let CommState::HELLO(protocol) = state {
+                    self.protocol = Some(protocol);
+                }
+                Some(state)
+            }
+            Err(_) => {
+                self.undecoded += 1;
+                None
+            }
+        }
+    }
+
+    /// Sender speaks a different protocol version than this build
+    pub fn mismatch(&self) -> bool {
+        self.protocol.is_some_and(|p| p != PROTOCOL)
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.protocol {
+            Some(p) if p != PROTOCOL => write!(f, "daemon protocol v{}, tui v{}", p, PROTOCOL)?,
+            Some(p) => write!(f, "protocol v{}", p)?,
+            None => write!(f, "protocol unknown")?,
+        }
+        if self.undecoded > 0 {
+            write!(f, ", {} undecodable", self.undecoded)?;
+        }
+        Ok(())
+    }
+}
+
+impl Widget for &Link {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let style = match self.mismatch() {
+            true => Style::default().fg(Color::Red),
+            false => Style::default(),
+        };
+        Paragraph::new(self.to_string())
+            .style(style)
+            .block(Block::default().borders(Borders::ALL).title("Telemetry"))
+            .render(area, buf);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StatusRegisters {
     pub ranginga: PLLRanging,
     pub pwrmode: PwrMode,
@@ -44,19 +149,90 @@ pub struct StatusRegisters {
     pub irq: IRQ,
     pub radio_event: RadioEvent,
     pub radio_state: RadioState,
+    pub fifo: FIFOState,
 }
 
 impl StatusRegisters {
+    /// Reads in bursts, these are sent every telemetry tick. POWSTICKYSTAT sits between PWRMODE
+    /// and the IRQ registers and clears on read, hence two.
     pub fn new(radio: &mut Registers) -> Result<Self> {
+        let ranginga = radio.PLLRANGINGA().read()?; // sticky lock bit ~ IRQPLLUNLIOCK, gate
+        let power = radio.read_burst(0x002, 2)?; // PWRMODE, POWSTAT
+        let status = radio.read_burst(0x00C, 0x11)?; // IRQREQUEST to RADIOSTATE
         Ok(Self {
-            ranginga: radio.PLLRANGINGA().read()?, // sticky lock bit ~ IRQPLLUNLIOCK, gate
-            pwrmode: radio.PWRMODE().read()?,
-            powstat: radio.POWSTAT().read()?,
-            irq: radio.IRQREQUEST().read()?,
-            radio_event: radio.RADIOEVENTREQ().read()?,
-            radio_state: radio.RADIOSTATE().read()?,
+            ranginga,
+            pwrmode: radio.PWRMODE().read_from(&power)?,
+            powstat: radio.POWSTAT().read_from(&power)?,
+            irq: radio.IRQREQUEST().read_from(&status)?,
+            radio_event: radio.RADIOEVENTREQ().read_from(&status)?,
+            radio_state: radio.RADIOSTATE().read_from(&status)?,
+            fifo: FIFOState::new(radio)?,
+        })
+    }
+}
+
+/// FIFO occupancy, to spot it filling up faster than it's drained on high rate passes
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct FIFOState {
+    pub stat: FIFOStat,
+    /// FIFOCOUNT, words waiting to be read
+    pub count: u16,
+    /// FIFOFREE, 0 while the radio is powered off
+    pub free: u16,
+}
+
+impl Default for FIFOState {
+    fn default() -> Self {
+        Self {
+            stat: FIFOStat::empty(),
+            count: 0,
+            free: 0,
+        }
+    }
+}
+
+impl FIFOState {
+    pub fn new(radio: &mut Registers) -> Result<Self> {
+        // FIFODATA between FIFOSTAT and FIFOCOUNT can't be in a burst
+        let stat = radio.FIFOSTAT().read()?;
+        let counts = radio.read_burst(0x02A, 4)?;
+        Ok(Self {
+            stat,
+            count: radio.FIFOCOUNT().read_from(&counts)?,
+            free: radio.FIFOFREE().read_from(&counts)?,
         })
     }
+
+    /// Fraction of the FIFO in use
+    pub fn ratio(&self) -> f64 {
+        let total = u32::from(self.count) + u32::from(self.free);
+        match total {
+            0 => 0.0,
+            _ => f64::from(self.count) / f64::from(total),
+        }
+    }
+
+    /// Occupancy gauge, red while the FIFO reports overflow. `overflows` is the running count
+    /// to show with it, rx::Stats::fifo_errors on the receive side.
+    pub fn gauge(&self, overflows: u64) -> Gauge<'static> {
+        let color = match self.stat.contains(FIFOStat::OVER) || self.ratio() > 0.75 {
+            true => Color::Red,
+            false => Color::Green,
+        };
+        Gauge::default()
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("FIFO, {} overflows", overflows)),
+            )
+            .gauge_style(Style::default().fg(color))
+            .ratio(self.ratio())
+            .label(format!(
+                "{}/{}",
+                self.count,
+                u32::from(self.count) + u32::from(self.free)
+            ))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -73,8 +249,26 @@ pub struct Config {
     pub channel: ChannelParameters,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Config {
+    pub fn new(radio: &mut Registers, board: &config::Board) -> Result<Self> {
+        Ok(Self {
+            txparams: TXParameters::new(radio, board)?,
+            rxparams: RXParams::new(radio, board)?,
+            set0: RXParameterSet::set0(radio)?,
+            set1: RXParameterSet::set1(radio)?,
+            set2: RXParameterSet::set2(radio)?,
+            set3: RXParameterSet::set3(radio)?,
+            synthesizer: Synthesizer::new(radio, board)?,
+            packet_controller: PacketController::new(radio)?,
+            packet_format: PacketFormat::new(radio)?,
+            channel: ChannelParameters::new(radio)?,
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct RXState {
+    /// dBm, see config::RssiCalibration
     pub rssi: f64,
     pub agccounter: f64,
     pub datarate: f64,
@@ -84,15 +278,20 @@ pub struct RXState {
     pub rffreq: f64,
     pub freq: f64,
     pub paramcurset: RxParamCurSet,
+    pub quality: ModulationQuality,
 }
 
 impl RXState {
-    pub fn new(radio: &mut Registers, channel: &config::ChannelParameters) -> Result<RXState> {
-        let signal = radio.SIGNALSTR().read()?;
-        let track = radio.RXTRACKING().read()?;
+    pub fn new(
+        radio: &mut Registers,
+        board: &config::Board,
+        channel: &config::ChannelParameters,
+    ) -> Result<RXState> {
+        let demod = DemodSnapshot::read(radio)?;
+        let (signal, track) = (demod.signal, demod.track);
 
         Ok(RXState {
-            rssi: f64::from(signal.rssi),
+            rssi: board.rssi.dbm(f64::from(signal.rssi)),
             agccounter: (f64::from(signal.agccounter) * 4.0) / 3.0,
             datarate: f64::from(track.datarate),
             ampl: f64::from(track.ampl),
@@ -101,10 +300,81 @@ impl RXState {
             rffreq: f64::from(track.rffreq.0),
             freq: f64::from(track.freq) * channel.datarate as f64 / 2f64.powf(16.0),
             paramcurset: radio.RXPARAMCURSET().read()?,
+            quality: ModulationQuality::new(&demod, channel),
         })
     }
 }
 
+/// The far end's transmitter as the demodulator sees it, for telling a transmitter set up with
+/// the wrong deviation or datarate apart from a weak link
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModulationQuality {
+    /// Measured FSK deviation, Hz. TRKFSKDEMOD is on the FREQDEV scale (PM Table 122), so this
+    /// is after the receive filter and reads low by kSF, around 0.8.
+    pub deviation: f64,
+    /// Far end datarate minus the channel's, bits/s. The tracking loop can't follow past
+    /// MAXDROFFSET, an error pinned there means the far end is further off still.
+    pub datarate_error: f64,
+}
+
+impl ModulationQuality {
+    pub fn new(demod: &DemodSnapshot, channel: &config::ChannelParameters) -> Self {
+        let datarate = channel.datarate as f64;
+        Self {
+            deviation: f64::from(demod.track.fskdemod.0).abs() * datarate / 2f64.powi(8),
+            datarate_error: demod.datarate_error(channel),
+        }
+    }
+}
+
+/// The periodic STATE samples from the last `window`, stamped on arrival, for plotting RSSI, AGC
+/// and frequency offset over a pass
+#[derive(Debug)]
+pub struct History {
+    window: Duration,
+    samples: VecDeque<(Instant, RXState)>,
+}
+
+impl History {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Adds a sample received at `at` and forgets the ones that fell out of the window
+    pub fn push(&mut self, at: Instant, state: RXState) {
+        self.samples.push_back((at, state));
+        while let Some((first, _)) = self.samples.front() {
+            if at.saturating_duration_since(*first) <= self.window {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> Option<&RXState> {
+        self.samples.back().map(|(_, state)| state)
+    }
+
+    /// (seconds before `now`, value) points, oldest first, ready for a Chart with x bounds
+    /// [-window, 0]
+    pub fn series(&self, now: Instant, value: impl Fn(&RXState) -> f64) -> Vec<(f64, f64)> {
+        self.samples
+            .iter()
+            .map(|(at, state)| {
+                let age = now.saturating_duration_since(*at).as_secs_f64();
+                (-age, value(state))
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct PacketFormat {
     addrcfg: PktAddrCfg,
@@ -119,10 +389,13 @@ impl Default for PacketFormat {
     fn default() -> Self {
         Self {
             addrcfg: PktAddrCfg {
-                addr_pos: 0,
+                addr_pos: U4::new(0),
                 flags: PktAddrCfgFlags::empty(),
             },
-            lencfg: PktLenCfg { pos: 0, bits: 0 },
+            lencfg: PktLenCfg {
+                pos: U4::new(0),
+                bits: U4::new(0),
+            },
             lenoffset: 0,
             maxlen: 0,
             addr: 0,
@@ -386,15 +659,15 @@ impl Default for Synthesizer {
                 flags: PLLVCODivFlags::empty(),
             },
             ranginga: PLLRanging {
-                vcor: 0,
+                vcor: U4::new(0),
                 flags: PLLRangingFlags::empty(),
             },
             rangingb: PLLRanging {
-                vcor: 0,
+                vcor: U4::new(0),
                 flags: PLLRangingFlags::empty(),
             },
             vcoi: PLLVCOI {
-                bias: 0,
+                bias: U6::new(0),
                 flags: PLLVCOIFlags::empty(),
             },
             vcoir: 0,
@@ -415,24 +688,27 @@ impl Default for Synthesizer {
 
 impl Synthesizer {
     pub fn new(radio: &mut Registers, board: &config::Board) -> Result<Synthesizer> {
+        let pll = radio.read_burst(0x030, 0x10)?; // PLLLOOP to FREQB
+        let vco = radio.read_burst(0x180, 4)?; // PLLVCOI to PLLRNGCLK
+        let tmg = radio.read_burst(0x220, 5)?; // TMGTXBOOST to TMGRXSETTLE
         Ok(Synthesizer {
-            pllloop: radio.PLLLOOP().read()?,
-            pllloopboost: radio.PLLLOOPBOOST().read()?,
-            cpi: radio.PLLCPI().read()?,
-            cpiboost: radio.PLLCPIBOOST().read()?,
-            vcodiv: radio.PLLVCODIV().read()?,
-            ranginga: radio.PLLRANGINGA().read()?,
-            rangingb: radio.PLLRANGINGB().read()?,
-            freqa: u64::from(radio.FREQA().read()?),
-            freqb: u64::from(radio.FREQB().read()?),
-            vcoi: radio.PLLVCOI().read()?,
-            vcoir: radio.PLLVCOIR().read()?,
-            lockdet: radio.PLLLOCKDET().read()?,
-            rngclk: radio.PLLRNGCLK().read()?,
-            txboost: radio.TMGTXBOOST().read()?,
-            txsettle: radio.TMGTXSETTLE().read()?,
-            rxboost: radio.TMGRXBOOST().read()?,
-            rxsettle: radio.TMGRXSETTLE().read()?,
+            pllloop: radio.PLLLOOP().read_from(&pll)?,
+            pllloopboost: radio.PLLLOOPBOOST().read_from(&pll)?,
+            cpi: radio.PLLCPI().read_from(&pll)?,
+            cpiboost: radio.PLLCPIBOOST().read_from(&pll)?,
+            vcodiv: radio.PLLVCODIV().read_from(&pll)?,
+            ranginga: radio.PLLRANGINGA().read_from(&pll)?,
+            rangingb: radio.PLLRANGINGB().read_from(&pll)?,
+            freqa: u64::from(radio.FREQA().read_from(&pll)?),
+            freqb: u64::from(radio.FREQB().read_from(&pll)?),
+            vcoi: radio.PLLVCOI().read_from(&vco)?,
+            vcoir: radio.PLLVCOIR().read_from(&vco)?,
+            lockdet: radio.PLLLOCKDET().read_from(&vco)?,
+            rngclk: radio.PLLRNGCLK().read_from(&vco)?,
+            txboost: radio.TMGTXBOOST().read_from(&tmg)?,
+            txsettle: radio.TMGTXSETTLE().read_from(&tmg)?,
+            rxboost: radio.TMGRXBOOST().read_from(&tmg)?,
+            rxsettle: radio.TMGRXSETTLE().read_from(&tmg)?,
             clk: board.xtal.freq,
         })
     }
@@ -634,35 +910,35 @@ impl Widget for Synthesizer {
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct RXParameterAGC {
-    attack: u8,
-    decay: u8,
+    attack: U4,
+    decay: U4,
     target: u8,
-    ahyst: u8,
-    min: u8,
-    max: u8,
+    ahyst: U3,
+    min: U3,
+    max: U3,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct RXParameterFreq {
-    phase: u8,
-    freq: u8,
+    phase: U5,
+    freq: U5,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct RXParameterGain {
     time: u8,
     rate: u8,
-    phase: u8,
-    filter: u8,
+    phase: U4,
+    filter: U2,
     baseband: RXParameterFreq,
     rf: RXParameterFreq,
-    amplitude: u8,
+    amplitude: U4,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct RXParameterBasebandOffset {
-    a: u8,
-    b: u8,
+    a: U4,
+    b: U4,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -670,7 +946,7 @@ pub struct RXParameterSet {
     agc: RXParameterAGC,
     gain: RXParameterGain,
     freq_dev: u16,
-    decay: u8,
+    decay: U4,
     baseband_offset: RXParameterBasebandOffset,
 }
 
@@ -697,7 +973,7 @@ impl RXParameterSet {
                 phase: phasegn.gain,
                 filter: phasegn.filter,
                 baseband: RXParameterFreq {
-                    phase: radio.FREQGAINA0().read()?.gain,
+                    phase: radio.FREQGAINA0().read()?.gain.widen(),
                     freq: radio.FREQGAINB0().read()?.gain,
                 },
                 rf: RXParameterFreq {
@@ -737,7 +1013,7 @@ impl RXParameterSet {
                 phase: phasegn.gain,
                 filter: phasegn.filter,
                 baseband: RXParameterFreq {
-                    phase: radio.FREQGAINA1().read()?.gain,
+                    phase: radio.FREQGAINA1().read()?.gain.widen(),
                     freq: radio.FREQGAINB1().read()?.gain,
                 },
                 rf: RXParameterFreq {
@@ -777,7 +1053,7 @@ impl RXParameterSet {
                 phase: phasegn.gain,
                 filter: phasegn.filter,
                 baseband: RXParameterFreq {
-                    phase: radio.FREQGAINA2().read()?.gain,
+                    phase: radio.FREQGAINA2().read()?.gain.widen(),
                     freq: radio.FREQGAINB2().read()?.gain,
                 },
                 rf: RXParameterFreq {
@@ -817,7 +1093,7 @@ impl RXParameterSet {
                 phase: phasegn.gain,
                 filter: phasegn.filter,
                 baseband: RXParameterFreq {
-                    phase: radio.FREQGAINA3().read()?.gain,
+                    phase: radio.FREQGAINA3().read()?.gain.widen(),
                     freq: radio.FREQGAINB3().read()?.gain,
                 },
                 rf: RXParameterFreq {
@@ -1217,6 +1493,84 @@ impl Widget for RadioState {
     }
 }
 
+/// What the transmit side is up to, sent on each telemetry tick and after every frame
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct TXState {
+    /// FIFOFREE, 0 while the radio is powered off
+    pub fifo_free: u16,
+    /// TXPWRCOEFFB relative to full scale, dB
+    pub power: f64,
+    pub radio_state: RadioState,
+    pub stats: tx::Stats,
+}
+
+impl TXState {
+    pub fn new(radio: &mut Registers, stats: &tx::Stats) -> Result<Self> {
+        let b = radio.TXPWRCOEFFB().read()?;
+        Ok(Self {
+            fifo_free: radio.FIFOFREE().read()?,
+            power: 20.0 * (f64::from(b) / f64::from(0xFFFu16)).log10(),
+            radio_state: radio.RADIOSTATE().read()?,
+            stats: *stats,
+        })
+    }
+}
+
+impl Default for TXState {
+    fn default() -> Self {
+        Self {
+            fifo_free: 0,
+            power: f64::NEG_INFINITY,
+            radio_state: RadioState::IDLE,
+            stats: tx::Stats::default(),
+        }
+    }
+}
+
+impl Widget for TXState {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let w = Table::new(
+            vec![
+                Row::new(vec![
+                    Cell::from("FIFO free"),
+                    Cell::from("Power"),
+                    Cell::from("State"),
+                    Cell::from("Queued"),
+                    Cell::from("Sent"),
+                    Cell::from("Forward"),
+                    Cell::from("VSWR"),
+                ]),
+                Row::new(vec![
+                    Cell::from(self.fifo_free.to_string()),
+                    Cell::from(format!("{:.1} dBFS", self.power)),
+                    Cell::from(format!("{:?}", self.radio_state)),
+                    Cell::from(self.stats.queued.to_string()),
+                    Cell::from(format!("{} ({} B)", self.stats.sent, self.stats.bytes)),
+                    Cell::from(match self.stats.rf {
+                        Some(rf) => format!("{:.1} dBm", rf.keyed.forward),
+                        None => "-".to_string(),
+                    }),
+                    Cell::from(match self.stats.rf {
+                        Some(rf) => format!("{:.2}", rf.keyed.vswr()),
+                        None => "-".to_string(),
+                    }),
+                ]),
+            ],
+            [
+                Constraint::Max(10),
+                Constraint::Max(12),
+                Constraint::Max(13),
+                Constraint::Max(8),
+                Constraint::Min(16),
+                Constraint::Max(10),
+                Constraint::Max(6),
+            ],
+        )
+        .block(Block::default().borders(Borders::ALL).title("TX State"));
+        Widget::render(w, area, buf);
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct TXParameters {
     pub modcfgf: ModCfgF,
@@ -1258,7 +1612,7 @@ impl Widget for ModCfgA {
         let shape = if self.flags.contains(ModCfgAFlags::AMPLSHAPE) {
             "Cos"
         } else {
-            "None"
+            "Hard"
         };
 
         let layout = Layout::default()
@@ -1429,3 +1783,843 @@ impl Default for ChannelParameters {
         }
     }
 }
+
+/// Rows kept by Waterfall, more than any terminal is tall
+const WATERFALL_DEPTH: usize = 256;
+
+/// Weakest to strongest
+const WATERFALL_PALETTE: [Color; 6] = [
+    Color::Black,
+    Color::Blue,
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::Red,
+];
+
+/// Scrolling spectrogram of RSSI sweeps, newest at the top. Columns span the sweep's frequency
+/// range, colors are scaled to the weakest and strongest mean RSSI in view.
+#[derive(Debug, Default)]
+pub struct Waterfall {
+    rows: VecDeque<Vec<spectrum::Reading>>,
+}
+
+impl Waterfall {
+    pub fn push(&mut self, sweep: Vec<spectrum::Reading>) {
+        if sweep.is_empty() {
+            return;
+        }
+        self.rows.push_front(sweep);
+        self.rows.truncate(WATERFALL_DEPTH);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Weakest and strongest mean RSSI in the first `rows` sweeps
+    fn range(&self, rows: usize) -> Option<(f64, f64)> {
+        let means = self.rows.iter().take(rows).flatten().map(|r| r.mean);
+        let min = means.clone().reduce(f64::min)?;
+        let max = means.reduce(f64::max)?;
+        Some((min, max))
+    }
+
+    fn color(mean: f64, (min, max): (f64, f64)) -> Color {
+        let last = WATERFALL_PALETTE.len() - 1;
+        let level = match max > min {
+            true => ((mean - min) / (max - min) * last as f64).round() as usize,
+            false => 0,
+        };
+        WATERFALL_PALETTE[level.min(last)]
+    }
+}
+
+impl Widget for &Waterfall {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default().borders(Borders::ALL);
+        let inner = block.inner(area);
+        let rows = usize::from(inner.height);
+        let title = match (self.rows.front(), self.range(rows)) {
+            (Some(sweep), Some((min, max))) => format!(
+                "Waterfall {} - {} Hz, {:.0} - {:.0} dBm",
+                sweep[0].freq,
+                sweep[sweep.len() - 1].freq,
+                min,
+                max
+            ),
+            _ => "Waterfall (no sweeps)".to_string(),
+        };
+        block.title(title).render(area, buf);
+
+        let Some(range) = self.range(rows) else {
+            return;
+        };
+        let width = usize::from(inner.width);
+        for (y, sweep) in (inner.top()..inner.bottom()).zip(&self.rows) {
+            for (x, column) in (inner.left()..inner.right()).zip(0..width) {
+                let reading = &sweep[column * sweep.len() / width];
+                buf.get_mut(x, y)
+                    .set_char('█')
+                    .set_fg(Waterfall::color(reading.mean, range));
+            }
+        }
+    }
+}
+
+/// Seconds averaged into each OffsetTrack point
+pub const OFFSET_STEP: f64 = 1.0;
+/// OffsetTrack points kept, two hours at OFFSET_STEP
+pub const OFFSET_POINTS: usize = 7200;
+
+/// RF frequency offset (TRKRFFREQ) across a whole pass, one averaged point per OFFSET_STEP.
+/// Residual Doppler shows as the S curve around closest approach, TCXO drift as a slope
+/// underneath it.
+#[derive(Debug, Default)]
+pub struct OffsetTrack {
+    start: Option<Instant>,
+    /// (seconds since start, Hz)
+    points: VecDeque<(f64, f64)>,
+    /// Step the samples being averaged belong to, their sum and count
+    bucket: (u64, f64, u32),
+}
+
+impl OffsetTrack {
+    /// Converts RXState::rffreq (raw TRKRFFREQ) to Hz
+    pub fn hz(rffreq: f64, board: &config::Board) -> f64 {
+        rffreq * board.xtal.freq as f64 / 2f64.powi(24)
+    }
+
+    pub fn push(&mut self, at: Instant, hz: f64) {
+        let start = *self.start.get_or_insert(at);
+        let step = (at.saturating_duration_since(start).as_secs_f64() / OFFSET_STEP) as u64;
+        let (current, sum, count) = self.bucket;
+        if step != current && count > 0 {
+            self.points
+                .push_back((current as f64 * OFFSET_STEP, sum / f64::from(count)));
+            if self.points.len() > OFFSET_POINTS {
+                self.points.pop_front();
+            }
+            self.bucket = (step, 0.0, 0);
+        }
+        self.bucket.0 = step;
+        self.bucket.1 += hz;
+        self.bucket.2 += 1;
+    }
+
+    /// Starts over, for the next pass
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Least squares slope over the kept points, Hz/s
+    pub fn drift(&self) -> Option<f64> {
+        slope(self.points.iter())
+    }
+}
+
+/// Least squares slope through (x, y) points, None with fewer than two distinct x
+pub fn slope<'a>(points: impl Iterator<Item = &'a (f64, f64)> + Clone) -> Option<f64> {
+    let n = points.clone().count() as f64;
+    if n < 2.0 {
+        return None;
+    }
+    let (sx, sy) = points
+        .clone()
+        .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+    let (mx, my) = (sx / n, sy / n);
+    let (sxy, sxx) = points.fold((0.0, 0.0), |(sxy, sxx), (x, y)| {
+        (sxy + (x - mx) * (y - my), sxx + (x - mx) * (x - mx))
+    });
+    match sxx > 0.0 {
+        true => Some(sxy / sxx),
+        false => None,
+    }
+}
+
+impl Widget for &OffsetTrack {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let points: Vec<(f64, f64)> = self.points.iter().copied().collect();
+        let (min, max) = points.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| {
+            (lo.min(p.1), hi.max(p.1))
+        });
+        let (low, high) = match points.is_empty() {
+            true => (-1.0, 1.0),
+            false => (min.floor() - 1.0, max.ceil() + 1.0),
+        };
+        let end = points.last().map_or(OFFSET_STEP, |p| p.0.max(OFFSET_STEP));
+        let title = match (points.last(), self.drift()) {
+            (Some(last), Some(drift)) => format!(
+                "RF offset {:.0} Hz, range {:.0}..{:.0}, trend {:+.2} Hz/s ('r' restarts)",
+                last.1, min, max, drift
+            ),
+            _ => "RF offset (TRKRFFREQ), waiting for STATE".to_string(),
+        };
+        let set = vec![Dataset::default()
+            .graph_type(GraphType::Line)
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&points)];
+        Chart::new(set)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .x_axis(
+                Axis::default()
+                    .bounds([0.0, end])
+                    .labels(vec!["0".into(), format!("{:.1} min", end / 60.0).into()]),
+            )
+            .y_axis(Axis::default().bounds([low, high]).labels(vec![
+                format!("{:.0}", low).into(),
+                format!("{:.0}", high).into(),
+            ]))
+            .render(area, buf);
+    }
+}
+
+/// Samples per CommState::TRACKING
+pub const TRACKING_BATCH: usize = 40;
+/// Samples kept by Constellation
+pub const CONSTELLATION_DEPTH: usize = 400;
+
+/// One TRKAMPL/TRKPHASE reading
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackSample {
+    pub ampl: u16,
+    /// TRKPHASE, signed 12 bit with full scale at ±π
+    pub phase: i16,
+}
+
+impl TrackSample {
+    pub fn new(radio: &mut Registers) -> Result<Self> {
+        Ok(Self {
+            ampl: radio.TRKAMPL().read()?,
+            phase: radio.TRKPHASE().read()?.0,
+        })
+    }
+
+    /// Where it lands on the scatter: amplitude as the radius, phase as the angle
+    pub fn point(&self) -> (f64, f64) {
+        let angle = f64::from(self.phase) * std::f64::consts::PI / 2048.0;
+        let ampl = f64::from(self.ampl);
+        (ampl * angle.cos(), ampl * angle.sin())
+    }
+}
+
+/// Daemon side of the constellation view: samples the tracking registers on every `decimate`th
+/// telemetry tick and hands them out TRACKING_BATCH at a time
+#[derive(Debug)]
+pub struct Tracker {
+    decimate: u32,
+    ticks: u32,
+    samples: Vec<TrackSample>,
+}
+
+impl Tracker {
+    /// `decimate` 0 never samples
+    pub fn new(decimate: u32) -> Self {
+        Self {
+            decimate,
+            ticks: 0,
+            samples: Vec::with_capacity(TRACKING_BATCH),
+        }
+    }
+
+    /// Call on every telemetry tick, Some once a batch is full
+    pub fn tick(&mut self, radio: &mut Registers) -> Result<Option<Vec<TrackSample>>> {
+        if self.decimate == 0 {
+            return Ok(None);
+        }
+        self.ticks += 1;
+        if self.ticks < self.decimate {
+            return Ok(None);
+        }
+        self.ticks = 0;
+        self.samples.push(TrackSample::new(radio)?);
+        if self.samples.len() < TRACKING_BATCH {
+            return Ok(None);
+        }
+        Ok(Some(std::mem::take(&mut self.samples)))
+    }
+}
+
+/// Daemon side of the per tick STATE, REGISTERS and TXSTATE: each tick refreshes one of them,
+/// so a tick costs a couple of SPI transactions rather than all of them back to back. The bins
+/// skip the refresh altogether while the radio IRQ is pending, the FIFO goes first and the
+/// telemetry waits a tick. The latest of each is kept for anything else that wants it.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    next: usize,
+    pub state: Option<RXState>,
+    pub registers: Option<StatusRegisters>,
+    pub tx: Option<TXState>,
+}
+
+impl Snapshot {
+    /// Reads the next part and returns it to send. `tx` is None on radios that never transmit,
+    /// which leaves TXSTATE out of the rotation.
+    pub fn step(
+        &mut self,
+        radio: &mut Registers,
+        board: &config::Board,
+        channel: &config::ChannelParameters,
+        tx: Option<&tx::Stats>,
+    ) -> Result<CommState> {
+        let parts = if tx.is_some() { 3 } else { 2 };
+        let part = self.next % parts;
+        self.next = (part + 1) % parts;
+        Ok(match (part, tx) {
+            (0, _) => CommState::STATE(
+                self.state
+                    .insert(RXState::new(radio, board, channel)?)
+                    .clone(),
+            ),
+            (2, Some(stats)) => CommState::TXSTATE(*self.tx.insert(TXState::new(radio, stats)?)),
+            _ => CommState::REGISTERS(self.registers.insert(StatusRegisters::new(radio)?).clone()),
+        })
+    }
+}
+
+/// Scatter of the latest tracking samples. A clean signal gives tight clusters at a steady
+/// amplitude, noise and interference smear them out.
+#[derive(Debug, Default)]
+pub struct Constellation {
+    samples: VecDeque<TrackSample>,
+}
+
+impl Constellation {
+    pub fn push(&mut self, batch: Vec<TrackSample>) {
+        self.samples.extend(batch);
+        while self.samples.len() > CONSTELLATION_DEPTH {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+impl Widget for &Constellation {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let points: Vec<(f64, f64)> = self.samples.iter().map(TrackSample::point).collect();
+        // Square and centered, scaled to the strongest sample in view
+        let max = self
+            .samples
+            .iter()
+            .map(|s| f64::from(s.ampl))
+            .fold(1.0, f64::max);
+        let bounds = [-max, max];
+        let labels = || vec![format!("{:.0}", -max).into(), format!("{:.0}", max).into()];
+        let set = vec![Dataset::default()
+            .graph_type(GraphType::Scatter)
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&points)];
+        Chart::new(set)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "Demodulator, last {} samples (TRKAMPL at TRKPHASE)",
+                self.samples.len()
+            )))
+            .x_axis(Axis::default().bounds(bounds).labels(labels()))
+            .y_axis(Axis::default().bounds(bounds).labels(labels()))
+            .render(area, buf);
+    }
+}
+
+/// A frame as the daemon saw it, for PacketLog
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub time: SystemTime,
+    /// RSSI when the FIFO was drained, dBm, see config::RssiCalibration
+    pub rssi: Option<f64>,
+    /// None if it was passed on, otherwise why it was dropped
+    pub rejected: Option<rx::Reason>,
+    /// Passed on though it failed the CRC, see capture::Meta::crc_failed
+    #[serde(default)]
+    pub crc_failed: bool,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(data: Vec<u8>, rssi: Option<f64>, rejected: Option<rx::Reason>) -> Self {
+        Self {
+            time: SystemTime::now(),
+            rssi,
+            rejected,
+            crc_failed: false,
+            data,
+        }
+    }
+
+    /// A header line (UTC time, RSSI, length, ok, CRC failed or the reason) then a 16 byte per line hex and
+    /// ASCII dump
+    pub fn lines(&self) -> Vec<String> {
+        let since = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since.as_secs() % 86400;
+        let mut header = format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            since.subsec_millis()
+        );
+        match self.rssi {
+            Some(rssi) => write!(header, " {:6.1} dBm", rssi).unwrap(),
+            None => header.push_str("      ? dBm"),
+        }
+        write!(header, " {:4} B ", self.data.len()).unwrap();
+        match self.rejected {
+            Some(ref reason) => write!(header, "REJECTED {}", reason).unwrap(),
+            None if self.crc_failed => header.push_str("CRC FAILED"),
+            None => header.push_str("ok"),
+        }
+
+        let mut lines = vec![header];
+        for (i, row) in self.data.chunks(16).enumerate() {
+            let mut line = format!("  {:04x} ", i * 16);
+            for b in row {
+                write!(line, " {:02x}", b).unwrap();
+            }
+            line.push_str(&"   ".repeat(16 - row.len()));
+            line.push_str("  |");
+            line.extend(
+                row.iter()
+                    .map(|&b| match b.is_ascii_graphic() || b == b' ' {
+                        true => char::from(b),
+                        false => '.',
+                    }),
+            );
+            line.push('|');
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+/// Frames kept by PacketLog
+const PACKET_LOG_DEPTH: usize = 500;
+
+/// Scrollable log of frames, newest at the top
+#[derive(Debug, Default)]
+pub struct PacketLog {
+    frames: VecDeque<Frame>,
+    /// Lines scrolled down from the newest frame
+    scroll: usize,
+}
+
+impl PacketLog {
+    pub fn push(&mut self, frame: Frame) {
+        // Keep what's on screen in place if the user scrolled away from the top
+        if self.scroll > 0 {
+            self.scroll += frame.lines().len();
+        }
+        self.frames.push_front(frame);
+        self.frames.truncate(PACKET_LOG_DEPTH);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Positive scrolls towards older frames
+    pub fn scroll(&mut self, lines: isize) {
+        let total: usize = self.frames.iter().map(|f| f.lines().len()).sum();
+        self.scroll = self
+            .scroll
+            .saturating_add_signed(lines)
+            .min(total.saturating_sub(1));
+    }
+}
+
+impl Widget for &PacketLog {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rejected = self.frames.iter().filter(|f| f.rejected.is_some()).count();
+        let lines: Vec<Line> = self
+            .frames
+            .iter()
+            .flat_map(|frame| {
+                let style = match frame.rejected {
+                    Some(_) => Style::default().fg(Color::Red),
+                    None if frame.crc_failed => Style::default().fg(Color::Magenta),
+                    None => Style::default().fg(Color::Yellow),
+                };
+                frame
+                    .lines()
+                    .into_iter()
+                    .map(move |l| Line::styled(l, style))
+            })
+            .collect();
+        Paragraph::new(lines)
+            .scroll((self.scroll.min(u16::MAX.into()) as u16, 0))
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "Packets {} ({} rejected), up/down to scroll",
+                self.frames.len(),
+                rejected
+            )))
+            .render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(rssi: f64) -> RXState {
+        RXState {
+            rssi,
+            agccounter: 0.0,
+            datarate: 0.0,
+            ampl: 0.0,
+            phase: 0.0,
+            fskdemod: 0.0,
+            rffreq: 0.0,
+            freq: 0.0,
+            paramcurset: RxParamCurSet {
+                index: 0,
+                number: RxParamSet::Set0,
+                special: 0,
+            },
+            quality: ModulationQuality::default(),
+        }
+    }
+
+    #[test]
+    fn modulation_quality() {
+        let config: config::Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+        let channel = &config.channel[0];
+        let datarate = channel.datarate as f64;
+        let demod = |datarate, fskdemod, rxdatarate| DemodSnapshot {
+            radio_state: RadioState::RX,
+            signal: SignalStr {
+                rssi: 0,
+                bgndrssi: 0,
+                diversity: Diversity::empty(),
+                agccounter: 0,
+            },
+            track: RXTracking {
+                datarate,
+                ampl: 0,
+                phase: TrkPhase(0),
+                rffreq: TrkRFFreq(0),
+                freq: 0,
+                fskdemod: TrkFSKDemod(fskdemod),
+                afskdemod: 0,
+            },
+            rxdatarate,
+        };
+
+        // MSK: FREQDEV = 2^6 * kSF, the deviation is a quarter of the datarate
+        let quality = ModulationQuality::new(&demod(0, -64, 0x1000), channel);
+        assert_eq!(quality.deviation, datarate / 4.0);
+        assert_eq!(quality.datarate_error, 0.0);
+
+        // Bits 1% longer than expected, the far end is 1% slow
+        let quality = ModulationQuality::new(&demod(40, 64, 4000), channel);
+        assert!((quality.datarate_error + datarate * 0.01).abs() < datarate * 1e-4);
+        assert_eq!(
+            ModulationQuality::new(&demod(40, 0, 0), channel).datarate_error,
+            0.0
+        );
+    }
+
+    #[test]
+    fn offset_track() {
+        let start = Instant::now();
+        let mut track = OffsetTrack::default();
+        assert_eq!(track.drift(), None);
+        // 4 samples a second, offset climbing 2 Hz/s with +-1 Hz jitter
+        for i in 0..40 {
+            let t = f64::from(i) * 0.25;
+            let jitter = if i % 4 < 2 { 1.0 } else { -1.0 };
+            track.push(start + Duration::from_secs_f64(t), 100.0 + 2.0 * t + jitter);
+        }
+        // The last second is still being averaged
+        assert_eq!(track.points.len(), 9);
+        assert_eq!(track.points[0], (0.0, 100.75));
+        assert!((track.drift().unwrap() - 2.0).abs() < 1e-9);
+
+        track.clear();
+        assert!(track.is_empty());
+    }
+
+    #[test]
+    fn tracker() {
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(crate::Bus::Sink, &mut callback);
+        let mut tracker = Tracker::new(2);
+        let batches = (0..4 * TRACKING_BATCH)
+            .filter_map(|_| tracker.tick(&mut radio).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(batches.len(), 2);
+        assert!(batches.iter().all(|b| b.len() == TRACKING_BATCH));
+
+        let mut off = Tracker::new(0);
+        assert!(off.tick(&mut radio).unwrap().is_none());
+
+        let mut constellation = Constellation::default();
+        for batch in batches.into_iter().cycle().take(20) {
+            constellation.push(batch);
+        }
+        assert_eq!(constellation.samples.len(), CONSTELLATION_DEPTH);
+    }
+
+    #[test]
+    fn snapshot() {
+        let transfers = std::cell::Cell::new(0);
+        let mut callback = |_: &_, _, _, _: &_| transfers.set(transfers.get() + 1);
+        let mut radio = Registers::new(crate::Bus::Sink, &mut callback);
+        let config: config::Config =
+            toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+        let channel = &config.channel[0];
+        let stats = tx::Stats::default();
+
+        let mut snapshot = Snapshot::default();
+        let mut parts = Vec::new();
+        let mut most = 0;
+        for _ in 0..4 {
+            let before = transfers.get();
+            parts.push(
+                snapshot
+                    .step(&mut radio, &config.board, channel, Some(&stats))
+                    .unwrap(),
+            );
+            most = most.max(transfers.get() - before);
+        }
+        assert!(matches!(
+            parts[..],
+            [
+                CommState::STATE(_),
+                CommState::REGISTERS(_),
+                CommState::TXSTATE(_),
+                CommState::STATE(_)
+            ]
+        ));
+        // REGISTERS is the largest part, all three back to back took 11
+        assert!(most <= 5, "{} transfers in one step", most);
+        assert!(snapshot.state.is_some() && snapshot.registers.is_some());
+        assert!(snapshot.tx.is_some());
+
+        // Without TX the rotation is two long
+        let mut snapshot = Snapshot::default();
+        let parts: Vec<_> = (0..3)
+            .map(|_| {
+                snapshot
+                    .step(&mut radio, &config.board, channel, None)
+                    .unwrap()
+            })
+            .collect();
+        assert!(matches!(
+            parts[..],
+            [
+                CommState::STATE(_),
+                CommState::REGISTERS(_),
+                CommState::STATE(_)
+            ]
+        ));
+        assert!(snapshot.tx.is_none());
+    }
+
+    #[test]
+    fn track_point() {
+        let close = |(x, y): (f64, f64), (ex, ey): (f64, f64)| {
+            assert!((x - ex).abs() < 1e-9 && (y - ey).abs() < 1e-9, "{x} {y}");
+        };
+        close(
+            TrackSample {
+                ampl: 100,
+                phase: 0,
+            }
+            .point(),
+            (100.0, 0.0),
+        );
+        close(
+            TrackSample {
+                ampl: 100,
+                phase: 1024,
+            }
+            .point(),
+            (0.0, 100.0),
+        );
+        close(
+            TrackSample {
+                ampl: 50,
+                phase: -2048,
+            }
+            .point(),
+            (-50.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn fifo_ratio() {
+        let mut fifo = FIFOState::default();
+        assert_eq!(fifo.ratio(), 0.0);
+        fifo.count = 64;
+        fifo.free = 192;
+        assert_eq!(fifo.ratio(), 0.25);
+    }
+
+    #[test]
+    fn history() {
+        let start = Instant::now();
+        let mut history = History::new(Duration::from_secs(10));
+        assert!(history.latest().is_none());
+        for i in 0..20 {
+            history.push(start + Duration::from_secs(i), state(i as f64));
+        }
+        let now = start + Duration::from_secs(20);
+        assert_eq!(
+            history.series(now, |s| s.rssi),
+            (9..20)
+                .map(|i| (i as f64 - 20.0, i as f64))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(history.latest().unwrap().rssi, 19.0);
+    }
+
+    #[test]
+    fn json() {
+        let stats = rx::Stats {
+            packets: 3,
+            ..Default::default()
+        };
+        let json = CommState::STATS(stats).to_json().unwrap();
+        assert!(json.starts_with(r#"{"STATS":{"packets":3,"#), "{}", json);
+        assert_eq!(
+            CommState::HELLO(PROTOCOL).to_json().unwrap(),
+            r#"{"HELLO":10}"#
+        );
+        CommState::STATUS(Status::READY | Status::PLL_LOCK)
+            .to_json()
+            .unwrap();
+    }
+
+    #[test]
+    fn link() {
+        let encode = |state: &CommState| {
+            let mut buf = Vec::new();
+            ciborium::ser::into_writer(state, &mut buf).unwrap();
+            buf
+        };
+        let mut link = Link::default();
+        assert!(!link.mismatch());
+
+        let hello = encode(&CommState::HELLO(PROTOCOL));
+        assert!(matches!(link.decode(&hello), Some(CommState::HELLO(_))));
+        assert_eq!(link.protocol, Some(PROTOCOL));
+        assert!(!link.mismatch());
+
+        // A variant from a newer daemon is skipped, not fatal
+        #[derive(Serialize)]
+        enum Newer {
+            #[allow(clippy::upper_case_acronyms)]
+            FUTURE(u32),
+        }
+        let mut future = Vec::new();
+        ciborium::ser::into_writer(&Newer::FUTURE(7), &mut future).unwrap();
+        assert!(link.decode(&future).is_none());
+        assert!(link.decode(&[0xff, 0x00]).is_none());
+        assert_eq!(link.undecoded, 2);
+        assert!(link
+            .decode(&encode(&CommState::STATS(rx::Stats::default())))
+            .is_some());
+
+        link.decode(&encode(&CommState::HELLO(PROTOCOL + 1)));
+        assert!(link.mismatch());
+        assert_eq!(
+            link.to_string(),
+            format!(
+                "daemon protocol v{}, tui v{}, 2 undecodable",
+                PROTOCOL + 1,
+                PROTOCOL
+            )
+        );
+    }
+
+    fn sweep(means: &[f64]) -> Vec<spectrum::Reading> {
+        means
+            .iter()
+            .enumerate()
+            .map(|(i, &mean)| spectrum::Reading {
+                freq: 435_000_000 + i as u64 * 25_000,
+                min: mean,
+                max: mean,
+                mean,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn waterfall() {
+        let mut waterfall = Waterfall::default();
+        waterfall.push(vec![]);
+        assert!(waterfall.is_empty());
+        waterfall.push(sweep(&[-120.0, -100.0]));
+        waterfall.push(sweep(&[-100.0, -70.0]));
+
+        let area = Rect::new(0, 0, 6, 5);
+        let mut buf = Buffer::empty(area);
+        waterfall.render(area, &mut buf);
+        // Newest on top, each reading two columns wide
+        let fg = |x, y| buf.get(x, y).fg;
+        assert_eq!([fg(1, 1), fg(3, 1)], [Color::Cyan, Color::Red]);
+        assert_eq!([fg(1, 2), fg(3, 2)], [Color::Black, Color::Cyan]);
+        assert_eq!(buf.get(1, 3).symbol(), " ");
+    }
+
+    #[test]
+    fn frame_dump() {
+        let frame = Frame {
+            time: UNIX_EPOCH + std::time::Duration::from_millis(3_723_004),
+            rssi: Some(-92.0),
+            rejected: None,
+            crc_failed: false,
+            data: b"Hello, world!\x00\xff\x7fAB".to_vec(),
+        };
+        assert_eq!(
+            frame.lines(),
+            vec![
+                "01:02:03.004  -92.0 dBm   18 B ok",
+                "  0000  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 00 ff 7f  |Hello, world!...|",
+                "  0010  41 42                                            |AB|",
+            ]
+        );
+
+        let frame = Frame {
+            rssi: None,
+            rejected: Some(rx::Reason::Runt),
+            data: vec![1],
+            ..frame
+        };
+        assert_eq!(
+            frame.lines()[0],
+            "01:02:03.004      ? dBm    1 B REJECTED runt"
+        );
+        let frame = Frame {
+            rejected: None,
+            crc_failed: true,
+            ..frame
+        };
+        assert_eq!(
+            frame.lines()[0],
+            "01:02:03.004      ? dBm    1 B CRC FAILED"
+        );
+    }
+
+    #[test]
+    fn packet_log_scroll() {
+        let mut log = PacketLog::default();
+        let frame = Frame::new(vec![0; 20], None, None); // 3 lines
+        log.push(frame.clone());
+        log.scroll(-1);
+        assert_eq!(log.scroll, 0);
+        log.scroll(10);
+        assert_eq!(log.scroll, 2);
+        log.push(frame);
+        assert_eq!(log.scroll, 5);
+    }
+}
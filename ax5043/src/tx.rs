@@ -0,0 +1,662 @@
+// Packet transmission with PA sequencing, shared by the bins.
+//
+// The PA is switched through TXCTRL chunks in the FIFO so it's only on for the preamble, the
+// packet and the postamble. The caller has to have the PA enable GPIO active, see guard.rs.
+//
+// Pattern sends a carrier, 0101... or PN9 instead of a packet, raw and unencoded, for measuring
+// occupied bandwidth and spurious emissions, see the txtest bin.
+//
+// For slotted uplinks Staged loads the FIFO with only the synthesizer running (SYNTHTX) and
+// switches to TX when a timerfd fires, early by the PLL settling and preamble time so the packet
+// itself starts on the requested instant.
+use crate::{registers::*, Error, Registers, Result, RX, TX};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use timerfd::{SetTimeFlags, TimerFd, TimerState};
+use tracing::{error, trace};
+
+/// Preamble and postamble flags, in bytes, see transmit()
+pub const PREAMBLE: usize = 0x50;
+pub const POSTAMBLE: usize = 0x5;
+
+/// Transmit side counterpart to rx::Stats, reported over telemetry in tui::TXState
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Frames read from the sockets and waiting for their turn
+    pub queued: u64,
+    pub sent: u64,
+    /// Payload bytes in the sent frames
+    pub bytes: u64,
+    /// Around the last frame sent, with a power::PowerSensor
+    pub rf: Option<crate::power::Readings>,
+}
+
+/// Sends `buf` as one HDLC packet (the radio adds the CRC), blocking until the radio is back in
+/// IDLE. Leaves the radio in POWEROFF. Polls the radio throughout, see Transmission for
+/// sending from a poll loop instead.
+pub fn transmit(radio: &mut Registers, buf: &[u8]) -> Result<()> {
+    let mut transmission = Transmission::start(radio, buf)?;
+    while !transmission.service(radio)? {}
+    Ok(())
+}
+
+/// Sends `len` bytes of `pattern`, blocking like transmit()
+pub fn transmit_pattern(radio: &mut Registers, pattern: Pattern, len: usize) -> Result<()> {
+    let mut transmission = Transmission::pattern(radio, pattern, len)?;
+    while !transmission.service(radio)? {}
+    Ok(())
+}
+
+/// Test signals for the spectrum analyzer. These bypass the framing, encoding (scrambler,
+/// NRZI) and CRC, so what goes out is the modulation of exactly these bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// All ones. An unmodulated carrier with ASK, the upper tone with the FSK family.
+    Carrier,
+    /// 0101..., the modulation's narrowest spectrum with the strongest sidebands
+    Alternating,
+    /// The x^9 + x^5 + 1 pseudorandom sequence, standing in for traffic for occupied bandwidth
+    PN9,
+}
+
+impl Pattern {
+    /// The first `len` bytes, sent MSB first
+    pub fn bytes(self, len: usize) -> Vec<u8> {
+        match self {
+            Pattern::Carrier => vec![0xFF; len],
+            Pattern::Alternating => vec![0x55; len],
+            Pattern::PN9 => pn9(len),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "carrier" => Ok(Pattern::Carrier),
+            "alternating" => Ok(Pattern::Alternating),
+            "pn9" => Ok(Pattern::PN9),
+            _ => Err(format!("expected carrier, alternating or pn9, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Pattern::Carrier => "carrier",
+            Pattern::Alternating => "alternating",
+            Pattern::PN9 => "pn9",
+        })
+    }
+}
+
+/// PN9 from the all ones seed, packed MSB first. Repeats every 511 bits.
+pub fn pn9(len: usize) -> Vec<u8> {
+    let mut lfsr: u16 = 0x1FF;
+    let mut bit = || {
+        let out = (lfsr & 1) as u8;
+        let feedback = (lfsr ^ (lfsr >> 5)) & 1;
+        lfsr = (lfsr >> 1) | (feedback << 8);
+        out
+    };
+    (0..len)
+        .map(|_| (0..8).fold(0, |byte, _| byte << 1 | bit()))
+        .collect()
+}
+
+/// Bytes of a pattern lasting `duration` at `datarate` bits/s
+pub fn pattern_len(duration: Duration, datarate: u64) -> usize {
+    (duration.as_micros() * u128::from(datarate) / 8_000_000) as usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// Preamble, one HDLC packet and the postamble
+    Packet,
+    /// Raw bits, nothing around them
+    Pattern,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    /// Packet chunks left to hand to the FIFO
+    Data,
+    /// Everything committed, waiting for the radio controller to finish
+    Tail,
+    Done,
+}
+
+/// One packet on its way out without blocking. start() arms the IRQ line and fills the FIFO, then
+/// the caller runs service() on each radio IRQ until it returns true: FIFOTHRFREE asks for more
+/// chunks, and once the postamble is in the radio controller's DONE event (through RADIOCTRL)
+/// ends it. The IRQ mask is left empty when done, rx::start() or the
+/// next start() sets its own.
+pub struct Transmission {
+    chunks: VecDeque<FIFOChunkTX>,
+    phase: Phase,
+    kind: Kind,
+    sticky: PowStat,
+}
+
+impl Transmission {
+    pub fn start(radio: &mut Registers, buf: &[u8]) -> Result<Self> {
+        let sticky = key(radio)?;
+        Ok(Self {
+            sticky,
+            ..Self::load(radio, buf, Kind::Packet)?
+        })
+    }
+
+    /// Keys up on `len` bytes of `pattern`. The PA is on for exactly those, with no preamble.
+    pub fn pattern(radio: &mut Registers, pattern: Pattern, len: usize) -> Result<Self> {
+        let sticky = key(radio)?;
+        Ok(Self {
+            sticky,
+            ..Self::load(radio, &pattern.bytes(len), Kind::Pattern)?
+        })
+    }
+
+    /// POWSTICKYSTAT as keying up read and cleared it, for supply::History::hold()
+    pub fn sticky(&self) -> PowStat {
+        self.sticky
+    }
+
+    /// Queues the PA control, preamble and as much of `buf` as fits in the FIFO
+    fn load(radio: &mut Registers, buf: &[u8], kind: Kind) -> Result<Self> {
+        // FIXME: I experienced some crashes that probably occured because FIFOTHRESH returned the
+        // wrong value. Once 0, once too big (but not measured). Lets hard code it for now to be
+        // safe for flight.
+        //let thresh: usize = radio.FIFOTHRESH().read()?.into();
+        let thresh: usize = 128;
+
+        let pa_on = FIFOChunkTX::TXCTRL(TXCtrl::SETPA | TXCtrl::PASTATE);
+        /* FIXME: this is the recommended preamble
+        let preamble = FIFOChunkTX::DATA {
+            flags: FIFODataTXFlags::RAW,
+            data: vec![0x11],
+        };
+        */
+        let preamble = FIFOChunkTX::REPEATDATA {
+            flags: FIFODataTXFlags::RAW | FIFODataTXFlags::NOCRC,
+            count: PREAMBLE as u8,
+            data: 0x7E,
+        };
+
+        // TODO: integrate recv
+        // TODO: maybe FIFOChunkTX::to_data -> Vec? It knows how big it should be
+        let header_size = 3; // size of FIFOChunkTX::DATA header
+
+        // I witnessed thresh read as 0 once, which crashed the driver. Having thresh (FIFOTHRESH)
+        // return 0 makes no sense, but lets guard against it anyway.
+        let Some(chunksize) = thresh.checked_sub(header_size) else {
+            radio.PWRMODE().write(PwrMode {
+                flags: PwrFlags::XOEN | PwrFlags::REFEN,
+                mode: PwrModes::POWEROFF,
+            })?;
+            return Err(Error::Invalid); // FIFOTHRESH returned 0. Weird
+        };
+        let chunk_flags = match kind {
+            Kind::Packet => FIFODataTXFlags::empty(),
+            Kind::Pattern => FIFODataTXFlags::RAW | FIFODataTXFlags::NOCRC | FIFODataTXFlags::UNENC,
+        };
+        let mut packet: VecDeque<FIFOChunkTX> = buf
+            .chunks(chunksize)
+            .map(|x| FIFOChunkTX::DATA {
+                flags: chunk_flags,
+                data: x.to_vec(),
+            })
+            .collect();
+        if kind == Kind::Packet {
+            if let Some(FIFOChunkTX::DATA { ref mut flags, .. }) = packet.front_mut() {
+                *flags |= FIFODataTXFlags::PKTSTART;
+            }
+            if let Some(FIFOChunkTX::DATA { ref mut flags, .. }) = packet.back_mut() {
+                *flags |= FIFODataTXFlags::PKTEND;
+            }
+        }
+        trace!(target: "ax5043::fifo", "TX chunks {:02X?}", packet);
+
+        // The threshold the FIFOTHRFREE IRQ and FREE_THR compare against
+        radio.FIFOTHRESH().write(thresh as u16)?;
+        radio.FIFODATATX().write(pa_on)?;
+        if kind == Kind::Packet {
+            radio.FIFODATATX().write(preamble)?;
+        }
+        radio.IRQMASK().write(IRQ::FIFOTHRFREE | IRQ::FIFOERROR)?;
+        let mut transmission = Self {
+            chunks: packet,
+            phase: Phase::Data,
+            kind,
+            sticky: PowStat::all(),
+        };
+        transmission.next(radio)?;
+        transmission.fill(radio)?;
+        Ok(transmission)
+    }
+
+    /// Moves the transmission along, returning true once the radio is back in POWEROFF. Harmless
+    /// on an IRQ that wasn't for it.
+    pub fn service(&mut self, radio: &mut Registers) -> Result<bool> {
+        match self.phase {
+            Phase::Data => {
+                self.fill(radio)?;
+                Ok(false)
+            }
+            Phase::Tail => {
+                // Reading RADIOEVENTREQ acknowledges the event
+                let events = radio.RADIOEVENTREQ().read()?;
+                if !events.contains(RadioEvent::DONE)
+                    && radio.RADIOSTATE().read()? != RadioState::IDLE
+                {
+                    return Ok(false);
+                }
+                radio.IRQMASK().write(IRQ::empty())?;
+                radio.RADIOEVENTMASK().write(RadioEvent::empty())?;
+                radio.PWRAMP().write(PwrAmp::empty())?; // FIXME why isn't pa_off doing this?
+                while radio.PWRAMP().read()?.contains(PwrAmp::PWRAMP) {} // TODO: Interrupt of some sort
+
+                radio.PWRMODE().write(PwrMode {
+                    flags: PwrFlags::XOEN | PwrFlags::REFEN,
+                    mode: PwrModes::POWEROFF,
+                })?;
+                self.phase = Phase::Done;
+                Ok(true)
+            }
+            Phase::Done => Ok(true),
+        }
+    }
+
+    /// Cuts the frame off where it is: PA off, FIFO cleared, back to POWEROFF. For a TX
+    /// inhibit, the far end sees a truncated frame.
+    pub fn abort(self, radio: &mut Registers) -> Result<()> {
+        radio.IRQMASK().write(IRQ::empty())?;
+        radio.RADIOEVENTMASK().write(RadioEvent::empty())?;
+        radio.PWRAMP().write(PwrAmp::empty())?;
+        radio.FIFOCMD().write(FIFOCmd {
+            mode: FIFOCmds::CLEAR_DATA,
+            auto_commit: false,
+        })?;
+        radio.PWRMODE().write(PwrMode {
+            flags: PwrFlags::XOEN | PwrFlags::REFEN,
+            mode: PwrModes::POWEROFF,
+        })
+    }
+
+    /// Commits chunks for as long as FREE_THR says there's room. The IRQ line is level triggered
+    /// but watched for edges, so it has to drop before the next FIFOTHRFREE can be seen.
+    fn fill(&mut self, radio: &mut Registers) -> Result<()> {
+        while self.phase == Phase::Data {
+            let stat = radio.FIFOSTAT().read()?;
+            if stat.contains(FIFOStat::OVER) || stat.contains(FIFOStat::UNDER) {
+                error!(target: "ax5043::fifo", "chunk: {:?}", stat);
+                // FIXME: I saw this happen once and then hang? We should probably abandon ship
+                // here. Possibly set the abort bit?
+                radio.FIFOCMD().write(FIFOCmd {
+                    mode: FIFOCmds::CLEAR_ERROR,
+                    auto_commit: false,
+                })?;
+                radio.FIFOCMD().write(FIFOCmd {
+                    mode: FIFOCmds::CLEAR_DATA,
+                    auto_commit: false,
+                })?;
+                self.chunks.clear();
+            } else if !stat.contains(FIFOStat::FREE_THR) {
+                break;
+            }
+            self.next(radio)?;
+        }
+        Ok(())
+    }
+
+    /// Commits the next chunk, or the postamble and PA off once they're all in
+    fn next(&mut self, radio: &mut Registers) -> Result<()> {
+        if let Some(chunk) = self.chunks.pop_front() {
+            radio.FIFODATATX().write(chunk)?;
+        } else {
+            let postamble = FIFOChunkTX::REPEATDATA {
+                flags: FIFODataTXFlags::RAW | FIFODataTXFlags::NOCRC,
+                count: POSTAMBLE as u8,
+                data: 0x7E,
+            };
+            let pa_off = FIFOChunkTX::TXCTRL(TXCtrl::SETPA);
+            if self.kind == Kind::Packet {
+                radio.FIFODATATX().write(postamble)?;
+            }
+            radio.FIFODATATX().write(pa_off)?;
+            radio.RADIOEVENTMASK().write(RadioEvent::DONE)?;
+            radio.IRQMASK().write(IRQ::RADIOCTRL)?;
+            self.phase = Phase::Tail;
+        }
+        radio.FIFOCMD().write(FIFOCmd {
+            mode: FIFOCmds::COMMIT,
+            auto_commit: false,
+        })?;
+        Ok(())
+    }
+}
+
+/// Enters TX, with the PLL lock and brownout gates armed. Arming the BROWN_GATE takes reading
+/// POWSTICKYSTAT, which clears it, so what it held is returned.
+fn key(radio: &mut Registers) -> Result<PowStat> {
+    radio.PWRMODE().write(PwrMode {
+        flags: PwrFlags::XOEN | PwrFlags::REFEN,
+        mode: PwrModes::TX,
+    })?;
+
+    _ = radio.PLLRANGINGA().read()?; // sticky lock bit ~ IRQPLLUNLIOCK, gate
+    radio.POWSTICKYSTAT().read() // sticky brownout bits, gate
+}
+
+/// Sends `buf` so its first bit after the preamble goes out at `at`, blocking until done like
+/// transmit(). `datarate` is the TX channel's, for the preamble time. Staging takes a few SPI
+/// transactions, so call it with some margin; if the slot can't be met nothing is sent and
+/// Error::Late says by how much it was missed.
+pub fn transmit_at(radio: &mut Registers, at: Instant, buf: &[u8], datarate: u64) -> Result<()> {
+    let staged = Staged::new(radio, buf, at, datarate)?;
+    let mut tfd = TimerFd::new()?;
+    if let Err(e) = staged.arm(&mut tfd) {
+        staged.cancel(radio)?;
+        return Err(e);
+    }
+    tfd.read();
+    let mut transmission = staged.release(radio)?;
+    while !transmission.service(radio)? {}
+    Ok(())
+}
+
+/// A packet loaded into the FIFO with the synthesizer running, waiting for its slot. For poll
+/// loops: arm() a timerfd, release() when it fires, then service() the Transmission as usual.
+pub struct Staged {
+    transmission: Transmission,
+    release: Instant,
+}
+
+impl Staged {
+    pub fn new(radio: &mut Registers, buf: &[u8], at: Instant, datarate: u64) -> Result<Self> {
+        let settle = u64::from(radio.TMGTXBOOST().read()?) + u64::from(radio.TMGTXSETTLE().read()?);
+        let preamble = (PREAMBLE * 8) as u64 * 1_000_000 / datarate.max(1);
+        let lead = Duration::from_micros(settle + preamble);
+
+        radio.PWRMODE().write(PwrMode {
+            flags: PwrFlags::XOEN | PwrFlags::REFEN,
+            mode: PwrModes::SYNTHTX,
+        })?;
+        Ok(Self {
+            transmission: Transmission::load(radio, buf, Kind::Packet)?,
+            release: at.checked_sub(lead).unwrap_or(at),
+        })
+    }
+
+    /// When release() has to run: the slot less TMGTXBOOST, TMGTXSETTLE and the preamble
+    pub fn release_at(&self) -> Instant {
+        self.release
+    }
+
+    /// Sets `tfd` to fire at release_at(). A timerfd set to 0 never fires, so a release time
+    /// that's already here or gone is Error::Late.
+    pub fn arm(&self, tfd: &mut TimerFd) -> Result<()> {
+        let now = Instant::now();
+        if self.release <= now {
+            return Err(Error::Late(now - self.release));
+        }
+        tfd.set_state(
+            TimerState::Oneshot(self.release - now),
+            SetTimeFlags::Default,
+        );
+        Ok(())
+    }
+
+    /// Keys up, the queued preamble starts right away
+    pub fn release(self, radio: &mut Registers) -> Result<Transmission> {
+        let sticky = key(radio)?;
+        Ok(Transmission {
+            sticky,
+            ..self.transmission
+        })
+    }
+
+    /// Drops the packet and powers off without transmitting
+    pub fn cancel(self, radio: &mut Registers) -> Result<()> {
+        radio.IRQMASK().write(IRQ::empty())?;
+        radio.RADIOEVENTMASK().write(RadioEvent::empty())?;
+        radio.FIFOCMD().write(FIFOCmd {
+            mode: FIFOCmds::CLEAR_DATA,
+            auto_commit: false,
+        })?;
+        radio.PWRMODE().write(PwrMode {
+            flags: PwrFlags::XOEN | PwrFlags::REFEN,
+            mode: PwrModes::POWEROFF,
+        })
+    }
+}
+
+/// Time on air for a `len` byte packet at `datarate` bits/s, counting the pre/postamble and
+/// CRC. Ignores HDLC bit stuffing so it runs slightly short.
+pub fn airtime(len: usize, datarate: u64) -> Duration {
+    let bits = ((PREAMBLE + len + 2 + POSTAMBLE) * 8) as u64;
+    Duration::from_micros(bits * 1_000_000 / datarate.max(1))
+}
+
+/// Slack on top of a frame's airtime before it counts as stuck, for the synthesizer settling and
+/// a late IRQ, see deadline()
+pub const OVERDUE: Duration = Duration::from_millis(250);
+
+/// How long a `len` byte frame at `datarate` bits/s may stay on the air before it's cut off: its
+/// airtime() with a quarter more for HDLC bit stuffing, plus OVERDUE
+pub fn deadline(len: usize, datarate: u64) -> Duration {
+    airtime(len, datarate) * 5 / 4 + OVERDUE
+}
+
+/// A recognizable `len` byte frame for bring-up: "AX5043 TEST <seq> " then a counting pattern,
+/// truncated if `len` is shorter than the header
+pub fn test_frame(seq: u32, len: usize) -> Vec<u8> {
+    let mut frame = format!("AX5043 TEST {} ", seq).into_bytes();
+    let header = frame.len();
+    frame.extend((header..len).map(|i| (i - header) as u8));
+    frame.truncate(len);
+    frame
+}
+
+/// Transmit budget over a sliding window, e.g. at most 10% of any 10 minutes
+pub struct DutyCycle {
+    window: Duration,
+    limit: f64,
+    history: VecDeque<(Instant, Duration)>,
+}
+
+impl DutyCycle {
+    /// `limit` is the allowed fraction of `window` spent transmitting
+    pub fn new(window: Duration, limit: f64) -> Self {
+        Self {
+            window,
+            limit,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Airtime within the window ending at `now`
+    pub fn used(&mut self, now: Instant) -> Duration {
+        while let Some(&(start, _)) = self.history.front() {
+            if now.saturating_duration_since(start) < self.window {
+                break;
+            }
+            self.history.pop_front();
+        }
+        self.history.iter().map(|&(_, airtime)| airtime).sum()
+    }
+
+    pub fn allows(&mut self, now: Instant, airtime: Duration) -> bool {
+        self.used(now) + airtime <= self.window.mul_f64(self.limit)
+    }
+
+    pub fn record(&mut self, now: Instant, airtime: Duration) {
+        self.history.push_back((now, airtime));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn airtime_9600() {
+        // 0x50 + 10 + 2 + 5 bytes = 97 bytes = 776 bits
+        assert_eq!(airtime(10, 9600), Duration::from_micros(80833));
+        assert_eq!(deadline(10, 9600), Duration::from_nanos(351_041_250));
+    }
+
+    #[test]
+    fn transmission_irqs() {
+        let mut services = 0;
+        let writes = crate::dry_run(|radio| {
+            // 300 bytes is three DATA chunks
+            let mut transmission = Transmission::start(radio, &[0xAA; 300])?;
+            while !transmission.service(radio)? {
+                services += 1;
+            }
+            assert!(transmission.service(radio)?);
+            Ok(())
+        })
+        .unwrap();
+        // The sink always has room, so start() got as far as the postamble
+        assert_eq!(services, 0);
+        let masks: Vec<_> = writes
+            .to("IRQMASK")
+            .iter()
+            .map(|w| w.data.clone())
+            .collect();
+        assert_eq!(
+            masks,
+            [vec![0x00, 0x18], vec![0x00, 0x40], vec![0x00, 0x00]]
+        );
+        assert!(writes.in_order(&["RADIOEVENTMASK", "IRQMASK", "PWRAMP", "PWRMODE"]));
+        // FIFOCMD shares its address with FIFOSTAT, which goes first in name()
+        let commits = writes.iter().filter(|w| w.addr == 0x028).count();
+        assert_eq!(commits, 4);
+    }
+
+    #[test]
+    fn abort() {
+        let writes = crate::dry_run(|radio| {
+            let transmission = Transmission::start(radio, &[0xAA; 300])?;
+            transmission.abort(radio)
+        })
+        .unwrap();
+        assert!(writes.in_order(&["IRQMASK", "PWRAMP", "PWRMODE"]));
+        assert_eq!(writes.to("PWRMODE").last().unwrap().data[0] & 0x0F, 0x00);
+    }
+
+    #[test]
+    fn scheduled() {
+        let (a, b) = crate::sim::pair(crate::sim::Channel::default());
+        let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+        let mut radio = Registers::new(crate::Bus::Sim(a.clone()), &mut ca);
+        let mut rx_radio = Registers::new(crate::Bus::Sim(b), &mut cb);
+        crate::rx::start(&mut rx_radio).unwrap();
+        let frame = test_frame(1, 300);
+        // The sim starts with zeros, these are the reset values
+        radio.TMGTXBOOST().write(Float5 { e: 1, m: 0x12 }).unwrap();
+        radio.TMGTXSETTLE().write(Float5 { e: 0, m: 0x0A }).unwrap();
+
+        // 0x50 bytes of preamble at 9600 is 66.6 ms, plus 36 + 10 µs to settle
+        let at = Instant::now() + Duration::from_millis(100);
+        let staged = Staged::new(&mut radio, &frame, at, 9600).unwrap();
+        assert_eq!(at - staged.release_at(), Duration::from_micros(66_712));
+        assert_eq!(a.stats().sent, 0);
+        staged.cancel(&mut radio).unwrap();
+        assert_eq!(a.stats().sent, 0);
+
+        transmit_at(&mut radio, at, &frame, 9600).unwrap();
+        assert!(Instant::now() >= at - Duration::from_micros(66_712));
+        assert_eq!(a.stats().sent, 1);
+        let received = crate::rx::PacketAssembler::new()
+            .drain(&mut rx_radio)
+            .unwrap();
+        assert_eq!(received, vec![frame.clone()]);
+
+        // Too late to make it, nothing goes out and the radio is left off
+        let err = transmit_at(&mut radio, Instant::now(), &frame, 9600).unwrap_err();
+        assert!(matches!(err, Error::Late(_)));
+        assert_eq!(a.stats().sent, 1);
+        assert_eq!(radio.PWRMODE().read().unwrap().mode, PwrModes::POWEROFF);
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!("PN9".parse(), Ok(Pattern::PN9));
+        assert_eq!("carrier".parse::<Pattern>().unwrap().to_string(), "carrier");
+        assert!("noise".parse::<Pattern>().is_err());
+        assert_eq!(Pattern::Alternating.bytes(2), [0x55, 0x55]);
+
+        // 511 bit period, 256 ones and 255 zeros in each
+        let bits: Vec<u8> = pn9(2 * 511)
+            .iter()
+            .flat_map(|b| (0..8).rev().map(move |i| b >> i & 1))
+            .collect();
+        assert_eq!(bits[..511], bits[511..1022]);
+        assert_eq!(bits[..511].iter().filter(|&&b| b == 1).count(), 256);
+        assert_eq!(&bits[..9], &[1; 9]);
+        assert_eq!(pattern_len(Duration::from_secs(2), 9600), 2400);
+
+        // No preamble, postamble or packet flags, just raw chunks between the PA switching
+        let writes = crate::dry_run(|radio| transmit_pattern(radio, Pattern::PN9, 300)).unwrap();
+        let chunks: Vec<_> = writes
+            .iter()
+            .filter(|w| w.addr == 0x029)
+            .map(|w| FIFOChunkTX::try_from(w.data.clone()).unwrap())
+            .collect();
+        assert_eq!(chunks.len(), 5);
+        assert!(matches!(chunks[0], FIFOChunkTX::TXCTRL(_)));
+        assert!(matches!(chunks[4], FIFOChunkTX::TXCTRL(_)));
+        let mut sent = Vec::new();
+        for chunk in &chunks[1..4] {
+            let FIFOChunkTX::DATA { flags, data } = chunk else {
+                panic!("{:?}", chunk);
+            };
+            assert_eq!(
+                *flags,
+                FIFODataTXFlags::RAW | FIFODataTXFlags::NOCRC | FIFODataTXFlags::UNENC
+            );
+            sent.extend_from_slice(data);
+        }
+        assert_eq!(sent, pn9(300));
+    }
+
+    #[test]
+    fn test_frames() {
+        let frame = test_frame(7, 20);
+        assert_eq!(frame.len(), 20);
+        assert_eq!(&frame[..14], b"AX5043 TEST 7 ");
+        assert_eq!(&frame[14..], &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(test_frame(7, 6), b"AX5043");
+        // The pattern wraps at 256
+        assert_eq!(test_frame(1, 400)[399], (399 - 14 - 256) as u8);
+    }
+
+    #[test]
+    fn duty_cycle_window() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut duty = DutyCycle::new(10 * second, 0.2);
+
+        assert!(duty.allows(start, 2 * second));
+        duty.record(start, second);
+        assert!(duty.allows(start, second));
+        assert!(!duty.allows(start, second + Duration::from_millis(1)));
+        duty.record(start + second, second);
+        assert!(!duty.allows(start + second, Duration::from_millis(1)));
+
+        // The first transmission ages out of the window
+        assert_eq!(duty.used(start + 10 * second), second);
+        assert!(duty.allows(start + 10 * second, second));
+        assert_eq!(duty.used(start + 11 * second), Duration::ZERO);
+    }
+}
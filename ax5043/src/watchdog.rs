@@ -0,0 +1,160 @@
+// Notices a radio that has stopped working so the bins can reset and reconfigure it without
+// someone restarting the service.
+//
+// Stuck means one of:
+// - no IRQ and no status change for the timeout while in RX
+// - REVISION can't be read or reads wrong (SPI or power trouble)
+// - FIFO errors on STRIKES checks in a row
+//
+// The bins reset for Reason::Supply as well, after supply trouble with --brownout-reset, see
+// supply.rs.
+//
+// The bins call check() from a timer about once a second and feed() on every IRQ and status
+// change. A quiet channel also looks silent, so keep the timeout well above the longest gap
+// expected between passes: --watchdog is off unless given, and hours rather than minutes.
+use crate::{rx::Stats, Registers, RX};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Consecutive checks with new FIFO errors before the radio counts as stuck
+pub const STRIKES: u32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Reason {
+    /// No IRQ or status change for this long
+    Silent(Duration),
+    /// What REVISION read, None if the read itself failed
+    Revision(Option<u8>),
+    /// FIFO errors over the last STRIKES checks
+    FIFOErrors(u64),
+    /// Not stuck but possibly lost its config, see supply.rs
+    Supply(crate::supply::Kind),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Silent(idle) => write!(f, "silent for {}s", idle.as_secs()),
+            Reason::Revision(None) => write!(f, "REVISION read failed"),
+            Reason::Revision(Some(rev)) => {
+                write!(
+                    f,
+                    "REVISION {:#04X}, expected {:#04X}",
+                    rev,
+                    crate::REVISION
+                )
+            }
+            Reason::FIFOErrors(n) => write!(f, "{} FIFO errors", n),
+            Reason::Supply(kind) => write!(f, "{}", kind),
+        }
+    }
+}
+
+pub struct Watchdog {
+    timeout: Duration,
+    last: Instant,
+    fifo_errors: u64,
+    strikes: u32,
+    errors: u64,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last: now,
+            fifo_errors: 0,
+            strikes: 0,
+            errors: 0,
+        }
+    }
+
+    /// Records activity (an IRQ or status change) at `at`
+    pub fn feed(&mut self, at: Instant) {
+        self.last = self.last.max(at);
+    }
+
+    /// Starts over after the radio was recovered, `stats` keeps counting across the reset
+    pub fn reset(&mut self, stats: &Stats, now: Instant) {
+        self.last = now;
+        self.fifo_errors = stats.fifo_errors;
+        self.strikes = 0;
+        self.errors = 0;
+    }
+
+    /// Some if the radio looks stuck and needs a reset
+    pub fn check(&mut self, radio: &mut Registers, stats: &Stats, now: Instant) -> Option<Reason> {
+        match radio.REVISION().read() {
+            Ok(crate::REVISION) => (),
+            Ok(rev) => return Some(Reason::Revision(Some(rev))),
+            Err(_) => return Some(Reason::Revision(None)),
+        }
+
+        let new = stats.fifo_errors.saturating_sub(self.fifo_errors);
+        self.fifo_errors = stats.fifo_errors;
+        if new > 0 {
+            self.strikes += 1;
+            self.errors += new;
+        } else {
+            self.strikes = 0;
+            self.errors = 0;
+        }
+        if self.strikes >= STRIKES {
+            return Some(Reason::FIFOErrors(self.errors));
+        }
+
+        let idle = now.saturating_duration_since(self.last);
+        match idle > self.timeout {
+            true => Some(Reason::Silent(idle)),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bus;
+
+    #[test]
+    fn stuck() {
+        let mut callback = |_: &_, _, _, _: &_| {};
+        let mut radio = Registers::new(Bus::Sink, &mut callback);
+        let start = Instant::now();
+        let mut stats = Stats::default();
+        let mut dog = Watchdog::new(Duration::from_secs(60), start);
+
+        assert_eq!(dog.check(&mut radio, &stats, start), None);
+        dog.feed(start + Duration::from_secs(50));
+        assert_eq!(
+            dog.check(&mut radio, &stats, start + Duration::from_secs(100)),
+            None
+        );
+        assert_eq!(
+            dog.check(&mut radio, &stats, start + Duration::from_secs(120)),
+            Some(Reason::Silent(Duration::from_secs(70)))
+        );
+
+        dog.reset(&stats, start);
+        for _ in 1..STRIKES {
+            stats.fifo_errors += 2;
+            assert_eq!(dog.check(&mut radio, &stats, start), None);
+        }
+        stats.fifo_errors += 2;
+        assert_eq!(
+            dog.check(&mut radio, &stats, start),
+            Some(Reason::FIFOErrors(2 * u64::from(STRIKES)))
+        );
+
+        // A clean check in between isn't persistent
+        dog.reset(&stats, start);
+        for _ in 0..STRIKES {
+            stats.fifo_errors += 1;
+            assert_eq!(dog.check(&mut radio, &stats, start), None);
+            assert_eq!(dog.check(&mut radio, &stats, start), None);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// PacketAssembler::drain_with() is the IRQ handler's drain and mustn't allocate once warmed up.
+// Counting needs a #[global_allocator], which would count for every test in the binary it's
+// linked into, so it gets a test binary of its own.
+use ax5043::{
+    rx::{self, PacketAssembler},
+    sim, tx, Bus, Registers, RX,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+/// Counts this thread's allocations
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+#[test]
+fn drain_with_allocates_nothing() {
+    let (a, b) = sim::pair(sim::Channel::default());
+    let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+    let mut tx_regs = Registers::new(Bus::Sim(a), &mut ca);
+    let mut rx_regs = Registers::new(Bus::Sim(b), &mut cb);
+    rx::start(&mut rx_regs).unwrap();
+    let mut asm = PacketAssembler::new();
+
+    // The first frame warms up the simulator's own buffers
+    for seq in 0..2 {
+        let frame = tx::test_frame(seq, 100);
+        tx::transmit(&mut tx_regs, &frame).unwrap();
+        _ = rx_regs.FIFOCOUNT().read().unwrap(); // delivers it
+
+        let mut received = 0;
+        let before = ALLOCATIONS.with(|a| a.get());
+        asm.drain_with(&mut rx_regs, |_, packet, _, _| {
+            assert_eq!(packet, frame);
+            received += 1;
+            Ok::<_, ax5043::Error>(())
+        })
+        .unwrap();
+        let allocations = ALLOCATIONS.with(|a| a.get()) - before;
+        assert_eq!(received, 1);
+        if seq > 0 {
+            assert_eq!(allocations, 0);
+        }
+    }
+
+    // Whereas drain() hands out owned packets, which shows the counter works
+    let frame = tx::test_frame(2, 100);
+    tx::transmit(&mut tx_regs, &frame).unwrap();
+    _ = rx_regs.FIFOCOUNT().read().unwrap();
+    let before = ALLOCATIONS.with(|a| a.get());
+    assert_eq!(asm.drain(&mut rx_regs).unwrap(), [frame]);
+    assert!(ALLOCATIONS.with(|a| a.get()) > before);
+    assert_eq!(asm.stats().packets, 3);
+}
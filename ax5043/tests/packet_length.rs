@@ -0,0 +1,59 @@
+// A channel with a length field end to end, the way the uhf bin does it: Downlink encodes each
+// frame with ChannelParameters::encode(), read_packet() decodes what the assembler hands it with
+// decode(), and the field only exists on the air in between.
+use ax5043::{
+    config::{Config, PacketLength},
+    registers::U4,
+    rx::{self, PacketAssembler},
+    sim, tx, Bus, Registers,
+};
+
+#[test]
+fn uhf_round_trip() {
+    let mut config: Config =
+        toml::from_str(include_str!("../examples/rpi-uhf-96000.toml")).unwrap();
+    let length = PacketLength::Dynamic {
+        pos: U4::new(0),
+        bits: U4::new(8),
+        offset: 0,
+        max: 200,
+    };
+    config.channel[0].length = length;
+    let channel = config.channel[0];
+
+    let (a, b) = sim::pair(sim::Channel::default());
+    let (mut ca, mut cb) = (|_: &_, _, _, _: &_| {}, |_: &_, _, _, _: &_| {});
+    let mut tx_regs = Registers::new(Bus::Sim(a), &mut ca);
+    let mut rx_regs = Registers::new(Bus::Sim(b), &mut cb);
+    rx::start(&mut rx_regs).unwrap();
+    let mut assembler = PacketAssembler::new()
+        .accept(config.accept)
+        .max_len(channel.length.max_len())
+        .trailer(channel.crc_trailer)
+        .crc(channel.crc)
+        .framing(channel.framing);
+
+    let payload = b"telemetry".to_vec();
+    let frame = channel.encode(&payload).unwrap();
+    assert_eq!(frame.len(), channel.coded_len(payload.len()));
+    assert_eq!(frame[0], 10);
+    tx::transmit(&mut tx_regs, &frame).unwrap();
+
+    let mut received = Vec::new();
+    assembler
+        .drain_with(&mut rx_regs, |_, packet, _, crc_ok| {
+            assert!(crc_ok);
+            received.push(channel.decode(packet)?.0.into_owned());
+            Ok::<_, ax5043::Error>(())
+        })
+        .unwrap();
+    assert_eq!(received, std::slice::from_ref(&payload));
+
+    // A field that disagrees with what arrived is a decode failure, not a frame
+    let mut bad = frame.into_owned();
+    bad[0] += 1;
+    assert!(matches!(
+        channel.decode(&bad),
+        Err(ax5043::Error::Length(_))
+    ));
+}